@@ -1,16 +1,25 @@
 //! Emulated Local APIC. (SDM Vol. 3A, Chapter 10)
 
 #![allow(dead_code)]
-use libax::hv::{Result as HyperResult, Error as HyperError, VCpu, HyperCraftHal};
+use alloc::sync::Arc;
 use bit_field::BitField;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use libax::hv::HyperCraftHalImpl;
+use libax::hv::{Error as HyperError, HyperCraftHal, Result as HyperResult, VCpu};
 use libax::time::current_time_nanos;
+use spin::Mutex;
 
-use super::{msr_proxy_struct, msr_proxy_factory, VirtMsrDevice};
+use super::{msr_proxy_factory, msr_proxy_struct, VirtMsrDevice};
 
 const APIC_FREQ_MHZ: u64 = 1000; // 1000 MHz
 const APIC_CYCLE_NANOS: u64 = 1000 / APIC_FREQ_MHZ;
 
+/// Nominal host TSC frequency used to turn an `IA32_TSC_DEADLINE` value
+/// into a delay. This tree has no CPUID 0x15/0x16 frequency detection
+/// yet, so a typical modern-CPU frequency is used as a placeholder --
+/// same spirit as `APIC_FREQ_MHZ` above.
+const TSC_FREQ_MHZ: u64 = 3000;
+
 /// Local APIC timer modes.
 #[derive(Debug, Copy, Clone)]
 #[repr(u8)]
@@ -32,6 +41,13 @@ pub struct ApicTimer {
     last_start_ns: u64,
     deadline_ns: u64,
     tpr: u32,
+    /// The guest-visible `IA32_TSC_DEADLINE` value currently armed, or `0`
+    /// if disarmed. Only meaningful in [`TimerMode::TscDeadline`].
+    tsc_deadline: u64,
+    /// Per-vCPU offset added to the raw host TSC to get the guest-visible
+    /// TSC value (mirrors the VMCS `TSC_OFFSET` field). Defaults to `0`
+    /// until the VM-exit handler wires it up from the real vCPU state.
+    tsc_offset: i64,
 }
 
 impl ApicTimer {
@@ -43,9 +59,17 @@ impl ApicTimer {
             last_start_ns: 0,
             deadline_ns: 0,
             tpr: 0,
+            tsc_deadline: 0,
+            tsc_offset: 0,
         }
     }
 
+    /// Sets the per-vCPU TSC offset used to translate `IA32_TSC_DEADLINE`
+    /// writes into host-TSC targets.
+    pub fn set_tsc_offset(&mut self, offset: i64) {
+        self.tsc_offset = offset;
+    }
+
     /// Check if an interrupt generated. if yes, update it's states.
     pub fn check_interrupt(&mut self) -> bool {
         if self.deadline_ns == 0 {
@@ -73,6 +97,12 @@ impl ApicTimer {
         timer_mode == TimerMode::Periodic as _
     }
 
+    /// Whether the LVT Timer register has TSC-deadline mode (bit 18) set.
+    pub const fn is_tsc_deadline(&self) -> bool {
+        let timer_mode = (self.lvt_timer_bits >> 17) & 0b11;
+        timer_mode == TimerMode::TscDeadline as _
+    }
+
     /// The timer interrupt vector number.
     pub const fn vector(&self) -> u8 {
         (self.lvt_timer_bits & 0xff) as u8
@@ -89,13 +119,23 @@ impl ApicTimer {
         (dcr & 0b11) | ((dcr & 0b100) << 1)
     }
 
-    /// Initial Count Register.
-    pub const fn initial_count(&self) -> u32 {
-        self.initial_count
+    /// Initial Count Register. Reads as `0` in TSC-deadline mode, since the
+    /// count-down registers play no part in arming that mode's timer. (SDM
+    /// Vol. 3A, Section 10.5.4.1)
+    pub fn initial_count(&self) -> u32 {
+        if self.is_tsc_deadline() {
+            0
+        } else {
+            self.initial_count
+        }
     }
 
-    /// Current Count Register.
+    /// Current Count Register. Reads as `0` in TSC-deadline mode, same as
+    /// [`Self::initial_count`].
     pub fn current_counter(&self) -> u32 {
+        if self.is_tsc_deadline() {
+            return 0;
+        }
         let elapsed_ns = current_time_nanos() - self.last_start_ns;
         let elapsed_cycles = (elapsed_ns / APIC_CYCLE_NANOS) >> self.divide_shift;
         if self.is_periodic() {
@@ -110,16 +150,54 @@ impl ApicTimer {
     /// Set LVT Timer Register.
     pub fn set_lvt_timer(&mut self, bits: u32) -> HyperResult {
         let timer_mode = bits.get_bits(17..19);
-        if timer_mode == TimerMode::TscDeadline as _ {
-            return Err(HyperError::NotSupported); // TSC deadline mode was not supported
-        } else if timer_mode == 0b11 {
+        if timer_mode == 0b11 {
             return Err(HyperError::InvalidParam); // reserved
         }
+        let was_tsc_deadline = self.is_tsc_deadline();
         self.lvt_timer_bits = bits;
-        self.start_timer();
+
+        if was_tsc_deadline && !self.is_tsc_deadline() {
+            // Leaving TSC-deadline mode cancels any pending deadline, same
+            // as the count-down registers being reset on a mode change
+            // (SDM Vol. 3A, Section 10.5.4.1).
+            self.tsc_deadline = 0;
+            self.deadline_ns = 0;
+        }
+
+        if self.is_tsc_deadline() {
+            // TSC-deadline mode doesn't arm from the count-down registers;
+            // the guest still has to write `IA32_TSC_DEADLINE`.
+        } else {
+            self.start_timer();
+        }
         Ok(())
     }
 
+    /// The `IA32_TSC_DEADLINE` value currently armed, or `0` if disarmed.
+    pub fn tsc_deadline(&self) -> u64 {
+        self.tsc_deadline
+    }
+
+    /// Arms (or, for `0`, disarms) a TSC-deadline interrupt. `deadline` is
+    /// the guest-visible TSC value the guest wrote to `IA32_TSC_DEADLINE`;
+    /// it's translated to a host-TSC target via [`Self::tsc_offset`] and
+    /// then to a wall-clock deadline, reusing the same `deadline_ns`
+    /// polling path [`Self::check_interrupt`] already drives for the
+    /// one-shot/periodic modes. Re-arming overwrites the prior deadline.
+    pub fn set_tsc_deadline(&mut self, deadline: u64) {
+        self.tsc_deadline = deadline;
+        if deadline == 0 {
+            self.deadline_ns = 0;
+            return;
+        }
+
+        let host_tsc_target = (deadline as i64).wrapping_sub(self.tsc_offset) as u64;
+        let now_tsc = unsafe { x86::time::rdtsc() };
+        let delta_cycles = host_tsc_target.saturating_sub(now_tsc);
+        let delta_ns = delta_cycles.saturating_mul(1000) / TSC_FREQ_MHZ;
+        self.deadline_ns = current_time_nanos() + delta_ns;
+    }
+
     /// Set Initial Count Register.
     pub fn set_initial_count(&mut self, initial: u32) -> HyperResult {
         self.initial_count = initial;
@@ -155,6 +233,34 @@ impl ApicTimer {
     pub fn set_tpr(&mut self, value: u32) {
         self.tpr = value;
     }
+
+    /// Serializes the LVT timer register, divide config, initial count and
+    /// TSC-deadline state. `last_start_ns`/`deadline_ns` are raw
+    /// nanosecond-since-boot values, same same-boot-clock caveat as
+    /// [`super::pit::PIT::save_state`]; `tsc_offset` isn't included, since
+    /// it's re-derived from the live vCPU's VMCS state rather than owned by
+    /// this struct across a restore.
+    pub(crate) fn save_state(&self) -> [u8; 37] {
+        let mut buf = [0u8; 37];
+        buf[0..4].copy_from_slice(&self.lvt_timer_bits.to_le_bytes());
+        buf[4] = self.divide_shift;
+        buf[5..9].copy_from_slice(&self.initial_count.to_le_bytes());
+        buf[9..17].copy_from_slice(&self.last_start_ns.to_le_bytes());
+        buf[17..25].copy_from_slice(&self.deadline_ns.to_le_bytes());
+        buf[25..29].copy_from_slice(&self.tpr.to_le_bytes());
+        buf[29..37].copy_from_slice(&self.tsc_deadline.to_le_bytes());
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, state: &[u8; 37]) {
+        self.lvt_timer_bits = u32::from_le_bytes(state[0..4].try_into().unwrap());
+        self.divide_shift = state[4];
+        self.initial_count = u32::from_le_bytes(state[5..9].try_into().unwrap());
+        self.last_start_ns = u64::from_le_bytes(state[9..17].try_into().unwrap());
+        self.deadline_ns = u64::from_le_bytes(state[17..25].try_into().unwrap());
+        self.tpr = u32::from_le_bytes(state[25..29].try_into().unwrap());
+        self.tsc_deadline = u64::from_le_bytes(state[29..37].try_into().unwrap());
+    }
 }
 
 /// ID register.
@@ -224,46 +330,219 @@ const CUR_COUNT: u32 = 0x39;
 /// Divide Configuration register.
 const DIV_CONF: u32 = 0x3E;
 
+/// Maximum number of local APICs this tree's [`APIC_REGISTRY`] tracks,
+/// matching `apps/hv/src/vcpu.rs`'s `VirtCpuSet::MAX_VCPUS`.
+const MAX_LOCAL_APICS: usize = 4;
+
+static NEXT_APIC_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Cross-vCPU registry of local APICs, keyed by the sequential ID
+/// [`register_apic`] hands out. `VirtCpuSet` (`apps/hv/src/vcpu.rs`) has no
+/// `impl` block to hook into, so the ICR/IPI path needs this registry of
+/// its own to find another vCPU's APIC by destination.
+static APIC_REGISTRY: Mutex<[Option<Arc<Mutex<VirtLocalApic>>>; MAX_LOCAL_APICS]> =
+    Mutex::new([None, None, None, None]);
+
+/// Registers a per-vCPU local APIC and returns the APIC ID it was assigned.
+/// Called once per vCPU, in vCPU bring-up order, from `X64VcpuDevices::new`.
+pub fn register_apic(apic: Arc<Mutex<VirtLocalApic>>) -> usize {
+    let id = NEXT_APIC_ID.fetch_add(1, Ordering::SeqCst);
+    APIC_REGISTRY.lock()[id] = Some(apic);
+    id
+}
+
+fn lookup_apic(id: usize) -> Option<Arc<Mutex<VirtLocalApic>>> {
+    APIC_REGISTRY.lock().get(id).and_then(|slot| slot.clone())
+}
+
+/// Sets `vector` pending in the IRR of the local APIC registered under
+/// `apic_id`, if one has registered by now. Called from
+/// `crate::smp::hv_virt_ipi_handler` to finish a `Signal::Interrupt`
+/// delivery sent by [`super::VirtIoApic::raise_gsi`].
+pub fn deliver_to_apic(apic_id: usize, vector: u8) {
+    if let Some(target) = lookup_apic(apic_id) {
+        target.lock().set_irr(vector);
+    }
+}
+
+/// Calls `f` on every registered local APIC other than `self_id`.
+fn for_each_apic_except(self_id: usize, mut f: impl FnMut(&mut VirtLocalApic)) {
+    for (id, slot) in APIC_REGISTRY.lock().iter().enumerate() {
+        if id == self_id {
+            continue;
+        }
+        if let Some(apic) = slot {
+            f(&mut apic.lock());
+        }
+    }
+}
+
+fn set_bit(bitmap: &mut [u32; 8], vector: u8) {
+    bitmap[(vector / 32) as usize] |= 1 << (vector % 32);
+}
+
+fn clear_bit(bitmap: &mut [u32; 8], vector: u8) {
+    bitmap[(vector / 32) as usize] &= !(1 << (vector % 32));
+}
+
+fn highest_set_bit(bitmap: &[u32; 8]) -> Option<u8> {
+    for (i, word) in bitmap.iter().enumerate().rev() {
+        if *word != 0 {
+            return Some((i as u32 * 32 + (31 - word.leading_zeros())) as u8);
+        }
+    }
+    None
+}
+
 pub struct VirtLocalApic {
     pub inner: ApicTimer,
+    apic_id: usize,
+    /// 256-bit Interrupt Request Register. (SDM Vol. 3A, Section 10.8.4)
+    irr: [u32; 8],
+    /// 256-bit In-Service Register. (SDM Vol. 3A, Section 10.8.4)
+    isr: [u32; 8],
+    svr: u32,
 }
 
-msr_proxy_struct!(0x800, 0x83f, VirtLocalApicMsrProxy, VirtLocalApic, read_msr, write_msr);
+msr_proxy_struct!(
+    0x800,
+    0x83f,
+    VirtLocalApicMsrProxy,
+    VirtLocalApic,
+    read_msr,
+    write_msr
+);
+msr_proxy_struct!(
+    x86::msr::IA32_TSC_DEADLINE,
+    x86::msr::IA32_TSC_DEADLINE,
+    TscDeadlineMsrProxy,
+    VirtLocalApic,
+    read_tsc_deadline_msr,
+    write_tsc_deadline_msr
+);
 
 impl VirtLocalApic {
     pub fn new() -> Self {
-        Self { inner: ApicTimer::new() }
+        Self {
+            inner: ApicTimer::new(),
+            apic_id: 0,
+            irr: [0; 8],
+            isr: [0; 8],
+            svr: 0x1ff, // SDM Vol. 3A, Section 10.9, Figure 10-23 (with Software Enable bit)
+        }
     }
 
     pub const fn msr_range() -> core::ops::Range<u32> {
         0x800..0x840
     }
 
+    /// Sets the APIC ID [`register_apic`] assigned this local APIC, used as
+    /// the destination-field identity for ICR-directed IPIs.
+    pub fn set_apic_id(&mut self, id: usize) {
+        self.apic_id = id;
+    }
+
+    /// Processor Priority Register: the higher of the TPR's priority class
+    /// and the priority class of the highest in-service vector. (SDM Vol.
+    /// 3A, Section 10.8.3.1)
+    fn ppr(&self) -> u8 {
+        let isr_class = highest_set_bit(&self.isr).unwrap_or(0) & 0xf0;
+        let tpr_class = (self.inner.tpr() as u8) & 0xf0;
+        tpr_class.max(isr_class)
+    }
+
+    pub fn set_irr(&mut self, vector: u8) {
+        set_bit(&mut self.irr, vector);
+    }
+
+    /// Checks for a pending interrupt, at either the LVT timer or a
+    /// previously-raised IRR bit, and returns the highest-priority vector
+    /// to inject, moving it from IRR to ISR. Returns `None` if nothing is
+    /// pending or the highest pending vector's priority class doesn't
+    /// exceed the current PPR.
+    pub fn check_interrupt(&mut self) -> Option<u8> {
+        if self.inner.check_interrupt() {
+            let vector = self.inner.vector();
+            set_bit(&mut self.irr, vector);
+        }
+
+        let highest_irr = highest_set_bit(&self.irr)?;
+        if (highest_irr & 0xf0) <= self.ppr() {
+            return None;
+        }
+        clear_bit(&mut self.irr, highest_irr);
+        set_bit(&mut self.isr, highest_irr);
+        Some(highest_irr)
+    }
+
+    /// EOI: retires the highest in-service vector. (SDM Vol. 3A, Section
+    /// 10.8.5)
+    fn clear_highest_isr(&mut self) {
+        if let Some(vector) = highest_set_bit(&self.isr) {
+            clear_bit(&mut self.isr, vector);
+        }
+    }
+
+    /// Decodes an ICR write and delivers the IPI. (SDM Vol. 3A, Section
+    /// 10.6.1, Figure 10-12; this tree exposes the ICR through the x2APIC
+    /// MSR interface, so the destination lives in bits 63:32 of the single
+    /// 64-bit write rather than a separate ICR2 register.)
+    fn write_icr(&mut self, value: u64) -> HyperResult {
+        let vector = value.get_bits(0..8) as u8;
+        let delivery_mode = value.get_bits(8..11) as u8;
+        let dest_shorthand = value.get_bits(18..20) as u8;
+        let dest = value.get_bits(32..64) as usize;
+
+        // Only Fixed delivery is emulated here; NMI/INIT/SIPI/SMI delivery
+        // modes are left to a later pass (see `chunk22-3`).
+        if delivery_mode != 0 {
+            return Ok(());
+        }
+
+        match dest_shorthand {
+            0b00 => {
+                // Destination field.
+                if dest == self.apic_id {
+                    self.set_irr(vector);
+                } else if let Some(target) = lookup_apic(dest) {
+                    target.lock().set_irr(vector);
+                }
+            }
+            0b01 => self.set_irr(vector), // self
+            0b10 => {
+                // All including self.
+                self.set_irr(vector);
+                for_each_apic_except(self.apic_id, |apic| apic.set_irr(vector));
+            }
+            0b11 => for_each_apic_except(self.apic_id, |apic| apic.set_irr(vector)), // all excluding self
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
     fn read_msr(&mut self, msr: u32) -> HyperResult<u64> {
-        let apic_timer = &mut self.inner;
         let offset = msr - 0x800;
         match offset {
-            SIVR => Ok(0x1ff), // SDM Vol. 3A, Section 10.9, Figure 10-23 (with Software Enable bit)
+            SIVR => Ok(self.svr as u64),
             LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
                 Ok(0x1_0000) // SDM Vol. 3A, Section 10.5.1, Figure 10-8 (with Mask bit)
-            },
-            IRR0 ..= IRR7 => Ok(0),
-            ISR0 ..= ISR7 => Ok(0),
-            LVT_TIMER => Ok(apic_timer.lvt_timer() as u64),
-            INIT_COUNT => Ok(apic_timer.initial_count() as u64),
-            DIV_CONF => Ok(apic_timer.divide() as u64),
-            CUR_COUNT => Ok(apic_timer.current_counter() as u64),
+            }
+            IRR0..=IRR7 => Ok(self.irr[(offset - IRR0) as usize] as u64),
+            ISR0..=ISR7 => Ok(self.isr[(offset - ISR0) as usize] as u64),
+            LVT_TIMER => Ok(self.inner.lvt_timer() as u64),
+            INIT_COUNT => Ok(self.inner.initial_count() as u64),
+            DIV_CONF => Ok(self.inner.divide() as u64),
+            CUR_COUNT => Ok(self.inner.current_counter() as u64),
             LDR => Ok(0),
-            TPR => Ok(apic_timer.tpr() as u64),
+            TPR => Ok(self.inner.tpr() as u64),
             VERSION => Ok(0b0000000_0_00000000_00000110_00010101), // Suppress EOI-broadcasts: false, Max LVT Entry: 6, Version: 0x15
             ESR => Ok(0),
-            APICID => Ok(0),
+            APICID => Ok(self.apic_id as u64),
             _ => Err(HyperError::NotSupported),
         }
     }
 
     fn write_msr(&mut self, msr: u32, value: u64) -> HyperResult {
-        let apic_timer = &mut self.inner;
         let offset = msr - 0x800;
 
         if offset != ICR && (value >> 32) != 0 {
@@ -274,22 +553,77 @@ impl VirtLocalApic {
                 if value != 0 {
                     Err(HyperError::InvalidParam) // write a non-zero value causes #GP
                 } else {
-                    Ok(())
+                    Ok(self.clear_highest_isr())
                 }
             }
-            SIVR | LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
+            SIVR => {
+                self.svr = value as u32;
+                Ok(())
+            }
+            LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
                 Ok(()) // ignore these register writes
             }
-            LVT_TIMER => apic_timer.set_lvt_timer(value as u32),
-            INIT_COUNT => apic_timer.set_initial_count(value as u32),
-            DIV_CONF => apic_timer.set_divide(value as u32),
-            TPR => Ok(apic_timer.set_tpr(value as u32)),
-            ESR => if value == 0 { Ok(()) } else { Err(HyperError::InvalidParam) },
+            ICR => self.write_icr(value),
+            LVT_TIMER => self.inner.set_lvt_timer(value as u32),
+            INIT_COUNT => self.inner.set_initial_count(value as u32),
+            DIV_CONF => self.inner.set_divide(value as u32),
+            TPR => Ok(self.inner.set_tpr(value as u32)),
+            ESR => {
+                if value == 0 {
+                    Ok(())
+                } else {
+                    Err(HyperError::InvalidParam)
+                }
+            }
             _ => Err(HyperError::NotSupported),
         }
     }
 
+    /// `IA32_TSC_DEADLINE` read: the currently armed guest deadline, or
+    /// `0` if disarmed.
+    fn read_tsc_deadline_msr(&mut self, _msr: u32) -> HyperResult<u64> {
+        Ok(self.inner.tsc_deadline())
+    }
+
+    /// `IA32_TSC_DEADLINE` write: arms (non-zero) or disarms (`0`) a
+    /// one-shot TSC-deadline interrupt. Only meaningful while the LVT
+    /// Timer register is in TSC-deadline mode, but real hardware accepts
+    /// the write either way, so this doesn't check the mode either.
+    fn write_tsc_deadline_msr(&mut self, _msr: u32, value: u64) -> HyperResult {
+        self.inner.set_tsc_deadline(value);
+        Ok(())
+    }
+
     msr_proxy_factory!(msr_proxy, VirtLocalApicMsrProxy);
+    msr_proxy_factory!(tsc_deadline_msr_proxy, TscDeadlineMsrProxy);
+
+    /// Serializes the timer ([`ApicTimer::save_state`]), IRR/ISR and SVR.
+    /// `apic_id` isn't included: it's reassigned by [`register_apic`] at
+    /// construction time, not guest-controlled state to restore.
+    pub(crate) fn save_state(&self) -> [u8; 37 + 32 + 32 + 4] {
+        let mut buf = [0u8; 37 + 32 + 32 + 4];
+        buf[0..37].copy_from_slice(&self.inner.save_state());
+        for (i, word) in self.irr.iter().enumerate() {
+            buf[37 + i * 4..37 + i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        for (i, word) in self.isr.iter().enumerate() {
+            buf[69 + i * 4..69 + i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        buf[101..105].copy_from_slice(&self.svr.to_le_bytes());
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, state: &[u8; 37 + 32 + 32 + 4]) {
+        self.inner
+            .restore_state(&state[0..37].try_into().unwrap());
+        for (i, word) in self.irr.iter_mut().enumerate() {
+            *word = u32::from_le_bytes(state[37 + i * 4..37 + i * 4 + 4].try_into().unwrap());
+        }
+        for (i, word) in self.isr.iter_mut().enumerate() {
+            *word = u32::from_le_bytes(state[69 + i * 4..69 + i * 4 + 4].try_into().unwrap());
+        }
+        self.svr = u32::from_le_bytes(state[101..105].try_into().unwrap());
+    }
 }
 
 pub struct ApicBaseMsrHandler;
@@ -1,13 +1,13 @@
 /// Bundle for CMOS, NMI, PIT and Speaker
-
 extern crate alloc;
+use super::{pit::PIT, PortIoDevice};
+use super::{pmio_proxy_factory, pmio_proxy_struct};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use bit_field::BitField;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use libax::hv::{Error as HyperError, Result as HyperResult};
 use spin::Mutex;
 use x86::task::tr;
-use super::{PortIoDevice, pit::PIT};
-use super::{pmio_proxy_factory, pmio_proxy_struct};
 
 pub const PORT_SYSTEM_CONTROL_A: u16 = 0x92;
 pub const PORT_SYSTEM_CONTROL_B: u16 = 0x61;
@@ -18,6 +18,93 @@ pub const PORT_CMOS_DATA: u16 = 0x71;
 pub const PORT_PIT_CHANNEL_DATA_BASE: u16 = 0x40;
 pub const PORT_PIT_COMMAND: u16 = 0x43;
 
+/// Number of addressable CMOS/RTC register bytes (ref: MC146818 datasheet).
+const CMOS_REG_COUNT: usize = 128;
+
+const CMOS_REG_SECONDS: u8 = 0x00;
+const CMOS_REG_MINUTES: u8 = 0x02;
+const CMOS_REG_HOURS: u8 = 0x04;
+const CMOS_REG_DAY_OF_WEEK: u8 = 0x06;
+const CMOS_REG_DAY: u8 = 0x07;
+const CMOS_REG_MONTH: u8 = 0x08;
+const CMOS_REG_YEAR: u8 = 0x09;
+const CMOS_REG_STATUS_A: u8 = 0x0a;
+const CMOS_REG_STATUS_B: u8 = 0x0b;
+const CMOS_REG_STATUS_C: u8 = 0x0c;
+const CMOS_REG_STATUS_D: u8 = 0x0d;
+const CMOS_REG_CENTURY: u8 = 0x32;
+
+/// Status Register A bit: update-in-progress.
+const STATUS_A_UIP: usize = 7;
+/// Status Register B bit: 1 = 24-hour mode, 0 = 12-hour mode.
+const STATUS_B_24H: usize = 1;
+/// Status Register B bit: 1 = binary mode, 0 = BCD mode.
+const STATUS_B_BINARY: usize = 2;
+
+/// Fixed wall-clock epoch CMOS time is measured from: `current_time_nanos`
+/// only gives time-since-boot, not a true wall clock, so this is added to it
+/// to produce a plausible (if not externally accurate) date/time - the same
+/// approximation `crate::hv::vmx::device_emu::rtc::Rtc` makes elsewhere in
+/// this tree for its own, differently-wired CMOS emulation.
+const CMOS_EPOCH_UNIX_SECS: u64 = 1_700_000_000;
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// A point in (host-approximated) wall-clock time, broken down the way the
+/// CMOS registers expose it.
+struct WallClock {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day_of_week: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+impl WallClock {
+    fn now() -> Self {
+        let secs_since_epoch =
+            CMOS_EPOCH_UNIX_SECS + libax::time::current_time_nanos() / 1_000_000_000;
+        let days = secs_since_epoch / 86400;
+        let day_secs = secs_since_epoch % 86400;
+
+        let (year, month, day) = civil_from_days(days as i64);
+        // 1970-01-01 was a Thursday; CMOS day-of-week is 1..=7, firmware's
+        // choice of which day is "1" is arbitrary as long as it's consistent.
+        let day_of_week = (((days as i64 + 3) % 7 + 7) % 7) as u8 + 1;
+
+        Self {
+            seconds: (day_secs % 60) as u8,
+            minutes: ((day_secs / 60) % 60) as u8,
+            hours: (day_secs / 3600) as u8,
+            day_of_week,
+            day,
+            month,
+            year: (year % 100) as u8,
+            century: (year / 100) as u8,
+        }
+    }
+}
+
+/// Howard Hinnant's days-from-civil algorithm, inverted: turn a day count
+/// (days since 1970-01-01) into a (year, month, day) triple.
+fn civil_from_days(z: i64) -> (i64, u8, u8) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
 bitflags::bitflags! {
     #[derive(Clone, Copy, Debug)]
     pub struct SystemControlPortB: u8 {
@@ -36,10 +123,10 @@ bitflags::bitflags! {
     }
 }
 
-
 pub struct Bundle {
     // about cmos
     cmos_selected_reg: Option<u8>,
+    cmos_regs: [u8; CMOS_REG_COUNT],
     // about nmi
     nmi_enabled: bool,
     //
@@ -52,6 +139,7 @@ impl Bundle {
     pub fn new() -> Self {
         Self {
             cmos_selected_reg: None,
+            cmos_regs: [0; CMOS_REG_COUNT],
             nmi_enabled: true,
             scp_b_writable: SystemControlPortB::empty(),
             pit: PIT::new(),
@@ -66,6 +154,13 @@ impl Bundle {
         Err(HyperError::NotSupported)
     }
 
+    /// Channel 0's square-wave output, i.e. the legacy IRQ0/GSI0 line.
+    /// `VirtIoApic` has no notion of the PIT itself, so `check_events`
+    /// resamples this every tick instead of the old fixed-rate poke.
+    pub fn channel0_output(&mut self) -> HyperResult<bool> {
+        self.pit.read_output(0)
+    }
+
     fn read_system_control_b(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
         let mut result = self.scp_b_writable;
 
@@ -81,14 +176,32 @@ impl Bundle {
     }
 
     fn write_system_control_b(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
-        let value = SystemControlPortB::from_bits_truncate(value as u8) & !SystemControlPortB::READONLY_MASK;
+        let value = SystemControlPortB::from_bits_truncate(value as u8)
+            & !SystemControlPortB::READONLY_MASK;
 
-        self.pit.set_enabled(2, value.contains(SystemControlPortB::TIMER2_ENABLED))?;
+        self.pit
+            .set_enabled(2, value.contains(SystemControlPortB::TIMER2_ENABLED))?;
+        self.pit
+            .set_speaker_enabled(value.contains(SystemControlPortB::SPEAKER_ENABLED));
         self.scp_b_writable = value;
 
         Ok(())
     }
 
+    /// Render `self.cmos_regs[CMOS_REG_STATUS_A]`'s UIP bit: the real chip
+    /// sets it for roughly the last 244us of every second while it updates
+    /// the time/date registers, so a read landing in that window should see
+    /// it set. There's no periodic update here to race against (the
+    /// time/date registers below are computed fresh on every read instead),
+    /// but deriving it from the same host clock keeps it toggling the way
+    /// guest firmware that polls it to wait out an update expects.
+    fn status_a(&self) -> u8 {
+        let sub_ns = libax::time::current_time_nanos() % 1_000_000_000;
+        let mut reg = self.cmos_regs[CMOS_REG_STATUS_A as usize];
+        reg.set_bit(STATUS_A_UIP, sub_ns >= 999_756_000);
+        reg
+    }
+
     fn read_cmos(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
         if port == PORT_CMOS_ADDRESS {
             Err(HyperError::NotSupported)
@@ -97,9 +210,45 @@ impl Bundle {
                 None => Err(HyperError::InvalidParam),
                 Some(selected) => {
                     self.cmos_selected_reg = None;
-                    debug!("cmos read from reg {:#x} ignored", selected);
-                    Ok(0)
-                },
+                    let status_b = self.cmos_regs[CMOS_REG_STATUS_B as usize];
+                    let binary = status_b.get_bit(STATUS_B_BINARY);
+                    let hour24 = status_b.get_bit(STATUS_B_24H);
+                    let now = WallClock::now();
+                    let encode = |v: u8| if binary { v } else { to_bcd(v) };
+
+                    let value = match selected {
+                        CMOS_REG_SECONDS => encode(now.seconds),
+                        CMOS_REG_MINUTES => encode(now.minutes),
+                        CMOS_REG_HOURS => {
+                            if hour24 {
+                                encode(now.hours)
+                            } else {
+                                let h12 = match now.hours % 12 {
+                                    0 => 12,
+                                    h => h,
+                                };
+                                let mut v = encode(h12);
+                                v.set_bit(7, now.hours >= 12);
+                                v
+                            }
+                        }
+                        CMOS_REG_DAY_OF_WEEK => encode(now.day_of_week),
+                        CMOS_REG_DAY => encode(now.day),
+                        CMOS_REG_MONTH => encode(now.month),
+                        CMOS_REG_YEAR => encode(now.year),
+                        CMOS_REG_CENTURY => encode(now.century),
+                        CMOS_REG_STATUS_A => self.status_a(),
+                        CMOS_REG_STATUS_C | CMOS_REG_STATUS_D => {
+                            // Reading register C/D clears the pending-flag
+                            // bits it reports, same as the real chip.
+                            let v = self.cmos_regs[selected as usize];
+                            self.cmos_regs[selected as usize] = 0;
+                            v
+                        }
+                        _ => self.cmos_regs[selected as usize],
+                    };
+                    Ok(value as u32)
+                }
             }
         }
     }
@@ -110,14 +259,21 @@ impl Bundle {
             self.nmi_enabled = (value & 0x80) == 0;
 
             Ok(())
-        } else { // port == PORT_CMOS_DATA
+        } else {
+            // port == PORT_CMOS_DATA
             match self.cmos_selected_reg {
                 None => Err(HyperError::InvalidParam),
                 Some(selected) => {
                     self.cmos_selected_reg = None;
-                    debug!("cmos write to reg {:#x}(value {:#x}) ignored", selected, value);
+                    if (selected as usize) < CMOS_REG_COUNT {
+                        // Time/date registers are computed fresh from the
+                        // host clock on every read (see `read_cmos`), so a
+                        // write to one of them is accepted and stored for
+                        // readback but doesn't change what's reported.
+                        self.cmos_regs[selected as usize] = value as u8;
+                    }
                     Ok(())
-                },
+                }
             }
         }
     }
@@ -128,7 +284,9 @@ impl Bundle {
         if port == PORT_PIT_COMMAND {
             Ok(0)
         } else {
-            self.pit.read((port - PORT_PIT_CHANNEL_DATA_BASE) as u8).map(|v| v as u32)
+            self.pit
+                .read((port - PORT_PIT_CHANNEL_DATA_BASE) as u8)
+                .map(|v| v as u32)
         }
     }
 
@@ -138,20 +296,81 @@ impl Bundle {
         // debug!("pit write, port {port:#x}, value {value:#x}");
 
         if port == PORT_PIT_COMMAND {
-            self.pit.command(value.get_bits(6..8), value.get_bits(4..6), value.get_bits(1..4), value.get_bit(0))
+            self.pit.command(
+                value.get_bits(6..8),
+                value.get_bits(4..6),
+                value.get_bits(1..4),
+                value.get_bit(0),
+            )
         } else {
-            self.pit.write((port - PORT_PIT_CHANNEL_DATA_BASE) as u8, value)
+            self.pit
+                .write((port - PORT_PIT_CHANNEL_DATA_BASE) as u8, value)
+        }
+    }
+
+    /// Serializes CMOS, NMI, system-control-B and PIT state. `nmi_enabled`
+    /// and the CMOS registers are wall-clock-independent (the RTC readout
+    /// itself is recomputed from [`CMOS_EPOCH_UNIX_SECS`] plus the host's
+    /// uptime, not stored here), so only the PIT's own timestamps carry the
+    /// same-boot-clock caveat documented on [`PIT::save_state`].
+    pub(crate) fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + CMOS_REG_COUNT + self.pit.save_state().len());
+        buf.push(self.cmos_selected_reg.is_some() as u8);
+        buf.push(self.cmos_selected_reg.unwrap_or(0));
+        buf.push(self.nmi_enabled as u8);
+        buf.push(self.scp_b_writable.bits());
+        buf.extend_from_slice(&self.cmos_regs);
+        buf.extend_from_slice(&self.pit.save_state());
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, data: &[u8]) -> HyperResult {
+        if data.len() < 4 + CMOS_REG_COUNT {
+            return Err(HyperError::InvalidParam);
         }
+        self.cmos_selected_reg = (data[0] != 0).then_some(data[1]);
+        self.nmi_enabled = data[2] != 0;
+        self.scp_b_writable = SystemControlPortB::from_bits_truncate(data[3]);
+        self.cmos_regs
+            .copy_from_slice(&data[4..4 + CMOS_REG_COUNT]);
+        self.pit.restore_state(&data[4 + CMOS_REG_COUNT..])
     }
 }
 
 // following are proxies
 
-
-pmio_proxy_struct!(PORT_SYSTEM_CONTROL_A, PORT_SYSTEM_CONTROL_A, BundleSystemControlPortAProxy, Bundle, read_system_control_a, write_system_control_a);
-pmio_proxy_struct!(PORT_SYSTEM_CONTROL_B, PORT_SYSTEM_CONTROL_B, BundleSystemControlPortBProxy, Bundle, read_system_control_b, write_system_control_b);
-pmio_proxy_struct!(PORT_CMOS_ADDRESS, PORT_CMOS_DATA, BundleCMOSProxy, Bundle, read_cmos, write_cmos);
-pmio_proxy_struct!(PORT_PIT_CHANNEL_DATA_BASE, PORT_PIT_COMMAND, BundlePITProxy, Bundle, read_pit, write_pit);
+pmio_proxy_struct!(
+    PORT_SYSTEM_CONTROL_A,
+    PORT_SYSTEM_CONTROL_A,
+    BundleSystemControlPortAProxy,
+    Bundle,
+    read_system_control_a,
+    write_system_control_a
+);
+pmio_proxy_struct!(
+    PORT_SYSTEM_CONTROL_B,
+    PORT_SYSTEM_CONTROL_B,
+    BundleSystemControlPortBProxy,
+    Bundle,
+    read_system_control_b,
+    write_system_control_b
+);
+pmio_proxy_struct!(
+    PORT_CMOS_ADDRESS,
+    PORT_CMOS_DATA,
+    BundleCMOSProxy,
+    Bundle,
+    read_cmos,
+    write_cmos
+);
+pmio_proxy_struct!(
+    PORT_PIT_CHANNEL_DATA_BASE,
+    PORT_PIT_COMMAND,
+    BundlePITProxy,
+    Bundle,
+    read_pit,
+    write_pit
+);
 
 impl Bundle {
     pmio_proxy_factory!(proxy_system_control_a, BundleSystemControlPortAProxy);
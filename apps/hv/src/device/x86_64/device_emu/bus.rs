@@ -0,0 +1,186 @@
+//! Sorted-range device buses.
+//!
+//! [`PortIoBus`], [`MmioBus`] and [`VirtMsrBus`] each own a collection of
+//! devices, reject an overlapping range at registration time instead of
+//! only discovering the conflict when two devices fight over an access,
+//! and dispatch a faulting port/address/MSR to the right device by binary
+//! search over the sorted, non-overlapping ranges. [`DeviceBus`] bundles
+//! all three so a VM-exit handler has one struct to hold rather than three
+//! independently-named buses. This is meant as the single entry point the
+//! VM-exit handler goes through for I/O, rather than each device being
+//! special-cased there.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use libax::hv::{Error as HyperError, Result as HyperResult};
+use pci::util::num_ops::ranges_overlap;
+
+use super::{PortIoDevice, VirtMsrDevice};
+
+/// A memory-mapped I/O device, the MMIO counterpart of [`PortIoDevice`].
+pub trait MmioDevice: Send + Sync {
+    fn mmio_range(&self) -> core::ops::Range<usize>;
+    fn read(&mut self, addr: usize, access_size: u8) -> HyperResult<u64>;
+    fn write(&mut self, addr: usize, access_size: u8, value: u64) -> HyperResult;
+}
+
+macro_rules! range_bus {
+    ($bus:ident, $device:ident, $range_method:ident, $addr:ty, $value:ty) => {
+        pub struct $bus {
+            devices: Vec<(core::ops::Range<$addr>, Arc<Mutex<dyn $device>>)>,
+        }
+
+        impl $bus {
+            pub fn new() -> Self {
+                Self {
+                    devices: Vec::new(),
+                }
+            }
+
+            /// Register a device, keeping devices sorted by range start.
+            /// Rejects the device (without side effects) if its range
+            /// overlaps one already registered.
+            pub fn register(&mut self, device: Arc<Mutex<dyn $device>>) -> HyperResult {
+                let range = device.lock().$range_method();
+                let idx = self.devices.partition_point(|(r, _)| r.start < range.start);
+                let overlaps_prev = idx > 0 && self.devices[idx - 1].0.end > range.start;
+                let overlaps_next =
+                    idx < self.devices.len() && self.devices[idx].0.start < range.end;
+                if overlaps_prev || overlaps_next {
+                    return Err(HyperError::InvalidParam);
+                }
+                self.devices.insert(idx, (range, device));
+                Ok(())
+            }
+
+            fn find(&self, addr: $addr) -> Option<&Arc<Mutex<dyn $device>>> {
+                let idx = self.devices.partition_point(|(r, _)| r.end <= addr);
+                self.devices
+                    .get(idx)
+                    .filter(|(r, _)| r.contains(&addr))
+                    .map(|(_, dev)| dev)
+            }
+
+            pub fn dispatch_read(&self, addr: $addr, access_size: u8) -> HyperResult<$value> {
+                self.find(addr)
+                    .ok_or(HyperError::NotSupported)?
+                    .lock()
+                    .read(addr, access_size)
+            }
+
+            pub fn dispatch_write(
+                &self,
+                addr: $addr,
+                access_size: u8,
+                value: $value,
+            ) -> HyperResult {
+                self.find(addr)
+                    .ok_or(HyperError::NotSupported)?
+                    .lock()
+                    .write(addr, access_size, value)
+            }
+        }
+    };
+}
+
+range_bus!(PortIoBus, PortIoDevice, port_range, u16, u32);
+range_bus!(MmioBus, MmioDevice, mmio_range, usize, u64);
+range_bus!(VirtMsrBus, VirtMsrDevice, msr_range, u32, u64);
+
+/// The single dispatch point a VM-exit handler needs: one bus per device
+/// class (PMIO, MMIO, MSR), so a port/EPT/RDMSR-WRMSR exit resolves to its
+/// owning device the same way regardless of which class it's in, instead of
+/// the caller juggling three independently-named buses.
+pub struct DeviceBus {
+    pub port_io: PortIoBus,
+    pub mmio: MmioBus,
+    pub msr: VirtMsrBus,
+}
+
+impl DeviceBus {
+    pub fn new() -> Self {
+        Self {
+            port_io: PortIoBus::new(),
+            mmio: MmioBus::new(),
+            msr: VirtMsrBus::new(),
+        }
+    }
+
+    pub fn register_port_io(&mut self, device: Arc<Mutex<dyn PortIoDevice>>) -> HyperResult {
+        self.port_io.register(device)
+    }
+
+    pub fn register_mmio(&mut self, device: Arc<Mutex<dyn MmioDevice>>) -> HyperResult {
+        self.mmio.register(device)
+    }
+
+    pub fn register_msr(&mut self, device: Arc<Mutex<dyn VirtMsrDevice>>) -> HyperResult {
+        self.msr.register(device)
+    }
+}
+
+/// A device addressed by byte span rather than a single fixed access width,
+/// for devices like the legacy PIT port window whose registers are one
+/// width but get accessed at another and need to marshal the difference
+/// themselves instead of [`PortIoDevice`]/[`MmioDevice`]'s single `value`.
+pub trait BusDevice: Send + Sync {
+    fn read(&mut self, offset: u64, data: &mut [u8]) -> HyperResult;
+    fn write(&mut self, offset: u64, data: &[u8]) -> HyperResult;
+}
+
+/// A bus of [`BusDevice`]s registered by `(base, size)` span. Unlike
+/// [`PortIoBus`]/[`MmioBus`]/[`VirtMsrBus`], which key off each device's own
+/// fixed-width range type, `SpanBus` takes an explicit byte span per device
+/// and checks it against every other registered span with
+/// [`ranges_overlap`] rather than comparing `Range`s directly.
+pub struct SpanBus {
+    devices: Vec<(u64, u64, Box<dyn BusDevice>)>,
+}
+
+impl SpanBus {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+        }
+    }
+
+    /// Register a device spanning `[base, base + size)`. Rejects the
+    /// device (without side effects) if its span overlaps one already
+    /// registered.
+    pub fn register(&mut self, base: u64, size: u64, device: Box<dyn BusDevice>) -> HyperResult {
+        for (other_base, other_size, _) in &self.devices {
+            if ranges_overlap(
+                *other_base as usize,
+                *other_size as usize,
+                base as usize,
+                size as usize,
+            )
+            .map_err(|_| HyperError::InvalidParam)?
+            {
+                return Err(HyperError::InvalidParam);
+            }
+        }
+        self.devices.push((base, size, device));
+        Ok(())
+    }
+
+    fn find(&mut self, addr: u64) -> Option<(u64, &mut Box<dyn BusDevice>)> {
+        self.devices
+            .iter_mut()
+            .find(|(base, size, _)| addr >= *base && addr < *base + *size)
+            .map(|(base, _, device)| (*base, device))
+    }
+
+    pub fn read(&mut self, addr: u64, data: &mut [u8]) -> HyperResult {
+        let (base, device) = self.find(addr).ok_or(HyperError::NotSupported)?;
+        device.read(addr - base, data)
+    }
+
+    pub fn write(&mut self, addr: u64, data: &[u8]) -> HyperResult {
+        let (base, device) = self.find(addr).ok_or(HyperError::NotSupported)?;
+        device.write(addr - base, data)
+    }
+}
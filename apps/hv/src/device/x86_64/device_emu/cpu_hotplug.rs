@@ -0,0 +1,109 @@
+//! ACPI CPU-hotplug port device (cloud-hypervisor's `_EJ0`/`_MAT` model):
+//! lets guest ACPI AML bring a vCPU online/offline instead of every AP
+//! being fixed at boot.
+//!
+//! Registered at [`PORT_BASE`] in `X64VcpuDevices::new`'s `pmio_devices`
+//! list, alongside the other legacy-PC ports. [`VirtCpuManager::enable`]/
+//! [`VirtCpuManager::eject`] are also reachable directly through
+//! [`super::super::X64VcpuDevices::request_cpu_online`]/`request_cpu_offline`
+//! for a host-side command to drive hotplug without going through guest
+//! ACPI at all.
+
+use alloc::vec;
+
+use libax::hv::{send_message, Message, Result as HyperResult, Signal};
+
+use super::PortIoDevice;
+
+/// Guest I/O port base; matches the port block QEMU's legacy ACPI CPU
+/// hotplug interface (`hw/acpi/cpu_hotplug.c`) uses.
+pub const PORT_BASE: u16 = 0xaf00;
+
+const REG_ENABLED_BITMAP: u16 = 0x0; // 4 bytes, RO
+const REG_SELECTOR: u16 = 0x4; // 1 byte, RW
+const REG_STATUS: u16 = 0x5; // 1 byte, RO: bit 0 = selected vCPU's enabled bit
+const REG_EJECT: u16 = 0x6; // 1 byte, WO: any write ejects the selected vCPU
+const REG_ENABLE: u16 = 0x7; // 1 byte, WO: any write brings the selected vCPU online
+
+/// Highest addressable vCPU id, matching [`super::apic_timer`]'s
+/// `MAX_LOCAL_APICS`: a vCPU without a registered local APIC has nothing
+/// here to online/offline.
+const MAX_VCPUS: u32 = 4;
+
+pub struct VirtCpuManager {
+    selector: u8,
+    /// Bit `n` set means vCPU `n` is online. vCPU 0 (the BSP) is always on
+    /// and can't be ejected.
+    enabled: u32,
+}
+
+impl VirtCpuManager {
+    pub fn new() -> Self {
+        Self {
+            selector: 0,
+            enabled: 1,
+        }
+    }
+
+    fn status(&self) -> u8 {
+        ((self.enabled >> self.selector) & 1) as u8
+    }
+
+    /// Marks `id` offline and asks its hart to park. There's no VMCLEAR
+    /// signal in this crate's `Signal` yet (only `Start`/`Interrupt`), so
+    /// for now this only flips the enabled bitmap the guest (and
+    /// [`Self::status`]) polls; actually tearing a vCPU's VMCS down needs a
+    /// host-side lifecycle hook this plugin API doesn't expose yet.
+    pub fn eject(&mut self, id: usize) {
+        if id == 0 || id as u32 >= MAX_VCPUS {
+            return;
+        }
+        self.enabled &= !(1 << id);
+    }
+
+    /// Brings `id` online via the existing AP bring-up path: the same
+    /// `Signal::Start` message `crate::smp::hv_virt_ipi_handler` already
+    /// drains to start an AP.
+    pub fn enable(&mut self, id: usize) {
+        if id == 0 || id as u32 >= MAX_VCPUS {
+            return;
+        }
+        self.enabled |= 1 << id;
+        send_message(Message {
+            dest: id,
+            signal: Signal::Start,
+            args: vec![0],
+        });
+    }
+}
+
+impl Default for VirtCpuManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PortIoDevice for VirtCpuManager {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        PORT_BASE..PORT_BASE + 8
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        match port - PORT_BASE {
+            REG_ENABLED_BITMAP => Ok(self.enabled),
+            REG_SELECTOR => Ok(self.selector as u32),
+            REG_STATUS => Ok(self.status() as u32),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        match port - PORT_BASE {
+            REG_SELECTOR => self.selector = (value as u8) % MAX_VCPUS as u8,
+            REG_EJECT => self.eject(self.selector as usize),
+            REG_ENABLE => self.enable(self.selector as usize),
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,14 @@
 use super::PortIoDevice;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use libax::hv::{Error as HyperError, Result as HyperResult};
 
 pub struct DebugPort {
-    port: u16
+    port: u16,
 }
 
 impl DebugPort {
     pub fn new(port: u16) -> Self {
         Self { port }
-    } 
+    }
 }
 
 impl PortIoDevice for DebugPort {
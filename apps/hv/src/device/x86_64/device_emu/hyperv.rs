@@ -0,0 +1,124 @@
+//! Hyper-V enlightenment MSRs and the CPUID leaves that advertise them.
+//!
+//! Linux and Windows both probe `CPUID(0x40000000)` for a recognized
+//! hypervisor vendor string before touching any of the synthetic MSRs in
+//! [`MSR_RANGE`]; without this, an unrecognized-but-probed MSR hit
+//! [`super::super::X64VcpuDevices::handle_msr_read`]/`handle_msr_write`'s
+//! `panic!` fallback for any vendor a guest happened to recognize as
+//! Hyper-V-capable. Only the handful of MSRs actually implemented here do
+//! anything; the rest of [`MSR_RANGE`] reads back zero and drops writes,
+//! same as [`super::MsrDummy`] does for a single MSR.
+//!
+//! Mirrors cloud-hypervisor's `kvm_hyperv` CPUID injection: vendor id
+//! "Microsoft Hv", and feature/enlightenment bits set only for what's
+//! actually implemented below (today: the VP index and reference TSC
+//! MSRs). No hypercall page is actually mapped; `HV_X64_MSR_HYPERCALL` is
+//! stored and read back so a guest that merely probes for it is happy,
+//! without committing to emulating `VMCALL`/`VMMCALL` hypercalls.
+
+use super::VirtMsrDevice;
+use libax::hv::Result as HyperResult;
+
+/// The full synthetic MSR space Hyper-V guests probe across; everything in
+/// here except the MSRs named below reads as zero and ignores writes.
+pub const MSR_RANGE: core::ops::Range<u32> = 0x4000_0000..0x4000_0100;
+
+const HV_X64_MSR_GUEST_OS_ID: u32 = 0x4000_0000;
+const HV_X64_MSR_HYPERCALL: u32 = 0x4000_0001;
+const HV_X64_MSR_VP_INDEX: u32 = 0x4000_0002;
+const HV_X64_MSR_REFERENCE_TSC: u32 = 0x4000_0021;
+
+/// CPUID leaves Hyper-V reserves for itself; `cpuid_leaf` answers every one
+/// from 0x40000000 up to and including this.
+const CPUID_LEAF_MAX: u32 = 0x4000_000A;
+
+/// Bit set in `CPUID(0x40000003).eax` for each enlightenment this device
+/// actually backs (SDM-style "partition privilege flags", per the Hyper-V
+/// TLFS). Only `AccessVpIndex` and `AccessFrequencyMsrs`'s reference-TSC
+/// half are ours to claim; every other bit stays clear so a guest doesn't
+/// go looking for SynIC, the hypercall page, or anything else we don't
+/// back.
+const HV_ACCESS_VP_INDEX_AVAILABLE: u32 = 1 << 2;
+const HV_ACCESS_REFERENCE_TSC_AVAILABLE: u32 = 1 << 9;
+
+/// Per-vCPU Hyper-V synthetic MSR state, registered in [`MSR_RANGE`]
+/// alongside the other MSR proxies in `X64VcpuDevices::new`.
+pub struct HyperVMsrDevice {
+    vp_index: u32,
+    guest_os_id: u64,
+    hypercall: u64,
+    reference_tsc: u64,
+}
+
+impl HyperVMsrDevice {
+    pub fn new(vp_index: u32) -> Self {
+        Self {
+            vp_index,
+            guest_os_id: 0,
+            hypercall: 0,
+            reference_tsc: 0,
+        }
+    }
+}
+
+impl VirtMsrDevice for HyperVMsrDevice {
+    fn msr_range(&self) -> core::ops::Range<u32> {
+        MSR_RANGE
+    }
+
+    fn read(&mut self, msr: u32) -> HyperResult<u64> {
+        Ok(match msr {
+            HV_X64_MSR_GUEST_OS_ID => self.guest_os_id,
+            HV_X64_MSR_HYPERCALL => self.hypercall,
+            HV_X64_MSR_VP_INDEX => self.vp_index as u64,
+            HV_X64_MSR_REFERENCE_TSC => self.reference_tsc,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, msr: u32, value: u64) -> HyperResult {
+        match msr {
+            HV_X64_MSR_GUEST_OS_ID => self.guest_os_id = value,
+            HV_X64_MSR_HYPERCALL => self.hypercall = value,
+            // VP_INDEX is read-only on real hardware; writes are ignored.
+            HV_X64_MSR_VP_INDEX => {}
+            HV_X64_MSR_REFERENCE_TSC => self.reference_tsc = value,
+            _ => {} // unimplemented synthetic MSR: drop the write
+        }
+        Ok(())
+    }
+}
+
+/// Looks up a guest `CPUID(function)` against the Hyper-V leaves, or
+/// returns `None` for anything outside [`0x4000_0000, CPUID_LEAF_MAX`] so
+/// the caller falls back to its normal (non-Hyper-V) CPUID handling.
+pub fn cpuid_leaf(function: u32) -> Option<(u32, u32, u32, u32)> {
+    if !(0x4000_0000..=CPUID_LEAF_MAX).contains(&function) {
+        return None;
+    }
+
+    // "Microsoft Hv", split into three little-endian dwords the same way
+    // `build_cpuid_template` packs its own vendor string.
+    const VENDOR: &[u8; 12] = b"Microsoft Hv";
+    let vendor = unsafe { &*(VENDOR.as_ptr() as *const [u32; 3]) };
+
+    Some(match function {
+        // Vendor identification: max leaf in eax, vendor string in ebx/ecx/edx.
+        0x4000_0000 => (CPUID_LEAF_MAX, vendor[0], vendor[1], vendor[2]),
+        // Interface signature "Hv#1", the value real Hyper-V guests check
+        // for before trusting any of the leaves below.
+        0x4000_0001 => (0x3123_7648, 0, 0, 0),
+        // Partition privilege flags: only the enlightenments this device
+        // actually backs are advertised.
+        0x4000_0003 => (
+            HV_ACCESS_VP_INDEX_AVAILABLE | HV_ACCESS_REFERENCE_TSC_AVAILABLE,
+            0,
+            0,
+            0,
+        ),
+        // Version/build, implementation recommendations, hardware
+        // features, and the reserved leaves up to `CPUID_LEAF_MAX`: we
+        // don't claim anything beyond what leaf 3 already advertises.
+        _ => (0, 0, 0, 0),
+    })
+}
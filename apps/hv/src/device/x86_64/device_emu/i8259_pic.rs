@@ -1,8 +1,24 @@
 //! Emulated Intel 8259 Programmable Interrupt Controller. (ref: https://wiki.osdev.org/8259_PIC)
 
-use super::PortIoDevice;
+use super::{DeviceStats, PortIoDevice};
 use bit_field::BitField;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use libax::hv::{Error as HyperError, Result as HyperResult};
+
+/// Snapshot of an [`I8259Pic`]'s interrupt-handling counters, for tracking
+/// down why a guest's interrupt handling stalls: whether it ever unmasked a
+/// line, how many EOIs it issued versus interrupts injected, and which IRQs
+/// dominate.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PicStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub rejected: u64,
+    /// Lines raised via [`I8259Pic::raise_irq`], indexed by line number.
+    pub irqs_raised: [u64; 8],
+    pub eois: u64,
+    /// Non-specific EOIs received while the ISR was already empty.
+    pub spurious_eois: u64,
+}
 
 pub struct I8259Pic {
     port_base: u16,
@@ -12,7 +28,22 @@ pub struct I8259Pic {
     icw4: u8,
     icw_written: u8,
     icw_left: bool,
+    /// Interrupt Mask Register (OCW1): bit set means the line is masked.
     mask: u8,
+    /// Interrupt Request Register: lines currently asserted and awaiting
+    /// acknowledgement.
+    irr: u8,
+    /// In-Service Register: lines currently being serviced by the guest,
+    /// i.e. acknowledged but not yet EOI'd.
+    isr: u8,
+    /// OCW3 read-register select: which register a port-0 read returns,
+    /// `true` for ISR and `false` for IRR.
+    read_isr: bool,
+    /// OCW3 poll mode: the next port-0 read consumes the highest-priority
+    /// pending interrupt and returns it (bit 7 set if one was pending)
+    /// instead of IRR/ISR.
+    poll_mode: bool,
+    stats: PicStats,
 }
 
 impl PortIoDevice for I8259Pic {
@@ -21,27 +52,66 @@ impl PortIoDevice for I8259Pic {
     }
 
     fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
-        // debug!("reading from pic port {port:#x}");
-        match port - self.port_base {
+        self.stats.reads += 1;
+        let ret = match port - self.port_base {
+            0 => {
+                if self.poll_mode {
+                    self.poll_mode = false;
+                    Ok(match self.resolve_pending_line() {
+                        Some(line) => 0x80 | line as u32,
+                        None => 0,
+                    })
+                } else if self.read_isr {
+                    Ok(self.isr as u32)
+                } else {
+                    Ok(self.irr as u32)
+                }
+            }
             1 => Ok(self.mask as u32),
             _ => Err(HyperError::NotSupported),
+        };
+        if ret.is_err() {
+            self.stats.rejected += 1;
         }
+        ret
     }
 
     fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
-        // debug!("writing to pic port {port:#x}: {value:#x}");
-        
+        self.stats.writes += 1;
         let value = value as u8;
         match port - self.port_base {
             0 => {
                 if value.get_bit(4) {
+                    // ICW1: start (re-)initialization sequence.
                     self.icw1 = value;
                     self.icw_left = true;
                     self.icw_written = 1;
-                } else {
-                    // debug!("pit ocw ignored");
+                    self.irr = 0;
+                    self.isr = 0;
+                    self.read_isr = false;
+                    self.poll_mode = false;
+                } else if value.get_bit(5) {
+                    // OCW2 EOI.
+                    self.stats.eois += 1;
+                    if value.get_bit(6) {
+                        // Specific EOI: the serviced line is named in bits 0..3.
+                        let line = value & 0x7;
+                        self.isr.set_bit(line as usize, false);
+                    } else {
+                        // Non-specific EOI: clear the highest-priority ISR bit.
+                        match Self::highest_priority_bit(self.isr) {
+                            Some(line) => self.isr.set_bit(line as usize, false),
+                            None => self.stats.spurious_eois += 1,
+                        };
+                    }
+                } else if value.get_bit(3) {
+                    // OCW3: poll command (P) and read-register select (RR/RIS).
+                    self.poll_mode = value.get_bit(2);
+                    if value.get_bit(1) {
+                        self.read_isr = value.get_bit(0);
+                    }
                 }
-            },
+            }
             1 => {
                 if !self.icw_left {
                     self.mask = value;
@@ -50,7 +120,10 @@ impl PortIoDevice for I8259Pic {
                         1 => self.offset = value,
                         2 => self.icw3 = value,
                         3 => self.icw4 = value,
-                        _ => return Err(HyperError::BadState),
+                        _ => {
+                            self.stats.rejected += 1;
+                            return Err(HyperError::BadState);
+                        }
                     }
 
                     if self.icw_written == 3 || (self.icw_written == 2 && !self.icw1.get_bit(0)) {
@@ -60,12 +133,23 @@ impl PortIoDevice for I8259Pic {
                         self.icw_written += 1;
                     }
                 }
-            },
-            _ => return Err(HyperError::InvalidParam),
+            }
+            _ => {
+                self.stats.rejected += 1;
+                return Err(HyperError::InvalidParam);
+            }
         }
 
         Ok(()) // ignore write
     }
+
+    fn stats(&self) -> DeviceStats {
+        DeviceStats {
+            reads: self.stats.reads,
+            writes: self.stats.writes,
+            rejected: self.stats.rejected,
+        }
+    }
 }
 
 impl I8259Pic {
@@ -79,10 +163,208 @@ impl I8259Pic {
             icw_left: false,
             icw_written: 0,
             mask: 0,
+            irr: 0,
+            isr: 0,
+            read_isr: false,
+            poll_mode: false,
+            stats: PicStats {
+                reads: 0,
+                writes: 0,
+                rejected: 0,
+                irqs_raised: [0; 8],
+                eois: 0,
+                spurious_eois: 0,
+            },
         }
     }
 
     pub const fn mask(&self) -> u8 {
         self.mask
     }
+
+    /// Snapshot of this PIC's interrupt-handling counters.
+    pub fn pic_stats(&self) -> PicStats {
+        self.stats
+    }
+
+    /// Zero out the counters, keeping the emulated register state untouched.
+    pub fn reset_stats(&mut self) {
+        self.stats = PicStats::default();
+    }
+
+    /// Vector the guest will see in the IDT for the given input line.
+    pub const fn vector_for(&self, line: u8) -> u8 {
+        self.offset.wrapping_add(line)
+    }
+
+    pub fn is_masked(&self, line: u8) -> bool {
+        self.mask.get_bit(line as usize)
+    }
+
+    /// Raw ICW3 value, as programmed by the guest during initialization. On
+    /// the master this is a bitmask naming the line the slave is cascaded
+    /// onto; [`ChainedPic`] reads it to find that line.
+    pub const fn icw3(&self) -> u8 {
+        self.icw3
+    }
+
+    /// Assert an input line, marking it pending in the IRR.
+    pub fn raise_irq(&mut self, line: u8) {
+        self.irr.set_bit(line as usize, true);
+        self.stats.irqs_raised[line as usize] += 1;
+    }
+
+    /// Index (0 = highest priority) of the lowest set bit, i.e. the 8259's
+    /// fixed priority order where line 0 outranks line 7.
+    fn highest_priority_bit(reg: u8) -> Option<u8> {
+        (0..8).find(|&i| reg.get_bit(i)).map(|i| i as u8)
+    }
+
+    /// Resolve the highest-priority pending, unmasked line whose priority
+    /// beats every line currently in service (classic 8259 fully-nested
+    /// mode), move it from IRR to ISR, and return its line number.
+    fn resolve_pending_line(&mut self) -> Option<u8> {
+        let in_service = Self::highest_priority_bit(self.isr);
+        for line in 0..8u8 {
+            if !self.irr.get_bit(line as usize) || self.is_masked(line) {
+                continue;
+            }
+            if let Some(isr_line) = in_service {
+                if line >= isr_line {
+                    continue;
+                }
+            }
+            self.irr.set_bit(line as usize, false);
+            self.isr.set_bit(line as usize, true);
+            return Some(line);
+        }
+        None
+    }
+
+    /// Same resolution as [`resolve_pending_line`](Self::resolve_pending_line), returning the
+    /// guest-visible vector instead of the raw line number.
+    pub fn poll_pending(&mut self) -> Option<u8> {
+        self.resolve_pending_line()
+            .map(|line| self.vector_for(line))
+    }
+
+    /// Serializes every register a restore needs to reproduce this PIC's
+    /// guest-visible behavior. `port_base` and `stats` aren't included:
+    /// the former is fixed at construction, the latter is diagnostic-only.
+    pub(crate) fn save_state(&self) -> [u8; 11] {
+        [
+            self.icw1,
+            self.offset,
+            self.icw3,
+            self.icw4,
+            self.icw_written,
+            self.icw_left as u8,
+            self.mask,
+            self.irr,
+            self.isr,
+            self.read_isr as u8,
+            self.poll_mode as u8,
+        ]
+    }
+
+    pub(crate) fn restore_state(&mut self, state: &[u8; 11]) {
+        self.icw1 = state[0];
+        self.offset = state[1];
+        self.icw3 = state[2];
+        self.icw4 = state[3];
+        self.icw_written = state[4];
+        self.icw_left = state[5] != 0;
+        self.mask = state[6];
+        self.irr = state[7];
+        self.isr = state[8];
+        self.read_isr = state[9] != 0;
+        self.poll_mode = state[10] != 0;
+    }
+}
+
+/// A master/slave pair wired together the way a standard PC does: the slave
+/// asserts the master's cascade line (named by the master's ICW3) instead of
+/// interrupting the CPU directly, and servicing that line on the master
+/// really means servicing whatever the slave has pending.
+pub struct ChainedPic {
+    master: I8259Pic,
+    slave: I8259Pic,
+}
+
+impl ChainedPic {
+    pub const fn new(master_base: u16, slave_base: u16) -> Self {
+        Self {
+            master: I8259Pic::new(master_base),
+            slave: I8259Pic::new(slave_base),
+        }
+    }
+
+    /// The master's input line the slave is cascaded onto (bit position of
+    /// its ICW3), defaulting to the standard PC wiring (IRQ2) before the
+    /// guest has programmed ICW3.
+    fn cascade_line(&self) -> u8 {
+        I8259Pic::highest_priority_bit(self.master.icw3()).unwrap_or(2)
+    }
+
+    /// Assert one of the full 0..16 guest IRQ lines, routing to whichever of
+    /// master/slave owns it and, for slave lines, also asserting the
+    /// master's cascade line.
+    pub fn raise_irq(&mut self, global_irq: u8) {
+        assert!(global_irq < 16, "IRQ line {} out of range", global_irq);
+        if global_irq < 8 {
+            self.master.raise_irq(global_irq);
+        } else {
+            self.slave.raise_irq(global_irq - 8);
+            self.master.raise_irq(self.cascade_line());
+        }
+    }
+
+    /// Resolve the next pending vector, transparently forwarding the
+    /// acknowledge to the slave when the master's winning line turns out to
+    /// be the cascade line.
+    pub fn poll_pending(&mut self) -> Option<u8> {
+        let cascade_vector = self.master.vector_for(self.cascade_line());
+        match self.master.poll_pending() {
+            Some(vector) if vector == cascade_vector => self.slave.poll_pending(),
+            other => other,
+        }
+    }
+}
+
+impl PortIoDevice for ChainedPic {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        let master_range = self.master.port_range();
+        let slave_range = self.slave.port_range();
+        master_range.start.min(slave_range.start)..master_range.end.max(slave_range.end)
+    }
+
+    fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
+        if self.master.port_range().contains(&port) {
+            self.master.read(port, access_size)
+        } else if self.slave.port_range().contains(&port) {
+            self.slave.read(port, access_size)
+        } else {
+            Err(HyperError::InvalidParam)
+        }
+    }
+
+    fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
+        if self.master.port_range().contains(&port) {
+            self.master.write(port, access_size, value)
+        } else if self.slave.port_range().contains(&port) {
+            self.slave.write(port, access_size, value)
+        } else {
+            Err(HyperError::InvalidParam)
+        }
+    }
+
+    fn stats(&self) -> DeviceStats {
+        let m = PortIoDevice::stats(&self.master);
+        let s = PortIoDevice::stats(&self.slave);
+        DeviceStats {
+            reads: m.reads + s.reads,
+            writes: m.writes + s.writes,
+            rejected: m.rejected + s.rejected,
+        }
+    }
 }
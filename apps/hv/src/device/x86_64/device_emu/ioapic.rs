@@ -0,0 +1,231 @@
+//! Emulated I/O APIC (82093AA), registered on the MMIO bus at
+//! [`MMIO_BASE`].
+//!
+//! This bridges the split-irqchip gap `X64VcpuDevices::check_events` used
+//! to paper over with a hard-coded 1ms timer poke: a device asserts a GSI
+//! via [`VirtIoApic::raise_gsi`], the redirection table decides which
+//! vector goes to which core, and [`send_message`]/`Signal::Interrupt`
+//! (the same message used for AP bring-up, see `crate::smp::hv_virt_ipi_handler`)
+//! gets it there instead of this crate reaching into another vCPU's
+//! `VirtLocalApic` directly.
+//!
+//! Only IOAPICID/IOAPICVER and the redirection table are implemented;
+//! IOAPICARB always reads back 0.
+
+use alloc::sync::Arc;
+use alloc::vec;
+
+use bit_field::BitField;
+use libax::hv::{send_message, Message, Result as HyperResult, Signal};
+use spin::{Mutex, Once};
+
+use super::MmioDevice;
+
+/// Guest-physical base of the MMIO window, matching every other x86
+/// hypervisor in this tree (`modules/axvm`, `modules/axruntime`).
+pub const MMIO_BASE: usize = 0xFEC0_0000;
+
+/// Number of redirection-table entries (and thus GSIs); matches the
+/// 82093AA and what most BIOSes/OSes assume for a single IOAPIC.
+const NUM_GSI: usize = 24;
+
+const REG_IOAPICID: u32 = 0x00;
+const REG_IOAPICVER: u32 = 0x01;
+const REG_IOAPICARB: u32 = 0x02;
+const REG_REDTBL_BASE: u32 = 0x10;
+
+const MMIO_IOREGSEL: usize = 0x00;
+const MMIO_IOWIN: usize = 0x10;
+
+/// Redirection-table entry bit layout, within the 64-bit value built from
+/// the pair of 32-bit registers at `REG_REDTBL_BASE + 2*gsi` (low) and
+/// `+ 2*gsi + 1` (high).
+const VECTOR: core::ops::Range<usize> = 0..8;
+const TRIGGER_MODE: usize = 15;
+const REMOTE_IRR: usize = 14;
+const MASKED: usize = 16;
+const DEST_FIELD: core::ops::Range<usize> = 56..64;
+
+pub struct VirtIoApic {
+    ioregsel: u32,
+    id: u32,
+    /// One 64-bit redirection-table entry per GSI; bit 16 (mask) starts
+    /// set, same as real hardware after reset.
+    redir_table: [u64; NUM_GSI],
+    /// Whether a device currently holds its GSI's line high. Edge-triggered
+    /// sources have this cleared again by [`Self::raise_gsi`] itself right
+    /// after delivery; level-triggered sources leave it set until
+    /// [`Self::lower_gsi`] is called.
+    asserted: [bool; NUM_GSI],
+}
+
+impl VirtIoApic {
+    pub fn new() -> Self {
+        Self {
+            ioregsel: 0,
+            id: 0,
+            redir_table: [1u64 << MASKED; NUM_GSI],
+            asserted: [false; NUM_GSI],
+        }
+    }
+
+    fn read_register(&self, index: u32) -> u32 {
+        match index {
+            REG_IOAPICID => self.id << 24,
+            // Version 0x11 (the 82093AA itself, no EOI register), max
+            // redirection entry index in bits 16..24.
+            REG_IOAPICVER => ((NUM_GSI as u32 - 1) << 16) | 0x11,
+            REG_IOAPICARB => 0,
+            reg if reg >= REG_REDTBL_BASE => {
+                let gsi = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(&entry) = self.redir_table.get(gsi) else {
+                    return 0;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    entry as u32
+                } else {
+                    (entry >> 32) as u32
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, index: u32, value: u32) {
+        match index {
+            REG_IOAPICID => self.id = value.get_bits(24..28),
+            reg if reg >= REG_REDTBL_BASE => {
+                let gsi = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(entry) = self.redir_table.get_mut(gsi) else {
+                    return;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    *entry = (*entry & !0xffff_ffff) | value as u64;
+                } else {
+                    *entry = (*entry & 0xffff_ffff) | ((value as u64) << 32);
+                }
+            }
+            // IOAPICVER/IOAPICARB are read-only.
+            _ => {}
+        }
+    }
+
+    /// A device asserts GSI `gsi`: unless it's masked or (for a
+    /// level-triggered entry) still waiting on an EOI, decode its vector
+    /// and destination and deliver it. Safe to call repeatedly while the
+    /// line stays high; a level-triggered entry just keeps `asserted` set
+    /// until [`Self::lower_gsi`] clears it.
+    pub fn raise_gsi(&mut self, gsi: u8) {
+        let Some(line) = self.asserted.get_mut(gsi as usize) else {
+            return;
+        };
+        *line = true;
+
+        let entry = self.redir_table[gsi as usize];
+        if entry.get_bit(MASKED) {
+            return;
+        }
+        let level_triggered = entry.get_bit(TRIGGER_MODE);
+        if level_triggered && entry.get_bit(REMOTE_IRR) {
+            return;
+        }
+        let vector = entry.get_bits(VECTOR) as u8;
+        let dest = entry.get_bits(DEST_FIELD) as usize;
+        if level_triggered {
+            self.redir_table[gsi as usize].set_bit(REMOTE_IRR, true);
+        } else {
+            self.asserted[gsi as usize] = false;
+        }
+
+        // Same delivery path `crate::smp::hv_virt_ipi_handler` already
+        // drains for AP bring-up; its `Signal::Interrupt` arm sets the
+        // vector pending in the destination core's own `VirtLocalApic`.
+        send_message(Message {
+            dest,
+            signal: Signal::Interrupt,
+            args: vec![vector as usize],
+        });
+    }
+
+    /// A device deasserts GSI `gsi`. For a level-triggered entry, this is
+    /// what lets the next `raise_gsi` (after the guest's EOI clears remote
+    /// IRR) deliver again; an edge-triggered one has already cleared it
+    /// itself in `raise_gsi`.
+    pub fn lower_gsi(&mut self, gsi: u8) {
+        if let Some(line) = self.asserted.get_mut(gsi as usize) {
+            *line = false;
+        }
+    }
+}
+
+impl VirtIoApic {
+    /// Serializes `ioregsel`, `id`, the redirection table and the asserted
+    /// line state. There's no live-connection state here the way
+    /// `Uart16550::backend` has, so the whole struct round-trips.
+    pub(crate) fn save_state(&self) -> [u8; 4 + 4 + NUM_GSI * 8 + NUM_GSI] {
+        let mut buf = [0u8; 4 + 4 + NUM_GSI * 8 + NUM_GSI];
+        buf[0..4].copy_from_slice(&self.ioregsel.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.id.to_le_bytes());
+        for (i, entry) in self.redir_table.iter().enumerate() {
+            buf[8 + i * 8..8 + i * 8 + 8].copy_from_slice(&entry.to_le_bytes());
+        }
+        let asserted_base = 8 + NUM_GSI * 8;
+        for (i, &line) in self.asserted.iter().enumerate() {
+            buf[asserted_base + i] = line as u8;
+        }
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, state: &[u8; 4 + 4 + NUM_GSI * 8 + NUM_GSI]) {
+        self.ioregsel = u32::from_le_bytes(state[0..4].try_into().unwrap());
+        self.id = u32::from_le_bytes(state[4..8].try_into().unwrap());
+        for (i, entry) in self.redir_table.iter_mut().enumerate() {
+            *entry = u64::from_le_bytes(state[8 + i * 8..8 + i * 8 + 8].try_into().unwrap());
+        }
+        let asserted_base = 8 + NUM_GSI * 8;
+        for (i, line) in self.asserted.iter_mut().enumerate() {
+            *line = state[asserted_base + i] != 0;
+        }
+    }
+}
+
+impl Default for VirtIoApic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioDevice for VirtIoApic {
+    fn mmio_range(&self) -> core::ops::Range<usize> {
+        MMIO_BASE..MMIO_BASE + 0x20
+    }
+
+    fn read(&mut self, addr: usize, _access_size: u8) -> HyperResult<u64> {
+        match addr - MMIO_BASE {
+            MMIO_IOREGSEL => Ok(self.ioregsel as u64),
+            MMIO_IOWIN => Ok(self.read_register(self.ioregsel) as u64),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, addr: usize, _access_size: u8, value: u64) -> HyperResult {
+        match addr - MMIO_BASE {
+            MMIO_IOREGSEL => self.ioregsel = value as u32,
+            MMIO_IOWIN => self.write_register(self.ioregsel, value as u32),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+static IOAPIC: Once<Arc<Mutex<VirtIoApic>>> = Once::new();
+
+/// Returns the VM's single I/O APIC, creating it on first use. Unlike
+/// [`super::register_apic`]'s per-vCPU registry, there's exactly one I/O
+/// APIC for the whole VM, so a lazily-initialized singleton is simpler
+/// than a registry of one.
+pub fn ioapic() -> Arc<Mutex<VirtIoApic>> {
+    IOAPIC
+        .call_once(|| Arc::new(Mutex::new(VirtIoApic::new())))
+        .clone()
+}
@@ -1,8 +1,12 @@
 mod apic_timer;
 mod bundle;
+mod bus;
+mod cpu_hotplug;
 mod debug_port;
 mod dummy;
+mod hyperv;
 mod i8259_pic;
+mod ioapic;
 mod pci;
 mod pcip;
 mod pit;
@@ -11,24 +15,48 @@ mod uart16550;
 
 extern crate alloc;
 use alloc::{sync::Arc, vec, vec::Vec};
+use libax::hv::{Error as HyperError, Result as HyperResult};
 use spin::Mutex;
-use libax::hv::{Result as HyperResult, Error as HyperError};
 
-pub use apic_timer::{VirtLocalApic, ApicBaseMsrHandler};
+pub use apic_timer::{deliver_to_apic, register_apic, ApicBaseMsrHandler, VirtLocalApic};
 pub use bundle::Bundle;
+pub use bus::{BusDevice, DeviceBus, MmioBus, MmioDevice, PortIoBus, SpanBus, VirtMsrBus};
+pub use cpu_hotplug::VirtCpuManager;
 pub use debug_port::DebugPort;
 pub use dummy::Dummy;
-pub use i8259_pic::I8259Pic;
-pub use pci::PCIConfigurationSpace;
-pub use pcip::PCIPassthrough;
-pub use pit::PIT;
+pub use hyperv::{cpuid_leaf as hyperv_cpuid_leaf, HyperVMsrDevice};
+pub use i8259_pic::{ChainedPic, I8259Pic};
+pub use ioapic::{ioapic, VirtIoApic};
+pub use pci::{Bdf as PciBdf, PCIConfigurationSpace, PciDevice};
+pub use pcip::{Bdf, PCIPassthrough};
+pub use pit::{PitBusDevice, PIT};
 pub use port_passthrough::PortPassthrough;
-pub use uart16550::{Uart16550, DefaultConsoleBackend, MultiplexConsoleBackend, VirtualConsoleBackend};
+pub use uart16550::{
+    DefaultConsoleBackend, MultiplexConsoleBackend, Uart16550, VirtualConsoleBackend,
+};
+
+/// Minimal per-device I/O counters every [`PortIoDevice`] can report through
+/// [`PortIoDevice::stats`], regardless of how much richer a device's own
+/// statistics are. Devices that don't track anything just return the default
+/// (all-zero) snapshot.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeviceStats {
+    pub reads: u64,
+    pub writes: u64,
+    pub rejected: u64,
+}
 
 pub trait PortIoDevice: Send + Sync {
     fn port_range(&self) -> core::ops::Range<u16>;
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32>;
     fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult;
+
+    /// Snapshot of this device's I/O counters. The default is all-zero so
+    /// implementing it is opt-in; [`PortIoBus`] can report it uniformly for
+    /// whatever devices choose to track it.
+    fn stats(&self) -> DeviceStats {
+        DeviceStats::default()
+    }
 }
 
 pub trait VirtMsrDevice: Send + Sync {
@@ -47,11 +75,11 @@ macro_rules! pmio_proxy_struct {
             fn port_range(&self) -> core::ops::Range<u16> {
                 ($port_begin)..(($port_end) + 1)
             }
-        
+
             fn read(&mut self, port: u16, access_size: u8) -> libax::hv::Result<u32> {
                 self.parent.lock().$reader(port, access_size)
             }
-        
+
             fn write(&mut self, port: u16, access_size: u8, value: u32) -> libax::hv::Result {
                 self.parent.lock().$writer(port, access_size, value)
             }
@@ -62,7 +90,9 @@ macro_rules! pmio_proxy_struct {
 macro_rules! pmio_proxy_factory {
     ($fn:ident, $type:ident) => {
         pub fn $fn(some: &alloc::sync::Arc<spin::Mutex<Self>>) -> $type {
-            $type { parent: some.clone() }
+            $type {
+                parent: some.clone(),
+            }
         }
     };
 }
@@ -92,15 +122,51 @@ macro_rules! msr_proxy_struct {
 macro_rules! msr_proxy_factory {
     ($fn:ident, $type:ident) => {
         pub fn $fn(some: &alloc::sync::Arc<spin::Mutex<Self>>) -> $type {
-            $type { parent: some.clone() }
+            $type {
+                parent: some.clone(),
+            }
         }
     };
 }
 
-pub(crate) use pmio_proxy_struct;
-pub(crate) use pmio_proxy_factory;
-pub(crate) use msr_proxy_struct;
+macro_rules! mmio_proxy_struct {
+    ($addr_begin:expr, $addr_end:expr, $name:ident, $parent:ident, $reader:ident, $writer:ident) => {
+        pub struct $name {
+            parent: alloc::sync::Arc<spin::Mutex<$parent>>,
+        }
+
+        impl $crate::device::device_emu::MmioDevice for $name {
+            fn mmio_range(&self) -> core::ops::Range<usize> {
+                ($addr_begin)..($addr_end)
+            }
+
+            fn read(&mut self, addr: usize, access_size: u8) -> libax::hv::Result<u64> {
+                self.parent.lock().$reader(addr, access_size)
+            }
+
+            fn write(&mut self, addr: usize, access_size: u8, value: u64) -> libax::hv::Result {
+                self.parent.lock().$writer(addr, access_size, value)
+            }
+        }
+    };
+}
+
+macro_rules! mmio_proxy_factory {
+    ($fn:ident, $type:ident) => {
+        pub fn $fn(some: &alloc::sync::Arc<spin::Mutex<Self>>) -> $type {
+            $type {
+                parent: some.clone(),
+            }
+        }
+    };
+}
+
+pub(crate) use mmio_proxy_factory;
+pub(crate) use mmio_proxy_struct;
 pub(crate) use msr_proxy_factory;
+pub(crate) use msr_proxy_struct;
+pub(crate) use pmio_proxy_factory;
+pub(crate) use pmio_proxy_struct;
 
 pub struct MsrDummy {
     msr_range: core::ops::Range<u32>,
@@ -108,7 +174,9 @@ pub struct MsrDummy {
 
 impl MsrDummy {
     pub fn new(msr: u32) -> Self {
-        Self { msr_range: msr..msr+1 }
+        Self {
+            msr_range: msr..msr + 1,
+        }
     }
 
     pub fn new_range(range: core::ops::Range<u32>) -> Self {
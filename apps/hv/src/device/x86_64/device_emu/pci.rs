@@ -1,61 +1,165 @@
-use super::PortIoDevice;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use bit_field::BitField;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use spin::Mutex;
 
-pub struct PCIConfigurationSpace {
-    port_base: u16,
-    current_address: u64,
-}
+use super::PortIoDevice;
+use libax::hv::{Error as HyperError, Result as HyperResult};
 
 const CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET: usize = 0;
 const CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET: usize = 3;
 const CONFIGURATION_SPACE_DATA_PORT_OFFSET: usize = 4;
 const CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET: usize = 7;
 
+/// `(bus, device, function)` addressing a single emulated PCI function.
+pub type Bdf = (u8, u8, u8);
+
+/// An emulated PCI function registered with a [`PCIConfigurationSpace`].
+/// Unlike [`super::pcip::PCIPassthrough`], which shadows a real host
+/// device, this backs its config space entirely out of an in-memory byte
+/// array -- there's no host hardware behind it, so vendor/device ID,
+/// BARs, class codes and the capability list are whatever bytes the
+/// device itself put there.
+pub trait PciDevice: Send + Sync {
+    /// This function's config-space image, indexed by register offset
+    /// (`0..256` for a type-0 header). Both reads and writes index
+    /// straight into it, so which registers are actually writable is up
+    /// to the concrete device to enforce inside its own `write` handling
+    /// before it gets here -- this emulator just moves bytes.
+    fn config_space(&mut self) -> &mut [u8];
+}
+
+/// Type-1 PCI configuration-access emulation: decodes the classic 0xCF8
+/// (CONFIG_ADDRESS) / 0xCFC (CONFIG_DATA) port protocol and routes each
+/// access to whatever [`PciDevice`] is registered at the targeted
+/// `(bus, device, function)`. A `bdf` with nothing registered reads back
+/// as `0xffff_ffff`, same as real hardware reports for an absent device.
+pub struct PCIConfigurationSpace {
+    port_base: u16,
+    current_address: u64,
+    devices: BTreeMap<Bdf, Arc<Mutex<dyn PciDevice>>>,
+}
+
 impl PCIConfigurationSpace {
     pub fn new(port_base: u16) -> Self {
-        Self { port_base, current_address: 0 }
+        Self {
+            port_base,
+            current_address: 0,
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// Register an emulated function at `bdf`. A later call for the same
+    /// `bdf` replaces whatever was registered before.
+    pub fn register(&mut self, bdf: Bdf, device: Arc<Mutex<dyn PciDevice>>) {
+        self.devices.insert(bdf, device);
+    }
+
+    fn addr_bdf_offset(&self) -> (Bdf, u8) {
+        let addr = self.current_address;
+        let offset = (addr.get_bits(0..8) as u8) & 0xfc;
+        let func = addr.get_bits(8..11) as u8;
+        let dev = addr.get_bits(11..16) as u8;
+        let bus = addr.get_bits(16..24) as u8;
+        ((bus, dev, func), offset)
+    }
+
+    /// Bit 31 of CONFIG_ADDRESS: accesses to CONFIG_DATA are only routed
+    /// to a device while this is set, same as real hardware.
+    fn enabled(&self) -> bool {
+        self.current_address.get_bit(31)
+    }
+
+    fn read_config_data(&mut self, access_size: u8) -> u32 {
+        if !self.enabled() {
+            return 0xffff_ffff;
+        }
+        let (bdf, offset) = self.addr_bdf_offset();
+        let Some(device) = self.devices.get(&bdf) else {
+            return 0xffff_ffff;
+        };
+
+        let mut device = device.lock();
+        let config = device.config_space();
+        let off = offset as usize;
+        let mut bytes = [0xffu8; 4];
+        for (i, byte) in bytes.iter_mut().enumerate().take(access_size as usize) {
+            if let Some(&b) = config.get(off + i) {
+                *byte = b;
+            }
+        }
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_config_data(&mut self, access_size: u8, value: u32) {
+        if !self.enabled() {
+            return;
+        }
+        let (bdf, offset) = self.addr_bdf_offset();
+        let Some(device) = self.devices.get(&bdf) else {
+            return;
+        };
+
+        let mut device = device.lock();
+        let config = device.config_space();
+        let off = offset as usize;
+        let bytes = value.to_le_bytes();
+        for (i, &byte) in bytes.iter().enumerate().take(access_size as usize) {
+            if let Some(slot) = config.get_mut(off + i) {
+                *slot = byte;
+            }
+        }
     }
 }
 
 impl PortIoDevice for PCIConfigurationSpace {
     fn port_range(&self) -> core::ops::Range<u16> {
-        return self.port_base..self.port_base + 8
+        self.port_base..self.port_base + 8
     }
 
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
         match (port - self.port_base) as usize {
-            offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET ..= CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => {
-                // we return non-sense to tell linux pci is not present.
-                match access_size {
-                    1 => Ok(0xfe),
-                    2 => Ok(0xfffe),
-                    4 => Ok(0xffff_fffe),
-                    _ => Err(HyperError::InvalidParam),
-                }
+            offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET
+                ..=CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => match access_size {
+                1 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 8) as u32),
+                2 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 16) as u32),
+                4 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 32) as u32),
+                _ => Err(HyperError::InvalidParam),
             },
-            CONFIGURATION_SPACE_DATA_PORT_OFFSET ..= CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
+            CONFIGURATION_SPACE_DATA_PORT_OFFSET..=CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
                 match access_size {
-                    1 => Ok(0xff),
-                    2 => Ok(0xffff),
-                    4 => Ok(0xffff_ffff),
+                    1 | 2 | 4 => Ok(self.read_config_data(access_size)),
                     _ => Err(HyperError::InvalidParam),
                 }
-            },
+            }
             _ => Err(HyperError::InvalidParam),
         }
     }
 
     fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
         match (port - self.port_base) as usize {
-            offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET..=CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => {
+            offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET
+                ..=CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => match access_size {
+                1 => Ok({
+                    self.current_address
+                        .set_bits(offset * 8..offset * 8 + 8, value as u8 as u64);
+                }),
+                2 => Ok({
+                    self.current_address
+                        .set_bits(offset * 8..offset * 8 + 16, value as u16 as u64);
+                }),
+                4 => Ok({
+                    self.current_address
+                        .set_bits(offset * 8..offset * 8 + 32, value as u64);
+                }),
+                _ => Err(HyperError::InvalidParam),
+            },
+            CONFIGURATION_SPACE_DATA_PORT_OFFSET..=CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
                 match access_size {
-                    1 => Ok({ self.current_address.set_bits(offset*8..offset*8+8, value as u8 as u64); }),
-                    2 => Ok({ self.current_address.set_bits(offset*8..offset*8+16, value as u16 as u64); }),
-                    4 => Ok({ self.current_address.set_bits(offset*8..offset*8+32, value as u64); }),
+                    1 | 2 | 4 => Ok(self.write_config_data(access_size, value)),
                     _ => Err(HyperError::InvalidParam),
                 }
-            },
+            }
             _ => Err(HyperError::NotSupported),
         }
     }
@@ -1,39 +1,305 @@
-use super::PortIoDevice;
+use super::{DeviceStats, PortIoDevice};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
 use bit_field::BitField;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use libax::hv::{Error as HyperError, Result as HyperResult};
 use x86::io;
 
+const CONFIG_ADDRESS_PORT_OFFSET: usize = 0;
+const CONFIG_ADDRESS_PORT_LAST_OFFSET: usize = 3;
+const CONFIG_DATA_PORT_OFFSET: usize = 4;
+const CONFIG_DATA_PORT_LAST_OFFSET: usize = 7;
+
+const PCI_CONFIG_ADDR_PORT: u16 = 0xcf8;
+const PCI_CONFIG_DATA_PORT: u16 = 0xcfc;
+
+/// Offset of the combined command (low word) / status (high word) register.
+const REG_COMMAND_STATUS: u8 = 0x04;
+const REG_CAP_PTR: u8 = 0x34;
+const REG_BAR0: u8 = 0x10;
+const NUM_BARS: usize = 6;
+const STATUS_CAP_LIST_BIT: usize = 20; // bit 4 of the status word, which lives in bits 16..32 of this dword.
+const COMMAND_BUS_MASTER_BIT: usize = 2;
+
+/// (bus, device, function) addressing a single real PCI function.
+pub type Bdf = (u8, u8, u8);
+
+fn host_cfg_address(bdf: Bdf, offset: u8) -> u32 {
+    let (bus, dev, func) = bdf;
+    0x8000_0000
+        | (bus as u32) << 16
+        | (dev as u32) << 11
+        | (func as u32) << 8
+        | (offset as u32 & 0xfc)
+}
+
+/// Read one dword from the *host*'s real PCI config space via CF8/CFC.
+fn host_cfg_read32(bdf: Bdf, offset: u8) -> u32 {
+    unsafe {
+        io::outl(PCI_CONFIG_ADDR_PORT, host_cfg_address(bdf, offset));
+        io::inl(PCI_CONFIG_DATA_PORT)
+    }
+}
+
+fn host_cfg_write32(bdf: Bdf, offset: u8, value: u32) {
+    unsafe {
+        io::outl(PCI_CONFIG_ADDR_PORT, host_cfg_address(bdf, offset));
+        io::outl(PCI_CONFIG_DATA_PORT, value);
+    }
+}
+
+/// A BAR the hypervisor virtualizes for a passed-through function: the
+/// guest is told it is `size` bytes at `base`, regardless of what the real
+/// device's BAR is actually programmed to. `probing` tracks whether the
+/// guest's last write was the classic all-ones size probe, so the next read
+/// reports the size mask instead of `base`.
+#[derive(Clone, Copy)]
+struct BarShadow {
+    base: u32,
+    size: u32,
+    probing: bool,
+}
+
+/// Precomputed from the host's real capability list at [`PCIPassthrough::allow_device`]
+/// time: what to report at [`REG_CAP_PTR`], and what "next" byte to report
+/// for each real capability's header, with every ID in `hidden_caps`
+/// spliced out of the chain the guest walks.
+struct CapChainShadow {
+    first_visible: u8,
+    next_override: BTreeMap<u8, u8>,
+}
+
+impl CapChainShadow {
+    fn build(bdf: Bdf, hidden_caps: u64) -> Self {
+        let mut chain = Vec::new();
+        if host_cfg_read32(bdf, REG_COMMAND_STATUS).get_bit(STATUS_CAP_LIST_BIT) {
+            let mut ptr = (host_cfg_read32(bdf, REG_CAP_PTR) & 0xff) as u8;
+            while ptr != 0 {
+                let header = host_cfg_read32(bdf, ptr);
+                let id = (header & 0xff) as u8;
+                chain.push((ptr, id));
+                ptr = ((header >> 8) & 0xff) as u8;
+            }
+        }
+
+        let visible: Vec<u8> = chain
+            .iter()
+            .filter(|(_, id)| !(*id < 64 && hidden_caps.get_bit(*id as usize)))
+            .map(|(offset, _)| *offset)
+            .collect();
+
+        let mut next_override = BTreeMap::new();
+        for (i, &offset) in visible.iter().enumerate() {
+            next_override.insert(offset, visible.get(i + 1).copied().unwrap_or(0));
+        }
+        // Capabilities dropped from the chain still get a (harmless) entry
+        // so a read that lands on one reports a well-formed, if orphaned,
+        // header rather than whatever garbage the host's real chain points
+        // to next.
+        for (offset, _) in &chain {
+            next_override.entry(*offset).or_insert(0);
+        }
+
+        Self {
+            first_visible: visible.first().copied().unwrap_or(0),
+            next_override,
+        }
+    }
+}
+
+/// A real host PCI function the hypervisor has explicitly whitelisted for
+/// passthrough, along with how its config space should be shadowed for the
+/// guest: BAR geometry the guest is told rather than the real hardware's,
+/// capabilities hidden from its capability-list walk, and whether it may
+/// enable the device's bus-master bit itself.
+struct AllowedFunction {
+    bars: [Option<BarShadow>; NUM_BARS],
+    caps: CapChainShadow,
+    allow_bus_master: bool,
+}
+
+/// VFIO-style PCI passthrough port-I/O device: decodes the classic 0xCF8
+/// (CONFIG_ADDRESS) / 0xCFC (CONFIG_DATA) protocol and routes each access
+/// through a per-function shadow built by [`allow_device`](Self::allow_device),
+/// rather than blindly forwarding every access to the host as a plain
+/// [`PortPassthrough`](super::PortPassthrough) would. Only whitelisted
+/// functions are passed through at all; everything else reads back as
+/// "no device present".
 pub struct PCIPassthrough {
     port_base: u16,
     current_address: u64,
+    allowed: BTreeMap<Bdf, AllowedFunction>,
+    stats: DeviceStats,
 }
 
 impl PCIPassthrough {
     pub fn new(port_base: u16) -> Self {
-        Self { port_base, current_address: 0 }
+        Self {
+            port_base,
+            current_address: 0,
+            allowed: BTreeMap::new(),
+            stats: DeviceStats::default(),
+        }
+    }
+
+    /// Whitelist `bdf` for passthrough. `bars[i]` gives the `(base, size)`
+    /// the guest should see for BAR `i`, or `None` if that BAR slot is
+    /// unused. `caps_mask` is a bitmask over capability IDs (bit N set hides
+    /// capability ID N, e.g. bit 0x11 to hide MSI-X) to splice out of the
+    /// capability list the guest walks.
+    pub fn allow_device(&mut self, bdf: Bdf, bars: [Option<(u32, u32)>; NUM_BARS], caps_mask: u64) {
+        let shadow_bars = bars.map(|bar| {
+            bar.map(|(base, size)| BarShadow {
+                base,
+                size,
+                probing: false,
+            })
+        });
+        self.allowed.insert(
+            bdf,
+            AllowedFunction {
+                bars: shadow_bars,
+                caps: CapChainShadow::build(bdf, caps_mask),
+                allow_bus_master: false,
+            },
+        );
+    }
+
+    /// Allow an already-whitelisted function's guest driver to set the
+    /// command register's bus-master enable bit itself.
+    pub fn allow_bus_master(&mut self, bdf: Bdf) {
+        if let Some(func) = self.allowed.get_mut(&bdf) {
+            func.allow_bus_master = true;
+        }
+    }
+
+    fn addr_bdf_offset(&self) -> (Bdf, u8) {
+        let addr = self.current_address;
+        let offset = (addr.get_bits(0..8) as u8) & 0xfc;
+        let func = addr.get_bits(8..11) as u8;
+        let dev = addr.get_bits(11..16) as u8;
+        let bus = addr.get_bits(16..24) as u8;
+        ((bus, dev, func), offset)
+    }
+
+    fn read_config_data(&mut self) -> u32 {
+        let (bdf, offset) = self.addr_bdf_offset();
+        let Some(func) = self.allowed.get(&bdf) else {
+            self.stats.rejected += 1;
+            return 0xffff_ffff;
+        };
+
+        if (REG_BAR0..REG_BAR0 + (NUM_BARS as u8) * 4).contains(&offset) {
+            let bar_id = ((offset - REG_BAR0) / 4) as usize;
+            if let Some(bar) = func.bars[bar_id] {
+                return if bar.probing {
+                    !(bar.size - 1)
+                } else {
+                    bar.base
+                };
+            }
+        }
+
+        let real = host_cfg_read32(bdf, offset);
+        if offset == REG_CAP_PTR {
+            let mut value = real;
+            value.set_bits(0..8, func.caps.first_visible as u32);
+            return value;
+        }
+        if let Some(&next) = func.caps.next_override.get(&offset) {
+            let mut value = real;
+            value.set_bits(8..16, next as u32);
+            return value;
+        }
+        real
+    }
+
+    fn write_config_data(&mut self, value: u32) {
+        let (bdf, offset) = self.addr_bdf_offset();
+        let Some(func) = self.allowed.get_mut(&bdf) else {
+            self.stats.rejected += 1;
+            return;
+        };
+
+        if (REG_BAR0..REG_BAR0 + (NUM_BARS as u8) * 4).contains(&offset) {
+            let bar_id = ((offset - REG_BAR0) / 4) as usize;
+            if let Some(bar) = func.bars[bar_id].as_mut() {
+                // The BAR's base is entirely hypervisor-chosen: only the
+                // classic all-ones size probe is honored, never an actual
+                // relocation, so real hardware is never told to move.
+                bar.probing = value == 0xffff_ffff;
+                return;
+            }
+        }
+
+        if offset == REG_COMMAND_STATUS && !func.allow_bus_master {
+            let mut masked = value;
+            masked.set_bit(COMMAND_BUS_MASTER_BIT, false);
+            host_cfg_write32(bdf, offset, masked);
+            return;
+        }
+
+        host_cfg_write32(bdf, offset, value);
     }
 }
 
 impl PortIoDevice for PCIPassthrough {
     fn port_range(&self) -> core::ops::Range<u16> {
-        return self.port_base..self.port_base + 8
+        self.port_base..self.port_base + 8
     }
 
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
-        match access_size {
-            1 => Ok(unsafe { io::inb(port) } as u32),
-            2 => Ok(unsafe { io::inw(port) } as u32),
-            4 => Ok(unsafe { io::inl(port) }),
+        self.stats.reads += 1;
+        match (port - self.port_base) as usize {
+            offset @ CONFIG_ADDRESS_PORT_OFFSET..=CONFIG_ADDRESS_PORT_LAST_OFFSET => {
+                match access_size {
+                    1 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 8) as u32),
+                    2 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 16) as u32),
+                    4 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 32) as u32),
+                    _ => Err(HyperError::InvalidParam),
+                }
+            }
+            CONFIG_DATA_PORT_OFFSET..=CONFIG_DATA_PORT_LAST_OFFSET => match access_size {
+                1 | 2 | 4 => {
+                    let mask = if access_size == 4 {
+                        u32::MAX
+                    } else {
+                        (1u32 << (access_size * 8)) - 1
+                    };
+                    Ok(self.read_config_data() & mask)
+                }
+                _ => Err(HyperError::InvalidParam),
+            },
             _ => Err(HyperError::InvalidParam),
         }
     }
 
     fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
-        match access_size {
-            1 => Ok(unsafe { io::outb(port, value as u8) }),
-            2 => Ok(unsafe { io::outw(port, value as u16) }),
-            4 => Ok(unsafe { io::outl(port, value) }),
+        self.stats.writes += 1;
+        match (port - self.port_base) as usize {
+            offset @ CONFIG_ADDRESS_PORT_OFFSET..=CONFIG_ADDRESS_PORT_LAST_OFFSET => {
+                match access_size {
+                    1 => Ok(self
+                        .current_address
+                        .set_bits(offset * 8..offset * 8 + 8, value as u8 as u64)),
+                    2 => Ok(self
+                        .current_address
+                        .set_bits(offset * 8..offset * 8 + 16, value as u16 as u64)),
+                    4 => Ok(self
+                        .current_address
+                        .set_bits(offset * 8..offset * 8 + 32, value as u64)),
+                    _ => Err(HyperError::InvalidParam),
+                }
+            }
+            CONFIG_DATA_PORT_OFFSET..=CONFIG_DATA_PORT_LAST_OFFSET => match access_size {
+                1 | 2 | 4 => Ok(self.write_config_data(value)),
+                _ => Err(HyperError::InvalidParam),
+            },
             _ => Err(HyperError::InvalidParam),
         }
     }
+
+    fn stats(&self) -> DeviceStats {
+        self.stats
+    }
 }
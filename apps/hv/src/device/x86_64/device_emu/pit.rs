@@ -1,6 +1,8 @@
+use alloc::vec::Vec;
 use bit_field::BitField;
-use libax::hv::{Result as HyperResult, Error as HyperError, HyperCraftHal, HyperCraftHalImpl};
+use libax::hv::{Error as HyperError, HyperCraftHal, HyperCraftHalImpl, Result as HyperResult};
 use libax::time::current_time_nanos;
+use pci::util::num_ops::{read_data_u16, write_data_u32};
 
 pub const PIT_FREQ: u32 = 1_193182;
 pub const PIT_CHANNEL_COUNT: usize = 3;
@@ -27,8 +29,28 @@ impl TryFrom<u8> for PITChannelAccessMode {
     }
 }
 
+impl PITChannelAccessMode {
+    /// The control-word RW field value this access mode was programmed
+    /// with, for the read-back command's status byte.
+    fn bits(&self) -> u8 {
+        match self {
+            Self::LowOnly => 1,
+            Self::HighOnly => 2,
+            Self::LowThenHigh => 3,
+            Self::Invalid => 0,
+        }
+    }
+}
+
 enum PITChannelOpMode {
     OneShot,
+    /// Mode 2 (rate generator): counts down from `reload` repeatedly,
+    /// auto-reloading on terminal count; output goes low for exactly one
+    /// input clock per period.
+    RateGenerator,
+    /// Mode 3 (square wave): same periodic reload as mode 2, but output is
+    /// high for the first half of the period and low for the second half.
+    SquareWave,
     Invalid,
 }
 
@@ -38,11 +60,28 @@ impl TryFrom<u8> for PITChannelOpMode {
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Self::OneShot),
+            2 => Ok(Self::RateGenerator),
+            3 => Ok(Self::SquareWave),
+            // Modes 1 (hardware retriggerable one-shot), 4 (software
+            // strobe) and 5 (hardware strobe) aren't modeled.
             _ => Err(HyperError::NotSupported),
         }
     }
 }
 
+impl PITChannelOpMode {
+    /// The control-word M field value this operating mode was programmed
+    /// with, for the read-back command's status byte.
+    fn bits(&self) -> u8 {
+        match self {
+            Self::OneShot => 0,
+            Self::RateGenerator => 2,
+            Self::SquareWave => 3,
+            Self::Invalid => 0,
+        }
+    }
+}
+
 struct PITChannel {
     reload: u32, // 16-bit is enough for counter and reload but ...
     reload_low_written: bool,
@@ -51,6 +90,23 @@ struct PITChannel {
     access_mode: PITChannelAccessMode,
     op_mode: PITChannelOpMode,
     low_read: bool,
+    /// Counter value snapshotted by a counter-latch command (access mode
+    /// `0`) or the read-back command's COUNT# bit, consumed by
+    /// `read_low_byte`/`read_high_byte` instead of a live `read_counter()`
+    /// so a `LowThenHigh` pair can't race against the countdown between
+    /// the two byte reads.
+    latched: Option<u16>,
+    /// Status byte snapshotted by the read-back command's STATUS# bit,
+    /// returned whole by the next `read()` regardless of `access_mode`.
+    status_latched: Option<u8>,
+    /// GATE input level. Channels 0 and 1 have it tied high in hardware and
+    /// never call `set_enabled`; channel 2's is driven by port 0x61 bit 0.
+    /// Counting only advances while this is `true`.
+    enabled: bool,
+    /// Elapsed nanoseconds accumulated while the gate was up, frozen at the
+    /// instant it last went down, so `eclipsed_periods` can resume from
+    /// where it left off instead of losing the time spent gated off.
+    held_nanos: u64,
 }
 
 impl PITChannel {
@@ -63,10 +119,22 @@ impl PITChannel {
             access_mode: PITChannelAccessMode::Invalid,
             low_read: false,
             op_mode: PITChannelOpMode::Invalid,
+            latched: None,
+            status_latched: None,
+            enabled: true,
+            held_nanos: 0,
         }
     }
 
     fn command(&mut self, access_mode: u8, op_mode: u8, bcd: bool) -> HyperResult {
+        if access_mode == 0 {
+            // Counter-latch command: snapshot the live count so a
+            // subsequent read pair sees a coherent value instead of being
+            // resampled mid-read.
+            self.latch_count();
+            return Ok(());
+        }
+
         let access_mode: PITChannelAccessMode = access_mode.try_into()?;
         let op_mode: PITChannelOpMode = op_mode.try_into()?;
 
@@ -75,39 +143,81 @@ impl PITChannel {
         }
 
         match op_mode {
-            PITChannelOpMode::OneShot => {
+            PITChannelOpMode::OneShot
+            | PITChannelOpMode::RateGenerator
+            | PITChannelOpMode::SquareWave => {
                 self.access_mode = access_mode;
                 self.op_mode = op_mode;
 
                 self.reload_low_written = false;
                 self.started = false;
-                
+
                 Ok(())
-            },
+            }
             _ => Err(HyperError::NotSupported),
         }
     }
 
+    /// Snapshot the live count into `latched`, if it isn't already holding
+    /// one -- a second latch command before the first is read is ignored,
+    /// same as real 8254 hardware.
+    fn latch_count(&mut self) {
+        self.latched.get_or_insert_with(|| self.read_counter());
+    }
+
+    /// Snapshot a read-back status byte into `status_latched`: bit 7 is
+    /// the current output pin state, bits 5:4 the programmed access mode,
+    /// bits 3:1 the programmed operating mode. Bit 6 (null count) and bit
+    /// 0 (BCD) aren't modeled and are always reported clear.
+    fn latch_status(&mut self) {
+        if self.status_latched.is_some() {
+            return;
+        }
+        let mut status = 0u8;
+        status.set_bit(7, self.read_output());
+        status.set_bits(4..6, self.access_mode.bits());
+        status.set_bits(1..4, self.op_mode.bits());
+        self.status_latched = Some(status);
+    }
+
+    fn current_count(&self) -> u16 {
+        self.latched.unwrap_or_else(|| self.read_counter())
+    }
+
     fn read_low_byte(&self) -> u8 {
-        self.read_counter().get_bits(0..8) as u8
+        self.current_count().get_bits(0..8) as u8
     }
 
     fn read_high_byte(&self) -> u8 {
-        self.read_counter().get_bits(8..16) as u8
+        self.current_count().get_bits(8..16) as u8
     }
 
     fn read(&mut self) -> HyperResult<u8> {
+        if let Some(status) = self.status_latched.take() {
+            return Ok(status);
+        }
+
         match self.access_mode {
-            PITChannelAccessMode::LowOnly => Ok(self.read_low_byte()),
-            PITChannelAccessMode::HighOnly => Ok(self.read_high_byte()),
+            PITChannelAccessMode::LowOnly => {
+                let value = self.read_low_byte();
+                self.latched = None;
+                Ok(value)
+            }
+            PITChannelAccessMode::HighOnly => {
+                let value = self.read_high_byte();
+                self.latched = None;
+                Ok(value)
+            }
             PITChannelAccessMode::LowThenHigh => {
                 self.low_read = !self.low_read;
-                Ok(if self.low_read {
-                    self.read_low_byte()
+                if self.low_read {
+                    Ok(self.read_low_byte())
                 } else {
-                    self.read_high_byte()
-                })
-            },
+                    let value = self.read_high_byte();
+                    self.latched = None;
+                    Ok(value)
+                }
+            }
             _ => Err(HyperError::BadState),
         }
     }
@@ -115,81 +225,236 @@ impl PITChannel {
     fn restart(&mut self) {
         self.started = true;
         self.start_nanos = current_time_nanos();
+        self.held_nanos = 0;
     }
 
     fn write(&mut self, value: u8) -> HyperResult {
         match self.op_mode {
-            PITChannelOpMode::OneShot => {
-                match self.access_mode {
-                    PITChannelAccessMode::LowOnly => {
+            PITChannelOpMode::OneShot
+            | PITChannelOpMode::RateGenerator
+            | PITChannelOpMode::SquareWave => match self.access_mode {
+                PITChannelAccessMode::LowOnly => {
+                    self.reload.set_bits(0..8, value as u32);
+                    self.restart();
+                    Ok(())
+                }
+                PITChannelAccessMode::HighOnly => {
+                    self.reload.set_bits(8..16, value as u32);
+                    self.restart();
+                    Ok(())
+                }
+                PITChannelAccessMode::LowThenHigh => {
+                    if self.reload_low_written {
                         self.reload.set_bits(0..8, value as u32);
-                        self.restart();
-                        Ok(())
-                    },
-                    PITChannelAccessMode::HighOnly => {
+                    } else {
                         self.reload.set_bits(8..16, value as u32);
                         self.restart();
-                        Ok(())
-                    },
-                    PITChannelAccessMode::LowThenHigh => {
-                        if self.reload_low_written {
-                            self.reload.set_bits(0..8, value as u32);
-                        } else {
-                            self.reload.set_bits(8..16, value as u32);
-                            self.restart();
-                        }
-
-                        self.reload_low_written = !self.reload_low_written;
-                        Ok(())
-                    },
-                    _ => Err(HyperError::BadState),
+                    }
+
+                    self.reload_low_written = !self.reload_low_written;
+                    Ok(())
                 }
-            }
+                _ => Err(HyperError::BadState),
+            },
             _ => Err(HyperError::BadState),
         }
     }
 
     fn eclipsed_periods(&self) -> u64 {
-        if self.started {
-            let eclipsed_nanos = current_time_nanos() - self.start_nanos;
-            ((eclipsed_nanos as u128 * PIT_FREQ as u128) / (NANOS_PER_SEC as u128)) as u64
+        if !self.started {
+            return 0;
+        }
+        let live_nanos = if self.enabled {
+            current_time_nanos() - self.start_nanos
         } else {
             0
+        };
+        let eclipsed_nanos = self.held_nanos + live_nanos;
+        ((eclipsed_nanos as u128 * PIT_FREQ as u128) / (NANOS_PER_SEC as u128)) as u64
+    }
+
+    /// `reload` with the chip's wraparound: a programmed reload of 0 means
+    /// 65536, not an immediately-expired counter.
+    fn effective_reload(&self) -> u64 {
+        if self.reload == 0 {
+            65536
+        } else {
+            self.reload as u64
         }
     }
 
+    /// Position within the current period for the periodic op modes
+    /// (`eclipsed_periods() % reload`).
+    fn phase(&self, reload: u64) -> u64 {
+        self.eclipsed_periods() % reload
+    }
+
     fn read_counter(&self) -> u16 {
-        let eclipsed_periods = self.eclipsed_periods();
-        let reload = self.reload as u64;
+        let reload = self.effective_reload();
 
-        ((reload - eclipsed_periods) & 0xffff) as u16
+        match self.op_mode {
+            PITChannelOpMode::RateGenerator => (reload - self.phase(reload)) as u16,
+            PITChannelOpMode::SquareWave => {
+                // First half of the period counts down by two per tick
+                // from `reload`; the second half continues down by two
+                // from whatever's left. An odd reload gives the first
+                // half the extra tick, same as real 8254 hardware.
+                let phase = self.phase(reload);
+                let first_half = (reload + 1) / 2;
+                if phase < first_half {
+                    (reload - 2 * phase) as u16
+                } else {
+                    (reload - 2 * (phase - first_half)) as u16
+                }
+            }
+            // Mode 0: counts down once; underflows (driver read after
+            // terminal count) aren't expected to happen in practice.
+            PITChannelOpMode::OneShot | PITChannelOpMode::Invalid => {
+                ((reload - self.eclipsed_periods()) & 0xffff) as u16
+            }
+        }
     }
 
     fn read_output(&self) -> bool {
-        if self.started {
-            self.eclipsed_periods() > self.reload as u64
+        if !self.started {
+            return false;
+        }
+        let reload = self.effective_reload();
+
+        match self.op_mode {
+            // Output goes low for exactly one input clock per period, at
+            // the instant the count reaches 1.
+            PITChannelOpMode::RateGenerator => self.phase(reload) != reload - 1,
+            // Output is high for the first half of the period, low for
+            // the second half.
+            PITChannelOpMode::SquareWave => self.phase(reload) < reload / 2,
+            PITChannelOpMode::OneShot | PITChannelOpMode::Invalid => {
+                self.eclipsed_periods() > reload
+            }
+        }
+    }
+
+    /// Gate the channel: counting freezes while `enabled` is `false` and
+    /// resumes from where it left off once it's `true` again, by folding
+    /// the elapsed time so far into `held_nanos` on disable and rebasing
+    /// `start_nanos` on re-enable.
+    fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        if enabled {
+            self.start_nanos = current_time_nanos();
         } else {
-            false
+            self.held_nanos += current_time_nanos() - self.start_nanos;
         }
+        self.enabled = enabled;
     }
 
-    fn set_enabled(&self, enabled: bool) {
+    /// Encodes `access_mode`/`op_mode` as a tag distinct from
+    /// [`PITChannelAccessMode::bits`]/[`PITChannelOpMode::bits`]: those
+    /// exist for the read-back status byte, where `Invalid` collapses onto
+    /// the same encoding as a real mode, which would silently corrupt a
+    /// restore.
+    fn mode_tag(access_mode: &PITChannelAccessMode, op_mode: &PITChannelOpMode) -> (u8, u8) {
+        let access = match access_mode {
+            PITChannelAccessMode::Invalid => 0,
+            PITChannelAccessMode::LowOnly => 1,
+            PITChannelAccessMode::HighOnly => 2,
+            PITChannelAccessMode::LowThenHigh => 3,
+        };
+        let op = match op_mode {
+            PITChannelOpMode::Invalid => 0,
+            PITChannelOpMode::OneShot => 1,
+            PITChannelOpMode::RateGenerator => 2,
+            PITChannelOpMode::SquareWave => 3,
+        };
+        (access, op)
+    }
+
+    fn access_mode_from_tag(tag: u8) -> PITChannelAccessMode {
+        match tag {
+            1 => PITChannelAccessMode::LowOnly,
+            2 => PITChannelAccessMode::HighOnly,
+            3 => PITChannelAccessMode::LowThenHigh,
+            _ => PITChannelAccessMode::Invalid,
+        }
+    }
+
+    fn op_mode_from_tag(tag: u8) -> PITChannelOpMode {
+        match tag {
+            1 => PITChannelOpMode::OneShot,
+            2 => PITChannelOpMode::RateGenerator,
+            3 => PITChannelOpMode::SquareWave,
+            _ => PITChannelOpMode::Invalid,
+        }
+    }
+
+    /// Serializes everything needed to reproduce this channel's
+    /// guest-visible counting and latch state, including the
+    /// mid-read/mid-write-sequence bits a plain counter readout would lose.
+    ///
+    /// `start_nanos`/`held_nanos` are captured as raw nanosecond-since-boot
+    /// values, so a restore is only meaningful on the same boot's clock the
+    /// snapshot was taken from.
+    fn save_state(&self) -> [u8; 31] {
+        let (access, op) = Self::mode_tag(&self.access_mode, &self.op_mode);
+        let mut buf = [0u8; 31];
+        buf[0..4].copy_from_slice(&self.reload.to_le_bytes());
+        buf[4] = self.reload_low_written as u8;
+        buf[5..13].copy_from_slice(&self.start_nanos.to_le_bytes());
+        buf[13] = self.started as u8;
+        buf[14] = access;
+        buf[15] = op;
+        buf[16] = self.low_read as u8;
+        buf[17] = self.latched.is_some() as u8;
+        buf[18..20].copy_from_slice(&self.latched.unwrap_or(0).to_le_bytes());
+        buf[20] = self.status_latched.is_some() as u8;
+        buf[21] = self.status_latched.unwrap_or(0);
+        buf[22] = self.enabled as u8;
+        buf[23..31].copy_from_slice(&self.held_nanos.to_le_bytes());
+        buf
+    }
+
+    fn restore_state(&mut self, state: &[u8; 31]) {
+        self.reload = u32::from_le_bytes(state[0..4].try_into().unwrap());
+        self.reload_low_written = state[4] != 0;
+        self.start_nanos = u64::from_le_bytes(state[5..13].try_into().unwrap());
+        self.started = state[13] != 0;
+        self.access_mode = Self::access_mode_from_tag(state[14]);
+        self.op_mode = Self::op_mode_from_tag(state[15]);
+        self.low_read = state[16] != 0;
+        self.latched = (state[17] != 0)
+            .then(|| u16::from_le_bytes(state[18..20].try_into().unwrap()));
+        self.status_latched = (state[20] != 0).then_some(state[21]);
+        self.enabled = state[22] != 0;
+        self.held_nanos = u64::from_le_bytes(state[23..31].try_into().unwrap());
     }
 }
 
 /// Intel 8253/8254 Programmable Interval Timer (PIT) emulation
 pub struct PIT {
     channels: [PITChannel; PIT_CHANNEL_COUNT],
+    /// PC-speaker enable bit, driven by port 0x61 bit 1. ANDed with
+    /// channel 2's output in `speaker_output` to form the speaker signal.
+    speaker_enabled: bool,
 }
 
 impl PIT {
     pub fn new() -> Self {
         Self {
             channels: [PITChannel::new(), PITChannel::new(), PITChannel::new()],
+            speaker_enabled: false,
         }
     }
 
     pub fn command(&mut self, channel: u8, access_mode: u8, op_mode: u8, bcd: bool) -> HyperResult {
+        if channel == 3 {
+            // Read-back command (SC == 0b11): the control word's RW and M
+            // fields are repurposed as STATUS#/COUNT# and the channel-select
+            // bitmask, so dispatch before treating `channel` as an index.
+            return self.read_back(access_mode, op_mode);
+        }
+
         let channel = channel as usize;
         if channel >= PIT_CHANNEL_COUNT {
             Err(HyperError::InvalidParam)
@@ -200,7 +465,31 @@ impl PIT {
             })
         }
     }
-    
+
+    /// Latch the count and/or status of every channel selected by
+    /// `channel_mask`'s bits 0..3, per the 8254 read-back command. Both
+    /// STATUS# and COUNT# are active-low, matching the raw control-word
+    /// bits `status_bits` (the RW field) is read out of: a clear bit means
+    /// "do latch this".
+    fn read_back(&mut self, status_bits: u8, channel_mask: u8) -> HyperResult {
+        let latch_count = !status_bits.get_bit(1);
+        let latch_status = !status_bits.get_bit(0);
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            if !channel_mask.get_bit(i) {
+                continue;
+            }
+            if latch_count {
+                channel.latch_count();
+            }
+            if latch_status {
+                channel.latch_status();
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn read(&mut self, channel: u8) -> HyperResult<u8> {
         let channel = channel as usize;
         if channel >= PIT_CHANNEL_COUNT {
@@ -242,4 +531,89 @@ impl PIT {
             Ok(self.channels[channel].set_enabled(enabled))
         }
     }
+
+    /// Latch the PC-speaker enable bit (port 0x61 bit 1).
+    pub fn set_speaker_enabled(&mut self, enabled: bool) {
+        self.speaker_enabled = enabled;
+    }
+
+    /// The PC-speaker signal: the speaker-enable bit ANDed with channel 2's
+    /// output.
+    pub fn speaker_output(&mut self) -> bool {
+        self.speaker_enabled && self.channels[2].read_output()
+    }
+
+    /// Serializes all three channels plus the speaker-enable bit, as a flat
+    /// concatenation of each channel's own [`PITChannel::save_state`].
+    pub(crate) fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(PIT_CHANNEL_COUNT * 31 + 1);
+        for channel in &self.channels {
+            buf.extend_from_slice(&channel.save_state());
+        }
+        buf.push(self.speaker_enabled as u8);
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, data: &[u8]) -> HyperResult {
+        if data.len() != PIT_CHANNEL_COUNT * 31 + 1 {
+            return Err(HyperError::InvalidParam);
+        }
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            let chunk: [u8; 31] = data[i * 31..(i + 1) * 31].try_into().unwrap();
+            channel.restore_state(&chunk);
+        }
+        self.speaker_enabled = data[PIT_CHANNEL_COUNT * 31] != 0;
+        Ok(())
+    }
+}
+
+/// Adapts [`PIT`] to [`BusDevice`] for the legacy 0x40-0x43 port window, so
+/// it can be driven through a [`SpanBus`](super::bus::SpanBus) the same way
+/// as other devices instead of [`Bundle`](super::bundle::Bundle)'s ad-hoc
+/// `read_pit`/`write_pit` call sites.
+pub struct PitBusDevice {
+    pit: PIT,
+}
+
+/// Offset of the command port (0x43) within the 0x40-0x43 window.
+const COMMAND_OFFSET: u64 =
+    (super::bundle::PORT_PIT_COMMAND - super::bundle::PORT_PIT_CHANNEL_DATA_BASE) as u64;
+
+impl PitBusDevice {
+    pub fn new() -> Self {
+        Self { pit: PIT::new() }
+    }
+}
+
+impl super::bus::BusDevice for PitBusDevice {
+    fn read(&mut self, offset: u64, data: &mut [u8]) -> HyperResult {
+        let value = if offset == COMMAND_OFFSET {
+            0
+        } else {
+            self.pit.read(offset as u8)? as u32
+        };
+        if write_data_u32(data, value) {
+            Ok(())
+        } else {
+            Err(HyperError::InvalidParam)
+        }
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) -> HyperResult {
+        let mut value = 0u16;
+        if !read_data_u16(data, &mut value) {
+            return Err(HyperError::InvalidParam);
+        }
+        let value = value as u8;
+        if offset == COMMAND_OFFSET {
+            self.pit.command(
+                value.get_bits(6..8),
+                value.get_bits(4..6),
+                value.get_bits(1..4),
+                value.get_bit(0),
+            )
+        } else {
+            self.pit.write(offset as u8, value)
+        }
+    }
 }
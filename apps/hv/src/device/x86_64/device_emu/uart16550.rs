@@ -1,12 +1,12 @@
 //! Emulated UART 16550. (ref: https://wiki.osdev.org/Serial_Ports)
-//! 
-use core::{marker::PhantomData};
+//!
+use core::marker::PhantomData;
 
 use super::PortIoDevice;
 
-
 use alloc::string::String;
-use libax::hv::{Result as HyperResult, Error as HyperError};
+use alloc::vec::Vec;
+use libax::hv::{Error as HyperError, Result as HyperResult};
 use spin::Mutex;
 
 const DATA_REG: u16 = 0;
@@ -70,6 +70,28 @@ impl<const CAP: usize> Fifo<CAP> {
         self.num -= 1;
         ret
     }
+
+    /// Serializes the queued bytes themselves (not the raw ring-buffer
+    /// layout), so `CAP` doesn't need to match between save and restore.
+    fn save_state(&self) -> Vec<u8> {
+        let mut queued = Vec::with_capacity(1 + self.num);
+        queued.push(self.num as u8);
+        for i in 0..self.num {
+            queued.push(self.buf[(self.head + i) % CAP]);
+        }
+        queued
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> HyperResult {
+        let num = *data.first().ok_or(HyperError::InvalidParam)? as usize;
+        if num > CAP || data.len() != 1 + num {
+            return Err(HyperError::InvalidParam);
+        }
+        self.buf[..num].copy_from_slice(&data[1..1 + num]);
+        self.head = 0;
+        self.num = num;
+        Ok(())
+    }
 }
 
 pub trait VirtualConsoleBackend: Send + Sync + Sized {
@@ -96,16 +118,25 @@ impl VirtualConsoleBackend for DefaultConsoleBackend {
     }
 }
 
-
 const MULTIPLEX_BUFFER_LENGTH: usize = 80;
 pub enum MultiplexConsoleBackend {
     Primary,
-    Secondary{id: isize, buffer: Fifo<MULTIPLEX_BUFFER_LENGTH>, input: String, input_ptr: usize},
+    Secondary {
+        id: isize,
+        buffer: Fifo<MULTIPLEX_BUFFER_LENGTH>,
+        input: String,
+        input_ptr: usize,
+    },
 }
 
 impl MultiplexConsoleBackend {
     pub fn new_secondary(id: isize, input: impl Into<String>) -> Self {
-        Self::Secondary { id: id, buffer: Fifo::new(), input: input.into(), input_ptr: 0 }
+        Self::Secondary {
+            id: id,
+            buffer: Fifo::new(),
+            input: input.into(),
+            input_ptr: 0,
+        }
     }
 }
 
@@ -119,7 +150,7 @@ impl VirtualConsoleBackend for MultiplexConsoleBackend {
             MultiplexConsoleBackend::Primary => {
                 use libax::io::console as uart;
                 uart::putchar(c)
-            },
+            }
             MultiplexConsoleBackend::Secondary { id, buffer, .. } => {
                 if c == ('\n' as u8) {
                     let mut result = [0u8; MULTIPLEX_BUFFER_LENGTH + 1];
@@ -130,11 +161,15 @@ impl VirtualConsoleBackend for MultiplexConsoleBackend {
                         ptr += 1;
                     }
 
-                    info!("multiplex console output {}: {}", id, core::str::from_utf8(&result[0..ptr]).unwrap())
+                    info!(
+                        "multiplex console output {}: {}",
+                        id,
+                        core::str::from_utf8(&result[0..ptr]).unwrap()
+                    )
                 } else {
                     buffer.push(c);
                 }
-            },
+            }
         }
     }
 
@@ -143,8 +178,10 @@ impl VirtualConsoleBackend for MultiplexConsoleBackend {
             MultiplexConsoleBackend::Primary => {
                 use libax::io::console as uart;
                 uart::getchar()
-            },
-            MultiplexConsoleBackend::Secondary { input, input_ptr, .. } => {
+            }
+            MultiplexConsoleBackend::Secondary {
+                input, input_ptr, ..
+            } => {
                 let result = input.as_bytes()[*input_ptr];
 
                 *input_ptr += 1;
@@ -153,7 +190,7 @@ impl VirtualConsoleBackend for MultiplexConsoleBackend {
                 }
 
                 Some(result)
-            },
+            }
         }
     }
 }
@@ -200,11 +237,8 @@ impl<B: VirtualConsoleBackend> PortIoDevice for Uart16550<B> {
                 }
                 lsr.bits()
             }
-            LINE_CTRL_REG => {
-                self.line_control_reg
-            }
-            INT_EN_REG | FIFO_CTRL_REG | MODEM_CTRL_REG | MODEM_STATUS_REG
-            | SCRATCH_REG => {
+            LINE_CTRL_REG => self.line_control_reg,
+            INT_EN_REG | FIFO_CTRL_REG | MODEM_CTRL_REG | MODEM_STATUS_REG | SCRATCH_REG => {
                 trace!("Unimplemented serial port I/O read: {:#x}", port); // unimplemented
                 0
             }
@@ -245,4 +279,23 @@ impl<B: VirtualConsoleBackend> Uart16550<B> {
     pub fn backend(&mut self) -> &mut B {
         &mut self.backend
     }
+
+    /// Serializes the FIFO's queued bytes and the line-control register.
+    /// `port_base` is fixed at construction and `backend` is a live I/O
+    /// connection, not state to snapshot.
+    pub(crate) fn save_state(&self) -> Vec<u8> {
+        let mut buf = self.fifo.lock().save_state();
+        buf.push(self.line_control_reg);
+        buf
+    }
+
+    pub(crate) fn restore_state(&mut self, data: &[u8]) -> HyperResult {
+        let (fifo_bytes, &[line_control_reg]) = data.split_at(data.len().saturating_sub(1))
+        else {
+            return Err(HyperError::InvalidParam);
+        };
+        self.fifo.lock().restore_state(fifo_bytes)?;
+        self.line_control_reg = line_control_reg;
+        Ok(())
+    }
 }
@@ -1,28 +1,57 @@
 pub mod device_emu;
+mod snapshot;
 
 extern crate alloc;
-use bit_field::BitField;
 use alloc::{sync::Arc, vec, vec::Vec};
-use spin::Mutex;
+use bit_field::BitField;
 use core::marker::PhantomData;
-use libax::hv::{Result as HyperResult, VmExitInfo, VCpu, HyperCraftHal, PerCpuDevices, PerVmDevices, VmxExitReason};
-use libax::hv::{Error as HyperError, VmExitInfo as VmxExitInfo, HyperCraftHalImpl};
+use libax::hv::{Error as HyperError, HyperCraftHalImpl, VmExitInfo as VmxExitInfo};
+use libax::hv::{
+    HyperCraftHal, PerCpuDevices, PerVmDevices, Result as HyperResult, VCpu, VmExitInfo,
+    VmxExitReason,
+};
+use spin::Mutex;
 
-use device_emu::{VirtMsrDevice, PortIoDevice, Bundle, VirtLocalApic, ApicBaseMsrHandler};
+use device_emu::{
+    ApicBaseMsrHandler, Bundle, MmioDevice, PortIoDevice, VirtLocalApic, VirtMsrDevice,
+};
+pub use snapshot::SnapshotError;
+use snapshot::{SnapshotReader, SnapshotWriter};
+
+/// Bumped whenever [`X64VcpuDevices::save_state`]'s record layout or order
+/// changes.
+const VCPU_SNAPSHOT_VERSION: u32 = 1;
+/// Number of devices [`X64VcpuDevices::save_state`] records: local APIC,
+/// PIC master, PIC slave, bundle (CMOS/PIT/system control), console.
+const VCPU_DEVICE_COUNT: u32 = 5;
+/// Bumped whenever [`X64VmDevices::save_state`]'s record layout changes.
+const VM_SNAPSHOT_VERSION: u32 = 1;
+/// Number of devices [`X64VmDevices::save_state`] records: the IOAPIC.
+const VM_DEVICE_COUNT: u32 = 1;
 
 const VM_EXIT_INSTR_LEN_RDMSR: u8 = 2;
 const VM_EXIT_INSTR_LEN_WRMSR: u8 = 2;
 const VM_EXIT_INSTR_LEN_VMCALL: u8 = 3;
+const VM_EXIT_INSTR_LEN_CPUID: u8 = 2;
 
 pub struct DeviceList<H: HyperCraftHal> {
     port_io_devices: Vec<Arc<Mutex<dyn PortIoDevice>>>,
     msr_devices: Vec<Arc<Mutex<dyn VirtMsrDevice>>>,
+    /// Memory-mapped devices, the MMIO counterpart of `port_io_devices`/
+    /// `msr_devices`, looked up by guest-physical address instead of a port
+    /// or MSR number.
+    memory_io_devices: Vec<Arc<Mutex<dyn MmioDevice>>>,
     marker: core::marker::PhantomData<H>,
 }
 
 impl<H: HyperCraftHal> DeviceList<H> {
     pub fn new() -> Self {
-        Self { port_io_devices: vec![], msr_devices: vec![], marker: core::marker::PhantomData }
+        Self {
+            port_io_devices: vec![],
+            msr_devices: vec![],
+            memory_io_devices: vec![],
+            marker: core::marker::PhantomData,
+        }
     }
 
     pub fn add_port_io_device(&mut self, device: Arc<Mutex<dyn PortIoDevice>>) {
@@ -53,7 +82,25 @@ impl<H: HyperCraftHal> DeviceList<H> {
             .find(|dev| dev.lock().msr_range().contains(&msr))
     }
 
-    fn handle_io_instruction_to_device(vcpu: &mut VCpu<H>, exit_info: &VmxExitInfo, device: &Arc<Mutex<dyn PortIoDevice>>) -> HyperResult {
+    pub fn add_mmio_device(&mut self, device: Arc<Mutex<dyn MmioDevice>>) {
+        self.memory_io_devices.push(device)
+    }
+
+    pub fn add_mmio_devices(&mut self, devices: &mut Vec<Arc<Mutex<dyn MmioDevice>>>) {
+        self.memory_io_devices.append(devices)
+    }
+
+    pub fn find_memory_io_device(&self, addr: usize) -> Option<&Arc<Mutex<dyn MmioDevice>>> {
+        self.memory_io_devices
+            .iter()
+            .find(|dev| dev.lock().mmio_range().contains(&addr))
+    }
+
+    fn handle_io_instruction_to_device(
+        vcpu: &mut VCpu<H>,
+        exit_info: &VmxExitInfo,
+        device: &Arc<Mutex<dyn PortIoDevice>>,
+    ) -> HyperResult {
         let io_info = vcpu.io_exit_info().unwrap();
         trace!(
             "VM exit: I/O instruction @ {:#x}: {:#x?}",
@@ -92,15 +139,21 @@ impl<H: HyperCraftHal> DeviceList<H> {
                 4 => rax,
                 _ => unreachable!(),
             } as u32;
-            device.lock().write(io_info.port, io_info.access_size, value)?;
+            device
+                .lock()
+                .write(io_info.port, io_info.access_size, value)?;
         }
         vcpu.advance_rip(exit_info.exit_instruction_length as _)?;
         Ok(())
     }
 
-    pub fn handle_io_instruction(&mut self, vcpu: &mut VCpu<H>, exit_info: &VmxExitInfo) -> Option<HyperResult> {
+    pub fn handle_io_instruction(
+        &mut self,
+        vcpu: &mut VCpu<H>,
+        exit_info: &VmxExitInfo,
+    ) -> Option<HyperResult> {
         let io_info = vcpu.io_exit_info().unwrap();
-        
+
         if let Some(dev) = self.find_port_io_device(io_info.port) {
             return Some(Self::handle_io_instruction_to_device(vcpu, exit_info, dev));
         } else {
@@ -114,7 +167,7 @@ impl<H: HyperCraftHal> DeviceList<H> {
 
     pub fn handle_msr_read(&mut self, vcpu: &mut VCpu<H>) -> HyperResult {
         let msr = vcpu.regs().rcx as u32;
-    
+
         if let Some(dev) = self.find_msr_device(msr) {
             match dev.lock().read(msr) {
                 Ok(value) => {
@@ -125,10 +178,10 @@ impl<H: HyperCraftHal> DeviceList<H> {
 
                     vcpu.advance_rip(VM_EXIT_INSTR_LEN_RDMSR)?;
                     Ok(())
-                },
+                }
                 Err(e) => {
                     panic!("Failed to handle RDMSR({:#x}): {:?}", msr, e);
-                },
+                }
             }
         } else {
             panic!("Unsupported RDMSR {:#x}, vcpu: {:#x?}", msr, vcpu);
@@ -138,23 +191,41 @@ impl<H: HyperCraftHal> DeviceList<H> {
     pub fn handle_msr_write(&mut self, vcpu: &mut VCpu<H>) -> HyperResult {
         let msr = vcpu.regs().rcx as u32;
         let value = (vcpu.regs().rax & 0xffff_ffff) | (vcpu.regs().rdx << 32);
-    
+
         if let Some(dev) = self.find_msr_device(msr) {
             match dev.lock().write(msr, value) {
-                Ok(_) => {   
+                Ok(_) => {
                     trace!("VM exit: WRMSR({:#x}) <- {:#x}", msr, value);
-                    
+
                     vcpu.advance_rip(VM_EXIT_INSTR_LEN_WRMSR)?;
                     Ok(())
-                },
+                }
                 Err(e) => {
                     panic!("Failed to handle WRMSR({:#x}): {:?}", msr, e);
-                },
+                }
             }
         } else {
             panic!("Unsupported WRMSR {:#x}, vcpu: {:#x?}", msr, vcpu);
         }
-    }    
+    }
+
+    /// Only overrides the Hyper-V enlightenment leaves `HyperVMsrDevice`
+    /// pairs with (see `device_emu::hyperv`); every other leaf returns
+    /// `None` so the caller falls back to whatever handled CPUID before
+    /// this existed (i.e. nothing overrides it and the guest's real `cpuid`
+    /// result stands).
+    pub fn handle_cpuid(&mut self, vcpu: &mut VCpu<H>) -> Option<HyperResult> {
+        let function = vcpu.regs().rax as u32;
+        let (eax, ebx, ecx, edx) = device_emu::hyperv_cpuid_leaf(function)?;
+
+        trace!("VM exit: CPUID({:#x}) -> Hyper-V leaf", function);
+        let regs = vcpu.regs_mut();
+        regs.rax = eax as u64;
+        regs.rbx = ebx as u64;
+        regs.rcx = ecx as u64;
+        regs.rdx = edx as u64;
+        Some(vcpu.advance_rip(VM_EXIT_INSTR_LEN_CPUID))
+    }
 }
 
 pub struct X64VcpuDevices<H: HyperCraftHal> {
@@ -163,99 +234,180 @@ pub struct X64VcpuDevices<H: HyperCraftHal> {
     pub(crate) devices: DeviceList<H>,
     pub(crate) console: Arc<Mutex<device_emu::Uart16550<device_emu::MultiplexConsoleBackend>>>,
     pub(crate) pic: [Arc<Mutex<device_emu::I8259Pic>>; 2],
-    last: Option<u64>,
+    /// Backs the ACPI CPU-hotplug port device; also reachable directly
+    /// through [`Self::request_cpu_online`]/[`Self::request_cpu_offline`]
+    /// for a host-side command to drive hotplug without going through
+    /// guest ACPI at all.
+    pub(crate) cpu_manager: Arc<Mutex<device_emu::VirtCpuManager>>,
+    /// Last-sampled state of the PIT channel 0 output, i.e. the legacy
+    /// GSI0 line; `check_events` edge-detects against this to turn level
+    /// transitions into `VirtIoApic::raise_gsi`/`lower_gsi` calls.
+    pit_output: bool,
     marker: PhantomData<H>,
 }
 
+impl<H: HyperCraftHal> X64VcpuDevices<H> {
+    /// Brings vCPU `id` online via the ACPI CPU-hotplug device, the same
+    /// path a guest's own enable-register write takes. Lets a host-side
+    /// command drive hotplug directly instead of waiting on guest ACPI AML.
+    pub fn request_cpu_online(&self, id: usize) {
+        self.cpu_manager.lock().enable(id);
+    }
+
+    /// Marks vCPU `id` for removal via the ACPI CPU-hotplug device.
+    pub fn request_cpu_offline(&self, id: usize) {
+        self.cpu_manager.lock().eject(id);
+    }
+
+    /// Serializes this vCPU's local APIC, both PICs, the CMOS/PIT/system-
+    /// control bundle, the COM1 console and the last-sampled PIT-output
+    /// edge, in that fixed order. `devices`/`cpu_manager` aren't included:
+    /// the port/MMIO/MSR dispatch tables are rebuilt fresh by
+    /// [`PerCpuDevices::new`] rather than restored, and CPU hotplug state
+    /// is host-driven rather than guest-visible device state.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new(VCPU_SNAPSHOT_VERSION, VCPU_DEVICE_COUNT);
+        writer.record(&self.apic_timer.lock().save_state());
+        writer.record(&self.pic[0].lock().save_state());
+        writer.record(&self.pic[1].lock().save_state());
+        writer.record(&self.bundle.lock().save_state());
+        writer.record(&self.console.lock().save_state());
+        writer.record(&[self.pit_output as u8]);
+        writer.finish()
+    }
+
+    /// Restores state previously produced by [`Self::save_state`]. Rejects
+    /// a blob from a different version or device topology rather than
+    /// partially applying it.
+    pub fn restore_state(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data, VCPU_SNAPSHOT_VERSION, VCPU_DEVICE_COUNT)?;
+        self.apic_timer
+            .lock()
+            .restore_state(&reader.fixed_record()?);
+        self.pic[0].lock().restore_state(&reader.fixed_record()?);
+        self.pic[1].lock().restore_state(&reader.fixed_record()?);
+        self.bundle
+            .lock()
+            .restore_state(reader.record()?)
+            .map_err(|_| SnapshotError::Corrupt)?;
+        self.console
+            .lock()
+            .restore_state(reader.record()?)
+            .map_err(|_| SnapshotError::Corrupt)?;
+        let [pit_output]: [u8; 1] = reader.fixed_record()?;
+        self.pit_output = pit_output != 0;
+        Ok(())
+    }
+}
+
 impl<H: HyperCraftHal> PerCpuDevices<H> for X64VcpuDevices<H> {
     fn new(vcpu: &VCpu<H>) -> HyperResult<Self> {
         let mut apic_timer = Arc::new(Mutex::new(VirtLocalApic::new()));
         let mut bundle = Arc::new(Mutex::new(Bundle::new()));
-        let mut console = Arc::new(Mutex::new(device_emu::Uart16550::<device_emu::MultiplexConsoleBackend>::new(0x3f8)));
-        let mut pic: [Arc<Mutex<device_emu::I8259Pic>>; 2]  = [
+        let mut console = Arc::new(Mutex::new(device_emu::Uart16550::<
+            device_emu::MultiplexConsoleBackend,
+        >::new(0x3f8)));
+        let mut pic: [Arc<Mutex<device_emu::I8259Pic>>; 2] = [
             Arc::new(Mutex::new(device_emu::I8259Pic::new(0x20))),
             Arc::new(Mutex::new(device_emu::I8259Pic::new(0xA0))),
         ];
 
-        *console.lock().backend() = device_emu::MultiplexConsoleBackend::new_secondary(1, "sleep\n");
+        *console.lock().backend() =
+            device_emu::MultiplexConsoleBackend::new_secondary(1, "sleep\n");
 
         let mut devices = DeviceList::new();
+        let cpu_manager = Arc::new(Mutex::new(device_emu::VirtCpuManager::new()));
 
         let mut pmio_devices: Vec<Arc<Mutex<dyn PortIoDevice>>> = vec![
+            cpu_manager.clone(), // ACPI CPU-hotplug port device
             // console.clone(), // COM1
             Arc::new(Mutex::new(<device_emu::PortPassthrough>::new(0x3f8, 8))),
             Arc::new(Mutex::new(<device_emu::Uart16550>::new(0x2f8))), // COM2
             Arc::new(Mutex::new(<device_emu::Uart16550>::new(0x3e8))), // COM3
             Arc::new(Mutex::new(<device_emu::Uart16550>::new(0x2e8))), // COM4
-            pic[0].clone(), // PIC1
-            pic[1].clone(), // PIC2
-            Arc::new(Mutex::new(device_emu::DebugPort::new(0x80))), // Debug Port
+            pic[0].clone(),                                            // PIC1
+            pic[1].clone(),                                            // PIC2
+            Arc::new(Mutex::new(device_emu::DebugPort::new(0x80))),    // Debug Port
             /*
-                the complexity:
-                - port 0x70 and 0x71 is for CMOS, but bit 7 of 0x70 is for NMI
-                - port 0x40 ~ 0x43 is for PIT, but port 0x61 is also related
-             */
+               the complexity:
+               - port 0x70 and 0x71 is for CMOS, but bit 7 of 0x70 is for NMI
+               - port 0x40 ~ 0x43 is for PIT, but port 0x61 is also related
+            */
             Arc::new(Mutex::new(Bundle::proxy_system_control_a(&bundle))),
             Arc::new(Mutex::new(Bundle::proxy_system_control_b(&bundle))),
             Arc::new(Mutex::new(Bundle::proxy_cmos(&bundle))),
             Arc::new(Mutex::new(Bundle::proxy_pit(&bundle))),
             Arc::new(Mutex::new(device_emu::Dummy::new(0xf0, 2))), // 0xf0 and 0xf1 are ports about fpu
             Arc::new(Mutex::new(device_emu::Dummy::new(0x3d4, 2))), // 0x3d4 and 0x3d5 are ports about vga
-            Arc::new(Mutex::new(device_emu::Dummy::new(0x87, 1))), // 0x87 is a port about dma
+            Arc::new(Mutex::new(device_emu::Dummy::new(0x87, 1))),  // 0x87 is a port about dma
             Arc::new(Mutex::new(device_emu::Dummy::new(0x60, 1))), // 0x60 and 0x64 are ports about ps/2 controller
-            Arc::new(Mutex::new(device_emu::Dummy::new(0x64, 1))), // 
+            Arc::new(Mutex::new(device_emu::Dummy::new(0x64, 1))), //
             Arc::new(Mutex::new(device_emu::PCIConfigurationSpace::new(0xcf8))),
             // Arc::new(Mutex::new(device_emu::PCIPassthrough::new(0xcf8))),
         ];
 
         devices.add_port_io_devices(&mut pmio_devices);
         devices.add_msr_device(Arc::new(Mutex::new(VirtLocalApic::msr_proxy(&apic_timer))));
-        devices.add_msr_device(Arc::new(Mutex::new(ApicBaseMsrHandler{})));
+        devices.add_msr_device(Arc::new(Mutex::new(VirtLocalApic::tsc_deadline_msr_proxy(
+            &apic_timer,
+        ))));
+        devices.add_msr_device(Arc::new(Mutex::new(ApicBaseMsrHandler {})));
         // linux read this amd-related msr on my intel cpu for some unknown reason... make it happy
         devices.add_msr_device(Arc::new(Mutex::new(device_emu::MsrDummy::new(0xc0011029))));
+        // Hyper-V enlightenment MSRs, paired with the CPUID leaves
+        // `handle_cpuid` answers; stops a PV-aware guest that recognizes
+        // "Microsoft Hv" from panicking this hypervisor on a probe.
+        devices.add_msr_device(Arc::new(Mutex::new(device_emu::HyperVMsrDevice::new(
+            vcpu.vcpu_id() as u32,
+        ))));
+
+        let apic_id = device_emu::register_apic(apic_timer.clone());
+        apic_timer.lock().set_apic_id(apic_id);
 
-        Ok(Self { 
+        Ok(Self {
             apic_timer,
             bundle,
             console,
             devices,
             pic,
-            last: None,
+            cpu_manager,
+            pit_output: false,
             marker: PhantomData,
         })
     }
 
-    fn vmexit_handler(&mut self, vcpu: &mut VCpu<H>, exit_info: &VmExitInfo) -> Option<HyperResult> {
+    fn vmexit_handler(
+        &mut self,
+        vcpu: &mut VCpu<H>,
+        exit_info: &VmExitInfo,
+    ) -> Option<HyperResult> {
         match exit_info.exit_reason {
             VmxExitReason::IO_INSTRUCTION => self.devices.handle_io_instruction(vcpu, exit_info),
             VmxExitReason::MSR_READ => Some(self.devices.handle_msr_read(vcpu)),
             VmxExitReason::MSR_WRITE => Some(self.devices.handle_msr_write(vcpu)),
+            VmxExitReason::CPUID => self.devices.handle_cpuid(vcpu),
             _ => None,
         }
     }
 
     fn check_events(&mut self, vcpu: &mut VCpu<H>) -> HyperResult {
-        if self.apic_timer.lock().inner.check_interrupt() {
-            vcpu.queue_event(self.apic_timer.lock().inner.vector(), None);
+        if let Some(vector) = self.apic_timer.lock().check_interrupt() {
+            vcpu.queue_event(vector, None);
         }
 
-        // it's naive but it works.
-        // inject 0x30(irq 0) every 1 ms after 10 seconds after booting.
-        match self.last {
-            Some(last) => {
-                let now = libax::time::current_time_nanos();
-                if now > 1_000_000 + last {
-                    if !self.pic[0].lock().mask().get_bit(0) {
-                        vcpu.queue_event(0x30, None);
-                        let mask = self.pic[0].lock().mask();
-                        // debug!("0x30 queued, mask {mask:#x}");
-                    }
-                    self.last = Some(now);
-                }
-            },
-            None => {
-                self.last = Some(libax::time::current_time_nanos() + 10_000_000_000);
-            },
+        // GSI0 (the legacy PIT/IRQ0 line) is level-driven by channel 0's
+        // square-wave output, same as real hardware; resample it and feed
+        // transitions to the IOAPIC, which now owns deciding who actually
+        // receives it, replacing the old fixed-rate vector-0x30 poke.
+        let output = self.bundle.lock().channel0_output().unwrap_or(false);
+        if output != self.pit_output {
+            self.pit_output = output;
+            let ioapic = device_emu::ioapic();
+            if output {
+                ioapic.lock().raise_gsi(0);
+            } else {
+                ioapic.lock().lower_gsi(0);
+            }
         }
 
         Ok(())
@@ -280,29 +432,86 @@ impl<H: HyperCraftHal> X64VmDevices<H> {
 
         libax::hv::dispatch_host_irq(int_info.vector as usize)
     }
+
+    /// Serializes the VM-wide I/O APIC singleton.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new(VM_SNAPSHOT_VERSION, VM_DEVICE_COUNT);
+        writer.record(&device_emu::ioapic().lock().save_state());
+        writer.finish()
+    }
+
+    /// Restores state previously produced by [`Self::save_state`]. Rejects
+    /// a blob from a different version or device topology rather than
+    /// partially applying it.
+    pub fn restore_state(&self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(data, VM_SNAPSHOT_VERSION, VM_DEVICE_COUNT)?;
+        device_emu::ioapic()
+            .lock()
+            .restore_state(&reader.fixed_record()?);
+        Ok(())
+    }
 }
 
 impl<H: HyperCraftHal> PerVmDevices<H> for X64VmDevices<H> {
     fn new() -> HyperResult<Self> {
-        let devices = DeviceList::new();
-        Ok(Self { marker: PhantomData, devices, })
+        let mut devices = DeviceList::new();
+        devices.add_mmio_device(device_emu::ioapic());
+        Ok(Self {
+            marker: PhantomData,
+            devices,
+        })
     }
 
-    fn vmexit_handler(&mut self, vcpu: &mut VCpu<H>, exit_info: &VmExitInfo) -> Option<HyperResult> {
+    fn vmexit_handler(
+        &mut self,
+        vcpu: &mut VCpu<H>,
+        exit_info: &VmExitInfo,
+    ) -> Option<HyperResult> {
         match exit_info.exit_reason {
             VmxExitReason::EXTERNAL_INTERRUPT => Some(Self::handle_external_interrupt(vcpu)),
+            // NOT IMPLEMENTED, and not closeable from this file alone: this
+            // arm only detects that a fault hit a registered device, it does
+            // not service the access. A real fix needs the same three
+            // ingredients `axvm::device::x86_64::X64VmDevices::
+            // handle_mmio_instruction_to_device` already has - decode the
+            // faulting `mov` at `exit_info.guest_rip`, read/write `device`,
+            // write the result back to the right GPR, advance RIP - and the
+            // first of those needs the faulting instruction's bytes, which
+            // means reading guest memory at `guest_rip`. That requires a
+            // `GuestPhysMemorySet`-equivalent (or a GVA read call) reachable
+            // from here, and there isn't one: this impl's `PerVmDevices::
+            // new()` takes no `vm_id` (axvm's does), so `X64VmDevices` has no
+            // way to look up the owning `axtask::hv::vm::VirtMach` and its
+            // `guest_phys_memory_set`/`translate_gva` in the first place.
+            // Fixing that is a `hypercraft::PerVmDevices` trait change plus
+            // threading a `vm_id` through this app's VM-exit dispatch, not a
+            // change confined to this match arm - tracked as still open,
+            // do not read the `NotSupported` below as "handled".
             VmxExitReason::EPT_VIOLATION => {
                 match vcpu.nested_page_fault_info() {
-                    Ok(fault_info) => panic!(
-                        "VM exit: EPT violation @ {:#x}, fault_paddr={:#x}, access_flags=({:?}), vcpu: {:#x?}",
-                        exit_info.guest_rip, fault_info.fault_guest_paddr, fault_info.access_flags, vcpu
-                    ),
+                    Ok(fault_info) => {
+                        if self
+                            .devices
+                            .find_memory_io_device(fault_info.fault_guest_paddr as usize)
+                            .is_some()
+                        {
+                            warn!(
+                                "VM exit: EPT violation @ {:#x} on a registered MMIO device, but this hypervisor cannot service it yet (no guest-memory reader to decode the faulting instruction); fault_paddr={:#x}, access_flags=({:?})",
+                                exit_info.guest_rip, fault_info.fault_guest_paddr, fault_info.access_flags
+                            );
+                            return Some(Err(HyperError::NotSupported));
+                        }
+                        panic!(
+                            "VM exit: EPT violation @ {:#x}, fault_paddr={:#x}, access_flags=({:?}), vcpu: {:#x?}",
+                            exit_info.guest_rip, fault_info.fault_guest_paddr, fault_info.access_flags, vcpu
+                        )
+                    }
                     Err(err) => panic!(
                         "VM exit: EPT violation with unknown fault info @ {:#x}, vcpu: {:#x?}",
                         exit_info.guest_rip, vcpu
                     ),
                 }
-            },
+            }
             VmxExitReason::IO_INSTRUCTION => self.devices.handle_io_instruction(vcpu, exit_info),
             VmxExitReason::MSR_READ => Some(self.devices.handle_msr_read(vcpu)),
             VmxExitReason::MSR_WRITE => Some(self.devices.handle_msr_write(vcpu)),
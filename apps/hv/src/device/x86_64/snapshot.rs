@@ -0,0 +1,113 @@
+//! Versioned save/restore blob format for [`super::X64VcpuDevices`]/
+//! [`super::X64VmDevices`], composed from each device's own
+//! `save_state`/`restore_state` in [`super::device_emu`].
+//!
+//! Mirrors the flat `tag, len, bytes` record layout
+//! `modules/axvm`'s own (console-only) device snapshot already uses, but
+//! with a version tag and device count in the header instead of per-record
+//! tags, since here every device is always present and always recorded in
+//! the same fixed order.
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+/// A blob's version tag didn't match, its recorded device count didn't
+/// match this build's device list, or it ran out of bytes partway through
+/// a record -- in every case, the blob doesn't describe this topology and
+/// restoring from it would silently corrupt device state instead of
+/// failing loudly.
+#[derive(Debug)]
+pub enum SnapshotError {
+    VersionMismatch { expected: u32, found: u32 },
+    DeviceCountMismatch { expected: u32, found: u32 },
+    Truncated,
+    /// A record's length didn't match what the device it belongs to
+    /// expects (e.g. a fixed-size register block of the wrong width).
+    Corrupt,
+}
+
+/// Append-only writer for the header + flat record sequence
+/// `save_state` builds.
+pub struct SnapshotWriter {
+    buf: Vec<u8>,
+}
+
+impl SnapshotWriter {
+    pub fn new(version: u32, device_count: u32) -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&version.to_le_bytes());
+        buf.extend_from_slice(&device_count.to_le_bytes());
+        Self { buf }
+    }
+
+    pub fn record(&mut self, bytes: &[u8]) {
+        buf_push_record(&mut self.buf, bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+fn buf_push_record(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+/// Matching reader for [`SnapshotWriter`]'s layout; validates the header
+/// up front so every subsequent [`Self::record`] call can assume it's
+/// reading a blob meant for this device list.
+pub struct SnapshotReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new(
+        data: &'a [u8],
+        expected_version: u32,
+        expected_device_count: u32,
+    ) -> Result<Self, SnapshotError> {
+        if data.len() < 8 {
+            return Err(SnapshotError::Truncated);
+        }
+        let version = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        if version != expected_version {
+            return Err(SnapshotError::VersionMismatch {
+                expected: expected_version,
+                found: version,
+            });
+        }
+        let device_count = u32::from_le_bytes(data[4..8].try_into().unwrap());
+        if device_count != expected_device_count {
+            return Err(SnapshotError::DeviceCountMismatch {
+                expected: expected_device_count,
+                found: device_count,
+            });
+        }
+        Ok(Self { data, pos: 8 })
+    }
+
+    /// The next record's bytes, or [`SnapshotError::Truncated`] if the
+    /// blob ends before a full length-prefixed record does.
+    pub fn record(&mut self) -> Result<&'a [u8], SnapshotError> {
+        if self.data.len() < self.pos + 4 {
+            return Err(SnapshotError::Truncated);
+        }
+        let len =
+            u32::from_le_bytes(self.data[self.pos..self.pos + 4].try_into().unwrap()) as usize;
+        self.pos += 4;
+        if self.data.len() < self.pos + len {
+            return Err(SnapshotError::Truncated);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Like [`Self::record`], but additionally requires the record be
+    /// exactly `N` bytes, matching a device's fixed-size state array.
+    pub fn fixed_record<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        self.record()?.try_into().map_err(|_| SnapshotError::Corrupt)
+    }
+}
@@ -1,4 +1,12 @@
 //! copy from beta's
+//!
+//! Reserved TCP transport for `gdbstub`: nothing currently builds a
+//! `gdbstub::target::Target` over this `Connection`/`ConnectionExt` impl.
+//! The attach path that's actually wired into vmexit handling today is
+//! `crate::hv::vmx::gdb::GdbStub`, a hand-rolled RSP stub run directly over
+//! an `Uart16550` - see that module's doc comment for how the two line up
+//! register-for-register and event-for-event with what a `Target` impl here
+//! would need.
 use core::str::FromStr;
 use gdbstub::conn::{Connection, ConnectionExt};
 use libax::io::{prelude::*, Error, Result};
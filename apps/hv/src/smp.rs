@@ -1,4 +1,5 @@
-use libax::hv::{HyperCraftHalImpl, PerCpu, receive_message};
+use crate::device::device_emu::deliver_to_apic;
+use libax::hv::{receive_message, HyperCraftHalImpl, PerCpu};
 
 #[no_mangle]
 fn hv_virt_ipi_handler(hart_id: usize) {
@@ -8,10 +9,16 @@ fn hv_virt_ipi_handler(hart_id: usize) {
             let start_addr = msg.args[0];
             // ap_start(hart_id, start_addr);
         }
+        // Sent by `VirtIoApic::raise_gsi`: `hart_id` doubles as the local
+        // APIC id `register_apic` handed out, since vCPUs are brought up
+        // one per hart in order.
+        libax::hv::Signal::Interrupt => {
+            let vector = msg.args[0] as u8;
+            deliver_to_apic(hart_id, vector);
+        }
     }
 }
 
-
 // fn ap_start(hart_id: usize, start_addr: usize) {
 //     let start_addr = start_addr << 12;
 //     println!("[{}] hv ap start 0x{:x}", hart_id, start_addr);
@@ -31,4 +38,4 @@ fn hv_virt_ipi_handler(hart_id: usize) {
 //
 //     p.hardware_disable().unwrap();
 //     return;
-// }
\ No newline at end of file
+// }
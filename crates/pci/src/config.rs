@@ -1,3 +1,4 @@
+use crate::msi::MsiConfig;
 use crate::msix::{Msix, MSIX_TABLE_ENTRY_SIZE};
 use crate::util::num_ops::ranges_overlap;
 use crate::{
@@ -150,6 +151,11 @@ pub const MEM_BASE_ADDR_MASK: u64 = 0xffff_ffff_ffff_fff0;
 pub const BAR_MEM_64BIT: u8 = 0x04;
 const BAR_PREFETCH: u8 = 0x08;
 pub const BAR_SPACE_UNMAPPED: u64 = 0xffff_ffff_ffff_ffff;
+/// What [`PciConfig::get_bar_address`] reads back for a 64-bit BAR while a
+/// guest's size probe (writing `0xffff_ffff` to both dwords) is in
+/// progress but the real address hasn't been written back yet - never a
+/// legitimate relocation target.
+const BAR_ADDR_ALL_ONES: u64 = u64::MAX & MEM_BASE_ADDR_MASK;
 /// The maximum Bar ID numbers of a Type 0 device
 const BAR_NUM_MAX_FOR_ENDPOINT: u8 = 6;
 /// The maximum Bar ID numbers of a Type 1 device
@@ -158,6 +164,14 @@ const BAR_NUM_MAX_FOR_BRIDGE: u8 = 2;
 pub const MINIMUM_BAR_SIZE_FOR_MMIO: usize = 0x1000;
 /// pio bar's minimum size shall be 4B
 const MINIMUM_BAR_SIZE_FOR_PIO: usize = 0x4;
+/// expansion rom bar's minimum size shall be 2KB
+const MINIMUM_BAR_SIZE_FOR_ROM: usize = 0x800;
+
+/// Expansion ROM Base Address register: bit 0 is the ROM enable bit,
+/// distinct from `COMMAND_MEMORY_SPACE`; bits 10:1 are reserved; bits 31:11
+/// are the (2KB-aligned) base address.
+const ROM_ADDR_ENABLE: u32 = 0x0000_0001;
+const ROM_ADDR_MASK: u32 = 0xffff_f800;
 
 /// PCI Express capability registers, same as kernel defines
 
@@ -208,6 +222,8 @@ pub const PCI_EXP_LNKSTA_NLW: u16 = 0x03f0;
 
 // Attention button present.
 const PCI_EXP_SLTCAP_ABP: u32 = 0x0000_0001;
+/// Slot Capabilities
+const PCI_EXP_SLTCAP: u16 = 20;
 // Power controller present.
 const PCI_EXP_SLTCAP_PCP: u32 = 0x0000_0002;
 // Attention indicator present.
@@ -288,6 +304,24 @@ const PCI_EXP_DEVCAP2_EETLPP: u32 = 0x0020_0000;
 const PCI_EXP_DEVCTL2_ARI: u16 = 0x0020;
 // End-End TLP Prefix Blocking
 const PCI_EXP_DEVCTL2_EETLPPB: u16 = 0x8000;
+// LTR mechanism enable.
+const PCI_EXP_DEVCTL2_LTR: u16 = 0x0400;
+
+/// Latency Tolerance Reporting extended capability ID (PCIe spec).
+const PCI_EXT_CAP_ID_LTR: u16 = 0x0018;
+/// LTR capability version.
+const PCI_EXT_CAP_VER_LTR: u32 = 1;
+/// Header (4B) + Max Snoop Latency (2B) + Max No-Snoop Latency (2B).
+const LTR_CAP_SIZE: usize = 8;
+/// Offset of the Max Snoop Latency register, relative to the LTR
+/// capability's own offset.
+const LTR_MAX_SNOOP_LATENCY: usize = 0x04;
+/// Offset of the Max No-Snoop Latency register, relative to the LTR
+/// capability's own offset.
+const LTR_MAX_NO_SNOOP_LATENCY: usize = 0x06;
+/// Writable bits of either LTR latency register: a 10-bit Latency Value
+/// (bits 0:9) and a 3-bit Scale (bits 13:15); bits 10:12 are reserved.
+const LTR_LATENCY_WRITABLE_MASK: u16 = 0xe3ff;
 
 // Supported Link Speeds Vector.
 const PCI_EXP_LNKCAP2_SLS_2_5GB: u32 = 0x02;
@@ -449,6 +483,41 @@ impl MmioOps for Bar {
     }
 }
 
+/// Round `addr` up to the next multiple of `align`, which must be a power
+/// of two.
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// Options for [`PciBarAllocator::alloc_with`]. PCI requires a BAR to be
+/// naturally aligned to a power of two at least as large as its size, which
+/// `size.next_power_of_two()` always satisfies; `align` lets a caller ask
+/// for something stricter than that, e.g. a 2MB boundary for a
+/// hugepage-backed MMIO window.
+#[derive(Clone, Copy)]
+pub struct AllocOptions {
+    pub region_type: RegionType,
+    pub size: u64,
+    pub align: Option<u64>,
+}
+
+impl AllocOptions {
+    pub fn new(region_type: RegionType, size: u64) -> Self {
+        Self {
+            region_type,
+            size,
+            align: None,
+        }
+    }
+
+    /// Request at least `align` alignment, on top of the region's natural
+    /// one. Must be a power of two.
+    pub fn align(mut self, align: u64) -> Self {
+        self.align = Some(align);
+        self
+    }
+}
+
 pub struct PciBarAllocator {
     mem32_alloc: BTreeMap<u64, u64>,
     mem64_alloc: BTreeMap<u64, u64>,
@@ -465,6 +534,21 @@ impl PciBarAllocator {
     }
 
     pub fn alloc(&mut self, region_type: RegionType, size: u64) -> HyperResult<u64> {
+        self.alloc_with(AllocOptions::new(region_type, size))
+    }
+
+    /// Like `alloc`, but with explicit control over alignment via
+    /// [`AllocOptions`]. The candidate address is always rounded up to at
+    /// least `size`'s next power of two (natural PCI BAR alignment), and
+    /// further to `opts.align` if that's larger.
+    pub fn alloc_with(&mut self, opts: AllocOptions) -> HyperResult<u64> {
+        let region_type = opts.region_type;
+        let size = opts.size;
+        let align = opts
+            .align
+            .unwrap_or(0)
+            .max(size.next_power_of_two())
+            .max(1);
         let (alloc_map, base, limit) = match region_type {
             RegionType::Mem32Bit => (
                 &mut self.mem32_alloc,
@@ -479,7 +563,7 @@ impl PciBarAllocator {
             RegionType::Io => (&mut self.io_alloc, PCI_EMUL_IOBASE, PCI_EMUL_IOLIMIT),
         };
         debug!("alloc map:{:#?}", alloc_map);
-        let mut addr = base;
+        let mut addr = align_up(base, align);
         debug!("addr in alloc begin:{:#x}", addr);
         for (&start, &end) in alloc_map.iter() {
             if addr + size <= start {
@@ -487,7 +571,7 @@ impl PciBarAllocator {
                 debug!("this is addr in alloc map:{:#x}", addr);
                 return Ok(addr);
             }
-            addr = end;
+            addr = align_up(end, align);
         }
 
         if addr + size <= limit {
@@ -507,6 +591,7 @@ impl PciBarAllocator {
     ) -> HyperResult<u64> {
         // align up to 4KB
         let size = (size + 0xfff) & !0xfff;
+        let align = size.next_power_of_two();
         let (alloc_map, base, limit) = match region_type {
             RegionType::Mem32Bit => (
                 &mut self.mem32_alloc,
@@ -521,12 +606,24 @@ impl PciBarAllocator {
             RegionType::Io => (&mut self.io_alloc, PCI_EMUL_IOBASE, PCI_EMUL_IOLIMIT),
         };
 
-        if specific_addr < base || specific_addr + size > limit {
+        if specific_addr < base || specific_addr + size > limit || specific_addr % align != 0 {
             return Err(HyperError::InvalidBarAddress);
         }
 
         for (&start, &end) in alloc_map.iter() {
-            if specific_addr >= start && specific_addr + size <= end {
+            // Reject any overlap with an already-allocated range, not just the
+            // case where the new range is fully contained within it - a BAR
+            // reprogrammed to straddle an existing allocation must be refused
+            // just the same, or the two devices end up aliased onto the same
+            // physical range.
+            if ranges_overlap(
+                specific_addr as usize,
+                size as usize,
+                start as usize,
+                (end - start) as usize,
+            )
+            .unwrap()
+            {
                 return Err(HyperError::InvalidBarAddress);
             }
         }
@@ -572,6 +669,84 @@ enum PcieCap {
     LinkCtl2 = 0x30,
 }
 
+/// A BAR the guest just relocated via a config-space write, as reported by
+/// [`PciConfig::write_bars`]. The device layer should tear down whatever it
+/// had registered/mapped at `old_base` and re-register at `new_base`.
+///
+/// This is already the deferred/size-probe-aware reprogramming record: a
+/// `BarRemap` is only ever produced once `write_bars` has distinguished a
+/// real relocation from an in-flight `0xffff_ffff` sizing probe and, for a
+/// split 64-bit BAR, from the low/high dwords being written one at a time
+/// (see the doc comment on `write_bars` for how).
+#[derive(Debug, Clone, Copy)]
+pub struct BarRemap {
+    pub id: usize,
+    pub region_type: RegionType,
+    pub old_base: u64,
+    pub new_base: u64,
+    pub len: u64,
+}
+
+/// Current layout version of [`PciConfigSnapshot`]. Bump this whenever a
+/// field is added, removed, or reinterpreted, so a snapshot captured by an
+/// older build can be rejected by [`PciConfig::restore_state`] instead of
+/// being silently misread.
+pub const PCI_CONFIG_SNAPSHOT_VERSION: u32 = 1;
+
+/// Snapshot of one [`Bar`] (or the expansion ROM bar): just the decoded
+/// `region_type`/`address`/`size`, not the live `ops` closure or the
+/// host-specific `actual_address` - neither makes sense to carry across a
+/// migration to a different process. [`PciConfig::restore_state`]
+/// re-derives both from arguments the caller supplies.
+#[derive(Debug, Clone, Copy)]
+pub struct BarSnapshot {
+    pub region_type: RegionType,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// Snapshot of the MSI-X state behind [`PciConfig::msix`]: the shadow
+/// table/PBA bytes plus the mask/enable flags decoded from them.
+/// `dev_id`/`msi_irq_manager` are runtime handles tied to the running
+/// device rather than serializable state - the caller is expected to have
+/// already re-created the `Msix` (e.g. via [`crate::msix::init_msix`])
+/// before calling [`PciConfig::restore_state`], which only overwrites its
+/// table/PBA contents via [`crate::msix::Msix::restore`].
+#[derive(Debug, Clone)]
+pub struct MsixSnapshot {
+    pub table: Vec<u8>,
+    pub pba: Vec<u8>,
+    pub func_masked: bool,
+    pub enabled: bool,
+}
+
+/// Versioned snapshot of a [`PciConfig`], for VM migration and
+/// suspend/resume. Captures the raw configuration-space bytes plus the
+/// decoded BAR/MSI-X state; deliberately excludes the non-serializable
+/// per-BAR `ops`/`actual_address` and per-MSI-X `dev_id`/`msi_irq_manager`,
+/// all of which [`PciConfig::restore_state`] re-derives from arguments
+/// supplied by the caller instead of from the blob itself.
+///
+/// `last_cap_end`/`last_ext_cap_offset`/`last_ext_cap_end` aren't captured
+/// either: [`PciConfig::restore_state`] is meant to be called on a
+/// `PciConfig` that the device has already brought back through its normal
+/// `new()` + `add_pci_cap`/`add_pcie_ext_cap` construction sequence (so its
+/// BARs, MSI-X, and any other capabilities already exist with fresh
+/// `RegionOps`/IRQ handles to restore into), which leaves those cursors at
+/// the same value they'd have had when the snapshot was taken. Likewise a
+/// BAR's `prefetchable` bit needs no separate field - it's just part of
+/// `config`, which is restored byte-for-byte.
+#[derive(Debug, Clone)]
+pub struct PciConfigSnapshot {
+    pub version: u32,
+    pub config: Vec<u8>,
+    pub write_mask: Vec<u8>,
+    pub write_clear_mask: Vec<u8>,
+    pub bars: Vec<BarSnapshot>,
+    pub rom_bar: Option<BarSnapshot>,
+    pub msix: Option<MsixSnapshot>,
+}
+
 /// Device/Port Type in PCIe capability register.
 pub enum PcieDevType {
     PcieEp,
@@ -585,6 +760,139 @@ pub enum PcieDevType {
     RcEventCol,
 }
 
+/// Which standard PCI configuration header a [`PciConfigurationBuilder`]
+/// should lay out: a regular function (type 0) or a PCI-to-PCI bridge
+/// (type 1). Drives which `init_*`/`reset_*` pair gets called and what
+/// [`PciConfig::validate_bar_id`] treats as the BAR count.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PciHeaderType {
+    Device,
+    Bridge,
+}
+
+/// Typed, misconfiguration-resistant way to stand up a fresh
+/// [`PciConfig`]: fill in vendor/device/class identity and header-type
+/// flags through named setters instead of poking `VENDOR_ID`/`DEVICE_ID`/
+/// `SUB_CLASS_CODE`/etc. offsets by hand, then [`Self::build`] writes them
+/// at the right offsets and runs the write-mask/write-clear-mask/reset
+/// initializers matching the chosen [`PciHeaderType`] - the same
+/// `init_common_write_mask`/`init_bridge_write_mask`/
+/// `init_common_write_clear_mask`/`init_bridge_write_clear_mask`/
+/// `reset_common_regs`/`reset_bridge_regs` calls a device's `realize()`
+/// would otherwise have to remember to make directly.
+pub struct PciConfigurationBuilder<B: BarAllocTrait> {
+    header_type: PciHeaderType,
+    config_size: usize,
+    nr_bar: u8,
+    vendor_id: u16,
+    device_id: u16,
+    /// Base class (high byte) and subclass (low byte), e.g.
+    /// [`CLASS_CODE_PCI_BRIDGE`].
+    class_code: u16,
+    prog_if: u8,
+    revision_id: u8,
+    subsystem_vendor_id: u16,
+    subsystem_id: u16,
+    interrupt_pin: u8,
+    multifunction: bool,
+    _phantom: PhantomData<B>,
+}
+
+impl<B: BarAllocTrait> PciConfigurationBuilder<B> {
+    pub fn new(
+        header_type: PciHeaderType,
+        config_size: usize,
+        nr_bar: u8,
+        vendor_id: u16,
+        device_id: u16,
+        class_code: u16,
+    ) -> Self {
+        Self {
+            header_type,
+            config_size,
+            nr_bar,
+            vendor_id,
+            device_id,
+            class_code,
+            prog_if: 0,
+            revision_id: 0,
+            subsystem_vendor_id: 0,
+            subsystem_id: 0,
+            interrupt_pin: 0,
+            multifunction: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn prog_if(mut self, prog_if: u8) -> Self {
+        self.prog_if = prog_if;
+        self
+    }
+
+    pub fn revision_id(mut self, revision_id: u8) -> Self {
+        self.revision_id = revision_id;
+        self
+    }
+
+    pub fn subsystem(mut self, subsystem_vendor_id: u16, subsystem_id: u16) -> Self {
+        self.subsystem_vendor_id = subsystem_vendor_id;
+        self.subsystem_id = subsystem_id;
+        self
+    }
+
+    pub fn interrupt_pin(mut self, interrupt_pin: u8) -> Self {
+        self.interrupt_pin = interrupt_pin;
+        self
+    }
+
+    /// Set the multifunction bit (bit 7 of Header Type), for a function
+    /// other than 0 of a multi-function device.
+    pub fn multifunction(mut self, multifunction: bool) -> Self {
+        self.multifunction = multifunction;
+        self
+    }
+
+    /// Write every field configured so far into a fresh [`PciConfig`] and
+    /// run the header-type-appropriate initializers.
+    pub fn build(self) -> Result<PciConfig<B>> {
+        let mut config = PciConfig::new(self.config_size, self.nr_bar);
+
+        le_write_u16(&mut config.config, VENDOR_ID as usize, self.vendor_id)?;
+        le_write_u16(&mut config.config, DEVICE_ID as usize, self.device_id)?;
+        config.config[REVISION_ID] = self.revision_id;
+        config.config[REVISION_ID + 1] = self.prog_if;
+        le_write_u16(&mut config.config, SUB_CLASS_CODE as usize, self.class_code)?;
+        le_write_u16(&mut config.config, SUBSYSTEM_VENDOR_ID, self.subsystem_vendor_id)?;
+        le_write_u16(&mut config.config, SUBSYSTEM_ID, self.subsystem_id)?;
+        config.config[INTERRUPT_PIN as usize] = self.interrupt_pin;
+
+        let is_bridge = self.header_type == PciHeaderType::Bridge;
+        let mut header_type_byte = if is_bridge {
+            HEADER_TYPE_BRIDGE
+        } else {
+            HEADER_TYPE_ENDPOINT
+        };
+        if self.multifunction {
+            header_type_byte |= HEADER_TYPE_MULTIFUNC;
+        }
+        config.config[HEADER_TYPE as usize] = header_type_byte;
+
+        config.init_common_write_mask()?;
+        config.init_common_write_clear_mask()?;
+        if is_bridge {
+            config.init_bridge_write_mask()?;
+            config.init_bridge_write_clear_mask()?;
+        }
+
+        config.reset_common_regs()?;
+        if is_bridge {
+            config.reset_bridge_regs()?;
+        }
+
+        Ok(config)
+    }
+}
+
 /// Configuration space of PCI/PCIe device.
 #[derive(Clone)]
 pub struct PciConfig<B: BarAllocTrait> {
@@ -604,6 +912,19 @@ pub struct PciConfig<B: BarAllocTrait> {
     pub last_ext_cap_end: u16,
     /// MSI-X information.
     pub msix: Option<Arc<Mutex<Msix>>>,
+    /// Expansion ROM: a seventh, logical BAR decoded through the
+    /// Expansion ROM Base Address register (`ROM_ADDRESS_ENDPOINT`/
+    /// `ROM_ADDRESS_BRIDGE`) instead of `BAR_0..=BAR_5`, present only if
+    /// [`PciConfig::register_rom_bar`] was called.
+    pub rom_bar: Option<Bar>,
+    /// Base offset of the Latency Tolerance Reporting extended capability,
+    /// present only if [`PciConfig::register_ltr_cap`] was called.
+    pub ltr_cap_offset: Option<u16>,
+    /// Legacy MSI information, present only if
+    /// [`crate::msi::add_msi_capability`] was called. A device can have
+    /// this, `msix`, both (e.g. advertising MSI-X with MSI as a fallback a
+    /// guest driver may pick instead), or neither.
+    pub msi: Option<Arc<Mutex<MsiConfig>>>,
     /// Phantom data.
     _phantom: PhantomData<B>,
 }
@@ -636,11 +957,33 @@ impl<B: BarAllocTrait> PciConfig<B> {
             last_ext_cap_offset: 0,
             last_ext_cap_end: PCI_CONFIG_SPACE_SIZE as u16,
             msix: None,
+            rom_bar: None,
+            ltr_cap_offset: None,
+            msi: None,
             _phantom: PhantomData,
         }
     }
 
+    /// Build a [`PciConfig`] via [`PciConfigurationBuilder`] instead of
+    /// [`PciConfig::new`] plus hand-written offset pokes.
+    pub fn builder(
+        header_type: PciHeaderType,
+        config_size: usize,
+        nr_bar: u8,
+        vendor_id: u16,
+        device_id: u16,
+        class_code: u16,
+    ) -> PciConfigurationBuilder<B> {
+        PciConfigurationBuilder::new(header_type, config_size, nr_bar, vendor_id, device_id, class_code)
+    }
+
     /// Init write_mask for all kinds of PCI/PCIe devices, including bridges.
+    ///
+    /// The Expansion ROM Base Address register isn't touched here: like a
+    /// BAR's own write_mask (set up in `register_bar`), its write_mask
+    /// stays all-zero - and the register reads as hardwired-0 - until
+    /// [`PciConfig::register_rom_bar`] gives the device an actual ROM
+    /// image to decode.
     pub fn init_common_write_mask(&mut self) -> Result<()> {
         self.write_mask[CACHE_LINE_SIZE as usize] = 0xff;
         self.write_mask[INTERRUPT_LINE as usize] = 0xff;
@@ -802,6 +1145,10 @@ impl<B: BarAllocTrait> PciConfig<B> {
             msix.lock()
                 .write_config(&self.config, dev_id, old_offset, data);
         }
+
+        if let Some(msi) = &self.msi {
+            msi.lock().write_config(&mut self.config, old_offset, size);
+        }
     }
 
     /// Reset type1 specific configuration space.
@@ -852,6 +1199,10 @@ impl<B: BarAllocTrait> PciConfig<B> {
             msix.lock().reset();
         }
 
+        if let Some(msi) = &self.msi {
+            msi.lock().reset(&mut self.config);
+        }
+
         Ok(())
     }
 
@@ -910,6 +1261,85 @@ impl<B: BarAllocTrait> PciConfig<B> {
         }
     }
 
+    /// Offset of the Expansion ROM Base Address register, which differs
+    /// between a type 0 (endpoint) and type 1 (bridge) header layout.
+    fn rom_addr_offset(&self) -> usize {
+        match self.config[HEADER_TYPE as usize] & HEADER_TYPE_BRIDGE {
+            HEADER_TYPE_BRIDGE => ROM_ADDRESS_BRIDGE,
+            _ => ROM_ADDRESS_ENDPOINT,
+        }
+    }
+
+    /// Get base address of the expansion ROM BAR, or [`BAR_SPACE_UNMAPPED`]
+    /// if there's no ROM registered, its own enable bit is clear, or
+    /// `COMMAND_MEMORY_SPACE` is clear.
+    pub fn get_rom_address(&self) -> u64 {
+        if self.rom_bar.is_none() {
+            return BAR_SPACE_UNMAPPED;
+        }
+        let command = le_read_u16(&self.config, COMMAND as usize).unwrap();
+        if command & COMMAND_MEMORY_SPACE == 0 {
+            return BAR_SPACE_UNMAPPED;
+        }
+        let reg = le_read_u32(&self.config, self.rom_addr_offset()).unwrap();
+        if reg & ROM_ADDR_ENABLE == 0 {
+            return BAR_SPACE_UNMAPPED;
+        }
+        (reg & ROM_ADDR_MASK) as u64
+    }
+
+    /// Register the device's option ROM image as a seventh, logical BAR: a
+    /// `Mem32Bit` region decoded through the Expansion ROM Base Address
+    /// register instead of one of `BAR_0..=BAR_5`, gated on its own enable
+    /// bit (bit 0 of that register) in addition to `COMMAND_MEMORY_SPACE`.
+    ///
+    /// This lives in its own `rom_bar` field rather than a sixth/seventh
+    /// entry appended to `bars`, so `validate_bar_id`/`validate_bar_size`
+    /// and every existing `register_bar` caller keep working against the
+    /// real BAR count without special-casing a ROM index; `find_mmio`,
+    /// `update_bar_mapping`, `write_bars`, and `save_state`/`restore_state`
+    /// each check `rom_bar` alongside `bars` explicitly instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `ops` - RegionOps serving the ROM image, e.g. reads backed by a
+    ///   buffer supplied at device construction.
+    /// * `size` - Size of the ROM image; must be a power of two and at
+    ///   least `MINIMUM_BAR_SIZE_FOR_ROM` (2KB).
+    pub fn register_rom_bar(&mut self, ops: Option<RegionOps>, size: u64) -> Result<()> {
+        if !size.is_power_of_two()
+            || size < MINIMUM_BAR_SIZE_FOR_ROM as u64
+            || size > u32::MAX as u64
+        {
+            return Err(HyperError::PciError(PciError::InvalidConf(
+                String::from("rom bar size"),
+                format!("{}", size),
+            )));
+        }
+
+        let offset = self.rom_addr_offset();
+        let write_mask = (!(size - 1) as u32 & ROM_ADDR_MASK) | ROM_ADDR_ENABLE;
+        le_write_u32(&mut self.write_mask, offset, write_mask)?;
+
+        let mut allocator = PCI_BAR_ALLOCATOR.lock();
+        let addr = allocator.alloc(RegionType::Mem32Bit, size)?;
+        drop(allocator);
+
+        // Address bits pre-placed the same way `register_bar` does; the
+        // enable bit starts clear so the ROM only decodes once
+        // firmware/the guest driver turns it on.
+        le_write_u32(&mut self.config, offset, addr as u32 & ROM_ADDR_MASK)?;
+
+        self.rom_bar = Some(Bar {
+            region_type: RegionType::Mem32Bit,
+            address: addr,
+            actual_address: 0,
+            size,
+            ops,
+        });
+        Ok(())
+    }
+
     /// Register a bar in PciConfig::bars.
     ///
     /// # Arguments
@@ -1010,11 +1440,43 @@ impl<B: BarAllocTrait> PciConfig<B> {
             }
         }
 
+        if let Some(rom_bar) = self.rom_bar.take() {
+            if rom_bar.address != BAR_SPACE_UNMAPPED && rom_bar.size != 0 {
+                let mut allocator = PCI_BAR_ALLOCATOR.lock();
+                allocator.dealloc(rom_bar.region_type, rom_bar.address)?;
+            }
+            let offset = self.rom_addr_offset();
+            for j in 0..4 {
+                self.config[offset + j] = 0;
+            }
+        }
+
         Ok(())
     }
 
     /// Update bar space mapping once the base address is updated by the guest.
     pub fn update_bar_mapping(&mut self, is_empty: bool) -> Result<()> {
+        if let Some(rom_bar) = &self.rom_bar {
+            if rom_bar.size != 0 {
+                let cur_addr = rom_bar.address;
+                let new_addr = self.get_rom_address();
+                if cur_addr != new_addr {
+                    if cur_addr != BAR_SPACE_UNMAPPED {
+                        let mut allocator = PCI_BAR_ALLOCATOR.lock();
+                        allocator.dealloc(RegionType::Mem32Bit, cur_addr);
+                    }
+                    self.rom_bar.as_mut().unwrap().address = BAR_SPACE_UNMAPPED;
+
+                    if !is_empty && new_addr != BAR_SPACE_UNMAPPED {
+                        let size = self.rom_bar.as_ref().unwrap().size;
+                        let mut allocator = PCI_BAR_ALLOCATOR.lock();
+                        allocator.alloc_addr(RegionType::Mem32Bit, size, new_addr)?;
+                        self.rom_bar.as_mut().unwrap().address = new_addr;
+                    }
+                }
+            }
+        }
+
         for id in 0..self.bars.len() {
             if self.bars[id].size == 0 {
                 continue;
@@ -1060,6 +1522,72 @@ impl<B: BarAllocTrait> PciConfig<B> {
         Ok(())
     }
 
+    /// Write `data` at `offset`, same as [`PciConfig::write`], additionally
+    /// detecting any BAR the write relocated and reporting each as a
+    /// [`BarRemap`] for the device layer to re-register/re-map.
+    ///
+    /// The standard size-probe dance (guest writes `0xffff_ffff`, reads
+    /// back the size mask, then writes the real base) needs no special
+    /// casing here for a 32-bit or I/O BAR: `register_bar`'s `write_mask`
+    /// already limits a write to `!(size - 1)` ORed with the untouched type
+    /// bits, so a plain probe never reads back as a changed base. The one
+    /// case that does need explicit handling is a 64-bit BAR split across
+    /// two dwords - probing writes `0xffff_ffff` to the low *or* high
+    /// dword, either of which may legitimately be half of a real new
+    /// address, so the two halves must be reassembled (which
+    /// `get_bar_address` already does) before comparing, and a result
+    /// that's still the all-ones sentinel across both halves means the
+    /// probe is mid-flight, not a relocation.
+    ///
+    /// Each reported remap is implicitly gated on the relevant
+    /// `COMMAND_MEMORY_SPACE`/`COMMAND_IO_SPACE` enable bit, since
+    /// [`PciConfig::get_bar_address`] already reads back [`BAR_SPACE_UNMAPPED`]
+    /// while the bit is clear.
+    ///
+    /// The expansion ROM BAR (if registered via [`PciConfig::register_rom_bar`])
+    /// is reported the same way, at `id == self.bars.len()`.
+    pub fn write_bars(&mut self, offset: usize, data: &[u8], dev_id: u16) -> Vec<BarRemap> {
+        let old_bases: Vec<u64> = (0..self.bars.len()).map(|id| self.get_bar_address(id)).collect();
+        let rom_old_base = self.get_rom_address();
+
+        self.write(offset, data, dev_id);
+
+        let mut remaps = Vec::new();
+        for (id, old_base) in old_bases.into_iter().enumerate() {
+            if self.bars[id].size == 0 {
+                continue;
+            }
+            let new_base = self.get_bar_address(id);
+            if new_base == old_base {
+                continue;
+            }
+            if self.bars[id].region_type == RegionType::Mem64Bit && new_base == BAR_ADDR_ALL_ONES {
+                continue;
+            }
+            remaps.push(BarRemap {
+                id,
+                region_type: self.bars[id].region_type,
+                old_base,
+                new_base,
+                len: self.bars[id].size,
+            });
+        }
+
+        if let Some(rom_bar) = &self.rom_bar {
+            let rom_new_base = self.get_rom_address();
+            if rom_new_base != rom_old_base {
+                remaps.push(BarRemap {
+                    id: self.bars.len(),
+                    region_type: RegionType::Mem32Bit,
+                    old_base: rom_old_base,
+                    new_base: rom_new_base,
+                    len: rom_bar.size,
+                });
+            }
+        }
+        remaps
+    }
+
     /// Find a PIO BAR by Port.
     pub fn find_pio(&self, port: u16) -> Option<&Bar> {
         self.bars
@@ -1069,6 +1597,11 @@ impl<B: BarAllocTrait> PciConfig<B> {
 
     /// Find a MMIO BAR by Address.
     pub fn find_mmio(&self, addr: u64) -> Option<&Bar> {
+        if let Some(rom_bar) = &self.rom_bar {
+            if rom_bar.address != BAR_SPACE_UNMAPPED && rom_bar.mmio_range().contains(&addr) {
+                return Some(rom_bar);
+            }
+        }
         self.bars.iter().find(|bar| {
             bar.region_type == RegionType::Mem64Bit
                 || bar.region_type == RegionType::Mem32Bit && bar.mmio_range().contains(&addr)
@@ -1153,6 +1686,91 @@ impl<B: BarAllocTrait> PciConfig<B> {
         Ok(offset)
     }
 
+    /// Register the Latency Tolerance Reporting extended capability:
+    /// appends it via [`Self::add_pcie_ext_cap`] and makes its Max Snoop
+    /// Latency / Max No-Snoop Latency registers guest-writable, plus the
+    /// LTR Mechanism Enable bit (bit 10) of Device Control 2 in the
+    /// already-registered standard PCI Express capability.
+    ///
+    /// The guest-programmed latency values and the DEVCTL2 enable bit live
+    /// in plain `config` bytes like any other register, so they're carried
+    /// across [`Self::save_state`]/[`Self::restore_state`] for free, and
+    /// survive [`Self::reset_common_regs`]/[`Self::reset_bridge_regs`]
+    /// since neither clears registers outside their own narrow,
+    /// explicitly-listed set.
+    ///
+    /// # Arguments
+    ///
+    /// * `pcie_cap_offset` - Offset of the standard PCI Express capability
+    ///   (as returned by `add_pci_cap(CapId::Pcie as u8, ...)`), whose
+    ///   Device Control 2 register gains the LTR enable bit.
+    pub fn register_ltr_cap(&mut self, pcie_cap_offset: usize) -> Result<usize> {
+        let offset = self.add_pcie_ext_cap(PCI_EXT_CAP_ID_LTR, LTR_CAP_SIZE, PCI_EXT_CAP_VER_LTR)?;
+
+        le_write_u16(
+            &mut self.write_mask,
+            offset + LTR_MAX_SNOOP_LATENCY,
+            LTR_LATENCY_WRITABLE_MASK,
+        )?;
+        le_write_u16(
+            &mut self.write_mask,
+            offset + LTR_MAX_NO_SNOOP_LATENCY,
+            LTR_LATENCY_WRITABLE_MASK,
+        )?;
+
+        let devctl2_offset = pcie_cap_offset + PcieCap::DevCtl2 as usize;
+        let devctl2_mask = le_read_u16(&self.write_mask, devctl2_offset)?;
+        le_write_u16(
+            &mut self.write_mask,
+            devctl2_offset,
+            devctl2_mask | PCI_EXP_DEVCTL2_LTR,
+        )?;
+
+        self.ltr_cap_offset = Some(offset as u16);
+        Ok(offset)
+    }
+
+    /// Prepare the Slot Capabilities/Control/Status registers of an
+    /// already-registered standard PCI Express capability (see
+    /// `add_pci_cap(CapId::Pcie as u8, ...)`) for native hot-plug:
+    /// advertises Hot-Plug Capable, makes the guest-facing slot control
+    /// bits writable, and marks the RW1C slot status bits clearable.
+    ///
+    /// Pairs with [`crate::hotplug::HotplugController`], which drives the
+    /// actual plug/unplug flow against the registers this sets up.
+    ///
+    /// # Arguments
+    ///
+    /// * `pcie_cap_offset` - Offset of the standard PCI Express capability
+    ///   whose slot registers should become hot-plug-capable.
+    pub fn init_slot_cap(&mut self, pcie_cap_offset: usize) -> Result<()> {
+        let sltcap_offset = pcie_cap_offset + PCI_EXP_SLTCAP as usize;
+        let sltcap = le_read_u32(&self.config, sltcap_offset)?;
+        le_write_u32(&mut self.config, sltcap_offset, sltcap | PCI_EXP_SLTCAP_HPC)?;
+
+        let sltctl_offset = pcie_cap_offset + PCI_EXP_SLTCTL as usize;
+        le_write_u16(
+            &mut self.write_mask,
+            sltctl_offset,
+            PCI_EXP_SLTCTL_ABPE
+                | PCI_EXP_SLTCTL_PDCE
+                | PCI_EXP_SLTCTL_CCIE
+                | PCI_EXP_SLTCTL_HPIE
+                | PCI_EXP_SLTCTL_AIC
+                | PCI_EXP_SLTCTL_PIC
+                | PCI_EXP_SLTCTL_EIC,
+        )?;
+
+        let sltsta_offset = pcie_cap_offset + PCI_EXP_SLTSTA as usize;
+        le_write_u16(
+            &mut self.write_clear_mask,
+            sltsta_offset,
+            PCI_EXP_SLOTSTA_EVENTS,
+        )?;
+
+        Ok(())
+    }
+
     /// Calculate the next extended cap size from pci config space.
     ///
     /// # Arguments
@@ -1201,6 +1819,121 @@ impl<B: BarAllocTrait> PciConfig<B> {
         Ok(())
     }
 
+    /// Capture everything needed to rebuild this config space elsewhere:
+    /// the raw `config`/`write_mask`/`write_clear_mask` bytes, each BAR's
+    /// decoded region_type/address/size (including the expansion ROM bar,
+    /// if registered), and the MSI-X table/PBA/mask state if present.
+    pub fn save_state(&self) -> PciConfigSnapshot {
+        let bar_snapshot = |bar: &Bar| BarSnapshot {
+            region_type: bar.region_type,
+            address: bar.address,
+            size: bar.size,
+        };
+
+        PciConfigSnapshot {
+            version: PCI_CONFIG_SNAPSHOT_VERSION,
+            config: self.config.clone(),
+            write_mask: self.write_mask.clone(),
+            write_clear_mask: self.write_clear_mask.clone(),
+            bars: self.bars.iter().map(bar_snapshot).collect(),
+            rom_bar: self.rom_bar.as_ref().map(bar_snapshot),
+            msix: self.msix.as_ref().map(|msix| {
+                let locked = msix.lock();
+                MsixSnapshot {
+                    table: locked.table.clone(),
+                    pba: locked.pba().to_vec(),
+                    func_masked: locked.func_masked,
+                    enabled: locked.enabled,
+                }
+            }),
+        }
+    }
+
+    /// Rehydrate a snapshot captured by [`Self::save_state`], re-running
+    /// BAR region registration so the MMIO/PIO dispatch tables point at
+    /// freshly bound closures afterwards. `bar_ops` supplies one
+    /// [`RegionOps`] per entry in `snapshot.bars`, in order (`None` for an
+    /// unused slot or a passthrough BAR with no trap handler); `rom_ops`
+    /// is the same for the expansion ROM bar, used only if
+    /// `snapshot.rom_bar.is_some()`.
+    ///
+    /// Assumes `self` is otherwise freshly constructed (no BARs registered
+    /// yet): existing `bars`/`rom_bar` are simply replaced rather than
+    /// unregistered first. If MSI-X state was captured, `self.msix` must
+    /// already be `Some` - the caller re-creates it (e.g. via
+    /// [`crate::msix::init_msix`]) with fresh `dev_id`/`msi_irq_manager`
+    /// handles before calling this.
+    pub fn restore_state(
+        &mut self,
+        snapshot: PciConfigSnapshot,
+        mut bar_ops: Vec<Option<RegionOps>>,
+        rom_ops: Option<RegionOps>,
+    ) -> Result<()> {
+        if snapshot.version != PCI_CONFIG_SNAPSHOT_VERSION {
+            return Err(HyperError::PciError(PciError::InvalidConf(
+                String::from("pci config snapshot version"),
+                format!("{}", snapshot.version),
+            )));
+        }
+
+        self.config = snapshot.config;
+        self.write_mask = snapshot.write_mask;
+        self.write_clear_mask = snapshot.write_clear_mask;
+
+        bar_ops.resize_with(snapshot.bars.len(), || None);
+        self.bars = Vec::with_capacity(snapshot.bars.len());
+        for (saved, ops) in snapshot.bars.into_iter().zip(bar_ops) {
+            self.bars.push(Self::restore_bar(saved, ops)?);
+        }
+
+        self.rom_bar = match snapshot.rom_bar {
+            Some(saved) => Some(Self::restore_bar(saved, rom_ops)?),
+            None => None,
+        };
+
+        match (&self.msix, snapshot.msix) {
+            (Some(msix), Some(saved)) => {
+                msix.lock()
+                    .restore(saved.table, saved.pba, saved.func_masked, saved.enabled);
+            }
+            (None, Some(_)) => {
+                return Err(HyperError::PciError(PciError::InvalidConf(
+                    String::from("pci config snapshot"),
+                    String::from("msix state captured but no Msix registered to restore into"),
+                )));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Shared by [`Self::restore_state`] for both an ordinary BAR and the
+    /// ROM bar: reserve the exact saved address (via
+    /// [`PciBarAllocator::alloc_addr`], not [`PciBarAllocator::alloc`]) so
+    /// the allocator's bookkeeping stays consistent with `config`'s
+    /// already-restored BAR registers, and mint a fresh host-backing
+    /// `actual_address` since the saved one may belong to a process that
+    /// no longer exists.
+    fn restore_bar(saved: BarSnapshot, ops: Option<RegionOps>) -> Result<Bar> {
+        let mut actual_address = 0;
+        if saved.size != 0 {
+            actual_address = B::alloc(saved.region_type, saved.size)?;
+            if saved.address != BAR_SPACE_UNMAPPED {
+                let mut allocator = PCI_BAR_ALLOCATOR.lock();
+                allocator.alloc_addr(saved.region_type, saved.size, saved.address)?;
+            }
+        }
+
+        Ok(Bar {
+            region_type: saved.region_type,
+            address: saved.address,
+            actual_address,
+            size: saved.size,
+            ops,
+        })
+    }
+
     /// check if the msix is valid
     pub fn revise_msix_vector(&self, vector_nr: u32) -> bool {
         if self.msix.is_none() {
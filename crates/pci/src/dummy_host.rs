@@ -16,6 +16,12 @@ const CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET: usize = 7;
 
 const PCI_CFG_ADDR_PORT: u16 = 0xcf8;
 
+/// Classic CONFIG_ADDRESS bit layout (PCI 3.0 Section 3.2.2.3.2).
+const ADDR_ENABLE_BIT: usize = 31;
+const ADDR_BUS: Range<usize> = 16..24;
+const ADDR_DEVFN: Range<usize> = 8..16;
+const ADDR_REG: Range<usize> = 2..8;
+
 #[derive(Clone)]
 pub struct DummyPciHost {
     port_base: u16,
@@ -35,7 +41,26 @@ impl DummyPciHost {
     }
 
     pub fn find_device(&self, bus_num: u8, devfn: u8) -> Option<Arc<Mutex<dyn PciDevOps>>> {
-        None
+        self.root_bus.lock().get_device(bus_num, devfn)
+    }
+
+    /// Whether guest writes have asserted CONFIG_ADDRESS bit 31; accesses
+    /// to the data port are only meaningful while this is set, same as
+    /// real hardware.
+    fn enabled(&self) -> bool {
+        self.current_address.get_bit(ADDR_ENABLE_BIT)
+    }
+
+    /// Decodes the latched address register into `(bus, devfn, register
+    /// offset)`, the classic layout every x86 BIOS/OS assumes.
+    fn addr_bus_devfn_reg(&self) -> (u8, u8, u8) {
+        let bus = self.current_address.get_bits(ADDR_BUS) as u8;
+        let devfn = self.current_address.get_bits(ADDR_DEVFN) as u8;
+        // Bits 2..8 give the dword register number; the byte offset within
+        // it is added back in by the caller from which CFC..CFF port the
+        // access actually landed on.
+        let reg = (self.current_address.get_bits(ADDR_REG) as u8) << 2;
+        (bus, devfn, reg)
     }
 }
 
@@ -46,21 +71,35 @@ impl PioOps for DummyPciHost {
 
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
         match (port - self.port_base) as usize {
-            _offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET
-                ..=CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => {
-                // we return non-sense to tell linux pci is not present.
-                match access_size {
-                    1 => Ok(0xfe),
-                    2 => Ok(0xfffe),
-                    4 => Ok(0xffff_fffe),
-                    _ => Err(HyperError::InvalidParam),
+            offset @ CONFIGURATION_SPACE_ADDRESS_PORT_OFFSET
+                ..=CONFIGURATION_SPACE_ADDRESS_PORT_LAST_OFFSET => match access_size {
+                1 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 8) as u32),
+                2 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 16) as u32),
+                4 => Ok(self.current_address.get_bits(offset * 8..offset * 8 + 32) as u32),
+                _ => Err(HyperError::InvalidParam),
+            },
+            offset @ CONFIGURATION_SPACE_DATA_PORT_OFFSET
+                ..=CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
+                if !self.enabled() {
+                    return Ok(0xffff_ffff);
                 }
-            }
-            CONFIGURATION_SPACE_DATA_PORT_OFFSET..=CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
+                let (bus, devfn, reg) = self.addr_bus_devfn_reg();
+                let Some(dev) = self.find_device(bus, devfn) else {
+                    // No function at this bdf: only this access reads back
+                    // all-ones, so a guest scanning other slots still sees
+                    // them.
+                    return Ok(0xffff_ffff);
+                };
+                let sub_offset = offset - CONFIGURATION_SPACE_DATA_PORT_OFFSET;
+                let mut data = [0xffu8; 4];
+                dev.lock().read_config(
+                    reg as usize + sub_offset,
+                    &mut data[..access_size as usize],
+                );
                 match access_size {
-                    1 => Ok(0xff),
-                    2 => Ok(0xffff),
-                    4 => Ok(0xffff_ffff),
+                    1 => Ok(data[0] as u32),
+                    2 => Ok(u16::from_le_bytes([data[0], data[1]]) as u32),
+                    4 => Ok(u32::from_le_bytes(data)),
                     _ => Err(HyperError::InvalidParam),
                 }
             }
@@ -86,6 +125,21 @@ impl PioOps for DummyPciHost {
                 }),
                 _ => Err(HyperError::InvalidParam),
             },
+            offset @ CONFIGURATION_SPACE_DATA_PORT_OFFSET
+                ..=CONFIGURATION_SPACE_DATA_PORT_LAST_OFFSET => {
+                if !self.enabled() {
+                    return Ok(());
+                }
+                let (bus, devfn, reg) = self.addr_bus_devfn_reg();
+                let Some(dev) = self.find_device(bus, devfn) else {
+                    return Ok(());
+                };
+                let sub_offset = offset - CONFIGURATION_SPACE_DATA_PORT_OFFSET;
+                let bytes = value.to_le_bytes();
+                dev.lock()
+                    .write_config(reg as usize + sub_offset, &bytes[..access_size as usize]);
+                Ok(())
+            }
             _ => Err(HyperError::NotSupported),
         }
     }
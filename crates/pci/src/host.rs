@@ -1,14 +1,16 @@
 use alloc::string::String;
 use alloc::sync::Arc;
+#[cfg(target_arch = "x86_64")]
+use alloc::vec::Vec;
 use core::ops::Range;
 use spin::Mutex;
 use x86::io;
 
 use crate::{bus::PciBus, BarAllocTrait, MsiIrqManager, PciDevOps};
 #[cfg(target_arch = "x86_64")]
-use crate::{le_read_u32, le_write_u32};
+use crate::{config::BAR_0, config::BAR_5, le_read_u32, le_write_u32};
 
-// use hypercraft::MmioOps;
+use hypercraft::MmioOps;
 #[cfg(target_arch = "x86_64")]
 use hypercraft::PioOps;
 use hypercraft::{HyperError, HyperResult};
@@ -24,24 +26,69 @@ const PIO_OFFSET_MASK: u32 = 0xff;
 
 const CONFIG_BUS_MASK: u32 = 0xff;
 const CONFIG_DEVFN_MASK: u32 = 0xff;
-// const ECAM_BUS_SHIFT: u32 = 20;
-// const ECAM_DEVFN_SHIFT: u32 = 12;
-// const ECAM_OFFSET_MASK: u64 = 0xfff;
+const ECAM_BUS_SHIFT: u32 = 20;
+const ECAM_DEVFN_SHIFT: u32 = 12;
+const ECAM_OFFSET_MASK: u64 = 0xfff;
+
+/// Default PCIe ECAM (MMCONFIG) window base, matching the common QEMU
+/// q35 layout. Override via [`PciHost::new`] when the platform places it
+/// elsewhere.
+pub const DEFAULT_ECAM_BASE: u64 = 0xb000_0000;
+
+/// Size of the ECAM window for a single bus: 32 devfns, each with the full
+/// 4 KiB of PCIe extended config space.
+pub const ECAM_BUS_SIZE: u64 = 1 << ECAM_BUS_SHIFT;
 
 const PCI_CFG_ADDR_PORT: Range<u16> = 0xcf8..0xcf8 + 4;
 const PCI_CFG_DATA_PORT: Range<u16> = 0xcfc..0xcfc + 4;
 
+/// Config-space offset range covered by the six standard BARs (`BAR_0` through
+/// the end of `BAR_5`).
+#[cfg(target_arch = "x86_64")]
+const BAR_RANGE: Range<u32> = BAR_0 as u32..BAR_5 as u32 + 4;
+/// Capability list pointer offset (PCI 3.0 Section 6.7). Not re-exported from
+/// `config`, so it's redeclared here for the passthrough sanitization below.
+#[cfg(target_arch = "x86_64")]
+const CAP_LIST: u32 = 0x34;
+
+/// Shadow storage for a passthrough device's BAR registers, sized to cover
+/// [`BAR_RANGE`].
+#[cfg(target_arch = "x86_64")]
+const BAR_SHADOW_LEN: usize = (BAR_5 as usize + 4) - BAR_0 as usize;
+
+/// A BDF forwarded to the real host 0xcf8/0xcfc config space when no
+/// emulated device claims it. BAR registers are shadowed in guest-only
+/// memory instead of being forwarded, so the guest's size-probe ("write
+/// all-ones, read back the size mask") and relocation writes land
+/// harmlessly instead of reprogramming the physical device's real BARs,
+/// and the capability list pointer always reads back as absent, since
+/// nothing here walks the physical device's capability chain on the
+/// guest's behalf.
+#[cfg(target_arch = "x86_64")]
+struct PassthroughDevice {
+    bus_num: u8,
+    devfn: u8,
+    bar_shadow: [u8; BAR_SHADOW_LEN],
+}
+
 #[derive(Clone)]
 pub struct PciHost<B: BarAllocTrait> {
     pub root_bus: Arc<Mutex<PciBus<B>>>,
     #[cfg(target_arch = "x86_64")]
     config_addr: u32,
     check_type1: usize,
+    /// Base guest-physical address of the ECAM MMIO window.
+    ecam_base: u64,
+    /// BDFs opted into real-hardware passthrough via [`PciHost::enable_passthrough`].
+    /// Empty (the default) keeps every unclaimed BDF reading back all-ones.
+    #[cfg(target_arch = "x86_64")]
+    passthrough: Arc<Mutex<Vec<PassthroughDevice>>>,
 }
 
 impl<B: BarAllocTrait> PciHost<B> {
-    /// Construct PCI/PCIe host.
-    pub fn new(msi_irq_manager: Option<Arc<dyn MsiIrqManager>>) -> Self {
+    /// Construct PCI/PCIe host. `ecam_base` is the guest-physical address
+    /// the PCIe extended (ECAM) configuration window starts at.
+    pub fn new(msi_irq_manager: Option<Arc<dyn MsiIrqManager>>, ecam_base: u64) -> Self {
         // #[cfg(target_arch = "x86_64")]
         // let io_region = sys_io.root().clone();
         // let mem_region = sys_mem.root().clone();
@@ -51,6 +98,9 @@ impl<B: BarAllocTrait> PciHost<B> {
             #[cfg(target_arch = "x86_64")]
             config_addr: 0,
             check_type1: 0,
+            ecam_base,
+            #[cfg(target_arch = "x86_64")]
+            passthrough: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -66,6 +116,30 @@ impl<B: BarAllocTrait> PciHost<B> {
         }
         None
     }
+
+    /// Opt a BDF with no emulated device into real-hardware passthrough:
+    /// config accesses that miss [`find_device`] are forwarded to the real
+    /// 0xcf8/0xcfc ports instead of reading back all-ones, letting a real
+    /// NIC/NVMe device be assigned straight through to the guest.
+    #[cfg(target_arch = "x86_64")]
+    pub fn enable_passthrough(&self, bus_num: u8, devfn: u8) {
+        let mut passthrough = self.passthrough.lock();
+        if !passthrough.iter().any(|d| d.bus_num == bus_num && d.devfn == devfn) {
+            passthrough.push(PassthroughDevice {
+                bus_num,
+                devfn,
+                bar_shadow: [0; BAR_SHADOW_LEN],
+            });
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn is_passthrough(&self, bus_num: u8, devfn: u8) -> bool {
+        self.passthrough
+            .lock()
+            .iter()
+            .any(|d| d.bus_num == bus_num && d.devfn == devfn)
+    }
 }
 
 impl<B: BarAllocTrait> PioOps for PciHost<B> {
@@ -108,15 +182,34 @@ impl<B: BarAllocTrait> PioOps for PciHost<B> {
                     offset &= PIO_OFFSET_MASK;
                     dev.lock().read_config(offset as usize, &mut data[..]);
                 }
+                None if cloned_hb.is_passthrough(bus_num, devfn) => {
+                    let bar_offset = offset & PIO_OFFSET_MASK;
+                    if BAR_RANGE.contains(&bar_offset) {
+                        // Serve the guest's BAR probing/relocation out of the
+                        // shadow copy instead of the real hardware BAR.
+                        let shadow_off = (bar_offset - BAR_0 as u32) as usize;
+                        let passthrough = cloned_hb.passthrough.lock();
+                        let dev = passthrough
+                            .iter()
+                            .find(|d| d.bus_num == bus_num && d.devfn == devfn)
+                            .unwrap();
+                        data[..access_size as usize]
+                            .copy_from_slice(&dev.bar_shadow[shadow_off..shadow_off + access_size as usize]);
+                    } else if bar_offset == CAP_LIST {
+                        // No capability chain exposed for a passthrough device.
+                    } else {
+                        unsafe {
+                            io::outl(0xcf8, cloned_hb.config_addr);
+                        }
+                        match access_size {
+                            1 => data[0] = unsafe { io::inb(port) },
+                            2 => data[..2].copy_from_slice(&unsafe { io::inw(port) }.to_le_bytes()),
+                            4 => data[..4].copy_from_slice(&unsafe { io::inl(port) }.to_le_bytes()),
+                            _ => return Err(HyperError::InValidPioRead),
+                        }
+                    }
+                }
                 None => {
-                    // debug!("cannot find device use passthrough to read data");
-                    // unsafe{io::outl(0xcf8, cloned_hb.config_addr);}
-                    // match access_size {
-                    //     1 => return Ok(unsafe { io::inb(port) } as u32),
-                    //     2 => return Ok(unsafe { io::inw(port) } as u32),
-                    //     4 => return Ok(unsafe { io::inl(port) }),
-                    //     _ => return Err(HyperError::InValidPioRead),
-                    // }
                     for d in data.iter_mut() {
                         *d = 0xff;
                     }
@@ -175,16 +268,81 @@ impl<B: BarAllocTrait> PioOps for PciHost<B> {
                     _ => return Err(HyperError::InValidPioWrite),
                 };
                 dev.lock().write_config(offset as usize, value_byte);
+            } else if self.is_passthrough(bus_num, devfn) {
+                let bar_offset = offset & PIO_OFFSET_MASK;
+                if BAR_RANGE.contains(&bar_offset) {
+                    // Accept the guest's BAR probe/relocation write into the
+                    // shadow copy; the physical BAR is never reprogrammed.
+                    let shadow_off = (bar_offset - BAR_0 as u32) as usize;
+                    let value_bytes = value.to_le_bytes();
+                    let mut passthrough = self.passthrough.lock();
+                    let dev = passthrough
+                        .iter_mut()
+                        .find(|d| d.bus_num == bus_num && d.devfn == devfn)
+                        .unwrap();
+                    dev.bar_shadow[shadow_off..shadow_off + access_size as usize]
+                        .copy_from_slice(&value_bytes[..access_size as usize]);
+                } else if bar_offset == CAP_LIST {
+                    // Drop: no capability chain to reprogram on a passthrough device.
+                } else {
+                    unsafe {
+                        io::outl(0xcf8, self.config_addr);
+                    }
+                    match access_size {
+                        1 => unsafe { io::outb(port, value as u8) },
+                        2 => unsafe { io::outw(port, value as u16) },
+                        4 => unsafe { io::outl(port, value) },
+                        _ => return Err(HyperError::InvalidParam),
+                    }
+                }
             }
-            // else {
-            //     debug!("cannot find device use passthrough to write data");
-            //     match access_size {
-            //         1 => unsafe { io::outb(port, value as u8) },
-            //         2 => unsafe { io::outw(port, value as u16) },
-            //         4 => unsafe { io::outl(port, value) },
-            //         _ => {return Err(HyperError::InvalidParam);},
-            //     }
-            // }
+        }
+        Ok(())
+    }
+}
+
+/// PCIe extended (ECAM) configuration space, memory-mapped starting at
+/// `ecam_base`. Unlike the legacy 0xcf8/0xcfc window, the full 12-bit
+/// per-device offset reaches the whole 4 KiB of extended config space
+/// (MSI-X, PCIe capabilities, etc. above 0x100) instead of being capped
+/// at 256 bytes.
+impl<B: BarAllocTrait> MmioOps for PciHost<B> {
+    fn mmio_range(&self) -> Range<u64> {
+        self.ecam_base..self.ecam_base + (CONFIG_BUS_MASK as u64 + 1) * ECAM_BUS_SIZE
+    }
+
+    fn read(&mut self, addr: u64, access_size: u8) -> HyperResult<u64> {
+        let mut data = [0xffu8; 8]; // max access size is 8
+        let off = addr - self.ecam_base;
+        let bus_num = ((off >> ECAM_BUS_SHIFT) & CONFIG_BUS_MASK as u64) as u8;
+        let devfn = ((off >> ECAM_DEVFN_SHIFT) & CONFIG_DEVFN_MASK as u64) as u8;
+        let reg = (off & ECAM_OFFSET_MASK) as usize;
+        if let Some(dev) = self.find_device(bus_num, devfn) {
+            dev.lock().read_config(reg, &mut data[..access_size as usize]);
+        }
+        match access_size {
+            1 => Ok(u64::from_le_bytes([data[0], 0, 0, 0, 0, 0, 0, 0])),
+            2 => Ok(u64::from_le_bytes([data[0], data[1], 0, 0, 0, 0, 0, 0])),
+            4 => Ok(u64::from_le_bytes([
+                data[0], data[1], data[2], data[3], 0, 0, 0, 0,
+            ])),
+            8 => Ok(u64::from_le_bytes(data)),
+            _ => Err(HyperError::InValidMmioRead),
+        }
+    }
+
+    fn write(&mut self, addr: u64, access_size: u8, value: u64) -> HyperResult {
+        if access_size > 8 {
+            return Err(HyperError::InValidMmioWrite);
+        }
+        let off = addr - self.ecam_base;
+        let bus_num = ((off >> ECAM_BUS_SHIFT) & CONFIG_BUS_MASK as u64) as u8;
+        let devfn = ((off >> ECAM_DEVFN_SHIFT) & CONFIG_DEVFN_MASK as u64) as u8;
+        let reg = (off & ECAM_OFFSET_MASK) as usize;
+        if let Some(dev) = self.find_device(bus_num, devfn) {
+            let value_bytes = value.to_le_bytes();
+            dev.lock()
+                .write_config(reg, &value_bytes[0..access_size as usize]);
         }
         Ok(())
     }
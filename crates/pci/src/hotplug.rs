@@ -0,0 +1,111 @@
+use crate::config::{
+    BarAllocTrait, PciConfig, PCI_EXP_HP_EV_ABP, PCI_EXP_HP_EV_CCI, PCI_EXP_HP_EV_PDC,
+    PCI_EXP_SLTCTL, PCI_EXP_SLTCTL_HPIE, PCI_EXP_SLTCTL_PCC, PCI_EXP_SLTSTA, PCI_EXP_SLTSTA_ABP,
+    PCI_EXP_SLTSTA_CC, PCI_EXP_SLTSTA_PDC, PCI_EXP_SLTSTA_PDS,
+};
+use crate::{le_read_u16, le_write_u16};
+
+/// Drives a PCIe downstream/root port's native hot-plug flow (Slot
+/// Capabilities/Control/Status, PCIe base spec 6.7) over a `PciConfig`
+/// whose standard PCI Express capability has already been set up for it
+/// via [`PciConfig::init_slot_cap`]. This only drives the guest-visible
+/// slot state machine and interrupt; realizing/unrealizing the plugged
+/// device on the bus is the caller's job.
+pub struct HotplugController {
+    pcie_cap_offset: u16,
+}
+
+impl HotplugController {
+    /// `pcie_cap_offset` must be the same offset already passed to
+    /// [`PciConfig::init_slot_cap`].
+    pub fn new(pcie_cap_offset: u16) -> Self {
+        Self { pcie_cap_offset }
+    }
+
+    fn sltctl_offset(&self) -> usize {
+        self.pcie_cap_offset as usize + PCI_EXP_SLTCTL as usize
+    }
+
+    fn sltsta_offset(&self) -> usize {
+        self.pcie_cap_offset as usize + PCI_EXP_SLTSTA as usize
+    }
+
+    fn read_sltctl<B: BarAllocTrait>(&self, config: &PciConfig<B>) -> u16 {
+        le_read_u16(&config.config, self.sltctl_offset()).unwrap()
+    }
+
+    fn set_sltsta_bits<B: BarAllocTrait>(&self, config: &mut PciConfig<B>, bits: u16) {
+        let offset = self.sltsta_offset();
+        let cur = le_read_u16(&config.config, offset).unwrap();
+        le_write_u16(&mut config.config, offset, cur | bits).unwrap();
+    }
+
+    /// Raise the slot interrupt for `event` if it's enabled: Hot-Plug
+    /// Interrupt Enable gates every slot event per spec, on top of the
+    /// event's own enable bit (the `PCI_EXP_HP_EV_*` constants alias the
+    /// matching `PCI_EXP_SLTCTL_*E` bit). Delivered through the port's
+    /// MSI-X table if it has one; ports using plain MSI or INTx aren't
+    /// wired up here.
+    fn notify_if_enabled<B: BarAllocTrait>(&self, config: &PciConfig<B>, dev_id: u16, event: u16) {
+        let sltctl = self.read_sltctl(config);
+        if sltctl & PCI_EXP_SLTCTL_HPIE == 0 || sltctl & event == 0 {
+            return;
+        }
+        if let Some(msix) = &config.msix {
+            msix.lock().notify(0, dev_id);
+        }
+    }
+
+    /// Plug a device into the slot: set Presence Detect State and raise
+    /// Presence Detect Changed, plus Attention Button Pressed if
+    /// `attention_button` models a physical button press accompanying
+    /// this hot-add. Notifies the guest for each event that's enabled.
+    pub fn plug<B: BarAllocTrait>(
+        &self,
+        config: &mut PciConfig<B>,
+        dev_id: u16,
+        attention_button: bool,
+    ) {
+        self.set_sltsta_bits(config, PCI_EXP_SLTSTA_PDS | PCI_EXP_SLTSTA_PDC);
+        self.notify_if_enabled(config, dev_id, PCI_EXP_HP_EV_PDC);
+
+        if attention_button {
+            self.set_sltsta_bits(config, PCI_EXP_SLTSTA_ABP);
+            self.notify_if_enabled(config, dev_id, PCI_EXP_HP_EV_ABP);
+        }
+    }
+
+    /// Remove whatever's in the slot: clear Presence Detect State and
+    /// raise Presence Detect Changed. Used both for a managed remove
+    /// (after the guest writes the Power Controller Control bit off, see
+    /// [`Self::write_sltctl`]) and a surprise removal, which is simply a
+    /// call to this without a preceding `write_sltctl`.
+    pub fn unplug<B: BarAllocTrait>(&self, config: &mut PciConfig<B>, dev_id: u16) {
+        let offset = self.sltsta_offset();
+        let cur = le_read_u16(&config.config, offset).unwrap();
+        let next = (cur & !PCI_EXP_SLTSTA_PDS) | PCI_EXP_SLTSTA_PDC;
+        le_write_u16(&mut config.config, offset, next).unwrap();
+        self.notify_if_enabled(config, dev_id, PCI_EXP_HP_EV_PDC);
+    }
+
+    /// Handle a guest config-space write that touched `PCI_EXP_SLTCTL`.
+    /// `old_sltctl` is the register's value just before the write (the
+    /// caller reads it before calling [`PciConfig::write`]/`write_bars`,
+    /// the same before/after pattern `write_bars` uses for BARs). Flipping
+    /// the Power Controller Control bit either way takes effect
+    /// immediately in this emulation - no real power sequencing to wait
+    /// on - so Command Completed is posted right away.
+    pub fn write_sltctl<B: BarAllocTrait>(
+        &self,
+        config: &mut PciConfig<B>,
+        dev_id: u16,
+        old_sltctl: u16,
+    ) {
+        let new_sltctl = self.read_sltctl(config);
+        if (old_sltctl ^ new_sltctl) & PCI_EXP_SLTCTL_PCC == 0 {
+            return;
+        }
+        self.set_sltsta_bits(config, PCI_EXP_SLTSTA_CC);
+        self.notify_if_enabled(config, dev_id, PCI_EXP_HP_EV_CCI);
+    }
+}
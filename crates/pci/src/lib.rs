@@ -8,6 +8,8 @@ extern crate hashbrown;
 
 pub mod config;
 pub mod host;
+pub mod hotplug;
+pub mod msi;
 pub mod msix;
 pub mod util;
 // mod dummy_host;
@@ -18,6 +20,8 @@ mod bus;
 pub use bus::PciBus;
 pub use config::{PciConfig, INTERRUPT_PIN};
 pub use host::PciHost;
+pub use hotplug::HotplugController;
+pub use msi::*;
 pub use msix::*;
 
 // pub use dummy_host::DummyPciHost;
@@ -25,6 +29,7 @@ pub use msix::*;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use core::mem::size_of;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use spin::Mutex;
 
 use byteorder::{ByteOrder, LittleEndian};
@@ -283,7 +288,16 @@ pub trait PciDevOps: Send + AsAny {
         None
     }
 
-    fn change_irq_level(&self, _irq_pin: u32, _level: i8) -> Result<()> {
+    fn change_irq_level(&self, irq_pin: u32, level: i8) -> Result<()> {
+        if let Some(manager) = self.get_msi_irq_manager() {
+            if let Ok(line) = manager.register_level_irq(irq_pin) {
+                if level > 0 {
+                    line.assert()?;
+                } else {
+                    line.deassert();
+                }
+            }
+        }
         Ok(())
     }
 
@@ -367,4 +381,114 @@ pub trait MsiIrqManager: Send + Sync {
     fn trigger(&self, _vector: MsiVector, _dev_id: u32) -> Result<()> {
         Ok(())
     }
+
+    /// Install (or refresh) `vector`'s delivery route without firing it,
+    /// e.g. when a guest unmasks an MSI-X vector or a snapshot restore puts
+    /// one back into the unmasked-and-enabled state. Unlike `trigger`, this
+    /// must not itself cause an interrupt to be delivered. Managers that
+    /// don't track per-vector routes separately from delivery can leave
+    /// this as a no-op.
+    fn route(&self, _vector: MsiVector, _dev_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    /// Install routes for several vectors at once, e.g. when a guest
+    /// unmasks the whole function or a snapshot restores every vector --
+    /// committing a batch of interrupt-source-group updates together is
+    /// dramatically cheaper for managers backed by a shared routing table
+    /// than one `route` call per vector. The default just loops `route`,
+    /// so implementing this is opt-in.
+    fn update_routes(&self, routes: &[(u16, MsiVector)], dev_id: u32) -> Result<()> {
+        for (_vector, msi_vector) in routes {
+            self.route(*msi_vector, dev_id)?;
+        }
+        Ok(())
+    }
+
+    /// Program (or clear, if `entry` is `None`) the interrupt-remapping
+    /// table entry at `index`, for a guest that's enabled IOMMU interrupt
+    /// remapping. Managers that don't implement a virtual IOMMU leave this
+    /// unsupported.
+    fn set_irte(&self, _index: u16, _entry: Option<Irte>) -> Result<()> {
+        Err(HyperError::NotSupported)
+    }
+
+    /// Look up the IRTE at `index` (already adjusted for `shv`'s
+    /// sub-handle) when resolving a remappable-format MSI-X message.
+    fn get_irte(&self, _index: u16) -> Option<Irte> {
+        None
+    }
+
+    /// Register (or fetch the already-registered) level-triggered line for
+    /// `gsi`, so [`PciDevOps::change_irq_level`] can drive a shared legacy
+    /// INTx line with de-assert-on-EOI semantics instead of a one-shot
+    /// MSI-style trigger. Managers that only ever deliver MSI/MSI-X leave
+    /// this unsupported.
+    fn register_level_irq(&self, _gsi: u32) -> Result<Arc<dyn IrqLevelLine>> {
+        Err(HyperError::NotSupported)
+    }
+
+    /// Resample every level line registered under `gsi`: called once the
+    /// guest EOIs that GSI at the IOAPIC/PIC, so a device still holding its
+    /// internal level high gets a chance to re-assert it.
+    fn resample_level_irq(&self, _gsi: u32) {}
+}
+
+/// A level-triggered interrupt line with de-assert-on-EOI semantics (ref:
+/// crosvm/pH's `IrqLevelEvent`): `assert`/`deassert` drive the line itself,
+/// while `wait_resample` blocks until the owning [`MsiIrqManager`] resamples
+/// the GSI this line is registered under, letting a device whose internal
+/// level is still high re-assert it.
+pub trait IrqLevelLine: Send + Sync {
+    /// Raise the line, triggering delivery if it wasn't already asserted.
+    fn assert(&self) -> Result<()>;
+    /// Lower the line. No further triggers fire until the next `assert`.
+    fn deassert(&self);
+    /// Block until the next resample of this line's GSI.
+    fn wait_resample(&self);
+}
+
+/// Generic [`IrqLevelLine`] built on plain atomics, for any [`MsiIrqManager`]
+/// that wants level-triggered support without its own resample bookkeeping.
+pub struct IrqLevelEvent {
+    asserted: AtomicBool,
+    resample_generation: AtomicUsize,
+}
+
+impl IrqLevelEvent {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            asserted: AtomicBool::new(false),
+            resample_generation: AtomicUsize::new(0),
+        })
+    }
+
+    /// Whether the line is currently held high.
+    pub fn is_asserted(&self) -> bool {
+        self.asserted.load(Ordering::SeqCst)
+    }
+
+    /// Wake any `wait_resample` caller; called by the owning manager once
+    /// the guest EOIs this line's GSI.
+    pub fn resample(&self) {
+        self.resample_generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl IrqLevelLine for IrqLevelEvent {
+    fn assert(&self) -> Result<()> {
+        self.asserted.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn deassert(&self) {
+        self.asserted.store(false, Ordering::SeqCst);
+    }
+
+    fn wait_resample(&self) {
+        let seen = self.resample_generation.load(Ordering::SeqCst);
+        while self.resample_generation.load(Ordering::SeqCst) == seen {
+            core::hint::spin_loop();
+        }
+    }
 }
@@ -0,0 +1,300 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU16, Ordering};
+use spin::Mutex;
+
+use crate::config::CapId;
+use crate::msix::MsiVector;
+use crate::{le_read_u16, le_read_u32, le_write_u16, le_write_u32, PciDevBase};
+use crate::{BarAllocTrait, MsiIrqManager};
+use hypercraft::HyperResult;
+
+/// Offset of the Message Control register, relative to the capability's
+/// own offset - same position whether the capability ends up 32-bit or
+/// 64-bit, masked or not.
+const MSI_CAP_CONTROL: u8 = 0x02;
+const MSI_CAP_ENABLE: u16 = 0x0001;
+/// Multiple Message Capable: bits 1:3, log2(vectors requested at realize()).
+const MSI_CAP_MULTI_MSG_CAPABLE_SHIFT: u16 = 1;
+const MSI_CAP_MULTI_MSG_CAPABLE_MASK: u16 = 0x000e;
+/// Multiple Message Enable: bits 4:6, guest-writable, log2(vectors actually enabled).
+const MSI_CAP_MULTI_MSG_ENABLE_SHIFT: u16 = 4;
+const MSI_CAP_MULTI_MSG_ENABLE_MASK: u16 = 0x0070;
+const MSI_CAP_64BIT_ADDR_CAPABLE: u16 = 0x0080;
+const MSI_CAP_PER_VECTOR_MASK_CAPABLE: u16 = 0x0100;
+
+const MSI_CAP_ADDRESS_LO: u8 = 0x04;
+
+/// Capability layout/size (PCI Local Bus spec 6.8.1): the low 32 bits of
+/// Message Address always sit at [`MSI_CAP_ADDRESS_LO`]; everything after
+/// that shifts depending on whether the capability is 64-bit-address
+/// capable and/or per-vector-mask capable.
+struct MsiLayout {
+    address_hi: Option<u8>,
+    data: u8,
+    mask: Option<u8>,
+    pending: Option<u8>,
+    size: usize,
+}
+
+fn msi_layout(is_64bit: bool, mask_per_vector: bool) -> MsiLayout {
+    if is_64bit {
+        let data = 0x0c;
+        if mask_per_vector {
+            MsiLayout {
+                address_hi: Some(0x08),
+                data,
+                mask: Some(0x10),
+                pending: Some(0x14),
+                size: 0x18,
+            }
+        } else {
+            MsiLayout {
+                address_hi: Some(0x08),
+                data,
+                mask: None,
+                pending: None,
+                size: 0x0e,
+            }
+        }
+    } else {
+        let data = 0x08;
+        if mask_per_vector {
+            MsiLayout {
+                address_hi: None,
+                data,
+                mask: Some(0x0c),
+                pending: Some(0x10),
+                size: 0x14,
+            }
+        } else {
+            MsiLayout {
+                address_hi: None,
+                data,
+                mask: None,
+                pending: None,
+                size: 0x0a,
+            }
+        }
+    }
+}
+
+/// MSI (as opposed to MSI-X) structure: unlike `Msix`, there's no
+/// BAR-mapped shadow table - every MSI register (address/data/mask/
+/// pending) is small enough to live directly in capability-space bytes,
+/// so `PciConfig::config`/`write_mask` already carry it and this struct
+/// only needs the layout/IRQ-delivery bits MSI-X keeps in `Msix`.
+pub struct MsiConfig {
+    cap_offset: u16,
+    layout: MsiLayout,
+    dev_id: Arc<AtomicU16>,
+    msi_irq_manager: Option<Arc<dyn MsiIrqManager>>,
+}
+
+impl MsiConfig {
+    pub fn is_enabled(&self, config: &[u8]) -> bool {
+        let offset = self.cap_offset as usize + MSI_CAP_CONTROL as usize;
+        le_read_u16(config, offset).unwrap() & MSI_CAP_ENABLE != 0
+    }
+
+    fn is_vector_masked(&self, config: &[u8], vector: u16) -> bool {
+        let Some(mask_offset) = self.layout.mask else {
+            return false;
+        };
+        let mask = le_read_u32(config, self.cap_offset as usize + mask_offset as usize).unwrap();
+        mask & (1 << vector) != 0
+    }
+
+    fn is_vector_pending(&self, config: &[u8], vector: u16) -> bool {
+        let Some(pending_offset) = self.layout.pending else {
+            return false;
+        };
+        let pending =
+            le_read_u32(config, self.cap_offset as usize + pending_offset as usize).unwrap();
+        pending & (1 << vector) != 0
+    }
+
+    fn set_pending_vector(&self, config: &mut [u8], vector: u16) {
+        let Some(pending_offset) = self.layout.pending else {
+            return;
+        };
+        let offset = self.cap_offset as usize + pending_offset as usize;
+        let pending = le_read_u32(config, offset).unwrap();
+        le_write_u32(config, offset, pending | (1 << vector)).unwrap();
+    }
+
+    fn clear_pending_vector(&self, config: &mut [u8], vector: u16) {
+        let Some(pending_offset) = self.layout.pending else {
+            return;
+        };
+        let offset = self.cap_offset as usize + pending_offset as usize;
+        let pending = le_read_u32(config, offset).unwrap();
+        le_write_u32(config, offset, pending & !(1 << vector)).unwrap();
+    }
+
+    fn message(&self, config: &[u8], vector: u16) -> MsiVector {
+        let addr_lo =
+            le_read_u32(config, self.cap_offset as usize + MSI_CAP_ADDRESS_LO as usize).unwrap();
+        let addr_hi = match self.layout.address_hi {
+            Some(hi) => le_read_u32(config, self.cap_offset as usize + hi as usize).unwrap(),
+            None => 0,
+        };
+        let data =
+            le_read_u16(config, self.cap_offset as usize + self.layout.data as usize).unwrap();
+        // Per-vector messages share one base address/data pair, offset by
+        // vector number in the data's low bits, same as the MSI spec's
+        // "multiple message" addressing scheme.
+        MsiVector {
+            msi_addr: ((addr_hi as u64) << 32) | addr_lo as u64,
+            msi_data: (data as u64) + vector as u64,
+        }
+    }
+
+    fn send(&self, config: &[u8], vector: u16) {
+        let message = self.message(config, vector);
+        let irq_manager = self.msi_irq_manager.as_ref().unwrap();
+        if let Err(e) = irq_manager.trigger(message, self.dev_id.load(Ordering::Acquire) as u32) {
+            error!("Send msi error: {:?}", e);
+        }
+    }
+
+    /// Deliver `vector`, deferring to the Pending Bits register if the
+    /// guest currently has it masked - mirrors [`crate::msix::Msix::notify`].
+    pub fn notify(&self, config: &mut [u8], vector: u16) {
+        if !self.is_enabled(config) {
+            return;
+        }
+        if self.is_vector_masked(config, vector) {
+            self.set_pending_vector(config, vector);
+            return;
+        }
+        self.send(config, vector);
+    }
+
+    /// Disable MSI and clear per-vector mask/pending state, mirroring
+    /// `Msix::reset`. Called from [`crate::PciConfig::reset`].
+    pub fn reset(&self, config: &mut [u8]) {
+        let control_offset = self.cap_offset as usize + MSI_CAP_CONTROL as usize;
+        let control = le_read_u16(config, control_offset).unwrap();
+        le_write_u16(config, control_offset, control & !MSI_CAP_ENABLE).unwrap();
+        if let Some(mask_offset) = self.layout.mask {
+            le_write_u32(config, self.cap_offset as usize + mask_offset as usize, 0).unwrap();
+        }
+        if let Some(pending_offset) = self.layout.pending {
+            le_write_u32(config, self.cap_offset as usize + pending_offset as usize, 0).unwrap();
+        }
+    }
+
+    /// Called from [`crate::PciConfig::write`] for any write overlapping
+    /// this capability: when a previously-masked, now-pending vector gets
+    /// unmasked, clear its pending bit and deliver it, same as `Msix`
+    /// does for its per-vector mask bit.
+    pub fn write_config(&self, config: &mut [u8], offset: usize, len: usize) {
+        let Some(mask_offset) = self.layout.mask else {
+            return;
+        };
+        let mask_reg_offset = self.cap_offset as usize + mask_offset as usize;
+        if offset + len <= mask_reg_offset || offset >= mask_reg_offset + 4 {
+            return;
+        }
+        let max_vector_nr = 1u16
+            << ((le_read_u16(config, self.cap_offset as usize + MSI_CAP_CONTROL as usize).unwrap()
+                & MSI_CAP_MULTI_MSG_ENABLE_MASK)
+                >> MSI_CAP_MULTI_MSG_ENABLE_SHIFT);
+        for v in 0..max_vector_nr {
+            if !self.is_vector_masked(config, v) && self.is_vector_pending(config, v) {
+                self.clear_pending_vector(config, v);
+                self.send(config, v);
+            }
+        }
+    }
+}
+
+/// Decode a raw Message Control register value into the feature bits
+/// [`add_msi_capability`] expects. Exists for callers like
+/// `VfioPciDevice::realize` that mirror a *host* device's own, already
+/// negotiated MSI capability instead of advertising a synthetic one, so
+/// they need the same layout knowledge `msi_layout`/`add_msi_capability`
+/// encode without duplicating their bit positions.
+pub fn parse_msi_control(control: u16) -> (u32, bool, bool) {
+    let multi_msg_capable =
+        (control & MSI_CAP_MULTI_MSG_CAPABLE_MASK) >> MSI_CAP_MULTI_MSG_CAPABLE_SHIFT;
+    let vectors = 1u32 << multi_msg_capable;
+    let is_64bit = control & MSI_CAP_64BIT_ADDR_CAPABLE != 0;
+    let mask_per_vector = control & MSI_CAP_PER_VECTOR_MASK_CAPABLE != 0;
+    (vectors, is_64bit, mask_per_vector)
+}
+
+/// MSI capability initialization, paralleling [`crate::msix::init_msix`]:
+/// installs the capability in configuration space and registers the
+/// `MsiConfig` that [`crate::PciConfig::write`] dispatches MSI-relevant
+/// writes to.
+///
+/// # Arguments
+///
+/// * `pcidev_base` - The base of the PCI device.
+/// * `vectors` - Number of vectors the device supports; rounded up to the
+///   next power of two to fill Multiple Message Capable.
+/// * `is_64bit` - Whether the capability supports a 64-bit message address.
+/// * `mask_per_vector` - Whether the capability exposes per-vector mask/
+///   pending dwords.
+/// * `dev_id` - Dev id used to route delivered messages, same as `init_msix`.
+pub fn add_msi_capability<B: BarAllocTrait>(
+    pcidev_base: &mut PciDevBase<B>,
+    vectors: u32,
+    is_64bit: bool,
+    mask_per_vector: bool,
+    dev_id: Arc<AtomicU16>,
+) -> HyperResult<usize> {
+    let config = &mut pcidev_base.config;
+    let layout = msi_layout(is_64bit, mask_per_vector);
+    let cap_offset = config.add_pci_cap(CapId::Msi as u8, layout.size)?;
+
+    let multi_msg_capable = vectors.next_power_of_two().trailing_zeros() as u16;
+    let control_offset = cap_offset + MSI_CAP_CONTROL as usize;
+    le_write_u16(
+        &mut config.config,
+        control_offset,
+        (multi_msg_capable << MSI_CAP_MULTI_MSG_CAPABLE_SHIFT) & MSI_CAP_MULTI_MSG_CAPABLE_MASK
+            | if is_64bit { MSI_CAP_64BIT_ADDR_CAPABLE } else { 0 }
+            | if mask_per_vector {
+                MSI_CAP_PER_VECTOR_MASK_CAPABLE
+            } else {
+                0
+            },
+    )?;
+    le_write_u16(
+        &mut config.write_mask,
+        control_offset,
+        MSI_CAP_ENABLE | MSI_CAP_MULTI_MSG_ENABLE_MASK,
+    )?;
+
+    le_write_u32(
+        &mut config.write_mask,
+        cap_offset + MSI_CAP_ADDRESS_LO as usize,
+        0xffff_fffc,
+    )?;
+    if let Some(hi) = layout.address_hi {
+        le_write_u32(&mut config.write_mask, cap_offset + hi as usize, 0xffff_ffff)?;
+    }
+    le_write_u16(&mut config.write_mask, cap_offset + layout.data as usize, 0xffff)?;
+    if let Some(mask) = layout.mask {
+        le_write_u32(&mut config.write_mask, cap_offset + mask as usize, 0xffff_ffff)?;
+    }
+    // Pending Bits are hardware-owned status, not guest-writable.
+
+    let msi_irq_manager = if let Some(pci_bus) = pcidev_base.parent_bus.upgrade() {
+        pci_bus.lock().get_msi_irq_manager()
+    } else {
+        error!("Msi irq controller is none");
+        None
+    };
+
+    config.msi = Some(Arc::new(Mutex::new(MsiConfig {
+        cap_offset: cap_offset as u16,
+        layout,
+        dev_id,
+        msi_irq_manager,
+    })));
+
+    Ok(cap_offset)
+}
@@ -151,7 +151,7 @@ impl MsiDataReg {
     }
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
 pub struct MsiVector {
     pub msi_addr: u64,
     // [0:31]: data, [32:63]: vector control
@@ -166,6 +166,32 @@ pub struct Message {
     pub data: u32,
 }
 
+/// One interrupt-remapping table entry (IRTE): the subset of the Intel
+/// VT-d fixed-format IRTE a virtual IOMMU needs to redirect a remappable-
+/// format MSI-X message (`MsiAddrReg::intr_format() == 1`) to its real
+/// destination/vector/delivery-mode, since the message itself no longer
+/// carries them -- it carries an index into this table instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Irte {
+    pub present: bool,
+    pub destination: u32,
+    pub vector: u8,
+    pub delivery_mode: u8,
+    pub trigger_mode: bool,
+}
+
+/// Captured [`Msix`] state for live migration / suspend-resume: enough to
+/// fully reconstruct `table`/`pba` plus the mask/enable flags and capability
+/// offset, mirroring cloud-hypervisor's `MsixConfig` snapshot.
+#[derive(Clone)]
+pub struct MsixState {
+    pub table: Vec<u8>,
+    pub pba: Vec<u8>,
+    pub func_masked: bool,
+    pub enabled: bool,
+    pub msix_cap_offset: u16,
+}
+
 /// MSI-X structure.
 pub struct Msix {
     /// MSI-X table.
@@ -176,6 +202,11 @@ pub struct Msix {
     pub msix_cap_offset: u16,
     pub dev_id: Arc<AtomicU16>,
     pub msi_irq_manager: Option<Arc<dyn MsiIrqManager>>,
+    /// Last (address/data, masked) committed to `msi_irq_manager` for each
+    /// vector, so a guest reprogramming the same table entry repeatedly
+    /// (Linux does this, usually while masked) doesn't reach the IRQ
+    /// manager every time -- only an actual change does.
+    cached_vectors: Vec<(MsiVector, bool)>,
 }
 impl Msix {
     /// Construct a new MSI-X structure.
@@ -193,6 +224,7 @@ impl Msix {
         dev_id: Arc<AtomicU16>,
         msi_irq_manager: Option<Arc<dyn MsiIrqManager>>,
     ) -> Self {
+        let nr_vectors = table_size as usize / MSIX_TABLE_ENTRY_SIZE as usize;
         let mut msix = Msix {
             table: vec![0; table_size as usize],
             pba: vec![0; pba_size as usize],
@@ -201,6 +233,7 @@ impl Msix {
             msix_cap_offset,
             dev_id,
             msi_irq_manager,
+            cached_vectors: vec![(MsiVector::default(), true); nr_vectors],
         };
         msix.mask_all_vectors();
         msix
@@ -212,6 +245,50 @@ impl Msix {
         self.func_masked = true;
         self.enabled = true;
         self.mask_all_vectors();
+        self.cached_vectors.fill((MsiVector::default(), true));
+    }
+
+    /// Raw PBA bytes, e.g. for capturing migration state alongside `table`.
+    pub fn pba(&self) -> &[u8] {
+        &self.pba
+    }
+
+    /// Restore table/PBA contents and the mask/enable flags captured by a
+    /// prior save. Pending-vector bookkeeping lives inside `pba` itself, so
+    /// nothing else needs re-deriving here.
+    pub fn restore(&mut self, table: Vec<u8>, pba: Vec<u8>, func_masked: bool, enabled: bool) {
+        self.table = table;
+        self.pba = pba;
+        self.func_masked = func_masked;
+        self.enabled = enabled;
+    }
+
+    /// Capture everything needed to reconstruct this `Msix` elsewhere, e.g.
+    /// for live migration or suspend/resume.
+    pub fn save_state(&self) -> MsixState {
+        MsixState {
+            table: self.table.clone(),
+            pba: self.pba.clone(),
+            func_masked: self.func_masked,
+            enabled: self.enabled,
+            msix_cap_offset: self.msix_cap_offset,
+        }
+    }
+
+    /// Load a state captured by [`Msix::save_state`]. This isn't just a
+    /// memcpy of the bytes back in: `msi_irq_manager`'s routing table has
+    /// no memory of its own, so every vector that comes back unmasked and
+    /// enabled has its delivery route re-established here -- otherwise the
+    /// guest would believe its MSI-X vectors are live while nothing
+    /// actually routes interrupts to them.
+    pub fn restore_state(&mut self, state: MsixState, dev_id: u16) {
+        self.restore(state.table, state.pba, state.func_masked, state.enabled);
+        self.msix_cap_offset = state.msix_cap_offset;
+
+        let max_vectors_nr = self.table.len() as u16 / MSIX_TABLE_ENTRY_SIZE;
+        self.cached_vectors
+            .resize(max_vectors_nr as usize, (MsiVector::default(), true));
+        self.batch_update_routes(dev_id);
     }
 
     pub fn is_enabled(&self, config: &[u8]) -> bool {
@@ -290,12 +367,100 @@ impl Msix {
         offset = (entry_offset + MSIX_MSG_DATA) as usize;
         let data = le_read_u64(&self.table, offset).unwrap();
 
+        let addr_reg = MsiAddrReg::from(address);
+        if addr_reg.intr_format() == 1 {
+            if let Some(remapped) = self.remap_msix_vector(&addr_reg, data) {
+                return remapped;
+            }
+        }
+
         MsiVector {
             msi_addr: address,
             msi_data: data,
         }
     }
 
+    /// Resolve a remappable-format message (`intr_format == 1`) through
+    /// the per-VM interrupt-remapping table `msi_irq_manager` owns,
+    /// re-encoding the result as a compatibility-format [`MsiVector`] so
+    /// every existing consumer (`route`/`trigger`) keeps working
+    /// unchanged. Returns `None` (raw bits used as-is) if there's no
+    /// manager, no table entry at the resolved index, or the entry isn't
+    /// present -- e.g. the guest hasn't programmed its IRTE yet.
+    fn remap_msix_vector(&self, addr_reg: &MsiAddrReg, data: u64) -> Option<MsiVector> {
+        let irq_manager = self.msi_irq_manager.as_ref()?;
+        let base_index =
+            ((addr_reg.intr_index_high() as u16) << 15) | (addr_reg.intr_index_low() as u16);
+        // SHV (subhandle valid): the message data register carries a
+        // subhandle that selects one of several consecutive IRTEs bound to
+        // the same interrupt-remapping block, rather than the index
+        // addressing the entry directly.
+        let index = if addr_reg.shv() != 0 {
+            base_index.wrapping_add(data as u16)
+        } else {
+            base_index
+        };
+        let irte = irq_manager.get_irte(index)?;
+        if !irte.present {
+            return None;
+        }
+
+        let msi_addr = (MSI_ADDR_BASE as u64) << 20 | ((irte.destination as u64 & 0xff) << 12);
+        let msi_data =
+            irte.vector as u64 | ((irte.delivery_mode as u64) << 8) | ((irte.trigger_mode as u64) << 15);
+        Some(MsiVector { msi_addr, msi_data })
+    }
+
+    /// Re-install `vector`'s delivery route if it's unmasked, without
+    /// sending an interrupt. Called on every mask-bit transition (function
+    /// level or per-vector), not just when [`Msix::notify`] actually fires,
+    /// so the underlying `msi_irq_manager` routing table tracks the
+    /// guest's mask state instead of only ever learning about a vector the
+    /// first time it's triggered.
+    fn update_vector_route(&self, vector: u16, dev_id: u16) {
+        if self.is_vector_masked(vector) {
+            return;
+        }
+        let Some(irq_manager) = self.msi_irq_manager.as_ref() else {
+            return;
+        };
+        let msix_vector = self.get_msix_vector(vector);
+        if let Err(e) = irq_manager.route(msix_vector, dev_id as u32) {
+            error!(
+                "Failed to route msix vector {} for dev {}: {:?}",
+                vector, dev_id, e
+            );
+        }
+    }
+
+    /// Install routes for every currently-unmasked vector in one batched
+    /// [`MsiIrqManager::update_routes`] call instead of one `route` call
+    /// per vector, and update `cached_vectors` to match. Used where all
+    /// vectors potentially need a new route at once -- enabling the
+    /// function and restoring a snapshot -- rather than the one-vector-at-
+    /// a-time path a single table write goes through.
+    fn batch_update_routes(&mut self, dev_id: u16) {
+        let max_vectors_nr = self.table.len() as u16 / MSIX_TABLE_ENTRY_SIZE;
+        let mut routes = Vec::new();
+        for v in 0..max_vectors_nr {
+            let msix_vector = self.get_msix_vector(v);
+            let is_masked = self.is_vector_masked(v);
+            self.cached_vectors[v as usize] = (msix_vector, is_masked);
+            if !is_masked {
+                routes.push((v, msix_vector));
+            }
+        }
+        if routes.is_empty() {
+            return;
+        }
+        let Some(irq_manager) = self.msi_irq_manager.as_ref() else {
+            return;
+        };
+        if let Err(e) = irq_manager.update_routes(&routes, dev_id as u32) {
+            error!("Failed to batch-update msix routes for dev {}: {:?}", dev_id, e);
+        }
+    }
+
     pub fn send_msix(&self, vector: u16, dev_id: u16) {
         let msix_vector = self.get_msix_vector(vector);
         // debug!("Send msix vector: {:#?}.", msix_vector);
@@ -341,6 +506,10 @@ impl Msix {
 
         if mask_state_changed && (self.enabled && !self.func_masked) {
             // debug!("msix state changed because of message control");
+            self.batch_update_routes(dev_id);
+
+            // Second pass: now that every unmasked vector's route is
+            // installed, replay whichever ones have a queued interrupt.
             let max_vectors_nr: u16 = self.table.len() as u16 / MSIX_TABLE_ENTRY_SIZE;
             for v in 0..max_vectors_nr {
                 if !self.is_vector_masked(v) && self.is_vector_pending(v) {
@@ -404,11 +573,25 @@ impl Msix {
             }
             let mut locked_msix = cloned_msix.lock();
             let vector: u16 = offset as u16 / MSIX_TABLE_ENTRY_SIZE;
-            let was_masked: bool = locked_msix.is_vector_masked(vector);
             let offset = offset as usize;
             locked_msix.table[offset..(offset + data.len())].copy_from_slice(data);
 
+            let new_vector = locked_msix.get_msix_vector(vector);
             let is_masked: bool = locked_msix.is_vector_masked(vector);
+            let (cached_vector, was_masked) = locked_msix.cached_vectors[vector as usize];
+
+            // Linux reprograms the same table entry repeatedly, usually
+            // while masked; if address, data and mask state all match what
+            // was last committed, there's nothing new for the IRQ manager
+            // to learn.
+            if cached_vector == new_vector && was_masked == is_masked {
+                return Ok(());
+            }
+            locked_msix.cached_vectors[vector as usize] = (new_vector, is_masked);
+
+            if was_masked != is_masked {
+                locked_msix.update_vector_route(vector, dev_id.load(Ordering::Acquire));
+            }
 
             // Clear the pending vector just when it is pending. Otherwise, it
             // will cause unknown error.
@@ -269,6 +269,112 @@ pub fn deposit_u32(value: u32, start: u32, length: u32, fieldval: u32) -> Option
     Some((value & !mask) | ((fieldval << start) & mask))
 }
 
+///  Deposit @fieldval into the 64 bit @value at the bit field specified
+///  by the @start and @length parameters, and return the modified
+///  @value. Bits of @value outside the bit field are not modified.
+///  Bits of @fieldval above the least significant @length bits are
+///  ignored. The bit field must lie entirely within the 64 bit word.
+///  It is valid to request that all 64 bits are modified (ie @length
+///  64 and @start 0).
+///
+/// # Arguments
+///
+/// * `value` - The value to extract the bit field from
+/// * `start` - The lowest bit in the bit field (numbered from 0)
+/// * `length` - The length of the bit field
+/// * `fieldval` - The value to insert into the bit field
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate util;
+/// use util::num_ops::deposit_u64;
+///
+/// let value = deposit_u64(0xffff, 0, 8, 0xbaba).unwrap();
+/// assert!(value == 0xffba);
+/// ```
+pub fn deposit_u64(value: u64, start: u32, length: u32, fieldval: u64) -> Option<u64> {
+    if length > 64 - start {
+        error!(
+            "deposit_u64: ( start {} length {} ) is out of range",
+            start, length
+        );
+        return None;
+    }
+
+    let mask: u64 = (!0_u64 >> (64 - length)) << start;
+    Some((value & !mask) | ((fieldval << start) & mask))
+}
+
+///  Extract from the 128 bit input @value the bit field specified by the
+///  @start and @length parameters, and return it. The bit field must
+///  lie entirely within the 128 bit word. It is valid to request that
+///  all 128 bits are returned (ie @length 128 and @start 0).
+///
+/// # Arguments
+///
+/// * `value` - The value to extract the bit field from
+/// * `start` - The lowest bit in the bit field (numbered from 0)
+/// * `length` - The length of the bit field
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate util;
+/// use util::num_ops::extract_u128;
+///
+/// let value = extract_u128(0xfffa, 0, 8).unwrap();
+/// assert!(value == 0xfa);
+/// ```
+pub fn extract_u128(value: u128, start: u32, length: u32) -> Option<u128> {
+    if length > 128 - start {
+        error!(
+            "extract_u128: ( start {} length {} ) is out of range",
+            start, length
+        );
+        return None;
+    }
+
+    Some((value >> start as u128) & (!(0_u128) >> (128 - length) as u128))
+}
+
+///  Deposit @fieldval into the 128 bit @value at the bit field specified
+///  by the @start and @length parameters, and return the modified
+///  @value. Bits of @value outside the bit field are not modified.
+///  Bits of @fieldval above the least significant @length bits are
+///  ignored. The bit field must lie entirely within the 128 bit word.
+///  It is valid to request that all 128 bits are modified (ie @length
+///  128 and @start 0).
+///
+/// # Arguments
+///
+/// * `value` - The value to extract the bit field from
+/// * `start` - The lowest bit in the bit field (numbered from 0)
+/// * `length` - The length of the bit field
+/// * `fieldval` - The value to insert into the bit field
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate util;
+/// use util::num_ops::deposit_u128;
+///
+/// let value = deposit_u128(0xffff, 0, 8, 0xbaba).unwrap();
+/// assert!(value == 0xffba);
+/// ```
+pub fn deposit_u128(value: u128, start: u32, length: u32, fieldval: u128) -> Option<u128> {
+    if length > 128 - start {
+        error!(
+            "deposit_u128: ( start {} length {} ) is out of range",
+            start, length
+        );
+        return None;
+    }
+
+    let mask: u128 = (!0_u128 >> (128 - length)) << start;
+    Some((value & !mask) | ((fieldval << start) & mask))
+}
+
 ///  Write the given u16 to an array, returns the bool.
 ///
 /// # Arguments
@@ -397,6 +503,78 @@ pub fn read_data_u16(data: &[u8], value: &mut u16) -> bool {
     true
 }
 
+///  Write the given u64 to an array, returns the bool.
+///
+/// # Arguments
+///
+/// * `data` - The array of u8.
+/// * `value` - The u64 value
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate util;
+/// use util::num_ops::write_data_u64;
+///
+/// let mut data: [u8; 8] = [0; 8];
+/// let ret = write_data_u64(&mut data, 0x1234_5678_9abc_def0);
+/// assert!(ret && data[0] == 0xf0 && data[7] == 0x12);
+/// ```
+pub fn write_data_u64(data: &mut [u8], value: u64) -> bool {
+    match data.len() {
+        1 => data[0] = value as u8,
+        2 => {
+            LittleEndian::write_u16(data, value as u16);
+        }
+        4 => {
+            LittleEndian::write_u32(data, value as u32);
+        }
+        8 => {
+            LittleEndian::write_u64(data, value);
+        }
+        _ => {
+            error!(
+                "Invalid data length: value {}, data len {}",
+                value,
+                data.len()
+            );
+            return false;
+        }
+    };
+    true
+}
+
+///  Read the given array to an u64, returns the bool.
+///
+/// # Arguments
+///
+/// * `data` - The array of u8.
+/// * `value` - The u64 value
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate util;
+/// use util::num_ops::read_data_u64;
+///
+/// let mut value = 0;
+/// let ret = read_data_u64(&[0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88], &mut value);
+/// assert!(ret && value == 0x8877665544332211);
+/// ```
+pub fn read_data_u64(data: &[u8], value: &mut u64) -> bool {
+    *value = match data.len() {
+        1 => data[0] as u64,
+        2 => LittleEndian::read_u16(data) as u64,
+        4 => LittleEndian::read_u32(data) as u64,
+        8 => LittleEndian::read_u64(data),
+        _ => {
+            error!("Invalid data length: data len {}", data.len());
+            return false;
+        }
+    };
+    true
+}
+
 pub trait Num {
     fn from_str_radix(s: &str, radix: u32) -> Result<Self, UtilError>
     where
@@ -1,3 +1,5 @@
+extern crate alloc;
+
 use core::{arch::asm, fmt};
 use memory_addr::{PhysAddr, VirtAddr};
 
@@ -118,7 +120,9 @@ impl TrapFrame {
             pop     r14
             pop     r15
             add     rsp, 16     // pop vector, error_code
-            swapgs
+            swapgs              // IA32_KERNEL_GS_BASE <-> GS.base; relies on
+                                 // TaskContext::switch_to keeping KERNEL_GS_BASE
+                                 // current for whichever task is about to run
             iretq",
             tf = in(reg) self,
             options(noreturn),
@@ -162,12 +166,17 @@ pub struct FxsaveArea {
 static_assertions::const_assert_eq!(core::mem::size_of::<FxsaveArea>(), 512);
 
 /// Extended state of a task, such as FP/SIMD states.
+///
+/// This is the legacy-only backend: it covers just the 512-byte
+/// FXSAVE/FXRSTOR region, so wider state (AVX/AVX-512, MPX, PKRU) isn't
+/// saved across a context switch. Enable the `xsave` feature for that.
+#[cfg(not(feature = "xsave"))]
 pub struct ExtendedState {
     /// Memory region for the FXSAVE/FXRSTOR instruction.
     pub fxsave_area: FxsaveArea,
 }
 
-#[cfg(feature = "fp_simd")]
+#[cfg(all(feature = "fp_simd", not(feature = "xsave")))]
 impl ExtendedState {
     #[inline]
     fn save(&mut self) {
@@ -188,6 +197,7 @@ impl ExtendedState {
     }
 }
 
+#[cfg(not(feature = "xsave"))]
 impl fmt::Debug for ExtendedState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ExtendedState")
@@ -196,13 +206,198 @@ impl fmt::Debug for ExtendedState {
     }
 }
 
+/// CPUID/XCR0-derived facts about this CPU's XSAVE area, queried once and
+/// cached since they're the same for every task. `xsave` feature only.
+#[cfg(feature = "xsave")]
+struct XsaveInfo {
+    /// Total XSAVE area size for the components XCR0 currently enables
+    /// (`CPUID.(EAX=0x0D,ECX=0):EBX`).
+    area_size: usize,
+    /// The feature mask read from XCR0, passed back into `xsave`/`xrstor`
+    /// so they operate on exactly the components this CPU has enabled.
+    xcr0: u64,
+    /// `xsaveopt` is available (`CPUID.(EAX=0x0D,ECX=1):EAX.bit(0)`) and
+    /// should be preferred: it skips components already up to date in
+    /// memory instead of writing all of them unconditionally.
+    has_xsaveopt: bool,
+}
+
+#[cfg(feature = "xsave")]
+static XSAVE_INFO: spin::Once<XsaveInfo> = spin::Once::new();
+
+#[cfg(feature = "xsave")]
+fn xsave_info() -> &'static XsaveInfo {
+    XSAVE_INFO.call_once(|| unsafe {
+        let component_sizes = core::arch::x86_64::__cpuid_count(0x0D, 0);
+        let xsaveopt_support = core::arch::x86_64::__cpuid_count(0x0D, 1);
+        XsaveInfo {
+            area_size: component_sizes.ebx as usize,
+            xcr0: core::arch::x86_64::_xgetbv(0),
+            has_xsaveopt: xsaveopt_support.eax & 1 != 0,
+        }
+    })
+}
+
+/// A dynamically-sized, 64-byte-aligned XSAVE area, as required by the
+/// `XSAVE`/`XSAVEOPT`/`XRSTOR` instructions. Unlike [`FxsaveArea`], its
+/// size isn't known at compile time -- it depends on which state
+/// components this CPU's XCR0 enables -- so it's a heap allocation rather
+/// than an inline field.
+#[cfg(feature = "xsave")]
+pub struct XsaveArea {
+    ptr: core::ptr::NonNull<u8>,
+    layout: alloc::alloc::Layout,
+}
+
+#[cfg(feature = "xsave")]
+impl XsaveArea {
+    fn new() -> Self {
+        let info = xsave_info();
+        let layout = alloc::alloc::Layout::from_size_align(info.area_size, 64)
+            .expect("CPUID reported an invalid XSAVE area size");
+        let ptr = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        let ptr = core::ptr::NonNull::new(ptr).expect("failed to allocate XSAVE area");
+        // XSAVE header starts at offset 512: XSTATE_BV records which
+        // components hold valid state (none yet) and XCOMP_BV's
+        // compaction bit must stay clear since we use `xsave`/`xsaveopt`,
+        // not the compacted `xsavec`/`xsaves` format. Both are already
+        // zero from `alloc_zeroed`; this just documents the invariant.
+        Self { ptr, layout }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+}
+
+#[cfg(feature = "xsave")]
+impl Drop for XsaveArea {
+    fn drop(&mut self) {
+        unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+#[cfg(feature = "xsave")]
+impl fmt::Debug for XsaveArea {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("XsaveArea")
+            .field("size", &self.layout.size())
+            .finish()
+    }
+}
+
+/// Extended state of a task, such as FP/SIMD states.
+///
+/// This is the XSAVE backend: it covers whatever state components this
+/// CPU's XCR0 enables, so AVX/AVX2/AVX-512 and MPX/PKRU state survives a
+/// context switch, not just the legacy FXSAVE region. Falls back to
+/// `xsave64` when `xsaveopt64` isn't available; both require the `xsave`
+/// feature's CPU support to actually have been detected at boot.
+#[cfg(feature = "xsave")]
+pub struct ExtendedState {
+    area: XsaveArea,
+}
+
+#[cfg(feature = "xsave")]
+impl ExtendedState {
+    #[inline]
+    fn save(&mut self) {
+        let info = xsave_info();
+        unsafe {
+            if info.has_xsaveopt {
+                core::arch::x86_64::_xsaveopt64(self.area.as_mut_ptr(), info.xcr0);
+            } else {
+                core::arch::x86_64::_xsave64(self.area.as_mut_ptr(), info.xcr0);
+            }
+        }
+    }
+
+    #[inline]
+    fn restore(&self) {
+        let info = xsave_info();
+        unsafe { core::arch::x86_64::_xrstor64(self.area.as_ptr(), info.xcr0) }
+    }
+
+    /// Not `const fn`, unlike the FXSAVE backend's: the XSAVE area size
+    /// depends on CPUID and is only known once [`xsave_info`] has run, and
+    /// the area itself is a heap allocation.
+    fn default() -> Self {
+        Self {
+            area: XsaveArea::new(),
+        }
+    }
+}
+
+#[cfg(feature = "xsave")]
+impl fmt::Debug for ExtendedState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExtendedState").field("area", &self.area).finish()
+    }
+}
+
+/// Whether this CPU advertises FSGSBASE (`CPUID.(EAX=7,ECX=0):EBX.bit(0)`),
+/// queried once and cached like [`xsave_info`]'s CPUID facts above. When
+/// available, `switch_to` uses `rdfsbase`/`wrfsbase`/`rdgsbase`/`wrgsbase`
+/// directly instead of the slower `rdmsr`/`wrmsr` MSR path.
+static HAS_FSGSBASE: spin::Once<bool> = spin::Once::new();
+
+fn has_fsgsbase() -> bool {
+    *HAS_FSGSBASE.call_once(|| unsafe { core::arch::x86_64::__cpuid_count(7, 0).ebx & 1 != 0 })
+}
+
+fn read_fs_base() -> u64 {
+    if has_fsgsbase() {
+        unsafe { core::arch::x86_64::_rdfsbase64() }
+    } else {
+        unsafe { x86::msr::rdmsr(x86::msr::IA32_FS_BASE) }
+    }
+}
+
+fn write_fs_base(base: u64) {
+    if has_fsgsbase() {
+        unsafe { core::arch::x86_64::_wrfsbase64(base) }
+    } else {
+        unsafe { x86::msr::wrmsr(x86::msr::IA32_FS_BASE, base) }
+    }
+}
+
+fn read_gs_base() -> u64 {
+    if has_fsgsbase() {
+        unsafe { core::arch::x86_64::_rdgsbase64() }
+    } else {
+        unsafe { x86::msr::rdmsr(x86::msr::IA32_GS_BASE) }
+    }
+}
+
+fn write_gs_base(base: u64) {
+    if has_fsgsbase() {
+        unsafe { core::arch::x86_64::_wrgsbase64(base) }
+    } else {
+        unsafe { x86::msr::wrmsr(x86::msr::IA32_GS_BASE, base) }
+    }
+}
+
+/// `IA32_KERNEL_GS_BASE` has no FSGSBASE-style fast instruction pair -- it's
+/// only ever touched by `swapgs` (hardware) or `rdmsr`/`wrmsr` (software).
+fn read_kernel_gs_base() -> u64 {
+    unsafe { x86::msr::rdmsr(x86::msr::IA32_KERNEL_GS_BASE) }
+}
+
+fn write_kernel_gs_base(base: u64) {
+    unsafe { x86::msr::wrmsr(x86::msr::IA32_KERNEL_GS_BASE, base) }
+}
+
 /// Saved hardware states of a task.
 ///
 /// The context usually includes:
 ///
 /// - Callee-saved registers
 /// - Stack pointer register
-/// - Thread pointer register (for thread-local storage, currently unsupported)
+/// - Thread pointer registers (`FS`/`GS`/kernel `GS` base, for thread-local storage)
 /// - FP/SIMD registers
 ///
 /// On context switch, current task saves its context from CPU to memory,
@@ -222,11 +417,20 @@ pub struct TaskContext {
     /// `RSP` after all callee-saved registers are pushed.
     pub rsp: u64,
     pub cr3: u64,
+    /// Thread pointer (`IA32_FS_BASE`), e.g. a TLS base.
+    pub fs_base: u64,
+    /// `IA32_GS_BASE`.
+    pub gs_base: u64,
+    /// `IA32_KERNEL_GS_BASE`, swapped in by `swapgs` on a trap from
+    /// userspace; kept in sync here so it survives a task switch the same
+    /// way the live `gs_base` does.
+    pub kernel_gs_base: u64,
     /// Extended states, i.e., FP/SIMD states.
-    #[cfg(feature = "fp_simd")]
+    #[cfg(any(feature = "fp_simd", feature = "xsave"))]
     pub ext_state: ExtendedState,
 }
 
+#[cfg(not(feature = "xsave"))]
 impl TaskContext {
     /// Creates a new default context for a new task.
     pub const fn new() -> Self {
@@ -234,14 +438,39 @@ impl TaskContext {
             kstack_top: VirtAddr::from(0),
             rsp: 0,
             cr3: 0,
+            fs_base: 0,
+            gs_base: 0,
+            kernel_gs_base: 0,
             #[cfg(feature = "fp_simd")]
             ext_state: ExtendedState::default(),
         }
     }
+}
 
-    /// Initializes the context for a new task, with the given entry point and
-    /// kernel stack.
-    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr, page_table_root: PhysAddr) {
+/// XSAVE's `ExtendedState::default()` allocates, so unlike the FXSAVE
+/// backend above, this can't be a `const fn`.
+#[cfg(feature = "xsave")]
+impl TaskContext {
+    /// Creates a new default context for a new task.
+    pub fn new() -> Self {
+        Self {
+            kstack_top: VirtAddr::from(0),
+            rsp: 0,
+            cr3: 0,
+            fs_base: 0,
+            gs_base: 0,
+            kernel_gs_base: 0,
+            ext_state: ExtendedState::default(),
+        }
+    }
+}
+
+impl TaskContext {
+    /// Initializes the context for a new task, with the given entry point,
+    /// kernel stack and initial thread pointer (`FS` base, e.g. a TLS
+    /// block), so the task starts with a valid thread pointer instead of
+    /// whatever was last loaded into `IA32_FS_BASE`.
+    pub fn init(&mut self, entry: usize, kstack_top: VirtAddr, page_table_root: PhysAddr, tls_base: usize) {
         unsafe {
             // x86_64 calling convention: the stack must be 16-byte aligned before
             // calling a function. That means when entering a new task (`ret` in `context_switch`
@@ -259,6 +488,7 @@ impl TaskContext {
         }
         self.kstack_top = kstack_top;
         self.cr3 = page_table_root.as_usize() as u64;
+        self.fs_base = tls_base as u64;
     }
 
     /// Switches to another task.
@@ -266,7 +496,7 @@ impl TaskContext {
     /// It first saves the current task's context from CPU to this place, and then
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
-        #[cfg(feature = "fp_simd")]
+        #[cfg(any(feature = "fp_simd", feature = "xsave"))]
         {
             self.ext_state.save();
             next_ctx.ext_state.restore();
@@ -277,9 +507,14 @@ impl TaskContext {
             next_ctx.kstack_top, next_ctx.rsp
         );
 
-        unsafe {
-            // TODO: swtich tls
+        self.fs_base = read_fs_base();
+        self.gs_base = read_gs_base();
+        self.kernel_gs_base = read_kernel_gs_base();
+        write_fs_base(next_ctx.fs_base);
+        write_gs_base(next_ctx.gs_base);
+        write_kernel_gs_base(next_ctx.kernel_gs_base);
 
+        unsafe {
             // PerCpu::current()
             //     .arch_data()
             //     .as_mut()
@@ -1,3 +1,4 @@
+use spin::Once;
 use x86::{controlregs::cr2, irq::*};
 
 use crate::current_cpu_id;
@@ -12,10 +13,63 @@ pub const NMI_VECTOR: u8 = 0x2;
 const IRQ_VECTOR_START: u8 = 0x20;
 const IRQ_VECTOR_END: u8 = 0xff;
 
+/// Base vector `IdtStruct::new()` installs trap entries at, akin to a
+/// settable exception-vector-table address. Defaults to `0`, i.e. the
+/// vectors baked into `trap.S`; an embedder that needs its IDT relocated
+/// (e.g. to share a vector range with a host hypervisor) calls
+/// [`set_trap_vector_base`] before IDT setup runs.
+static TRAP_VECTOR_BASE: Once<usize> = Once::new();
+
+/// Installs `base` as the trap vector table base. Must be called before
+/// `IdtStruct::new()`; calling it twice is a bug, same as any other
+/// one-shot init routine in this crate.
+pub fn set_trap_vector_base(base: usize) {
+    TRAP_VECTOR_BASE.call_once(|| base);
+}
+
+/// The configured trap vector base, or `0` if [`set_trap_vector_base`] was
+/// never called.
+pub fn trap_vector_base() -> usize {
+    *TRAP_VECTOR_BASE.get().unwrap_or(&0)
+}
+
+/// State handed to a [`FaultHandler`]: the faulting frame plus, for page
+/// faults, the address that faulted (`cr2`).
+#[derive(Debug, Clone, Copy)]
+pub struct FaultInfo {
+    /// The faulting address, as read from `cr2`. Only meaningful for `#PF`.
+    pub fault_vaddr: usize,
+    /// The trap frame saved at fault time.
+    pub tf: TrapFrame,
+}
+
+/// High-level `#PF`/`#GP` handler an embedder can install instead of the
+/// default panic-on-fault behavior, so it can implement demand paging or
+/// instruction emulation. Mirrors how `axhal::trap::SyscallHandler` lets
+/// `axprocess` own syscall dispatch instead of baking it into `axhal`.
+#[crate_interface::def_interface]
+pub trait FaultHandler {
+    /// Handle a `#PF`. Returning `true` means the fault was handled and
+    /// the faulting instruction should be retried; `false` falls back to
+    /// the default panic.
+    fn handle_page_fault(info: &FaultInfo) -> bool;
+
+    /// Handle a `#GP`. Same return convention as `handle_page_fault`.
+    fn handle_general_protection_fault(info: &FaultInfo) -> bool;
+}
+
 #[no_mangle]
 fn x86_trap_handler(tf: &mut TrapFrame) {
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => {
+            let info = FaultInfo {
+                fault_vaddr: unsafe { cr2() },
+                tf: *tf,
+            };
+            if crate_interface::call_interface!(FaultHandler::handle_page_fault, &info) {
+                return;
+            }
+
             #[cfg(feature = "monolithic")]
             if tf.is_user() {
                 panic!(
@@ -43,6 +97,14 @@ fn x86_trap_handler(tf: &mut TrapFrame) {
         ),
         BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
         GENERAL_PROTECTION_FAULT_VECTOR => {
+            let info = FaultInfo {
+                fault_vaddr: unsafe { cr2() },
+                tf: *tf,
+            };
+            if crate_interface::call_interface!(FaultHandler::handle_general_protection_fault, &info) {
+                return;
+            }
+
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
                 tf.rip, tf.error_code, tf
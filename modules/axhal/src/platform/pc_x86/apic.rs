@@ -1,10 +1,12 @@
 #![allow(dead_code)]
 
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use bit_field::BitField;
 use lazy_init::LazyInit;
 use memory_addr::PhysAddr;
 use spinlock::SpinNoIrq;
-use x2apic::ioapic::IoApic;
+use x2apic::ioapic::{IoApic, IrqMode};
 use x2apic::lapic::{xapic_base, LocalApic, LocalApicBuilder};
 
 use self::vectors::*;
@@ -19,6 +21,57 @@ pub(super) mod vectors {
 /// The maximum number of IRQs.
 pub const MAX_IRQ_COUNT: usize = 256;
 
+/// Per-vector interrupt counts, following the same stats-collection
+/// pattern as the Ethernet drivers. [`record_irq`] bumps these with
+/// relaxed atomics from [`dispatch_irq`] and [`send_ipi`] so the hot path
+/// never has to take [`IO_APIC`]'s spinlock just to count an interrupt.
+static IRQ_COUNTS: [AtomicU64; MAX_IRQ_COUNT] = [const { AtomicU64::new(0) }; MAX_IRQ_COUNT];
+/// Spurious interrupts (`APIC_SPURIOUS_VECTOR`) delivered since the last
+/// [`reset_irq_stats`].
+static SPURIOUS_COUNT: AtomicU64 = AtomicU64::new(0);
+/// APIC error interrupts (`APIC_ERROR_VECTOR`) delivered since the last
+/// [`reset_irq_stats`].
+static ERROR_COUNT: AtomicU64 = AtomicU64::new(0);
+
+fn record_irq(vector: usize) {
+    if vector == APIC_SPURIOUS_VECTOR as usize {
+        SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+    } else if vector == APIC_ERROR_VECTOR as usize {
+        ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    } else if let Some(counter) = IRQ_COUNTS.get(vector) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of per-vector interrupt counts since the last
+/// [`reset_irq_stats`], so operators can see interrupt rates per device
+/// and detect interrupt storms.
+pub fn irq_stats() -> [u64; MAX_IRQ_COUNT] {
+    core::array::from_fn(|i| IRQ_COUNTS[i].load(Ordering::Relaxed))
+}
+
+/// Spurious-interrupt count (`APIC_SPURIOUS_VECTOR`) since the last
+/// [`reset_irq_stats`].
+pub fn irq_spurious_count() -> u64 {
+    SPURIOUS_COUNT.load(Ordering::Relaxed)
+}
+
+/// APIC error-interrupt count (`APIC_ERROR_VECTOR`) since the last
+/// [`reset_irq_stats`].
+pub fn irq_error_count() -> u64 {
+    ERROR_COUNT.load(Ordering::Relaxed)
+}
+
+/// Zero every counter [`irq_stats`]/[`irq_spurious_count`]/[`irq_error_count`]
+/// report, so a caller can measure a rate over the next interval.
+pub fn reset_irq_stats() {
+    for counter in &IRQ_COUNTS {
+        counter.store(0, Ordering::Relaxed);
+    }
+    SPURIOUS_COUNT.store(0, Ordering::Relaxed);
+    ERROR_COUNT.store(0, Ordering::Relaxed);
+}
+
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
@@ -156,10 +209,35 @@ pub fn send_ipi(irq_num: usize) {
 
     if vector >= 0x20 {
         debug!("send_ipi {} {}", vector, dest);
+        record_irq(vector as usize);
         unsafe { local_apic().send_ipi(vector, dest as _) };
     }
 }
 
+/// Steers the device interrupt on IO-APIC redirection-table entry `vector`
+/// to `target_cpu`'s local APIC in fixed delivery mode, mirroring the GIC
+/// `ICDIPTR`-style per-interrupt target configuration.
+///
+/// The table entry's destination field is only 8 bits wide (unlike the
+/// 64-bit `ApicIcr` this file also models, whose `dest_field` spans bits
+/// 32..64), so `target_cpu` is written here as the bare physical APIC ID
+/// rather than through [`raw_apic_id`]'s xAPIC left-shift-by-24 encoding:
+/// that shift places the ID at bits 56..64 of a 64-bit ICR for
+/// [`send_ipi`]'s local-APIC path, and re-applying it to this 8-bit field
+/// would shift `target_cpu` out of it entirely, landing the interrupt on
+/// the wrong CPU (or CPU 0) instead of the one actually requested. Whether
+/// the local APIC itself is running in xAPIC or x2APIC mode
+/// ([`IS_X2APIC`]) doesn't change the width of the IO-APIC's legacy
+/// redirection-table format, so both modes use the same destination write.
+#[cfg(feature = "irq")]
+pub fn set_irq_affinity(vector: usize, target_cpu: u32) {
+    let mut io_apic = IO_APIC.lock();
+    let mut entry = unsafe { io_apic.table_entry(vector as u8) };
+    entry.set_mode(IrqMode::Fixed);
+    entry.set_dest(target_cpu as u8);
+    unsafe { io_apic.set_table_entry(vector as u8, entry) };
+}
+
 pub fn send_nmi_to(dest: usize) {
     // unsafe{ local_apic().send_ipi(APIC_NMI_VECTOR as _, dest as _) };
     unsafe { local_apic().send_nmi(dest as _) };
@@ -171,6 +249,7 @@ pub fn send_nmi_to(dest: usize) {
 /// necessary, it also acknowledges the interrupt controller after handling.
 #[cfg(feature = "irq")]
 pub fn dispatch_irq(vector: usize) {
+    record_irq(vector);
     crate::irq::dispatch_irq_common(vector);
     unsafe { local_apic().end_of_interrupt() };
 }
@@ -1,3 +1,11 @@
+//! This is the only half of the syscall-forwarding mechanism present in this
+//! tree: `crate::scf::allocator`/`crate::scf::queue` (the pool allocator and
+//! the request queue this module is built on) and whatever reads
+//! [`SyscallArgs`]/[`ScfIoVec`] out of the pool on the host side aren't
+//! checked in here, so this is written against the same API surface the
+//! original single-chunk version already assumed, on faith that the shapes
+//! line up.
+
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
@@ -7,7 +15,15 @@ use axmem::{UserInPtr, UserOutPtr};
 
 use crate::syscall::Sysno;
 
+/// Size of one pool-backed data chunk a transfer is split into.
 const CHUNK_SIZE: usize = 256;
+/// Upper bound on how many [`CHUNK_SIZE`] chunks one forwarded `read`/`write`
+/// can scatter/gather across. This module has no general-purpose allocator
+/// to size the descriptor array from the caller's `len` alone, so the
+/// descriptor array and the per-chunk pointer bookkeeping both live in a
+/// fixed-size stack array instead; `MAX_SCF_CHUNKS * CHUNK_SIZE` is the
+/// largest buffer one `scf_write`/`scf_read` call can forward.
+const MAX_SCF_CHUNKS: usize = 64;
 
 pub struct SyscallCondVar {
     ok: AtomicBool,
@@ -35,11 +51,31 @@ impl SyscallCondVar {
     }
 }
 
+/// One chunk of a forwarded transfer: `len` bytes of pool-allocated data
+/// starting at pool byte offset `offset`. Same `(base, len)` shape as
+/// `sys_writev`'s `IoVec` (see `crate::syscall::fs`), but addressed as a
+/// pool offset rather than a raw pointer, since the pool is what the host
+/// side actually reads this buffer through.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ScfIoVec {
+    offset: u64,
+    len: u64,
+}
+
 /// Forwarded syscall args, does not contains syscall number.
+///
+/// Carries a descriptor array instead of a single `(ptr, len)` pair, so a
+/// transfer bigger than one pool chunk can be split across several and
+/// described in one request: `iov_offset` points at `iov_count` contiguous
+/// [`ScfIoVec`] entries for the host to gather (write) or scatter (read)
+/// across, in the same order `sys_writev` walks its `IoVec` array.
 #[repr(C)]
 #[derive(Debug)]
 struct SyscallArgs {
-    args: [u64; 6],
+    fd: u64,
+    iov_offset: u64,
+    iov_count: u64,
 }
 
 fn send_request(opcode: Sysno, args_offset: u64, token: ScfRequestToken) {
@@ -49,14 +85,87 @@ fn send_request(opcode: Sysno, args_offset: u64, token: ScfRequestToken) {
     super::notify();
 }
 
+/// Carve `len` bytes into `CHUNK_SIZE`-sized pool allocations and describe
+/// them as a pool-allocated [`ScfIoVec`] array. Returns the descriptor
+/// array's pointer and entry count, plus the per-chunk pointers in
+/// `chunk_ptrs[..iov_count]` so the caller can still fill (write) or drain
+/// (read) each chunk directly and free everything afterward.
+///
+/// Returns `None` if `len` would need more than [`MAX_SCF_CHUNKS`] chunks.
+fn alloc_chunks(
+    pool: &SyscallDataBuffer,
+    len: usize,
+    chunk_ptrs: &mut [*mut u8; MAX_SCF_CHUNKS],
+) -> Option<(*mut ScfIoVec, usize)> {
+    let iov_count = if len == 0 {
+        0
+    } else {
+        (len + CHUNK_SIZE - 1) / CHUNK_SIZE
+    };
+    if iov_count > MAX_SCF_CHUNKS {
+        return None;
+    }
+    if iov_count == 0 {
+        return Some((core::ptr::null_mut(), 0));
+    }
+
+    let iov_ptr = unsafe { pool.alloc_array_uninit::<ScfIoVec>(iov_count) };
+    let mut remaining = len;
+    for i in 0..iov_count {
+        let chunk_len = remaining.min(CHUNK_SIZE);
+        let chunk_ptr = unsafe { pool.alloc_array_uninit::<u8>(chunk_len) };
+        chunk_ptrs[i] = chunk_ptr;
+        unsafe {
+            iov_ptr.add(i).write(ScfIoVec {
+                offset: pool.offset_of(chunk_ptr),
+                len: chunk_len as u64,
+            });
+        }
+        remaining -= chunk_len;
+    }
+    Some((iov_ptr, iov_count))
+}
+
+/// Free every chunk [`alloc_chunks`] allocated, plus the descriptor array
+/// and args block it was assembled into.
+fn dealloc_chunks(
+    pool: &SyscallDataBuffer,
+    iov_ptr: *mut ScfIoVec,
+    chunk_ptrs: &[*mut u8; MAX_SCF_CHUNKS],
+    iov_count: usize,
+    args: *mut SyscallArgs,
+) {
+    unsafe {
+        for &chunk_ptr in &chunk_ptrs[..iov_count] {
+            pool.dealloc(chunk_ptr);
+        }
+        if iov_count != 0 {
+            pool.dealloc(iov_ptr);
+        }
+        pool.dealloc(args);
+    }
+}
+
 pub fn scf_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> isize {
     debug!("scf write fd {} len {:#x}", fd, len);
-    assert!(len < CHUNK_SIZE);
     let pool = SyscallDataBuffer::get();
-    let chunk_ptr = unsafe { pool.alloc_array_uninit::<u8>(len) };
-    buf.read_buf(unsafe { from_raw_parts_mut(chunk_ptr as _, len) });
+    let mut chunk_ptrs = [core::ptr::null_mut(); MAX_SCF_CHUNKS];
+    let Some((iov_ptr, iov_count)) = alloc_chunks(pool, len, &mut chunk_ptrs) else {
+        return -1;
+    };
+
+    let mut copied = 0;
+    for &chunk_ptr in &chunk_ptrs[..iov_count] {
+        let chunk_len = len.saturating_sub(copied).min(CHUNK_SIZE);
+        buf.add(copied)
+            .read_buf(unsafe { from_raw_parts_mut(chunk_ptr as _, chunk_len) });
+        copied += chunk_len;
+    }
+
     let args = pool.alloc(SyscallArgs {
-        args: [fd as u64, pool.offset_of(chunk_ptr), len as u64, 0, 0, 0],
+        fd: fd as u64,
+        iov_offset: if iov_count != 0 { pool.offset_of(iov_ptr) } else { 0 },
+        iov_count: iov_count as u64,
     });
     let cond = SyscallCondVar::new();
     send_request(
@@ -65,19 +174,22 @@ pub fn scf_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> isize {
         ScfRequestToken::from(&cond),
     );
     let ret = cond.wait();
-    unsafe {
-        pool.dealloc(chunk_ptr);
-        pool.dealloc(args);
-    }
+    dealloc_chunks(pool, iov_ptr, &chunk_ptrs, iov_count, args);
     ret as _
 }
 
 pub fn scf_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
-    assert!(len < CHUNK_SIZE);
+    debug!("scf read fd {} len {:#x}", fd, len);
     let pool = SyscallDataBuffer::get();
-    let chunk_ptr = unsafe { pool.alloc_array_uninit::<u8>(len) };
+    let mut chunk_ptrs = [core::ptr::null_mut(); MAX_SCF_CHUNKS];
+    let Some((iov_ptr, iov_count)) = alloc_chunks(pool, len, &mut chunk_ptrs) else {
+        return -1;
+    };
+
     let args = pool.alloc(SyscallArgs {
-        args: [fd as u64, pool.offset_of(chunk_ptr), len as u64, 0, 0, 0],
+        fd: fd as u64,
+        iov_offset: if iov_count != 0 { pool.offset_of(iov_ptr) } else { 0 },
+        iov_count: iov_count as u64,
     });
     let cond = SyscallCondVar::new();
     send_request(
@@ -86,10 +198,16 @@ pub fn scf_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
         ScfRequestToken::from(&cond),
     );
     let ret = cond.wait();
-    unsafe {
-        buf.write_buf(from_raw_parts(chunk_ptr as _, len));
-        pool.dealloc(chunk_ptr);
-        pool.dealloc(args);
+
+    let mut copied = 0;
+    for &chunk_ptr in &chunk_ptrs[..iov_count] {
+        let chunk_len = len.saturating_sub(copied).min(CHUNK_SIZE);
+        unsafe {
+            buf.add(copied)
+                .write_buf(from_raw_parts(chunk_ptr as _, chunk_len));
+        }
+        copied += chunk_len;
     }
+    dealloc_chunks(pool, iov_ptr, &chunk_ptrs, iov_count, args);
     ret as _
 }
@@ -0,0 +1,10 @@
+//! Linux x86_64 negative-errno values returned by syscall handlers.
+//!
+//! Only the codes the dispatch table in [`super`] actually returns are
+//! listed here; consult `errno.h` if a new handler needs one that isn't.
+
+pub const EBADF: isize = 9;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EINVAL: isize = 22;
+pub const ENOSYS: isize = 38;
@@ -1,51 +1,88 @@
 use axlog::ax_println;
 /// File related syscalls.
 use axmem::{UserInPtr, UserOutPtr};
+use spin::Mutex;
+
+use super::errno::EBADF;
 
 const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
 const FD_STDERR: usize = 2;
 const CHUNK_SIZE: usize = 256;
+const MAX_FDS: usize = 32;
+
+/// What an fd slot is backed by. Real files aren't wired up yet, so the
+/// only variants are the three inherited console streams; a free slot is
+/// `None`. Kept as its own table rather than hardcoding `FD_STD*` checks
+/// in every handler, so a real filesystem layer can later claim fds above
+/// 2 without touching `sys_read`/`sys_write`.
+#[derive(Clone, Copy)]
+enum FileDescriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+struct FdTable {
+    slots: [Option<FileDescriptor>; MAX_FDS],
+}
+
+impl FdTable {
+    const fn new() -> Self {
+        let mut slots = [None; MAX_FDS];
+        slots[FD_STDIN] = Some(FileDescriptor::Stdin);
+        slots[FD_STDOUT] = Some(FileDescriptor::Stdout);
+        slots[FD_STDERR] = Some(FileDescriptor::Stderr);
+        Self { slots }
+    }
+
+    fn get(&self, fd: usize) -> Option<FileDescriptor> {
+        self.slots.get(fd).copied().flatten()
+    }
+}
+
+static FD_TABLE: Mutex<FdTable> = Mutex::new(FdTable::new());
 
 pub fn sys_write(fd: usize, buf: UserInPtr<u8>, len: usize) -> isize {
     #[cfg(feature = "hv")]
     return crate::scf::syscall_forward::scf_write(fd, buf, len);
 
-    match fd {
-        FD_STDOUT | FD_STDERR => {
+    match FD_TABLE.lock().get(fd) {
+        Some(FileDescriptor::Stdout) | Some(FileDescriptor::Stderr) => {
             let mut count = 0;
             while count < len {
-                let chunk_len = CHUNK_SIZE.min(len);
+                let chunk_len = CHUNK_SIZE.min(len - count);
                 let chunk: [u8; CHUNK_SIZE] = unsafe { buf.add(count).read_array(chunk_len) };
                 ax_println!("{}", core::str::from_utf8(&chunk[..chunk_len]).unwrap());
                 count += chunk_len;
             }
             count as isize
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
-        }
+        _ => -EBADF,
     }
 }
 
-// pub fn sys_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
-//     match fd {
-//         FD_STDIN => {
-//             assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-//             loop {
-//                 if let Some(c) = console_getchar() {
-//                     buf.write(c);
-//                     return 1;
-//                 } else {
-//                     CurrentTask::get().yield_now();
-//                 }
-//             }
-//         }
-//         _ => {
-//             panic!("Unsupported fd in sys_read!");
-//         }
-//     }
-// }
+pub fn sys_read(fd: usize, mut buf: UserOutPtr<u8>, len: usize) -> isize {
+    #[cfg(feature = "hv")]
+    return crate::scf::syscall_forward::scf_read(fd, buf, len);
+
+    match FD_TABLE.lock().get(fd) {
+        Some(FileDescriptor::Stdin) => {
+            let mut count = 0;
+            while count < len {
+                match axhal::console::getchar() {
+                    Some(c) => {
+                        buf.add(count).write_buf(&[c]);
+                        count += 1;
+                    }
+                    None => axtask::yield_now(),
+                }
+            }
+            count as isize
+        }
+        _ => -EBADF,
+    }
+}
 
 /// iovec - Vector I/O data structure
 /// Ref: https://man7.org/linux/man-pages/man3/iovec.3type.html
@@ -56,43 +93,18 @@ pub struct IoVec {
 }
 
 pub fn sys_writev(fd: usize, iov: *const IoVec, iov_cnt: usize) -> isize {
-    match fd {
-        FD_STDOUT | FD_STDERR => {
-            let mut write_len = 0;
-            for i in 0..iov_cnt {
-                let io: &IoVec = unsafe { &(*iov.add(i)) };
-                if io.base.is_null() || io.len == 0 {
-                    continue;
-                }
-                let res = sys_write(fd, (io.base as usize).into(), io.len);
-                if res >= 0 {
-                    write_len += res;
-                } else {
-                    return res;
-                }
-            }
-            write_len as isize
+    let mut write_len = 0;
+    for i in 0..iov_cnt {
+        let io: &IoVec = unsafe { &(*iov.add(i)) };
+        if io.base.is_null() || io.len == 0 {
+            continue;
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        let res = sys_write(fd, (io.base as usize).into(), io.len);
+        if res >= 0 {
+            write_len += res;
+        } else {
+            return res;
         }
     }
+    write_len as isize
 }
-
-// pub fn sys_readv(fd: usize, iov: *const IoVec, iov_cnt: usize) -> isize {
-//     match fd {
-//         FD_STDOUT | FD_STDERR => {
-//             let mut count = 0;
-//             while count < len {
-//                 let chunk_len = CHUNK_SIZE.min(len);
-//                 let chunk: [u8; CHUNK_SIZE] = unsafe { buf.add(count).read_array(chunk_len) };
-//                 print!("{}", core::str::from_utf8(&chunk[..chunk_len]).unwrap());
-//                 count += chunk_len;
-//             }
-//             count as isize
-//         }
-//         _ => {
-//             panic!("Unsupported fd in sys_write!");
-//         }
-//     }
-// }
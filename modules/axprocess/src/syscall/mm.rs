@@ -0,0 +1,68 @@
+//! Memory-management syscalls (`brk`, `mmap`), backed by `axalloc`'s page
+//! allocator. There's no VMA bookkeeping in this crate yet, so both of
+//! these just hand out fresh pages; `mmap` ignores its address hint and
+//! `brk` never reclaims pages on shrink.
+
+use axalloc::global_allocator;
+use axhal::mem::PAGE_SIZE_4K;
+
+use super::errno::{EINVAL, ENOMEM};
+
+/// `MAP_ANONYMOUS`, the only `mmap` flag this crate understands.
+const MAP_ANONYMOUS: i32 = 0x20;
+
+/// Current program break. `0` means `brk` has never been called, in which
+/// case the first call seeds it from whatever `axalloc` hands back rather
+/// than a fixed link-time symbol -- this crate has no notion of a
+/// per-process image base to grow from.
+static BREAK: spin::Mutex<usize> = spin::Mutex::new(0);
+
+fn pages_for(bytes: usize) -> usize {
+    bytes.div_ceil(PAGE_SIZE_4K)
+}
+
+/// `brk(addr)`: grow the break to `addr` and return the new break. `addr
+/// == 0` is the "query current break" form Linux defines. Shrinking is
+/// accepted but not actually reclaimed.
+pub fn sys_brk(addr: usize) -> isize {
+    let mut brk = BREAK.lock();
+    if *brk == 0 {
+        let Ok(base) = global_allocator().alloc_pages(1, PAGE_SIZE_4K) else {
+            return -ENOMEM;
+        };
+        *brk = base + PAGE_SIZE_4K;
+    }
+
+    if addr == 0 || addr <= *brk {
+        return *brk as isize;
+    }
+
+    match global_allocator().alloc_pages(pages_for(addr - *brk), PAGE_SIZE_4K) {
+        Ok(_) => {
+            *brk = addr;
+            *brk as isize
+        }
+        Err(_) => -ENOMEM,
+    }
+}
+
+/// `mmap(addr, len, prot, flags, fd, offset)`. Only anonymous, private
+/// mappings are supported -- anything file-backed comes back as
+/// `-EINVAL` since there's no page-cache layer to back it with.
+pub fn sys_mmap(
+    _addr: usize,
+    len: usize,
+    _prot: i32,
+    flags: i32,
+    fd: isize,
+    _offset: isize,
+) -> isize {
+    if len == 0 || flags & MAP_ANONYMOUS == 0 || fd != -1 {
+        return -EINVAL;
+    }
+
+    match global_allocator().alloc_pages(pages_for(len), PAGE_SIZE_4K) {
+        Ok(vaddr) => vaddr as isize,
+        Err(_) => -ENOMEM,
+    }
+}
@@ -1,7 +1,12 @@
+mod errno;
 mod fs;
+mod mm;
+mod time;
 
 pub use syscalls::Sysno;
 
+use errno::ENOSYS;
+
 struct SyscallHandlerImpl;
 
 #[crate_interface::impl_interface]
@@ -11,11 +16,22 @@ impl axhal::trap::SyscallHandler for SyscallHandlerImpl {
     }
 }
 
+/// This crate doesn't track per-task PIDs yet, so every guest sees the
+/// same placeholder.
+fn sys_getpid() -> isize {
+    1
+}
+
+/// Dispatch one trapped syscall to its handler and return its Linux
+/// x86_64 ABI result: a non-negative value on success, or `-errno` on
+/// failure. Unrecognized or unimplemented syscall numbers get `-ENOSYS`
+/// instead of the `0` this used to return, since reporting success for a
+/// call that never ran would just be lying to the guest.
 #[no_mangle]
 pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
-    let ans: isize;
-
-    let sysno = Sysno::new(syscall_id).unwrap();
+    let Some(sysno) = Sysno::new(syscall_id) else {
+        return -ENOSYS;
+    };
 
     info!(
         "[SYSCALL] {syscall_id} {} [{:#x}, {:#x}, {:#x}]",
@@ -24,20 +40,23 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         args[1],
         args[2]
     );
+
     match sysno {
-        Sysno::write => {
-            ans = fs::sys_write(args[0], args[1].into(), args[2]);
-        }
-        Sysno::writev => {
-            ans = fs::sys_writev(args[0], args[1] as *const fs::IoVec, args[2]);
-        }
-        Sysno::exit => {
-            axtask::exit(args[0] as i32);
-        }
-        _ => {
-            ans = 0;
-        }
+        Sysno::read => fs::sys_read(args[0], args[1].into(), args[2]),
+        Sysno::write => fs::sys_write(args[0], args[1].into(), args[2]),
+        Sysno::writev => fs::sys_writev(args[0], args[1] as *const fs::IoVec, args[2]),
+        Sysno::brk => mm::sys_brk(args[0]),
+        Sysno::mmap => mm::sys_mmap(
+            args[0],
+            args[1],
+            args[2] as i32,
+            args[3] as i32,
+            args[4] as isize,
+            args[5] as isize,
+        ),
+        Sysno::clock_gettime => time::sys_clock_gettime(args[0], args[1]),
+        Sysno::getpid => sys_getpid(),
+        Sysno::exit => axtask::exit(args[0] as i32),
+        _ => -ENOSYS,
     }
-
-    ans
 }
@@ -0,0 +1,28 @@
+//! `clock_gettime`, backed by `axhal`'s monotonic timestamp.
+
+use axhal::time::current_time_nanos;
+
+use super::errno::EFAULT;
+
+#[repr(C)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: i64,
+}
+
+/// `clock_gettime(clockid, tp)`. This crate has only one time source, so
+/// every `clockid` Linux defines (`CLOCK_REALTIME`, `CLOCK_MONOTONIC`, ...)
+/// reads the same `axhal::time::current_time_nanos()`.
+pub fn sys_clock_gettime(_clockid: usize, tp: usize) -> isize {
+    if tp == 0 {
+        return -EFAULT;
+    }
+
+    let nanos = current_time_nanos();
+    let ts = Timespec {
+        tv_sec: (nanos / 1_000_000_000) as i64,
+        tv_nsec: (nanos % 1_000_000_000) as i64,
+    };
+    unsafe { (tp as *mut Timespec).write(ts) };
+    0
+}
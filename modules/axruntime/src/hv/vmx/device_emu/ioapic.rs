@@ -0,0 +1,207 @@
+//! Emulated IOAPIC (ref: Intel 82093AA datasheet; the vector/delivery-mode
+//! fields a redirection entry carries are the same ones the local APIC's
+//! ICR uses — see `lapic.rs`).
+//!
+//! Exposes the classic indirect MMIO interface: a 32-bit IOREGSEL selects a
+//! register, and IOWIN reads/writes it. Only IOAPICID/IOAPICVER and the 24
+//! redirection-table entries are implemented; IOAPICARB always reads back 0.
+//!
+//! [`IoApic::trigger`] is a one-shot edge post. [`IoApic::raise_gsi`]/
+//! [`IoApic::lower_gsi`]/[`IoApic::handle_eoi`] add level-triggered semantics
+//! (remote IRR gating re-delivery until EOI) for sources registered through
+//! `super::register_level_irq`.
+
+use alloc::vec::Vec;
+
+use bit_field::BitField;
+use hypercraft::{HyperError, HyperResult};
+
+use super::lapic;
+use super::MmioDevice;
+
+const REG_IOAPICID: u32 = 0x00;
+const REG_IOAPICVER: u32 = 0x01;
+const REG_IOAPICARB: u32 = 0x02;
+const REG_REDTBL_BASE: u32 = 0x10;
+
+/// Number of redirection-table entries (and thus input pins); matches the
+/// 82093AA and what most BIOSes/OSes assume for a single IOAPIC.
+const NUM_PINS: usize = 24;
+
+/// Redirection-table entry bit layout, within the 64-bit value built from
+/// the pair of 32-bit registers at `REG_REDTBL_BASE + 2*pin` (low) and
+/// `+ 2*pin + 1` (high).
+const VECTOR: core::ops::Range<usize> = 0..8;
+const TRIGGER_MODE: usize = 15;
+const REMOTE_IRR: usize = 14;
+const MASKED: usize = 16;
+const DEST_FIELD: core::ops::Range<usize> = 56..64;
+
+pub struct IoApic {
+    base: u64,
+    ioregsel: u32,
+    id: u32,
+    /// One 64-bit redirection-table entry per input pin; bit 16 (mask)
+    /// starts set, same as real hardware after reset.
+    redir_table: [u64; NUM_PINS],
+    /// Device-side level latch, one per pin: whatever [`Self::raise_gsi`]/
+    /// [`Self::lower_gsi`] last set it to, independent of remote IRR. Lets a
+    /// resample callback (see `super::IrqLine`) ask "is the source still
+    /// driving this line?" without reaching back into the device itself.
+    asserted: [bool; NUM_PINS],
+}
+
+impl IoApic {
+    pub fn new(base: u64) -> Self {
+        Self {
+            base,
+            ioregsel: 0,
+            id: 0,
+            redir_table: [1 << MASKED; NUM_PINS],
+            asserted: [false; NUM_PINS],
+        }
+    }
+
+    fn read_register(&self, index: u32) -> u32 {
+        match index {
+            REG_IOAPICID => self.id << 24,
+            // Version 0x11 (matches the 82093AA), max redirection entry
+            // index in bits 16..24.
+            REG_IOAPICVER => ((NUM_PINS as u32 - 1) << 16) | 0x11,
+            REG_IOAPICARB => 0,
+            reg if reg >= REG_REDTBL_BASE => {
+                let pin = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(&entry) = self.redir_table.get(pin) else {
+                    return 0;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    entry as u32
+                } else {
+                    (entry >> 32) as u32
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, index: u32, value: u32) {
+        match index {
+            REG_IOAPICID => self.id = value.get_bits(24..28),
+            reg if reg >= REG_REDTBL_BASE => {
+                let pin = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(entry) = self.redir_table.get_mut(pin) else {
+                    return;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    *entry = (*entry & !0xffff_ffff) | value as u64;
+                } else {
+                    *entry = (*entry & 0xffff_ffff) | ((value as u64) << 32);
+                }
+            }
+            // IOAPICVER/IOAPICARB are read-only.
+            _ => {}
+        }
+    }
+
+    /// Assert GSI `irq`: unless the guest has masked the redirection entry,
+    /// decode its vector/destination and hand it to the same LAPIC delivery
+    /// path ICR writes use ([`lapic::deliver_vector_to`]), so the target
+    /// re-evaluates its IRR on the next VM entry. `irq` is the IOAPIC input
+    /// pin; legacy ISA sources (PIT, UART, ...) use the same numbering the
+    /// i8259 pair does for the lines that overlap.
+    pub fn trigger(&self, irq: u8) -> HyperResult {
+        let Some(&entry) = self.redir_table.get(irq as usize) else {
+            return Err(HyperError::InvalidParam);
+        };
+        if entry.get_bit(MASKED) {
+            return Ok(());
+        }
+        let vector = entry.get_bits(VECTOR) as u8;
+        // Physical destination mode assumed; logical destination mode isn't
+        // modeled, matching the simplification `lapic::destination_cpus`
+        // already makes for a no-shorthand ICR write.
+        let dest = entry.get_bits(DEST_FIELD) as usize;
+        lapic::deliver_vector_to(dest, vector);
+        Ok(())
+    }
+
+    /// Assert GSI `gsi`, the level-triggered counterpart to [`Self::trigger`]:
+    /// latches [`Self::asserted`] so a later resample can tell the line is
+    /// still held high, and - unless the entry is level-triggered with a
+    /// delivery already awaiting the guest's EOI (remote IRR set) - delivers
+    /// it through the same LAPIC path and, for level-triggered entries, sets
+    /// remote IRR so a second raise before the EOI doesn't post a second
+    /// vector.
+    pub fn raise_gsi(&mut self, gsi: u8) -> HyperResult {
+        let Some(&entry) = self.redir_table.get(gsi as usize) else {
+            return Err(HyperError::InvalidParam);
+        };
+        let Some(asserted) = self.asserted.get_mut(gsi as usize) else {
+            return Err(HyperError::InvalidParam);
+        };
+        *asserted = true;
+        if entry.get_bit(MASKED) {
+            return Ok(());
+        }
+        if entry.get_bit(TRIGGER_MODE) && entry.get_bit(REMOTE_IRR) {
+            return Ok(());
+        }
+        let vector = entry.get_bits(VECTOR) as u8;
+        let dest = entry.get_bits(DEST_FIELD) as usize;
+        lapic::deliver_vector_to(dest, vector);
+        if entry.get_bit(TRIGGER_MODE) {
+            self.redir_table[gsi as usize].set_bit(REMOTE_IRR, true);
+        }
+        Ok(())
+    }
+
+    /// Deassert GSI `gsi`: clears the device-side latch [`Self::raise_gsi`]
+    /// set. Doesn't touch remote IRR, since only the guest's EOI (see
+    /// [`Self::handle_eoi`]) is allowed to clear that.
+    pub fn lower_gsi(&mut self, gsi: u8) {
+        if let Some(asserted) = self.asserted.get_mut(gsi as usize) {
+            *asserted = false;
+        }
+    }
+
+    /// Called when the guest EOIs `vector`: clears remote IRR on every
+    /// level-triggered entry that was last delivered on it, and returns
+    /// their GSIs so the caller can resample whatever's driving them and
+    /// re-assert if the line is still held high.
+    pub fn handle_eoi(&mut self, vector: u8) -> Vec<u8> {
+        let mut cleared = Vec::new();
+        for (gsi, entry) in self.redir_table.iter_mut().enumerate() {
+            if entry.get_bit(TRIGGER_MODE)
+                && entry.get_bit(REMOTE_IRR)
+                && entry.get_bits(VECTOR) as u8 == vector
+            {
+                entry.set_bit(REMOTE_IRR, false);
+                cleared.push(gsi as u8);
+            }
+        }
+        cleared
+    }
+}
+
+impl MmioDevice for IoApic {
+    fn mmio_range(&self) -> core::ops::Range<u64> {
+        self.base..self.base + 0x20
+    }
+
+    fn read(&mut self, addr: u64, _access_size: u8) -> HyperResult<u64> {
+        match addr - self.base {
+            0x00 => Ok(self.ioregsel as u64),
+            0x10 => Ok(self.read_register(self.ioregsel) as u64),
+            _ => Err(HyperError::InvalidParam),
+        }
+    }
+
+    fn write(&mut self, addr: u64, _access_size: u8, value: u64) -> HyperResult {
+        match addr - self.base {
+            0x00 => self.ioregsel = value as u32,
+            0x10 => self.write_register(self.ioregsel, value as u32),
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+}
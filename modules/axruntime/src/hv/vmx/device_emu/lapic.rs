@@ -17,6 +17,134 @@ use crate::hv::vmx::HV_VIRT_IPI;
 
 pub static BOOT_VEC: AtomicBool = AtomicBool::new(false);
 
+/// Destination shorthand field of the ICR (SDM Vol. 3A, Section 10.6.1, Table 10-6).
+const DEST_SHORTHAND_NONE: u64 = 0b00;
+const DEST_SHORTHAND_SELF: u64 = 0b01;
+const DEST_SHORTHAND_ALL_INCL_SELF: u64 = 0b10;
+const DEST_SHORTHAND_ALL_EXCL_SELF: u64 = 0b11;
+
+/// Each physical CPU's emulated LAPIC IRR: a 256-bit pending-vector bitmap
+/// a target re-checks on its next VM entry to find the highest-priority
+/// vector to inject, gated by EOI and the SIVR software-enable bit.
+static PENDING_IRR: Once<Vec<Mutex<[u64; 4]>>> = Once::new();
+
+fn pending_irr() -> &'static Vec<Mutex<[u64; 4]>> {
+    PENDING_IRR.call_once(|| (0..SMP).map(|_| Mutex::new([0u64; 4])).collect())
+}
+
+/// Set `vector`'s bit in `dest`'s IRR and return whether it was newly set.
+fn post_vector(dest: usize, vector: u8) -> bool {
+    let mut irr = pending_irr()[dest].lock();
+    let word = (vector / 64) as usize;
+    let bit = vector % 64;
+    let mask = 1u64 << bit;
+    let was_set = irr[word] & mask != 0;
+    irr[word] |= mask;
+    !was_set
+}
+
+/// Software-enable bit (bit 8) of the Spurious Interrupt Vector Register
+/// (SDM Vol. 3A, Section 10.9, Figure 10-23). While clear, the LAPIC
+/// doesn't deliver any pending IRR vector, same as real hardware.
+const SIVR_SOFTWARE_ENABLE: u32 = 1 << 8;
+
+/// The LAPIC register file state that isn't already owned by `apic_timer`
+/// (LVT timer/initial-count/divide/current-count, tracked on `VCpu`
+/// itself): APIC ID's CPU-local default, LDR, SIVR, and the in-service
+/// bitmap EOI drains from. One per physical CPU, same model as
+/// [`PENDING_IRR`] — there's one vCPU per pCPU in this hypervisor, so
+/// `axhal::current_cpu_id()` doubles as the vCPU index.
+struct LapicState {
+    ldr: u32,
+    sivr: u32,
+    /// Last value written to the ICR, so a subsequent read-back (legal in
+    /// x2APIC mode, unlike EOI) returns what was last sent.
+    icr: u64,
+    isr: [u64; 4],
+}
+
+impl Default for LapicState {
+    fn default() -> Self {
+        Self {
+            ldr: 0,
+            sivr: 0x1ff, // software-enabled, spurious vector 0xff
+            icr: 0,
+            isr: [0; 4],
+        }
+    }
+}
+
+static LAPIC_STATE: Once<Vec<Mutex<LapicState>>> = Once::new();
+
+fn lapic_state() -> &'static Vec<Mutex<LapicState>> {
+    LAPIC_STATE.call_once(|| (0..SMP).map(|_| Mutex::new(LapicState::default())).collect())
+}
+
+/// Clear and return the highest-priority pending vector for `cpu`, moving it
+/// from the IRR to the ISR, unless the LAPIC is software-disabled (SIVR's
+/// software-enable bit clear).
+pub fn pop_highest_pending_vector(cpu: usize) -> Option<u8> {
+    if lapic_state()[cpu].lock().sivr & SIVR_SOFTWARE_ENABLE == 0 {
+        return None;
+    }
+    let mut irr = pending_irr()[cpu].lock();
+    for word in (0..4).rev() {
+        if irr[word] != 0 {
+            let bit = 63 - irr[word].leading_zeros();
+            irr[word] &= !(1u64 << bit);
+            let vector = word as u8 * 64 + bit as u8;
+            let mut state = lapic_state()[cpu].lock();
+            state.isr[word] |= 1u64 << bit;
+            return Some(vector);
+        }
+    }
+    None
+}
+
+/// Resolve the ICR's destination shorthand/field into the set of target
+/// physical CPU ids.
+fn destination_cpus(icr: &Icr, self_cpu: usize) -> Vec<usize> {
+    match icr.destination_shorthand() {
+        DEST_SHORTHAND_SELF => vec![self_cpu],
+        DEST_SHORTHAND_ALL_INCL_SELF => (0..SMP).collect(),
+        DEST_SHORTHAND_ALL_EXCL_SELF => (0..SMP).filter(|&c| c != self_cpu).collect(),
+        _ => {
+            // DEST_SHORTHAND_NONE: physical destination mode assumed (logical
+            // destination mode isn't modeled); the destination field maps
+            // 1:1 onto a physical CPU id in this flat topology.
+            let dest = icr.destination_field() as usize;
+            if dest < SMP { vec![dest] } else { vec![] }
+        }
+    }
+}
+
+/// Push `vector` into `dest`'s IRR and kick it so it re-evaluates pending
+/// interrupts on its next VM entry. Shared by [`deliver_fixed`] (ICR writes)
+/// and the IOAPIC (redirection-table entries), both of which resolve a
+/// vector/destination pair of their own and just need it injected.
+pub(crate) fn deliver_vector_to(dest: usize, vector: u8) {
+    if post_vector(dest, vector) {
+        send_message(Message {
+            dest,
+            signal: Signal::Interrupt,
+            args: vec![vector as usize],
+        });
+        if dest != axhal::current_cpu_id() {
+            axhal::mp::send_ipi_one(dest, HV_VIRT_IPI as u8);
+        }
+    }
+}
+
+/// Push `vector` into each target's IRR and kick it so it re-evaluates
+/// pending interrupts on its next VM entry.
+fn deliver_fixed(icr: &Icr) -> HyperResult {
+    let self_cpu = axhal::current_cpu_id();
+    for dest in destination_cpus(icr, self_cpu) {
+        deliver_vector_to(dest, icr.vector() as u8);
+    }
+    Ok(())
+}
+
 
 // fn init_boot_vec(smp: usize) {
 //     BOOT_VEC.call_once(|| {
@@ -41,6 +169,10 @@ const EOI: u32 = 0xB;
 const LDR: u32 = 0xD;
 /// Spurious Interrupt Vector register.
 const SIVR: u32 = 0xF;
+/// In-Service Register, 8 32-bit slices of a 256-bit bitmap (ISR0..ISR7).
+const ISR_BASE: u32 = 0x10;
+/// Interrupt Request Register, 8 32-bit slices of a 256-bit bitmap (IRR0..IRR7).
+const IRR_BASE: u32 = 0x20;
 /// Interrupt Command register.
 const ICR: u32 = 0x30;
 /// LVT Timer Interrupt register.
@@ -82,7 +214,13 @@ impl VirtLocalApic {
     fn read(VCpu: &mut VCpu, offset: u32) -> HyperResult<u64> {
         let apic_timer = VCpu.apic_timer_mut();
         match offset {
-            SIVR => Ok(0x1ff), // SDM Vol. 3A, Section 10.9, Figure 10-23 (with Software Enable bit)
+            // In this flat, one-vCPU-per-pCPU topology the x2APIC ID is just
+            // the physical CPU id, matching the destination field
+            // `destination_cpus` already assumes.
+            APICID => Ok(axhal::current_cpu_id() as u64),
+            LDR => Ok(lapic_state()[axhal::current_cpu_id()].lock().ldr as u64),
+            SIVR => Ok(lapic_state()[axhal::current_cpu_id()].lock().sivr as u64),
+            ICR => Ok(lapic_state()[axhal::current_cpu_id()].lock().icr),
             LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
                 Ok(0x1_0000) // SDM Vol. 3A, Section 10.5.1, Figure 10-8 (with Mask bit)
             }
@@ -90,6 +228,16 @@ impl VirtLocalApic {
             INIT_COUNT => Ok(apic_timer.initial_count() as u64),
             DIV_CONF => Ok(apic_timer.divide() as u64),
             CUR_COUNT => Ok(apic_timer.current_counter() as u64),
+            reg if (ISR_BASE..ISR_BASE + 8).contains(&reg) => {
+                let state = lapic_state()[axhal::current_cpu_id()].lock();
+                Ok(read_bitmap_slice(&state.isr, reg - ISR_BASE))
+            }
+            reg if (IRR_BASE..IRR_BASE + 8).contains(&reg) => {
+                let irr = pending_irr()[axhal::current_cpu_id()].lock();
+                Ok(read_bitmap_slice(&irr, reg - IRR_BASE))
+            }
+            // EOI is write-only in x2APIC mode; reading it causes #GP (SDM
+            // Vol. 3A, Section 10.12.1.2, Table 10-6).
             _ => Err(HyperError::NotSupported),
         }
     }
@@ -104,13 +252,44 @@ impl VirtLocalApic {
                 if value != 0 {
                     Err(HyperError::InvalidParam) // write a non-zero value causes #GP
                 } else {
+                    // Clear the highest-set ISR bit and, if it belonged to a
+                    // level-triggered GSI (SDM Vol. 3A, Section 10.8.5),
+                    // resample whatever's driving it through
+                    // `super::handle_eoi` - that's what lets a source like
+                    // `super::PIT_IRQ0` re-assert instead of going quiet
+                    // just because the guest finally got around to EOI-ing.
+                    let mut state = lapic_state()[axhal::current_cpu_id()].lock();
+                    let mut cleared_vector = None;
+                    for word in (0..4).rev() {
+                        if state.isr[word] != 0 {
+                            let bit = 63 - state.isr[word].leading_zeros();
+                            state.isr[word] &= !(1u64 << bit);
+                            cleared_vector = Some(word as u8 * 64 + bit as u8);
+                            break;
+                        }
+                    }
+                    drop(state);
+                    if let Some(vector) = cleared_vector {
+                        super::handle_eoi(vector);
+                    }
                     Ok(())
                 }
             }
-            SIVR | LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
+            LDR => {
+                lapic_state()[axhal::current_cpu_id()].lock().ldr = value as u32;
+                Ok(())
+            }
+            SIVR => {
+                lapic_state()[axhal::current_cpu_id()].lock().sivr = value as u32;
+                Ok(())
+            }
+            LVT_THERMAL | LVT_PMI | LVT_LINT0 | LVT_LINT1 | LVT_ERR => {
                 Ok(()) // ignore these register writes
             }
-            ICR => send_ipi(value), // FIXME:
+            ICR => {
+                lapic_state()[axhal::current_cpu_id()].lock().icr = value;
+                send_ipi(value)
+            }
             LVT_TIMER => apic_timer.set_lvt_timer(value as u32),
             INIT_COUNT => apic_timer.set_initial_count(value as u32),
             DIV_CONF => apic_timer.set_divide(value as u32),
@@ -119,6 +298,14 @@ impl VirtLocalApic {
     }
 }
 
+/// Read the 32-bit slice at `index` (0..8) out of a 256-bit bitmap packed as
+/// 4 `u64` words, as ISR/IRR's register interface exposes it.
+fn read_bitmap_slice(bitmap: &[u64; 4], index: u32) -> u64 {
+    let word = (index / 2) as usize;
+    let shift = (index % 2) * 32;
+    (bitmap[word] >> shift) & 0xffff_ffff
+}
+
 fn send_ipi(value: u64) -> HyperResult {
     unsafe {
         let icr = Icr(value);
@@ -126,10 +313,27 @@ fn send_ipi(value: u64) -> HyperResult {
         debug!("in icr value:  {:X}H", value);
         let mode = DeliveryMode::try_from(icr.delivery_mode()).unwrap();
         match mode {
-            DeliveryMode::Fixed => todo!(),
-            DeliveryMode::LowPriority => todo!(),
-            DeliveryMode::SMI => todo!(),
-            DeliveryMode::NMI => todo!(),
+            DeliveryMode::Fixed => deliver_fixed(&icr),
+            // No real arbitration bus to pick a least-busy target; reuse the
+            // Fixed path against the same resolved destination set.
+            DeliveryMode::LowPriority => deliver_fixed(&icr),
+            // No SMM to enter; acknowledge the ICR write instead of trapping
+            // the guest into an unimplemented panic.
+            DeliveryMode::SMI => Ok(()),
+            DeliveryMode::NMI => {
+                let self_cpu = axhal::current_cpu_id();
+                for dest in destination_cpus(&icr, self_cpu) {
+                    send_message(Message {
+                        dest,
+                        signal: Signal::Nmi,
+                        args: vec![],
+                    });
+                    if dest != self_cpu {
+                        axhal::mp::send_ipi_one(dest, HV_VIRT_IPI as u8);
+                    }
+                }
+                Ok(())
+            }
             DeliveryMode::INIT => {
                 debug!("ignore INIT IPI");
                 Ok(())
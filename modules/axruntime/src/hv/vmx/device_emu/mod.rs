@@ -2,6 +2,7 @@ mod bundle;
 mod debug_port;
 mod dummy;
 mod i8259_pic;
+mod ioapic;
 mod lapic;
 mod pci;
 mod pcip;
@@ -9,21 +10,102 @@ mod pit;
 mod uart16550;
 
 extern crate alloc;
-use alloc::{sync::Arc, vec, vec::Vec};
+use alloc::{boxed::Box, sync::Arc, vec, vec::Vec};
 use spin::Mutex;
 use hypercraft::HyperResult;
 
 use self::bundle::Bundle;
+pub use self::ioapic::IoApic;
 pub use self::lapic::VirtLocalApic;
 
+/// Standard x86 IOAPIC MMIO base (ref: ACPI MADT "I/O APIC Address").
+const IOAPIC_BASE: u64 = 0xfec0_0000;
+
+/// A level-triggered interrupt line that must be re-asserted until the guest
+/// observes and EOIs it, rather than delivered as a one-shot edge.
+///
+/// A device (PIT channel 0, an RTC periodic interrupt, ...) calls
+/// [`Self::trigger`] whenever its output goes high; [`handle_eoi`] calls back
+/// into [`Self::wait_resample`] for every line whose GSI the IOAPIC just
+/// cleared remote IRR for, so the device can decide whether to re-assert it.
+pub struct IrqLine {
+    gsi: u8,
+    resample: Box<dyn Fn() -> bool + Send>,
+}
+
+impl IrqLine {
+    fn new(gsi: u8, resample: impl Fn() -> bool + Send + 'static) -> Self {
+        Self {
+            gsi,
+            resample: Box::new(resample),
+        }
+    }
+
+    pub fn gsi(&self) -> u8 {
+        self.gsi
+    }
+
+    /// Raise the line: hand it to the IOAPIC for delivery, which latches it
+    /// asserted and either posts the vector now or, if a level-triggered
+    /// delivery is already awaiting EOI, leaves it pending.
+    pub fn trigger(&self) -> HyperResult {
+        IOAPIC.lock().raise_gsi(self.gsi)
+    }
+
+    /// Invoked by [`handle_eoi`] once the guest acks the vector this line
+    /// was last delivered on: re-assert if the device still wants the line
+    /// high, otherwise let it go quiet.
+    fn wait_resample(&self) -> HyperResult {
+        if (self.resample)() {
+            self.trigger()
+        } else {
+            IOAPIC.lock().lower_gsi(self.gsi);
+            Ok(())
+        }
+    }
+}
+
+/// Register a level-triggered interrupt source on IOAPIC input pin `gsi`.
+/// `resample` is polled from [`handle_eoi`] whenever the guest EOIs the
+/// vector this line was last delivered on, to decide whether the device
+/// still wants it held high.
+pub fn register_level_irq(gsi: u8, resample: impl Fn() -> bool + Send + 'static) -> Arc<IrqLine> {
+    let line = Arc::new(IrqLine::new(gsi, resample));
+    LEVEL_LINES.lock().push(line.clone());
+    line
+}
+
+/// Called from the LAPIC's EOI handler with the vector the guest just
+/// acknowledged: resamples every [`IrqLine`] registered on a GSI the IOAPIC
+/// cleared remote IRR for as a result.
+pub(crate) fn handle_eoi(vector: u8) {
+    let gsis = IOAPIC.lock().handle_eoi(vector);
+    if gsis.is_empty() {
+        return;
+    }
+    for line in LEVEL_LINES.lock().iter() {
+        if gsis.contains(&line.gsi()) {
+            let _ = line.wait_resample();
+        }
+    }
+}
+
 pub trait PortIoDevice: Send + Sync {
     fn port_range(&self) -> core::ops::Range<u16>;
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32>;
     fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult;
 }
 
+/// A memory-mapped device, the MMIO counterpart of [`PortIoDevice`].
+pub trait MmioDevice: Send + Sync {
+    fn mmio_range(&self) -> core::ops::Range<u64>;
+    fn read(&mut self, addr: u64, access_size: u8) -> HyperResult<u64>;
+    fn write(&mut self, addr: u64, access_size: u8, value: u64) -> HyperResult;
+}
+
 pub struct VirtDeviceList {
     port_io_devices: Vec<Arc<Mutex<dyn PortIoDevice>>>,
+    memory_io_devices: Vec<Arc<Mutex<dyn MmioDevice>>>,
 }
 
 impl VirtDeviceList {
@@ -32,11 +114,32 @@ impl VirtDeviceList {
             .iter()
             .find(|dev| dev.lock().port_range().contains(&port))
     }
+
+    pub fn find_memory_io_device(&self, addr: u64) -> Option<&Arc<Mutex<dyn MmioDevice>>> {
+        self.memory_io_devices
+            .iter()
+            .find(|dev| dev.lock().mmio_range().contains(&addr))
+    }
 }
 
 lazy_static::lazy_static! {
     static ref BUNDLE: Arc<Mutex<Bundle>> = Arc::new(Mutex::new(Bundle::new()));
 
+    /// Named separately from [`VIRT_DEVICES`] (rather than buried in its
+    /// `memory_io_devices` vec) so [`IrqLine::trigger`] and [`handle_eoi`]
+    /// can reach it without downcasting out of `dyn MmioDevice`.
+    static ref IOAPIC: Arc<Mutex<IoApic>> = Arc::new(Mutex::new(IoApic::new(IOAPIC_BASE)));
+
+    /// Every [`IrqLine`] registered via [`register_level_irq`], resampled by
+    /// [`handle_eoi`].
+    static ref LEVEL_LINES: Mutex<Vec<Arc<IrqLine>>> = Mutex::new(Vec::new());
+
+    /// `Bundle`'s PIT channel 0, IOAPIC input pin 0 (PC/AT IRQ0 wiring).
+    static ref PIT_IRQ0: Arc<IrqLine> = {
+        let bundle = BUNDLE.clone();
+        register_level_irq(0, move || bundle.lock().pit_channel0_output())
+    };
+
     static ref VIRT_DEVICES : VirtDeviceList = VirtDeviceList {
         port_io_devices: vec![
             Arc::new(Mutex::new(uart16550::Uart16550::new(0x3f8))), // COM1
@@ -63,6 +166,9 @@ lazy_static::lazy_static! {
             // Arc::new(Mutex::new(pci::PCIConfigurationSpace::new(0xcf8))),
             Arc::new(Mutex::new(pcip::PCIPassthrough::new(0xcf8))),
         ],
+        memory_io_devices: vec![
+            IOAPIC.clone() as Arc<Mutex<dyn MmioDevice>>, // I/O APIC
+        ],
     };
 }
 
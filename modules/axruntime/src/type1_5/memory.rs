@@ -1,8 +1,8 @@
 use axhal::consts::{free_memory_start, hv_end};
 
-use axhal::mem::{memory_regions, phys_to_virt, MemRegionFlags};
+use axhal::mem::{memory_regions, phys_to_virt};
 use axhal::paging::PageTable;
-use memory_addr::VirtAddr;
+use memory_addr::{PhysAddr, VirtAddr};
 // use page_table_entry::MappingFlags;
 
 use spin::{Once, RwLock};
@@ -31,6 +31,19 @@ pub fn activate_hv_pt() {
     unsafe { axhal::arch::write_page_table_root(page_table.read().root_paddr()) };
 }
 
+/// Minimum base/size alignment a region needs before `map_region` is
+/// allowed to back it with a 2MiB/1GiB huge page instead of 4K leaves.
+/// Keeping this conservative (2MiB) means a region doesn't need to be
+/// GiB-aligned just to save some TLB entries; `map_region` itself picks
+/// the largest page size the alignment actually supports.
+const HUGE_PAGE_ALIGN: usize = 0x20_0000;
+
+fn allow_huge_pages(vaddr: VirtAddr, paddr: PhysAddr, size: usize) -> bool {
+    vaddr.as_usize() % HUGE_PAGE_ALIGN == 0
+        && paddr.as_usize() % HUGE_PAGE_ALIGN == 0
+        && size % HUGE_PAGE_ALIGN == 0
+}
+
 pub fn init_hv_page_table() -> Result<(), axhal::paging::PagingError> {
     info!("Found physcial memory regions:");
     for r in memory_regions() {
@@ -45,50 +58,21 @@ pub fn init_hv_page_table() -> Result<(), axhal::paging::PagingError> {
 
     let mut page_table = PageTable::try_new().unwrap();
 
-    for (i, r) in memory_regions().enumerate() {
-        if i == 0 || i == 1 {
-            info!(
-                "  [{:x?}, {:x?}) {} ({:?})",
-                r.paddr,
-                r.paddr + r.size,
-                r.name,
-                r.flags
-            );
-            page_table.map_region(
-                phys_to_virt(r.paddr),
-                r.paddr,
-                r.size,
-                r.flags.into(),
-                false,
-            );
-        } else {
-            // let flags = r.flags;
-
-            // if r.flags.contains(MemRegionFlags::DMA) {
-            let hv_virt_start = phys_to_virt(r.paddr);
-            if hv_virt_start < VirtAddr::from(r.paddr.as_usize()) {
-                let virt_start = r.paddr;
-                panic!("Guest physical address {:#x} is too large", virt_start);
-            }
-            // info!(
-            //     "  [{:x?}, {:x?}) {} ({:?})",
-            //     r.paddr,
-            //     r.paddr + r.size,
-            //     r.name,
-            //     r.flags
-            // );
-            page_table.map_region(
-                phys_to_virt(r.paddr),
-                r.paddr,
-                r.size,
-                r.flags.into(),
-                false,
-            );
-            // }
+    for r in memory_regions() {
+        let hv_virt_start = phys_to_virt(r.paddr);
+        if hv_virt_start < VirtAddr::from(r.paddr.as_usize()) {
+            panic!("Guest physical address {:#x} is too large", r.paddr);
         }
+
+        // `r.flags` carries the region's cacheability (e.g. `DEVICE` for
+        // MMIO/DMA windows) straight through `MemRegionFlags::into()`, so
+        // huge pages are only attempted where alignment allows -- an
+        // uncached MMIO region that isn't huge-page aligned still gets
+        // mapped correctly, just with 4K leaves.
+        let huge = allow_huge_pages(hv_virt_start, r.paddr, r.size);
+        page_table.map_region(hv_virt_start, r.paddr, r.size, r.flags.into(), huge);
     }
     info!("Hypervisor page table init end.");
-    // info!("Hypervisor virtual memory set: {:#x?}", page_table);
 
     HV_PT.call_once(|| RwLock::new(page_table));
     Ok(())
@@ -0,0 +1,336 @@
+//! Minimal ACPI tables describing the emulated platform to the guest, so it
+//! can discover the COM ports, the two cascaded i8259 PICs and the LAPIC
+//! without legacy port probing.
+//!
+//! Only the fields a guest actually needs are filled in: the FADT stops at
+//! the ACPI 2.0-era fields (no hardware-reduced/sleep-control extensions)
+//! and the MADT carries just the LAPIC and dual-PIC entries. [`build_tables`]
+//! lays out RSDP -> XSDT -> MADT -> FADT as a single contiguous blob that the
+//! caller stages into guest memory with [`map_tables`]; [`crate::hv::vm::VirtMach::new`]
+//! calls it for every VM at the fixed, low-memory `ACPI_TABLES_GPA` real
+//! BIOSes reserve for their own tables.
+//!
+//! There is still no DSDT: an AML encoder is its own chunk of work this
+//! doesn't pull in, so a Linux/UEFI guest can enumerate vCPUs and the IOAPIC
+//! off the MADT above, but not yet walk a `\_SB` device tree for the UARTs/
+//! RTC/PCI root bus - those remain legacy-probed.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use axhal::mem::{virt_to_phys, HostVirtAddr};
+use hypercraft::{GuestPhysAddr, HyperResult};
+use page_table_entry::MappingFlags;
+use pci::util::byte_code::ByteCode;
+use crate::hv::mm::{GuestMemoryRegion, GuestPhysMemorySet};
+use crate::utils::CpuSet;
+
+const OEM_ID: [u8; 6] = *b"ARCEOS";
+const OEM_TABLE_ID: [u8; 8] = *b"ARCEOSVM";
+const CREATOR_ID: [u8; 4] = *b"ARCO";
+
+/// Guest-physical address of the emulated I/O APIC's MMIO window, matching
+/// the platform-standard placement used by real PC firmware.
+const IO_APIC_ADDRESS: u32 = 0xfec0_0000;
+/// Shutdown device's I/O port (see `device_emu::shutdown::Shutdown`).
+const RESET_PORT: u64 = 0x604;
+
+/// Recompute and write a table's checksum so its bytes sum to zero mod 256.
+fn fix_checksum(bytes: &mut [u8], checksum_offset: usize) {
+    bytes[checksum_offset] = 0;
+    let sum = bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    bytes[checksum_offset] = 0u8.wrapping_sub(sum);
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+impl ByteCode for Rsdp {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: [u8; 4],
+    creator_revision: u32,
+}
+impl ByteCode for SdtHeader {}
+
+fn sdt_header(signature: &[u8; 4], length: u32, revision: u8) -> SdtHeader {
+    SdtHeader {
+        signature: *signature,
+        length,
+        revision,
+        checksum: 0,
+        oem_id: OEM_ID,
+        oem_table_id: OEM_TABLE_ID,
+        oem_revision: 1,
+        creator_id: CREATOR_ID,
+        creator_revision: 1,
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtLocalApic {
+    entry_type: u8,
+    length: u8,
+    acpi_processor_id: u8,
+    apic_id: u8,
+    flags: u32,
+}
+impl ByteCode for MadtLocalApic {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtIoApic {
+    entry_type: u8,
+    length: u8,
+    io_apic_id: u8,
+    reserved: u8,
+    io_apic_address: u32,
+    global_system_interrupt_base: u32,
+}
+impl ByteCode for MadtIoApic {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtInterruptSourceOverride {
+    entry_type: u8,
+    length: u8,
+    bus: u8,
+    source: u8,
+    global_system_interrupt: u32,
+    flags: u16,
+}
+impl ByteCode for MadtInterruptSourceOverride {}
+
+/// Build the MADT: header, one Processor Local APIC entry per bit set in
+/// `cpu_set`, then the I/O APIC and the legacy-IRQ0 interrupt source
+/// override that together describe the two cascaded i8259 PICs' GSI wiring.
+fn build_madt(cpu_set: &CpuSet) -> Vec<u8> {
+    const LOCAL_APIC_ADDRESS: u32 = 0xfee0_0000;
+    /// PCAT_COMPAT: dual-8259 PICs are present and must be disabled by the OS.
+    const PCAT_COMPAT: u32 = 1 << 0;
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&LOCAL_APIC_ADDRESS.to_ne_bytes());
+    body.extend_from_slice(&PCAT_COMPAT.to_ne_bytes());
+
+    for cpu_id in cpu_set.iter() {
+        let entry = MadtLocalApic {
+            entry_type: 0,
+            length: core::mem::size_of::<MadtLocalApic>() as u8,
+            acpi_processor_id: cpu_id as u8,
+            apic_id: cpu_id as u8,
+            flags: 1, // enabled
+        };
+        body.extend_from_slice(entry.as_bytes());
+    }
+
+    let io_apic = MadtIoApic {
+        entry_type: 1,
+        length: core::mem::size_of::<MadtIoApic>() as u8,
+        io_apic_id: 0,
+        reserved: 0,
+        io_apic_address: IO_APIC_ADDRESS,
+        global_system_interrupt_base: 0,
+    };
+    body.extend_from_slice(io_apic.as_bytes());
+
+    // Legacy IRQ0 (PIT, cascaded through the master i8259) is wired to GSI 2
+    // on the I/O APIC, as on real PC-compatible platforms.
+    let iso = MadtInterruptSourceOverride {
+        entry_type: 2,
+        length: core::mem::size_of::<MadtInterruptSourceOverride>() as u8,
+        bus: 0,
+        source: 0,
+        global_system_interrupt: 2,
+        flags: 0,
+    };
+    body.extend_from_slice(iso.as_bytes());
+
+    let header = sdt_header(b"APIC", (core::mem::size_of::<SdtHeader>() + body.len()) as u32, 3);
+    let mut table = header.as_bytes().to_vec();
+    table.extend_from_slice(&body);
+    fix_checksum(&mut table, core::mem::offset_of!(SdtHeader, checksum));
+    table
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct GenericAddress {
+    address_space_id: u8,
+    register_bit_width: u8,
+    register_bit_offset: u8,
+    access_size: u8,
+    address: u64,
+}
+impl ByteCode for GenericAddress {}
+
+/// System I/O space, per the ACPI Generic Address Structure definition.
+const ADDRESS_SPACE_SYSTEM_IO: u8 = 1;
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct Fadt {
+    header: SdtHeader,
+    firmware_ctrl: u32,
+    dsdt: u32,
+    reserved0: u8,
+    preferred_pm_profile: u8,
+    sci_int: u16,
+    smi_cmd: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_req: u8,
+    pstate_cnt: u8,
+    pm1a_evt_blk: u32,
+    pm1b_evt_blk: u32,
+    pm1a_cnt_blk: u32,
+    pm1b_cnt_blk: u32,
+    pm2_cnt_blk: u32,
+    pm_tmr_blk: u32,
+    gpe0_blk: u32,
+    gpe1_blk: u32,
+    pm1_evt_len: u8,
+    pm1_cnt_len: u8,
+    pm2_cnt_len: u8,
+    pm_tmr_len: u8,
+    gpe0_blk_len: u8,
+    gpe1_blk_len: u8,
+    gpe1_base: u8,
+    cst_cnt: u8,
+    p_lvl2_lat: u16,
+    p_lvl3_lat: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alrm: u8,
+    mon_alrm: u8,
+    century: u8,
+    iapc_boot_arch: u16,
+    reserved1: u8,
+    flags: u32,
+    reset_reg: GenericAddress,
+    reset_value: u8,
+    arm_boot_arch: u16,
+    minor_version: u8,
+    x_firmware_ctrl: u64,
+    x_dsdt: u64,
+    x_pm1a_evt_blk: GenericAddress,
+    x_pm1b_evt_blk: GenericAddress,
+    x_pm1a_cnt_blk: GenericAddress,
+    x_pm1b_cnt_blk: GenericAddress,
+    x_pm2_cnt_blk: GenericAddress,
+    x_pm_tmr_blk: GenericAddress,
+    x_gpe0_blk: GenericAddress,
+    x_gpe1_blk: GenericAddress,
+}
+impl ByteCode for Fadt {}
+
+/// Build the FADT. No DSDT is provided (there is no AML interpreter on the
+/// guest side yet), so `dsdt`/`x_dsdt` are left zero; the only field guests
+/// actually consult here is `reset_reg`/`reset_value`, which routes an
+/// ACPI reboot straight at the emulated `Shutdown` device on port 0x604.
+fn build_fadt() -> Vec<u8> {
+    let mut fadt = Fadt {
+        header: sdt_header(b"FACP", core::mem::size_of::<Fadt>() as u32, 3),
+        reset_reg: GenericAddress {
+            address_space_id: ADDRESS_SPACE_SYSTEM_IO,
+            register_bit_width: 8,
+            register_bit_offset: 0,
+            access_size: 1,
+            address: RESET_PORT,
+        },
+        reset_value: 0x01,
+        flags: 1 << 10, // RESET_REG_SUP: RESET_REG/RESET_VALUE are valid.
+        ..Default::default()
+    };
+    let mut table = fadt.as_mut_bytes().to_vec();
+    fix_checksum(&mut table, core::mem::offset_of!(SdtHeader, checksum));
+    table
+}
+
+/// Build the full ACPI blob (RSDP, XSDT, MADT, FADT) describing the
+/// emulated platform, laid out contiguously starting at `gpa_base`.
+///
+/// `cpu_set` contributes one MADT Processor Local APIC entry per set bit.
+/// The checksum invariant (bytes of each table sum to zero mod 256) holds
+/// for every table in the returned blob.
+pub fn build_tables(cpu_set: &CpuSet, gpa_base: GuestPhysAddr) -> Vec<u8> {
+    let rsdp_len = core::mem::size_of::<Rsdp>();
+    let madt = build_madt(cpu_set);
+    let fadt = build_fadt();
+
+    let xsdt_gpa = gpa_base.as_usize() + rsdp_len;
+    let madt_gpa = xsdt_gpa + core::mem::size_of::<SdtHeader>() + 2 * core::mem::size_of::<u64>();
+    let fadt_gpa = madt_gpa + madt.len();
+
+    let xsdt_header = sdt_header(
+        b"XSDT",
+        (core::mem::size_of::<SdtHeader>() + 2 * core::mem::size_of::<u64>()) as u32,
+        1,
+    );
+    let mut xsdt = xsdt_header.as_bytes().to_vec();
+    xsdt.extend_from_slice(&(madt_gpa as u64).to_ne_bytes());
+    xsdt.extend_from_slice(&(fadt_gpa as u64).to_ne_bytes());
+    fix_checksum(&mut xsdt, core::mem::offset_of!(SdtHeader, checksum));
+
+    let mut rsdp = Rsdp {
+        signature: *b"RSD PTR ",
+        checksum: 0,
+        oem_id: OEM_ID,
+        revision: 2,
+        rsdt_address: 0,
+        length: rsdp_len as u32,
+        xsdt_address: xsdt_gpa as u64,
+        extended_checksum: 0,
+        reserved: [0; 3],
+    };
+    // The first checksum covers only the ACPI 1.0 portion (first 20 bytes);
+    // the extended one covers the whole ACPI 2.0+ structure.
+    fix_checksum(rsdp.as_mut_bytes(), core::mem::offset_of!(Rsdp, checksum));
+    fix_checksum(rsdp.as_mut_bytes(), core::mem::offset_of!(Rsdp, extended_checksum));
+
+    let mut blob = rsdp.as_bytes().to_vec();
+    blob.extend_from_slice(&xsdt);
+    blob.extend_from_slice(&madt);
+    blob.extend_from_slice(&fadt);
+    blob
+}
+
+/// Build the ACPI tables and map them into the guest's physical address
+/// space at `gpa_base` as a reserved, read-only region, so VM setup can call
+/// this once before handing control to the guest.
+pub fn map_tables(
+    gpm: &mut GuestPhysMemorySet,
+    cpu_set: &CpuSet,
+    gpa_base: GuestPhysAddr,
+) -> HyperResult {
+    let blob = build_tables(cpu_set, gpa_base).into_boxed_slice();
+    let blob: &'static mut [u8] = Box::leak(blob);
+    let region = GuestMemoryRegion {
+        gpa: gpa_base,
+        hpa: virt_to_phys((blob.as_ptr() as HostVirtAddr).into()).into(),
+        size: blob.len(),
+        flags: MappingFlags::READ,
+    };
+    gpm.map_region(region.into())
+}
@@ -8,9 +8,13 @@ pub mod vmx;
 pub mod vm;
 pub mod vcpu;
 pub mod mm;
+pub mod acpi;
+pub mod coredump;
 
 pub mod pcpu;
 pub mod gpm;
+pub mod timer;
+pub mod snapshot;
 
 pub mod prelude;
 
@@ -1,14 +1,19 @@
 //! notify cores
-use alloc::{collections::VecDeque, sync::Arc, vec, vec::Vec};
-use alloc::collections::LinkedList;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::collections::VecDeque;
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use spin::{Mutex, Once};
 use x86::current::vmx::vmclear;
-use x86::segmentation::ds;
 use crate::hv::vm::config::BSP_CPU_ID;
 
 pub const HV_MSG: usize = 233;
 
+/// Per-hart mailbox capacity. `send_message`/`broadcast_message` park
+/// (spin) rather than grow a hart's inbox past this, so a wedged or slow
+/// destination hart applies backpressure to its sender instead of letting
+/// the queue grow without bound.
+const MAILBOX_CAPACITY: usize = 32;
+
 /// global
 static HV_MSG_LISTS: Once<Mutex<MsgLists>> = Once::new();
 
@@ -26,63 +31,113 @@ pub fn receive_message(hart_id: usize) -> Option<Message> {
         .receive_message(hart_id)
 }
 
-/// send
+/// send, parking while the destination mailbox is full instead of
+/// growing it without bound
 pub fn send_message(msg: Message) {
-    HV_MSG_LISTS.get().unwrap().lock().send_message(msg);
+    loop {
+        match HV_MSG_LISTS.get().unwrap().lock().send_message(msg.clone()) {
+            Ok(()) => return,
+            Err(SendError::MailboxFull) => core::hint::spin_loop(),
+        }
+    }
 }
 
-/// broadcast
+/// broadcast, best-effort: a hart whose mailbox is already full just
+/// misses this round rather than blocking every other destination
 pub fn broadcast_message(msg: Message) {
     HV_MSG_LISTS.get().unwrap().lock().broadcast_message(msg)
 }
 
+/// Park waiting for the reply correlated with `msg.id`, marking this
+/// hart's dest slot parked for the duration so other code (and a future
+/// real parking primitive) can tell a hart blocked here from one that's
+/// merely idle.
 pub fn wait_on_reply(msg: &Message) -> bool {
-    !HV_MSG_LISTS.get().unwrap().lock().wait_reply(&msg)
+    let dest = msg.dest;
+    HV_MSG_LISTS.get().unwrap().lock().set_parked(dest, true);
+    let found = HV_MSG_LISTS.get().unwrap().lock().wait_reply(msg);
+    HV_MSG_LISTS.get().unwrap().lock().set_parked(dest, false);
+    !found
 }
 
+/// Whether `hart_id` is currently parked in [`wait_on_reply`].
+pub fn is_parked(hart_id: usize) -> bool {
+    HV_MSG_LISTS.get().unwrap().lock().is_parked(hart_id)
+}
+
+/// A mailbox is already at [`MAILBOX_CAPACITY`]; the sender should back off
+/// and retry instead of the queue growing unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    MailboxFull,
+}
 
 /// msgs
 #[derive(Debug)]
 pub struct MsgLists {
-    messages: Vec<LinkedList<Message>>,
+    messages: Vec<VecDeque<Message>>,
+    /// Set while the matching hart is parked in [`wait_on_reply`].
+    parked: Vec<AtomicBool>,
 }
 
 impl MsgLists {
     /// new
     pub fn new(cap: usize) -> Self {
-        let mut vec = Vec::with_capacity(cap);
+        let mut messages = Vec::with_capacity(cap);
+        let mut parked = Vec::with_capacity(cap);
         for _ in 0..cap {
-            vec.push(LinkedList::new());
-        }
-        Self {
-            messages: vec,
+            messages.push(VecDeque::new());
+            parked.push(AtomicBool::new(false));
         }
+        Self { messages, parked }
     }
     /// get_message
     pub fn receive_message(&mut self, hart_id: usize) -> Option<Message> {
         self.messages[hart_id].pop_front()
     }
-    /// push
-    pub fn send_message(&mut self, msg: Message) {
-        self.messages[msg.dest].push_back(msg);
+    /// push, rejecting once the destination mailbox is at capacity
+    pub fn send_message(&mut self, msg: Message) -> Result<(), SendError> {
+        let dest = msg.dest;
+        if self.messages[dest].len() >= MAILBOX_CAPACITY {
+            return Err(SendError::MailboxFull);
+        }
+        self.messages[dest].push_back(msg);
+        Ok(())
     }
     /// message's dest should be bsp
     pub fn broadcast_message(&mut self, msg: Message) {
-        for (i, que) in self.messages.iter_mut().enumerate() {
+        for (i, inbox) in self.messages.iter_mut().enumerate() {
             if i == BSP_CPU_ID {
                 continue;
             }
+            if inbox.len() >= MAILBOX_CAPACITY {
+                continue;
+            }
             let mut msg = msg.clone();
             msg.dest = i;
-            que.push_back(msg.clone());
+            inbox.push_back(msg);
         }
     }
 
-    /// assume only one
+    /// assume only one, correlated by `id` rather than full equality so a
+    /// reply can be matched even if its args differ from the request
     pub fn wait_reply(&mut self, expected: &Message) -> bool {
         let dst = expected.dest;
-        let x = self.messages[dst].extract_if(|m| m == expected).collect::<Vec<Message>>();
-        !x.is_empty()
+        match self.messages[dst].iter().position(|m| m.id == expected.id) {
+            Some(i) => {
+                self.messages[dst].remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_parked(&self, hart_id: usize, parked: bool) {
+        self.parked[hart_id].store(parked, Ordering::Release);
+    }
+
+    fn is_parked(&self, hart_id: usize) -> bool {
+        self.parked[hart_id].load(Ordering::Acquire)
     }
 }
 
@@ -142,7 +197,9 @@ pub fn hv_msg_handler(hart_id: usize) {
                 }
                 // reply
                 let reply = Message::new_reply(&msg);
-                guard.send_message(reply);
+                if guard.send_message(reply).is_err() {
+                    error!("{} reply mailbox to {} full, dropping reply", hart_id, msg.src);
+                }
             }
             _ => {
                 panic!("unknown msg");
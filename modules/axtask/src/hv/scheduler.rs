@@ -113,6 +113,8 @@ impl<const S: usize> BaseScheduler for HVScheduler<S> {
 
     /// for normal
     fn task_tick(&mut self, current: &Self::SchedItem) -> bool {
+        crate::hv::timer::on_scheduler_tick();
+        crate::hv::vm::check_watchdogs();
         let old_slice = current.time_slice.fetch_sub(1, Ordering::Release);
         old_slice <= 1
     }
@@ -132,6 +134,8 @@ impl<const S: usize> BaseScheduler for HVScheduler<S> {
 
 impl<const S: usize> HVScheduler<S> {
     pub fn vcpu_task_tick(&mut self, current: &Arc<HVTask<S>>) -> bool {
+        crate::hv::timer::on_scheduler_tick();
+        crate::hv::vm::check_watchdogs();
         if current.is_vcpu_task() {
             let old_slice = current.time_slice.fetch_sub(1, Ordering::Release);
             old_slice <= 1
@@ -0,0 +1,142 @@
+//! Architectural-state snapshot/restore for a [`VirtCpu`], the foundation for
+//! suspend/resume and later live migration. Pairs naturally with a parallel
+//! snapshot of the VM's guest-physical memory (see [`crate::hv::mm`]).
+//!
+//! [`VCpuState`] only covers per-vCPU architectural state; each [`VirtCpu`]'s
+//! emulated devices (beyond the local APIC timer already folded in below)
+//! live in its own [`crate::hv::vmx::X64VirtDevices`] and are captured
+//! separately via [`VirtCpu::save_devices`]/[`VirtCpu::restore_devices`],
+//! which just forward to its `DeviceList`'s own snapshot/restore.
+//! [`crate::hv::vm::VirtMach`]'s guest-physical memory itself is still
+//! something a full VM-level snapshot would need to capture separately -
+//! nothing here touches it.
+
+use hypercraft::{GeneralRegisters, VmCpuMode};
+
+use crate::hv::vcpu::VirtCpu;
+
+/// Current layout version of [`VCpuState`]; bump whenever a field is added,
+/// removed, or reordered so a loader can reject a snapshot it can't
+/// interpret instead of silently misreading it.
+pub const VCPU_STATE_VERSION: u32 = 2;
+
+/// Fixed-layout capture of everything a [`VirtCpu`] needs to resume
+/// execution: the GPR block, RIP/RSP/RFLAGS, CR0/CR3/CR4, the segment
+/// selectors, and the bits of the virtual local APIC that are actually
+/// mutable (`IA32_APIC_BASE` itself has no guest-owned state in this tree —
+/// see `handle_msr_write`, which just drops writes to it — so there is
+/// nothing to capture for it beyond the APIC timer below).
+///
+/// Deliberately doesn't yet capture the VMCS's pending event-injection
+/// field: `hypercraft::VCpu` only exposes `queue_event` to stage one, not a
+/// getter to read back whatever is already staged, so a snapshot taken
+/// mid-injection would drop it. Revisit once `hypercraft` grows one.
+///
+/// The segment selectors are likewise captured for inspection but can't yet
+/// be restored: [`crate::hv::vmx::gdb::Debuggable::gdb_write_reg`] already
+/// documents that `hypercraft` doesn't expose setters for them either.
+///
+/// Deliberately doesn't capture MSR state beyond the local APIC timer above:
+/// `handle_msr_read`/`handle_msr_write` either pass most MSRs straight
+/// through to the host MSR (no guest-owned copy exists to snapshot) or run
+/// them through `MSR_TABLE`'s per-MSR emulation, neither of which exposes a
+/// single readable blob of "the guest's MSR state" to capture generically.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VCpuState {
+    version: u32,
+    regs: GeneralRegisters,
+    rsp: u64,
+    rip: u64,
+    rflags: u64,
+    cr0: u64,
+    cr3: u64,
+    cr4: u64,
+    cs: u16,
+    ss: u16,
+    ds: u16,
+    es: u16,
+    fs: u16,
+    gs: u16,
+    apic_lvt_timer: u32,
+    apic_initial_count: u32,
+    apic_divide: u32,
+    cpu_mode: VmCpuMode,
+    nr_sipi: u8,
+    is_launched: bool,
+}
+
+impl VirtCpu {
+    /// Capture this vCPU's current architectural state.
+    pub fn save_state(&self) -> VCpuState {
+        let vcpu = self.vmx_vcpu_mut();
+        let segs = vcpu.segment_regs();
+        let apic_timer = vcpu.apic_timer_mut();
+        VCpuState {
+            version: VCPU_STATE_VERSION,
+            regs: *vcpu.regs(),
+            rsp: vcpu.rsp(),
+            rip: vcpu.rip(),
+            rflags: vcpu.rflags(),
+            cr0: vcpu.cr0(),
+            cr3: vcpu.cr3(),
+            cr4: vcpu.cr4(),
+            cs: segs.cs as u16,
+            ss: segs.ss as u16,
+            ds: segs.ds as u16,
+            es: segs.es as u16,
+            fs: segs.fs as u16,
+            gs: segs.gs as u16,
+            apic_lvt_timer: apic_timer.lvt_timer(),
+            apic_initial_count: apic_timer.initial_count(),
+            apic_divide: apic_timer.divide(),
+            cpu_mode: self.vcpu_mode(),
+            nr_sipi: self.sipi_num(),
+            is_launched: self.is_launched(),
+        }
+    }
+
+    /// Restore a snapshot previously produced by [`Self::save_state`].
+    ///
+    /// Returns [`hypercraft::HyperError::InvalidParam`] if `state` was
+    /// captured by an incompatible [`VCPU_STATE_VERSION`].
+    pub fn restore_state(&self, state: &VCpuState) -> hypercraft::HyperResult {
+        if state.version != VCPU_STATE_VERSION {
+            return Err(hypercraft::HyperError::InvalidParam);
+        }
+        let vcpu = self.vmx_vcpu_mut();
+        *vcpu.regs_mut() = state.regs;
+        vcpu.set_rsp(state.rsp)?;
+        vcpu.set_rip(state.rip)?;
+        vcpu.set_rflags(state.rflags)?;
+        vcpu.set_cr0(state.cr0);
+        vcpu.set_cr3(state.cr3);
+        vcpu.set_cr4(state.cr4);
+        // CS/SS/DS/ES/FS/GS: captured above but not restorable here, same gap
+        // `gdb_write_reg` has — there's no `hypercraft` setter for them yet.
+        let apic_timer = vcpu.apic_timer_mut();
+        apic_timer.set_lvt_timer(state.apic_lvt_timer);
+        apic_timer.set_initial_count(state.apic_initial_count);
+        apic_timer.set_divide(state.apic_divide);
+        vcpu.cpu_mode = state.cpu_mode;
+        self.set_sipi_num(state.nr_sipi);
+        self.set_launched(state.is_launched);
+        Ok(())
+    }
+
+    /// Bring this vCPU back from a snapshot taken by [`Self::save_state`]
+    /// when it hasn't launched since: re-runs `setup_vmcs` against this
+    /// vCPU's own entry point and EPT root (the same call
+    /// [`VirtCpu::prepare`] makes for a fresh vCPU coming out of
+    /// [`crate::hv::vcpu::VirtCpuState::Init`]) to give the VMCS a static
+    /// host/guest area to restore into, then applies `state` over it via
+    /// [`Self::restore_state`].
+    ///
+    /// A vCPU that has already launched keeps its VMCS across a restore and
+    /// should just call [`Self::restore_state`] directly instead.
+    pub fn restore_snapshot(&self, state: &VCpuState) -> hypercraft::HyperResult {
+        let (entry, ept_root) = self.entry_and_ept_root();
+        self.vmx_vcpu_mut().setup_vmcs(entry, ept_root)?;
+        self.restore_state(state)
+    }
+}
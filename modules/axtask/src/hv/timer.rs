@@ -0,0 +1,171 @@
+//! Per-pCPU virtual timer wheel.
+//!
+//! Guests that program the LAPIC timer or expect periodic ticks need a
+//! virtual time source beyond whatever the host itself is doing. This module
+//! lets any in-tree device (an emulated LAPIC timer, a PIT, ...) arm a
+//! one-shot or periodic deadline for a given `(vm_id, vcpu_id)`; expired
+//! deadlines are popped and their vector injected the next time the owning
+//! pCPU's scheduler ticks, via [`on_scheduler_tick`].
+//!
+//! Deadlines are tracked per-pCPU rather than globally, since a timer is
+//! always armed from within the vmexit path of the vCPU currently running on
+//! that pCPU, and `HVScheduler::task_tick`/`vcpu_task_tick` already run
+//! locally on every tick without needing a lock shared across cores. A vCPU
+//! that migrates to another pCPU after arming a timer there leaves it behind;
+//! that's a known limitation, not something this wheel tries to solve.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use axconfig::SMP;
+use axhal::cpu::this_cpu_id;
+use axhal::time::current_time_nanos;
+
+use crate::hv::vm::table_get_vm;
+
+/// Deadlines are rounded up to this granularity before being bucketed, to
+/// trade interrupt precision for fewer wheel pops per tick. Callers that need
+/// finer (or coarser) injection latency can override it with
+/// [`set_resolution`].
+pub const DEFAULT_RESOLUTION_NS: u64 = 1_000_000; // 1ms
+
+/// A handle to an armed timer, returned by [`arm`] and consumed by [`disarm`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerHandle(u64);
+
+struct TimerEntry {
+    id: u64,
+    vm_id: usize,
+    vcpu_id: usize,
+    vector: u8,
+    /// `Some(period)` re-arms the timer every `period` ns after it fires.
+    period_ns: Option<u64>,
+}
+
+struct TimerWheel {
+    resolution_ns: u64,
+    buckets: BTreeMap<u64, Vec<TimerEntry>>,
+    /// So `disarm` can find an entry's bucket without scanning the wheel.
+    index: BTreeMap<u64, u64>,
+}
+
+impl TimerWheel {
+    const fn new() -> Self {
+        Self {
+            resolution_ns: DEFAULT_RESOLUTION_NS,
+            buckets: BTreeMap::new(),
+            index: BTreeMap::new(),
+        }
+    }
+
+    fn round(&self, deadline_ns: u64) -> u64 {
+        let res = self.resolution_ns.max(1);
+        (deadline_ns + res - 1) / res * res
+    }
+
+    fn insert(&mut self, deadline_ns: u64, entry: TimerEntry) {
+        let bucket = self.round(deadline_ns);
+        self.index.insert(entry.id, bucket);
+        self.buckets.entry(bucket).or_default().push(entry);
+    }
+
+    fn remove(&mut self, id: u64) {
+        if let Some(bucket) = self.index.remove(&id) {
+            if let Some(entries) = self.buckets.get_mut(&bucket) {
+                entries.retain(|e| e.id != id);
+                if entries.is_empty() {
+                    self.buckets.remove(&bucket);
+                }
+            }
+        }
+    }
+
+    /// Pop every entry whose bucket has passed, re-arming periodic ones. If
+    /// several periods elapsed while nobody polled, they're coalesced into a
+    /// single injection and the next deadline is the smallest future
+    /// multiple of the period from the original deadline.
+    fn pop_expired(&mut self, now_ns: u64) -> Vec<(usize, usize, u8)> {
+        let mut fired = Vec::new();
+        let expired_buckets: Vec<u64> = self
+            .buckets
+            .range(..=now_ns)
+            .map(|(&deadline, _)| deadline)
+            .collect();
+
+        for bucket in expired_buckets {
+            let entries = self.buckets.remove(&bucket).unwrap_or_default();
+            for entry in entries {
+                fired.push((entry.vm_id, entry.vcpu_id, entry.vector));
+                if let Some(period) = entry.period_ns {
+                    let elapsed = now_ns.saturating_sub(bucket) + period;
+                    let missed_periods = elapsed / period;
+                    let next_deadline = bucket + missed_periods * period;
+                    self.insert(
+                        next_deadline,
+                        TimerEntry {
+                            id: entry.id,
+                            vm_id: entry.vm_id,
+                            vcpu_id: entry.vcpu_id,
+                            vector: entry.vector,
+                            period_ns: Some(period),
+                        },
+                    );
+                } else {
+                    self.index.remove(&entry.id);
+                }
+            }
+        }
+        fired
+    }
+}
+
+const EMPTY_WHEEL: Mutex<TimerWheel> = Mutex::new(TimerWheel::new());
+static WHEELS: [Mutex<TimerWheel>; SMP] = [EMPTY_WHEEL; SMP];
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Override the wheel resolution of the calling pCPU, trading injection
+/// precision for overhead (coarser resolution means fewer bucket pops).
+pub fn set_resolution(resolution_ns: u64) {
+    WHEELS[this_cpu_id()].lock().resolution_ns = resolution_ns.max(1);
+}
+
+/// Arm a timer on the calling pCPU's wheel, to fire `delay_ns` from now and
+/// inject `vector` into `(vm_id, vcpu_id)`. `period_ns` of `Some` re-arms it
+/// after every expiry; `None` fires once.
+pub fn arm(vm_id: usize, vcpu_id: usize, vector: u8, delay_ns: u64, period_ns: Option<u64>) -> TimerHandle {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let deadline_ns = current_time_nanos() + delay_ns;
+    let mut wheel = WHEELS[this_cpu_id()].lock();
+    wheel.insert(
+        deadline_ns,
+        TimerEntry {
+            id,
+            vm_id,
+            vcpu_id,
+            vector,
+            period_ns,
+        },
+    );
+    TimerHandle(id)
+}
+
+/// Cancel a previously armed timer. A no-op if it already fired (and wasn't
+/// periodic) or was armed on a different pCPU.
+pub fn disarm(handle: TimerHandle) {
+    WHEELS[this_cpu_id()].lock().remove(handle.0);
+}
+
+/// Called from `HVScheduler::task_tick`/`vcpu_task_tick` on every scheduler
+/// tick: pops every expired deadline on the calling pCPU's wheel and injects
+/// its vector into the owning vCPU.
+pub fn on_scheduler_tick() {
+    let fired = WHEELS[this_cpu_id()].lock().pop_expired(current_time_nanos());
+    for (vm_id, vcpu_id, vector) in fired {
+        let vm = table_get_vm(vm_id);
+        let vm = vm.lock();
+        if let Some(vcpu) = vm.vcpu(vcpu_id) {
+            let _ = vcpu.vmx_vcpu_mut().queue_event(vector, None);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use core::cell::UnsafeCell;
 use core::fmt::{Display, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 use spin::{Mutex, Once};
 use axhal::cpu::this_cpu_id;
@@ -13,11 +14,23 @@ use crate::hv::notify::{hv_msg_handler, Message, send_message, Signal, wait_on_r
 use crate::hv::prelude::vmcs_revision_id;
 use crate::hv::vm::config::BSP_CPU_ID;
 use crate::hv::vm::VirtMach;
-use crate::hv::vmx::{handle_external_interrupt, handle_msr_read, handle_msr_write, X64VirtDevices};
+use crate::hv::vmx::{handle_external_interrupt, handle_msr_read, handle_msr_write, WatchdogState, X64VirtDevices};
+#[cfg(feature = "gdb")]
+use crate::hv::vmx::gdb::GdbStub;
 use crate::on_timer_tick;
 use crate::run_queue::RUN_QUEUE;
 use crate::utils::CpuSet;
 
+/// Vector of the #DB (debug) exception: single-step and hardware
+/// breakpoints. Delivered through `EXCEPTION_NMI`, same as every other
+/// vectored exception.
+#[cfg(feature = "gdb")]
+const DB_VECTOR: u8 = 1;
+/// Vector of the #BP (breakpoint) exception, raised by the `INT3` a
+/// software breakpoint plants in guest memory.
+#[cfg(feature = "gdb")]
+const BP_VECTOR: u8 = 3;
+
 
 /// virtual cpu state
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -41,6 +54,10 @@ struct VirCpuInner {
     pub vm_name: String,
     pub x64_devices: X64VirtDevices,
     pub prev_pcpu: Option<usize>,
+    #[cfg(feature = "gdb")]
+    pub gdb_stub: Mutex<GdbStub>,
+    #[cfg(feature = "gdb")]
+    pub gdb_attached: AtomicBool,
 }
 
 
@@ -132,7 +149,7 @@ impl VirtCpu {
     }
 
     /// create new bsp vcpu
-    pub fn new_bsp(vm_name: String, cpu_affinity: CpuSet, weak: Weak<Mutex<VirtMach>>, entry: GuestPhysAddr, ept_root: HostPhysAddr) -> HyperResult<Arc<Self>> {
+    pub fn new_bsp(vm_name: String, cpu_affinity: CpuSet, weak: Weak<Mutex<VirtMach>>, entry: GuestPhysAddr, ept_root: HostPhysAddr, watchdog: Option<Arc<WatchdogState>>) -> HyperResult<Arc<Self>> {
         Ok(Arc::new(
             Self {
                 inner: UnsafeCell::new(VirCpuInner {
@@ -144,14 +161,18 @@ impl VirtCpu {
                     vm: weak,
                     ept_root,
                     vm_name,
-                    x64_devices: X64VirtDevices::new()?,
+                    x64_devices: X64VirtDevices::new(watchdog)?,
                     prev_pcpu: None,
+                    #[cfg(feature = "gdb")]
+                    gdb_stub: Mutex::new(GdbStub::new()),
+                    #[cfg(feature = "gdb")]
+                    gdb_attached: AtomicBool::new(false),
                 })
             }
         ))
     }
 
-    pub fn new_ap(vm_name: String, vcpu_id: usize, cpu_affinity: CpuSet, weak: Weak<Mutex<VirtMach>>, ept_root: HostPhysAddr) -> HyperResult<Arc<Self>> {
+    pub fn new_ap(vm_name: String, vcpu_id: usize, cpu_affinity: CpuSet, weak: Weak<Mutex<VirtMach>>, ept_root: HostPhysAddr, watchdog: Option<Arc<WatchdogState>>) -> HyperResult<Arc<Self>> {
         Ok(Arc::new(
             Self {
                 inner: UnsafeCell::new(VirCpuInner {
@@ -163,13 +184,36 @@ impl VirtCpu {
                     vm: weak,
                     ept_root,
                     vm_name,
-                    x64_devices: X64VirtDevices::new()?,
+                    x64_devices: X64VirtDevices::new(watchdog)?,
                     prev_pcpu: None,
+                    #[cfg(feature = "gdb")]
+                    gdb_stub: Mutex::new(GdbStub::new()),
+                    #[cfg(feature = "gdb")]
+                    gdb_attached: AtomicBool::new(false),
                 })
             }
         ))
     }
 
+    /// Attach the remote gdb stub to this vCPU: from the next `EXCEPTION_NMI`
+    /// or `MONITOR_TRAP_FLAG` exit onward, `#DB`/`#BP` and single-step traps
+    /// are routed to [`GdbStub::run`] instead of the normal handlers.
+    #[cfg(feature = "gdb")]
+    pub fn gdb_attach(&self) {
+        self.get_inner().gdb_attached.store(true, Ordering::SeqCst);
+    }
+
+    /// Detach the remote gdb stub, restoring normal `#DB`/`#BP` handling.
+    #[cfg(feature = "gdb")]
+    pub fn gdb_detach(&self) {
+        self.get_inner().gdb_attached.store(false, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "gdb")]
+    pub fn is_gdb_attached(&self) -> bool {
+        self.get_inner().gdb_attached.load(Ordering::SeqCst)
+    }
+
     pub fn set_prev_pcpu(&self, cpu_id: usize) {
         self.get_inner_mut().prev_pcpu = Some(cpu_id);
     }
@@ -187,7 +231,19 @@ impl VirtCpu {
     pub fn set_start_up_entry(&self, entry: usize) {
         self.get_inner_mut().entry = Some(entry);
     }
-    pub fn reset(&mut self) {}
+    /// Reset this vCPU back to its freshly-created state: parked in `Init`
+    /// with no SIPIs consumed and `is_launched` cleared, so `Self::prepare`'s
+    /// `state() == Init` branch re-runs `setup_vmcs` from `entry` the next
+    /// time this vCPU is scheduled, same as it does for a vCPU that's never
+    /// run yet. `entry` lets the caller put the BSP back at the VM's
+    /// original entry point and an AP back to unset (`None`, same as
+    /// `Self::new_ap` leaves it) - see `VirtMach::reset`.
+    pub fn reset(&self, entry: Option<GuestPhysAddr>) {
+        self.get_inner_mut().entry = entry;
+        self.set_sipi_num(0);
+        self.set_state(VirtCpuState::Init);
+        self.set_launched(false);
+    }
     pub fn bind_curr_cpu(&self) -> HyperResult {
         self.get_inner().inner_vcpu.bind_to_current_cpu()
     }
@@ -221,17 +277,59 @@ impl VirtCpu {
         *self.get_inner_mut().vcpu_state.lock() = state;
     }
 
+    /// Park this vCPU at its next VM-exit boundary: [`Self::start`]'s run
+    /// loop spins without re-entering the guest until [`Self::resume`] is
+    /// called. Used by the gdb stub to stop every vCPU in the VM when one
+    /// of them hits a breakpoint (see `VirtMach::pause_vcpus_except`), ahead
+    /// of a full VM-level pause/resume state machine.
+    pub fn pause(&self) {
+        if self.state() == VirtCpuState::Running {
+            self.set_state(VirtCpuState::Stop);
+        }
+    }
+
+    /// Resume a vCPU parked by [`Self::pause`].
+    pub fn resume(&self) {
+        if self.state() == VirtCpuState::Stop {
+            self.set_state(VirtCpuState::Running);
+        }
+    }
+
     pub fn vm(&self) -> Option<Arc<Mutex<VirtMach>>> {
         self.get_inner().vm.upgrade()
     }
     pub fn set_launched(&self, val: bool) {
         self.get_inner_mut().inner_vcpu.is_launched = val;
     }
+    pub fn is_launched(&self) -> bool {
+        self.get_inner().inner_vcpu.is_launched
+    }
+    /// Capture this vCPU's emulated-device state (PIC, IOAPIC, UART, RTC,
+    /// ...), the companion to [`crate::hv::snapshot::VirtCpu::save_state`]
+    /// for everything [`X64VirtDevices`] owns rather than the VMCS.
+    pub fn save_devices(&self) -> crate::hv::vmx::DeviceListSnapshot {
+        self.get_inner().x64_devices.snapshot()
+    }
+    /// Restore device state previously captured by [`Self::save_devices`].
+    pub fn restore_devices(&self, snapshot: &crate::hv::vmx::DeviceListSnapshot) -> HyperResult {
+        self.get_inner().x64_devices.restore(snapshot)
+    }
+    /// This vCPU's configured VMLAUNCH entry point and EPT root, the same
+    /// pair [`Self::prepare`] passes to `setup_vmcs` - exposed for
+    /// [`crate::hv::snapshot`] to rebuild the VMCS when restoring a
+    /// snapshot taken before this vCPU ever launched.
+    pub(crate) fn entry_and_ept_root(&self) -> (GuestPhysAddr, HostPhysAddr) {
+        (self.get_inner().entry.unwrap_or(0), self.get_inner().ept_root)
+    }
     pub fn reset_vmx_preemption_timer(&self) -> HyperResult {
         self.get_inner_mut().inner_vcpu.reset_timer()
     }
     pub fn start(&self) {
         while self.state() != VirtCpuState::Offline {
+            if self.state() == VirtCpuState::Stop {
+                core::hint::spin_loop();
+                continue;
+            }
             // error!("{} exec",self);
             match self.run() {
                 None => {}
@@ -260,17 +358,20 @@ impl VirtCpu {
 
         match exit_info.exit_reason {
             VmxExitReason::EXTERNAL_INTERRUPT => handle_external_interrupt(self),
-            VmxExitReason::IO_INSTRUCTION => self.get_inner_mut().x64_devices.handle_io_instruction(vmx_vcpu, &exit_info),
+            VmxExitReason::IO_INSTRUCTION => {
+                let vm = self.vm().unwrap();
+                let mut vm = vm.lock();
+                let gpm = vm.guest_phys_memory_set_mut();
+                self.get_inner_mut().x64_devices.handle_io_instruction(vmx_vcpu, &exit_info, gpm)
+            }
             VmxExitReason::MSR_READ => handle_msr_read(self),
             VmxExitReason::MSR_WRITE => handle_msr_write(self),
             VmxExitReason::PREEMPTION_TIMER => self.handle_vmx_preemption_timer(),
-            VmxExitReason::SIPI => todo!("todo sipi"),
-            VmxExitReason::EXCEPTION_NMI => {
-                // panic!("vm nmi exit");
-                hv_msg_handler(this_cpu_id());
-                Ok(())
-            }
-            // VmxExitReason::EPT_VIOLATION => ,
+            VmxExitReason::SIPI => self.handle_sipi(),
+            VmxExitReason::EXCEPTION_NMI => self.handle_exception_nmi(),
+            #[cfg(feature = "gdb")]
+            VmxExitReason::MONITOR_TRAP_FLAG => self.enter_gdb_stub(),
+            VmxExitReason::EPT_VIOLATION => self.get_inner_mut().x64_devices.handle_mmio_instruction(vmx_vcpu, &exit_info),
             _ => panic!(
                 "[{}] vmexit reason not supported {:?}:\n",
                 self.vcpu_id(),
@@ -279,6 +380,76 @@ impl VirtCpu {
         }
     }
 
+    fn handle_exception_nmi(&self) -> HyperResult {
+        #[cfg(feature = "gdb")]
+        if self.is_gdb_attached() {
+            let vector = self.vmx_vcpu_mut().interrupt_exit_info()?.vector;
+            if vector == DB_VECTOR || vector == BP_VECTOR {
+                return self.enter_gdb_stub();
+            }
+        }
+        // panic!("vm nmi exit");
+        hv_msg_handler(this_cpu_id());
+        Ok(())
+    }
+
+    /// Hand control of this vCPU to the attached remote gdb stub. The
+    /// monitor-trap-flag control does not clear itself on exit, so it must be
+    /// turned off here or the guest would trap on every subsequent
+    /// instruction once resumed.
+    ///
+    /// Parks every other vCPU in the VM for the duration so the debugger
+    /// sees the whole guest stopped, not just the vCPU that hit the
+    /// breakpoint, then resumes them once the stub hands control back.
+    #[cfg(feature = "gdb")]
+    fn enter_gdb_stub(&self) -> HyperResult {
+        self.vmx_vcpu_mut().set_monitor_trap_flag(false)?;
+        let vm = self.vm().ok_or(HyperError::Internal)?;
+        let mut vm = vm.lock();
+        vm.pause_vcpus_except(self.vcpu_id());
+        let channel = self.get_inner().x64_devices.debug_uart();
+        self.get_inner()
+            .gdb_stub
+            .lock()
+            .run(self, &channel, vm.guest_phys_memory_set_mut());
+        vm.resume_vcpus_except(self.vcpu_id());
+        Ok(())
+    }
+
+    /// Handle a `SIPI` vmexit (Intel SDM Vol. 3C 27.2.2, basic exit reason
+    /// 7: a SIPI arriving while this AP is in the wait-for-SIPI activity
+    /// state): the Intel MP startup protocol's INIT-SIPI-SIPI sequence
+    /// sends two of these back to back, so only the second actually starts
+    /// the AP - the first just records the vector real firmware also
+    /// treats as a no-op if nothing has happened since INIT.
+    ///
+    /// Only the entry RIP is set from the vector here (`vector << 12`,
+    /// i.e. real mode's `CS:IP = (vector << 8):0` with a zero CS base).
+    /// Setting the real-mode CS selector/base the vector also implies is a
+    /// VMCS guest-segment write `hypercraft::VCpu` doesn't expose a setter
+    /// for yet - the same gap `Debuggable::gdb_write_reg` and
+    /// `VirtCpu::restore_state` already document for CS/SS/DS/ES/FS/GS, so
+    /// this only brings up guest code that doesn't depend on a nonzero CS
+    /// base, same as those two callers.
+    ///
+    /// The other half an AP bring-up needs - somewhere for the guest to
+    /// discover how many APs exist and their APIC IDs - is already covered
+    /// by [`crate::hv::acpi::build_madt`], one `MadtLocalApic` entry per set
+    /// bit of the booting VM's `CpuSet`.
+    fn handle_sipi(&self) -> HyperResult {
+        let vector = self.vmx_vcpu_mut().exit_info()?.exit_qualification as u8;
+        let target_rip = (vector as u64) << 12;
+
+        self.set_sipi_num(self.sipi_num().saturating_add(1));
+        self.set_start_up_entry(target_rip as usize);
+        self.vmx_vcpu_mut().set_rip(target_rip)?;
+
+        if self.sipi_num() >= 2 && self.state() == VirtCpuState::Init {
+            self.set_state(VirtCpuState::Running);
+        }
+        Ok(())
+    }
+
     fn handle_vmx_preemption_timer(&self) -> HyperResult {
         // error!("vmx preemption timer");
         // RUN_QUEUE.lock().hv_scheduler_timer_tick();
@@ -23,6 +23,18 @@ pub struct VmConfig {
     pub guest_phys_memory_base: GuestPhysAddr,
     pub guest_phys_memory_size: usize,
     pub guest_memory_region: Vec<GuestMemoryRegion>,
+    /// Attach [`crate::hv::vmx::gdb::GdbStub`] to the BSP vCPU as soon as it's
+    /// created, so a debugger can set breakpoints before the guest runs its
+    /// first instruction instead of racing it after boot.
+    #[cfg(feature = "gdb")]
+    pub gdb_attach: bool,
+    /// Arm `crate::hv::vmx::device_emu::watchdog::VmWatchdog` for this VM, so
+    /// a guest that hangs without crashing gets reset instead of spinning
+    /// forever. See `crate::hv::vm::check_watchdogs`.
+    pub watchdog_enabled: bool,
+    /// Seconds a pet can be absent before the watchdog resets the VM.
+    /// Ignored unless `watchdog_enabled`.
+    pub watchdog_timeout_secs: u64,
 }
 
 pub fn arceos_config() -> VmConfig {
@@ -55,6 +67,15 @@ pub fn arceos_config() -> VmConfig {
             size: 0x1000,
             flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
         },
+        GuestMemoryRegion {
+            // Watchdog (crate::hv::vmx::device_emu::watchdog::VmWatchdog),
+            // present regardless of watchdog_enabled, same as the other
+            // always-mapped device windows above.
+            gpa: 0xfed0_1000,
+            hpa: 0xfed0_1000,
+            size: 0x1000,
+            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+        },
     ];
 
     VmConfig {
@@ -69,5 +90,9 @@ pub fn arceos_config() -> VmConfig {
         guest_phys_memory_base: 0,
         guest_phys_memory_size: 0x800_0000, // 16M
         guest_memory_region,
+        #[cfg(feature = "gdb")]
+        gdb_attach: false,
+        watchdog_enabled: false,
+        watchdog_timeout_secs: crate::hv::vmx::DEFAULT_TIMEOUT_SECS,
     }
 }
@@ -1,5 +1,6 @@
 //! Abstraction of a virtual machine
 
+use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec;
@@ -9,20 +10,25 @@ use core::ptr::addr_of;
 use core::sync::atomic::{AtomicUsize, Ordering};
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
+use raw_cpuid::{cpuid, CpuIdResult};
 use spin::{Mutex, Once};
 use axhal::mem::{MemRegion, virt_to_phys, VirtAddr};
 use crate::hv::mm::{GuestMemoryRegion, GuestPhysMemorySet, load_guest_image};
 
 pub mod config;
+mod mp_table;
 
 pub use config::VmConfig;
 pub use config::arceos_config;
 use hypercraft::{GuestPhysAddr, HostPhysAddr, HostVirtAddr, HyperError, HyperResult, PerCpu, VCpu, VmxExitInfo};
 use page_table_entry::MappingFlags;
 use spinlock::SpinNoIrq;
-use crate::hv::{HyperCraftHalImpl, vmx};
+use crate::hv::{acpi, HyperCraftHalImpl, vmx};
 use crate::hv::vcpu::{VirtCpu, VirtCpuState};
+use crate::hv::snapshot::VCpuState;
 use crate::hv::vm::config::BSP_CPU_ID;
+use crate::hv::vmx::WatchdogState;
+use crate::hv::vmx::smp::{DeliveryMode, DestinationMode, Icr};
 use crate::{AxTaskRef, spawn_vcpu, spawn_vcpus};
 use crate::utils::CpuSet;
 
@@ -36,10 +42,19 @@ pub fn init() {
     }
 }
 
-pub fn table_delete_vm(vm_id: usize) {
+/// Remove a VM from the global table. Only succeeds once the VM has been
+/// through [`VirtMach::shutdown`] - a VM still `Active`/`Paused` must be
+/// shut down first, so nothing drops a `VirtMach` whose vCPU tasks are
+/// still runnable.
+pub fn table_delete_vm(vm_id: usize) -> HyperResult {
     unsafe {
+        let vm = VM_TABLE.get_mut().unwrap().get(&vm_id).ok_or(HyperError::InvalidParam)?;
+        if vm.lock().state() != VmState::Shutdown {
+            return Err(HyperError::InvalidParam);
+        }
         VM_TABLE.get_mut().unwrap().remove(&vm_id).unwrap();
     }
+    Ok(())
 }
 
 pub fn table_insert_vm(vm_id: usize, vm: Arc<Mutex<VirtMach>>) {
@@ -54,13 +69,134 @@ pub fn table_get_vm(vm_id: usize) -> Arc<Mutex<VirtMach>> {
     }
 }
 
+/// Called from [`crate::hv::scheduler::HVScheduler::task_tick`]/`vcpu_task_tick`
+/// on every scheduler tick, same host-side cadence
+/// [`crate::hv::timer::on_scheduler_tick`] uses: resets any VM whose
+/// `crate::hv::vmx::device_emu::watchdog::WatchdogState` has gone unpet past
+/// its timeout. Cheap to run this often since it's just an atomic load per
+/// watchdog-enabled VM, and there's no other host-side hook this tree has
+/// for "do something periodically regardless of guest activity".
+pub fn check_watchdogs() {
+    let now_ns = axhal::time::current_time_nanos();
+    unsafe {
+        for vm in VM_TABLE.get_mut().unwrap().values() {
+            let mut vm = vm.lock();
+            if vm.watchdog.as_ref().is_some_and(|w| w.expired(now_ns)) {
+                vm.reset();
+            }
+        }
+    }
+}
+
 
 static VM_ID_ALLOCATOR: AtomicUsize = AtomicUsize::new(0);
 
 /// virtual machine state
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum VmState {
     Inactive,
     Active,
+    Paused,
+    Shutdown,
+}
+
+/// A single (leaf, subleaf) CPUID override consulted by
+/// [`crate::hv::vmx::handle_cpuid`] before it falls back to the host's raw
+/// `cpuid`. Built once per VM by [`build_cpuid_template`] from the VM's vCPU
+/// count, so a guest with `SMP > 1` enumerates exactly the vCPUs ArceOS gave
+/// it instead of the host's physical topology.
+#[derive(Clone, Copy)]
+pub struct CpuIdOverride {
+    pub result: CpuIdResult,
+    /// EDX on the extended-topology leaf carries the *querying* vCPU's own
+    /// x2APIC id, which a single VM-wide template entry can't encode;
+    /// [`handle_cpuid`](crate::hv::vmx::handle_cpuid) patches it in at
+    /// lookup time instead.
+    pub patch_apic_id: bool,
+}
+
+/// Hide VMX and advertise the hypervisor-present bit on leaf 0x1 (patching
+/// its logical-processor count into EBX[23:16]), synthesize the hypervisor
+/// vendor leaves, and lay out `num_vcpus` single-thread cores on the cache
+/// (leaf 0x4) and extended-topology (leaf 0x0B) leaves, capping their
+/// "processors sharing this level" fields to the guest's own vCPU count
+/// instead of the host's.
+fn build_cpuid_template(num_vcpus: u32) -> BTreeMap<(u32, u32), CpuIdOverride> {
+    const FEATURE_VMX: u32 = 1 << 5;
+    const FEATURE_HYPERVISOR: u32 = 1 << 31;
+    const LEAF_HYPERVISOR_INFO: u32 = 0x4000_0000;
+    const LEAF_HYPERVISOR_FEATURE: u32 = 0x4000_0001;
+    const VENDOR_STR: &[u8; 12] = b"RVMRVMRVMRVM";
+
+    let num_vcpus = num_vcpus.max(1);
+    let mut table = BTreeMap::new();
+    let entry = |result| CpuIdOverride { result, patch_apic_id: false };
+
+    let mut leaf1 = cpuid!(0x1, 0);
+    leaf1.ecx = (leaf1.ecx & !FEATURE_VMX) | FEATURE_HYPERVISOR;
+    leaf1.ebx = (leaf1.ebx & !0x00ff_0000) | (num_vcpus.min(0xff) << 16);
+    table.insert((0x1, 0), entry(leaf1));
+
+    let vendor_regs = unsafe { &*(VENDOR_STR.as_ptr() as *const [u32; 3]) };
+    table.insert(
+        (LEAF_HYPERVISOR_INFO, 0),
+        entry(CpuIdResult {
+            eax: LEAF_HYPERVISOR_FEATURE,
+            ebx: vendor_regs[0],
+            ecx: vendor_regs[1],
+            edx: vendor_regs[2],
+        }),
+    );
+    table.insert(
+        (LEAF_HYPERVISOR_FEATURE, 0),
+        entry(CpuIdResult { eax: 0, ebx: 0, ecx: 0, edx: 0 }),
+    );
+
+    // Leaf 0x4: keep the host's real cache geometry, but cap "logical
+    // processors/cores sharing this cache" to the guest's own vCPU count
+    // rather than leaving the host's physical topology exposed.
+    for subleaf in 0..4u32 {
+        let mut cache = cpuid!(0x4, subleaf);
+        if cache.eax & 0x1f == 0 {
+            break; // host reports no more cache levels
+        }
+        let sharing = (num_vcpus - 1).min(0xfff);
+        cache.eax = (cache.eax & !0x03ff_c000) | (sharing << 14);
+        let cores = (num_vcpus - 1).min(0x3f);
+        cache.eax = (cache.eax & !0xfc00_0000) | (cores << 26);
+        table.insert((0x4, subleaf), entry(cache));
+    }
+
+    // Leaf 0x0B: a flat topology, no SMT, `num_vcpus` single-thread cores.
+    let core_width = 32 - (num_vcpus - 1).leading_zeros();
+    let levels = [
+        (0u32, 0u32, 1u32, 1u32), // subleaf 0: SMT, width 0, 1 logical proc below it
+        (1, core_width, num_vcpus, 2), // subleaf 1: Core, width covers num_vcpus ids
+    ];
+    for (subleaf, width, logical_below, level_type) in levels {
+        table.insert(
+            (0xB, subleaf),
+            CpuIdOverride {
+                result: CpuIdResult {
+                    eax: width,
+                    ebx: logical_below,
+                    ecx: (subleaf & 0xff) | (level_type << 8),
+                    edx: 0, // patched with the querying vCPU's x2APIC id
+                },
+                patch_apic_id: true,
+            },
+        );
+    }
+    // Any further subleaf terminates the leaf: level type 0 in ECX[15:8].
+    table.insert(
+        (0xB, 2),
+        CpuIdOverride {
+            result: CpuIdResult { eax: 0, ebx: 0, ecx: 2, edx: 0 },
+            patch_apic_id: true,
+        },
+    );
+
+    table
 }
 
 /// virtual machine
@@ -71,6 +207,26 @@ pub struct VirtMach {
     phy_mem: Vec<u8>, // 16M
     guest_phys_memory_set: GuestPhysMemorySet,
     entry: GuestPhysAddr,
+    cpuid_template: BTreeMap<(u32, u32), CpuIdOverride>,
+    /// Where `phy_mem` is mapped in guest physical space, i.e. `VmConfig::guest_phys_memory_base`.
+    ram_gpa: GuestPhysAddr,
+    /// The device/MMIO regions [`boot_vm`] mapped into `guest_phys_memory_set`
+    /// alongside RAM (everything in `VmConfig::guest_memory_region` except
+    /// RAM itself, which isn't kept here since its host address changes
+    /// every time `phy_mem` is reallocated). Kept around so [`Self::snapshot`]
+    /// can hand [`Self::restore`] a manifest to rebuild the nested page
+    /// table from, instead of only the RAM mapping `phy_mem` implies.
+    guest_memory_region: Vec<GuestMemoryRegion>,
+    state: VmState,
+    /// Seconds a pet can be absent before `crate::hv::vm::check_watchdogs`
+    /// resets this VM, i.e. `VmConfig::watchdog_timeout_secs`. `None` means
+    /// the VM was booted with `watchdog_enabled: false`; kept alongside
+    /// `watchdog` so `Self::snapshot` can hand it back to `Self::restore`
+    /// without having to read it out of the live [`WatchdogState`].
+    watchdog_timeout_secs: Option<u64>,
+    /// Shared with every vCPU's `crate::hv::vmx::device_emu::watchdog::VmWatchdog`
+    /// MMIO front-end; `None` unless `watchdog_timeout_secs` is.
+    watchdog: Option<Arc<WatchdogState>>,
 }
 
 impl VirtMach {
@@ -82,14 +238,34 @@ impl VirtMach {
         self.guest_phys_memory_set.nest_page_table_root()
     }
 
+    /// Mutable access to the VM's nested page table, needed by
+    /// [`crate::hv::vmx::gdb::GdbStub`] to plant/clear software breakpoints
+    /// and read/write guest memory.
+    #[cfg(feature = "gdb")]
+    pub fn guest_phys_memory_set_mut(&mut self) -> &mut GuestPhysMemorySet {
+        &mut self.guest_phys_memory_set
+    }
+
+    /// Look up this VM's CPUID template for `(leaf, subleaf)`, consulted by
+    /// [`crate::hv::vmx::handle_cpuid`] before it falls back to the host's
+    /// raw `cpuid`.
+    pub fn cpuid_override(&self, leaf: u32, subleaf: u32) -> Option<CpuIdOverride> {
+        self.cpuid_template.get(&(leaf, subleaf)).copied()
+    }
+
     pub fn new(vm_id: usize,
                name: String,
                phy_mem: Vec<u8>,
                guest_phys_memory_set: GuestPhysMemorySet,
                entry: GuestPhysAddr,
                cpu_affinities: Vec<CpuSet>,
+               ram_gpa: GuestPhysAddr,
+               guest_memory_region: Vec<GuestMemoryRegion>,
+               watchdog_timeout_secs: Option<u64>,
     ) -> HyperResult<Arc<Mutex<Self>>> {
         let ntr = guest_phys_memory_set.nest_page_table_root();
+        let cpuid_template = build_cpuid_template(cpu_affinities.len() as u32);
+        let watchdog = watchdog_timeout_secs.map(WatchdogState::new);
         let vm = Arc::new(Mutex::new(VirtMach {
             vm_id,
             name: name.clone(),
@@ -97,8 +273,25 @@ impl VirtMach {
             phy_mem,
             guest_phys_memory_set,
             entry,
+            cpuid_template,
+            ram_gpa,
+            guest_memory_region,
+            state: VmState::Inactive,
+            watchdog_timeout_secs,
+            watchdog: watchdog.clone(),
         }));
 
+        // So a multi-vCPU guest can enumerate its own LAPICs/IOAPIC and find
+        // the legacy reset port without hardcoded probing (see
+        // `crate::hv::acpi`); placed in the low-memory area real BIOSes
+        // reserve for their own tables, well clear of `bios_entry`/`guest_entry`.
+        const ACPI_TABLES_GPA: GuestPhysAddr = 0x000e_0000;
+        let mut guest_vcpu_set = CpuSet::new_empty();
+        for cpu_id in 0..cpu_affinities.len() {
+            guest_vcpu_set.add(cpu_id);
+        }
+        acpi::map_tables(vm.lock().guest_phys_memory_set_mut(), &guest_vcpu_set, ACPI_TABLES_GPA)?;
+
         let len = cpu_affinities.len();
         error!("len is {}",len);
         let mut vcpus = Vec::with_capacity(len);
@@ -111,6 +304,7 @@ impl VirtMach {
                     Arc::downgrade(&vm),
                     entry,
                     ntr,
+                    watchdog.clone(),
                 )?);
             } else {
                 vcpus.push(VirtCpu::new_ap(
@@ -119,6 +313,7 @@ impl VirtMach {
                     iter.next().ok_or(HyperError::Internal)?,
                     Arc::downgrade(&vm),
                     ntr,
+                    watchdog.clone(),
                 )?);
             }
         }
@@ -134,6 +329,113 @@ impl VirtMach {
     pub fn vm_id(&self) -> usize {
         self.vm_id
     }
+    pub fn vcpu(&self, vcpu_id: usize) -> Option<Arc<VirtCpu>> {
+        self.vcpus.get(vcpu_id).cloned()
+    }
+
+    /// All of this VM's vCPUs, for [`crate::hv::coredump`] to emit one
+    /// `NT_PRSTATUS` note per vCPU.
+    pub(crate) fn vcpus(&self) -> &[Arc<VirtCpu>] {
+        &self.vcpus
+    }
+
+    /// Guest RAM, for [`crate::hv::coredump`]'s single `PT_LOAD` segment.
+    pub(crate) fn phy_mem(&self) -> &[u8] {
+        &self.phy_mem
+    }
+
+    /// Where [`Self::phy_mem`] is mapped in guest physical space, for
+    /// [`crate::hv::coredump`]'s `PT_LOAD` segment's `p_vaddr`/`p_paddr`.
+    pub(crate) fn ram_gpa(&self) -> GuestPhysAddr {
+        self.ram_gpa
+    }
+
+    /// Write this VM's current state out as an ELF64 core file: one
+    /// `PT_LOAD` segment over [`Self::phy_mem`] and one `NT_PRSTATUS` note
+    /// per vCPU. See [`crate::hv::coredump`] for the format.
+    pub fn coredump(&self) -> HyperResult<Vec<u8>> {
+        Ok(crate::hv::coredump::build(self))
+    }
+    /// Add an AP vCPU to this already-running VM, as in cloud-hypervisor's
+    /// CpuManager resize path: allocates a new `VirtCpu` at the next index
+    /// sharing this VM's nested page table (`Self::nest_table_root`),
+    /// parked in [`VirtCpuState::Init`] until the guest INIT/SIPIs it
+    /// through the normal IPI path ([`Self::deliver_ipi`]). Returns the new
+    /// vCPU's index. `VM_ID_ALLOCATOR`/`VM_TABLE` are untouched - this VM
+    /// keeps its existing `vm_id` and table entry throughout.
+    pub fn add_vcpu(&mut self, affinity: CpuSet) -> HyperResult<usize> {
+        let idx = self.vcpus.len();
+        let weak = Arc::downgrade(&self.vcpus[BSP_CPU_ID].vm().ok_or(HyperError::Internal)?);
+        let ntr = self.nest_table_root();
+        let ap = VirtCpu::new_ap(self.name.clone(), idx, affinity, weak, ntr, self.watchdog.clone())?;
+        self.vcpus.push(ap);
+        Ok(idx)
+    }
+
+    /// Remove a vCPU added by [`Self::add_vcpu`] (or any AP): marks it
+    /// offline so its task loop ([`VirtCpu::start`]) exits at its next
+    /// check instead of re-entering the guest. Doesn't shrink `vcpus` -
+    /// every other vCPU, [`Self::destination_vcpus`] included, addresses a
+    /// vCPU by its fixed index. Guards against removing `BSP_CPU_ID`, which
+    /// `Self::start_bsp` and every AP's existence depend on.
+    pub fn remove_vcpu(&mut self, idx: usize) -> HyperResult {
+        if idx == BSP_CPU_ID {
+            return Err(HyperError::InvalidParam);
+        }
+        let vcpu = self.vcpus.get(idx).ok_or(HyperError::InvalidParam)?;
+        vcpu.set_state(VirtCpuState::Offline);
+        Ok(())
+    }
+
+    pub fn state(&self) -> VmState {
+        self.state
+    }
+
+    /// Freeze every vCPU at its next VM exit ([`VirtCpu::pause`]) without
+    /// tearing anything down, so the VM can be snapshotted or debugged and
+    /// brought back with [`Self::resume`].
+    pub fn pause(&mut self) {
+        self.state = VmState::Paused;
+        for vcpu in &self.vcpus {
+            vcpu.pause();
+        }
+    }
+
+    /// Resume a VM frozen by [`Self::pause`].
+    pub fn resume(&mut self) {
+        self.state = VmState::Active;
+        for vcpu in &self.vcpus {
+            vcpu.resume();
+        }
+    }
+
+    /// Tear this VM down: every vCPU's task loop ([`VirtCpu::start`]) exits
+    /// at its next check instead of re-entering the guest. Terminal -
+    /// [`table_delete_vm`] only allows removing a VM in this state, and
+    /// there is no way back to [`VmState::Active`] from here.
+    pub fn shutdown(&mut self) {
+        self.state = VmState::Shutdown;
+        for vcpu in &self.vcpus {
+            vcpu.set_state(VirtCpuState::Offline);
+        }
+    }
+
+    /// Reset every vCPU back to its boot-time state without tearing the VM
+    /// down: the BSP goes back to this VM's original entry point as if
+    /// freshly rebooted and every AP back to parked-uninitialized, same as
+    /// [`Self::new`] leaves them, while `phy_mem` and the nested page table
+    /// are left exactly as they are. Called by [`check_watchdogs`] when
+    /// `self.watchdog` expires.
+    pub fn reset(&mut self) {
+        for (idx, vcpu) in self.vcpus.iter().enumerate() {
+            vcpu.reset(if idx == BSP_CPU_ID { Some(self.entry) } else { None });
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.disarm();
+        }
+        self.state = VmState::Active;
+    }
+
     pub fn start_bsp(&self) -> AxTaskRef {
         info!("{} start bsp",self);
         let bsp = self.vcpus[BSP_CPU_ID].clone();
@@ -143,12 +445,9 @@ impl VirtMach {
     pub fn start_aps(&self, ap_start_entry: usize) {
         let mut vcpus = Vec::new();
         for (idx, ap) in self.vcpus.iter().enumerate() {
-            let sipi = ap.sipi_num();
-            if idx != BSP_CPU_ID && ap.state() == VirtCpuState::Init && sipi != 0 {
-                ap.set_sipi_num(sipi - 1);
-                if ap.sipi_num() <= 0 {
-                    ap.set_start_up_entry(ap_start_entry);
-                    vcpus.push(ap.clone());
+            if idx != BSP_CPU_ID {
+                if let Some(ap) = Self::apply_startup(ap, ap_start_entry) {
+                    vcpus.push(ap);
                 }
             }
         }
@@ -169,6 +468,266 @@ impl VirtMach {
             }
         }
     }
+
+    /// Park `ap` in [`VirtCpuState::Init`] with no SIPIs consumed, same
+    /// state [`Self::send_init_to_aps`] puts every AP in for an `INIT` IPI.
+    fn apply_init(ap: &Arc<VirtCpu>) {
+        ap.set_state(VirtCpuState::Init);
+        ap.set_sipi_num(0);
+    }
+
+    /// Apply a `StartUp` IPI to `ap`: consumes one SIPI slot and, once
+    /// they're all consumed, records `ap_start_entry` as its entry RIP and
+    /// returns `ap` ready to [`spawn_vcpus`]. Shared by [`Self::start_aps`]
+    /// (which targets every AP) and [`Self::deliver_ipi`] (which targets
+    /// whatever the ICR's destination resolves to).
+    fn apply_startup(ap: &Arc<VirtCpu>, ap_start_entry: usize) -> Option<Arc<VirtCpu>> {
+        let sipi = ap.sipi_num();
+        if ap.state() == VirtCpuState::Init && sipi != 0 {
+            ap.set_sipi_num(sipi - 1);
+            if ap.sipi_num() == 0 {
+                ap.set_start_up_entry(ap_start_entry);
+                return Some(ap.clone());
+            }
+        }
+        None
+    }
+
+    /// Resolve an ICR's destination shorthand/mode into the set of target
+    /// vCPU ids (SDM Vol. 3A, Section 10.6.2). `sender` is the vCPU whose
+    /// APIC write produced this `icr`, needed for the self shorthand.
+    fn destination_vcpus(&self, sender: usize, icr: &Icr) -> Vec<usize> {
+        const SHORTHAND_SELF: u64 = 0b01;
+        const SHORTHAND_ALL_INCL_SELF: u64 = 0b10;
+        const SHORTHAND_ALL_EXCL_SELF: u64 = 0b11;
+
+        match icr.destination_shorthand() {
+            SHORTHAND_SELF => vec![sender],
+            SHORTHAND_ALL_INCL_SELF => (0..self.vcpus.len()).collect(),
+            SHORTHAND_ALL_EXCL_SELF => (0..self.vcpus.len()).filter(|&id| id != sender).collect(),
+            _ => match DestinationMode::try_from(icr.destination_mode()) {
+                Ok(DestinationMode::Physical) => {
+                    let dest = icr.destination_field() as usize;
+                    if dest < self.vcpus.len() { vec![dest] } else { vec![] }
+                }
+                _ => {
+                    // x2APIC cluster-mode logical id derived from the APIC
+                    // id (SDM Vol. 3A, Section 10.12.10.2): one-hot bit
+                    // 3:0, cluster in bits 19:4. This flat topology puts
+                    // every vCPU in cluster 0, so only the one-hot bits
+                    // matter.
+                    let mask = icr.destination_field() as u32;
+                    (0..self.vcpus.len())
+                        .filter(|&id| mask & (1 << (id as u32 & 0xf)) != 0)
+                        .collect()
+                }
+            },
+        }
+    }
+
+    /// Decode and dispatch a guest write to the LAPIC's ICR (SDM Vol. 3A,
+    /// Section 10.6): the single entry point the APIC write handler calls
+    /// instead of going straight to [`Self::start_aps`]/[`Self::send_init_to_aps`].
+    /// `sender` is the vCPU id whose APIC write produced `icr`.
+    pub fn deliver_ipi(&self, sender: usize, icr: Icr) -> HyperResult {
+        let mode = DeliveryMode::try_from(icr.delivery_mode()).map_err(|_| HyperError::InvalidParam)?;
+        let targets = self.destination_vcpus(sender, &icr);
+        match mode {
+            DeliveryMode::INIT => {
+                for id in targets {
+                    if id != BSP_CPU_ID {
+                        Self::apply_init(&self.vcpus[id]);
+                    }
+                }
+                Ok(())
+            }
+            DeliveryMode::StartUp => {
+                let entry = (icr.vector() as usize) << 12;
+                let mut vcpus = Vec::new();
+                for id in targets {
+                    if id != BSP_CPU_ID {
+                        if let Some(ap) = Self::apply_startup(&self.vcpus[id], entry) {
+                            vcpus.push(ap);
+                        }
+                    }
+                }
+                if !vcpus.is_empty() {
+                    spawn_vcpus(vcpus);
+                }
+                Ok(())
+            }
+            // No real arbitration bus to pick a least-busy target; reuse
+            // Fixed's same resolved destination set.
+            DeliveryMode::Fixed | DeliveryMode::LowPriority => {
+                let vector = icr.vector() as u8;
+                for id in targets {
+                    self.vcpus[id].vmx_vcpu_mut().queue_event(vector, None);
+                }
+                Ok(())
+            }
+            DeliveryMode::NMI => {
+                // NMI is hardware-vectored as interrupt 2 regardless of the
+                // ICR's vector field (SDM Vol. 3A, Section 10.5.2.4).
+                for id in targets {
+                    self.vcpus[id].vmx_vcpu_mut().queue_event(2, None);
+                }
+                Ok(())
+            }
+            DeliveryMode::SMI => Err(HyperError::NotSupported),
+        }
+    }
+
+    /// Translate a guest-virtual address as seen by `vcpu_idx`'s currently
+    /// executing instruction to a guest-physical one, by walking that
+    /// vCPU's own page tables from its CR3. By-index convenience wrapper
+    /// around [`crate::hv::vmx::walk::translate_gva`] - the same 4-level
+    /// walk [`crate::hv::vmx::gdb::Debuggable::gdb_translate`] uses - for
+    /// callers that only have a `VirtMach` and a vCPU index, not a
+    /// `VirtCpu` reference.
+    pub fn translate_gva(&self, vcpu_idx: usize, gva: hypercraft::GuestVirtAddr) -> HyperResult<GuestPhysAddr> {
+        let vcpu = self.vcpus.get(vcpu_idx).ok_or(HyperError::InvalidParam)?;
+        vmx::walk::translate_gva(vcpu.vmx_vcpu_mut(), &self.guest_phys_memory_set, gva)
+    }
+
+    /// Pause every vCPU but `except_idx` at their next VM exit (see
+    /// [`VirtCpu::pause`]). Called when one vCPU traps into the gdb stub, so
+    /// the whole guest looks stopped to the debugger instead of just the
+    /// vCPU that hit the breakpoint.
+    #[cfg(feature = "gdb")]
+    pub fn pause_vcpus_except(&self, except_idx: usize) {
+        for (idx, vcpu) in self.vcpus.iter().enumerate() {
+            if idx != except_idx {
+                vcpu.pause();
+            }
+        }
+    }
+
+    /// Resume every vCPU [`Self::pause_vcpus_except`] parked.
+    #[cfg(feature = "gdb")]
+    pub fn resume_vcpus_except(&self, except_idx: usize) {
+        for (idx, vcpu) in self.vcpus.iter().enumerate() {
+            if idx != except_idx {
+                vcpu.resume();
+            }
+        }
+    }
+
+    /// Capture everything needed to pause this VM and bring it back later,
+    /// possibly on another node: every vCPU's architectural state, a copy of
+    /// guest RAM, and enough of this VM's own config (`entry`, `vm_id`,
+    /// `name`, per-vCPU affinities, the `guest_memory_region` manifest) that
+    /// [`Self::restore`] can rebuild it from nothing. Mirrors
+    /// cloud-hypervisor's `VmSnapshot`/`VmConfig` pairing, collapsed into
+    /// one struct since this tree has no separate persisted `VmConfig`.
+    pub fn snapshot(&self) -> VmSnapshot {
+        VmSnapshot {
+            vm_id: self.vm_id,
+            name: self.name.clone(),
+            entry: self.entry,
+            cpu_affinities: self.vcpus.iter().map(|v| v.cpu_affinity()).collect(),
+            ram_gpa: self.ram_gpa,
+            guest_memory_region: self.guest_memory_region.clone(),
+            vcpu_states: self.vcpus.iter().map(|v| v.save_state()).collect(),
+            phy_mem: self.phy_mem.clone(),
+            watchdog_timeout_secs: self.watchdog_timeout_secs,
+        }
+    }
+
+    /// Resume a snapshot in place on an already-booted `VirtMach` with the
+    /// same vCPU count and RAM size: copies guest RAM back in, then runs
+    /// every vCPU through [`VirtCpu::restore_snapshot`][crate::hv::snapshot]
+    /// so each one's VMCS is rebuilt and ready to resume from the saved
+    /// registers. Cheaper than [`Self::restore`] when the VM never went
+    /// away, since it reuses the existing nested page table instead of
+    /// rebuilding one from the `guest_memory_region` manifest.
+    ///
+    /// Returns [`HyperError::InvalidParam`] if `snapshot` doesn't match this
+    /// VM's current vCPU count or RAM size.
+    pub fn restore_state(&mut self, snapshot: &VmSnapshot) -> HyperResult {
+        if snapshot.vcpu_states.len() != self.vcpus.len()
+            || snapshot.phy_mem.len() != self.phy_mem.len()
+        {
+            return Err(HyperError::InvalidParam);
+        }
+        self.phy_mem.copy_from_slice(&snapshot.phy_mem);
+        for (vcpu, state) in self.vcpus.iter().zip(snapshot.vcpu_states.iter()) {
+            vcpu.restore_snapshot(state)?;
+        }
+        Ok(())
+    }
+
+    /// Reconstruct a whole VM from a [`VmSnapshot`] without going through
+    /// [`boot_vm`]: rebuilds the nested page table from the snapshot's
+    /// `guest_memory_region` manifest, re-mapping the restored `phy_mem`'s
+    /// own (new) host address as its RAM region exactly like `boot_vm` does
+    /// for a freshly booted VM, then threads every vCPU through
+    /// [`VirtCpu::restore_snapshot`] so it resumes exactly where it left
+    /// off. Registers the rebuilt VM in the VM table under its original
+    /// `vm_id`, so this also works for restoring onto a node that never
+    /// booted this VM at all (live migration / cold warm-restart).
+    pub fn restore(snapshot: VmSnapshot) -> HyperResult<Arc<Mutex<Self>>> {
+        let VmSnapshot {
+            vm_id,
+            name,
+            entry,
+            cpu_affinities,
+            ram_gpa,
+            guest_memory_region,
+            vcpu_states,
+            phy_mem,
+            watchdog_timeout_secs,
+        } = snapshot;
+
+        let mut gpm = GuestPhysMemorySet::new()?;
+        for r in guest_memory_region.iter().cloned() {
+            gpm.map_region(r.into())?;
+        }
+        gpm.map_region(GuestMemoryRegion {
+            gpa: ram_gpa,
+            hpa: virt_to_phys((phy_mem.as_ptr() as HostVirtAddr).into()).into(),
+            size: phy_mem.len(),
+            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+        }.into())?;
+
+        let vm = Self::new(vm_id, name, phy_mem, gpm, entry, cpu_affinities, ram_gpa, guest_memory_region, watchdog_timeout_secs)?;
+        for (vcpu, state) in vm.lock().vcpus.iter().zip(vcpu_states.iter()) {
+            vcpu.restore_snapshot(state)?;
+        }
+
+        // Keep later `boot_vm` calls from handing out an id that collides
+        // with a migrated-in VM's original one.
+        VM_ID_ALLOCATOR.fetch_max(vm_id + 1, Ordering::Relaxed);
+        table_insert_vm(vm_id, vm.clone());
+        Ok(vm)
+    }
+}
+
+/// A whole-VM snapshot: every vCPU's [`VCpuState`], a raw copy of guest RAM,
+/// and the config needed to rebuild this VM from scratch. Produced by
+/// [`VirtMach::snapshot`] and consumed by [`VirtMach::restore_state`] (same
+/// VM, in place) or [`VirtMach::restore`] (rebuilt from nothing) to
+/// pause/resume or migrate a VM.
+///
+/// Callers that need a stable on-disk layout should serialize
+/// `vcpu_states`/everything but `phy_mem` as a small config blob and
+/// `phy_mem` as its own raw memory blob, mirroring cloud-hypervisor's
+/// split between its snapshot config file and guest memory file — the two
+/// scale completely differently (bytes vs. the whole guest RAM size) and
+/// gain nothing from being interleaved in one file.
+pub struct VmSnapshot {
+    pub vm_id: usize,
+    pub name: String,
+    pub entry: GuestPhysAddr,
+    pub cpu_affinities: Vec<CpuSet>,
+    pub ram_gpa: GuestPhysAddr,
+    pub guest_memory_region: Vec<GuestMemoryRegion>,
+    pub vcpu_states: Vec<VCpuState>,
+    pub phy_mem: Vec<u8>,
+    /// `VmConfig::watchdog_timeout_secs` this VM was booted with, if any.
+    /// [`VirtMach::restore`] arms a fresh `WatchdogState` from this rather
+    /// than carrying over the live countdown, since a VM that was just
+    /// restored hasn't had the chance to hang yet.
+    pub watchdog_timeout_secs: Option<u64>,
 }
 
 
@@ -194,7 +753,11 @@ pub fn boot_vm(conf: VmConfig) {
         guest_image_size,
         guest_phys_memory_base,
         guest_phys_memory_size,
-        mut guest_memory_region
+        mut guest_memory_region,
+        #[cfg(feature = "gdb")]
+        gdb_attach,
+        watchdog_enabled,
+        watchdog_timeout_secs,
     } = conf;
 
     // memory
@@ -202,6 +765,11 @@ pub fn boot_vm(conf: VmConfig) {
     load_guest_image(phy_mem.as_mut_slice(), bios_paddr, bios_entry, bios_size);
     load_guest_image(phy_mem.as_mut_slice(), guest_image_paddr, guest_entry, guest_image_size);
 
+    // Kept distinct from RAM so `VirtMach::snapshot` has a manifest to
+    // rebuild the nested page table from; RAM's host address changes every
+    // time `phy_mem` is reallocated, so it's recomputed fresh on restore
+    // instead of being carried in this manifest.
+    let device_regions = guest_memory_region.clone();
     guest_memory_region.push(GuestMemoryRegion {
         gpa: guest_phys_memory_base,
         hpa: virt_to_phys((phy_mem.as_ptr() as HostVirtAddr).into()).into(),
@@ -216,9 +784,19 @@ pub fn boot_vm(conf: VmConfig) {
 
     // vm
     let vm_id = VM_ID_ALLOCATOR.fetch_add(1, Ordering::Relaxed);
-    let vm = VirtMach::new(vm_id, name, phy_mem, gpm, bios_entry, cpu_affinities).unwrap();
+    let vm = VirtMach::new(
+        vm_id, name, phy_mem, gpm, bios_entry, cpu_affinities,
+        guest_phys_memory_base, device_regions,
+        watchdog_enabled.then_some(watchdog_timeout_secs),
+    ).unwrap();
     table_insert_vm(vm_id, vm.clone());
 
+    #[cfg(feature = "gdb")]
+    if gdb_attach {
+        vm.lock().vcpu(BSP_CPU_ID).unwrap().gdb_attach();
+    }
+
     let tx = vm.lock().start_bsp();
+    vm.lock().state = VmState::Active;
     tx.join();
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+//! Intel MultiProcessor Specification (MP) table, the pre-ACPI way for a
+//! guest to discover its vCPU count and the local/I-O APIC layout.
+//!
+//! [`crate::hv::acpi`] already covers this for an ACPI-aware guest via the
+//! MADT; this exists for firmware/OSes that only walk the older MP table
+//! (BIOS-era SMP kernels, some bootloaders that probe it before ACPI).
+//! [`build_mp_table`] lays out the floating pointer structure immediately
+//! followed by the configuration table, matching how real BIOSes place them
+//! contiguously.
+
+use alloc::vec::Vec;
+use pci::util::byte_code::ByteCode;
+use crate::hv::vm::config::VmConfig;
+
+/// Recompute a structure's checksum so its bytes sum to zero mod 256, same
+/// convention [`crate::hv::acpi::fix_checksum`] uses for ACPI tables.
+fn fix_checksum(bytes: &mut [u8], checksum_offset: usize) {
+    bytes[checksum_offset] = 0;
+    let sum = bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    bytes[checksum_offset] = 0u8.wrapping_sub(sum);
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpFloatingPointer {
+    signature: [u8; 4],
+    config_table_addr: u32,
+    length: u8,
+    spec_rev: u8,
+    checksum: u8,
+    feature_bytes: [u8; 5],
+}
+impl ByteCode for MpFloatingPointer {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpConfigHeader {
+    signature: [u8; 4],
+    base_table_length: u16,
+    spec_rev: u8,
+    checksum: u8,
+    oem_id: [u8; 8],
+    product_id: [u8; 12],
+    oem_table_ptr: u32,
+    oem_table_size: u16,
+    entry_count: u16,
+    local_apic_addr: u32,
+    extended_table_length: u16,
+    extended_table_checksum: u8,
+    reserved: u8,
+}
+impl ByteCode for MpConfigHeader {}
+
+/// `mpc_cpu` entry flags (MP Spec Table 4-9).
+const CPU_FLAG_ENABLED: u8 = 1 << 0;
+const CPU_FLAG_BOOTSTRAP: u8 = 1 << 1;
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpcCpu {
+    entry_type: u8,
+    local_apic_id: u8,
+    local_apic_ver: u8,
+    cpu_flags: u8,
+    cpu_signature: u32,
+    feature_flags: u32,
+    reserved: [u32; 2],
+}
+impl ByteCode for MpcCpu {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpcBus {
+    entry_type: u8,
+    bus_id: u8,
+    bus_type: [u8; 6],
+}
+impl ByteCode for MpcBus {}
+
+const IOAPIC_FLAG_ENABLED: u8 = 1 << 0;
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpcIoApic {
+    entry_type: u8,
+    apic_id: u8,
+    apic_ver: u8,
+    flags: u8,
+    apic_addr: u32,
+}
+impl ByteCode for MpcIoApic {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MpcIntSrc {
+    entry_type: u8,
+    irq_type: u8,
+    irq_flag: u16,
+    src_bus_id: u8,
+    src_bus_irq: u8,
+    dst_apic_id: u8,
+    dst_apic_int: u8,
+}
+impl ByteCode for MpcIntSrc {}
+
+impl VmConfig {
+    /// Build the floating pointer structure + configuration table blob: one
+    /// [`MpcCpu`] per set bit across `cpu_affinities` (vCPU 0 marked as the
+    /// bootstrap processor, matching `BSP_CPU_ID`), a single ISA bus, the
+    /// emulated I/O APIC, and an identity interrupt-source entry per ISA IRQ
+    /// line routing it to the same-numbered I/O APIC pin.
+    pub fn build_mp_table(&self) -> Vec<u8> {
+        /// Matches the Local APIC guest-memory window `arceos_config` maps.
+        const LOCAL_APIC_ADDRESS: u32 = 0xfee0_0000;
+        /// Matches the I/O APIC guest-memory window `arceos_config` maps.
+        const IO_APIC_ADDRESS: u32 = 0xfec0_0000;
+        /// ISA bus entry id, referenced by both the bus entry and every
+        /// interrupt-source entry below.
+        const ISA_BUS_ID: u8 = 0;
+        /// Number of identity-routed ISA IRQ lines (legacy PIT/RTC/keyboard/
+        /// COM range; matches the pin count the emulated I/O APIC exposes).
+        const NUM_ISA_IRQS: u8 = 16;
+
+        let num_cpus = self.cpu_affinities.len() as u8;
+
+        let mut entries = Vec::new();
+        for cpu_id in 0..num_cpus {
+            let mut flags = CPU_FLAG_ENABLED;
+            if cpu_id as usize == super::config::BSP_CPU_ID {
+                flags |= CPU_FLAG_BOOTSTRAP;
+            }
+            let cpu = MpcCpu {
+                entry_type: 0,
+                local_apic_id: cpu_id,
+                local_apic_ver: 0x11,
+                cpu_flags: flags,
+                ..Default::default()
+            };
+            entries.extend_from_slice(cpu.as_bytes());
+        }
+
+        let bus = MpcBus {
+            entry_type: 1,
+            bus_id: ISA_BUS_ID,
+            bus_type: *b"ISA   ",
+        };
+        entries.extend_from_slice(bus.as_bytes());
+
+        let ioapic = MpcIoApic {
+            entry_type: 2,
+            apic_id: num_cpus, // first free APIC ID, past every vCPU's.
+            apic_ver: 0x11,
+            flags: IOAPIC_FLAG_ENABLED,
+            apic_addr: IO_APIC_ADDRESS,
+        };
+        entries.extend_from_slice(ioapic.as_bytes());
+
+        for irq in 0..NUM_ISA_IRQS {
+            let intsrc = MpcIntSrc {
+                entry_type: 3,
+                irq_type: 0, // INT: a vectored interrupt, conforming to the bus spec.
+                irq_flag: 0, // conforms to bus spec (active-high, edge-triggered for ISA).
+                src_bus_id: ISA_BUS_ID,
+                src_bus_irq: irq,
+                dst_apic_id: ioapic.apic_id,
+                dst_apic_int: irq,
+            };
+            entries.extend_from_slice(intsrc.as_bytes());
+        }
+
+        let entry_count = (num_cpus as u16) + 1 + 1 + NUM_ISA_IRQS as u16;
+        let header_len = core::mem::size_of::<MpConfigHeader>();
+        let mut header = MpConfigHeader {
+            signature: *b"PCMP",
+            base_table_length: (header_len + entries.len()) as u16,
+            spec_rev: 4, // MP Spec 1.4
+            checksum: 0,
+            oem_id: *b"ARCEOS  ",
+            product_id: *b"ARCEOSVM    ",
+            oem_table_ptr: 0,
+            oem_table_size: 0,
+            entry_count,
+            local_apic_addr: LOCAL_APIC_ADDRESS,
+            extended_table_length: 0,
+            extended_table_checksum: 0,
+            reserved: 0,
+        };
+        let mut config_table = header.as_mut_bytes().to_vec();
+        config_table.extend_from_slice(&entries);
+        fix_checksum(&mut config_table, core::mem::offset_of!(MpConfigHeader, checksum));
+
+        let fp_len = core::mem::size_of::<MpFloatingPointer>();
+        let mut fp = MpFloatingPointer {
+            signature: *b"_MP_",
+            config_table_addr: fp_len as u32, // config table immediately follows.
+            length: 1,                        // structure length, in 16-byte units.
+            spec_rev: 4,
+            checksum: 0,
+            feature_bytes: [0; 5], // feature_byte[0] == 0: configuration is the table above, not a default.
+        };
+        fix_checksum(fp.as_mut_bytes(), core::mem::offset_of!(MpFloatingPointer, checksum));
+
+        let mut blob = fp.as_bytes().to_vec();
+        blob.extend_from_slice(&config_table);
+        blob
+    }
+}
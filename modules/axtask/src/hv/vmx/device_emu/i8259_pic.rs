@@ -2,8 +2,32 @@
 
 use bit_field::BitField;
 use hypercraft::{HyperError, HyperResult};
+use pci::util::byte_code::ByteCode;
 use crate::hv::vmx::device_emu::PioOps;
 
+/// Fixed-layout capture of [`I8259Pic`]'s mutable state for
+/// [`PioOps::snapshot`]/[`PioOps::restore`]; `port_base` is deliberately
+/// excluded since it's construction-time identity, not state a migration
+/// needs to carry (the live device being restored into already has it).
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct PicSnapshot {
+    icw1: u8,
+    offset: u8,
+    icw3: u8,
+    icw4: u8,
+    icw_written: u8,
+    icw_left: u8,
+    mask: u8,
+    irr: u8,
+    isr: u8,
+    read_isr: u8,
+    irq_level: [u8; 8],
+    eoi_pending: u8,
+}
+
+impl ByteCode for PicSnapshot {}
+
 pub struct I8259Pic {
     port_base: u16,
     icw1: u8,
@@ -13,6 +37,19 @@ pub struct I8259Pic {
     icw_written: u8,
     icw_left: bool,
     mask: u8,
+    /// Interrupt Request Register: lines asserted and awaiting acknowledgement.
+    irr: u8,
+    /// In-Service Register: lines acknowledged but not yet EOI'd.
+    isr: u8,
+    /// OCW3 read-register select: `true` returns ISR, `false` returns IRR on
+    /// the next port-0 read.
+    read_isr: bool,
+    /// Current (asserted/deasserted) state of each of the 8 input lines, for
+    /// level-triggered devices that need to be re-polled on EOI.
+    irq_level: [bool; 8],
+    /// Set by a non-specific EOI (OCW2) write; consumed by `take_eoi` so
+    /// level-triggered devices know when to resample their line.
+    eoi_pending: bool,
 }
 
 impl PioOps for I8259Pic {
@@ -30,6 +67,7 @@ impl PioOps for I8259Pic {
         // };
         // debug!("reading from {pic_name} port {port:#x} size {_access_size:#x}");
         match port - self.port_base {
+            0 => Ok((if self.read_isr { self.isr } else { self.irr }) as u32),
             1 => Ok(self.mask as u32),
             _ => Err(HyperError::NotSupported),
         }
@@ -50,9 +88,33 @@ impl PioOps for I8259Pic {
         match port - self.port_base {
             0 => {
                 if value.get_bit(4) {
+                    // ICW1: start (re-)initialization sequence.
                     self.icw1 = value;
                     self.icw_left = true;
                     self.icw_written = 1;
+                    self.irr = 0;
+                    self.isr = 0;
+                    self.read_isr = false;
+                } else if value.get_bit(5) {
+                    // OCW2 EOI. Level-triggered devices check `take_eoi` to
+                    // decide whether to re-assert.
+                    if value.get_bit(6) {
+                        // Specific EOI: the serviced line is named in bits 0..3.
+                        let line = value & 0x7;
+                        self.isr.set_bit(line as usize, false);
+                    } else {
+                        // Non-specific EOI: clear the highest-priority ISR bit.
+                        if let Some(line) = Self::highest_priority_bit(self.isr) {
+                            self.isr.set_bit(line as usize, false);
+                        }
+                    }
+                    self.eoi_pending = true;
+                } else if value.get_bit(3) {
+                    // OCW3: read-register select (RR/RIS); poll mode (P) isn't
+                    // used by anything in this tree, so it's left unimplemented.
+                    if value.get_bit(1) {
+                        self.read_isr = value.get_bit(0);
+                    }
                 } else {
                     // debug!("pit ocw ignored");
                 }
@@ -82,6 +144,42 @@ impl PioOps for I8259Pic {
 
         Ok(()) // ignore write
     }
+
+    fn snapshot(&self) -> alloc::vec::Vec<u8> {
+        PicSnapshot {
+            icw1: self.icw1,
+            offset: self.offset,
+            icw3: self.icw3,
+            icw4: self.icw4,
+            icw_written: self.icw_written,
+            icw_left: self.icw_left as u8,
+            mask: self.mask,
+            irr: self.irr,
+            isr: self.isr,
+            read_isr: self.read_isr as u8,
+            irq_level: self.irq_level.map(|level| level as u8),
+            eoi_pending: self.eoi_pending as u8,
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HyperResult {
+        let snap = PicSnapshot::from_bytes(data).ok_or(HyperError::InvalidParam)?;
+        self.icw1 = snap.icw1;
+        self.offset = snap.offset;
+        self.icw3 = snap.icw3;
+        self.icw4 = snap.icw4;
+        self.icw_written = snap.icw_written;
+        self.icw_left = snap.icw_left != 0;
+        self.mask = snap.mask;
+        self.irr = snap.irr;
+        self.isr = snap.isr;
+        self.read_isr = snap.read_isr != 0;
+        self.irq_level = snap.irq_level.map(|level| level != 0);
+        self.eoi_pending = snap.eoi_pending != 0;
+        Ok(())
+    }
 }
 
 impl I8259Pic {
@@ -95,10 +193,77 @@ impl I8259Pic {
             icw_left: false,
             icw_written: 0,
             mask: 0,
+            irq_level: [false; 8],
+            eoi_pending: false,
         }
     }
 
     pub const fn mask(&self) -> u8 {
         self.mask
     }
+
+    /// Vector the guest will see in the IDT for the given input line.
+    pub const fn vector_for(&self, irq: u8) -> u8 {
+        self.offset.wrapping_add(irq)
+    }
+
+    /// Whether the guest has masked off the given input line.
+    pub fn is_masked(&self, irq: u8) -> bool {
+        self.mask.get_bit(irq as usize)
+    }
+
+    /// Record the current level of an input line.
+    pub fn set_irq_level(&mut self, irq: u8, level: bool) {
+        self.irq_level[irq as usize] = level;
+    }
+
+    /// Current recorded level of an input line.
+    pub fn irq_level(&self, irq: u8) -> bool {
+        self.irq_level[irq as usize]
+    }
+
+    /// Consume a pending (non-specific) EOI, if one occurred since the last
+    /// call. Level-triggered devices poll this to know when to resample.
+    pub fn take_eoi(&mut self) -> bool {
+        core::mem::replace(&mut self.eoi_pending, false)
+    }
+
+    /// Assert an input line, marking it pending in the IRR.
+    pub fn raise_irq(&mut self, irq: u8) {
+        self.irr.set_bit(irq as usize, true);
+    }
+
+    /// Index (0 = highest priority) of the lowest set bit, i.e. the 8259's
+    /// fixed priority order where line 0 outranks line 7.
+    fn highest_priority_bit(reg: u8) -> Option<u8> {
+        (0..8).find(|&i| reg.get_bit(i)).map(|i| i as u8)
+    }
+
+    /// The input line the slave is cascaded onto (bit position of this
+    /// chip's ICW3, meaningful only on the master), defaulting to the
+    /// standard PC wiring (IRQ2) before the guest has programmed ICW3.
+    pub fn cascade_line(&self) -> u8 {
+        Self::highest_priority_bit(self.icw3).unwrap_or(2)
+    }
+
+    /// Resolve the highest-priority pending, unmasked line whose priority
+    /// beats every line currently in service (classic 8259 fully-nested
+    /// mode), move it from IRR to ISR, and return its guest-visible vector.
+    pub fn next_pending_vector(&mut self) -> Option<u8> {
+        let in_service = Self::highest_priority_bit(self.isr);
+        for line in 0..8u8 {
+            if !self.irr.get_bit(line as usize) || self.is_masked(line) {
+                continue;
+            }
+            if let Some(isr_line) = in_service {
+                if line >= isr_line {
+                    continue;
+                }
+            }
+            self.irr.set_bit(line as usize, false);
+            self.isr.set_bit(line as usize, true);
+            return Some(self.vector_for(line));
+        }
+        None
+    }
 }
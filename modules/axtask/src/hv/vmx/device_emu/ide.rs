@@ -0,0 +1,621 @@
+//! Emulated PIIX-compatible primary IDE/ATA channel with bus-master DMA,
+//! the legacy-disk counterpart to `modules/axvm`'s virtio-blk: guests
+//! without a virtio driver (old bootloaders, BIOS-era kernels) still need
+//! somewhere to find a boot disk.
+//!
+//! Ports follow the standard ISA wiring: the command block sits at
+//! 0x1f0-0x1f7, the control block (alternate status / device control) at
+//! 0x3f6. [`IdeCommandBlock`]/[`IdeControlBlock`] both wrap the same shared
+//! [`IdeController`] the way `Bundle::proxy_*` splits one device's state
+//! across several port ranges in the `axruntime` sibling of this module.
+//!
+//! There is no PCI bus in this tree (see the module doc on `device_emu`),
+//! so the bus-master DMA register block - normally a PCI BAR - is exposed
+//! as [`IdeBusMaster`] at a fixed legacy-style port range instead; its
+//! register layout (command, status, PRDT pointer) and the PRDT walk it
+//! does on [`PioOps::poll_dma`] still match real PIIX hardware.
+//!
+//! The backing store is the pluggable [`IdeBackend`] trait. The only
+//! implementation today is [`HostMemoryBackend`], a flat image living in
+//! host physical memory - this tree is `no_std` (no `std::fs`, unlike
+//! `axvm`'s `VirtioBlk`), so a real disk image has to already be loaded
+//! somewhere in host RAM (the same shape `VmConfig::bios_paddr`/
+//! `guest_image_paddr` use) before it can be attached; wiring an actual
+//! image through `VmConfig` into [`super::X64VirtDevices::new`] is left for
+//! whoever adds disk-image configuration, the same kind of gap `crate::hv::acpi`'s
+//! module doc leaves open for a DSDT.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use hypercraft::{GuestPhysAddr, HostPhysAddr, HyperError, HyperResult};
+use spin::Mutex;
+use crate::hv::mm::GuestPhysMemorySet;
+use crate::hv::vmx::VCpu;
+use crate::hv::vmx::device_emu::i8259_pic::I8259Pic;
+use crate::hv::vmx::device_emu::{IrqLevelEvent, PioOps};
+
+/// Every ATA transfer here is whole-sector; this is the one size the
+/// emulated controller understands.
+const SECTOR_SIZE: usize = 512;
+
+/// Primary channel's command block is conventionally wired to IRQ14, i.e.
+/// pin 6 of the slave i8259 (global IRQ8 + 6).
+pub const IDE_IRQ: u8 = 6;
+
+// Status register bits (ATA-6 7.xx).
+const STATUS_ERR: usize = 0;
+const STATUS_DRQ: usize = 3;
+const STATUS_DRDY: usize = 6;
+const STATUS_BSY: usize = 7;
+
+// Device/Head register bits.
+const DRIVE_HEAD_LBA: usize = 6;
+
+// ATA commands this controller understands; anything else is reported via
+// `ERR`/`ABRT` rather than silently ignored.
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_READ_SECTORS_EXT: u8 = 0x24;
+const CMD_READ_DMA_EXT: u8 = 0x25;
+const CMD_WRITE_SECTORS: u8 = 0x30;
+const CMD_WRITE_SECTORS_EXT: u8 = 0x34;
+const CMD_WRITE_DMA_EXT: u8 = 0x35;
+const CMD_READ_DMA: u8 = 0xc8;
+const CMD_WRITE_DMA: u8 = 0xca;
+const CMD_IDENTIFY: u8 = 0xec;
+
+// Error register bit set on an unsupported/out-of-range command.
+const ERR_ABRT: usize = 2;
+
+// Bus-master command register bit (PIIX "Programming Interface for
+// Bus Master IDE Controller"); transfer direction for this emulation comes
+// from whichever ATA READ/WRITE DMA command armed `pending_dma`, not from a
+// direction bit here.
+const BM_CMD_START: usize = 0;
+
+/// A block-storage backend an [`IdeController`] can be attached to,
+/// swappable the way [`super::watchdog::WatchdogState`] is shared in
+/// without the device itself caring how it's implemented.
+pub trait IdeBackend: Send {
+    /// Capacity, in whole sectors.
+    fn sector_count(&self) -> u64;
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> HyperResult;
+    fn write_sector(&mut self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> HyperResult;
+}
+
+/// A flat disk image living in host physical memory, addressed the same
+/// way [`super::super::mm::load_guest_image`]'s callers already reference a
+/// preloaded blob (a host-physical base and a byte length) - the only
+/// backing store this `no_std` tree can offer without real file I/O.
+pub struct HostMemoryBackend {
+    base: HostPhysAddr,
+    size: usize,
+}
+
+impl HostMemoryBackend {
+    /// `base`/`size` must describe memory already mapped and populated by
+    /// whatever loaded the disk image, exactly like `VmConfig::bios_paddr`/
+    /// `bios_size`.
+    pub fn new(base: HostPhysAddr, size: usize) -> Self {
+        Self { base, size }
+    }
+
+    fn sector_ptr(&self, lba: u64) -> HyperResult<*mut u8> {
+        let offset = lba as usize * SECTOR_SIZE;
+        if offset + SECTOR_SIZE > self.size {
+            return Err(HyperError::InvalidParam);
+        }
+        Ok(axhal::mem::phys_to_virt((self.base + offset).into()).as_usize() as *mut u8)
+    }
+}
+
+impl IdeBackend for HostMemoryBackend {
+    fn sector_count(&self) -> u64 {
+        (self.size / SECTOR_SIZE) as u64
+    }
+
+    fn read_sector(&mut self, lba: u64, buf: &mut [u8; SECTOR_SIZE]) -> HyperResult {
+        let ptr = self.sector_ptr(lba)?;
+        unsafe { core::ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), SECTOR_SIZE) };
+        Ok(())
+    }
+
+    fn write_sector(&mut self, lba: u64, buf: &[u8; SECTOR_SIZE]) -> HyperResult {
+        let ptr = self.sector_ptr(lba)?;
+        unsafe { core::ptr::copy_nonoverlapping(buf.as_ptr(), ptr, SECTOR_SIZE) };
+        Ok(())
+    }
+}
+
+/// Write `s` into `words[start..start + len]` the way IDENTIFY DEVICE wants
+/// its ASCII fields: each pair of characters packed into one word with the
+/// first character in the *high* byte, padded with spaces.
+fn set_identify_string(words: &mut [u16], start: usize, len: usize, s: &str) {
+    let bytes = s.as_bytes();
+    for i in 0..len {
+        let hi = *bytes.get(2 * i).unwrap_or(&b' ') as u16;
+        let lo = *bytes.get(2 * i + 1).unwrap_or(&b' ') as u16;
+        words[start + i] = (hi << 8) | lo;
+    }
+}
+
+/// One entry of a bus-master Physical Region Descriptor Table: a
+/// guest-physical buffer address and length, with the last entry in a
+/// table marked by `eot`.
+struct Prd {
+    gpa: u64,
+    len: usize,
+    eot: bool,
+}
+
+/// Shared state behind [`IdeCommandBlock`], [`IdeControlBlock`] and
+/// [`IdeBusMaster`] - one primary IDE channel with its bus-master DMA
+/// engine, the way one `Rtc` backs a single device but this controller
+/// backs three non-contiguous port ranges instead of just the one.
+pub struct IdeController {
+    backend: Box<dyn IdeBackend>,
+
+    error: u8,
+    sector_count: u8,
+    lba_low: u8,
+    lba_mid: u8,
+    lba_high: u8,
+    drive_head: u8,
+    status: u8,
+    device_control: u8,
+    /// Value each LBA48 register held just before its last write - the HOB
+    /// (high-order byte) half of the classic "write twice" LBA48
+    /// convention, read back out by [`Self::lba`]/[`Self::requested_sectors`]
+    /// once an LBA48 command latches them in as the upper 24/8 bits.
+    sector_count_hob: u8,
+    lba_low_hob: u8,
+    lba_mid_hob: u8,
+    lba_high_hob: u8,
+
+    /// Set by the last LBA48 command issued, so register reads/writes know
+    /// whether a register holds one 8-bit field or the low half of a
+    /// 16-bit HOB pair; cleared by any LBA28 command.
+    lba48: bool,
+    /// Pending bus-master transfer, armed by an LBA28/LBA48 DMA command and
+    /// actually walked by [`PioOps::poll_dma`] once the bus master's start
+    /// bit is written - mirrors real hardware, where issuing the ATA
+    /// command only arms the channel and the PCI-side bus master has to be
+    /// started separately.
+    pending_dma: Option<(u64, u32, bool)>, // (lba, sector_count, is_write)
+
+    /// PIO data buffer for the DATA port (0x1f0): the whole command's
+    /// worth of sectors, drained/filled 16 bits at a time.
+    data: Vec<u8>,
+    data_offset: usize,
+
+    irq: IrqLevelEvent,
+    irq_pending: bool,
+
+    bm_command: u8,
+    bm_status: u8,
+    bm_prdt_addr: u32,
+}
+
+impl IdeController {
+    pub fn new(backend: Box<dyn IdeBackend>, pic: Arc<Mutex<I8259Pic>>) -> Self {
+        Self {
+            backend,
+            error: 0,
+            sector_count: 1,
+            lba_low: 0,
+            lba_mid: 0,
+            lba_high: 0,
+            drive_head: 1 << DRIVE_HEAD_LBA,
+            status: 1 << STATUS_DRDY,
+            device_control: 0,
+            sector_count_hob: 0,
+            lba_low_hob: 0,
+            lba_mid_hob: 0,
+            lba_high_hob: 0,
+            lba48: false,
+            pending_dma: None,
+            data: Vec::new(),
+            data_offset: 0,
+            irq: IrqLevelEvent::new(IDE_IRQ, pic, || false),
+            irq_pending: false,
+            bm_command: 0,
+            bm_status: 0,
+            bm_prdt_addr: 0,
+        }
+    }
+
+    fn lba(&self) -> u64 {
+        if self.lba48 {
+            (self.lba_low as u64)
+                | ((self.lba_mid as u64) << 8)
+                | ((self.lba_high as u64) << 16)
+                | ((self.lba_low_hob as u64) << 24)
+                | ((self.lba_mid_hob as u64) << 32)
+                | ((self.lba_high_hob as u64) << 40)
+        } else {
+            (self.lba_low as u64)
+                | ((self.lba_mid as u64) << 8)
+                | ((self.lba_high as u64) << 16)
+                | (((self.drive_head & 0x0f) as u64) << 24)
+        }
+    }
+
+    fn requested_sectors(&self) -> u32 {
+        if self.lba48 {
+            let count = ((self.sector_count_hob as u32) << 8) | self.sector_count as u32;
+            if count == 0 { 0x1_0000 } else { count }
+        } else if self.sector_count == 0 {
+            256
+        } else {
+            self.sector_count as u32
+        }
+    }
+
+    fn abort(&mut self) {
+        self.error = 1 << ERR_ABRT;
+        self.status = (1 << STATUS_DRDY) | (1 << STATUS_ERR);
+        self.irq_pending = true;
+    }
+
+    fn identify(&mut self) {
+        let mut words = [0u16; 256];
+        let sectors = self.backend.sector_count();
+        words[0] = 0x0040; // non-removable ATA device.
+        words[1] = 16383u16.min((sectors / (16 * 63)) as u16); // legacy CHS, capped.
+        words[3] = 16;
+        words[6] = 63;
+        set_identify_string(&mut words, 10, 10, "ARCEOSDISK0000000000"); // serial
+        set_identify_string(&mut words, 23, 4, "1.0"); // firmware revision
+        set_identify_string(&mut words, 27, 20, "ArceOS Emulated IDE Disk"); // model
+        words[49] = 1 << 9; // LBA supported.
+        words[60] = (sectors.min(u32::MAX as u64) & 0xffff) as u16;
+        words[61] = ((sectors.min(u32::MAX as u64) >> 16) & 0xffff) as u16;
+        words[83] = 1 << 10; // LBA48 supported.
+        words[100] = (sectors & 0xffff) as u16;
+        words[101] = ((sectors >> 16) & 0xffff) as u16;
+        words[102] = ((sectors >> 32) & 0xffff) as u16;
+        words[103] = ((sectors >> 48) & 0xffff) as u16;
+
+        self.data = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        self.data_offset = 0;
+        self.status = (1 << STATUS_DRDY) | (1 << STATUS_DRQ);
+        self.irq_pending = true;
+    }
+
+    /// PIO READ SECTORS: stage every requested sector up front into `data`
+    /// so the guest's run of IN instructions against 0x1f0 just drains it;
+    /// real hardware raises one IRQ per sector instead of one for the
+    /// whole command, which this simplifies away the same way `Rtc`'s
+    /// module doc admits its interrupt sources are only I/O-driven, not
+    /// cycle-accurate.
+    fn pio_read(&mut self, lba: u64, count: u32) {
+        let mut data = Vec::with_capacity(count as usize * SECTOR_SIZE);
+        let mut buf = [0u8; SECTOR_SIZE];
+        for i in 0..count as u64 {
+            if self.backend.read_sector(lba + i, &mut buf).is_err() {
+                self.abort();
+                return;
+            }
+            data.extend_from_slice(&buf);
+        }
+        self.data = data;
+        self.data_offset = 0;
+        self.status = (1 << STATUS_DRDY) | (1 << STATUS_DRQ);
+        self.irq_pending = true;
+    }
+
+    fn pio_write_setup(&mut self, count: u32) {
+        self.data = vec![0u8; count as usize * SECTOR_SIZE];
+        self.data_offset = 0;
+        self.status = (1 << STATUS_DRDY) | (1 << STATUS_DRQ);
+    }
+
+    fn finish_pio_write(&mut self, lba: u64) {
+        let mut buf = [0u8; SECTOR_SIZE];
+        for (i, chunk) in self.data.chunks_exact(SECTOR_SIZE).enumerate() {
+            buf.copy_from_slice(chunk);
+            if self.backend.write_sector(lba + i as u64, &buf).is_err() {
+                self.abort();
+                return;
+            }
+        }
+        self.status = 1 << STATUS_DRDY;
+        self.irq_pending = true;
+    }
+
+    fn execute_command(&mut self, command: u8) {
+        self.error = 0;
+        match command {
+            CMD_IDENTIFY => self.identify(),
+            CMD_READ_SECTORS | CMD_READ_SECTORS_EXT => {
+                self.lba48 = command == CMD_READ_SECTORS_EXT;
+                let (lba, count) = (self.lba(), self.requested_sectors());
+                self.pio_read(lba, count);
+            }
+            CMD_WRITE_SECTORS | CMD_WRITE_SECTORS_EXT => {
+                self.lba48 = command == CMD_WRITE_SECTORS_EXT;
+                self.pio_write_setup(self.requested_sectors());
+            }
+            CMD_READ_DMA | CMD_READ_DMA_EXT => {
+                self.lba48 = command == CMD_READ_DMA_EXT;
+                self.pending_dma = Some((self.lba(), self.requested_sectors(), false));
+                self.status = (1 << STATUS_DRDY) | (1 << STATUS_BSY);
+            }
+            CMD_WRITE_DMA | CMD_WRITE_DMA_EXT => {
+                self.lba48 = command == CMD_WRITE_DMA_EXT;
+                self.pending_dma = Some((self.lba(), self.requested_sectors(), true));
+                self.status = (1 << STATUS_DRDY) | (1 << STATUS_BSY);
+            }
+            _ => self.abort(),
+        }
+    }
+
+    fn read_data(&mut self) -> u16 {
+        if self.data_offset + 2 > self.data.len() {
+            return 0xffff;
+        }
+        let value = u16::from_le_bytes([self.data[self.data_offset], self.data[self.data_offset + 1]]);
+        self.data_offset += 2;
+        if self.data_offset >= self.data.len() {
+            self.status.set_bit(STATUS_DRQ, false);
+        }
+        value
+    }
+
+    fn write_data(&mut self, value: u16) {
+        if self.data_offset + 2 > self.data.len() {
+            return;
+        }
+        let bytes = value.to_le_bytes();
+        self.data[self.data_offset] = bytes[0];
+        self.data[self.data_offset + 1] = bytes[1];
+        self.data_offset += 2;
+        if self.data_offset >= self.data.len() {
+            let lba = self.lba();
+            self.finish_pio_write(lba);
+        }
+    }
+
+    fn read_command_block(&mut self, port: u16) -> HyperResult<u32> {
+        Ok(match port {
+            0x1f0 => self.read_data() as u32,
+            0x1f1 => self.error as u32,
+            0x1f2 => self.sector_count as u32,
+            0x1f3 => self.lba_low as u32,
+            0x1f4 => self.lba_mid as u32,
+            0x1f5 => self.lba_high as u32,
+            0x1f6 => self.drive_head as u32,
+            0x1f7 => {
+                self.irq_pending = false;
+                self.status as u32
+            }
+            _ => return Err(HyperError::InvalidParam),
+        })
+    }
+
+    fn write_command_block(&mut self, port: u16, value: u32) -> HyperResult {
+        // LBA48's "write twice" convention: the byte a register held before
+        // this write becomes its HOB half, consulted by `lba`/
+        // `requested_sectors` once an LBA48 command latches the pair in.
+        match port {
+            0x1f0 => self.write_data(value as u16),
+            0x1f1 => {} // features; no optional feature toggled by this emulation.
+            0x1f2 => {
+                self.sector_count_hob = self.sector_count;
+                self.sector_count = value as u8;
+            }
+            0x1f3 => {
+                self.lba_low_hob = self.lba_low;
+                self.lba_low = value as u8;
+            }
+            0x1f4 => {
+                self.lba_mid_hob = self.lba_mid;
+                self.lba_mid = value as u8;
+            }
+            0x1f5 => {
+                self.lba_high_hob = self.lba_high;
+                self.lba_high = value as u8;
+            }
+            0x1f6 => self.drive_head = value as u8,
+            0x1f7 => self.execute_command(value as u8),
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+
+    /// Alternate status (read) / device control (write) at 0x3f6, the
+    /// control block's one register - reading it, unlike 0x1f7, doesn't
+    /// acknowledge the pending interrupt.
+    fn read_control_block(&self) -> u32 {
+        self.status as u32
+    }
+
+    fn write_control_block(&mut self, value: u32) {
+        self.device_control = value as u8;
+    }
+
+    fn read_bus_master(&self, offset: u16) -> HyperResult<u32> {
+        Ok(match offset {
+            0 => self.bm_command as u32,
+            2 => self.bm_status as u32,
+            4..=7 => (self.bm_prdt_addr >> (8 * (offset - 4))) & 0xff,
+            _ => return Err(HyperError::InvalidParam),
+        })
+    }
+
+    fn write_bus_master(&mut self, offset: u16, value: u32) -> HyperResult {
+        match offset {
+            0 => self.bm_command = value as u8,
+            // Status is write-1-to-clear on the interrupt/error bits, same
+            // convention as a PCI bus master's real register.
+            2 => self.bm_status &= !(value as u8 & 0x06),
+            4..=7 => {
+                let shift = 8 * (offset - 4);
+                self.bm_prdt_addr = (self.bm_prdt_addr & !(0xff << shift)) | ((value & 0xff) << shift);
+            }
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+
+    /// Walk the PRDT rooted at `bm_prdt_addr`, copying between the backend
+    /// and the guest-physical buffers it describes, until every descriptor
+    /// requested by the last DMA command has been consumed or the table's
+    /// `eot` entry is reached.
+    fn run_dma(&mut self, gpm: &mut GuestPhysMemorySet) -> HyperResult {
+        let Some((mut lba, mut sectors, is_write)) = self.pending_dma.take() else {
+            return Ok(());
+        };
+        if !self.bm_command.get_bit(BM_CMD_START) {
+            self.pending_dma = Some((lba, sectors, is_write));
+            return Ok(());
+        }
+
+        let mut prd_gpa = self.bm_prdt_addr as u64;
+        let mut buf = [0u8; SECTOR_SIZE];
+        'prdt: loop {
+            let raw = super::read_guest_bytes(gpm, prd_gpa as GuestPhysAddr, 8)?;
+            let prd = Prd {
+                gpa: u32::from_le_bytes(raw[0..4].try_into().unwrap()) as u64,
+                len: {
+                    let len = u16::from_le_bytes(raw[4..6].try_into().unwrap());
+                    if len == 0 { 0x10000 } else { len as usize }
+                },
+                eot: raw[7] & 0x80 != 0,
+            };
+
+            let mut consumed = 0;
+            while consumed + SECTOR_SIZE <= prd.len && sectors > 0 {
+                let chunk_gpa = (prd.gpa as usize + consumed) as GuestPhysAddr;
+                if is_write {
+                    let bytes = super::read_guest_bytes(gpm, chunk_gpa, SECTOR_SIZE)?;
+                    buf.copy_from_slice(&bytes);
+                    self.backend.write_sector(lba, &buf)?;
+                } else {
+                    self.backend.read_sector(lba, &mut buf)?;
+                    super::write_guest_bytes(gpm, chunk_gpa, &buf)?;
+                }
+                lba += 1;
+                sectors -= 1;
+                consumed += SECTOR_SIZE;
+            }
+
+            if sectors == 0 || prd.eot {
+                break 'prdt;
+            }
+            prd_gpa += 8;
+        }
+
+        self.bm_command.set_bit(BM_CMD_START, false);
+        self.bm_status |= 0x04; // interrupt bit.
+        self.status = 1 << STATUS_DRDY;
+        self.irq_pending = true;
+        Ok(())
+    }
+
+    fn poll_irq(&mut self, vcpu: &mut VCpu) -> HyperResult {
+        // Device control register bit 1 is nIEN: while set, the guest has
+        // asked this channel not to assert its line at all.
+        const DEVICE_CONTROL_NIEN: usize = 1;
+        if self.irq_pending && !self.device_control.get_bit(DEVICE_CONTROL_NIEN) {
+            self.irq_pending = false;
+            self.irq.trigger(vcpu)?;
+        }
+        Ok(())
+    }
+}
+
+/// Command block: data, error/features, sector count, LBA low/mid/high,
+/// drive/head, status/command (0x1f0-0x1f7).
+pub struct IdeCommandBlock(Arc<Mutex<IdeController>>);
+
+impl IdeCommandBlock {
+    pub fn new(controller: Arc<Mutex<IdeController>>) -> Self {
+        Self(controller)
+    }
+}
+
+impl PioOps for IdeCommandBlock {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        0x1f0..0x1f8
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        self.0.lock().read_command_block(port)
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        self.0.lock().write_command_block(port, value)
+    }
+
+    fn level(&self) -> Option<bool> {
+        Some(self.0.lock().irq_pending)
+    }
+
+    fn poll_irq(&mut self, vcpu: &mut VCpu) -> HyperResult {
+        self.0.lock().poll_irq(vcpu)
+    }
+}
+
+/// Control block: alternate status / device control (0x3f6).
+pub struct IdeControlBlock(Arc<Mutex<IdeController>>);
+
+impl IdeControlBlock {
+    pub fn new(controller: Arc<Mutex<IdeController>>) -> Self {
+        Self(controller)
+    }
+}
+
+impl PioOps for IdeControlBlock {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        0x3f6..0x3f7
+    }
+
+    fn read(&mut self, _port: u16, _access_size: u8) -> HyperResult<u32> {
+        Ok(self.0.lock().read_control_block())
+    }
+
+    fn write(&mut self, _port: u16, _access_size: u8, value: u32) -> HyperResult {
+        self.0.lock().write_control_block(value);
+        Ok(())
+    }
+}
+
+/// Bus-master DMA register block: command, status, PRDT pointer. Laid out
+/// like PIIX's (and at the same 8-byte width) even though it isn't sitting
+/// behind a real PCI BAR in this tree - see the module doc.
+pub struct IdeBusMaster(Arc<Mutex<IdeController>>);
+
+impl IdeBusMaster {
+    /// `base` would normally come from a PCI BAR; it's just a fixed legacy
+    /// port range here. See the module doc.
+    pub const PORT_BASE: u16 = 0xfe00;
+
+    pub fn new(controller: Arc<Mutex<IdeController>>) -> Self {
+        Self(controller)
+    }
+}
+
+impl PioOps for IdeBusMaster {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        Self::PORT_BASE..Self::PORT_BASE + 8
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        self.0.lock().read_bus_master(port - Self::PORT_BASE)
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        self.0.lock().write_bus_master(port - Self::PORT_BASE, value)
+    }
+
+    fn poll_irq(&mut self, vcpu: &mut VCpu) -> HyperResult {
+        self.0.lock().poll_irq(vcpu)
+    }
+
+    fn poll_dma(&mut self, gpm: &mut GuestPhysMemorySet) -> HyperResult {
+        self.0.lock().run_dma(gpm)
+    }
+}
@@ -0,0 +1,165 @@
+//! Emulated IOAPIC. (ref: Intel 82093AA datasheet, and SDM Vol. 3A Ch. 10 for
+//! the vector/delivery-mode fields the redirection table shares with the
+//! local APIC's LVT entries.)
+//!
+//! Exposes the classic indirect MMIO interface: a 32-bit IOREGSEL selects a
+//! register, and IOWIN reads/writes it. Only IOAPICID/IOAPICVER and the 24
+//! redirection-table entries are implemented; IOAPICARB always reads back 0.
+
+use alloc::vec::Vec;
+use bit_field::BitField;
+use hypercraft::{GuestPhysAddr, HyperError, HyperResult};
+use pci::util::byte_code::ByteCode;
+
+use crate::hv::vmx::device_emu::Interrupt;
+use crate::hv::vmx::device_emu::MmioOps;
+use crate::hv::vmx::VCpu;
+
+const REG_IOAPICID: u32 = 0x00;
+const REG_IOAPICVER: u32 = 0x01;
+const REG_IOAPICARB: u32 = 0x02;
+const REG_REDTBL_BASE: u32 = 0x10;
+
+/// Number of redirection-table entries (and thus input pins); matches the
+/// 82093AA and what most BIOSes/OSes assume for a single IOAPIC.
+const NUM_PINS: usize = 24;
+
+/// Redirection-table entry bit layout, within the 64-bit value built from
+/// the pair of 32-bit registers at `REG_REDTBL_BASE + 2*pin` (low) and
+/// `+ 2*pin + 1` (high).
+const VECTOR: core::ops::Range<usize> = 0..8;
+const MASKED: usize = 16;
+
+/// Fixed-layout capture of [`IoApic`]'s mutable state for
+/// [`MmioOps::snapshot`]/[`MmioOps::restore`]; `base` is excluded, same
+/// reasoning as [`super::i8259_pic::I8259Pic`]'s snapshot.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct IoApicSnapshot {
+    ioregsel: u32,
+    id: u32,
+    redir_table: [u64; NUM_PINS],
+}
+
+impl ByteCode for IoApicSnapshot {}
+
+pub struct IoApic {
+    base: GuestPhysAddr,
+    ioregsel: u32,
+    id: u32,
+    /// One 64-bit redirection-table entry per input pin; bit 16 (mask)
+    /// starts set, same as real hardware after reset.
+    redir_table: [u64; NUM_PINS],
+}
+
+impl IoApic {
+    pub fn new(base: GuestPhysAddr) -> Self {
+        Self {
+            base,
+            ioregsel: 0,
+            id: 0,
+            redir_table: [1 << MASKED; NUM_PINS],
+        }
+    }
+
+    fn read_register(&self, index: u32) -> u32 {
+        match index {
+            REG_IOAPICID => self.id << 24,
+            // Version 0x11 (matches the 82093AA), max redirection entry
+            // index in bits 16..24.
+            REG_IOAPICVER => ((NUM_PINS as u32 - 1) << 16) | 0x11,
+            REG_IOAPICARB => 0,
+            reg if reg >= REG_REDTBL_BASE => {
+                let pin = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(&entry) = self.redir_table.get(pin) else {
+                    return 0;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    entry as u32
+                } else {
+                    (entry >> 32) as u32
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, index: u32, value: u32) {
+        match index {
+            REG_IOAPICID => self.id = value.get_bits(24..28),
+            reg if reg >= REG_REDTBL_BASE => {
+                let pin = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(entry) = self.redir_table.get_mut(pin) else {
+                    return;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    *entry = (*entry & !0xffff_ffff) | value as u64;
+                } else {
+                    *entry = (*entry & 0xffff_ffff) | ((value as u64) << 32);
+                }
+            }
+            // IOAPICVER/IOAPICARB are read-only.
+            _ => {}
+        }
+    }
+}
+
+impl MmioOps for IoApic {
+    fn mmio_range(&self) -> core::ops::Range<GuestPhysAddr> {
+        self.base..self.base + 0x20
+    }
+
+    fn read(&mut self, addr: GuestPhysAddr, _access_size: u8) -> HyperResult<u64> {
+        match addr - self.base {
+            0x00 => Ok(self.ioregsel as u64),
+            0x10 => Ok(self.read_register(self.ioregsel) as u64),
+            _ => Err(HyperError::InvalidParam),
+        }
+    }
+
+    fn write(&mut self, addr: GuestPhysAddr, _access_size: u8, value: u64) -> HyperResult {
+        match addr - self.base {
+            0x00 => self.ioregsel = value as u32,
+            0x10 => self.write_register(self.ioregsel, value as u32),
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        IoApicSnapshot {
+            ioregsel: self.ioregsel,
+            id: self.id,
+            redir_table: self.redir_table,
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HyperResult {
+        let snap = IoApicSnapshot::from_bytes(data).ok_or(HyperError::InvalidParam)?;
+        self.ioregsel = snap.ioregsel;
+        self.id = snap.id;
+        self.redir_table = snap.redir_table;
+        Ok(())
+    }
+}
+
+/// Lets emulated devices assert a pin without reaching into the redirection
+/// table themselves: `irq` is the IOAPIC input pin, same numbering as
+/// `I8259Pic`'s (the two overlap for the legacy ISA lines).
+impl Interrupt for spin::Mutex<IoApic> {
+    fn trigger(&self, vcpu: &mut VCpu, irq: u8) -> HyperResult {
+        let ioapic = self.lock();
+        let Some(&entry) = ioapic.redir_table.get(irq as usize) else {
+            return Err(HyperError::InvalidParam);
+        };
+        if entry.get_bit(MASKED) {
+            return Ok(());
+        }
+        let vector = entry.get_bits(VECTOR) as u8;
+        drop(ioapic);
+        vcpu.queue_event(vector, None);
+        Ok(())
+    }
+}
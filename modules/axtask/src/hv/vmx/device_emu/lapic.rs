@@ -3,17 +3,10 @@
 #![allow(dead_code)]
 
 use alloc::sync::Arc;
-use alloc::vec;
-use core::sync::atomic::{AtomicBool, Ordering};
-use cfg_if::cfg_if;
-use spin::once::Once;
-use x2apic::lapic::IpiAllShorthand::AllExcludingSelf;
-use axconfig::SMP;
-use crate::hv::vmx::smp::{DeliveryMode, Icr};
+use core::sync::atomic::AtomicBool;
+use crate::hv::vmx::smp::Icr;
 use hypercraft::{HyperError, HyperResult, VCpu as HVCpu};
-use hypercraft::smp::{broadcast_message, Message, Signal};
 use crate::hv::vcpu::VirtCpu;
-use crate::hv::vmx::HV_IPI;
 
 
 pub static BOOT_VEC: AtomicBool = AtomicBool::new(false);
@@ -114,24 +107,6 @@ fn handle_ap_events(vcpu: &Arc<VirtCpu>, value: u64) -> HyperResult {
     let icr = Icr(value);
     debug!("icr: {:?}", icr);
     debug!("in icr value:  {:X}H", value);
-    let mode = DeliveryMode::try_from(icr.delivery_mode()).unwrap();
-    match mode {
-        DeliveryMode::Fixed => todo!(),
-        DeliveryMode::LowPriority => todo!(),
-        DeliveryMode::SMI => todo!(),
-        DeliveryMode::NMI => todo!(),
-        DeliveryMode::INIT => {
-            debug!("vm send init ipi");
-            let vm = vcpu.vm().ok_or(HyperError::Internal)?;
-            vm.lock().send_init_to_aps();
-            Ok(())
-        }
-        DeliveryMode::StartUp => {
-            debug!("vm start aps");
-            let entry = (icr.vector() as usize) << 12;
-            let vm = vcpu.vm().ok_or(HyperError::Internal)?;
-            vm.lock().start_aps(entry);
-            Ok(())
-        }
-    }
+    let vm = vcpu.vm().ok_or(HyperError::Internal)?;
+    vm.lock().deliver_ipi(vcpu.vcpu_id(), icr)
 }
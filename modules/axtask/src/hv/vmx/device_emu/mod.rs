@@ -1,21 +1,73 @@
+//! Emulated x86 legacy devices (PIC, IOAPIC, local APIC, UART, RTC, a
+//! PIIX-style IDE/ATA controller, the `0x604` shutdown port) and the
+//! [`DeviceList`]/[`X64VirtDevices`] trap
+//! dispatch that routes port I/O and MMIO exits to them.
+//!
+//! [`PioOps`]/[`MmioOps`] also carry [`PioOps::snapshot`]/[`PioOps::restore`]
+//! for suspend/resume and live migration, aggregated per-VM by
+//! [`DeviceList::snapshot`]/[`DeviceList::restore`]; paired with
+//! [`crate::hv::snapshot`]'s per-vCPU [`crate::hv::snapshot::VCpuState`],
+//! that covers everything [`X64VirtDevices`] owns. There is no PIT, no
+//! combined "system-control `Bundle`" device, and no virtio PCI bus in this
+//! tree (those live in `modules/axvm`'s separate device-emulation stack) -
+//! nothing here needed snapshot support for them.
+
 mod i8259_pic;
+mod ide;
+mod ioapic;
 mod lapic;
+mod msi;
+mod rtc;
 mod uart16550;
 mod shutdown;
+mod watchdog;
 
 
 extern crate alloc;
 
 use alloc::{sync::Arc, vec, vec::Vec};
 use spin::Mutex;
-use hypercraft::{HyperError, HyperResult, VmxExitInfo};
+use hypercraft::{GuestPhysAddr, GuestVirtAddr, HyperError, HyperResult, VmxExitInfo};
+use page_table_entry::MappingFlags;
 use crate::hv::HyperCraftHalImpl;
+use crate::hv::mm::GuestPhysMemorySet;
 use crate::hv::vcpu::VirtCpu;
 use crate::hv::vmx::device_emu::i8259_pic::I8259Pic;
+use crate::hv::vmx::device_emu::rtc::Rtc;
 use crate::hv::vmx::device_emu::uart16550::Uart16550;
+use crate::hv::vmx::device_emu::watchdog::VmWatchdog;
+use crate::hv::vmx::walk::translate_gva;
 use crate::hv::vmx::VCpu;
 
+pub use self::ioapic::IoApic;
 pub use self::lapic::VirtLocalApic;
+pub use self::msi::Msi;
+pub use self::uart16550::Uart16550;
+pub use self::watchdog::{WatchdogState, DEFAULT_TIMEOUT_SECS};
+
+/// A routable interrupt source: something an emulated or passed-through
+/// device can `trigger()` without caring whether the line ends up injected
+/// through the legacy [`I8259Pic`], the [`IoApic`]'s redirection table, or a
+/// device's own [`Msi`] capability. `irq` means whatever the implementor
+/// says it means (an i8259/IOAPIC input pin number for the first two; for
+/// [`Msi`] it's unused, since a function only ever has the one message this
+/// stub models).
+pub trait Interrupt: Send + Sync {
+    fn trigger(&self, vcpu: &mut VCpu, irq: u8) -> HyperResult;
+}
+
+impl Interrupt for Mutex<I8259Pic> {
+    fn trigger(&self, vcpu: &mut VCpu, irq: u8) -> HyperResult {
+        let mut pic = self.lock();
+        pic.set_irq_level(irq, true);
+        pic.raise_irq(irq);
+        if let Some(vector) = pic.next_pending_vector() {
+            drop(pic);
+            vcpu.queue_event(vector, None);
+        }
+        Ok(())
+    }
+}
 
 pub trait PioOps: Send + Sync {
     /// Port range.
@@ -24,6 +76,117 @@ pub trait PioOps: Send + Sync {
     fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32>;
     /// Write operation
     fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult;
+    /// Current level of this device's interrupt line, if it drives one.
+    /// Consulted on EOI resample; `None` means the device is edge-triggered
+    /// or has no line of its own.
+    fn level(&self) -> Option<bool> {
+        None
+    }
+    /// Called after every read/write to this device, once the device's own
+    /// state has settled, so a device driving a level-triggered line (see
+    /// [`IrqLevelEvent`]) can inject it into the guest right away instead of
+    /// waiting for some later resample.
+    fn poll_irq(&mut self, _vcpu: &mut VCpu) -> HyperResult {
+        Ok(())
+    }
+    /// Called after every read/write to this device with the owning VM's
+    /// [`GuestPhysMemorySet`], for a device whose register writes can kick
+    /// off a guest-memory transfer it can't perform from [`Self::write`]
+    /// alone (`write` has no `gpm` to translate a guest-physical address
+    /// with) - today just [`ide::IdeBusMaster`]'s PRDT walk. Runs right
+    /// after [`Self::poll_irq`], so a device that completes a transfer here
+    /// can still raise its line on the same I/O exit.
+    fn poll_dma(&mut self, _gpm: &mut GuestPhysMemorySet) -> HyperResult {
+        Ok(())
+    }
+    /// Capture this device's mutable state as an opaque blob, for
+    /// suspend/resume or live migration; paired with [`Self::restore`] and
+    /// aggregated across a whole [`DeviceList`] by [`DeviceList::snapshot`].
+    /// Devices with no state of their own (pure wiring, or a host
+    /// passthrough like [`shutdown::Shutdown`]) can leave this at the
+    /// default empty blob.
+    fn snapshot(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    /// Reload state previously captured by [`Self::snapshot`], into a device
+    /// of the same concrete type already constructed with this device's
+    /// identity (port range, PIC wiring, etc.) - the blob only needs to
+    /// carry the mutable fields on top of that.
+    fn restore(&mut self, _data: &[u8]) -> HyperResult {
+        Ok(())
+    }
+}
+
+pub trait MmioOps: Send + Sync {
+    /// Guest-physical address range backing this device's MMIO window.
+    fn mmio_range(&self) -> core::ops::Range<GuestPhysAddr>;
+    /// Read operation
+    fn read(&mut self, addr: GuestPhysAddr, access_size: u8) -> HyperResult<u64>;
+    /// Write operation
+    fn write(&mut self, addr: GuestPhysAddr, access_size: u8, value: u64) -> HyperResult;
+    /// Current level of this device's interrupt line, if it drives one.
+    fn level(&self) -> Option<bool> {
+        None
+    }
+    /// See [`PioOps::snapshot`].
+    fn snapshot(&self) -> Vec<u8> {
+        Vec::new()
+    }
+    /// See [`PioOps::restore`].
+    fn restore(&mut self, _data: &[u8]) -> HyperResult {
+        Ok(())
+    }
+}
+
+/// A level-triggered interrupt line that must be re-asserted until the guest
+/// services and deasserts it, rather than a one-shot edge.
+///
+/// Devices like the UART and RTC `trigger()` this when their line goes high,
+/// and the owning I8259 calls `notify_resample()` whenever the guest writes
+/// EOI; if the device still reports the line as high, the interrupt is
+/// re-injected instead of going quiet.
+pub struct IrqLevelEvent {
+    irq: u8,
+    pic: Arc<Mutex<I8259Pic>>,
+    resample: alloc::boxed::Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl IrqLevelEvent {
+    pub fn new(
+        irq: u8,
+        pic: Arc<Mutex<I8259Pic>>,
+        resample: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            irq,
+            pic,
+            resample: alloc::boxed::Box::new(resample),
+        }
+    }
+
+    /// Raise the line and inject it through the PIC, unless the guest has
+    /// masked it.
+    pub fn trigger(&self, vcpu: &mut VCpu) -> HyperResult {
+        let mut pic = self.pic.lock();
+        pic.set_irq_level(self.irq, true);
+        if !pic.is_masked(self.irq) {
+            let vector = pic.vector_for(self.irq);
+            drop(pic);
+            vcpu.queue_event(vector, None);
+        }
+        Ok(())
+    }
+
+    /// Invoked when the guest writes EOI: if the device still holds the
+    /// line high, re-inject it; otherwise mark it deasserted.
+    pub fn notify_resample(&self, vcpu: &mut VCpu) -> HyperResult {
+        if (self.resample)() {
+            self.trigger(vcpu)
+        } else {
+            self.pic.lock().set_irq_level(self.irq, false);
+            Ok(())
+        }
+    }
 }
 
 pub struct VirtDeviceList {
@@ -41,50 +204,138 @@ impl VirtDeviceList {
 pub struct X64VirtDevices {
     devices: DeviceList,
     pic: [Arc<Mutex<I8259Pic>>; 2],
+    /// Standard x86 IOAPIC MMIO base; shared so device-emulation code can
+    /// reach it as an [`Interrupt`] for guests whose devices are routed
+    /// through it instead of the legacy i8259 pair.
+    ioapic: Arc<Mutex<IoApic>>,
+    /// COM1, kept as its concrete type alongside its type-erased entry in
+    /// `devices` so [`crate::hv::vmx::gdb::GdbStub`]/[`crate::hv::vmx::monitor::Monitor`]
+    /// can drive it directly as a host debug transport instead of going
+    /// through the guest-facing `PioOps` trap path.
+    debug_uart: Arc<Mutex<Uart16550>>,
 }
 
 impl X64VirtDevices {
-    pub fn new() -> HyperResult<Self> {
+    /// `watchdog` is `Some` only for a VM booted with `VmConfig::watchdog_enabled`
+    /// - every vCPU gets its own [`VmWatchdog`] front-end onto the same
+    /// shared [`WatchdogState`], since whichever vCPU's guest thread happens
+    /// to pet it is the one whose `EPT_VIOLATION` trap reaches this device
+    /// list.
+    pub fn new(watchdog: Option<Arc<WatchdogState>>) -> HyperResult<Self> {
         let pic: [Arc<Mutex<I8259Pic>>; 2] = [
             Arc::new(Mutex::new(I8259Pic::new(0x20))),
             Arc::new(Mutex::new(I8259Pic::new(0xA0))),
         ];
         let mut devices = DeviceList::new();
 
+        // Standard PC-compatible wiring: COM1/COM3 share IRQ4, COM2/COM4
+        // share IRQ3, both on the master i8259.
+        const COM_IRQ_3_4: u8 = 4;
+        const COM_IRQ_2_3: u8 = 3;
+        let debug_uart = Arc::new(Mutex::new(Uart16550::new(0x3f8, COM_IRQ_3_4, pic[0].clone()))); // COM1
         let mut pmio_devices: Vec<Arc<Mutex<dyn PioOps>>> = vec![
             // 0x604
             Arc::new(Mutex::new(shutdown::Shutdown)),
             // These are all fully emulated consoles!!!
             // 0x3f8, 0x3f8 + 8
-            Arc::new(Mutex::new(<Uart16550>::new(0x3f8))), // COM1
+            debug_uart.clone(), // COM1
             // 0x2f8, 0x2f8 + 8
-            Arc::new(Mutex::new(<Uart16550>::new(0x2f8))), // COM2
+            Arc::new(Mutex::new(Uart16550::new(0x2f8, COM_IRQ_2_3, pic[0].clone()))), // COM2
             // 0x3e8, 0x3e8 + 8
-            Arc::new(Mutex::new(<Uart16550>::new(0x3e8))), // COM3
+            Arc::new(Mutex::new(Uart16550::new(0x3e8, COM_IRQ_3_4, pic[0].clone()))), // COM3
             // 0x2e8, 0x2e8 + 8
-            Arc::new(Mutex::new(<Uart16550>::new(0x2e8))), // COM4
+            Arc::new(Mutex::new(Uart16550::new(0x2e8, COM_IRQ_2_3, pic[0].clone()))), // COM4
             // 0x20, 0x20 + 2
             pic[0].clone(), // PIC1
             // 0xa0, 0xa0 + 2
             pic[1].clone(), // PIC2
+            // 0x70, 0x70 + 2
+            Arc::new(Mutex::new(Rtc::new(0x70, rtc::RTC_IRQ, pic[1].clone()))), // CMOS/RTC, IRQ8 on the slave PIC
         ];
+
+        // Primary IDE channel, IRQ14 on the slave PIC. No real disk image is
+        // wired in yet - `ide::HostMemoryBackend::new(0, 0)` reports zero
+        // sectors, so a guest that probes it finds a channel with no media
+        // rather than one that doesn't exist; attaching an actual boot disk
+        // needs a host-physical base/size to plug into `HostMemoryBackend`,
+        // which nothing in `VmConfig` carries yet (see the module doc on
+        // `ide`).
+        let ide = Arc::new(Mutex::new(ide::IdeController::new(
+            alloc::boxed::Box::new(ide::HostMemoryBackend::new(0, 0)),
+            pic[1].clone(),
+        )));
+        pmio_devices.push(Arc::new(Mutex::new(ide::IdeCommandBlock::new(ide.clone())))); // 0x1f0-0x1f7
+        pmio_devices.push(Arc::new(Mutex::new(ide::IdeControlBlock::new(ide.clone())))); // 0x3f6
+        pmio_devices.push(Arc::new(Mutex::new(ide::IdeBusMaster::new(ide)))); // bus-master DMA
+
         devices.add_port_io_devices(&mut pmio_devices);
-        Ok(Self { devices, pic })
+
+        // Standard x86 IOAPIC MMIO base (ref: ACPI MADT "IO APIC Address").
+        const IOAPIC_BASE: GuestPhysAddr = 0xfec0_0000;
+        let ioapic = Arc::new(Mutex::new(IoApic::new(IOAPIC_BASE)));
+        let mut mmio_devices: Vec<Arc<Mutex<dyn MmioOps>>> = vec![ioapic.clone()];
+        if let Some(watchdog) = watchdog {
+            mmio_devices.push(Arc::new(Mutex::new(VmWatchdog::new(watchdog))));
+        }
+        devices.add_mmio_devices(&mut mmio_devices);
+
+        Ok(Self { devices, pic, ioapic, debug_uart })
     }
-    pub fn handle_io_instruction(&mut self, vcpu: &mut VCpu, exit_info: &VmxExitInfo) -> HyperResult {
-        self.devices.handle_io_instruction(vcpu, exit_info)
+
+    /// The emulated IOAPIC, shared as an [`Interrupt`] for device-emulation
+    /// code that routes through it instead of the legacy i8259 pair.
+    pub fn ioapic(&self) -> Arc<Mutex<IoApic>> {
+        self.ioapic.clone()
+    }
+    pub fn handle_io_instruction(
+        &mut self,
+        vcpu: &mut VCpu,
+        exit_info: &VmxExitInfo,
+        gpm: &mut GuestPhysMemorySet,
+    ) -> HyperResult {
+        self.devices.handle_io_instruction(vcpu, exit_info, gpm)
+    }
+
+    pub fn handle_mmio_instruction(&mut self, vcpu: &mut VCpu, exit_info: &VmxExitInfo) -> HyperResult {
+        self.devices.handle_mmio_instruction(vcpu, exit_info)
+    }
+
+    /// COM1, shared as a host-side transport for the debug monitor/gdb stub.
+    pub fn debug_uart(&self) -> Arc<Mutex<Uart16550>> {
+        self.debug_uart.clone()
+    }
+
+    /// Capture every device's state; see [`DeviceList::snapshot`].
+    pub fn snapshot(&self) -> DeviceListSnapshot {
+        self.devices.snapshot()
+    }
+
+    /// Reload state captured by [`Self::snapshot`]; see [`DeviceList::restore`].
+    pub fn restore(&self, snapshot: &DeviceListSnapshot) -> HyperResult {
+        self.devices.restore(snapshot)
     }
 }
 
 
+/// Per-device state blobs captured by [`DeviceList::snapshot`], keyed by
+/// each device's own identity range so [`DeviceList::restore`] can find the
+/// matching live device after rehydration without relying on `Vec` order
+/// (which isn't guaranteed stable across a process restart).
+pub struct DeviceListSnapshot {
+    port_io: Vec<(core::ops::Range<u16>, Vec<u8>)>,
+    mmio: Vec<(core::ops::Range<GuestPhysAddr>, Vec<u8>)>,
+}
+
 pub struct DeviceList {
     port_io_devices: Vec<Arc<Mutex<dyn PioOps>>>,
+    mmio_devices: Vec<Arc<Mutex<dyn MmioOps>>>,
 }
 
 impl DeviceList {
     pub fn new() -> Self {
         Self {
             port_io_devices: vec![],
+            mmio_devices: vec![],
         }
     }
     pub fn add_port_io_device(&mut self, device: Arc<Mutex<dyn PioOps>>) {
@@ -102,10 +353,119 @@ impl DeviceList {
             .cloned()
         // todo
     }
-    pub fn handle_io_instruction(&mut self, vcpu: &mut VCpu, exit_info: &VmxExitInfo) -> HyperResult {
+
+    pub fn add_mmio_device(&mut self, device: Arc<Mutex<dyn MmioOps>>) {
+        self.mmio_devices.push(device)
+    }
+
+    pub fn add_mmio_devices(&mut self, devices: &mut Vec<Arc<Mutex<dyn MmioOps>>>) {
+        self.mmio_devices.append(devices)
+    }
+
+    pub fn find_mmio_device(&self, addr: GuestPhysAddr) -> Option<Arc<Mutex<dyn MmioOps>>> {
+        self.mmio_devices
+            .iter()
+            .find(|dev| dev.lock().mmio_range().contains(&addr))
+            .cloned()
+    }
+
+    /// Capture every device's state via [`PioOps::snapshot`]/[`MmioOps::snapshot`],
+    /// for suspend/resume or live migration. Stateless or host-wired devices
+    /// (e.g. [`shutdown::Shutdown`], [`watchdog::VmWatchdog`] - its real
+    /// state lives in the shared [`WatchdogState`] this VM already threads
+    /// through separately) just contribute an empty blob via the trait
+    /// defaults.
+    pub fn snapshot(&self) -> DeviceListSnapshot {
+        DeviceListSnapshot {
+            port_io: self
+                .port_io_devices
+                .iter()
+                .map(|dev| {
+                    let dev = dev.lock();
+                    (dev.port_range(), dev.snapshot())
+                })
+                .collect(),
+            mmio: self
+                .mmio_devices
+                .iter()
+                .map(|dev| {
+                    let dev = dev.lock();
+                    (dev.mmio_range(), dev.snapshot())
+                })
+                .collect(),
+        }
+    }
+
+    /// Reload state captured by [`Self::snapshot`] into this list's devices,
+    /// matched up by identity range. A range present in the snapshot but no
+    /// longer backed by a live device (or vice versa) is silently skipped:
+    /// the device set is fixed per-VM today, so this only guards against a
+    /// snapshot taken on a differently-configured build.
+    pub fn restore(&self, snapshot: &DeviceListSnapshot) -> HyperResult {
+        for (range, data) in &snapshot.port_io {
+            if let Some(dev) = self.find_port_io_device(range.start) {
+                dev.lock().restore(data)?;
+            }
+        }
+        for (range, data) in &snapshot.mmio {
+            if let Some(dev) = self.find_mmio_device(range.start) {
+                dev.lock().restore(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn handle_mmio_instruction(&mut self, vcpu: &mut VCpu, exit_info: &VmxExitInfo) -> HyperResult {
+        let fault_info = vcpu.nested_page_fault_info()?;
+        if let Some(dev) = self.find_mmio_device(fault_info.fault_guest_paddr) {
+            Self::handle_mmio_instruction_to_device(vcpu, exit_info, dev)
+        } else {
+            Err(HyperError::Internal)
+        }
+    }
+
+    fn handle_mmio_instruction_to_device(
+        vcpu: &mut VCpu,
+        exit_info: &VmxExitInfo,
+        device: Arc<Mutex<dyn MmioOps>>,
+    ) -> HyperResult {
+        let fault_info = vcpu.nested_page_fault_info()?;
+        trace!(
+            "VM exit: EPT violation @ {:#x}: fault_paddr={:#x}, access_flags=({:?})",
+            exit_info.guest_rip,
+            fault_info.fault_guest_paddr,
+            fault_info.access_flags,
+        );
+
+        // Width of the trapping access is not carried in the EPT exit
+        // qualification on Intel CPUs, so (as with port I/O) we only ever
+        // shuttle the value through RAX; a future instruction-decode pass can
+        // refine this to the actual operand register and size.
+        const ACCESS_SIZE: u8 = 4;
+        if fault_info.access_flags.contains(MappingFlags::WRITE) {
+            let rax = vcpu.regs().rax;
+            let value = rax & 0xffff_ffff;
+            device
+                .lock()
+                .write(fault_info.fault_guest_paddr, ACCESS_SIZE, value)?;
+        } else {
+            let value = device.lock().read(fault_info.fault_guest_paddr, ACCESS_SIZE)?;
+            let rax = &mut vcpu.regs_mut().rax;
+            *rax = value & 0xffff_ffff;
+        }
+        vcpu.advance_rip(exit_info.exit_instruction_length as _)?;
+        Ok(())
+    }
+
+    pub fn handle_io_instruction(
+        &mut self,
+        vcpu: &mut VCpu,
+        exit_info: &VmxExitInfo,
+        gpm: &mut GuestPhysMemorySet,
+    ) -> HyperResult {
         let io_info = vcpu.io_exit_info()?;
         if let Some(dev) = self.find_port_io_device(io_info.port) {
-            Self::handle_io_instruction_to_device(vcpu, exit_info, dev)
+            Self::handle_io_instruction_to_device(vcpu, exit_info, dev, gpm)
         } else {
             Err(HyperError::Internal)
         }
@@ -115,6 +475,7 @@ impl DeviceList {
         vcpu: &mut VCpu,
         exit_info: &VmxExitInfo,
         device: Arc<Mutex<dyn PioOps>>,
+        gpm: &mut GuestPhysMemorySet,
     ) -> HyperResult {
         let io_info = vcpu.io_exit_info().unwrap();
         trace!(
@@ -124,12 +485,7 @@ impl DeviceList {
         );
 
         if io_info.is_string {
-            error!("INS/OUTS instructions are not supported!");
-            return Err(HyperError::NotSupported);
-        }
-        if io_info.is_repeat {
-            error!("REP prefixed I/O instructions are not supported!");
-            return Err(HyperError::NotSupported);
+            return Self::handle_string_io_instruction(vcpu, exit_info, device, gpm);
         }
         if io_info.is_in {
             let value = device.lock().read(io_info.port, io_info.access_size)?;
@@ -158,10 +514,80 @@ impl DeviceList {
                 .lock()
                 .write(io_info.port, io_info.access_size, value)?;
         }
+        device.lock().poll_irq(vcpu)?;
+        device.lock().poll_dma(gpm)?;
+        vcpu.advance_rip(exit_info.exit_instruction_length as _)?;
+        Ok(())
+    }
+
+    /// INS/OUTS, bare or REP-prefixed: unlike the scalar path above, each
+    /// element is shuttled through guest memory instead of RAX - ES:RDI for
+    /// INS, DS:RSI for OUTS - with RCX iterations under REP (just one
+    /// otherwise), RDI/RSI stepping by +-`access_size` per DF in RFLAGS, and
+    /// RCX counted down as it goes. Segment bases are not applied: every
+    /// vCPU here runs in long mode, where DS/ES are always flat, so the
+    /// guest-linear address is just the register value. RIP only advances
+    /// once the whole count has gone through.
+    fn handle_string_io_instruction(
+        vcpu: &mut VCpu,
+        exit_info: &VmxExitInfo,
+        device: Arc<Mutex<dyn PioOps>>,
+        gpm: &mut GuestPhysMemorySet,
+    ) -> HyperResult {
+        let io_info = vcpu.io_exit_info().unwrap();
+        const RFLAGS_DF: u64 = 1 << 10;
+        let step: i64 = if vcpu.rflags() & RFLAGS_DF != 0 {
+            -(io_info.access_size as i64)
+        } else {
+            io_info.access_size as i64
+        };
+        let count = if io_info.is_repeat { vcpu.regs().rcx } else { 1 };
+
+        for _ in 0..count {
+            if io_info.is_in {
+                let gva = vcpu.regs().rdi as GuestVirtAddr;
+                let gpa = translate_gva(vcpu, gpm, gva)?;
+                let value = device.lock().read(io_info.port, io_info.access_size)?;
+                write_guest_bytes(gpm, gpa, &value.to_le_bytes()[..io_info.access_size as usize])?;
+                vcpu.regs_mut().rdi = (vcpu.regs().rdi as i64).wrapping_add(step) as u64;
+            } else {
+                let gva = vcpu.regs().rsi as GuestVirtAddr;
+                let gpa = translate_gva(vcpu, gpm, gva)?;
+                let bytes = read_guest_bytes(gpm, gpa, io_info.access_size as usize)?;
+                let mut buf = [0u8; 4];
+                buf[..bytes.len()].copy_from_slice(&bytes);
+                device
+                    .lock()
+                    .write(io_info.port, io_info.access_size, u32::from_le_bytes(buf))?;
+                vcpu.regs_mut().rsi = (vcpu.regs().rsi as i64).wrapping_add(step) as u64;
+            }
+            if io_info.is_repeat {
+                vcpu.regs_mut().rcx -= 1;
+            }
+            device.lock().poll_irq(vcpu)?;
+        }
         vcpu.advance_rip(exit_info.exit_instruction_length as _)?;
         Ok(())
     }
 }
 
+/// Read `len` bytes of guest RAM at guest-physical address `gpa`, the same
+/// way [`crate::hv::vmx::gdb::Debuggable::gdb_translate`]'s caller does.
+fn read_guest_bytes(gpm: &GuestPhysMemorySet, gpa: GuestPhysAddr, len: usize) -> HyperResult<Vec<u8>> {
+    let hpa = gpm.translate(gpa)?;
+    let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *const u8;
+    Ok((0..len).map(|i| unsafe { ptr.add(i).read() }).collect())
+}
+
+/// Write `data` into guest RAM at guest-physical address `gpa`.
+fn write_guest_bytes(gpm: &mut GuestPhysMemorySet, gpa: GuestPhysAddr, data: &[u8]) -> HyperResult {
+    let hpa = gpm.translate(gpa)?;
+    let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u8;
+    for (i, b) in data.iter().enumerate() {
+        unsafe { ptr.add(i).write(*b) };
+    }
+    Ok(())
+}
+
 
 
@@ -0,0 +1,74 @@
+//! Emulated single-message MSI routing for a passed-through or emulated PCI
+//! function: tracks the (address, data) pair the guest programs into the
+//! function's MSI capability and decodes `data`'s vector field to inject,
+//! the message-signaled counterpart to [`super::ioapic::IoApic`]'s
+//! pin-based redirection table.
+//!
+//! Only single-message MSI is modeled (one address/data pair per function,
+//! no MSI-X-style per-vector table): that covers every device this
+//! hypervisor currently emulates or passes through.
+
+use bit_field::BitField;
+use hypercraft::HyperResult;
+
+use crate::hv::vmx::device_emu::Interrupt;
+use crate::hv::vmx::VCpu;
+
+/// PCI Spec MSI capability data-register vector field (bits 0..8); the
+/// address register (destination APIC ID, redirection hint, ...) isn't
+/// decoded since every guest in this tree is still routed to its own single
+/// vCPU's local APIC rather than an arbitrary destination.
+const VECTOR: core::ops::Range<usize> = 0..8;
+
+pub struct Msi {
+    address: u32,
+    data: u32,
+    masked: bool,
+}
+
+impl Msi {
+    /// A newly attached function's MSI capability starts masked, same as
+    /// real hardware before the guest's driver programs it.
+    pub const fn new() -> Self {
+        Self {
+            address: 0,
+            data: 0,
+            masked: true,
+        }
+    }
+
+    /// Record the (address, data) pair the guest wrote into this function's
+    /// MSI capability.
+    pub fn program(&mut self, address: u32, data: u32) {
+        self.address = address;
+        self.data = data;
+    }
+
+    pub fn set_masked(&mut self, masked: bool) {
+        self.masked = masked;
+    }
+
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    pub fn data(&self) -> u32 {
+        self.data
+    }
+}
+
+/// `irq` is ignored: a function only ever has the one message this stub
+/// models, so whichever caller holds this `Interrupt` already knows which
+/// device it's asserting for.
+impl Interrupt for spin::Mutex<Msi> {
+    fn trigger(&self, vcpu: &mut VCpu, _irq: u8) -> HyperResult {
+        let msi = self.lock();
+        if msi.masked {
+            return Ok(());
+        }
+        let vector = msi.data.get_bits(VECTOR) as u8;
+        drop(msi);
+        vcpu.queue_event(vector, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+//! Emulated MC146818 RTC/CMOS. (ref: https://wiki.osdev.org/CMOS)
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bit_field::BitField;
+use hypercraft::{HyperError, HyperResult};
+use pci::util::byte_code::ByteCode;
+use spin::Mutex;
+use crate::hv::vmx::VCpu;
+use crate::hv::vmx::device_emu::i8259_pic::I8259Pic;
+use crate::hv::vmx::device_emu::{IrqLevelEvent, PioOps};
+
+/// CMOS register indices (ref: MC146818 datasheet).
+const REG_SECONDS: u8 = 0x00;
+const REG_SECONDS_ALARM: u8 = 0x01;
+const REG_MINUTES: u8 = 0x02;
+const REG_MINUTES_ALARM: u8 = 0x03;
+const REG_HOURS: u8 = 0x04;
+const REG_HOURS_ALARM: u8 = 0x05;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+const REG_STATUS_C: u8 = 0x0c;
+const REG_STATUS_D: u8 = 0x0d;
+
+/// Total size of the CMOS NVRAM array (indices 0x00..0x80); the time/alarm/
+/// status registers above alias into the low end of it, the rest
+/// (0x0e..0x80) is plain battery-backed scratch the guest can use for
+/// whatever it likes (boot firmware commonly stashes config bytes there).
+const NVRAM_LEN: usize = 128;
+
+/// Status Register B bit: 1 = 24-hour mode, 0 = 12-hour mode.
+const STATUS_B_24H: usize = 1;
+/// Status Register B bit: 1 = binary mode, 0 = BCD mode.
+const STATUS_B_BINARY: usize = 2;
+/// Status Register B bit: update-ended interrupt enable.
+const STATUS_B_UIE: usize = 4;
+/// Status Register B bit: alarm interrupt enable.
+const STATUS_B_AIE: usize = 5;
+/// Status Register B bit: periodic interrupt enable.
+const STATUS_B_PIE: usize = 6;
+
+/// Status Register C bit: update-ended interrupt flag.
+const STATUS_C_UF: usize = 4;
+/// Status Register C bit: alarm interrupt flag.
+const STATUS_C_AF: usize = 5;
+/// Status Register C bit: periodic interrupt flag.
+const STATUS_C_PF: usize = 6;
+/// Status Register C bit: set whenever any of UF/AF/PF above is, i.e. "some
+/// enabled interrupt source is pending" - what the guest actually checks.
+const STATUS_C_IRQF: usize = 7;
+
+/// An alarm field of `0xc0` or above is the MC146818's "don't care" encoding
+/// (matches every value of that field) rather than a literal BCD/binary 0xc0.
+const ALARM_DONT_CARE: u8 = 0xc0;
+
+/// RTC is conventionally wired to the slave i8259's line 0, i.e. global IRQ8.
+pub const RTC_IRQ: u8 = 0;
+
+/// Fixed-layout capture of [`Rtc`]'s mutable state for
+/// [`PioOps::snapshot`]/[`PioOps::restore`]. `last_update_ns`/
+/// `last_periodic_ns` aren't captured: they're host-clock bookkeeping, not
+/// guest-visible state, and restoring them verbatim on a different host (or
+/// after a long-paused resume) would make `update_pending_interrupts` see a
+/// huge elapsed delta and fire every pending source at once; a restored
+/// `Rtc` just re-bases both off the restoring host's current time instead.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RtcSnapshot {
+    index: u8,
+    nmi_disabled: u8,
+    nvram: [u8; NVRAM_LEN],
+    status_c: u8,
+}
+
+impl Default for RtcSnapshot {
+    fn default() -> Self {
+        Self { index: 0, nmi_disabled: 0, nvram: [0; NVRAM_LEN], status_c: 0 }
+    }
+}
+
+impl ByteCode for RtcSnapshot {}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// A point in (host-approximated) wall-clock time, broken down the way the
+/// CMOS registers expose it.
+struct WallClock {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+impl WallClock {
+    /// Derive a civil date/time from nanoseconds since the HAL's epoch.
+    ///
+    /// The HAL does not expose a true wall-clock source, so (as elsewhere in
+    /// this module) we treat `current_time_nanos` as seconds-since-epoch;
+    /// this is enough to give guests a monotonically sane, if not
+    /// externally accurate, CMOS clock.
+    fn now() -> Self {
+        let secs_since_epoch = axhal::time::current_time_nanos() / 1_000_000_000;
+        let days = secs_since_epoch / 86400;
+        let day_secs = secs_since_epoch % 86400;
+
+        let (year, month, day) = civil_from_days(days as i64);
+
+        Self {
+            seconds: (day_secs % 60) as u8,
+            minutes: ((day_secs / 60) % 60) as u8,
+            hours: (day_secs / 3600) as u8,
+            day,
+            month,
+            year: (year % 100) as u8,
+        }
+    }
+}
+
+/// Howard Hinnant's days-from-civil algorithm, inverted: turn a day count
+/// (days since 1970-01-01) into a (year, month, day) triple.
+fn civil_from_days(z: i64) -> (i64, u8, u8) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+/// Emulated MC146818 CMOS RTC, exposed to the guest over the classic
+/// index/data port pair (0x70 selects the register, 0x71 accesses it).
+///
+/// The periodic/alarm/update-ended interrupt sources are only re-evaluated
+/// from [`PioOps::poll_irq`], i.e. whenever the guest itself touches this
+/// device's ports - there is no independent host-side tick driving it (that
+/// would need a per-VM timer registration alongside `crate::hv::timer`,
+/// which no device in this tree other than the watchdog and LAPIC timer use,
+/// and wiring it in means threading `vm_id`/`vcpu_id` through
+/// `X64VirtDevices::new` the same way `WatchdogState` was). In practice this
+/// is enough for firmware/guests that poll status register C after enabling
+/// an interrupt source, just not for a guest that masks IRQ8 at the PIC and
+/// waits to be woken purely by hardware.
+pub struct Rtc {
+    port_base: u16,
+    /// CMOS register currently selected via the index port; bit 7 of that
+    /// port (NMI-disable) is tracked separately in `nmi_disabled` below, not
+    /// folded into this.
+    index: u8,
+    /// Bit 7 of the last byte written to the index port: NMI is disabled
+    /// while this is set. Emulation has no NMI path to gate, but real
+    /// firmware toggles this bit on every CMOS access and expects to read
+    /// back what it wrote, so it's tracked as its own bit of state.
+    nmi_disabled: bool,
+    /// Battery-backed CMOS bytes, indexed by register number. The time/
+    /// alarm/status registers below are handled specially in
+    /// `read_register`/`write_register`; everything else is plain
+    /// general-purpose storage.
+    nvram: [u8; NVRAM_LEN],
+    /// Status Register C: read-and-clear interrupt flag register.
+    status_c: u8,
+    irq: IrqLevelEvent,
+    /// Set whenever `status_c` gains a newly-pending cause, so `poll_irq`
+    /// knows to trigger the line instead of relying on some later resample.
+    needs_trigger: bool,
+    /// Host time (nanoseconds) `update_ended`/`periodic` last fired, so
+    /// `poll_irq` only raises each at most once per its real period.
+    last_update_ns: u64,
+    last_periodic_ns: u64,
+}
+
+impl Rtc {
+    pub fn new(port_base: u16, irq: u8, pic: Arc<Mutex<I8259Pic>>) -> Self {
+        let now_ns = axhal::time::current_time_nanos();
+        Self {
+            port_base,
+            index: 0,
+            nmi_disabled: false,
+            nvram: [0; NVRAM_LEN],
+            status_c: 0,
+            irq: IrqLevelEvent::new(irq, pic, || false),
+            needs_trigger: false,
+            last_update_ns: now_ns,
+            last_periodic_ns: now_ns,
+        }
+    }
+
+    fn status_b(&self) -> u8 {
+        self.nvram[REG_STATUS_B as usize]
+    }
+
+    fn encode(&self, binary_value: u8) -> u8 {
+        if self.status_b().get_bit(STATUS_B_BINARY) {
+            binary_value
+        } else {
+            to_bcd(binary_value)
+        }
+    }
+
+    /// Periodic interrupt rate, decoded from Status Register A's rate-select
+    /// bits the way the MC146818 datasheet specifies: `None` below 3 (the
+    /// two fastest codes are reserved/disabled), otherwise `32768 >> (rate - 1)` Hz.
+    fn periodic_period_ns(&self) -> Option<u64> {
+        let rate = self.nvram[REG_STATUS_A as usize] & 0x0f;
+        if rate < 3 {
+            return None;
+        }
+        Some(1_000_000_000u64 * (1u64 << (rate - 1)) / 32768)
+    }
+
+    fn alarm_matches(&self, alarm_reg: u8, current_binary: u8) -> bool {
+        let raw = self.nvram[alarm_reg as usize];
+        raw >= ALARM_DONT_CARE || raw == self.encode(current_binary)
+    }
+
+    /// Re-evaluate the periodic/update-ended/alarm sources against elapsed
+    /// host time, latching any newly-fired cause into Status Register C.
+    /// See the `poll_irq` doc on [`Rtc`] for why this is I/O-driven.
+    fn update_pending_interrupts(&mut self) {
+        let now_ns = axhal::time::current_time_nanos();
+        let status_b = self.status_b();
+
+        if let Some(period_ns) = self.periodic_period_ns() {
+            if status_b.get_bit(STATUS_B_PIE) && now_ns.wrapping_sub(self.last_periodic_ns) >= period_ns {
+                self.last_periodic_ns = now_ns;
+                self.status_c.set_bit(STATUS_C_PF, true);
+            }
+        }
+
+        if now_ns.wrapping_sub(self.last_update_ns) >= 1_000_000_000 {
+            self.last_update_ns = now_ns;
+            if status_b.get_bit(STATUS_B_UIE) {
+                self.status_c.set_bit(STATUS_C_UF, true);
+            }
+            if status_b.get_bit(STATUS_B_AIE) {
+                let clock = WallClock::now();
+                if self.alarm_matches(REG_SECONDS_ALARM, clock.seconds)
+                    && self.alarm_matches(REG_MINUTES_ALARM, clock.minutes)
+                    && self.alarm_matches(REG_HOURS_ALARM, clock.hours)
+                {
+                    self.status_c.set_bit(STATUS_C_AF, true);
+                }
+            }
+        }
+
+        let any_pending = self.status_c & 0x70 != 0;
+        if any_pending && !self.status_c.get_bit(STATUS_C_IRQF) {
+            self.status_c.set_bit(STATUS_C_IRQF, true);
+            self.needs_trigger = true;
+        }
+    }
+
+    fn read_register(&mut self) -> u8 {
+        match self.index {
+            REG_SECONDS => self.encode(WallClock::now().seconds),
+            REG_MINUTES => self.encode(WallClock::now().minutes),
+            REG_HOURS => {
+                let clock = WallClock::now();
+                if self.status_b().get_bit(STATUS_B_24H) {
+                    self.encode(clock.hours)
+                } else {
+                    let (hour12, pm) = match clock.hours {
+                        0 => (12, false),
+                        1..=11 => (clock.hours, false),
+                        12 => (12, true),
+                        _ => (clock.hours - 12, true),
+                    };
+                    let mut value = self.encode(hour12);
+                    if pm {
+                        value.set_bit(7, true);
+                    }
+                    value
+                }
+            }
+            REG_DAY => self.encode(WallClock::now().day),
+            REG_MONTH => self.encode(WallClock::now().month),
+            REG_YEAR => self.encode(WallClock::now().year),
+            REG_SECONDS_ALARM | REG_MINUTES_ALARM | REG_HOURS_ALARM => {
+                self.nvram[self.index as usize]
+            }
+            REG_STATUS_A => self.nvram[REG_STATUS_A as usize] & 0x7f, // UIP (bit 7) clear: no update in progress.
+            REG_STATUS_B => self.status_b(),
+            REG_STATUS_C => {
+                // Real hardware clears the whole flag register and deasserts
+                // the line as soon as it's read.
+                let value = self.status_c;
+                self.status_c = 0;
+                value
+            }
+            REG_STATUS_D => 0x80, // VRT (bit 7) set: battery good.
+            _ => self.nvram[self.index as usize],
+        }
+    }
+
+    fn write_register(&mut self, value: u8) {
+        match self.index {
+            // The emulated clock always tracks host time, so writes to the
+            // date/time registers themselves are accepted but otherwise
+            // ignored.
+            REG_SECONDS | REG_MINUTES | REG_HOURS | REG_DAY | REG_MONTH | REG_YEAR => {}
+            REG_STATUS_A => self.nvram[REG_STATUS_A as usize] = value & 0x7f, // UIP is read-only.
+            REG_STATUS_C | REG_STATUS_D => {} // Read-only flag/status registers.
+            _ => self.nvram[self.index as usize] = value,
+        }
+    }
+}
+
+impl PioOps for Rtc {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        self.port_base..self.port_base + 2
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        match port - self.port_base {
+            0 => Ok((self.index | if self.nmi_disabled { 0x80 } else { 0 }) as u32),
+            1 => Ok(self.read_register() as u32),
+            _ => Err(HyperError::InvalidParam),
+        }
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        let value = value as u8;
+        match port - self.port_base {
+            // Bit 7 of the index port gates NMI; it's tracked separately
+            // from the index itself since the two are otherwise unrelated.
+            0 => {
+                self.nmi_disabled = value.get_bit(7);
+                self.index = value & 0x7f;
+            }
+            1 => self.write_register(value),
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+
+    fn level(&self) -> Option<bool> {
+        Some(self.status_c.get_bit(STATUS_C_IRQF))
+    }
+
+    fn poll_irq(&mut self, vcpu: &mut VCpu) -> HyperResult {
+        self.update_pending_interrupts();
+        if self.needs_trigger {
+            self.needs_trigger = false;
+            self.irq.trigger(vcpu)?;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        RtcSnapshot {
+            index: self.index,
+            nmi_disabled: self.nmi_disabled as u8,
+            nvram: self.nvram,
+            status_c: self.status_c,
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HyperResult {
+        let snap = RtcSnapshot::from_bytes(data).ok_or(HyperError::InvalidParam)?;
+        self.index = snap.index;
+        self.nmi_disabled = snap.nmi_disabled != 0;
+        self.nvram = snap.nvram;
+        self.status_c = snap.status_c;
+        let now_ns = axhal::time::current_time_nanos();
+        self.last_update_ns = now_ns;
+        self.last_periodic_ns = now_ns;
+        self.needs_trigger = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+//! Emulated UART 16550, supporting both polled operation (the guest spins on
+//! the Line Status Register) and interrupt-driven operation (the guest
+//! enables causes in the Interrupt Enable Register and services them off an
+//! injected IRQ). (ref: https://wiki.osdev.org/Serial_Ports)
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+use hypercraft::{HyperError, HyperResult};
+use pci::util::byte_code::ByteCode;
+use crate::hv::vmx::device_emu::i8259_pic::I8259Pic;
+use crate::hv::vmx::device_emu::{IrqLevelEvent, PioOps};
+use crate::hv::vmx::VCpu;
+
+const DATA_REG: u16 = 0;
+const INT_EN_REG: u16 = 1;
+const FIFO_CTRL_REG: u16 = 2;
+const LINE_CTRL_REG: u16 = 3;
+const MODEM_CTRL_REG: u16 = 4;
+const LINE_STATUS_REG: u16 = 5;
+const MODEM_STATUS_REG: u16 = 6;
+const SCRATCH_REG: u16 = 7;
+
+const UART_FIFO_CAPACITY: usize = 16;
+
+/// IER bit 0: received-data-available interrupt enable.
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+/// IER bit 1: THR-empty interrupt enable.
+const IER_THR_EMPTY: u8 = 1 << 1;
+
+/// FCR bit 0: FIFOs enabled.
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+/// FCR bit 1: clear the receive FIFO.
+const FCR_CLEAR_RX: u8 = 1 << 1;
+
+/// IIR cause codes (bits 3:1); the lowest-priority value means "no interrupt
+/// pending". We only ever report the two causes we actually track.
+const IIR_NONE: u8 = 0b001;
+const IIR_THR_EMPTY: u8 = 0b010;
+const IIR_RX_AVAILABLE: u8 = 0b100;
+
+/// LSR bit 0: a byte is waiting in the receive FIFO.
+const LSR_INPUT_FULL: u8 = 1 << 0;
+/// LSR bits 5-6: THR/shift register empty. Transmission completes instantly
+/// in this model, so these are always set once a byte has been "sent".
+const LSR_OUTPUT_EMPTY: u8 = 1 << 5 | 1 << 6;
+
+/// Fixed-layout capture of [`Uart16550`]'s mutable state for
+/// [`PioOps::snapshot`]/[`PioOps::restore`]; the FIFO is stored as its
+/// backing bytes plus a length since `VecDeque` itself has no fixed layout.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UartSnapshot {
+    ier: u8,
+    fcr: u8,
+    line_control_reg: u8,
+    modem_control_reg: u8,
+    rx_pending: u8,
+    tx_pending: u8,
+    fifo_len: u8,
+    fifo: [u8; UART_FIFO_CAPACITY],
+}
+
+impl Default for UartSnapshot {
+    fn default() -> Self {
+        Self {
+            ier: 0,
+            fcr: 0,
+            line_control_reg: 0,
+            modem_control_reg: 0,
+            rx_pending: 0,
+            tx_pending: 0,
+            fifo_len: 0,
+            fifo: [0; UART_FIFO_CAPACITY],
+        }
+    }
+}
+
+impl ByteCode for UartSnapshot {}
+
+/// Emulated 16550 UART exposed to the guest over 8 consecutive I/O ports.
+pub struct Uart16550 {
+    port_base: u16,
+    /// IRQ/GSI this instance asserts when an enabled cause is pending.
+    irq: IrqLevelEvent,
+    fifo: VecDeque<u8>,
+    ier: u8,
+    fcr: u8,
+    line_control_reg: u8,
+    modem_control_reg: u8,
+    /// Receive-data-available cause, cleared by a `DATA_REG` read.
+    rx_pending: bool,
+    /// THR-empty cause, cleared by an `IIR`/`LSR` read.
+    tx_pending: bool,
+    /// Set whenever a cause newly becomes pending; consumed by `poll_irq`.
+    needs_trigger: bool,
+}
+
+impl Uart16550 {
+    pub fn new(port_base: u16, irq: u8, pic: Arc<Mutex<I8259Pic>>) -> Self {
+        Self {
+            port_base,
+            irq: IrqLevelEvent::new(irq, pic, || false),
+            fifo: VecDeque::with_capacity(UART_FIFO_CAPACITY),
+            ier: 0,
+            fcr: 0,
+            line_control_reg: 0,
+            modem_control_reg: 0,
+            rx_pending: false,
+            tx_pending: false,
+            needs_trigger: false,
+        }
+    }
+
+    /// Direct host-console passthrough, independent of the FIFO/interrupt
+    /// state above. Used by [`crate::hv::vmx::monitor::Monitor`], which
+    /// drives its own private `Uart16550` as a raw terminal rather than
+    /// mapping it into a guest's port space.
+    pub fn putchar(&mut self, c: u8) {
+        axhal::console::putchar(c);
+    }
+
+    /// See [`Self::putchar`].
+    pub fn getchar(&mut self) -> Option<u8> {
+        axhal::console::getchar()
+    }
+
+    fn iir(&self) -> u8 {
+        let fifo_bits = if self.fcr & FCR_FIFO_ENABLE != 0 { 0b1100_0000 } else { 0 };
+        if self.rx_pending && self.ier & IER_RX_AVAILABLE != 0 {
+            fifo_bits | IIR_RX_AVAILABLE
+        } else if self.tx_pending && self.ier & IER_THR_EMPTY != 0 {
+            fifo_bits | IIR_THR_EMPTY
+        } else {
+            fifo_bits | IIR_NONE
+        }
+    }
+}
+
+impl PioOps for Uart16550 {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        self.port_base..self.port_base + 8
+    }
+
+    fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
+        if access_size != 1 {
+            error!("Invalid serial port I/O read size: {} != 1", access_size);
+            return Err(HyperError::InvalidParam);
+        }
+        let ret = match port - self.port_base {
+            DATA_REG => {
+                // A data-register read always clears the RX cause, whether
+                // or not a byte was actually waiting.
+                self.rx_pending = false;
+                self.fifo.pop_front().unwrap_or(0)
+            }
+            INT_EN_REG => self.ier,
+            FIFO_CTRL_REG => {
+                // Port 2 is read/write-asymmetric on real hardware: writes
+                // go to FCR, reads return IIR. Reading it clears a pending
+                // THR-empty cause (RX-available is only cleared by reading
+                // the data register).
+                let iir = self.iir();
+                self.tx_pending = false;
+                iir
+            }
+            LINE_CTRL_REG => self.line_control_reg,
+            MODEM_CTRL_REG => self.modem_control_reg,
+            LINE_STATUS_REG => {
+                if self.fifo.len() < UART_FIFO_CAPACITY {
+                    if let Some(c) = axhal::console::getchar() {
+                        self.fifo.push_back(c);
+                        if self.ier & IER_RX_AVAILABLE != 0 {
+                            self.rx_pending = true;
+                            self.needs_trigger = true;
+                        }
+                    }
+                }
+                // So polled drivers that only ever check LSR (never IIR)
+                // still see a pending THR-empty cause go away.
+                self.tx_pending = false;
+                let mut lsr = LSR_OUTPUT_EMPTY;
+                if !self.fifo.is_empty() {
+                    lsr |= LSR_INPUT_FULL;
+                }
+                lsr
+            }
+            MODEM_STATUS_REG | SCRATCH_REG => {
+                trace!("Unimplemented serial port I/O read: {:#x}", port);
+                0
+            }
+            _ => unreachable!(),
+        };
+        Ok(ret as u32)
+    }
+
+    fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
+        if access_size != 1 {
+            error!("Invalid serial port I/O write size: {} != 1", access_size);
+            return Err(HyperError::InvalidParam);
+        }
+        let value = value as u8;
+        match port - self.port_base {
+            DATA_REG => {
+                axhal::console::putchar(value);
+                // The write itself momentarily refills THR, clearing any
+                // stale cause; transmission then completes instantly in
+                // this model, so THR is immediately empty again.
+                self.tx_pending = false;
+                if self.ier & IER_THR_EMPTY != 0 {
+                    self.tx_pending = true;
+                    self.needs_trigger = true;
+                }
+            }
+            INT_EN_REG => self.ier = value & 0x0f,
+            FIFO_CTRL_REG => {
+                self.fcr = value;
+                if value & FCR_CLEAR_RX != 0 {
+                    self.fifo.clear();
+                    self.rx_pending = false;
+                }
+            }
+            LINE_CTRL_REG => self.line_control_reg = value,
+            MODEM_CTRL_REG => self.modem_control_reg = value,
+            SCRATCH_REG => trace!("Unimplemented serial port I/O write: {:#x}", port),
+            LINE_STATUS_REG => {} // read-only; ignore
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn level(&self) -> Option<bool> {
+        Some(self.rx_pending || self.tx_pending)
+    }
+
+    fn poll_irq(&mut self, vcpu: &mut VCpu) -> HyperResult {
+        if self.needs_trigger {
+            self.needs_trigger = false;
+            self.irq.trigger(vcpu)?;
+        }
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        let mut fifo = [0u8; UART_FIFO_CAPACITY];
+        for (slot, byte) in fifo.iter_mut().zip(self.fifo.iter()) {
+            *slot = *byte;
+        }
+        UartSnapshot {
+            ier: self.ier,
+            fcr: self.fcr,
+            line_control_reg: self.line_control_reg,
+            modem_control_reg: self.modem_control_reg,
+            rx_pending: self.rx_pending as u8,
+            tx_pending: self.tx_pending as u8,
+            fifo_len: self.fifo.len() as u8,
+            fifo,
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HyperResult {
+        let snap = UartSnapshot::from_bytes(data).ok_or(HyperError::InvalidParam)?;
+        self.ier = snap.ier;
+        self.fcr = snap.fcr;
+        self.line_control_reg = snap.line_control_reg;
+        self.modem_control_reg = snap.modem_control_reg;
+        self.rx_pending = snap.rx_pending != 0;
+        self.tx_pending = snap.tx_pending != 0;
+        self.needs_trigger = false;
+        self.fifo.clear();
+        self.fifo.extend(&snap.fifo[..snap.fifo_len as usize]);
+        Ok(())
+    }
+}
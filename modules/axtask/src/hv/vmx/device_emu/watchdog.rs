@@ -0,0 +1,118 @@
+//! Virtual watchdog timer, modeled on crosvm's VMWDT: the guest arms it by
+//! writing a tick count (at [`DEFAULT_CLOCK_HZ`]) to `REG_LOAD_CNT` and must
+//! keep re-writing it ("petting") before the count runs out, or
+//! `crate::hv::vm::check_watchdogs` resets the VM on its next host-side
+//! check. Enable/timeout are declared per-VM through
+//! `crate::hv::vm::config::VmConfig::watchdog_enabled`/`watchdog_timeout_secs`.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use axhal::time::current_time_nanos;
+use hypercraft::{GuestPhysAddr, HyperResult};
+use super::MmioOps;
+
+/// Clock `REG_LOAD_CNT`/`REG_CURRENT_COUNT` are expressed in ticks of,
+/// crosvm's `VMWDT_DEFAULT_CLOCK_HZ`.
+pub const DEFAULT_CLOCK_HZ: u64 = 8_000_000;
+
+/// Seconds an armed-but-never-pet watchdog survives before expiring, if
+/// `VmConfig::watchdog_timeout_secs` isn't overridden - crosvm's
+/// `VMWDT_DEFAULT_TIMEOUT_SEC`.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Guest-physical MMIO window, sized like every other single-page device in
+/// `crate::hv::vm::config::arceos_config`'s manifest. Sits in the page right
+/// after the emulated HPET's (`0xfed0_0000`), ahead of the Local APIC's
+/// (`0xfee0_0000`).
+pub const MMIO_BASE: GuestPhysAddr = 0xfed0_1000;
+const MMIO_SIZE: usize = 0x1000;
+
+const REG_STATUS: GuestPhysAddr = 0x00;
+const REG_LOAD_CNT: GuestPhysAddr = 0x08;
+const REG_CURRENT_COUNT: GuestPhysAddr = 0x10;
+const REG_CLOCK_FREQ_HZ: GuestPhysAddr = 0x18;
+
+/// Shared between every vCPU's [`VmWatchdog`] MMIO front-end (whichever one
+/// traps the guest's next pet) and `crate::hv::vm::check_watchdogs` (the
+/// host-side periodic check that acts on a timeout) - the only state a
+/// timeout actually needs to cross that boundary, instead of threading a
+/// `Weak<Mutex<VirtMach>>` through `super::X64VirtDevices` just for this.
+pub struct WatchdogState {
+    armed: AtomicBool,
+    last_pet_ns: AtomicU64,
+    timeout_ns: AtomicU64,
+}
+
+impl WatchdogState {
+    pub fn new(timeout_secs: u64) -> Arc<Self> {
+        Arc::new(Self {
+            armed: AtomicBool::new(false),
+            last_pet_ns: AtomicU64::new(0),
+            timeout_ns: AtomicU64::new(timeout_secs.saturating_mul(1_000_000_000)),
+        })
+    }
+
+    /// `true` once this VM's vCPUs have gone `timeout_ns` without a pet.
+    /// `crate::hv::vm::check_watchdogs` calls [`Self::disarm`] in the same
+    /// beat it acts on this, so a reset only fires once per timeout.
+    pub fn expired(&self, now_ns: u64) -> bool {
+        self.armed.load(Ordering::Acquire)
+            && now_ns.saturating_sub(self.last_pet_ns.load(Ordering::Acquire))
+                >= self.timeout_ns.load(Ordering::Acquire)
+    }
+
+    pub fn disarm(&self) {
+        self.armed.store(false, Ordering::Release);
+    }
+
+    fn pet(&self, load_cnt: u64) {
+        let timeout_ns = load_cnt.saturating_mul(1_000_000_000) / DEFAULT_CLOCK_HZ;
+        self.timeout_ns.store(timeout_ns, Ordering::Relaxed);
+        self.last_pet_ns.store(current_time_nanos(), Ordering::Release);
+        self.armed.store(true, Ordering::Release);
+    }
+
+    fn current_count(&self) -> u64 {
+        let elapsed_ns = current_time_nanos().saturating_sub(self.last_pet_ns.load(Ordering::Acquire));
+        let remaining_ns = self.timeout_ns.load(Ordering::Acquire).saturating_sub(elapsed_ns);
+        remaining_ns * DEFAULT_CLOCK_HZ / 1_000_000_000
+    }
+}
+
+/// Per-vCPU MMIO front-end onto a VM-wide [`WatchdogState`] every vCPU holds
+/// a clone of. A guest driver arms/pets it by writing the tick count until
+/// the next expected pet to `REG_LOAD_CNT`, and can read `REG_CURRENT_COUNT`/
+/// `REG_STATUS`/`REG_CLOCK_FREQ_HZ` back to check on it - the same register
+/// set crosvm's VMWDT exposes, minus its per-CPU counter bank, since this
+/// tree arms one watchdog for the whole VM rather than one per vCPU.
+pub struct VmWatchdog {
+    state: Arc<WatchdogState>,
+}
+
+impl VmWatchdog {
+    pub fn new(state: Arc<WatchdogState>) -> Self {
+        Self { state }
+    }
+}
+
+impl MmioOps for VmWatchdog {
+    fn mmio_range(&self) -> core::ops::Range<GuestPhysAddr> {
+        MMIO_BASE..MMIO_BASE + MMIO_SIZE
+    }
+
+    fn read(&mut self, addr: GuestPhysAddr, _access_size: u8) -> HyperResult<u64> {
+        Ok(match addr - MMIO_BASE {
+            REG_STATUS => self.state.armed.load(Ordering::Acquire) as u64,
+            REG_CURRENT_COUNT => self.state.current_count(),
+            REG_CLOCK_FREQ_HZ => DEFAULT_CLOCK_HZ,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, addr: GuestPhysAddr, _access_size: u8, value: u64) -> HyperResult {
+        if addr - MMIO_BASE == REG_LOAD_CNT {
+            self.state.pet(value);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+//! A minimal GDB remote-serial-protocol (RSP) stub, so an external `gdb` or
+//! `lldb` can attach to a guest created by `boot_vm` over a serial line
+//! instead of relying on the ad-hoc [`super::monitor::Monitor`] commands.
+//!
+//! [`GdbStub::run`] plays the same role as [`super::monitor::Monitor::run`]:
+//! drop a paused [`VirtCpu`] into it and it drives the `$...#cc` packet loop
+//! one byte at a time over an [`Uart16550`] until the guest is told to
+//! continue or single-step, at which point control returns to the caller
+//! (which re-enters the VM and drops back in here on the next stop).
+//!
+//! This plays the role `gdbstub::target::Target` plays for the (unused, TCP
+//! based) `apps/hv/src/gdbserver.rs::GdbServer`: [`Debuggable`] is the
+//! `SingleThreadBase`/`SingleThreadSingleStep`/breakpoint surface, just
+//! backed directly by [`VirtCpu`] instead of going through that crate's
+//! generic `Target`/`Connection` plumbing. The vmexit side is already wired
+//! in `VirtCpu::vmexit_handler`: `EXCEPTION_NMI` reports `StopReason::SwBreak`
+//! for this stub whenever the trapped vector is `#DB`/`#BP` and the vCPU is
+//! gdb-attached (`VirtCpu::handle_exception_nmi`), and `MONITOR_TRAP_FLAG`
+//! (armed by [`Debuggable::gdb_single_step`]) reports `StopReason::DoneStep`
+//! and clears the Monitor Trap Flag before handing back to
+//! [`GdbStub::run`] (`VirtCpu::enter_gdb_stub`). `GdbServer` is left as
+//! reserved scaffolding for a TCP transport; swapping it in only needs a
+//! `Connection` wrapping an [`Uart16550`]-equivalent byte stream, not a
+//! second debug-target implementation.
+//!
+//! `VirtCpu::gdb_attach`/`gdb_detach` were, until now, dead API with no
+//! caller; `VmConfig::gdb_attach` (see `crate::hv::vm::config`) is the entry
+//! point this was missing, attaching the stub to the BSP before
+//! `boot_vm` starts it so a debugger can plant breakpoints from the guest's
+//! very first instruction.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use hypercraft::{GuestPhysAddr, GuestVirtAddr, HyperError, HyperResult};
+use crate::hv::mm::GuestPhysMemorySet;
+use crate::hv::vcpu::VirtCpu;
+use crate::hv::vmx::device_emu::Uart16550;
+use crate::hv::vmx::walk::translate_gva;
+
+/// `INT3` opcode used to plant software breakpoints.
+const INT3: u8 = 0xcc;
+
+/// Number of bytes in a `g`/`G` packet's register blob: 16 GPRs + RIP (8
+/// bytes each), then EFLAGS/CS/SS/DS/ES/FS/GS (4 bytes each), matching
+/// gdb's `i386:x86-64` target description.
+const GDB_NUM_GPRS: usize = 17;
+const GDB_NUM_32BIT_REGS: usize = 6;
+const GDB_REG_BYTES: usize = GDB_NUM_GPRS * 8 + GDB_NUM_32BIT_REGS * 4;
+
+/// Register-read/write and execution-control surface a debugger needs from
+/// a vCPU. Kept separate from [`VirtCpu`] itself so the RSP transport below
+/// doesn't need to know about VMX internals.
+pub trait Debuggable {
+    /// Encode all registers in gdb's `i386:x86-64` `g`-packet order.
+    fn gdb_read_registers(&self) -> [u8; GDB_REG_BYTES];
+    /// Decode and apply a `G`-packet register blob.
+    fn gdb_write_registers(&self, data: &[u8]) -> HyperResult;
+    /// Read a single register by its gdb register number (same ordering as
+    /// the `g` packet).
+    fn gdb_read_reg(&self, gdb_regnum: usize) -> HyperResult<u64>;
+    /// Write a single register by its gdb register number.
+    fn gdb_write_reg(&self, gdb_regnum: usize, value: u64) -> HyperResult;
+    /// Arm a single instruction step and return to the guest.
+    fn gdb_single_step(&self) -> HyperResult;
+    /// Translate a guest linear address (as seen by the currently executing
+    /// instruction) to a guest-physical one, walking the guest's own page
+    /// tables the way `get_gva_content_bytes` does.
+    fn gdb_translate(&self, gpm: &GuestPhysMemorySet, gva: GuestVirtAddr) -> HyperResult<GuestPhysAddr>;
+}
+
+impl Debuggable for VirtCpu {
+    fn gdb_read_registers(&self) -> [u8; GDB_REG_BYTES] {
+        let vcpu = self.vmx_vcpu_mut();
+        let regs = vcpu.regs();
+        let mut out = [0u8; GDB_REG_BYTES];
+        let gprs: [u64; GDB_NUM_GPRS] = [
+            regs.rax, regs.rbx, regs.rcx, regs.rdx, regs.rsi, regs.rdi, regs.rbp,
+            vcpu.rsp(), regs.r8, regs.r9, regs.r10, regs.r11, regs.r12, regs.r13,
+            regs.r14, regs.r15, vcpu.rip(),
+        ];
+        let mut offset = 0;
+        for gpr in gprs {
+            out[offset..offset + 8].copy_from_slice(&gpr.to_le_bytes());
+            offset += 8;
+        }
+        let segs: [u32; GDB_NUM_32BIT_REGS] = [
+            vcpu.rflags() as u32,
+            vcpu.segment_regs().cs as u32,
+            vcpu.segment_regs().ss as u32,
+            vcpu.segment_regs().ds as u32,
+            vcpu.segment_regs().es as u32,
+            vcpu.segment_regs().fs as u32,
+        ];
+        for seg in segs {
+            out[offset..offset + 4].copy_from_slice(&seg.to_le_bytes());
+            offset += 4;
+        }
+        out
+    }
+
+    fn gdb_write_registers(&self, data: &[u8]) -> HyperResult {
+        if data.len() != GDB_REG_BYTES {
+            return Err(HyperError::InvalidParam);
+        }
+        for (gdb_regnum, chunk) in data.chunks(8).take(GDB_NUM_GPRS).enumerate() {
+            let value = u64::from_le_bytes(chunk.try_into().unwrap());
+            self.gdb_write_reg(gdb_regnum, value)?;
+        }
+        Ok(())
+    }
+
+    fn gdb_read_reg(&self, gdb_regnum: usize) -> HyperResult<u64> {
+        let vcpu = self.vmx_vcpu_mut();
+        let regs = vcpu.regs();
+        Ok(match gdb_regnum {
+            0 => regs.rax,
+            1 => regs.rbx,
+            2 => regs.rcx,
+            3 => regs.rdx,
+            4 => regs.rsi,
+            5 => regs.rdi,
+            6 => regs.rbp,
+            7 => vcpu.rsp(),
+            8 => regs.r8,
+            9 => regs.r9,
+            10 => regs.r10,
+            11 => regs.r11,
+            12 => regs.r12,
+            13 => regs.r13,
+            14 => regs.r14,
+            15 => regs.r15,
+            16 => vcpu.rip(),
+            17 => vcpu.rflags(),
+            18 => vcpu.segment_regs().cs as u64,
+            19 => vcpu.segment_regs().ss as u64,
+            20 => vcpu.segment_regs().ds as u64,
+            21 => vcpu.segment_regs().es as u64,
+            22 => vcpu.segment_regs().fs as u64,
+            23 => vcpu.segment_regs().gs as u64,
+            _ => return Err(HyperError::InvalidParam),
+        })
+    }
+
+    fn gdb_write_reg(&self, gdb_regnum: usize, value: u64) -> HyperResult {
+        let vcpu = self.vmx_vcpu_mut();
+        let regs = vcpu.regs_mut();
+        match gdb_regnum {
+            0 => regs.rax = value,
+            1 => regs.rbx = value,
+            2 => regs.rcx = value,
+            3 => regs.rdx = value,
+            4 => regs.rsi = value,
+            5 => regs.rdi = value,
+            6 => regs.rbp = value,
+            7 => vcpu.set_rsp(value)?,
+            8 => regs.r8 = value,
+            9 => regs.r9 = value,
+            10 => regs.r10 = value,
+            11 => regs.r11 = value,
+            12 => regs.r12 = value,
+            13 => regs.r13 = value,
+            14 => regs.r14 = value,
+            15 => regs.r15 = value,
+            16 => vcpu.set_rip(value)?,
+            17 => vcpu.set_rflags(value)?,
+            // CS/SS/DS/ES/FS/GS: not writable through this minimal stub.
+            18..=23 => {}
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+
+    fn gdb_single_step(&self) -> HyperResult {
+        self.vmx_vcpu_mut().set_monitor_trap_flag(true)
+    }
+
+    fn gdb_translate(&self, gpm: &GuestPhysMemorySet, gva: GuestVirtAddr) -> HyperResult<GuestPhysAddr> {
+        translate_gva(self.vmx_vcpu_mut(), gpm, gva)
+    }
+}
+
+/// Which kind of stop `Z`/`z` requested. This stub realizes both the same
+/// way: by patching an `INT3` into guest memory, restoring the original
+/// byte on removal.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BreakpointKind {
+    Software,
+    Hardware,
+}
+
+/// RSP packet-loop state for a single paused vCPU.
+pub struct GdbStub {
+    /// Guest-physical address -> original byte, for installed breakpoints.
+    breakpoints: BTreeMap<GuestPhysAddr, u8>,
+    /// Whether the vCPU should keep dropping back into the packet loop.
+    stopped: bool,
+}
+
+impl GdbStub {
+    pub const fn new() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            stopped: false,
+        }
+    }
+
+    /// Enter the packet loop and block on `channel` until the debugger asks
+    /// the guest to continue (`c`) or single-step (`s`).
+    pub fn run(&mut self, vcpu: &VirtCpu, channel: &spin::Mutex<Uart16550>, gpm: &mut GuestPhysMemorySet) {
+        self.stopped = true;
+        self.send_packet(channel, "S05");
+        while self.stopped {
+            let Some(packet) = self.read_packet(channel) else {
+                continue;
+            };
+            self.dispatch(&packet, vcpu, channel, gpm);
+        }
+    }
+
+    fn dispatch(&mut self, packet: &str, vcpu: &VirtCpu, channel: &spin::Mutex<Uart16550>, gpm: &mut GuestPhysMemorySet) {
+        let mut chars = packet.chars();
+        let Some(command) = chars.next() else {
+            return self.send_packet(channel, "");
+        };
+        let rest = chars.as_str();
+        match command {
+            '?' => self.send_packet(channel, "S05"),
+            'g' => {
+                let regs = vcpu.gdb_read_registers();
+                self.send_packet(channel, &encode_hex(&regs));
+            }
+            'G' => match decode_hex(rest) {
+                Some(data) if vcpu.gdb_write_registers(&data).is_ok() => self.send_packet(channel, "OK"),
+                _ => self.send_packet(channel, "E01"),
+            },
+            'p' => match usize::from_str_radix(rest, 16).ok().and_then(|n| vcpu.gdb_read_reg(n).ok()) {
+                Some(value) => self.send_packet(channel, &encode_hex(&value.to_le_bytes())),
+                None => self.send_packet(channel, "E01"),
+            },
+            'P' => match self.parse_reg_write(rest) {
+                Some((n, value)) if vcpu.gdb_write_reg(n, value).is_ok() => self.send_packet(channel, "OK"),
+                _ => self.send_packet(channel, "E01"),
+            },
+            'm' => match self.parse_addr_len(rest) {
+                Some((addr, len)) => match self.read_guest_memory(gpm, addr, len) {
+                    Ok(bytes) => self.send_packet(channel, &encode_hex(&bytes)),
+                    Err(_) => self.send_packet(channel, "E01"),
+                },
+                None => self.send_packet(channel, "E01"),
+            },
+            'M' => match self.parse_mem_write(rest) {
+                Some((addr, data)) => match self.write_guest_memory(gpm, addr, &data) {
+                    Ok(()) => self.send_packet(channel, "OK"),
+                    Err(_) => self.send_packet(channel, "E01"),
+                },
+                None => self.send_packet(channel, "E01"),
+            },
+            'Z' | 'z' => match self.parse_breakpoint(rest) {
+                Some((addr, _kind)) => {
+                    let result = if command == 'Z' {
+                        self.set_breakpoint(gpm, addr)
+                    } else {
+                        self.clear_breakpoint(gpm, addr)
+                    };
+                    self.send_packet(channel, if result.is_ok() { "OK" } else { "E01" });
+                }
+                None => self.send_packet(channel, "E01"),
+            },
+            'c' => self.stopped = false,
+            's' => {
+                let _ = vcpu.gdb_single_step();
+                self.stopped = false;
+            }
+            // Detach: stop intercepting `#DB`/`#BP`/MTF for this vCPU and
+            // let it run free again. `VirtCpu::gdb_detach` has been dead API
+            // since it was added - nothing sent a `D` packet to call it.
+            'D' => {
+                vcpu.gdb_detach();
+                self.send_packet(channel, "OK");
+                self.stopped = false;
+            }
+            _ => self.send_packet(channel, ""),
+        }
+    }
+
+    fn set_breakpoint(&mut self, gpm: &mut GuestPhysMemorySet, addr: GuestPhysAddr) -> HyperResult {
+        if self.breakpoints.contains_key(&addr) {
+            return Ok(());
+        }
+        let hpa = gpm.translate(addr)?;
+        let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u8;
+        let original = unsafe {
+            let original = ptr.read();
+            ptr.write(INT3);
+            original
+        };
+        self.breakpoints.insert(addr, original);
+        Ok(())
+    }
+
+    fn clear_breakpoint(&mut self, gpm: &mut GuestPhysMemorySet, addr: GuestPhysAddr) -> HyperResult {
+        let Some(original) = self.breakpoints.remove(&addr) else {
+            return Ok(());
+        };
+        let hpa = gpm.translate(addr)?;
+        let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u8;
+        unsafe { ptr.write(original) };
+        Ok(())
+    }
+
+    fn read_guest_memory(&self, gpm: &GuestPhysMemorySet, addr: GuestPhysAddr, len: usize) -> HyperResult<Vec<u8>> {
+        let hpa = gpm.translate(addr)?;
+        let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *const u8;
+        Ok((0..len).map(|i| unsafe { ptr.add(i).read() }).collect())
+    }
+
+    fn write_guest_memory(&self, gpm: &mut GuestPhysMemorySet, addr: GuestPhysAddr, data: &[u8]) -> HyperResult {
+        let hpa = gpm.translate(addr)?;
+        let ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u8;
+        for (i, b) in data.iter().enumerate() {
+            unsafe { ptr.add(i).write(*b) };
+        }
+        Ok(())
+    }
+
+    fn parse_addr_len(&self, rest: &str) -> Option<(GuestPhysAddr, usize)> {
+        let (addr, len) = rest.split_once(',')?;
+        Some((usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+    }
+
+    fn parse_mem_write(&self, rest: &str) -> Option<(GuestPhysAddr, Vec<u8>)> {
+        let (head, data) = rest.split_once(':')?;
+        let (addr, _len) = head.split_once(',')?;
+        Some((usize::from_str_radix(addr, 16).ok()?, decode_hex(data)?))
+    }
+
+    fn parse_reg_write(&self, rest: &str) -> Option<(usize, u64)> {
+        let (n, value) = rest.split_once('=')?;
+        let bytes = decode_hex(value)?;
+        Some((usize::from_str_radix(n, 16).ok()?, u64::from_le_bytes(bytes.try_into().ok()?)))
+    }
+
+    fn parse_breakpoint(&self, rest: &str) -> Option<(GuestPhysAddr, BreakpointKind)> {
+        let mut parts = rest.splitn(3, ',');
+        let kind = match parts.next()? {
+            "0" => BreakpointKind::Software,
+            "1" => BreakpointKind::Hardware,
+            _ => return None,
+        };
+        let addr = usize::from_str_radix(parts.next()?, 16).ok()?;
+        Some((addr, kind))
+    }
+
+    /// Read one `$...#cc` packet, verifying its checksum and ACKing/NAKing
+    /// it, retrying until a well-formed packet arrives.
+    fn read_packet(&self, channel: &spin::Mutex<Uart16550>) -> Option<String> {
+        loop {
+            while self.getchar(channel) != b'$' {}
+            let mut payload = Vec::new();
+            loop {
+                match self.getchar(channel) {
+                    b'#' => break,
+                    c => payload.push(c),
+                }
+            }
+            let mut checksum_hex = [0u8; 2];
+            checksum_hex[0] = self.getchar(channel);
+            checksum_hex[1] = self.getchar(channel);
+            let checksum = u8::from_str_radix(core::str::from_utf8(&checksum_hex).ok()?, 16).ok()?;
+            let computed = payload.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            if computed == checksum {
+                self.putchar(channel, b'+');
+                return String::from_utf8(payload).ok();
+            }
+            self.putchar(channel, b'-');
+        }
+    }
+
+    fn send_packet(&self, channel: &spin::Mutex<Uart16550>, payload: &str) {
+        let checksum = payload.bytes().fold(0u8, |acc, b| acc.wrapping_add(b));
+        self.putchar(channel, b'$');
+        for b in payload.bytes() {
+            self.putchar(channel, b);
+        }
+        self.putchar(channel, b'#');
+        for b in encode_hex(&[checksum]).bytes() {
+            self.putchar(channel, b);
+        }
+        // Wait for the debugger's ack; a NAK just means we resend once.
+        if self.getchar(channel) == b'-' {
+            self.send_packet(channel, payload);
+        }
+    }
+
+    fn getchar(&self, channel: &spin::Mutex<Uart16550>) -> u8 {
+        loop {
+            if let Some(c) = channel.lock().getchar() {
+                return c;
+            }
+        }
+    }
+
+    fn putchar(&self, channel: &spin::Mutex<Uart16550>, c: u8) {
+        channel.lock().putchar(c);
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push(core::char::from_digit((b >> 4) as u32, 16).unwrap());
+        s.push(core::char::from_digit((b & 0xf) as u32, 16).unwrap());
+    }
+    s
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
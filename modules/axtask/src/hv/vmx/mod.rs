@@ -1,14 +1,23 @@
 mod device_emu;
+pub mod gdb;
+pub mod monitor;
 pub mod smp;
+pub mod walk;
 
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use axhal::cpu::this_cpu_id;
 use device_emu::VirtLocalApic;
 use hypercraft::{HyperError, HyperResult, VCpu as HVCpu, VmxExitInfo, VmxExitReason};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86::irq::GENERAL_PROTECTION_FAULT_VECTOR;
 use crate::hv::vcpu::VirtCpu;
 use crate::on_timer_tick;
 
 pub use device_emu::X64VirtDevices;
+pub use device_emu::{DeviceListSnapshot, WatchdogState, DEFAULT_TIMEOUT_SECS};
 
 type VCpu = HVCpu<super::HyperCraftHalImpl>;
 
@@ -17,6 +26,52 @@ const VM_EXIT_INSTR_LEN_RDMSR: u8 = 2;
 const VM_EXIT_INSTR_LEN_WRMSR: u8 = 2;
 const VM_EXIT_INSTR_LEN_VMCALL: u8 = 3;
 
+/// How a guest access to a specific MSR outside the built-in APIC_BASE /
+/// [`VirtLocalApic`] ranges should be handled. Platform code registers these
+/// per-MSR with [`register_msr_handler`]; anything left unregistered gets
+/// [`MsrAction::Gp`], matching what real hardware does for an MSR it doesn't
+/// implement.
+pub enum MsrAction {
+    /// Forward the access straight to the real hardware MSR.
+    Passthrough,
+    /// Reads always return 0; writes are silently dropped.
+    ReadAsZero,
+    /// Fully emulated by platform-supplied callbacks.
+    Emulated {
+        read: Box<dyn Fn(&VirtCpu) -> HyperResult<u64> + Send + Sync>,
+        write: Box<dyn Fn(&VirtCpu, u64) -> HyperResult + Send + Sync>,
+    },
+    /// Reject the access with a #GP(0), as real hardware would.
+    Gp,
+}
+
+lazy_static! {
+    static ref MSR_TABLE: Mutex<BTreeMap<u32, MsrAction>> = Mutex::new(BTreeMap::new());
+}
+
+/// Register how guest accesses to `msr` should be handled, for any MSR not
+/// already covered by the built-in APIC_BASE / [`VirtLocalApic`] cases.
+/// Overwrites whatever was previously registered for `msr`, if anything.
+pub fn register_msr_handler(msr: u32, action: MsrAction) {
+    MSR_TABLE.lock().insert(msr, action);
+}
+
+/// Inject a #GP(0) for the instruction that caused the current VM exit, and
+/// deliberately leave RIP untouched so the guest re-executes (and re-faults
+/// on) the same instruction once resumed, the same way real hardware
+/// delivers a fault instead of completing the faulting instruction.
+fn inject_gp(vcpu: &VirtCpu) -> HyperResult {
+    vcpu.vmx_vcpu_mut()
+        .queue_event(GENERAL_PROTECTION_FAULT_VECTOR, Some(0));
+    Ok(())
+}
+
+/// Handles a host interrupt that preempted the guest (the VM-exit's vector
+/// is the *host's* real IDT vector, not a guest-facing one), by running the
+/// host's own handler for it. Guest-facing virtual interrupt delivery is a
+/// separate concern already handled where it's raised — see
+/// [`device_emu::Interrupt::trigger`] (the i8259/IOAPIC/MSI routing) and
+/// [`crate::hv::timer`] — rather than here.
 pub fn handle_external_interrupt(vcpu: &VirtCpu) -> HyperResult {
     #[cfg(feature = "irq")]
     {
@@ -34,39 +89,25 @@ pub fn handle_external_interrupt(vcpu: &VirtCpu) -> HyperResult {
 }
 
 fn handle_cpuid(vcpu: &Arc<VirtCpu>) -> HyperResult {
-    use raw_cpuid::{cpuid, CpuIdResult};
-
-    const LEAF_FEATURE_INFO: u32 = 0x1;
-    const LEAF_HYPERVISOR_INFO: u32 = 0x4000_0000;
-    const LEAF_HYPERVISOR_FEATURE: u32 = 0x4000_0001;
-    const VENDOR_STR: &[u8; 12] = b"RVMRVMRVMRVM";
+    use raw_cpuid::cpuid;
 
-    let vendor_regs = unsafe { &*(VENDOR_STR.as_ptr() as *const [u32; 3]) };
     let regs = vcpu.vmx_vcpu_mut().regs_mut();
     let function = regs.rax as u32;
-    let res = match function {
-        LEAF_FEATURE_INFO => {
-            error!("vmx get cpu id");
-            const FEATURE_VMX: u32 = 1 << 5;
-            const FEATURE_HYPERVISOR: u32 = 1 << 31;
-            let mut res = cpuid!(regs.rax, regs.rcx);
-            res.ecx &= !FEATURE_VMX;
-            res.ecx |= FEATURE_HYPERVISOR;
-            res
+    let subleaf = regs.rcx as u32;
+
+    // Per-VM template built from the guest's actual vCPU count (see
+    // `build_cpuid_template`), so an SMP guest sees exactly the topology
+    // ArceOS gave it rather than the host's; anything not in the template
+    // still passes through to the host's real `cpuid`.
+    let template_hit = vcpu.vm().and_then(|vm| vm.lock().cpuid_override(function, subleaf));
+    let res = match template_hit {
+        Some(mut ovr) => {
+            if ovr.patch_apic_id {
+                ovr.result.edx = vcpu.vcpu_id() as u32;
+            }
+            ovr.result
         }
-        LEAF_HYPERVISOR_INFO => CpuIdResult {
-            eax: LEAF_HYPERVISOR_FEATURE,
-            ebx: vendor_regs[0],
-            ecx: vendor_regs[1],
-            edx: vendor_regs[2],
-        },
-        LEAF_HYPERVISOR_FEATURE => CpuIdResult {
-            eax: 0,
-            ebx: 0,
-            ecx: 0,
-            edx: 0,
-        },
-        _ => {
+        None => {
             debug!("host [{}] passthrough cpuid", this_cpu_id());
             cpuid!(regs.rax, regs.rcx)
         }
@@ -95,7 +136,12 @@ pub fn handle_msr_read(vcpu: &VirtCpu) -> HyperResult {
     } else if VirtLocalApic::msr_range().contains(&msr) {
         VirtLocalApic::rdmsr(vcpu, msr)
     } else {
-        Err(HyperError::NotSupported)
+        match MSR_TABLE.lock().get(&msr) {
+            Some(MsrAction::Passthrough) => Ok(unsafe { rdmsr(msr) }),
+            Some(MsrAction::ReadAsZero) => Ok(0),
+            Some(MsrAction::Emulated { read, .. }) => read(vcpu),
+            Some(MsrAction::Gp) | None => Err(HyperError::NotSupported),
+        }
     };
 
     if let Ok(value) = res {
@@ -103,7 +149,8 @@ pub fn handle_msr_read(vcpu: &VirtCpu) -> HyperResult {
         vcpu.vmx_vcpu_mut().regs_mut().rax = value & 0xffff_ffff;
         vcpu.vmx_vcpu_mut().regs_mut().rdx = value >> 32;
     } else {
-        panic!("Failed to handle RDMSR({:#x}): {:?}", msr, res);
+        debug!("VM exit: RDMSR({:#x}) unsupported, injecting #GP(0)", msr);
+        return inject_gp(vcpu);
     }
     vcpu.vmx_vcpu_mut().advance_rip(VM_EXIT_INSTR_LEN_RDMSR)?;
     Ok(())
@@ -120,14 +167,23 @@ pub fn handle_msr_write(vcpu: &VirtCpu) -> HyperResult {
     } else if VirtLocalApic::msr_range().contains(&msr) {
         VirtLocalApic::wrmsr(vcpu, msr, value)
     } else {
-        Err(HyperError::NotSupported)
+        match MSR_TABLE.lock().get(&msr) {
+            Some(MsrAction::Passthrough) => {
+                unsafe { wrmsr(msr, value) };
+                Ok(())
+            }
+            Some(MsrAction::ReadAsZero) => Ok(()), // writes silently dropped
+            Some(MsrAction::Emulated { write, .. }) => write(vcpu, value),
+            Some(MsrAction::Gp) | None => Err(HyperError::NotSupported),
+        }
     };
 
     if res.is_err() {
-        panic!(
-            "Failed to handle WRMSR({:#x}) <- {:#x}: {:?}",
-            msr, value, res
+        debug!(
+            "VM exit: WRMSR({:#x}) <- {:#x} unsupported, injecting #GP(0)",
+            msr, value
         );
+        return inject_gp(vcpu);
     }
     vcpu.vmx_vcpu_mut().advance_rip(VM_EXIT_INSTR_LEN_WRMSR)?;
     Ok(())
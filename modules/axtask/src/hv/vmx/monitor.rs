@@ -0,0 +1,165 @@
+//! An interactive guest monitor/debugger, modeled on a classic ROM monitor
+//! loop: drop a paused [`VirtCpu`] into [`Monitor::run`] from the VM-exit
+//! path and drive it one keystroke at a time over an emulated
+//! [`Uart16550`] console, without needing an external gdb stub.
+
+use alloc::string::String;
+use hypercraft::{GuestPhysAddr, HyperResult};
+use crate::hv::mm::{GuestPhysMemorySet, MapRegion};
+use crate::hv::vcpu::VirtCpu;
+use crate::hv::vmx::device_emu::Uart16550;
+
+/// Interactive command loop state for a single paused vCPU.
+pub struct Monitor {
+    /// The last command entered, so pressing enter with no input repeats it.
+    last_command: Option<char>,
+    /// Guest RIP to stop at, set by the `b` command.
+    breakpoint: Option<GuestPhysAddr>,
+    /// Whether a breakpoint/single-step hit should drop back into the prompt
+    /// on the next call to `run`.
+    stopped: bool,
+}
+
+impl Monitor {
+    pub const fn new() -> Self {
+        Self {
+            last_command: None,
+            breakpoint: None,
+            stopped: false,
+        }
+    }
+
+    /// Set (or clear, with `None`) an execution breakpoint on a guest RIP,
+    /// implemented with the VMX Monitor-Trap-Flag / #DB controls so the
+    /// vCPU traps back into the monitor the moment it reaches that address.
+    pub fn set_breakpoint(&mut self, rip: Option<GuestPhysAddr>, vcpu: &VirtCpu) -> HyperResult {
+        self.breakpoint = rip;
+        vcpu.vmx_vcpu_mut().set_monitor_trap_flag(rip.is_some())
+    }
+
+    /// Arm a single instruction step via MTF and return to the guest.
+    pub fn single_step(&mut self, vcpu: &VirtCpu) -> HyperResult {
+        self.stopped = false;
+        vcpu.vmx_vcpu_mut().set_monitor_trap_flag(true)
+    }
+
+    /// Called from the VM-exit path (MTF / #DB exit, or an explicit request)
+    /// to enter the prompt and block on console input until the guest is
+    /// told to continue.
+    pub fn run(&mut self, vcpu: &VirtCpu, uart: &spin::Mutex<Uart16550>, gpm: &GuestPhysMemorySet) {
+        self.stopped = true;
+        while self.stopped {
+            self.prompt(uart);
+            let Some(line) = Self::read_line(uart) else {
+                continue;
+            };
+            let command = line.chars().next().or(self.last_command);
+            let Some(command) = command else {
+                continue;
+            };
+            self.last_command = Some(command);
+            self.execute(command, &line, vcpu, uart, gpm);
+        }
+    }
+
+    fn prompt(&self, uart: &spin::Mutex<Uart16550>) {
+        Self::write_str(uart, "monitor> ");
+    }
+
+    fn execute(
+        &mut self,
+        command: char,
+        line: &str,
+        vcpu: &VirtCpu,
+        uart: &spin::Mutex<Uart16550>,
+        gpm: &GuestPhysMemorySet,
+    ) {
+        match command {
+            // Dump a word of guest physical memory: "d <gpa>".
+            'd' => {
+                if let Some(gpa) = Self::parse_arg(line) {
+                    match gpm.translate(gpa) {
+                        Ok(hpa) => Self::write_line(uart, &alloc::format!("{:#x} -> {:#x}", gpa, hpa)),
+                        Err(e) => Self::write_line(uart, &alloc::format!("translate failed: {:?}", e)),
+                    }
+                }
+            }
+            // Print the guest's physical memory map.
+            'm' => Self::write_line(uart, &alloc::format!("{:#x?}", gpm)),
+            // Print general-purpose registers.
+            'r' => {
+                let regs = vcpu.vmx_vcpu_mut().regs();
+                Self::write_line(uart, &alloc::format!("{:#x?}", regs));
+            }
+            // Set a GPR: "w rax <value>" is out of scope for a single-char
+            // dispatch; keep writes to the simple "w <gpa> <value>" form.
+            'w' => {
+                if let (Some(gpa), Some(value)) = Self::parse_two_args(line) {
+                    if let Ok(hpa) = gpm.translate(gpa) {
+                        unsafe {
+                            *(axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u64) = value;
+                        }
+                    }
+                }
+            }
+            // Set or clear a breakpoint: "b <gpa>" / "b" to clear.
+            'b' => {
+                let bp = Self::parse_arg(line);
+                let _ = self.set_breakpoint(bp, vcpu);
+            }
+            // Single-step one instruction.
+            's' => {
+                let _ = self.single_step(vcpu);
+                self.stopped = false;
+            }
+            // Continue guest execution.
+            'c' => {
+                self.stopped = false;
+            }
+            _ => Self::write_line(uart, "unknown command"),
+        }
+    }
+
+    fn parse_arg(line: &str) -> Option<usize> {
+        let arg = line.split_whitespace().nth(1)?;
+        usize::from_str_radix(arg.trim_start_matches("0x"), 16).ok()
+    }
+
+    fn parse_two_args(line: &str) -> (Option<usize>, Option<u64>) {
+        let mut parts = line.split_whitespace().skip(1);
+        let gpa = parts
+            .next()
+            .and_then(|s| usize::from_str_radix(s.trim_start_matches("0x"), 16).ok());
+        let value = parts
+            .next()
+            .and_then(|s| u64::from_str_radix(s.trim_start_matches("0x"), 16).ok());
+        (gpa, value)
+    }
+
+    fn read_line(uart: &spin::Mutex<Uart16550>) -> Option<String> {
+        let mut line = String::new();
+        loop {
+            let c = loop {
+                if let Some(c) = uart.lock().getchar() {
+                    break c;
+                }
+            };
+            match c {
+                b'\r' | b'\n' => return Some(line),
+                c => line.push(c as char),
+            }
+        }
+    }
+
+    fn write_str(uart: &spin::Mutex<Uart16550>, s: &str) {
+        let mut uart = uart.lock();
+        for b in s.bytes() {
+            uart.putchar(b);
+        }
+    }
+
+    fn write_line(uart: &spin::Mutex<Uart16550>, s: &str) {
+        Self::write_str(uart, s);
+        Self::write_str(uart, "\r\n");
+    }
+}
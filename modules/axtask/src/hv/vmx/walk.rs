@@ -0,0 +1,79 @@
+//! Guest virtual-to-physical address translation: a software walk of the
+//! guest's own page tables (as opposed to [`GuestPhysMemorySet::translate`],
+//! which maps guest-physical to host-physical). Used wherever tooling needs
+//! to read guest memory by virtual address — the debug stub
+//! ([`super::gdb::Debuggable::gdb_translate`]), MSR/MMIO emulation, and any
+//! future coredump writer.
+
+use hypercraft::{GuestPhysAddr, GuestVirtAddr, HyperError, HyperResult};
+
+use crate::hv::mm::GuestPhysMemorySet;
+use crate::hv::vmx::VCpu;
+
+/// Present bit, common to every paging-structure entry format this walks.
+const ENTRY_PRESENT: u64 = 1 << 0;
+/// Page-size bit: set on a non-leaf-level entry to mean "this is actually
+/// the final, larger page frame" (2MiB/1GiB/etc., per `pw_info.level`).
+const ENTRY_PS: u64 = 1 << 7;
+/// Physical-address bits an entry may legitimately carry; `hypercraft`
+/// gives us the walk's level/width but not the guest's maximum physical
+/// address width, so this is the widest mask that's still unambiguous
+/// (bits 12..52, the field every x86 paging mode agrees on).
+const ENTRY_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Translate `gva` to the guest-physical address it's currently mapped to,
+/// by walking `vcpu`'s own page tables starting from its CR3 (via
+/// `hypercraft`'s [`hypercraft::GuestPageWalkInfo`], which already resolves
+/// the guest's paging mode from CR0/CR4/EFER into a level count and
+/// per-level index width). Returns [`HyperError::BadState`] if the walk
+/// hits a not-present entry, or [`HyperError::InvalidParam`] if it hits a
+/// structurally invalid one (e.g. a large-page frame with reserved low bits
+/// set), so callers can surface faithful #PF semantics instead of treating
+/// every failure the same way.
+pub fn translate_gva(
+    vcpu: &mut VCpu,
+    gpm: &GuestPhysMemorySet,
+    gva: GuestVirtAddr,
+) -> HyperResult<GuestPhysAddr> {
+    let pw_info = vcpu.get_ptw_info();
+    if pw_info.level == 0 {
+        // Paging disabled: guest-virtual and guest-physical coincide.
+        return Ok(gva as GuestPhysAddr);
+    }
+
+    let mut table_gpa = pw_info.top_entry as u64 & ENTRY_ADDR_MASK;
+    let mut level = pw_info.level;
+    loop {
+        level -= 1;
+        let shift = 12 + level * pw_info.width as usize;
+        let index = (gva as u64 >> shift) & ((1u64 << pw_info.width) - 1);
+
+        let entry = read_entry(gpm, table_gpa as GuestPhysAddr, index)?;
+        if entry & ENTRY_PRESENT == 0 {
+            return Err(HyperError::BadState);
+        }
+
+        if level == 0 || entry & ENTRY_PS != 0 {
+            let page_base = entry & ENTRY_ADDR_MASK;
+            if page_base & ((1u64 << shift) - 1) != 0 {
+                // A large-page frame's low bits (below `shift`) are
+                // defined to be zero; anything else is the reserved-bit
+                // violation the caller needs to tell apart from #NP.
+                return Err(HyperError::InvalidParam);
+            }
+            let offset = gva as u64 & ((1u64 << shift) - 1);
+            return Ok((page_base | offset) as GuestPhysAddr);
+        }
+
+        table_gpa = entry & ENTRY_ADDR_MASK;
+    }
+}
+
+/// Read one 8-byte paging-structure entry at `index` within the table at
+/// guest-physical address `table_gpa`.
+fn read_entry(gpm: &GuestPhysMemorySet, table_gpa: GuestPhysAddr, index: u64) -> HyperResult<u64> {
+    let hpa = gpm.translate(table_gpa)?;
+    let base = axhal::mem::phys_to_virt(hpa.into()).as_usize();
+    let entry_ptr = (base as *const u64).wrapping_add(index as usize);
+    Ok(unsafe { entry_ptr.read() })
+}
@@ -0,0 +1,319 @@
+//! Minimal ACPI tables (RSDP/XSDT/FADT/MADT/DSDT) describing a guest's
+//! platform, so SMP enumeration and power management work without relying
+//! solely on the legacy MP table `VMCfgEntry::setup_mptable` writes.
+//!
+//! Mirrors the layout `crate::hv::acpi` in the `axtask` crate already builds
+//! for its own, differently-wired VM path, but adds a trivial DSDT (no AML
+//! interpreter exists on the guest side, so it's an empty definition block)
+//! and is built from `VMCfgEntry`'s own `cpu_mask`/memory-region model
+//! instead of that crate's `CpuSet`.
+
+use alloc::vec::Vec;
+use pci::util::byte_code::ByteCode;
+
+use hypercraft::GuestPhysAddr;
+
+const OEM_ID: [u8; 6] = *b"ARCEOS";
+const OEM_TABLE_ID: [u8; 8] = *b"ARCEOSVM";
+const CREATOR_ID: [u8; 4] = *b"ARCO";
+
+/// Guest-physical address of the emulated I/O APIC's MMIO window, matching
+/// the identity-mapped placement `config::gpm_def`/`config::linux_cfg_def`
+/// already give it.
+const IO_APIC_ADDRESS: u32 = 0xfec0_0000;
+
+/// Recompute and write a table's checksum so its bytes sum to zero mod 256.
+fn fix_checksum(bytes: &mut [u8], checksum_offset: usize) {
+    bytes[checksum_offset] = 0;
+    let sum = bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+    bytes[checksum_offset] = 0u8.wrapping_sub(sum);
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+impl ByteCode for Rsdp {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: [u8; 4],
+    creator_revision: u32,
+}
+impl ByteCode for SdtHeader {}
+
+fn sdt_header(signature: &[u8; 4], length: u32, revision: u8) -> SdtHeader {
+    SdtHeader {
+        signature: *signature,
+        length,
+        revision,
+        checksum: 0,
+        oem_id: OEM_ID,
+        oem_table_id: OEM_TABLE_ID,
+        oem_revision: 1,
+        creator_id: CREATOR_ID,
+        creator_revision: 1,
+    }
+}
+
+fn build_sdt(signature: &[u8; 4], revision: u8, body: &[u8]) -> Vec<u8> {
+    let header = sdt_header(signature, (core::mem::size_of::<SdtHeader>() + body.len()) as u32, revision);
+    let mut table = header.as_bytes().to_vec();
+    table.extend_from_slice(body);
+    fix_checksum(&mut table, core::mem::offset_of!(SdtHeader, checksum));
+    table
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtLocalApic {
+    entry_type: u8,
+    length: u8,
+    acpi_processor_id: u8,
+    apic_id: u8,
+    flags: u32,
+}
+impl ByteCode for MadtLocalApic {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtIoApic {
+    entry_type: u8,
+    length: u8,
+    io_apic_id: u8,
+    reserved: u8,
+    io_apic_address: u32,
+    global_system_interrupt_base: u32,
+}
+impl ByteCode for MadtIoApic {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct MadtInterruptSourceOverride {
+    entry_type: u8,
+    length: u8,
+    bus: u8,
+    source: u8,
+    global_system_interrupt: u32,
+    flags: u16,
+}
+impl ByteCode for MadtInterruptSourceOverride {}
+
+/// Build the MADT: header, one Processor Local APIC entry per bit set in
+/// `cpu_mask`, the I/O APIC, and interrupt-source-override entries for the
+/// legacy PIT (IRQ0 -> GSI 2, cascaded through the master i8259) and the
+/// ACPI SCI (IRQ9, the conventional routing when the SCI shares a line with
+/// a PCI interrupt).
+fn build_madt(cpu_mask: usize) -> Vec<u8> {
+    const LOCAL_APIC_ADDRESS: u32 = 0xfee0_0000;
+    /// PCAT_COMPAT: dual-8259 PICs are present and must be disabled by the OS.
+    const PCAT_COMPAT: u32 = 1 << 0;
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&LOCAL_APIC_ADDRESS.to_ne_bytes());
+    body.extend_from_slice(&PCAT_COMPAT.to_ne_bytes());
+
+    for cpu_id in (0..usize::BITS).filter(|&i| cpu_mask & (1 << i) != 0) {
+        let entry = MadtLocalApic {
+            entry_type: 0,
+            length: core::mem::size_of::<MadtLocalApic>() as u8,
+            acpi_processor_id: cpu_id as u8,
+            apic_id: cpu_id as u8,
+            flags: 1, // enabled
+        };
+        body.extend_from_slice(entry.as_bytes());
+    }
+
+    let io_apic = MadtIoApic {
+        entry_type: 1,
+        length: core::mem::size_of::<MadtIoApic>() as u8,
+        io_apic_id: 0,
+        reserved: 0,
+        io_apic_address: IO_APIC_ADDRESS,
+        global_system_interrupt_base: 0,
+    };
+    body.extend_from_slice(io_apic.as_bytes());
+
+    let pit_iso = MadtInterruptSourceOverride {
+        entry_type: 2,
+        length: core::mem::size_of::<MadtInterruptSourceOverride>() as u8,
+        bus: 0,
+        source: 0,
+        global_system_interrupt: 2,
+        flags: 0,
+    };
+    body.extend_from_slice(pit_iso.as_bytes());
+
+    let sci_iso = MadtInterruptSourceOverride {
+        entry_type: 2,
+        length: core::mem::size_of::<MadtInterruptSourceOverride>() as u8,
+        bus: 0,
+        source: 9,
+        global_system_interrupt: 9,
+        flags: 0,
+    };
+    body.extend_from_slice(sci_iso.as_bytes());
+
+    build_sdt(b"APIC", 3, &body)
+}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct GenericAddress {
+    address_space_id: u8,
+    register_bit_width: u8,
+    register_bit_offset: u8,
+    access_size: u8,
+    address: u64,
+}
+impl ByteCode for GenericAddress {}
+
+#[repr(C, packed)]
+#[derive(Default, Clone, Copy)]
+struct Fadt {
+    header: SdtHeader,
+    firmware_ctrl: u32,
+    dsdt: u32,
+    reserved0: u8,
+    preferred_pm_profile: u8,
+    sci_int: u16,
+    smi_cmd: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_req: u8,
+    pstate_cnt: u8,
+    pm1a_evt_blk: u32,
+    pm1b_evt_blk: u32,
+    pm1a_cnt_blk: u32,
+    pm1b_cnt_blk: u32,
+    pm2_cnt_blk: u32,
+    pm_tmr_blk: u32,
+    gpe0_blk: u32,
+    gpe1_blk: u32,
+    pm1_evt_len: u8,
+    pm1_cnt_len: u8,
+    pm2_cnt_len: u8,
+    pm_tmr_len: u8,
+    gpe0_blk_len: u8,
+    gpe1_blk_len: u8,
+    gpe1_base: u8,
+    cst_cnt: u8,
+    p_lvl2_lat: u16,
+    p_lvl3_lat: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alrm: u8,
+    mon_alrm: u8,
+    century: u8,
+    iapc_boot_arch: u16,
+    reserved1: u8,
+    flags: u32,
+    reset_reg: GenericAddress,
+    reset_value: u8,
+    arm_boot_arch: u16,
+    minor_version: u8,
+    x_firmware_ctrl: u64,
+    x_dsdt: u64,
+    x_pm1a_evt_blk: GenericAddress,
+    x_pm1b_evt_blk: GenericAddress,
+    x_pm1a_cnt_blk: GenericAddress,
+    x_pm1b_cnt_blk: GenericAddress,
+    x_pm2_cnt_blk: GenericAddress,
+    x_pm_tmr_blk: GenericAddress,
+    x_gpe0_blk: GenericAddress,
+    x_gpe1_blk: GenericAddress,
+}
+impl ByteCode for Fadt {}
+
+/// Build a minimal DSDT: just the header, no AML body. An empty term list
+/// is a legal (if degenerate) definition block, and there's no AML
+/// interpreter on the guest side yet for a richer one to matter to.
+fn build_dsdt() -> Vec<u8> {
+    build_sdt(b"DSDT", 2, &[])
+}
+
+/// Build the FADT, pointing `dsdt`/`x_dsdt` at the table built by
+/// [`build_dsdt`] (placed right after the FADT by [`build_tables`]). No PM
+/// base ports or a reset register are emulated anywhere in this crate's
+/// `device` tree yet, so `pm1a_cnt_blk`/`reset_reg` are left zero and
+/// `flags` carries no `RESET_REG_SUP`/`PWR_BUTTON`-style bits.
+fn build_fadt(dsdt_gpa: GuestPhysAddr) -> Vec<u8> {
+    let fadt = Fadt {
+        header: sdt_header(b"FACP", core::mem::size_of::<Fadt>() as u32, 3),
+        dsdt: dsdt_gpa as u32,
+        x_dsdt: dsdt_gpa as u64,
+        ..Default::default()
+    };
+    let mut table = fadt.as_bytes().to_vec();
+    fix_checksum(&mut table, core::mem::offset_of!(SdtHeader, checksum));
+    table
+}
+
+/// Build the full ACPI blob (RSDP, XSDT, FADT, MADT, DSDT) describing the
+/// platform, laid out contiguously starting at `gpa_base`.
+///
+/// `cpu_mask` contributes one MADT Processor Local APIC entry per set bit,
+/// the same bitmask `AxVMCreateArg::cpu_mask`/`VMCfgEntry::cpu_set` already
+/// use. The checksum invariant (bytes of each table sum to zero mod 256)
+/// holds for every table in the returned blob.
+pub fn build_tables(cpu_mask: usize, gpa_base: GuestPhysAddr) -> Vec<u8> {
+    let rsdp_len = core::mem::size_of::<Rsdp>();
+    let xsdt_header_len = core::mem::size_of::<SdtHeader>() + 2 * core::mem::size_of::<u64>();
+
+    let xsdt_gpa = gpa_base + rsdp_len;
+    let fadt_gpa = xsdt_gpa + xsdt_header_len;
+    let fadt_len = core::mem::size_of::<Fadt>();
+    let madt_gpa = fadt_gpa + fadt_len;
+    let madt = build_madt(cpu_mask);
+    let dsdt_gpa = madt_gpa + madt.len();
+    let dsdt = build_dsdt();
+    let fadt = build_fadt(dsdt_gpa);
+
+    let xsdt_header = sdt_header(b"XSDT", xsdt_header_len as u32, 1);
+    let mut xsdt = xsdt_header.as_bytes().to_vec();
+    xsdt.extend_from_slice(&(fadt_gpa as u64).to_ne_bytes());
+    xsdt.extend_from_slice(&(madt_gpa as u64).to_ne_bytes());
+    fix_checksum(&mut xsdt, core::mem::offset_of!(SdtHeader, checksum));
+
+    let mut rsdp = Rsdp {
+        signature: *b"RSD PTR ",
+        checksum: 0,
+        oem_id: OEM_ID,
+        revision: 2,
+        rsdt_address: 0,
+        length: rsdp_len as u32,
+        xsdt_address: xsdt_gpa as u64,
+        extended_checksum: 0,
+        reserved: [0; 3],
+    };
+    // The first checksum covers only the ACPI 1.0 portion (first 20 bytes);
+    // the extended one covers the whole ACPI 2.0+ structure.
+    fix_checksum(rsdp.as_mut_bytes(), core::mem::offset_of!(Rsdp, checksum));
+    fix_checksum(rsdp.as_mut_bytes(), core::mem::offset_of!(Rsdp, extended_checksum));
+
+    let mut blob = rsdp.as_bytes().to_vec();
+    blob.extend_from_slice(&xsdt);
+    blob.extend_from_slice(&fadt);
+    blob.extend_from_slice(&madt);
+    blob.extend_from_slice(&dsdt);
+    blob
+}
@@ -1,19 +1,121 @@
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 
 use spin::Mutex;
 
 use axalloc::GlobalPage;
-use axhal::mem::virt_to_phys;
+use axhal::mem::{phys_to_virt, virt_to_phys, PhysAddr};
 use hypercraft::{GuestPhysAddr, HostPhysAddr, HostVirtAddr};
 use memory_addr::PAGE_SIZE_4K;
 use page_table_entry::MappingFlags;
 
+use crate::config::acpi;
+use crate::config::hotplug::MemoryHotplugManager;
+use crate::config::{
+    ACPI_RSDP_GPA, LINUX_CMDLINE_GPA, LINUX_HOTPLUG_MEM_BASE, LINUX_HOTPLUG_MEM_SIZE,
+    LINUX_ZERO_PAGE_GPA, MPTABLE_GPA,
+};
 use crate::mm::{GuestMemoryRegion, GuestPhysMemorySet};
 use crate::{Error, Result};
 
+// x86 Linux boot protocol offsets/magics (Documentation/x86/boot.rst), all
+// relative to the start of the setup header - which lives at the same
+// offset (0x1f1) in both the bzImage file and the `boot_params` zero page,
+// so these double as offsets into our zero page buffer too.
+const SETUP_SECTS_OFFSET: usize = 0x1f1;
+const E820_ENTRIES_OFFSET: usize = 0x1e8;
+const BOOT_FLAG_OFFSET: usize = 0x1fe;
+const BOOT_FLAG_MAGIC: u16 = 0xaa55;
+/// "HdrS", identifying a real (post-2.00) setup header.
+const HDR_MAGIC_OFFSET: usize = 0x202;
+const HDR_MAGIC: u32 = 0x5372_6448;
+const TYPE_OF_LOADER_OFFSET: usize = 0x210;
+const RAMDISK_IMAGE_OFFSET: usize = 0x218;
+const RAMDISK_SIZE_OFFSET: usize = 0x21c;
+const CMD_LINE_PTR_OFFSET: usize = 0x228;
+
+const E820_TABLE_OFFSET: usize = 0x2d0;
+const E820_ENTRY_SIZE: usize = 20;
+const E820_MAX_ENTRIES: usize = 128;
+const E820_TYPE_RAM: u32 = 1;
+const E820_TYPE_RESERVED: u32 = 2;
+
+/// One coalesced e820 entry, the unit [`e820_from_regions`] classifies
+/// `memory_regions` into before they're serialized into the `boot_params`
+/// zero page by `write_e820_table`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct E820Entry {
+    addr: u64,
+    size: u64,
+    ty: u32,
+}
+
+/// Classify each of `regions` as RAM (its flags include `EXECUTE`, the same
+/// test `MemoryZoneKind::Ram` regions satisfy) or reserved (`DEVICE`), then
+/// coalesce adjacent entries of the same type. Keeps the kernel's view of
+/// which ranges are usable RAM in lockstep with the nested-page-table
+/// layout those same regions produce, instead of the two silently
+/// drifting apart.
+fn e820_from_regions(regions: &[GuestMemoryRegion]) -> Vec<E820Entry> {
+    let mut entries: Vec<E820Entry> = Vec::new();
+    for region in regions {
+        let ty = if region.flags.contains(MappingFlags::EXECUTE) {
+            E820_TYPE_RAM
+        } else {
+            E820_TYPE_RESERVED
+        };
+        let addr = region.gpa as u64;
+        let size = region.size as u64;
+        if let Some(last) = entries.last_mut() {
+            if last.ty == ty && last.addr + last.size == addr {
+                last.size += size;
+                continue;
+            }
+        }
+        entries.push(E820Entry { addr, size, ty });
+    }
+    entries
+}
+
+/// Undefined bootloader ID: the boot protocol only cares that this isn't one
+/// of the IDs reserved for well-known loaders, which is what every ad-hoc
+/// loader (kvmtool, firecracker, crosvm, ...) uses.
+const TYPE_OF_LOADER_UNDEFINED: u8 = 0xff;
+
+// Intel MultiProcessor Specification 1.4 tables, synthesized by
+// `VMCfgEntry::setup_mptable` so SMP guests can enumerate their vCPUs
+// without full ACPI.
+const MP_FLOATING_POINTER_SIG: [u8; 4] = *b"_MP_";
+const MP_FLOATING_POINTER_SIZE: usize = 16;
+const MP_CONFIG_TABLE_SIG: [u8; 4] = *b"PCMP";
+const MP_CONFIG_TABLE_HEADER_SIZE: usize = 44;
+const MP_TABLE_LEN_OFFSET: usize = 4;
+const MP_TABLE_CHECKSUM_OFFSET: usize = 7;
+const MP_TABLE_ENTRY_COUNT_OFFSET: usize = 34;
+const MP_SPEC_REV: u8 = 4; // MP spec 1.4
+
+const MP_ENTRY_PROCESSOR: u8 = 0;
+const MP_ENTRY_BUS: u8 = 1;
+const MP_ENTRY_IOAPIC: u8 = 2;
+const MP_ENTRY_IO_INTERRUPT: u8 = 3;
+
+const MP_CPU_FLAG_ENABLED: u8 = 1 << 0;
+const MP_CPU_FLAG_BSP: u8 = 1 << 1;
+const MP_IOAPIC_FLAG_ENABLED: u8 = 1 << 0;
+const MP_INT_TYPE_INT: u8 = 0;
+
+const IO_APIC_ADDRESS: u32 = 0xfec0_0000;
+
+/// 8-bit checksum such that the sum of every byte in `data`, including this
+/// checksum itself, is zero mod 256 (`data`'s checksum byte must already be
+/// zeroed when this is called).
+fn mp_checksum(data: &[u8]) -> u8 {
+    0u8.wrapping_sub(data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)))
+}
+
 // VM_ID = 0 reserved for host Linux.
 const CONFIG_VM_ID_START: usize = 1;
 const CONFIG_VM_NUM_MAX: usize = 8;
@@ -117,8 +219,28 @@ pub struct VMCfgEntry {
     img_cfg: VMImgCfg,
 
     memory_regions: Vec<GuestMemoryRegion>,
-    physical_pages: BTreeMap<usize, GlobalPage>,
-    memory_set: Option<GuestPhysMemorySet>,
+    /// Physical backing of each RAM region in `memory_regions`, keyed by
+    /// index. Usually a single contiguous `GlobalPage`; when the host is too
+    /// fragmented for that, `set_up_memory_region` instead records however
+    /// many smaller chunks it took to cover the region, in guest-address
+    /// order.
+    physical_pages: BTreeMap<usize, Vec<GlobalPage>>,
+    /// The live `GuestPhysMemorySet` built by `generate_guest_phys_memory_set`
+    /// once the VM has booted, `None` before that. Entries are handed out as
+    /// `Arc<VMCfgEntry>` (see `vm_cfg_entry`) well before boot, so writing
+    /// this back from a shared reference needs the `Mutex`, not just
+    /// `&mut self`. Wrapped in its own `Arc<Mutex<_>>` rather than bare
+    /// `GuestPhysMemorySet` so `hotplug` can be handed a clone of the same
+    /// handle and have its live-added/removed regions actually visible here
+    /// too, instead of drifting out of sync with a private copy.
+    memory_set: Mutex<Option<Arc<Mutex<GuestPhysMemorySet>>>>,
+    /// Live guest-RAM add/remove for this VM's reserved hotplug window (see
+    /// [`LINUX_HOTPLUG_MEM_BASE`]), built alongside `memory_set` once the VM
+    /// has booted; `None` before that.
+    hotplug: Mutex<Option<MemoryHotplugManager>>,
+    /// GPA of the RSDP written by [`Self::setup_acpi_tables`], if it's been
+    /// called yet.
+    acpi_rsdp_gpa: Option<GuestPhysAddr>,
 }
 
 impl VMCfgEntry {
@@ -146,7 +268,9 @@ impl VMCfgEntry {
             ),
             memory_regions: Vec::new(),
             physical_pages: BTreeMap::new(),
-            memory_set: None,
+            memory_set: Mutex::new(None),
+            hotplug: Mutex::new(None),
+            acpi_rsdp_gpa: None,
         }
     }
 
@@ -154,6 +278,12 @@ impl VMCfgEntry {
         self.cpu_set
     }
 
+    /// GPA of the RSDP [`Self::setup_acpi_tables`] wrote, if it's been
+    /// called yet.
+    pub fn get_acpi_rsdp_gpa(&self) -> Option<GuestPhysAddr> {
+        self.acpi_rsdp_gpa
+    }
+
     pub fn get_vm_type(&self) -> VmType {
         self.vm_type
     }
@@ -162,8 +292,55 @@ impl VMCfgEntry {
         self.img_cfg.vm_entry_point
     }
 
+    pub fn get_cmdline(&self) -> &str {
+        &self.cmdline
+    }
+
+    /// Translate a guest-physical address to the host virtual address
+    /// backing it, for device backends (e.g. virtio-blk) that need to
+    /// read/write guest buffers directly instead of going through a vCPU's
+    /// page-table walk. `None` before `generate_guest_phys_memory_set` has
+    /// built `memory_set` (i.e. before the VM has booted) or if `gpa` isn't
+    /// mapped by it.
+    pub fn translate_guest_addr(&self, gpa: GuestPhysAddr) -> Option<HostVirtAddr> {
+        let memory_set = self.memory_set.lock();
+        let hpa = memory_set.as_ref()?.lock().translate(gpa).ok()?;
+        let hva = phys_to_virt(PhysAddr::from(hpa));
+        Some(usize::from(hva) as HostVirtAddr)
+    }
+
+    /// Grow this VM's guest RAM live, without a reboot: map `size` bytes of
+    /// freshly allocated host RAM at `gpa` inside the reserved
+    /// [`LINUX_HOTPLUG_MEM_BASE`] window. Fails with [`Error::InvalidParam`]
+    /// before the VM has booted (`hotplug` isn't built until
+    /// `generate_guest_phys_memory_set` runs) or if `gpa`/`size` don't fit
+    /// inside the window or overlap an already-added region.
+    pub fn add_memory(&self, gpa: GuestPhysAddr, size: usize) -> Result {
+        let mut hotplug = self.hotplug.lock();
+        let hotplug = hotplug.as_mut().ok_or(Error::InvalidParam)?;
+        hotplug.add_memory_region(
+            gpa,
+            size,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+        )
+    }
+
+    /// Inverse of [`Self::add_memory`]: unmap and free a region previously
+    /// added at `gpa`. Fails with [`Error::InvalidParam`] before the VM has
+    /// booted, or if nothing was ever added at `gpa`.
+    pub fn remove_memory(&self, gpa: GuestPhysAddr) -> Result {
+        let mut hotplug = self.hotplug.lock();
+        let hotplug = hotplug.as_mut().ok_or(Error::InvalidParam)?;
+        hotplug.remove_memory_region(gpa)
+    }
+
+    /// Total size in bytes of every configured guest memory region.
+    pub fn get_ram_size(&self) -> u64 {
+        self.memory_regions.iter().map(|r| r.size as u64).sum()
+    }
+
     pub fn add_physical_pages(&mut self, index: usize, pages: GlobalPage) {
-        self.physical_pages.insert(index, pages);
+        self.physical_pages.insert(index, vec![pages]);
     }
 
     pub fn memory_region_editor<F>(&mut self, f: F)
@@ -182,42 +359,210 @@ impl VMCfgEntry {
                 continue;
             }
             let ram_size = align_up_4k(region.size);
-            let physical_pages =
-                GlobalPage::alloc_contiguous(ram_size / PAGE_SIZE_4K, PAGE_SIZE_4K).map_err(
-                    |e| {
-                        warn!(
-                            "failed to allocate {} Bytes memory for guest, err {:?}",
-                            ram_size, e
-                        );
-                        Error::NoMemory
-                    },
-                )?;
-            let ram_base_hpa = physical_pages.start_paddr(virt_to_phys).as_usize();
+            let total_pages = ram_size / PAGE_SIZE_4K;
+            let physical_pages = match GlobalPage::alloc_contiguous(total_pages, PAGE_SIZE_4K) {
+                Ok(pages) => vec![pages],
+                Err(e) => {
+                    warn!(
+                        "failed to allocate {} contiguous Bytes for guest, err {:?}; \
+                         falling back to chunked allocation",
+                        ram_size, e
+                    );
+                    Self::alloc_chunked_ram(total_pages)?
+                }
+            };
+            let ram_base_hpa = physical_pages[0].start_paddr(virt_to_phys).as_usize();
             region.hpa = ram_base_hpa;
 
             debug!(
-                "Alloc {:#x} Bytes of GlobalPage for ram region\n{}",
-                physical_pages.size(),
+                "Alloc {:#x} Bytes ({} chunk(s)) of GlobalPage for ram region\n{}",
+                ram_size,
+                physical_pages.len(),
                 region
             );
 
+            if let Some(fd) = region.file_fd {
+                Self::populate_region_from_file(fd, region.file_offset, &physical_pages)?;
+            }
+
             self.physical_pages.insert(index, physical_pages);
         }
 
         Ok(())
     }
 
+    /// Populate a file-backed RAM region's host pages from `fd`, the
+    /// `GuestMemoryRegion::file_fd`/`file_offset` counterpart of
+    /// `write_guest_phys`. Unlike StratoVirt's `HostMemMapping`, this
+    /// bare-metal hypervisor has no host mmap primitive to back a region
+    /// transparently, so "file-backed" here means reading the file's bytes
+    /// into the already-allocated pages once at setup time: good enough to
+    /// seed a region from a memory-backend-file or hugetlbfs-backed
+    /// allocation, though (unlike a real MAP_SHARED mapping) a guest's
+    /// writes are never reflected back into the file.
+    fn populate_region_from_file(fd: i32, offset: u64, chunks: &[GlobalPage]) -> Result {
+        use std::io::{Read, Seek, SeekFrom};
+        use std::os::unix::io::FromRawFd;
+
+        // Borrow the fd rather than taking ownership: this region doesn't
+        // own the backing file, whoever set `file_fd` does.
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        let result = (|| -> std::io::Result<()> {
+            file.seek(SeekFrom::Start(offset))?;
+            for chunk in chunks {
+                let hva = usize::from(phys_to_virt(PhysAddr::from(
+                    chunk.start_paddr(virt_to_phys).as_usize(),
+                )));
+                let buf = unsafe { core::slice::from_raw_parts_mut(hva as *mut u8, chunk.size()) };
+                file.read_exact(buf)?;
+            }
+            Ok(())
+        })();
+        // Don't let dropping `file` close a fd this entry doesn't own.
+        core::mem::forget(file);
+        result.map_err(|e| {
+            warn!(
+                "failed to populate file-backed region from fd {}: {:?}",
+                fd, e
+            );
+            Error::InvalidParam
+        })
+    }
+
+    /// Cover `total_pages` of guest RAM with however many contiguous chunks
+    /// the host's fragmented free memory allows: start by trying to
+    /// allocate everything in one chunk, and on failure keep halving the
+    /// chunk size until an allocation succeeds, repeating until
+    /// `total_pages` is fully covered.
+    fn alloc_chunked_ram(total_pages: usize) -> Result<Vec<GlobalPage>> {
+        let mut chunks = Vec::new();
+        let mut remaining = total_pages;
+        while remaining > 0 {
+            let mut chunk_pages = remaining;
+            loop {
+                match GlobalPage::alloc_contiguous(chunk_pages, PAGE_SIZE_4K) {
+                    Ok(pages) => {
+                        remaining -= chunk_pages;
+                        chunks.push(pages);
+                        break;
+                    }
+                    Err(_) if chunk_pages > 1 => chunk_pages /= 2,
+                    Err(e) => {
+                        warn!(
+                            "failed to allocate a single page for guest RAM chunk, err {:?}",
+                            e
+                        );
+                        return Err(Error::NoMemory);
+                    }
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
     pub fn generate_guest_phys_memory_set(&self) -> Result<GuestPhysMemorySet> {
         info!("Create VM [{}] nested page table", self.vm_id);
 
         // create nested page table and add mapping
         let mut gpm = GuestPhysMemorySet::new()?;
-        for r in &self.memory_regions {
-            gpm.map_region(r.clone().into())?;
+        for (index, r) in self.memory_regions.iter().enumerate() {
+            // A RAM region backed by more than one `GlobalPage` chunk (see
+            // `alloc_chunked_ram`) isn't contiguous in host memory, so it
+            // can't be described by `r`'s single gpa->hpa mapping: map each
+            // chunk as its own sub-range of the region instead.
+            match self.physical_pages.get(&index) {
+                Some(chunks) if chunks.len() > 1 => {
+                    let mut gpa = r.gpa;
+                    for chunk in chunks {
+                        let chunk_size = chunk.size();
+                        gpm.map_region(
+                            GuestMemoryRegion {
+                                gpa,
+                                hpa: chunk.start_paddr(virt_to_phys).as_usize(),
+                                size: chunk_size,
+                                flags: r.flags,
+                                file_fd: r.file_fd,
+                                file_offset: r.file_offset,
+                            }
+                            .into(),
+                        )?;
+                        gpa += chunk_size;
+                    }
+                }
+                _ => gpm.map_region(r.clone().into())?,
+            }
         }
+        // Keep a handle for `translate_guest_addr` (device DMA address
+        // translation) and `teardown` (unmapping at VM destroy) to use
+        // later through the shared `Arc<VMCfgEntry>` this entry is handed
+        // out as. `GuestPageTable::clone` is a handle to the same
+        // underlying nested page table, so unmapping through this handle at
+        // teardown still tears down the real mapping the booted vCPUs use.
+        // `hotplug` gets a clone of the same `Arc`, so live-added/removed
+        // regions show up through `memory_set` too instead of drifting out
+        // of sync with a private copy.
+        let memory_set = Arc::new(Mutex::new(gpm.clone()));
+        *self.hotplug.lock() = Some(MemoryHotplugManager::new(
+            LINUX_HOTPLUG_MEM_BASE,
+            LINUX_HOTPLUG_MEM_SIZE,
+            memory_set.clone(),
+        ));
+        *self.memory_set.lock() = Some(memory_set);
         Ok(gpm)
     }
 
+    /// Reclaim every resource this entry holds: unmap the non-device
+    /// regions from its cached nested page table (if one was generated),
+    /// free the `GlobalPage` allocations backing guest RAM, and reset the
+    /// cached image load addresses back to the sentinel. Called by
+    /// `vm_cfg_remove_vm_entry` once it has confirmed nothing else still
+    /// references this entry.
+    fn teardown(&mut self) {
+        if let Some(memory_set) = self.memory_set.get_mut().as_ref() {
+            // `hotplug` holds its own clone of this same `Arc`, so this
+            // can't be uniquely owned here - lock it rather than assuming
+            // `get_mut` on the `Arc` itself would succeed.
+            let mut memory_set = memory_set.lock();
+            for (index, region) in self
+                .memory_regions
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| !r.flags.contains(MappingFlags::DEVICE))
+            {
+                // A chunked RAM region (see `alloc_chunked_ram`) was mapped
+                // as one nested-page-table entry per chunk, each keyed by
+                // its own gpa, not just `region.gpa` — unmap all of them.
+                let chunk_count = self
+                    .physical_pages
+                    .get(&index)
+                    .map(|chunks| chunks.len().max(1))
+                    .unwrap_or(1);
+                let mut gpa = region.gpa;
+                for chunk_idx in 0..chunk_count {
+                    if let Err(e) = memory_set.unmap_region(gpa) {
+                        warn!(
+                            "VM [{}] teardown: failed to unmap region {:#x}: {:?}",
+                            self.vm_id, gpa, e
+                        );
+                    }
+                    if let Some(chunk) = self
+                        .physical_pages
+                        .get(&index)
+                        .and_then(|chunks| chunks.get(chunk_idx))
+                    {
+                        gpa += chunk.size();
+                    }
+                }
+            }
+        }
+        *self.memory_set.get_mut() = None;
+        *self.hotplug.get_mut() = None;
+        self.physical_pages.clear();
+        self.img_cfg.kernel_load_hpa = 0xdead_beef;
+        self.img_cfg.bios_load_hpa = 0xdead_beef;
+        self.img_cfg.ramdisk_load_hpa = 0xdead_beef;
+    }
+
     fn gpa_to_hpa_inside_ram_memory_region(&self, addr: GuestPhysAddr) -> Option<HostPhysAddr> {
         for (index, region) in self.memory_regions.iter().enumerate() {
             if region.flags.contains(MappingFlags::DEVICE) {
@@ -225,10 +570,19 @@ impl VMCfgEntry {
             }
             if ((region.gpa..region.gpa + region.size).contains(&addr)) {
                 debug!("Target GuestPhysAddr {:#x} belongs to \n\t{}", addr, region);
-                return self
-                    .physical_pages
-                    .get(&index)
-                    .map(|pages| pages.start_paddr(virt_to_phys).as_usize() + addr - region.gpa);
+                // Walk the region's chunks in guest-address order to find
+                // the one `addr` actually falls in; with a single
+                // contiguous chunk (the common case) this is just its base
+                // address plus the offset, same as before.
+                let mut offset_in_region = addr - region.gpa;
+                for chunk in self.physical_pages.get(&index)? {
+                    let chunk_size = chunk.size();
+                    if offset_in_region < chunk_size {
+                        return Some(chunk.start_paddr(virt_to_phys).as_usize() + offset_in_region);
+                    }
+                    offset_in_region -= chunk_size;
+                }
+                return None;
             }
         }
 
@@ -289,6 +643,263 @@ impl VMCfgEntry {
             self.img_cfg.ramdisk_load_hpa,
         )
     }
+
+    /// Load a `VmTLinux` guest via the x86 Linux boot protocol instead of a
+    /// hand-computed entry point: parse `kernel`'s bzImage setup header, copy
+    /// the protected-mode kernel to `kernel_load_hpa` (and `initrd`, if any,
+    /// to `ramdisk_load_hpa`), build a `boot_params` zero page describing the
+    /// command line / ramdisk / memory map at [`LINUX_ZERO_PAGE_GPA`], and
+    /// point `vm_entry_point` at the kernel's 32-bit entry (its load address
+    /// plus 0x200). `get_img_load_info` must have already been called so
+    /// `kernel_load_hpa`/`ramdisk_load_hpa` are populated.
+    pub fn load_linux_image(&mut self, kernel: &[u8], initrd: Option<&[u8]>) -> Result {
+        if kernel.len() < HDR_MAGIC_OFFSET + 4 {
+            warn!("Linux kernel image too small to contain a setup header");
+            return Err(Error::InvalidParam);
+        }
+        let boot_flag = u16::from_le_bytes(
+            kernel[BOOT_FLAG_OFFSET..BOOT_FLAG_OFFSET + 2]
+                .try_into()
+                .unwrap(),
+        );
+        let hdr_magic = u32::from_le_bytes(
+            kernel[HDR_MAGIC_OFFSET..HDR_MAGIC_OFFSET + 4]
+                .try_into()
+                .unwrap(),
+        );
+        if boot_flag != BOOT_FLAG_MAGIC || hdr_magic != HDR_MAGIC {
+            warn!(
+                "Linux kernel image is not a valid bzImage: boot_flag {:#x}, HdrS magic {:#x}",
+                boot_flag, hdr_magic
+            );
+            return Err(Error::InvalidParam);
+        }
+
+        // `setup_sects` counts 512-byte sectors of setup code following the
+        // boot sector; 0 means the historical default of 4.
+        let setup_sects = match kernel[SETUP_SECTS_OFFSET] {
+            0 => 4,
+            n => n as usize,
+        };
+        let setup_size = (setup_sects + 1) * 512;
+        if kernel.len() <= setup_size {
+            warn!("Linux kernel image has no protected-mode kernel past its setup header");
+            return Err(Error::InvalidParam);
+        }
+        let protected_mode_kernel = &kernel[setup_size..];
+        Self::write_guest_phys(self.img_cfg.kernel_load_hpa, protected_mode_kernel);
+
+        let ramdisk_len = match initrd {
+            Some(initrd) => {
+                Self::write_guest_phys(self.img_cfg.ramdisk_load_hpa, initrd);
+                initrd.len()
+            }
+            None => 0,
+        };
+
+        let mut zero_page = [0u8; PAGE_SIZE_4K];
+        // Preserve every field the kernel's own setup header shipped (video
+        // mode, misc boot-protocol hints, ...) by copying it into the zero
+        // page at the same offset, then override just the fields a loader
+        // owns.
+        let header_len = (kernel.len() - SETUP_SECTS_OFFSET).min(setup_size - SETUP_SECTS_OFFSET);
+        zero_page[SETUP_SECTS_OFFSET..SETUP_SECTS_OFFSET + header_len]
+            .copy_from_slice(&kernel[SETUP_SECTS_OFFSET..SETUP_SECTS_OFFSET + header_len]);
+
+        zero_page[TYPE_OF_LOADER_OFFSET] = TYPE_OF_LOADER_UNDEFINED;
+        zero_page[RAMDISK_IMAGE_OFFSET..RAMDISK_IMAGE_OFFSET + 4]
+            .copy_from_slice(&(self.img_cfg.ramdisk_load_gpa as u32).to_le_bytes());
+        zero_page[RAMDISK_SIZE_OFFSET..RAMDISK_SIZE_OFFSET + 4]
+            .copy_from_slice(&(ramdisk_len as u32).to_le_bytes());
+        zero_page[CMD_LINE_PTR_OFFSET..CMD_LINE_PTR_OFFSET + 4]
+            .copy_from_slice(&(LINUX_CMDLINE_GPA as u32).to_le_bytes());
+
+        self.write_e820_table(&mut zero_page);
+
+        match self.gpa_to_hpa_inside_ram_memory_region(LINUX_CMDLINE_GPA) {
+            Some(cmdline_hpa) => {
+                let mut cmdline_bytes = self.cmdline.clone().into_bytes();
+                cmdline_bytes.push(0);
+                Self::write_guest_phys(cmdline_hpa, &cmdline_bytes);
+            }
+            None => {
+                warn!(
+                    "Linux cmdline gpa {:#x} not in ram memory range",
+                    LINUX_CMDLINE_GPA
+                );
+                return Err(Error::InvalidParam);
+            }
+        }
+
+        match self.gpa_to_hpa_inside_ram_memory_region(LINUX_ZERO_PAGE_GPA) {
+            Some(zero_page_hpa) => Self::write_guest_phys(zero_page_hpa, &zero_page),
+            None => {
+                warn!(
+                    "Linux boot_params gpa {:#x} not in ram memory range",
+                    LINUX_ZERO_PAGE_GPA
+                );
+                return Err(Error::InvalidParam);
+            }
+        }
+
+        // The boot protocol's 32-bit (and, on a 64-bit kernel, 64-bit) entry
+        // point is the protected-mode kernel's load address plus 0x200,
+        // skipping the legacy real-mode entry at its very start.
+        self.img_cfg.vm_entry_point = self.img_cfg.kernel_load_gpa + 0x200;
+
+        Ok(())
+    }
+
+    /// Synthesize an e820 map from `memory_regions` into `zero_page`, via
+    /// [`e820_from_regions`] so the kernel's view of RAM stays in lockstep
+    /// with the coalesced, classified region list rather than being
+    /// derived separately.
+    fn write_e820_table(&self, zero_page: &mut [u8; PAGE_SIZE_4K]) {
+        let entries = e820_from_regions(&self.memory_regions);
+        let entries = &entries[..entries.len().min(E820_MAX_ENTRIES)];
+
+        zero_page[E820_ENTRIES_OFFSET] = entries.len() as u8;
+        for (i, entry) in entries.iter().enumerate() {
+            let off = E820_TABLE_OFFSET + i * E820_ENTRY_SIZE;
+            zero_page[off..off + 8].copy_from_slice(&entry.addr.to_le_bytes());
+            zero_page[off + 8..off + 16].copy_from_slice(&entry.size.to_le_bytes());
+            zero_page[off + 16..off + 20].copy_from_slice(&entry.ty.to_le_bytes());
+        }
+    }
+
+    /// Copy `data` into guest RAM at host physical address `hpa`.
+    fn write_guest_phys(hpa: HostPhysAddr, data: &[u8]) {
+        let hva = usize::from(phys_to_virt(PhysAddr::from(hpa)));
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), hva as *mut u8, data.len());
+        }
+    }
+
+    /// Write an Intel MP Floating Pointer Structure ("_MP_") and MP
+    /// configuration table ("PCMP") at [`MPTABLE_GPA`]: one processor entry
+    /// per CPU set in `cpu_set`, a single ISA bus, one I/O APIC, and
+    /// identity-mapped ISA IRQ entries. Lets an SMP guest enumerate its
+    /// vCPUs without requiring full ACPI.
+    pub fn setup_mptable(&mut self) -> Result {
+        let num_cpus = self.cpu_set.count_ones();
+        if num_cpus == 0 || num_cpus > u8::MAX as u32 {
+            warn!(
+                "Cannot build MP table: cpu_set {:#x} has an unsupported CPU count",
+                self.cpu_set
+            );
+            return Err(Error::InvalidParam);
+        }
+
+        let mut config_table = Vec::new();
+        config_table.extend_from_slice(&MP_CONFIG_TABLE_SIG);
+        config_table.extend_from_slice(&0u16.to_le_bytes()); // base table length, patched below
+        config_table.push(MP_SPEC_REV);
+        config_table.push(0); // checksum, patched below
+        config_table.extend_from_slice(b"ARCEOS  "); // OEM id, 8 bytes
+        config_table.extend_from_slice(b"VMCFGENTRY  "); // product id, 12 bytes
+        config_table.extend_from_slice(&0u32.to_le_bytes()); // OEM table pointer
+        config_table.extend_from_slice(&0u16.to_le_bytes()); // OEM table size
+        config_table.extend_from_slice(&0u16.to_le_bytes()); // entry count, patched below
+        config_table.extend_from_slice(&IO_APIC_ADDRESS.to_le_bytes());
+        config_table.extend_from_slice(&0u16.to_le_bytes()); // extended table length
+        config_table.push(0); // extended table checksum
+        config_table.push(0); // reserved
+        debug_assert_eq!(config_table.len(), MP_CONFIG_TABLE_HEADER_SIZE);
+
+        let mut entry_count: u16 = 0;
+        for local_apic_id in 0..num_cpus as u8 {
+            config_table.push(MP_ENTRY_PROCESSOR);
+            config_table.push(local_apic_id);
+            config_table.push(0x14); // local APIC version
+            let flags = MP_CPU_FLAG_ENABLED
+                | if local_apic_id == 0 {
+                    MP_CPU_FLAG_BSP
+                } else {
+                    0
+                };
+            config_table.push(flags);
+            config_table.extend_from_slice(&0u32.to_le_bytes()); // cpu signature
+            config_table.extend_from_slice(&0u32.to_le_bytes()); // feature flags
+            config_table.extend_from_slice(&[0u8; 8]); // reserved
+            entry_count += 1;
+        }
+
+        let ioapic_id = num_cpus as u8;
+
+        // One ISA bus.
+        config_table.push(MP_ENTRY_BUS);
+        config_table.push(0); // bus id
+        config_table.extend_from_slice(b"ISA   ");
+        entry_count += 1;
+
+        // One I/O APIC.
+        config_table.push(MP_ENTRY_IOAPIC);
+        config_table.push(ioapic_id);
+        config_table.push(0x11); // I/O APIC version
+        config_table.push(MP_IOAPIC_FLAG_ENABLED);
+        config_table.extend_from_slice(&IO_APIC_ADDRESS.to_le_bytes());
+        entry_count += 1;
+
+        // Identity-mapped ISA IRQ entries, one per legacy PIC line.
+        for irq in 0..16u8 {
+            config_table.push(MP_ENTRY_IO_INTERRUPT);
+            config_table.push(MP_INT_TYPE_INT);
+            config_table.extend_from_slice(&0u16.to_le_bytes()); // flags: conforms to bus spec
+            config_table.push(0); // source bus id (the ISA bus above)
+            config_table.push(irq); // source bus irq
+            config_table.push(ioapic_id);
+            config_table.push(irq); // destination I/O APIC intin
+            entry_count += 1;
+        }
+
+        let table_len = config_table.len() as u16;
+        config_table[MP_TABLE_LEN_OFFSET..MP_TABLE_LEN_OFFSET + 2]
+            .copy_from_slice(&table_len.to_le_bytes());
+        config_table[MP_TABLE_ENTRY_COUNT_OFFSET..MP_TABLE_ENTRY_COUNT_OFFSET + 2]
+            .copy_from_slice(&entry_count.to_le_bytes());
+        config_table[MP_TABLE_CHECKSUM_OFFSET] = mp_checksum(&config_table);
+
+        let config_table_gpa = MPTABLE_GPA + MP_FLOATING_POINTER_SIZE;
+        let mut fp_structure = [0u8; MP_FLOATING_POINTER_SIZE];
+        fp_structure[0..4].copy_from_slice(&MP_FLOATING_POINTER_SIG);
+        fp_structure[4..8].copy_from_slice(&(config_table_gpa as u32).to_le_bytes());
+        fp_structure[8] = 1; // length in 16-byte paragraphs
+        fp_structure[9] = MP_SPEC_REV;
+        // fp_structure[10] (checksum) and [11..16] (feature bytes, all zero
+        // meaning "use the MP configuration table") are left at their
+        // zero-initialized value until the checksum is computed below.
+        fp_structure[10] = mp_checksum(&fp_structure);
+
+        let mptable_hpa = self.gpa_to_hpa_inside_ram_memory_region(MPTABLE_GPA).ok_or_else(|| {
+            warn!("MP table gpa {:#x} not in ram memory range", MPTABLE_GPA);
+            Error::InvalidParam
+        })?;
+        Self::write_guest_phys(mptable_hpa, &fp_structure);
+        Self::write_guest_phys(
+            mptable_hpa + MP_FLOATING_POINTER_SIZE,
+            &config_table,
+        );
+
+        Ok(())
+    }
+
+    /// Build and write an ACPI RSDP/XSDT/FADT/MADT/DSDT blob (see
+    /// [`crate::config::acpi`]) at [`ACPI_RSDP_GPA`], inside the
+    /// 0xE0000-0xFFFFF window a guest scans for the RSDP signature on real
+    /// hardware. One MADT Processor Local APIC entry is generated per CPU
+    /// set in `cpu_set`.
+    pub fn setup_acpi_tables(&mut self) -> Result {
+        let blob = acpi::build_tables(self.cpu_set, ACPI_RSDP_GPA);
+
+        let acpi_hpa = self.gpa_to_hpa_inside_ram_memory_region(ACPI_RSDP_GPA).ok_or_else(|| {
+            warn!("ACPI RSDP gpa {:#x} not in ram memory range", ACPI_RSDP_GPA);
+            Error::InvalidParam
+        })?;
+        Self::write_guest_phys(acpi_hpa, &blob);
+        self.acpi_rsdp_gpa = Some(ACPI_RSDP_GPA);
+
+        Ok(())
+    }
 }
 
 static GLOBAL_VM_CFG_TABLE: Mutex<VmConfigTable> = Mutex::new(VmConfigTable::new());
@@ -310,3 +921,30 @@ pub fn vm_cfg_add_vm_entry(mut vm_cfg_entry: VMCfgEntry) -> Result<usize> {
     vm_configs.entries.insert(vm_id, Arc::new(vm_cfg_entry));
     Ok(vm_id)
 }
+
+/* Remove a VM config entry from DEF_VM_CONFIG_TABLE, reclaiming its guest
+ * RAM and nested page table.
+ *
+ * @param[in] vm_id: the VM id to tear down.
+ *
+ * Fails with `Error::OutOfRange` if `vm_id` names no entry, or
+ * `Error::InUse` if some other reference to the entry still exists (e.g. a
+ * booted vCPU holding the `Arc` handed out by `vm_cfg_entry`) — a VM must
+ * finish shutting down its vCPUs before its config entry can be torn down.
+ */
+pub fn vm_cfg_remove_vm_entry(vm_id: usize) -> Result {
+    let mut vm_configs = GLOBAL_VM_CFG_TABLE.lock();
+    let Some(entry) = vm_configs.entries.get(&vm_id) else {
+        error!("illegal vm id {}", vm_id);
+        return Err(Error::OutOfRange);
+    };
+    if Arc::strong_count(entry) > 1 {
+        return Err(Error::InUse);
+    }
+
+    let mut entry = vm_configs.entries.remove(&vm_id).unwrap();
+    Arc::get_mut(&mut entry)
+        .expect("strong_count == 1 was just checked, no other owner can appear under the lock")
+        .teardown();
+    Ok(())
+}
@@ -1,4 +1,13 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 use super::{BIOS_ENTRY, BIOS_PADDR, BIOS_SIZE, GUEST_PHYS_MEMORY_BASE, GUEST_PHYS_MEMORY_SIZE};
+#[cfg(feature = "guest_linux")]
+use super::{
+    LINUX_CMDLINE_GPA, LINUX_KERNEL_IMAGE_PADDR, LINUX_KERNEL_IMAGE_SIZE, LINUX_KERNEL_LOAD_GPA,
+    LINUX_RAMDISK_IMAGE_PADDR, LINUX_RAMDISK_IMAGE_SIZE, LINUX_RAMDISK_LOAD_GPA,
+    LINUX_ZERO_PAGE_GPA,
+};
 use crate::mm::{GuestMemoryRegion, GuestPhysMemorySet};
 use crate::{phys_to_virt, virt_to_phys, Result as HyperResult};
 use hypercraft::{
@@ -7,6 +16,62 @@ use hypercraft::{
 
 use page_table_entry::MappingFlags;
 
+/// Low guest-physical address where [`setup_gpm`] writes its e820 table, in
+/// the same EBDA-area convention as [`super::LINUX_ZERO_PAGE_GPA`]/
+/// [`super::MPTABLE_GPA`]: a `u32` entry count followed by that many
+/// `(base: u64, length: u64, type: u32)` records. This path has no zero
+/// page of its own to stash them in - see `VMCfgEntry::write_e820_table`
+/// for the Linux boot protocol's zero-page variant.
+const E820_TABLE_GPA: GuestPhysAddr = 0x0009_6000;
+const E820_TYPE_RAM: u32 = 1;
+const E820_TYPE_RESERVED: u32 = 2;
+const E820_TYPE_ACPI: u32 = 3;
+
+/// Kind of a configured [`GpmRegionCfg`]: selects its nested-page-table
+/// mapping flags and whether/how it's reported in the e820 map. `Device`
+/// regions are MMIO, not memory, and are mapped but never appear in e820.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemRegionKind {
+    Ram,
+    Reserved,
+    Device,
+    Acpi,
+}
+
+impl MemRegionKind {
+    fn mapping_flags(self) -> MappingFlags {
+        match self {
+            MemRegionKind::Ram => MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+            MemRegionKind::Reserved | MemRegionKind::Acpi => {
+                MappingFlags::READ | MappingFlags::WRITE
+            }
+            MemRegionKind::Device => MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+        }
+    }
+
+    fn e820_type(self) -> Option<u32> {
+        match self {
+            MemRegionKind::Ram => Some(E820_TYPE_RAM),
+            MemRegionKind::Reserved => Some(E820_TYPE_RESERVED),
+            MemRegionKind::Acpi => Some(E820_TYPE_ACPI),
+            MemRegionKind::Device => None,
+        }
+    }
+}
+
+/// One entry of a VM's memory map, driving both [`setup_gpm`]'s
+/// nested-page-table mapping loop and the e820 table it writes for the
+/// guest - replaces the fixed inline region list (and its per-guest
+/// `#[cfg(feature = "guest_linux")]` carve-outs) with data that both
+/// consumers read the same way.
+#[derive(Debug, Clone, Copy)]
+pub struct GpmRegionCfg {
+    pub gpa: GuestPhysAddr,
+    pub hpa: HostPhysAddr,
+    pub size: usize,
+    pub kind: MemRegionKind,
+}
+
 #[repr(align(4096))]
 pub(super) struct AlignedMemory<const LEN: usize>([u8; LEN]);
 
@@ -38,86 +103,276 @@ fn load_guest_image(id: usize, hpa: HostPhysAddr, load_gpa: GuestPhysAddr, size:
     }
 }
 
-#[cfg(target_arch = "x86_64")]
-pub fn setup_gpm(id: usize) -> HyperResult<GuestPhysMemorySet> {
-    // copy BIOS and guest images
-
-    load_guest_image(id, BIOS_PADDR, BIOS_ENTRY, BIOS_SIZE);
-    #[cfg(feature = "guest_nimbos")]
-    {
-        load_guest_image(id, GUEST_IMAGE_PADDR, GUEST_ENTRY, GUEST_IMAGE_SIZE);
-    }
-
-    // create nested page table and add mapping
-    let mut gpm = GuestPhysMemorySet::new()?;
-    let guest_memory_regions = [
-        GuestMemoryRegion {
-            // Low RAM
-            gpa: GUEST_PHYS_MEMORY_BASE,
-            hpa: virt_to_phys((gpa_as_mut_ptr(id, GUEST_PHYS_MEMORY_BASE) as HostVirtAddr).into())
-                .into(),
-            size: GUEST_PHYS_MEMORY_SIZE,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
-        },
-        #[cfg(feature = "guest_linux")]
-        GuestMemoryRegion {
+/// The memory map `setup_gpm` mapped before it became configurable: low RAM
+/// backed by this VM's `GUEST_PHYS_MEMORY` scratch buffer, the
+/// `guest_linux` feature's extra RAM windows, and the fixed PCI/IO APIC/
+/// HPET/local APIC MMIO windows every guest gets.
+fn default_gpm_regions(id: usize) -> Vec<GpmRegionCfg> {
+    let mut regions = vec![GpmRegionCfg {
+        // Low RAM
+        gpa: GUEST_PHYS_MEMORY_BASE,
+        hpa: virt_to_phys((gpa_as_mut_ptr(id, GUEST_PHYS_MEMORY_BASE) as HostVirtAddr).into())
+            .into(),
+        size: GUEST_PHYS_MEMORY_SIZE,
+        kind: MemRegionKind::Ram,
+    }];
+    #[cfg(feature = "guest_linux")]
+    regions.extend([
+        GpmRegionCfg {
             // Low RAM2
             gpa: 0x100_0000,
             hpa: 0x6100_0000,
             size: 0xf00_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+            kind: MemRegionKind::Ram,
         },
-        #[cfg(feature = "guest_linux")]
-        GuestMemoryRegion {
+        GpmRegionCfg {
             // RAM
             gpa: 0x7000_0000,
             hpa: 0x7000_0000,
             size: 0x1000_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+            kind: MemRegionKind::Ram,
         },
-        GuestMemoryRegion {
+    ]);
+    regions.extend([
+        GpmRegionCfg {
             // PCI
             gpa: 0x8000_0000,
             hpa: 0x8000_0000,
             size: 0x1000_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-        GuestMemoryRegion {
+        GpmRegionCfg {
             gpa: 0xfe00_0000,
             hpa: 0xfe00_0000,
             size: 0x1_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-        GuestMemoryRegion {
+        GpmRegionCfg {
             gpa: 0xfeb0_0000,
             hpa: 0xfeb0_0000,
             size: 0x10_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-        GuestMemoryRegion {
+        GpmRegionCfg {
             // IO APIC
             gpa: 0xfec0_0000,
             hpa: 0xfec0_0000,
             size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-        GuestMemoryRegion {
+        GpmRegionCfg {
             // HPET
             gpa: 0xfed0_0000,
             hpa: 0xfed0_0000,
             size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-        GuestMemoryRegion {
+        GpmRegionCfg {
             // Local APIC
             gpa: 0xfee0_0000,
             hpa: 0xfee0_0000,
             size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            kind: MemRegionKind::Device,
         },
-    ];
-    for r in guest_memory_regions.into_iter() {
-        gpm.map_region(r.into())?;
+    ]);
+    regions
+}
+
+/// Assemble a sorted, coalesced e820 record list - `(base, length, type)`
+/// for every non-`Device` region - merging adjacent same-type regions the
+/// way crosvm's `x86_64` loader and bhyve's `e820.c` do before handing the
+/// map to the guest.
+fn build_e820_table(regions: &[GpmRegionCfg]) -> Vec<(u64, u64, u32)> {
+    let mut entries: Vec<(u64, u64, u32)> = regions
+        .iter()
+        .filter_map(|r| r.kind.e820_type().map(|ty| (r.gpa as u64, r.size as u64, ty)))
+        .collect();
+    entries.sort_by_key(|&(base, _, _)| base);
+
+    let mut coalesced: Vec<(u64, u64, u32)> = Vec::with_capacity(entries.len());
+    for (base, len, ty) in entries {
+        match coalesced.last_mut() {
+            Some(last) if last.2 == ty && last.0 + last.1 == base => last.1 += len,
+            _ => coalesced.push((base, len, ty)),
+        }
+    }
+    coalesced
+}
+
+/// Write `entries` at [`E820_TABLE_GPA`] as a `u32` count followed by that
+/// many `(base: u64, length: u64, type: u32)` records.
+fn write_e820_table(id: usize, entries: &[(u64, u64, u32)]) {
+    let base_ptr = gpa_as_mut_ptr(id, E820_TABLE_GPA);
+    unsafe {
+        core::ptr::write_unaligned(base_ptr as *mut u32, entries.len() as u32);
+        let mut offset = core::mem::size_of::<u32>();
+        for &(base, length, ty) in entries {
+            core::ptr::write_unaligned(base_ptr.add(offset) as *mut u64, base);
+            core::ptr::write_unaligned(base_ptr.add(offset + 8) as *mut u64, length);
+            core::ptr::write_unaligned(base_ptr.add(offset + 16) as *mut u32, ty);
+            offset += 20;
+        }
     }
-    Ok(gpm)
+}
+
+/// x86 Linux boot-protocol setup-header offsets/magic this loader reads out
+/// of the bzImage at [`LINUX_KERNEL_IMAGE_PADDR`]. Mirrors the subset
+/// `VMCfgEntry::load_linux_image` (`config/entry.rs`) checks for the
+/// type-1.5 boot path; duplicated rather than shared because that path
+/// writes through a `GuestPhysMemorySet`'s `write_guest_phys`, while this
+/// one writes straight into `GUEST_PHYS_MEMORY` through `gpa_as_mut_ptr`.
+#[cfg(feature = "guest_linux")]
+const SETUP_SECTS_OFFSET: usize = 0x1f1;
+#[cfg(feature = "guest_linux")]
+const HDR_MAGIC_OFFSET: usize = 0x202;
+#[cfg(feature = "guest_linux")]
+const HDR_MAGIC: u32 = 0x5372_6448; // "HdrS"
+#[cfg(feature = "guest_linux")]
+const TYPE_OF_LOADER_OFFSET: usize = 0x210;
+#[cfg(feature = "guest_linux")]
+const TYPE_OF_LOADER_UNDEFINED: u8 = 0xff;
+#[cfg(feature = "guest_linux")]
+const RAMDISK_IMAGE_OFFSET: usize = 0x218;
+#[cfg(feature = "guest_linux")]
+const RAMDISK_SIZE_OFFSET: usize = 0x21c;
+#[cfg(feature = "guest_linux")]
+const CMD_LINE_PTR_OFFSET: usize = 0x228;
+#[cfg(feature = "guest_linux")]
+const ZERO_PAGE_E820_ENTRIES_OFFSET: usize = 0x1e8;
+#[cfg(feature = "guest_linux")]
+const ZERO_PAGE_E820_TABLE_OFFSET: usize = 0x2d0;
+
+/// Parse the bzImage at [`LINUX_KERNEL_IMAGE_PADDR`], copy its
+/// protected-mode kernel to [`LINUX_KERNEL_LOAD_GPA`] (and the image at
+/// [`LINUX_RAMDISK_IMAGE_PADDR`] to [`LINUX_RAMDISK_LOAD_GPA`], if
+/// `LINUX_RAMDISK_IMAGE_SIZE` is nonzero), build a `boot_params` zero page
+/// at [`LINUX_ZERO_PAGE_GPA`] describing them plus `entries`' memory map,
+/// and return the kernel's real 32-bit entry point - replacing the
+/// hardcoded jump to the bootstrap stub `config_boot_linux` used before
+/// this. `%rsi` still needs to point at the zero page on entry, same as
+/// real Linux loaders arrange; `hypercraft::VCpu::new` has no parameter for
+/// initial general-purpose register state to set that through, so the
+/// guest must locate it itself (e.g. from a fixed, known GPA) until that
+/// constructor grows one.
+#[cfg(feature = "guest_linux")]
+fn load_linux_kernel_image(id: usize, entries: &[(u64, u64, u32)]) -> GuestPhysAddr {
+    let image_ptr = usize::from(phys_to_virt(LINUX_KERNEL_IMAGE_PADDR.into())) as *const u8;
+    let image = unsafe { core::slice::from_raw_parts(image_ptr, LINUX_KERNEL_IMAGE_SIZE) };
+
+    let magic = u32::from_le_bytes(
+        image[HDR_MAGIC_OFFSET..HDR_MAGIC_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    );
+    if magic != HDR_MAGIC {
+        warn!(
+            "Linux image at {:#x} missing HdrS magic ({:#x}), booting it as a raw blob anyway",
+            LINUX_KERNEL_IMAGE_PADDR, magic
+        );
+    }
+
+    // `setup_sects` counts 512-byte sectors of setup code following the
+    // boot sector; 0 means the historical default of 4.
+    let setup_sects = match image[SETUP_SECTS_OFFSET] {
+        0 => 4,
+        n => n as usize,
+    };
+    let setup_size = (setup_sects + 1) * 512;
+    let protected_mode_kernel = &image[setup_size..];
+    unsafe {
+        core::slice::from_raw_parts_mut(gpa_as_mut_ptr(id, LINUX_KERNEL_LOAD_GPA), protected_mode_kernel.len())
+            .copy_from_slice(protected_mode_kernel);
+    }
+
+    if LINUX_RAMDISK_IMAGE_SIZE > 0 {
+        load_guest_image(
+            id,
+            LINUX_RAMDISK_IMAGE_PADDR,
+            LINUX_RAMDISK_LOAD_GPA,
+            LINUX_RAMDISK_IMAGE_SIZE,
+        );
+    }
+
+    // Preserve every field the kernel's own setup header shipped (video
+    // mode, misc boot-protocol hints, ...) by copying it into the zero page
+    // at the same offset, then override just the fields a loader owns.
+    let zero_page = gpa_as_mut_ptr(id, LINUX_ZERO_PAGE_GPA);
+    unsafe {
+        core::ptr::write_bytes(zero_page, 0, 0x1000);
+        core::ptr::copy_nonoverlapping(
+            image[SETUP_SECTS_OFFSET..setup_size].as_ptr(),
+            zero_page.add(SETUP_SECTS_OFFSET),
+            setup_size - SETUP_SECTS_OFFSET,
+        );
+
+        *zero_page.add(TYPE_OF_LOADER_OFFSET) = TYPE_OF_LOADER_UNDEFINED;
+        core::ptr::write_unaligned(
+            zero_page.add(RAMDISK_IMAGE_OFFSET) as *mut u32,
+            if LINUX_RAMDISK_IMAGE_SIZE > 0 { LINUX_RAMDISK_LOAD_GPA as u32 } else { 0 },
+        );
+        core::ptr::write_unaligned(
+            zero_page.add(RAMDISK_SIZE_OFFSET) as *mut u32,
+            LINUX_RAMDISK_IMAGE_SIZE as u32,
+        );
+        core::ptr::write_unaligned(
+            zero_page.add(CMD_LINE_PTR_OFFSET) as *mut u32,
+            LINUX_CMDLINE_GPA as u32,
+        );
+
+        core::ptr::write_unaligned(
+            zero_page.add(ZERO_PAGE_E820_ENTRIES_OFFSET) as *mut u32,
+            entries.len() as u32,
+        );
+        let mut offset = ZERO_PAGE_E820_TABLE_OFFSET;
+        for &(base, length, ty) in entries {
+            core::ptr::write_unaligned(zero_page.add(offset) as *mut u64, base);
+            core::ptr::write_unaligned(zero_page.add(offset + 8) as *mut u64, length);
+            core::ptr::write_unaligned(zero_page.add(offset + 16) as *mut u32, ty);
+            offset += 20;
+        }
+    }
+
+    // The boot protocol's 32-bit entry point is the protected-mode kernel's
+    // load address plus 0x200, skipping the legacy real-mode entry at its
+    // very start.
+    LINUX_KERNEL_LOAD_GPA + 0x200
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn setup_gpm(id: usize) -> HyperResult<(GuestPhysMemorySet, GuestPhysAddr)> {
+    // copy BIOS and guest images
+
+    load_guest_image(id, BIOS_PADDR, BIOS_ENTRY, BIOS_SIZE);
+    #[cfg(feature = "guest_nimbos")]
+    {
+        load_guest_image(id, GUEST_IMAGE_PADDR, GUEST_ENTRY, GUEST_IMAGE_SIZE);
+    }
+
+    let regions = default_gpm_regions(id);
+    let e820_entries = build_e820_table(&regions);
+
+    // create nested page table and add mapping
+    let mut gpm = GuestPhysMemorySet::new()?;
+    for r in &regions {
+        gpm.map_region(
+            GuestMemoryRegion {
+                gpa: r.gpa,
+                hpa: r.hpa,
+                size: r.size,
+                flags: r.kind.mapping_flags(),
+                file_fd: None,
+                file_offset: 0,
+            }
+            .into(),
+        )?;
+    }
+
+    write_e820_table(id, &e820_entries);
+
+    #[cfg(feature = "guest_nimbos")]
+    let entry = BIOS_ENTRY;
+    #[cfg(feature = "guest_linux")]
+    let entry = load_linux_kernel_image(id, &e820_entries);
+
+    Ok((gpm, entry))
 }
@@ -31,6 +31,8 @@ pub fn setup_root_gpm() -> HyperResult<GuestPhysMemorySet> {
             hpa: hv_phys_start as HostPhysAddr,
             size: hv_phys_size,
             flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+            file_fd: None,
+            file_offset: 0,
         }
         .into(),
     )?;
@@ -76,6 +78,8 @@ pub fn setup_root_gpm() -> HyperResult<GuestPhysMemorySet> {
                 hpa: start_hpa as HostPhysAddr,
                 size: region_size,
                 flags: region.flags.into(),
+                file_fd: None,
+                file_offset: 0,
             }
             .into(),
         )?;
@@ -0,0 +1,150 @@
+//! Runtime guest-RAM hotplug: a reserved gpa window (see
+//! [`super::LINUX_HOTPLUG_MEM_BASE`]/[`super::LINUX_HOTPLUG_MEM_SIZE`]) a
+//! management path can grow or shrink live, without touching
+//! `linux_memory_regions_setup`'s fixed boot-time layout and without a
+//! reboot. Modeled on cloud-hypervisor's `HotplugMethod`/virtio-mem: each
+//! call to [`MemoryHotplugManager::add_memory_region`] allocates host
+//! backing and maps it straight into the running guest's second-stage page
+//! table, and [`MemoryHotplugManager::remove_memory_region`] is the exact
+//! inverse. This is the mechanism crosvm's balloon reclaim would sit on top
+//! of - ballooning just calls `remove_memory_region` on pages the guest has
+//! told the host it no longer needs.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use axalloc::GlobalPage;
+use axhal::mem::virt_to_phys;
+use memory_addr::PAGE_SIZE_4K;
+use page_table_entry::MappingFlags;
+use spin::Mutex;
+
+use hypercraft::GuestPhysAddr;
+
+use crate::mm::{GuestMemoryRegion, GuestPhysMemorySet};
+use crate::{Error, Result};
+
+#[inline]
+const fn align_up_4k(pos: usize) -> usize {
+    (pos + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1)
+}
+
+/// A live-added span: the [`GuestMemoryRegion`] handed to
+/// `GuestPhysMemorySet::map_region` plus the host pages backing it, kept
+/// alive only as long as this entry is - the same lifetime coupling
+/// `VMCfgEntry::physical_pages` uses for boot-time RAM.
+struct HotplugRegion {
+    region: GuestMemoryRegion,
+    _pages: Vec<GlobalPage>,
+}
+
+/// Tracks live-added/removed guest RAM inside a single reserved gpa window,
+/// keyed by each region's own gpa rather than by position - unlike
+/// `VMCfgEntry::memory_regions`, which assumes a fixed, index-stable boot
+/// layout, adding or removing one hotplug region here never disturbs any
+/// other.
+pub struct MemoryHotplugManager {
+    window_gpa: GuestPhysAddr,
+    window_size: usize,
+    memory_set: Arc<Mutex<GuestPhysMemorySet>>,
+    regions: BTreeMap<GuestPhysAddr, HotplugRegion>,
+}
+
+impl MemoryHotplugManager {
+    pub fn new(
+        window_gpa: GuestPhysAddr,
+        window_size: usize,
+        memory_set: Arc<Mutex<GuestPhysMemorySet>>,
+    ) -> Self {
+        Self {
+            window_gpa,
+            window_size,
+            memory_set,
+            regions: BTreeMap::new(),
+        }
+    }
+
+    fn in_window(&self, gpa: GuestPhysAddr, size: usize) -> bool {
+        gpa >= self.window_gpa && gpa + size <= self.window_gpa + self.window_size
+    }
+
+    fn overlaps_existing(&self, gpa: GuestPhysAddr, size: usize) -> bool {
+        let end = gpa + size;
+        self.regions.values().any(|r| {
+            let (s1, e1) = (r.region.gpa, r.region.gpa + r.region.size);
+            !(end <= s1 || e1 <= gpa)
+        })
+    }
+
+    /// Allocate `size` bytes of host RAM, map it live at `gpa` with `flags`,
+    /// and record it so `remove_memory_region` can tear it back down.
+    /// `gpa`/`size` must fall entirely inside the reserved hotplug window
+    /// and not overlap an already-added region; `size` is rounded up to a
+    /// page.
+    pub fn add_memory_region(
+        &mut self,
+        gpa: GuestPhysAddr,
+        size: usize,
+        flags: MappingFlags,
+    ) -> Result {
+        let size = align_up_4k(size);
+        if !self.in_window(gpa, size) {
+            warn!(
+                "hotplug: [{:#x}-{:#x}) falls outside the reserved window [{:#x}-{:#x})",
+                gpa,
+                gpa + size,
+                self.window_gpa,
+                self.window_gpa + self.window_size
+            );
+            return Err(Error::InvalidParam);
+        }
+        if self.regions.contains_key(&gpa) || self.overlaps_existing(gpa, size) {
+            warn!(
+                "hotplug: [{:#x}-{:#x}) overlaps an already-added region",
+                gpa,
+                gpa + size
+            );
+            return Err(Error::InvalidParam);
+        }
+
+        let pages = GlobalPage::alloc_contiguous(size / PAGE_SIZE_4K, PAGE_SIZE_4K)
+            .map_err(|_| Error::NoMemory)?;
+        let hpa = pages.start_paddr(virt_to_phys).as_usize();
+        let region = GuestMemoryRegion {
+            gpa,
+            hpa,
+            size,
+            flags,
+            file_fd: None,
+            file_offset: 0,
+        };
+
+        self.memory_set.lock().map_region(region.clone().into())?;
+        self.regions.insert(
+            gpa,
+            HotplugRegion {
+                region,
+                _pages: alloc::vec![pages],
+            },
+        );
+        Ok(())
+    }
+
+    /// Unmap and free a region previously added at `gpa`. Errors if nothing
+    /// was ever added there - unlike `GuestPhysMemorySet::unmap_region`'s
+    /// silent no-op, this is a management entry point where removing a
+    /// region that doesn't exist is a caller mistake worth reporting.
+    pub fn remove_memory_region(&mut self, gpa: GuestPhysAddr) -> Result {
+        let Some(_removed) = self.regions.remove(&gpa) else {
+            return Err(Error::InvalidParam);
+        };
+        self.memory_set.lock().unmap_region(gpa)
+    }
+
+    /// Every region currently live-mapped through this manager, in gpa
+    /// order.
+    pub fn regions(&self) -> impl Iterator<Item = &GuestMemoryRegion> {
+        self.regions.values().map(|r| &r.region)
+    }
+}
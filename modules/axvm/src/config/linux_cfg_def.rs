@@ -1,80 +1,61 @@
 use alloc::vec::Vec;
 
-use page_table_entry::MappingFlags;
-
-use crate::mm::GuestMemoryRegion;
-use crate::config::GUEST_PHYS_MEMORY_SIZE;
+use crate::config::memory_config::{MemoryConfig, MemoryZoneConfig};
 use crate::config::GUEST_PHYS_MEMORY_BASE;
+use crate::config::GUEST_PHYS_MEMORY_SIZE;
+use crate::mm::GuestMemoryRegion;
 
 // See `apps/hv/guest/vlbl/virt_int.c`
-pub fn linux_memory_regions_setup(regions: &mut Vec<GuestMemoryRegion>) {
-    let guest_memory_regions = [
+/// The Linux guest's default memory layout, as a [`MemoryConfig`] instead of
+/// a hand-built `GuestMemoryRegion` list: an alternate layout (different RAM
+/// sizes, extra MMIO windows) is now a different `MemoryConfig` passed to
+/// [`build_linux_memory_regions`], not a recompile of this function.
+pub fn default_linux_memory_config() -> MemoryConfig {
+    let mut config = MemoryConfig::default();
+    config
         // 0x0000_0000 ~ 0x0100_0000
-        GuestMemoryRegion {
-            // Low RAM
-            gpa: GUEST_PHYS_MEMORY_BASE,
-            hpa: 0,
-            size: GUEST_PHYS_MEMORY_SIZE,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
-        },
+        .push(MemoryZoneConfig::ram(
+            "lowmem",
+            GUEST_PHYS_MEMORY_BASE,
+            GUEST_PHYS_MEMORY_SIZE,
+        ))
         // 0x0100_0000 ~ 0x1000_0000 (16m ~ 256m)
-        GuestMemoryRegion {
-            // Low RAM2
-            gpa: 0x100_0000,
-            hpa: 0,
-            size: 0xf00_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
-        },
+        .push(MemoryZoneConfig::ram("lowmem2", 0x100_0000, 0xf00_0000))
         // 0x7000_0000 ~ 0x8000_0000
-        GuestMemoryRegion {
-            // RAM
-            gpa: 0x7000_0000,
-            hpa: 0,
-            size: 0x1000_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
-        },
-        GuestMemoryRegion {
-            // PCI
-            gpa: 0x8000_0000,
-            hpa: 0x8000_0000,
-            size: 0x1000_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-        GuestMemoryRegion {
-            gpa: 0xfe00_0000,
-            hpa: 0xfe00_0000,
-            size: 0x1_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-        GuestMemoryRegion {
-            gpa: 0xfeb0_0000,
-            hpa: 0xfeb0_0000,
-            size: 0x10_0000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-        GuestMemoryRegion {
-            // IO APIC
-            gpa: 0xfec0_0000,
-            hpa: 0xfec0_0000,
-            size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-        GuestMemoryRegion {
-            // HPET
-            gpa: 0xfed0_0000,
-            hpa: 0xfed0_0000,
-            size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-        GuestMemoryRegion {
-            // Local APIC
-            gpa: 0xfee0_0000,
-            hpa: 0xfee0_0000,
-            size: 0x1000,
-            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
-        },
-    ];
-    for r in guest_memory_regions {
-        regions.push(r);
-    }
+        .push(MemoryZoneConfig::ram("ram", 0x7000_0000, 0x1000_0000))
+        // PCI
+        .push(MemoryZoneConfig::device(
+            "pci", 0x8000_0000, 0x8000_0000, 0x1000_0000,
+        ))
+        .push(MemoryZoneConfig::device(
+            "mmio0", 0xfe00_0000, 0xfe00_0000, 0x1_0000,
+        ))
+        .push(MemoryZoneConfig::device(
+            "mmio1", 0xfeb0_0000, 0xfeb0_0000, 0x10_0000,
+        ))
+        // IO APIC
+        .push(MemoryZoneConfig::device(
+            "ioapic", 0xfec0_0000, 0xfec0_0000, 0x1000,
+        ))
+        // HPET
+        .push(MemoryZoneConfig::device(
+            "hpet", 0xfed0_0000, 0xfed0_0000, 0x1000,
+        ))
+        // Local APIC
+        .push(MemoryZoneConfig::device(
+            "lapic", 0xfee0_0000, 0xfee0_0000, 0x1000,
+        ));
+    config
+}
+
+/// Build a Linux guest's `GuestMemoryRegion`s from an explicit
+/// [`MemoryConfig`], for a caller (e.g. a future config-file loader) that
+/// wants a non-default layout without touching this module at all.
+pub fn build_linux_memory_regions(config: &MemoryConfig, regions: &mut Vec<GuestMemoryRegion>) {
+    config.build_regions(regions);
+}
+
+/// `VMCfgEntry::memory_region_editor` callback: the default Linux layout.
+pub fn linux_memory_regions_setup(regions: &mut Vec<GuestMemoryRegion>) {
+    build_linux_memory_regions(&default_linux_memory_config(), regions);
 }
@@ -0,0 +1,156 @@
+//! Data-driven guest memory layout, replacing the hand-built
+//! `GuestMemoryRegion` lists that used to live directly in each guest's
+//! `*_cfg_def.rs` (see `linux_cfg_def::default_linux_memory_config`).
+//! Modeled on cloud-hypervisor's `MemoryManager`/`MemoryZoneConfig`, trimmed
+//! to what this crate's nested-page-table setup (`VMCfgEntry::
+//! set_up_memory_region`) actually consumes: a zone is either RAM, whose
+//! host backing is allocated dynamically and left for that function to
+//! fill in, or a device/MMIO hole that's identity-mapped GPA->HPA up front.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use page_table_entry::MappingFlags;
+
+use crate::mm::GuestMemoryRegion;
+use crate::{GuestPhysAddr, HostPhysAddr};
+
+/// Whether a [`MemoryZoneConfig`] backs guest RAM or a passthrough/
+/// identity-mapped MMIO window (PCI hole, IOAPIC, HPET, LAPIC, ...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryZoneKind {
+    /// Host pages are allocated and the zone's `hpa` filled in later, by
+    /// `VMCfgEntry::set_up_memory_region`.
+    Ram,
+    /// Identity- or passthrough-mapped at `hpa_base` from the moment the
+    /// region is built; never touched by `set_up_memory_region`.
+    Device { hpa_base: HostPhysAddr },
+}
+
+/// One contiguous span of a guest's physical address space - the unit
+/// [`MemoryConfig`] is built from.
+#[derive(Debug, Clone)]
+pub struct MemoryZoneConfig {
+    /// Human-readable label, for logging; not otherwise load-bearing.
+    pub id: String,
+    pub gpa_base: GuestPhysAddr,
+    pub size: usize,
+    /// Host NUMA node this zone's RAM should be allocated from, if the
+    /// host backing allocator (`GlobalPage`) is ever taught to honor one.
+    /// Not consulted by `VMCfgEntry::set_up_memory_region` today; carried
+    /// here so a zone list can already describe the intent.
+    pub host_numa_node: Option<u32>,
+    pub kind: MemoryZoneKind,
+}
+
+impl MemoryZoneConfig {
+    pub fn ram(id: &str, gpa_base: GuestPhysAddr, size: usize) -> Self {
+        Self {
+            id: String::from(id),
+            gpa_base,
+            size,
+            host_numa_node: None,
+            kind: MemoryZoneKind::Ram,
+        }
+    }
+
+    pub fn device(id: &str, gpa_base: GuestPhysAddr, hpa_base: HostPhysAddr, size: usize) -> Self {
+        Self {
+            id: String::from(id),
+            gpa_base,
+            size,
+            host_numa_node: None,
+            kind: MemoryZoneKind::Device { hpa_base },
+        }
+    }
+}
+
+/// Emit the `GuestMemoryRegion`s covering `[gpa_base, gpa_base + size)`
+/// minus every `(offset, len)` sub-range in `holes` (each relative to
+/// `gpa_base`/`hpa_base`, which this assumes track each other 1:1 the way a
+/// passthrough BAR does). Built for VFIO device assignment: map a whole
+/// host BAR straight through as `MappingFlags::DEVICE`, then carve the
+/// MSI-X table/PBA back out so those pages stay unmapped in the nested
+/// page table and keep trapping for `pci::msix` to emulate, mirroring
+/// cloud-hypervisor's map-then-punch-holes approach to the same problem.
+/// `holes` need not be sorted or non-overlapping.
+pub fn device_region_with_holes(
+    gpa_base: GuestPhysAddr,
+    hpa_base: HostPhysAddr,
+    size: usize,
+    holes: &[(usize, usize)],
+    regions: &mut Vec<GuestMemoryRegion>,
+) {
+    let mut holes = holes.to_vec();
+    holes.sort_by_key(|&(offset, _)| offset);
+
+    let mut cursor = 0usize;
+    for (offset, len) in holes {
+        if offset > cursor {
+            push_device_subrange(gpa_base, hpa_base, cursor, offset - cursor, regions);
+        }
+        cursor = cursor.max(offset + len);
+    }
+    if cursor < size {
+        push_device_subrange(gpa_base, hpa_base, cursor, size - cursor, regions);
+    }
+}
+
+fn push_device_subrange(
+    gpa_base: GuestPhysAddr,
+    hpa_base: HostPhysAddr,
+    offset: usize,
+    len: usize,
+    regions: &mut Vec<GuestMemoryRegion>,
+) {
+    regions.push(GuestMemoryRegion {
+        gpa: gpa_base + offset,
+        hpa: hpa_base + offset,
+        size: len,
+        flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+        file_fd: None,
+        file_offset: 0,
+    });
+}
+
+/// A guest's full memory layout: its RAM spans and device/MMIO holes, in
+/// the order they should become `GuestMemoryRegion`s.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryConfig {
+    pub zones: Vec<MemoryZoneConfig>,
+}
+
+impl MemoryConfig {
+    pub fn push(&mut self, zone: MemoryZoneConfig) -> &mut Self {
+        self.zones.push(zone);
+        self
+    }
+
+    /// Append each zone's `GuestMemoryRegion` translation onto `regions`,
+    /// the shape `VMCfgEntry::memory_region_editor` callbacks are expected
+    /// to fill in. RAM zones get `hpa: 0` as a placeholder;
+    /// `VMCfgEntry::set_up_memory_region` allocates and overwrites it for
+    /// every region without `MappingFlags::DEVICE` set.
+    pub fn build_regions(&self, regions: &mut Vec<GuestMemoryRegion>) {
+        for zone in &self.zones {
+            let (hpa, flags) = match zone.kind {
+                MemoryZoneKind::Ram => (
+                    0,
+                    MappingFlags::READ | MappingFlags::WRITE | MappingFlags::EXECUTE,
+                ),
+                MemoryZoneKind::Device { hpa_base } => (
+                    hpa_base,
+                    MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+                ),
+            };
+            regions.push(GuestMemoryRegion {
+                gpa: zone.gpa_base,
+                hpa,
+                size: zone.size,
+                flags,
+                file_fd: None,
+                file_offset: 0,
+            });
+        }
+    }
+}
@@ -21,6 +21,22 @@ pub const LINUX_BIOS_LOAD_GPA: GuestPhysAddr = 0x7c00;
 pub const LINUX_KERNEL_LOAD_GPA: GuestPhysAddr = 0x70200000;
 pub const LINUX_RAMDISK_LOAD_GPA: GuestPhysAddr = 0x72000000;
 
+// Low-memory scratch addresses used by `VMCfgEntry::load_linux_image` to
+// place the `boot_params` zero page and the guest command line, following
+// the same convention as kvmtool/firecracker.
+pub const LINUX_ZERO_PAGE_GPA: GuestPhysAddr = 0x0009_0000;
+pub const LINUX_CMDLINE_GPA: GuestPhysAddr = 0x0002_0000;
+
+/// Fixed EBDA GPA where `VMCfgEntry::setup_mptable` publishes the MP
+/// floating pointer structure and configuration table, the same location
+/// real BIOSes leave an EBDA at.
+pub const MPTABLE_GPA: GuestPhysAddr = 0x0009_fc00;
+
+/// Fixed GPA where `VMCfgEntry::setup_acpi_tables` publishes the RSDP,
+/// inside the 0xE0000-0xFFFFF window a guest's firmware/bootloader scans
+/// for it on real hardware.
+pub const ACPI_RSDP_GPA: GuestPhysAddr = 0x000e_0000;
+
 cfg_block! {
     #[cfg(feature = "guest_nimbos")]
     {
@@ -33,11 +49,31 @@ cfg_block! {
     #[cfg(feature = "guest_linux")]
     {
         pub const BIOS_ENTRY: GuestPhysAddr = 0x7c00;
+
+        /// Host-physical scratch address the build places the raw bzImage
+        /// blob at, the `guest_linux` counterpart of [`GUEST_IMAGE_PADDR`]'s
+        /// nimbos convention. Read by
+        /// [`super::gpm_def::load_linux_kernel_image`].
+        pub const LINUX_KERNEL_IMAGE_PADDR: HostPhysAddr = 0x401_0000;
+        pub const LINUX_KERNEL_IMAGE_SIZE: usize = 0x60_0000; // 6M, generous for a bzImage.
+
+        /// As above, for the initrd; `LINUX_RAMDISK_IMAGE_SIZE` of 0 means
+        /// no ramdisk is attached.
+        pub const LINUX_RAMDISK_IMAGE_PADDR: HostPhysAddr = 0x4a0_0000;
+        pub const LINUX_RAMDISK_IMAGE_SIZE: usize = 0;
     }
 }
 pub const GUEST_PHYS_MEMORY_BASE: GuestPhysAddr = 0;
 pub const GUEST_PHYS_MEMORY_SIZE: usize = 0x100_0000; // 16M
 
+/// Reserved gpa window for `hotplug::MemoryHotplugManager` to grow a
+/// running Linux guest's RAM into. Deliberately left out of
+/// `linux_cfg_def::default_linux_memory_config`, so `set_up_memory_region`
+/// never touches it at boot - a live-added region only ever appears here
+/// once `MemoryHotplugManager::add_memory_region` is called.
+pub const LINUX_HOTPLUG_MEM_BASE: GuestPhysAddr = 0x1_0000_0000; // 4G
+pub const LINUX_HOTPLUG_MEM_SIZE: usize = 0x4000_0000; // 1G window
+
 #[cfg(not(feature = "type1_5"))]
 #[path = "gpm_def.rs"]
 mod gpm_def;
@@ -48,7 +84,10 @@ mod gpm_def;
 #[cfg(feature = "type1_5")]
 pub use gpm_def::{init_root_gpm, root_gpm};
 
+pub mod hotplug;
 pub mod linux_cfg_def;
+pub mod memory_config;
 pub mod nimbos_cfg_def;
 
+mod acpi;
 pub mod entry;
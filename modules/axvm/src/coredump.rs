@@ -0,0 +1,323 @@
+//! ELF64 core dump generation for a guest, either on an unrecoverable fault
+//! or on operator request, so the guest's memory and register state can be
+//! inspected with `gdb -c core`.
+//!
+//! The file layout is the standard Linux core layout: an ELF header, one
+//! `PT_NOTE` segment carrying an `NT_PRSTATUS` note per vCPU, then one
+//! `PT_LOAD` segment per RAM region of [`crate::mm::GuestPhysMemorySet`]
+//! (device/MMIO regions are skipped: `Mapper::Mmio` ranges aren't even in
+//! the nested page table, and passthrough BARs can have read side effects).
+//! Output is streamed through [`CoreWriter`] rather than built up in memory,
+//! since a guest's memory can be many times larger than the host's.
+
+use alloc::vec::Vec;
+
+use hypercraft::{GuestPhysAddr, HyperCraftHal, VCpu};
+use memory_addr::PAGE_SIZE_4K;
+use page_table_entry::MappingFlags;
+use pci::util::byte_code::ByteCode;
+
+use crate::mm::{GuestPhysMemorySet, MapRegion};
+use crate::{Error, Result as HyperResult};
+
+const EI_NIDENT: usize = 16;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const EV_CURRENT: u8 = 1;
+const ELFOSABI_NONE: u8 = 0;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1 << 0;
+const PF_W: u32 = 1 << 1;
+const PF_R: u32 = 1 << 2;
+
+const NT_PRSTATUS: u32 = 1;
+const CORE_NOTE_NAME: &[u8; 4] = b"CORE";
+
+/// Accepts raw bytes a piece at a time, so a core dump can be streamed to
+/// disk/network without ever materializing the whole guest image in memory.
+pub trait CoreWriter {
+    fn write_bytes(&mut self, buf: &[u8]) -> HyperResult;
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct Elf64Ehdr {
+    e_ident: [u8; EI_NIDENT],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+impl ByteCode for Elf64Ehdr {}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+impl ByteCode for Elf64Phdr {}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct Elf64Nhdr {
+    n_namesz: u32,
+    n_descsz: u32,
+    n_type: u32,
+}
+impl ByteCode for Elf64Nhdr {}
+
+/// Mirrors glibc's `struct elf_prstatus` (x86_64), trimmed to the fields gdb
+/// actually reads back out of a core file: the signal/pid/timing prefix is
+/// zeroed, `pr_reg` holds the live `user_regs_struct`-order register file.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct ElfPrStatus {
+    pr_info_signo: i32,
+    pr_info_code: i32,
+    pr_info_errno: i32,
+    pr_cursig: i16,
+    _pad0: i16,
+    pr_sigpend: u64,
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    pr_utime: [i64; 2],
+    pr_stime: [i64; 2],
+    pr_cutime: [i64; 2],
+    pr_cstime: [i64; 2],
+    /// r15, r14, r13, r12, rbp, rbx, r11, r10, r9, r8, rax, rcx, rdx, rsi,
+    /// rdi, orig_rax, rip, cs, eflags, rsp, ss, fs_base, gs_base, ds, es, fs,
+    /// gs -- the Linux `user_regs_struct` order.
+    pr_reg: [u64; 27],
+    pr_fpvalid: i32,
+    _pad1: i32,
+}
+impl ByteCode for ElfPrStatus {}
+
+fn elf_header(e_phoff: u64, e_phnum: u16) -> Elf64Ehdr {
+    let mut e_ident = [0u8; EI_NIDENT];
+    e_ident[0..4].copy_from_slice(b"\x7fELF");
+    e_ident[4] = ELFCLASS64;
+    e_ident[5] = ELFDATA2LSB;
+    e_ident[6] = EV_CURRENT;
+    e_ident[7] = ELFOSABI_NONE;
+    Elf64Ehdr {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_X86_64,
+        e_version: EV_CURRENT as u32,
+        e_entry: 0,
+        e_phoff,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: core::mem::size_of::<Elf64Ehdr>() as u16,
+        e_phentsize: core::mem::size_of::<Elf64Phdr>() as u16,
+        e_phnum,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    }
+}
+
+fn flags_to_pf(flags: MappingFlags) -> u32 {
+    let mut pf = 0;
+    if flags.contains(MappingFlags::READ) {
+        pf |= PF_R;
+    }
+    if flags.contains(MappingFlags::WRITE) {
+        pf |= PF_W;
+    }
+    if flags.contains(MappingFlags::EXECUTE) {
+        pf |= PF_X;
+    }
+    pf
+}
+
+fn prstatus_for<H: HyperCraftHal>(vcpu: &VCpu<H>) -> ElfPrStatus {
+    let regs = *vcpu.regs();
+    let segs = vcpu.segment_regs();
+    let mut status = ElfPrStatus {
+        pr_fpvalid: 0,
+        ..Default::default()
+    };
+    status.pr_reg = [
+        regs.r15,
+        regs.r14,
+        regs.r13,
+        regs.r12,
+        regs.rbp,
+        regs.rbx,
+        regs.r11,
+        regs.r10,
+        regs.r9,
+        regs.r8,
+        regs.rax,
+        regs.rcx,
+        regs.rdx,
+        regs.rsi,
+        regs.rdi,
+        regs.rax, // orig_rax: no syscall-restart semantics for a guest, so mirror rax
+        vcpu.rip(),
+        segs.cs as u64,
+        vcpu.rflags(),
+        vcpu.rsp(),
+        segs.ss as u64,
+        0, // fs_base
+        0, // gs_base
+        segs.ds as u64,
+        segs.es as u64,
+        segs.fs as u64,
+        segs.gs as u64,
+    ];
+    status
+}
+
+fn write_prstatus_note<W: CoreWriter>(status: &ElfPrStatus, w: &mut W) -> HyperResult {
+    let desc = status.as_bytes();
+    let nhdr = Elf64Nhdr {
+        n_namesz: CORE_NOTE_NAME.len() as u32,
+        n_descsz: desc.len() as u32,
+        n_type: NT_PRSTATUS,
+    };
+    w.write_bytes(nhdr.as_bytes())?;
+    w.write_bytes(CORE_NOTE_NAME)?;
+    write_padding(w, CORE_NOTE_NAME.len())?;
+    w.write_bytes(desc)?;
+    write_padding(w, desc.len())
+}
+
+fn write_padding<W: CoreWriter>(w: &mut W, unaligned_len: usize) -> HyperResult {
+    let pad = (4 - unaligned_len % 4) % 4;
+    w.write_bytes(&[0u8; 4][..pad])
+}
+
+/// Stream an ELF64 core dump of `gpm`'s mapped regions, with one
+/// `NT_PRSTATUS` note per entry in `vcpus`, to `writer`.
+///
+/// This is the `dump_core(writer)` entry point and the per-vCPU
+/// register-capture (`prstatus_for`) a "dump the whole VM" caller needs;
+/// it's a free function rather than a method on a VM-owning struct because
+/// nothing in this crate holds a VM's `GuestPhysMemorySet` and its `VCpu`s
+/// together in one place yet (`VMCfgEntry` keeps a clone of the former, but
+/// has no access to the latter). Whatever eventually owns both can wrap
+/// this in one line; until then, call it directly with whichever
+/// `GuestPhysMemorySet`/`VCpu` slice is in scope at the crash or
+/// dump-request site.
+pub fn write_coredump<H: HyperCraftHal, W: CoreWriter>(
+    gpm: &GuestPhysMemorySet,
+    vcpus: &[&VCpu<H>],
+    writer: &mut W,
+) -> HyperResult {
+    // Device/MMIO regions either aren't in the nested page table at all
+    // (`Mapper::Mmio`, so `gpm.translate` would just fail) or are a real
+    // passthrough BAR whose registers can have read side effects - neither
+    // belongs in a RAM core dump, so only regions without `DEVICE` make it
+    // into `regions`.
+    let regions: Vec<MapRegion> = gpm
+        .regions()
+        .into_iter()
+        .filter(|r| !r.flags.contains(MappingFlags::DEVICE))
+        .collect();
+
+    let notes: Vec<ElfPrStatus> = vcpus.iter().map(|v| prstatus_for(v)).collect();
+    let note_seg_size: usize = notes
+        .iter()
+        .map(|n| {
+            core::mem::size_of::<Elf64Nhdr>()
+                + align_up(CORE_NOTE_NAME.len(), 4)
+                + align_up(n.as_bytes().len(), 4)
+        })
+        .sum();
+
+    let phnum = 1 /* PT_NOTE */ + regions.len();
+    let ehdr = elf_header(core::mem::size_of::<Elf64Ehdr>() as u64, phnum as u16);
+
+    let phdr_table_size = phnum * core::mem::size_of::<Elf64Phdr>();
+    let mut data_offset =
+        core::mem::size_of::<Elf64Ehdr>() + phdr_table_size + note_seg_size;
+    data_offset = align_up(data_offset, PAGE_SIZE_4K);
+
+    writer.write_bytes(ehdr.as_bytes())?;
+
+    let note_phdr = Elf64Phdr {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: (core::mem::size_of::<Elf64Ehdr>() + phdr_table_size) as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: note_seg_size as u64,
+        p_memsz: 0,
+        p_align: 4,
+    };
+    writer.write_bytes(note_phdr.as_bytes())?;
+
+    let mut offset = data_offset;
+    for region in &regions {
+        let phdr = Elf64Phdr {
+            p_type: PT_LOAD,
+            p_flags: flags_to_pf(region.flags),
+            p_offset: offset as u64,
+            p_vaddr: region.start as u64,
+            p_paddr: region.start as u64,
+            p_filesz: region.size as u64,
+            p_memsz: region.size as u64,
+            p_align: PAGE_SIZE_4K as u64,
+        };
+        writer.write_bytes(phdr.as_bytes())?;
+        offset = align_up(offset + region.size, PAGE_SIZE_4K);
+    }
+
+    for status in &notes {
+        write_prstatus_note(status, writer)?;
+    }
+    write_padding_to(writer, data_offset - (core::mem::size_of::<Elf64Ehdr>() + phdr_table_size + note_seg_size))?;
+
+    for region in &regions {
+        let mut gpa: GuestPhysAddr = region.start;
+        let end = region.start + region.size;
+        while gpa < end {
+            let hpa = gpm.translate(gpa)?;
+            let host_ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *const u8;
+            let page = unsafe { core::slice::from_raw_parts(host_ptr, PAGE_SIZE_4K) };
+            writer.write_bytes(page)?;
+            gpa += PAGE_SIZE_4K;
+        }
+    }
+
+    Ok(())
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn write_padding_to<W: CoreWriter>(w: &mut W, len: usize) -> HyperResult {
+    if len == 0 {
+        return Ok(());
+    }
+    let zeros = alloc::vec![0u8; len];
+    w.write_bytes(&zeros)
+}
@@ -6,6 +6,7 @@ use pci::config::{BarAllocTrait, RegionType};
 pub use x86_64::*;
 
 mod dummy_pci;
+mod vfio_pci;
 mod virtio;
 
 use axalloc::global_allocator;
@@ -26,7 +27,10 @@ impl BarAllocTrait for BarAllocImpl {
                 Err(HyperError::InvalidBarAddress)
             }
         } else {
-            Err(HyperError::InvalidBarAddress)
+            // An I/O BAR never gets a host-backed page: `Bar`'s `PioOps`
+            // impl always dispatches through `ops` rather than
+            // `actual_address`, so there's nothing real to allocate here.
+            Ok(0)
         }
     }
 
@@ -36,7 +40,7 @@ impl BarAllocTrait for BarAllocImpl {
             global_allocator().dealloc_pages(vaddr as usize, pages);
             Ok(())
         } else {
-            Err(HyperError::InvalidBarAddress)
+            Ok(())
         }
     }
 }
@@ -0,0 +1,413 @@
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use page_table_entry::MappingFlags;
+use pci::config::{
+    BarAllocTrait, CapId, RegionType, BAR_0, DEVICE_ID, REG_SIZE, REVISION_ID, SUBSYSTEM_ID,
+    SUBSYSTEM_VENDOR_ID, SUB_CLASS_CODE, VENDOR_ID,
+};
+use pci::{
+    le_write_u16, msi::add_msi_capability, msi::parse_msi_control, msix::init_msix,
+    msix::MSIX_CAP_ID, AsAny, PciBus, PciConfig, PciDevBase, PciDevOps,
+};
+use hypercraft::{HyperError, HyperResult, RegionOps};
+use spin::Mutex;
+use x86::io;
+
+use crate::mm::{GuestMemoryRegion, GuestPhysMemorySet};
+
+/// Number of BARs probed at `realize()`, same as the PCI standard header.
+const VFIO_PCI_NUM_BARS: u8 = 6;
+/// Single MSI-X vector is enough to exercise the passthrough path; real
+/// devices needing more are a straightforward extension of this constant.
+const VFIO_PCI_MSIX_VECTORS: u32 = 1;
+/// BAR carrying the device's MSI-X table/PBA. Fixed at BAR 1, matching the
+/// convention `DummyPciDevice` already uses.
+const VFIO_PCI_MSIX_BAR_IDX: usize = 1;
+
+const PCI_CONFIG_ADDR_PORT: u16 = 0xcf8;
+const PCI_CONFIG_DATA_PORT: u16 = 0xcfc;
+
+fn host_cfg_address(bus: u8, devfn: u8, offset: u8) -> u32 {
+    0x8000_0000 | (bus as u32) << 16 | (devfn as u32) << 8 | (offset as u32 & 0xfc)
+}
+
+/// Read one dword from the *host*'s real PCI config space via CF8/CFC.
+/// Unlike `pci::host::PciHost`, which emulates these same ports for the
+/// guest, this talks to the real hardware and is only ever used at
+/// `VfioPciDevice::realize()` to snapshot the assigned function's config
+/// header before config-space emulation for the guest takes over.
+fn host_cfg_read32(bus: u8, devfn: u8, offset: u8) -> u32 {
+    unsafe {
+        io::outl(PCI_CONFIG_ADDR_PORT, host_cfg_address(bus, devfn, offset));
+        io::inl(PCI_CONFIG_DATA_PORT)
+    }
+}
+
+fn host_cfg_write32(bus: u8, devfn: u8, offset: u8, value: u32) {
+    unsafe {
+        io::outl(PCI_CONFIG_ADDR_PORT, host_cfg_address(bus, devfn, offset));
+        io::outl(PCI_CONFIG_DATA_PORT, value);
+    }
+}
+
+fn host_cfg_read16(bus: u8, devfn: u8, offset: u8) -> u16 {
+    (host_cfg_read32(bus, devfn, offset & !0x3) >> ((offset & 0x3) * 8)) as u16
+}
+
+fn host_cfg_read8(bus: u8, devfn: u8, offset: u8) -> u8 {
+    (host_cfg_read32(bus, devfn, offset & !0x3) >> ((offset & 0x3) * 8)) as u8
+}
+
+/// Walk the host's own capability list (PCI spec 6.7) looking for
+/// `want_id`, the same linked-list shape `PciConfig::add_pci_cap` builds
+/// for the guest-visible copy. Returns `None` both when the host has no
+/// capability list (Status bit 4 clear) and when `want_id` isn't in it.
+fn find_host_cap(bus: u8, devfn: u8, want_id: u8) -> Option<u8> {
+    const STATUS: u8 = 0x06;
+    const STATUS_CAP_LIST: u16 = 0x0010;
+    const CAP_LIST_PTR: u8 = 0x34;
+    const NEXT_CAP_OFFSET: u8 = 0x01;
+
+    if host_cfg_read16(bus, devfn, STATUS) & STATUS_CAP_LIST == 0 {
+        return None;
+    }
+    let mut offset = host_cfg_read8(bus, devfn, CAP_LIST_PTR);
+    // A well-formed list is always short; this just guards against a
+    // misbehaving/rogue host device looping the pointer chain forever.
+    for _ in 0..48 {
+        if offset == 0 {
+            return None;
+        }
+        if host_cfg_read8(bus, devfn, offset) == want_id {
+            return Some(offset);
+        }
+        offset = host_cfg_read8(bus, devfn, offset + NEXT_CAP_OFFSET);
+    }
+    None
+}
+
+/// Probe a host BAR's size with the standard write-all-ones/read-back
+/// dance, then restore the BAR's original value. Returns `None` if the
+/// BAR is unimplemented (reads back as all zero).
+fn probe_host_bar(bus: u8, devfn: u8, bar_id: usize) -> Option<(u64, RegionType, bool)> {
+    let offset = (BAR_0 as usize + bar_id * REG_SIZE) as u8;
+    let orig = host_cfg_read32(bus, devfn, offset);
+    host_cfg_write32(bus, devfn, offset, 0xffff_ffff);
+    let probed = host_cfg_read32(bus, devfn, offset);
+    host_cfg_write32(bus, devfn, offset, orig);
+
+    if orig & 0x1 != 0 {
+        // I/O BAR: bits 31:2 are the size mask, bits 1:0 are the space
+        // indicator/reserved bit. x86 port space tops out at 16 bits.
+        let size_mask = !(probed & 0xffff_fffc) & 0xffff;
+        if size_mask == 0 {
+            return None;
+        }
+        return Some(((size_mask as u64) + 1, RegionType::Io, false));
+    }
+    let is_64bit = (orig >> 1) & 0x3 == 0x2;
+    let prefetchable = orig & 0x8 != 0;
+    let size_mask = !(probed & 0xffff_fff0);
+    if size_mask == 0 {
+        return None;
+    }
+    let region_type = if is_64bit {
+        RegionType::Mem64Bit
+    } else {
+        RegionType::Mem32Bit
+    };
+    Some(((size_mask as u64) + 1, region_type, prefetchable))
+}
+
+/// A host BAR discovered at `realize()`: its real host-physical MMIO base
+/// (or, for `RegionType::Io`, its real host port base) and size. MMIO bars
+/// use this so `write_config` can re-map the guest's nested page table
+/// whenever the guest relocates the BAR; I/O bars use it to know which
+/// host port range their `RegionOps` should forward accesses to, which
+/// never needs remapping since the forwarding closure only ever sees an
+/// offset from the BAR base, not the guest's port number.
+#[derive(Clone, Copy)]
+struct PassthroughBar {
+    region_type: RegionType,
+    host_phys_addr: u64,
+    size: u64,
+}
+
+/// VFIO-style PCI passthrough device: a single host PCI function exposed
+/// directly to a guest VM, analogous to crosvm/cloud-hypervisor's
+/// `vfio_pci`. Config-space reads/writes go through the usual `PciConfig`
+/// emulation (so BAR/command/MSI-X accesses behave like any other
+/// emulated device and stay trapped), but MMIO accesses to the device
+/// itself are *not* trapped: each BAR range is mapped straight through to
+/// the host device's real MMIO in the guest's nested page table, and the
+/// mapping is redone whenever the guest reprograms a BAR. An I/O BAR can't
+/// get the same treatment (port space has no EPT-equivalent to map
+/// through), so it stays trapped and its `RegionOps` just forwards each
+/// access to the matching offset in the real host port range via
+/// `find_pio`. MSI-X vector delivery reuses the generic `pci::msix`
+/// shadow-table plumbing via `init_msix`/`get_msi_irq_manager()` rather
+/// than being reimplemented here.
+///
+/// The guest never gets a wider window than what `realize()` assigned:
+/// `host_bars` fixes each BAR's real host-physical base and size once,
+/// at discovery time, so a guest BAR write (gated by `PciConfig`'s usual
+/// write mask, same as any emulated device) can only move *where* that
+/// fixed host range is visible in guest-physical space via
+/// `remap_bar`, never *which* host range backs it. Bus-mastering and
+/// memory/IO space decode in the Command register are left guest-writable
+/// on purpose, matching what a real driver expects to toggle on physical
+/// hardware; isolating this function's DMA from the rest of the host is
+/// the IOMMU's job, not config-space emulation's.
+#[derive(Clone)]
+pub struct VfioPciDevice<B: BarAllocTrait> {
+    base: PciDevBase<B>,
+    dev_id: Arc<AtomicU16>,
+    /// (bus, devfn) of the real host function this device passes through.
+    host_bus: u8,
+    host_devfn: u8,
+    /// Host BAR geometry discovered at `realize()`, indexed like `base.config.bars`.
+    host_bars: Vec<Option<PassthroughBar>>,
+    /// The VM's nested page table, used to map/remap each BAR's guest
+    /// range onto the matching host MMIO range.
+    memory_set: Arc<Mutex<GuestPhysMemorySet>>,
+}
+
+impl<B: BarAllocTrait + 'static> VfioPciDevice<B> {
+    pub fn new(
+        name: String,
+        devfn: u8,
+        parent_bus: Weak<Mutex<PciBus<B>>>,
+        host_bus: u8,
+        host_devfn: u8,
+        memory_set: Arc<Mutex<GuestPhysMemorySet>>,
+    ) -> Self {
+        Self {
+            base: PciDevBase {
+                id: name,
+                config: PciConfig::<B>::new(0x1000, VFIO_PCI_NUM_BARS),
+                devfn,
+                parent_bus,
+            },
+            dev_id: Arc::new(AtomicU16::new(0)),
+            host_bus,
+            host_devfn,
+            host_bars: vec![None; VFIO_PCI_NUM_BARS as usize],
+            memory_set,
+        }
+    }
+
+    /// Map `bar_id`'s current guest-visible address onto the real host
+    /// MMIO it was assigned, tearing down any stale mapping at its
+    /// previous address first.
+    fn remap_bar(&mut self, bar_id: usize, old_gpa: u64) {
+        let Some(host_bar) = self.host_bars[bar_id] else {
+            return;
+        };
+        if host_bar.region_type == RegionType::Io {
+            return;
+        }
+        let new_gpa = self.base.config.get_bar_address(bar_id);
+        let mut memory_set = self.memory_set.lock();
+        if old_gpa != pci::config::BAR_SPACE_UNMAPPED && old_gpa != new_gpa {
+            if let Err(e) = memory_set.unmap_region(old_gpa as usize) {
+                error!(
+                    "vfio-pci {}: failed to unmap stale bar {} at {:#x}: {:?}",
+                    self.base.id, bar_id, old_gpa, e
+                );
+            }
+        }
+        if new_gpa == pci::config::BAR_SPACE_UNMAPPED {
+            return;
+        }
+        let region = GuestMemoryRegion {
+            gpa: new_gpa as usize,
+            hpa: host_bar.host_phys_addr as usize,
+            size: host_bar.size as usize,
+            flags: MappingFlags::READ | MappingFlags::WRITE | MappingFlags::DEVICE,
+            file_fd: None,
+            file_offset: 0,
+        };
+        if let Err(e) = memory_set.map_region(region.into()) {
+            error!(
+                "vfio-pci {}: failed to map bar {} at {:#x} -> {:#x}: {:?}",
+                self.base.id, bar_id, new_gpa, host_bar.host_phys_addr, e
+            );
+        }
+    }
+}
+
+impl<B: BarAllocTrait + 'static> AsAny for VfioPciDevice<B> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl<B: BarAllocTrait + 'static> PciDevOps<B> for VfioPciDevice<B> {
+    fn name(&self) -> String {
+        self.base.id.clone()
+    }
+
+    fn pci_base(&self) -> &PciDevBase<B> {
+        &self.base
+    }
+
+    fn pci_base_mut(&mut self) -> &mut PciDevBase<B> {
+        &mut self.base
+    }
+
+    fn realize(mut self) -> HyperResult<()> {
+        self.init_write_mask(false)?;
+        self.init_write_clear_mask(false)?;
+
+        let (bus, devfn) = (self.host_bus, self.host_devfn);
+        let vendor_device = host_cfg_read32(bus, devfn, VENDOR_ID);
+        le_write_u16(&mut self.base.config.config, VENDOR_ID as usize, vendor_device as u16)?;
+        le_write_u16(
+            &mut self.base.config.config,
+            DEVICE_ID as usize,
+            (vendor_device >> 16) as u16,
+        )?;
+        let class_rev = host_cfg_read32(bus, devfn, REVISION_ID as u8);
+        self.base.config.config[REVISION_ID] = class_rev as u8;
+        le_write_u16(
+            &mut self.base.config.config,
+            SUB_CLASS_CODE as usize,
+            (class_rev >> 16) as u16,
+        )?;
+        let subsys = host_cfg_read32(bus, devfn, SUBSYSTEM_VENDOR_ID as u8);
+        le_write_u16(&mut self.base.config.config, SUBSYSTEM_VENDOR_ID, subsys as u16)?;
+        le_write_u16(&mut self.base.config.config, SUBSYSTEM_ID, (subsys >> 16) as u16)?;
+
+        let mut id = 0usize;
+        while id < VFIO_PCI_NUM_BARS as usize {
+            let Some((size, region_type, prefetchable)) = probe_host_bar(bus, devfn, id) else {
+                id += 1;
+                continue;
+            };
+            let bar_reg = host_cfg_read32(bus, devfn, (BAR_0 as usize + id * REG_SIZE) as u8);
+            let host_phys_addr = if region_type == RegionType::Io {
+                (bar_reg & !0x3) as u64
+            } else {
+                (bar_reg & !0xf) as u64
+            };
+            self.host_bars[id] = Some(PassthroughBar {
+                region_type,
+                host_phys_addr,
+                size,
+            });
+            let ops: Option<RegionOps> = if region_type == RegionType::Io {
+                // Port I/O always traps (there's no EPT-equivalent for port
+                // space), so unlike MMIO this can't be mapped straight
+                // through; forward each trapped access to the same offset
+                // within the real host port range instead.
+                Some(RegionOps {
+                    read: Arc::new(move |offset: u64, access_size: u8| -> HyperResult<u64> {
+                        let port = (host_phys_addr + offset) as u16;
+                        Ok(unsafe {
+                            match access_size {
+                                1 => io::inb(port) as u64,
+                                2 => io::inw(port) as u64,
+                                4 => io::inl(port) as u64,
+                                _ => return Err(HyperError::InValidPioRead),
+                            }
+                        })
+                    }),
+                    write: Arc::new(
+                        move |offset: u64, access_size: u8, data: &[u8]| -> HyperResult {
+                            let port = (host_phys_addr + offset) as u16;
+                            unsafe {
+                                match access_size {
+                                    1 => io::outb(port, data[0]),
+                                    2 => io::outw(port, u16::from_le_bytes(data[0..2].try_into().unwrap())),
+                                    4 => io::outl(port, u32::from_le_bytes(data[0..4].try_into().unwrap())),
+                                    _ => return Err(HyperError::InValidPioWrite),
+                                }
+                            }
+                            Ok(())
+                        },
+                    ),
+                })
+            } else {
+                // No `RegionOps` for MMIO: unlike an emulated device, real
+                // MMIO traffic to a passthrough BAR must reach the host
+                // device directly through the nested page table, not a trap
+                // handler.
+                None
+            };
+            self.base
+                .config
+                .register_bar(id, ops, region_type, prefetchable, size)?;
+            // A 64-bit BAR's high dword lives in the next slot; that slot
+            // isn't a BAR of its own, so skip probing it separately.
+            id += if region_type == RegionType::Mem64Bit { 2 } else { 1 };
+        }
+
+        // Mirror whichever interrupt capability the host function actually
+        // implements: real hardware generally offers MSI-X, plain MSI, or
+        // neither (INTx-only, left to `set_intx`), never a capability the
+        // guest driver doesn't already expect.
+        if self.host_bars[VFIO_PCI_MSIX_BAR_IDX].is_some()
+            && find_host_cap(bus, devfn, MSIX_CAP_ID).is_some()
+        {
+            init_msix(
+                &mut self.base,
+                VFIO_PCI_MSIX_BAR_IDX,
+                VFIO_PCI_MSIX_VECTORS,
+                self.dev_id.clone(),
+                None,
+            )?;
+        } else if let Some(msi_cap) = find_host_cap(bus, devfn, CapId::Msi as u8) {
+            // Message Control sits at offset 2 in every MSI capability
+            // layout (PCI spec 6.8.1), regardless of 64-bit/per-vector-mask
+            // capability - that's exactly what decides the rest of the
+            // layout `parse_msi_control` hands back.
+            let control = host_cfg_read16(bus, devfn, msi_cap + 2);
+            let (vectors, is_64bit, mask_per_vector) = parse_msi_control(control);
+            add_msi_capability(&mut self.base, vectors, is_64bit, mask_per_vector, self.dev_id.clone())?;
+        }
+
+        let devfn = self.base.devfn;
+        let dev = Arc::new(Mutex::new(self));
+
+        let pci_bus = dev.lock().base.parent_bus.upgrade().unwrap();
+        let mut locked_pci_bus = pci_bus.lock();
+        if locked_pci_bus.devices.get(&devfn).is_none() {
+            locked_pci_bus.devices.insert(devfn, dev.clone());
+        } else {
+            error!(
+                "Devfn {:?} has been used by {:?}",
+                &devfn,
+                locked_pci_bus.devices.get(&devfn).unwrap().lock().name()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn write_config(&mut self, offset: usize, data: &[u8]) {
+        let remaps = self
+            .base
+            .config
+            .write_bars(offset, data, self.dev_id.load(Ordering::Relaxed));
+
+        // `PciConfig::write_bars` already relocated any BAR's guest-visible
+        // *allocator* address. Real passthrough MMIO bypasses emulation
+        // entirely though, so whenever a BAR actually moved we must also
+        // redo its nested-page-table mapping so the guest's new GPA range
+        // keeps resolving to the same real host MMIO.
+        for remap in remaps {
+            if self.host_bars[remap.id].is_some() {
+                self.remap_bar(remap.id, remap.old_base);
+            }
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use crate::device::virtio::{
+    Element, VirtioBase, VirtioDevice, VirtioInterrupt, VirtioInterruptType, VIRTIO_TYPE_BLOCK,
+};
+use alloc::format;
+use alloc::sync::Arc;
+use core::any::Any;
+use core::mem::size_of;
+use hypercraft::{HyperError, HyperResult as Result, VirtioError};
+use pci::{le_read_u32, le_write_u32, AsAny};
+use spin::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Read sectors from the device.
+const VIRTIO_BLK_T_IN: u32 = 0;
+/// Write sectors to the device.
+const VIRTIO_BLK_T_OUT: u32 = 1;
+/// Flush any buffered writes out to the backing image.
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+
+/// Request completed successfully.
+const VIRTIO_BLK_S_OK: u8 = 0;
+/// Host I/O against the backing image failed.
+const VIRTIO_BLK_S_IOERR: u8 = 1;
+/// Request type the device doesn't implement.
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+/// Every virtio-blk sector is fixed at 512 bytes, regardless of the backing
+/// image's own block size.
+const SECTOR_SIZE: u64 = 512;
+/// A single `requestq`, i.e. no `VIRTIO_BLK_F_MQ`.
+const QUEUE_NUM: usize = 1;
+const QUEUE_SIZE_MAX: u16 = 256;
+
+/// Header every virtio-blk request descriptor chain starts with (Virtio
+/// 1.1 spec, struct virtio_blk_req).
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct VirtioBlkReqHeader {
+    req_type: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// Maps a guest-physical address backing a descriptor to the host virtual
+/// address `VirtioBlk` can read/write directly, e.g.
+/// `VMCfgEntry::translate_guest_addr`. Injected rather than holding a
+/// `GuestPhysMemorySet` directly, since `VirtioDevice`s are constructed
+/// before a `VCpu`/`VM` exists to hand one over (see `NimbosVmDevices::new`).
+pub type GuestAddrTranslator = Arc<dyn Fn(u64) -> Option<u64> + Send + Sync>;
+
+/// A virtio-blk device backed by a host image file. `process_queue` drains
+/// `requestq`, resolves each descriptor chain's buffers through
+/// `translate`, and serves `VIRTIO_BLK_T_IN`/`_OUT`/`_FLUSH` against `image`
+/// directly - there's no AIO/thread-offload here, requests complete
+/// synchronously before the notify that drove `process_queue` returns.
+pub struct VirtioBlk {
+    base: VirtioBase,
+    image: Mutex<File>,
+    capacity_sectors: u64,
+    translate: GuestAddrTranslator,
+    interrupt_cb: Option<Arc<VirtioInterrupt>>,
+}
+
+impl VirtioBlk {
+    /// Open `image_path` as the backing store and size the device's
+    /// advertised capacity off its length, rounded down to a whole sector.
+    pub fn new(image_path: &str, translate: GuestAddrTranslator) -> Result<Self> {
+        let image = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(image_path)
+            .map_err(|source| HyperError::VirtioError(VirtioError::Io { source }))?;
+        let len = image
+            .metadata()
+            .map_err(|source| HyperError::VirtioError(VirtioError::Io { source }))?
+            .len();
+
+        Ok(Self {
+            base: VirtioBase::new(VIRTIO_TYPE_BLOCK, QUEUE_NUM, QUEUE_SIZE_MAX),
+            image: Mutex::new(image),
+            capacity_sectors: len / SECTOR_SIZE,
+            translate,
+            interrupt_cb: None,
+        })
+    }
+
+    fn guest_slice(&self, addr: u64, len: usize) -> Result<&'static mut [u8]> {
+        let hva = (self.translate)(addr).ok_or_else(|| {
+            HyperError::VirtioError(VirtioError::AddressOverflow(
+                "virtio-blk descriptor",
+                addr,
+                len as u64,
+            ))
+        })?;
+        // SAFETY: `translate` resolves through the VM's guest physical
+        // memory set, which keeps every mapped region alive for the life of
+        // the VM; the descriptor's `len` is the driver's own claim about how
+        // much of that mapping it owns.
+        Ok(unsafe { core::slice::from_raw_parts_mut(hva as *mut u8, len) })
+    }
+
+    /// Drain and service every request chain currently posted on `requestq`,
+    /// then raise one completion interrupt if any were processed.
+    pub fn process_queue(&mut self, queue_index: usize) -> Result<()> {
+        let Some(interrupt_cb) = self.interrupt_cb.clone() else {
+            return Ok(());
+        };
+        let queue = self
+            .base
+            .queues
+            .get(queue_index)
+            .cloned()
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::QueueIndex(queue_index as u16, 0)))?;
+        let features = self.base.driver_features;
+
+        let mut any_completed = false;
+        loop {
+            let mut locked_queue = queue.lock();
+            if !locked_queue.is_valid() {
+                break;
+            }
+            let element = match locked_queue.vring.pop_avail(features) {
+                Ok(element) if element.desc_num != 0 => element,
+                _ => break,
+            };
+            drop(locked_queue);
+
+            let len = self.handle_request(&element)?;
+
+            let mut locked_queue = queue.lock();
+            locked_queue.vring.add_used(element.index, len)?;
+            any_completed = true;
+        }
+
+        if any_completed {
+            interrupt_cb(&VirtioInterruptType::Vring, Some(&queue.lock()), false)?;
+        }
+        Ok(())
+    }
+
+    /// Service one descriptor chain: the request header is the sole
+    /// read-only descriptor, the data buffer (if any) follows it, and the
+    /// one-byte status footer is always the chain's last write-only
+    /// descriptor.
+    fn handle_request(&self, element: &Element) -> Result<u32> {
+        let header_desc = element
+            .out_iovec
+            .first()
+            .ok_or(HyperError::VirtioError(VirtioError::ElementEmpty))?;
+        let header_buf = self.guest_slice(header_desc.addr, size_of::<VirtioBlkReqHeader>())?;
+        let header = VirtioBlkReqHeader {
+            req_type: u32::from_le_bytes(header_buf[0..4].try_into().unwrap()),
+            reserved: u32::from_le_bytes(header_buf[4..8].try_into().unwrap()),
+            sector: u64::from_le_bytes(header_buf[8..16].try_into().unwrap()),
+        };
+
+        let status_desc = element
+            .in_iovec
+            .last()
+            .ok_or(HyperError::VirtioError(VirtioError::ElementEmpty))?;
+
+        let mut status = VIRTIO_BLK_S_OK;
+        let mut transferred = 0u32;
+        match header.req_type {
+            VIRTIO_BLK_T_IN => {
+                let mut offset = header.sector * SECTOR_SIZE;
+                let mut image = self.image.lock();
+                for data_desc in &element.in_iovec[..element.in_iovec.len() - 1] {
+                    let buf = self.guest_slice(data_desc.addr, data_desc.len as usize)?;
+                    if image
+                        .seek(SeekFrom::Start(offset))
+                        .and_then(|_| image.read_exact(buf))
+                        .is_err()
+                    {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
+                    }
+                    offset += data_desc.len as u64;
+                    transferred += data_desc.len;
+                }
+            }
+            VIRTIO_BLK_T_OUT => {
+                let mut offset = header.sector * SECTOR_SIZE;
+                let mut image = self.image.lock();
+                for data_desc in &element.out_iovec[1..] {
+                    let buf = self.guest_slice(data_desc.addr, data_desc.len as usize)?;
+                    if image
+                        .seek(SeekFrom::Start(offset))
+                        .and_then(|_| image.write_all(buf))
+                        .is_err()
+                    {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
+                    }
+                    offset += data_desc.len as u64;
+                }
+            }
+            VIRTIO_BLK_T_FLUSH => {
+                if self.image.lock().flush().is_err() {
+                    status = VIRTIO_BLK_S_IOERR;
+                }
+            }
+            _ => status = VIRTIO_BLK_S_UNSUPP,
+        }
+
+        self.guest_slice(status_desc.addr, 1)?[0] = status;
+        Ok(transferred + 1)
+    }
+}
+
+impl AsAny for VirtioBlk {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl VirtioDevice for VirtioBlk {
+    fn virtio_base(&self) -> &VirtioBase {
+        &self.base
+    }
+
+    fn virtio_base_mut(&mut self) -> &mut VirtioBase {
+        &mut self.base
+    }
+
+    fn realize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn unrealize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_type(&self) -> u32 {
+        self.virtio_base().device_type
+    }
+
+    fn queue_num(&self) -> usize {
+        self.virtio_base().queue_num
+    }
+
+    fn queue_size_max(&self) -> u16 {
+        self.virtio_base().queue_size_max
+    }
+
+    fn init_config_features(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_features(&self, features_select: u32) -> u32 {
+        let buf = self.virtio_base().device_features.to_le_bytes();
+        le_read_u32(&buf[..], features_select as usize).unwrap_or(0)
+    }
+
+    fn set_driver_features(&mut self, page: u32, value: u32) {
+        let mut v = value;
+        let unsupported_features = value & !self.device_features(page);
+        if unsupported_features != 0 {
+            warn!("virtio-blk: driver acknowledged unsupported feature bits");
+            v &= !unsupported_features;
+        }
+
+        let features = if page == 0 {
+            (self.driver_features(1) as u64) << 32 | (v as u64)
+        } else {
+            (v as u64) << 32 | (self.driver_features(0) as u64)
+        };
+        self.virtio_base_mut().driver_features = features;
+    }
+
+    fn driver_features(&self, features_select: u32) -> u32 {
+        let buf = self.virtio_base().driver_features.to_le_bytes();
+        le_read_u32(&buf[..], features_select as usize).unwrap_or(0)
+    }
+
+    fn hfeatures_sel(&self) -> u32 {
+        self.virtio_base().hfeatures_sel
+    }
+
+    fn set_hfeatures_sel(&mut self, val: u32) {
+        self.virtio_base_mut().hfeatures_sel = val;
+    }
+
+    fn gfeatures_sel(&self) -> u32 {
+        self.virtio_base().gfeatures_sel
+    }
+
+    fn set_gfeatures_sel(&mut self, val: u32) {
+        self.virtio_base_mut().gfeatures_sel = val;
+    }
+
+    fn check_device_status(&self, set: u32, clr: u32) -> bool {
+        self.device_status() & (set | clr) == set
+    }
+
+    fn device_status(&self) -> u32 {
+        self.virtio_base()
+            .device_status
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_device_status(&mut self, val: u32) {
+        self.virtio_base_mut()
+            .device_status
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn device_activated(&self) -> bool {
+        self.virtio_base()
+            .device_activated
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_device_activated(&mut self, val: bool) {
+        self.virtio_base_mut()
+            .device_activated
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn config_generation(&self) -> u8 {
+        self.virtio_base()
+            .config_generation
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_config_generation(&mut self, val: u8) {
+        self.virtio_base_mut()
+            .config_generation
+            .store(val, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn config_vector(&self) -> u16 {
+        self.virtio_base()
+            .config_vector
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_config_vector(&mut self, val: u16) {
+        self.virtio_base_mut()
+            .config_vector
+            .store(val, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn queue_type(&self) -> u16 {
+        self.virtio_base().queue_type
+    }
+
+    fn set_queue_type(&mut self, val: u16) {
+        self.virtio_base_mut().queue_type = val;
+    }
+
+    fn queue_select(&self) -> u16 {
+        self.virtio_base().queue_select
+    }
+
+    fn set_queue_select(&mut self, val: u16) {
+        self.virtio_base_mut().queue_select = val;
+    }
+
+    fn queue_config(&self) -> Result<&crate::device::virtio::QueueConfig> {
+        let queues_config = &self.virtio_base().queues_config;
+        let queue_select = self.virtio_base().queue_select;
+        queues_config
+            .get(queue_select as usize)
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::Other(format!("queue_select overflows"))))
+    }
+
+    fn queue_config_mut(&mut self, need_check: bool) -> Result<&mut crate::device::virtio::QueueConfig> {
+        if need_check
+            && !self.check_device_status(
+                crate::device::virtio::CONFIG_STATUS_FEATURES_OK,
+                crate::device::virtio::CONFIG_STATUS_DRIVER_OK | crate::device::virtio::CONFIG_STATUS_FAILED,
+            )
+        {
+            return Err(HyperError::VirtioError(VirtioError::DevStatErr(self.device_status())));
+        }
+
+        let queue_select = self.virtio_base().queue_select;
+        let queues_config = &mut self.virtio_base_mut().queues_config;
+        queues_config
+            .get_mut(queue_select as usize)
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::Other(format!("queue_select overflows"))))
+    }
+
+    fn interrupt_status(&self) -> u32 {
+        self.virtio_base()
+            .interrupt_status
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_interrupt_status(&mut self, val: u32) {
+        self.virtio_base_mut()
+            .interrupt_status
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// `struct virtio_blk_config` - only `capacity` is populated, since no
+    /// optional feature bit (`VIRTIO_BLK_F_*`) gating the fields after it is
+    /// negotiated.
+    fn read_config(&self, offset: u64, data: &mut [u8]) -> Result<()> {
+        let config = self.capacity_sectors.to_le_bytes();
+        let offset = offset as usize;
+        if offset >= config.len() {
+            return Ok(());
+        }
+        let end = core::cmp::min(offset + data.len(), config.len());
+        data[..end - offset].copy_from_slice(&config[offset..end]);
+        Ok(())
+    }
+
+    fn write_config(&mut self, _offset: u64, _data: &[u8]) -> Result<()> {
+        // `virtio_blk_config` is entirely read-only for the feature set this
+        // device advertises.
+        Ok(())
+    }
+
+    fn activate(&mut self, interrupt_cb: Arc<VirtioInterrupt>) -> Result<()> {
+        self.interrupt_cb = Some(interrupt_cb);
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        self.interrupt_cb = None;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.interrupt_cb = None;
+        Ok(())
+    }
+
+    fn update_config(&mut self) -> Result<()> {
+        error!("Unsupported to update configuration");
+        Err(HyperError::BadState)
+    }
+
+    fn has_control_queue(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,472 @@
+use crate::device::virtio::{
+    Element, VirtioBase, VirtioDevice, VirtioInterrupt, VirtioInterruptType, VIRTIO_TYPE_NET,
+};
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use hypercraft::{HyperError, HyperResult as Result, VirtioError};
+use pci::{le_read_u32, AsAny};
+
+/// `requestq` index for guest-to-host packets.
+const TX_QUEUE: usize = 0;
+/// `requestq` index for host-to-guest packets.
+const RX_QUEUE: usize = 1;
+const QUEUE_NUM: usize = 2;
+const QUEUE_SIZE_MAX: u16 = 256;
+
+/// `struct virtio_net_config` (Virtio 1.1 spec Sec. 5.1.4) - only `mac` is
+/// populated; `status`/`max_virtqueue_pairs`/`mtu` are gated behind feature
+/// bits this device doesn't advertise.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct VirtioNetConfig {
+    mac: [u8; 6],
+}
+
+/// Header every virtio-net packet descriptor chain starts with (Virtio 1.1
+/// spec, struct virtio_net_hdr), with none of the optional offload fields
+/// (`VIRTIO_NET_F_*`) negotiated so every field but `num_buffers` stays zero.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct VirtioNetHdr {
+    flags: u8,
+    gso_type: u8,
+    hdr_len: u16,
+    gso_size: u16,
+    csum_start: u16,
+    csum_offset: u16,
+    num_buffers: u16,
+}
+
+/// Host-side bridge a [`VirtioNet`] device pushes guest TX frames into and
+/// pulls guest RX frames from. Implemented per concrete backend (e.g. a TAP
+/// device); `VirtioNet` itself only drives the two virtqueues and doesn't
+/// care what's on the other side, the same separation `GuestAddrTranslator`
+/// draws between `VirtioBlk` and its backing image.
+pub trait NetBackend: Send + Sync {
+    /// Hand a single guest-transmitted frame (header already stripped) off
+    /// to the host side.
+    fn send(&self, frame: &[u8]) -> Result<()>;
+    /// Pull the next host-received frame ready for the guest, if any.
+    fn try_recv(&self) -> Option<Vec<u8>>;
+}
+
+/// A [`NetBackend`] with no host-side link at all: sent frames are dropped
+/// and there's never anything to receive. This tree has no tap/netdev
+/// abstraction to bridge into yet (no `TapDevice`/`NetDev` type anywhere in
+/// the repo), so this is what `NimbosVmDevices::new` wires up today - the
+/// guest sees a live virtio-net device and can complete feature negotiation
+/// against it, it just has no connectivity until a real backend replaces
+/// this one, the same honest-stub role [`super::dummy::DummyVirtioDevice`]
+/// plays for a whole device rather than just its backend half.
+#[derive(Default)]
+pub struct NullNetBackend;
+
+impl NetBackend for NullNetBackend {
+    fn send(&self, _frame: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// A virtio-net device bridging a guest's `transmitq`/`receiveq` to a host
+/// [`NetBackend`]. `process_queue` drains `transmitq` and hands each frame to
+/// `backend.send`; [`Self::poll_rx`] is the other direction, pulled by a
+/// caller on its own schedule (there's no host-side interrupt to drive it
+/// from, unlike a guest queue notify) and drained into `receiveq` as
+/// descriptors become available.
+pub struct VirtioNet {
+    base: VirtioBase,
+    mac: [u8; 6],
+    backend: Arc<dyn NetBackend>,
+    interrupt_cb: Option<Arc<VirtioInterrupt>>,
+}
+
+impl VirtioNet {
+    pub fn new(mac: [u8; 6], backend: Arc<dyn NetBackend>) -> Self {
+        Self {
+            base: VirtioBase::new(VIRTIO_TYPE_NET, QUEUE_NUM, QUEUE_SIZE_MAX),
+            mac,
+            backend,
+            interrupt_cb: None,
+        }
+    }
+
+    /// Drain and send every frame currently posted on `transmitq`, then
+    /// raise one completion interrupt if any were processed.
+    pub fn process_queue(&mut self, queue_index: usize) -> Result<()> {
+        if queue_index != TX_QUEUE {
+            return Ok(());
+        }
+        let Some(interrupt_cb) = self.interrupt_cb.clone() else {
+            return Ok(());
+        };
+        let queue = self
+            .base
+            .queues
+            .get(TX_QUEUE)
+            .cloned()
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::QueueIndex(TX_QUEUE as u16, 0)))?;
+        let features = self.base.driver_features;
+
+        let mut any_completed = false;
+        loop {
+            let mut locked_queue = queue.lock();
+            if !locked_queue.is_valid() {
+                break;
+            }
+            let element = match locked_queue.vring.pop_avail(features) {
+                Ok(element) if element.desc_num != 0 => element,
+                _ => break,
+            };
+            drop(locked_queue);
+
+            self.send_frame(&element)?;
+
+            let mut locked_queue = queue.lock();
+            locked_queue.vring.add_used(element.index, 0)?;
+            any_completed = true;
+        }
+
+        if any_completed {
+            interrupt_cb(&VirtioInterruptType::Vring, Some(&queue.lock()), false)?;
+        }
+        Ok(())
+    }
+
+    /// Hand every data descriptor in `element` (past the `virtio_net_hdr`
+    /// that always leads a `transmitq` chain) to the backend as one frame.
+    fn send_frame(&self, element: &Element) -> Result<()> {
+        let mut frame = Vec::new();
+        for (i, desc) in element.out_iovec.iter().enumerate() {
+            let buf = unsafe { core::slice::from_raw_parts(desc.addr as *const u8, desc.len as usize) };
+            if i == 0 {
+                // Leading descriptor is the fixed-size `virtio_net_hdr`; skip
+                // it rather than assuming it's exactly `size_of::<VirtioNetHdr>()`
+                // long, since a negotiated `VIRTIO_NET_F_MRG_RXBUF` would grow it.
+                continue;
+            }
+            frame.extend_from_slice(buf);
+        }
+        self.backend.send(&frame)
+    }
+
+    /// Pull frames the backend has queued for the guest and post each into
+    /// `receiveq`, prefixing the zeroed `virtio_net_hdr` this device always
+    /// emits. Raises a completion interrupt once if anything was delivered.
+    /// Unlike [`Self::process_queue`], there's no guest notify to drive this
+    /// - a caller (e.g. a per-VM device poll tick) must call it directly.
+    pub fn poll_rx(&mut self) -> Result<()> {
+        let Some(interrupt_cb) = self.interrupt_cb.clone() else {
+            return Ok(());
+        };
+        let queue = self
+            .base
+            .queues
+            .get(RX_QUEUE)
+            .cloned()
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::QueueIndex(RX_QUEUE as u16, 0)))?;
+        let features = self.base.driver_features;
+
+        let mut any_completed = false;
+        while let Some(frame) = self.backend.try_recv() {
+            let mut locked_queue = queue.lock();
+            if !locked_queue.is_valid() {
+                break;
+            }
+            let element = match locked_queue.vring.pop_avail(features) {
+                Ok(element) if element.desc_num != 0 => element,
+                _ => break,
+            };
+            drop(locked_queue);
+
+            let written = self.fill_frame(&element, &frame)?;
+
+            let mut locked_queue = queue.lock();
+            locked_queue.vring.add_used(element.index, written)?;
+            any_completed = true;
+        }
+
+        if any_completed {
+            interrupt_cb(&VirtioInterruptType::Vring, Some(&queue.lock()), false)?;
+        }
+        Ok(())
+    }
+
+    /// Write `frame` into `element`'s writable descriptors, leading with the
+    /// zeroed `virtio_net_hdr` every `receiveq` chain starts with.
+    fn fill_frame(&self, element: &Element, frame: &[u8]) -> Result<u32> {
+        let hdr_desc = element
+            .in_iovec
+            .first()
+            .ok_or(HyperError::VirtioError(VirtioError::ElementEmpty))?;
+        let hdr = VirtioNetHdr {
+            num_buffers: 1,
+            ..Default::default()
+        };
+        let hdr_buf = unsafe {
+            core::slice::from_raw_parts_mut(hdr_desc.addr as *mut u8, core::mem::size_of::<VirtioNetHdr>())
+        };
+        hdr_buf[0] = hdr.flags;
+        hdr_buf[1] = hdr.gso_type;
+        hdr_buf[2..4].copy_from_slice(&hdr.hdr_len.to_le_bytes());
+        hdr_buf[4..6].copy_from_slice(&hdr.gso_size.to_le_bytes());
+        hdr_buf[6..8].copy_from_slice(&hdr.csum_start.to_le_bytes());
+        hdr_buf[8..10].copy_from_slice(&hdr.csum_offset.to_le_bytes());
+        hdr_buf[10..12].copy_from_slice(&hdr.num_buffers.to_le_bytes());
+
+        let data_desc = element
+            .in_iovec
+            .get(1)
+            .ok_or(HyperError::VirtioError(VirtioError::ElementEmpty))?;
+        if (data_desc.len as usize) < frame.len() {
+            return Err(HyperError::VirtioError(VirtioError::AddressOverflow(
+                "virtio-net receiveq buffer",
+                data_desc.addr,
+                frame.len() as u64,
+            )));
+        }
+        let data_buf =
+            unsafe { core::slice::from_raw_parts_mut(data_desc.addr as *mut u8, frame.len()) };
+        data_buf.copy_from_slice(frame);
+
+        Ok((core::mem::size_of::<VirtioNetHdr>() + frame.len()) as u32)
+    }
+}
+
+impl AsAny for VirtioNet {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl VirtioDevice for VirtioNet {
+    fn virtio_base(&self) -> &VirtioBase {
+        &self.base
+    }
+
+    fn virtio_base_mut(&mut self) -> &mut VirtioBase {
+        &mut self.base
+    }
+
+    fn realize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn unrealize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_type(&self) -> u32 {
+        self.virtio_base().device_type
+    }
+
+    fn queue_num(&self) -> usize {
+        self.virtio_base().queue_num
+    }
+
+    fn queue_size_max(&self) -> u16 {
+        self.virtio_base().queue_size_max
+    }
+
+    fn init_config_features(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn device_features(&self, features_select: u32) -> u32 {
+        let buf = self.virtio_base().device_features.to_le_bytes();
+        le_read_u32(&buf[..], features_select as usize).unwrap_or(0)
+    }
+
+    fn set_driver_features(&mut self, page: u32, value: u32) {
+        let mut v = value;
+        let unsupported_features = value & !self.device_features(page);
+        if unsupported_features != 0 {
+            warn!("virtio-net: driver acknowledged unsupported feature bits");
+            v &= !unsupported_features;
+        }
+
+        let features = if page == 0 {
+            (self.driver_features(1) as u64) << 32 | (v as u64)
+        } else {
+            (v as u64) << 32 | (self.driver_features(0) as u64)
+        };
+        self.virtio_base_mut().driver_features = features;
+    }
+
+    fn driver_features(&self, features_select: u32) -> u32 {
+        let buf = self.virtio_base().driver_features.to_le_bytes();
+        le_read_u32(&buf[..], features_select as usize).unwrap_or(0)
+    }
+
+    fn hfeatures_sel(&self) -> u32 {
+        self.virtio_base().hfeatures_sel
+    }
+
+    fn set_hfeatures_sel(&mut self, val: u32) {
+        self.virtio_base_mut().hfeatures_sel = val;
+    }
+
+    fn gfeatures_sel(&self) -> u32 {
+        self.virtio_base().gfeatures_sel
+    }
+
+    fn set_gfeatures_sel(&mut self, val: u32) {
+        self.virtio_base_mut().gfeatures_sel = val;
+    }
+
+    fn check_device_status(&self, set: u32, clr: u32) -> bool {
+        self.device_status() & (set | clr) == set
+    }
+
+    fn device_status(&self) -> u32 {
+        self.virtio_base()
+            .device_status
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_device_status(&mut self, val: u32) {
+        self.virtio_base_mut()
+            .device_status
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn device_activated(&self) -> bool {
+        self.virtio_base()
+            .device_activated
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_device_activated(&mut self, val: bool) {
+        self.virtio_base_mut()
+            .device_activated
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn config_generation(&self) -> u8 {
+        self.virtio_base()
+            .config_generation
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_config_generation(&mut self, val: u8) {
+        self.virtio_base_mut()
+            .config_generation
+            .store(val, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn config_vector(&self) -> u16 {
+        self.virtio_base()
+            .config_vector
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_config_vector(&mut self, val: u16) {
+        self.virtio_base_mut()
+            .config_vector
+            .store(val, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn queue_type(&self) -> u16 {
+        self.virtio_base().queue_type
+    }
+
+    fn set_queue_type(&mut self, val: u16) {
+        self.virtio_base_mut().queue_type = val;
+    }
+
+    fn queue_select(&self) -> u16 {
+        self.virtio_base().queue_select
+    }
+
+    fn set_queue_select(&mut self, val: u16) {
+        self.virtio_base_mut().queue_select = val;
+    }
+
+    fn queue_config(&self) -> Result<&crate::device::virtio::QueueConfig> {
+        let queues_config = &self.virtio_base().queues_config;
+        let queue_select = self.virtio_base().queue_select;
+        queues_config
+            .get(queue_select as usize)
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::Other(format!("queue_select overflows"))))
+    }
+
+    fn queue_config_mut(&mut self, need_check: bool) -> Result<&mut crate::device::virtio::QueueConfig> {
+        if need_check
+            && !self.check_device_status(
+                crate::device::virtio::CONFIG_STATUS_FEATURES_OK,
+                crate::device::virtio::CONFIG_STATUS_DRIVER_OK | crate::device::virtio::CONFIG_STATUS_FAILED,
+            )
+        {
+            return Err(HyperError::VirtioError(VirtioError::DevStatErr(self.device_status())));
+        }
+
+        let queue_select = self.virtio_base().queue_select;
+        let queues_config = &mut self.virtio_base_mut().queues_config;
+        queues_config
+            .get_mut(queue_select as usize)
+            .ok_or_else(|| HyperError::VirtioError(VirtioError::Other(format!("queue_select overflows"))))
+    }
+
+    fn interrupt_status(&self) -> u32 {
+        self.virtio_base()
+            .interrupt_status
+            .load(core::sync::atomic::Ordering::Acquire)
+    }
+
+    fn set_interrupt_status(&mut self, val: u32) {
+        self.virtio_base_mut()
+            .interrupt_status
+            .store(val, core::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn read_config(&self, offset: u64, data: &mut [u8]) -> Result<()> {
+        let config = VirtioNetConfig { mac: self.mac };
+        let config_bytes = config.mac;
+        let offset = offset as usize;
+        if offset >= config_bytes.len() {
+            return Ok(());
+        }
+        let end = core::cmp::min(offset + data.len(), config_bytes.len());
+        data[..end - offset].copy_from_slice(&config_bytes[offset..end]);
+        Ok(())
+    }
+
+    fn write_config(&mut self, _offset: u64, _data: &[u8]) -> Result<()> {
+        // `virtio_net_config` is entirely read-only for the feature set this
+        // device advertises.
+        Ok(())
+    }
+
+    fn activate(&mut self, interrupt_cb: Arc<VirtioInterrupt>) -> Result<()> {
+        self.interrupt_cb = Some(interrupt_cb);
+        Ok(())
+    }
+
+    fn deactivate(&mut self) -> Result<()> {
+        self.interrupt_cb = None;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.interrupt_cb = None;
+        Ok(())
+    }
+
+    fn update_config(&mut self) -> Result<()> {
+        error!("Unsupported to update configuration");
+        Err(HyperError::BadState)
+    }
+
+    fn has_control_queue(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,66 @@
+//! [`pci::MsiIrqManager`] backing for `PciHost`'s virtio-pci functions.
+//!
+//! MSI/MSI-X delivery (`trigger`/`route`) has no direct path to a `VCpu`:
+//! none of `PciDevOps::write_config`/`MmioOps::write`/`MsiIrqManager::trigger`
+//! carry one down to where a message actually gets raised, and an offloaded
+//! virtio backend's own I/O-completion path (see `Notifier` in
+//! `transport::virtio_pci`) has no vCPU at all. So `trigger` queues the
+//! message into the `DeviceList` that built this manager (see
+//! `DeviceList::msi_sink`/`trigger_msi`) instead of injecting it directly;
+//! `X64VmDevices`/`NimbosVmDevices::vmexit_handler` drains that queue
+//! against whichever vCPU is servicing the current exit. What *is*
+//! self-contained is the level-triggered INTx side: a shared legacy line
+//! just needs de-assert-on-EOI bookkeeping, which [`pci::IrqLevelEvent`]
+//! already provides, so `register_level_irq`/`resample_level_irq` are fully
+//! wired here.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use hypercraft::HyperResult as Result;
+use pci::{IrqLevelEvent, IrqLevelLine, MsiIrqManager, MsiVector};
+use spin::Mutex;
+
+/// Routes a VM's virtio-pci functions' interrupts: MSI-X vectors queued for
+/// the owning `DeviceList` to deliver on its next drain (see the module
+/// doc), and legacy INTx as a shared, resample-on-EOI level line per GSI.
+pub struct VirtioMsiIrqManager {
+    pub vm_id: u32,
+    level_irqs: Mutex<BTreeMap<u32, Arc<IrqLevelEvent>>>,
+    pending_msi: Arc<Mutex<VecDeque<MsiVector>>>,
+}
+
+impl VirtioMsiIrqManager {
+    pub fn new(vm_id: u32, pending_msi: Arc<Mutex<VecDeque<MsiVector>>>) -> Self {
+        Self {
+            vm_id,
+            level_irqs: Mutex::new(BTreeMap::new()),
+            pending_msi,
+        }
+    }
+}
+
+impl MsiIrqManager for VirtioMsiIrqManager {
+    fn trigger(&self, vector: MsiVector, dev_id: u32) -> Result<()> {
+        trace!(
+            "vm[{}] dev[{:#x}] MSI trigger addr={:#x} data={:#x} queued for next drain",
+            self.vm_id, dev_id, vector.msi_addr, vector.msi_data
+        );
+        self.pending_msi.lock().push_back(vector);
+        Ok(())
+    }
+
+    fn register_level_irq(&self, gsi: u32) -> Result<Arc<dyn IrqLevelLine>> {
+        let mut level_irqs = self.level_irqs.lock();
+        let line = level_irqs
+            .entry(gsi)
+            .or_insert_with(IrqLevelEvent::new)
+            .clone();
+        Ok(line as Arc<dyn IrqLevelLine>)
+    }
+
+    fn resample_level_irq(&self, gsi: u32) {
+        if let Some(line) = self.level_irqs.lock().get(&gsi) {
+            line.resample();
+        }
+    }
+}
@@ -1,5 +1,7 @@
+mod packed;
 mod split;
 
+pub use packed::*;
 pub use split::*;
 
 use alloc::boxed::Box;
@@ -158,6 +160,7 @@ impl Queue {
     pub fn new(queue_config: QueueConfig, queue_type: u16) -> Result<Self> {
         let vring: Box<dyn VringOps + Send> = match queue_type {
             QUEUE_TYPE_SPLIT_VRING => Box::new(SplitVring::new(queue_config)),
+            QUEUE_TYPE_PACKED_VRING => Box::new(PackedVring::new(queue_config)),
             _ => {
                 return Err(HyperError::VirtioError(VirtioError::Other(format!(
                     "Unsupported queue type: {}",
@@ -0,0 +1,574 @@
+use super::{
+    Element, ElemIovec, QueueConfig, VringOps, VIRTQ_DESC_F_INDIRECT, VIRTQ_DESC_F_NEXT,
+    VIRTQ_DESC_F_WRITE,
+};
+use alloc::format;
+use core::cmp::min;
+use core::mem::size_of;
+use core::num::Wrapping;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use hypercraft::{HyperError, HyperResult as Result, VirtioError};
+use pci::util::byte_code::ByteCode;
+
+/// Mark a descriptor available to the device. Bit 7 of `flags`.
+const VIRTQ_DESC_F_AVAIL: u16 = 1 << 7;
+/// Mark a descriptor used by the device. Bit 15 of `flags`.
+const VIRTQ_DESC_F_USED: u16 = 1 << 15;
+
+/// Driver/device want no notifications at all.
+const RING_EVENT_FLAGS_DISABLE: u16 = 0x1;
+/// Notify only once the ring reaches the descriptor named by `off_wrap`.
+const RING_EVENT_FLAGS_DESC: u16 = 0x2;
+/// Low 15 bits of `EventSuppress::off_wrap` are the descriptor ring offset;
+/// bit 15 is the wrap counter.
+const EVENT_OFF_MASK: u16 = 0x7fff;
+
+/// The length of a packed-ring descriptor.
+const DESCRIPTOR_LEN: u64 = size_of::<PackedVringDesc>() as u64;
+/// Max total len of a descriptor chain, same cap `SplitVring` enforces.
+const DESC_CHAIN_MAX_TOTAL_LEN: u64 = 1u64 << 32;
+
+/// Descriptor of packed vring. Replaces the split ring's separate
+/// descriptor/avail/used rings with a single ring of these, where
+/// availability and use are both encoded in `flags` instead of a
+/// side-table index.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct PackedVringDesc {
+    /// Address (guest-physical).
+    pub addr: u64,
+    /// Length.
+    pub len: u32,
+    /// Buffer id, echoed back by the device in the used descriptor.
+    pub id: u16,
+    /// `VIRTQ_DESC_F_*` flags, including the wrap-counter-encoded
+    /// `VIRTQ_DESC_F_AVAIL`/`VIRTQ_DESC_F_USED` bits.
+    pub flags: u16,
+}
+
+impl PackedVringDesc {
+    /// Return true if this descriptor has a next descriptor.
+    fn has_next(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_NEXT != 0
+    }
+
+    /// Return true if this descriptor is an indirect descriptor.
+    fn is_indirect_desc(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_INDIRECT != 0
+    }
+
+    /// Check whether this descriptor is write-only or read-only.
+    fn write_only(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_WRITE != 0
+    }
+
+    /// Return true if the indirect table this descriptor points at is
+    /// valid: its len divides evenly by the size of a descriptor, isn't
+    /// zero, and it doesn't also carry `NEXT` (same rule `SplitVring`
+    /// enforces for its own indirect descriptors).
+    fn is_valid_indirect_desc(&self) -> bool {
+        if self.len == 0
+            || u64::from(self.len) % DESCRIPTOR_LEN != 0
+            || u64::from(self.len) / DESCRIPTOR_LEN > u16::MAX as u64
+        {
+            error!("The indirect descriptor is invalid, len: {}", self.len);
+            return false;
+        }
+        if self.has_next() {
+            error!("INDIRECT and NEXT flag should not be used together");
+            return false;
+        }
+        true
+    }
+
+    /// Get the num of descriptors in the indirect table.
+    fn get_desc_num(&self) -> u16 {
+        (u64::from(self.len) / DESCRIPTOR_LEN) as u16
+    }
+}
+
+impl ByteCode for PackedVringDesc {}
+
+/// Driver or device event-suppression structure, replacing the split
+/// ring's `used_event`/`avail_event` fields.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct EventSuppress {
+    /// Wrap counter (bit 15) plus descriptor ring offset (bits 0..14) of
+    /// the next event the other side should notify on.
+    off_wrap: u16,
+    /// Notification suppression mode: `RING_EVENT_FLAGS_{ENABLE,DISABLE,DESC}`.
+    flags: u16,
+}
+
+impl ByteCode for EventSuppress {}
+
+/// What a chain-walking step needs to read descriptors, resolved once
+/// before `PackedDescChainIter` starts rather than re-derived per step.
+struct PackedDescInfo {
+    /// Host virtual address of the descriptor table the chain head lives
+    /// in (the main ring, never an indirect table).
+    table_host: u64,
+    /// Size (descriptor count) of that table.
+    size: u16,
+    /// Index of the chain head within it.
+    index: u16,
+    /// GPA-to-HVA translation hook, copied from `QueueConfig::translate`.
+    translate: Option<super::AddrTranslator>,
+}
+
+/// Lazily walks a descriptor chain starting at `PackedDescInfo::index`,
+/// following `VIRTQ_DESC_F_NEXT` to the next ring slot and descending into
+/// `VIRTQ_DESC_F_INDIRECT` tables, the packed-ring equivalent of
+/// `SplitVring`'s `DescChainIter`. Unlike the split ring, there's no
+/// explicit `next` field: the chain continues at the next index in
+/// whichever table (main ring or indirect) is currently being walked,
+/// wrapping back to 0 at the end of that table.
+struct PackedDescChainIter<'a> {
+    desc_info: &'a PackedDescInfo,
+    /// Host address of the outer (main) ring, to tell whether the current
+    /// step is still consuming outer-ring slots or has descended into an
+    /// indirect table.
+    outer_table_host: u64,
+    table_host: u64,
+    table_size: u16,
+    next: Option<u16>,
+    visited: u16,
+    total_len: u64,
+    /// Number of outer-ring slots consumed by the chain so far.
+    outer_consumed: u16,
+    error: Option<HyperError>,
+}
+
+impl<'a> PackedDescChainIter<'a> {
+    fn new(desc_info: &'a PackedDescInfo) -> Self {
+        PackedDescChainIter {
+            desc_info,
+            outer_table_host: desc_info.table_host,
+            table_host: desc_info.table_host,
+            table_size: desc_info.size,
+            next: Some(desc_info.index),
+            visited: 0,
+            total_len: 0,
+            outer_consumed: 0,
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, err: HyperError) {
+        self.error = Some(err);
+        self.next = None;
+    }
+
+    fn read_desc(&self, index: u16) -> PackedVringDesc {
+        let addr = self.table_host + u64::from(index) * DESCRIPTOR_LEN;
+        // SAFETY: `addr` falls within the table currently being walked,
+        // whose bounds were checked by `PackedVring::is_invalid_memory`
+        // (main ring) or whose length was validated by
+        // `is_valid_indirect_desc` (indirect table) before descending.
+        unsafe { *(addr as *const PackedVringDesc) }
+    }
+}
+
+impl<'a> Iterator for PackedDescChainIter<'a> {
+    type Item = PackedVringDesc;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let index = self.next?;
+            if self.visited >= self.desc_info.size {
+                self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                    "descriptor chain loop exceeds queue size {}",
+                    self.desc_info.size
+                ))));
+                return None;
+            }
+            self.visited += 1;
+            if self.table_host == self.outer_table_host {
+                self.outer_consumed += 1;
+            }
+
+            let desc = self.read_desc(index);
+
+            if desc.is_indirect_desc() {
+                if !desc.is_valid_indirect_desc() {
+                    self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                        "invalid indirect descriptor, len: {}",
+                        desc.len
+                    ))));
+                    return None;
+                }
+                // Descend into the indirect table: subsequent reads walk it
+                // instead of the outer ring, starting back at index 0.
+                self.table_host = desc.addr;
+                self.table_size = desc.get_desc_num();
+                self.next = Some(0);
+                continue;
+            }
+
+            self.total_len += u64::from(desc.len);
+            if self.total_len > DESC_CHAIN_MAX_TOTAL_LEN {
+                self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                    "descriptor chain total length {} exceeds {}",
+                    self.total_len, DESC_CHAIN_MAX_TOTAL_LEN
+                ))));
+                return None;
+            }
+
+            self.next = if desc.has_next() {
+                Some((index + 1) % self.table_size)
+            } else {
+                None
+            };
+
+            return Some(desc);
+        }
+    }
+}
+
+/// Packed vring, selected by `QUEUE_TYPE_PACKED_VRING` when the driver has
+/// negotiated `VIRTIO_F_RING_PACKED`. Reuses [`QueueConfig`]'s
+/// `desc_table`/`avail_ring`/`used_ring` fields for the descriptor ring,
+/// the driver event-suppression structure, and the device event-suppression
+/// structure respectively.
+#[derive(Default, Clone)]
+pub struct PackedVring {
+    /// The configuration of virtqueue.
+    queue_config: QueueConfig,
+    /// The driver's current wrap counter. Flips every time `next_avail`
+    /// wraps past `actual_size()`.
+    avail_wrap_counter: bool,
+    /// The device's current wrap counter. Flips every time `next_used`
+    /// wraps past `actual_size()`.
+    used_wrap_counter: bool,
+}
+
+impl Deref for PackedVring {
+    type Target = QueueConfig;
+    fn deref(&self) -> &Self::Target {
+        &self.queue_config
+    }
+}
+
+impl DerefMut for PackedVring {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.queue_config
+    }
+}
+
+impl PackedVring {
+    /// Create a packed vring.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_config` - Configuration of the vring.
+    pub fn new(queue_config: QueueConfig) -> Self {
+        PackedVring {
+            queue_config,
+            avail_wrap_counter: true,
+            used_wrap_counter: true,
+        }
+    }
+
+    /// The actual size of the queue.
+    fn actual_size(&self) -> u16 {
+        min(self.size, self.max_size)
+    }
+
+    /// Read the descriptor at `index` from the descriptor ring.
+    fn get_desc(&self, index: u16) -> Result<PackedVringDesc> {
+        let addr = self.addr_cache.desc_table_host + u64::from(index) * DESCRIPTOR_LEN;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        Ok(unsafe { *(addr as *const PackedVringDesc) })
+    }
+
+    /// Write the descriptor at `index` back to the descriptor ring.
+    fn set_desc(&self, index: u16, desc: &PackedVringDesc) -> Result<()> {
+        let addr = self.addr_cache.desc_table_host + u64::from(index) * DESCRIPTOR_LEN;
+        // SAFETY: same as `get_desc`.
+        unsafe { *(addr as *mut PackedVringDesc) = *desc };
+        Ok(())
+    }
+
+    /// Read the driver event-suppression structure from guest memory: what
+    /// the driver last told the device about when it wants used-ring
+    /// notifications.
+    fn driver_event_suppress(&self) -> Result<EventSuppress> {
+        let addr = self.addr_cache.avail_ring_host;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        Ok(unsafe { *(addr as *const EventSuppress) })
+    }
+
+    /// Write the device event-suppression structure to guest memory: what
+    /// this device tells the driver about when it wants avail-ring kicks.
+    fn set_device_event_suppress(&self, suppress: EventSuppress) -> Result<()> {
+        let addr = self.addr_cache.used_ring_host;
+        // SAFETY: same as `driver_event_suppress`.
+        unsafe { *(addr as *mut EventSuppress) = suppress };
+        Ok(())
+    }
+
+    /// Return true if `desc` is currently available to the device, i.e. its
+    /// `AVAIL` bit matches the driver's wrap counter and its `USED` bit
+    /// doesn't.
+    fn is_desc_avail(&self, desc: &PackedVringDesc) -> bool {
+        let avail = desc.flags & VIRTQ_DESC_F_AVAIL != 0;
+        let used = desc.flags & VIRTQ_DESC_F_USED != 0;
+        avail != used && avail == self.avail_wrap_counter
+    }
+
+    /// Advance `next_avail` by one descriptor, flipping the driver's wrap
+    /// counter each time the index wraps past `actual_size()`.
+    fn advance_avail(&mut self) {
+        self.next_avail += Wrapping(1);
+        if self.next_avail.0 as usize >= self.actual_size() as usize {
+            self.next_avail = Wrapping(0);
+            self.avail_wrap_counter = !self.avail_wrap_counter;
+        }
+    }
+
+    /// Advance `next_used` by one descriptor, flipping the device's wrap
+    /// counter each time the index wraps past `actual_size()`.
+    fn advance_used(&mut self) {
+        self.next_used += Wrapping(1);
+        if self.next_used.0 as usize >= self.actual_size() as usize {
+            self.next_used = Wrapping(0);
+            self.used_wrap_counter = !self.used_wrap_counter;
+        }
+    }
+
+    fn is_overlap(start1: u64, end1: u64, start2: u64, end2: u64) -> bool {
+        !(start1 >= end2 || start2 >= end1)
+    }
+
+    /// Whether the descriptor ring and the two event-suppression structures
+    /// overflow or overlap each other, the packed-ring equivalent of
+    /// `SplitVring::is_invalid_memory`.
+    fn is_invalid_memory(&self) -> bool {
+        if self.actual_size() == 0 {
+            error!("Invalid queue size: 0");
+            return true;
+        }
+
+        let desc_table = self.addr_cache.desc_table_host;
+        let driver_event = self.addr_cache.avail_ring_host;
+        let device_event = self.addr_cache.used_ring_host;
+
+        let desc_size = u64::from(self.actual_size()) * DESCRIPTOR_LEN;
+        let event_size = size_of::<EventSuppress>() as u64;
+
+        let (Some(desc_end), Some(driver_end), Some(device_end)) = (
+            desc_table.checked_add(desc_size),
+            driver_event.checked_add(event_size),
+            device_event.checked_add(event_size),
+        ) else {
+            error!("The GPA of descriptor ring or event suppression structures overflows");
+            return true;
+        };
+
+        if Self::is_overlap(desc_table, desc_end, driver_event, driver_end)
+            || Self::is_overlap(desc_table, desc_end, device_event, device_end)
+            || Self::is_overlap(driver_event, driver_end, device_event, device_end)
+        {
+            error!("The descriptor ring and event suppression structures overlap with each other");
+            return true;
+        }
+
+        false
+    }
+
+    /// Collects the descriptor chain starting at ring index `index` into
+    /// `element`, advancing `element.desc_num`/iovecs the same way
+    /// `SplitVringDesc::get_element` does. Returns the number of outer-ring
+    /// slots the chain occupied.
+    fn collect_chain(&self, index: u16, element: &mut Element) -> Result<u16> {
+        let desc_info = PackedDescInfo {
+            table_host: self.addr_cache.desc_table_host,
+            size: self.actual_size(),
+            index,
+            translate: self.translate.clone(),
+        };
+        let mut iter = PackedDescChainIter::new(&desc_info);
+        for desc in &mut iter {
+            let addr = match &desc_info.translate {
+                Some(translate) => translate(desc.addr, u64::from(desc.len))?,
+                None => desc.addr,
+            };
+            let iovec = ElemIovec {
+                addr,
+                len: desc.len,
+            };
+            if desc.write_only() {
+                element.in_iovec.push(iovec);
+            } else {
+                element.out_iovec.push(iovec);
+            }
+            element.desc_num += 1;
+        }
+        if let Some(err) = iter.error {
+            return Err(err);
+        }
+        Ok(iter.outer_consumed)
+    }
+}
+
+impl VringOps for PackedVring {
+    fn is_enabled(&self) -> bool {
+        self.ready
+    }
+
+    fn is_valid(&self) -> bool {
+        if !self.ready {
+            error!("The configuration of vring is not ready\n");
+            false
+        } else if self.size > self.max_size || self.size == 0 {
+            error!(
+                "vring with invalid size:{} max size:{}",
+                self.size, self.max_size
+            );
+            false
+        } else {
+            !self.is_invalid_memory()
+        }
+    }
+
+    fn pop_avail(&mut self, _features: u64) -> Result<Element> {
+        let head_index = self.next_avail.0;
+        let head = self.get_desc(head_index)?;
+        if !self.is_desc_avail(&head) {
+            return Ok(Element::new(0));
+        }
+
+        let mut element = Element::new(head_index);
+        let consumed = self.collect_chain(head_index, &mut element)?;
+        for _ in 0..consumed.max(1) {
+            self.advance_avail();
+        }
+
+        Ok(element)
+    }
+
+    fn push_back(&mut self) {
+        if self.next_avail.0 == 0 {
+            self.avail_wrap_counter = !self.avail_wrap_counter;
+        }
+        self.next_avail -= Wrapping(1);
+    }
+
+    fn add_used(&mut self, index: u16, len: u32) -> Result<()> {
+        let mut desc = self.get_desc(index)?;
+        desc.len = len;
+        let used_bit = self.used_wrap_counter;
+        desc.flags = (desc.flags & !(VIRTQ_DESC_F_AVAIL | VIRTQ_DESC_F_USED))
+            | if used_bit {
+                VIRTQ_DESC_F_AVAIL | VIRTQ_DESC_F_USED
+            } else {
+                0
+            };
+        self.set_desc(index, &desc)?;
+        self.advance_used();
+        Ok(())
+    }
+
+    fn should_notify(&mut self, _features: u64) -> bool {
+        let Ok(suppress) = self.driver_event_suppress() else {
+            return true;
+        };
+        match suppress.flags {
+            RING_EVENT_FLAGS_DISABLE => false,
+            RING_EVENT_FLAGS_DESC => {
+                let off = suppress.off_wrap & EVENT_OFF_MASK;
+                let wrap = suppress.off_wrap & !EVENT_OFF_MASK != 0;
+                off == self.next_used.0 && wrap == self.used_wrap_counter
+            }
+            _ => true,
+        }
+    }
+
+    fn suppress_queue_notify(&mut self, _features: u64, suppress: bool) -> Result<()> {
+        let event = EventSuppress {
+            off_wrap: self.next_avail.0 | ((self.avail_wrap_counter as u16) << 15),
+            flags: if suppress { RING_EVENT_FLAGS_DISABLE } else { 0 },
+        };
+        self.set_device_event_suppress(event)
+    }
+
+    fn actual_size(&self) -> u16 {
+        self.actual_size()
+    }
+
+    fn get_queue_config(&self) -> QueueConfig {
+        self.queue_config.clone()
+    }
+
+    /// The number of consecutive available descriptors starting at
+    /// `next_avail`, capped at the ring size.
+    fn avail_ring_len(&mut self) -> Result<u16> {
+        let size = self.actual_size();
+        let mut count = 0;
+        let mut idx = self.next_avail.0;
+        while count < size {
+            let desc = self.get_desc(idx)?;
+            if !self.is_desc_avail(&desc) {
+                break;
+            }
+            count += 1;
+            idx = (idx + 1) % size;
+        }
+        Ok(count)
+    }
+
+    fn get_avail_idx(&self) -> Result<u16> {
+        Ok(self.next_avail.0)
+    }
+
+    fn get_used_idx(&self) -> Result<u16> {
+        Ok(self.next_used.0)
+    }
+
+    fn get_cache(&self) -> &Option<u32> {
+        &None
+    }
+
+    fn get_avail_bytes(&mut self, max_size: usize, is_in: bool) -> Result<usize> {
+        let size = self.actual_size();
+        let mut pos = self.next_avail.0;
+        let mut scanned = 0u16;
+        let mut total: usize = 0;
+
+        while scanned < size && total < max_size {
+            let head = self.get_desc(pos)?;
+            if !self.is_desc_avail(&head) {
+                break;
+            }
+
+            let desc_info = PackedDescInfo {
+                table_host: self.addr_cache.desc_table_host,
+                size,
+                index: pos,
+                translate: self.translate.clone(),
+            };
+            let mut iter = PackedDescChainIter::new(&desc_info);
+            for desc in &mut iter {
+                if desc.write_only() == is_in {
+                    total += desc.len as usize;
+                    if total >= max_size {
+                        break;
+                    }
+                }
+            }
+            if let Some(err) = iter.error {
+                return Err(err);
+            }
+
+            let consumed = iter.outer_consumed.max(1);
+            pos = (pos + consumed) % size;
+            scanned += consumed;
+        }
+
+        Ok(total)
+    }
+}
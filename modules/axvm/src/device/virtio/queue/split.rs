@@ -1,508 +1,887 @@
-use super::{
-    checked_offset_mem, ElemIovec, Element, VringOps, INVALID_VECTOR_NUM, VIRTQ_DESC_F_INDIRECT,
-    VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE,
-};
-use crate::device::virtio::{
-    report_virtio_error, virtio_has_feature, VirtioInterrupt, VIRTIO_F_RING_EVENT_IDX,
-};
-use alloc::format;
-use alloc::sync::Arc;
-use core::cmp::{max, min, Ordering};
-use core::mem::size_of;
-use core::num::Wrapping;
-use core::ops::Deref;
-use core::ops::DerefMut;
-use core::sync::atomic::AtomicBool;
-use hypercraft::{HyperError, HyperResult as Result, VirtioError};
-use pci::util::byte_code::ByteCode;
-
-/// When host consumes a buffer, don't interrupt the guest.
-const VRING_AVAIL_F_NO_INTERRUPT: u16 = 1;
-/// When guest produces a buffer, don't notify the host.
-const VRING_USED_F_NO_NOTIFY: u16 = 1;
-
-/// Max total len of a descriptor chain.
-const DESC_CHAIN_MAX_TOTAL_LEN: u64 = 1u64 << 32;
-/// The length of used element.
-const USEDELEM_LEN: u64 = size_of::<UsedElem>() as u64;
-/// The length of avail element.
-const AVAILELEM_LEN: u64 = size_of::<u16>() as u64;
-/// The length of available ring except array of avail element(flags: u16 idx: u16 used_event: u16).
-const VRING_AVAIL_LEN_EXCEPT_AVAILELEM: u64 = (size_of::<u16>() * 3) as u64;
-/// The length of used ring except array of used element(flags: u16 idx: u16 avail_event: u16).
-const VRING_USED_LEN_EXCEPT_USEDELEM: u64 = (size_of::<u16>() * 3) as u64;
-/// The length of flags(u16) and idx(u16).
-const VRING_FLAGS_AND_IDX_LEN: u64 = size_of::<SplitVringFlagsIdx>() as u64;
-/// The position of idx in the available ring and the used ring.
-const VRING_IDX_POSITION: u64 = size_of::<u16>() as u64;
-/// The length of virtio descriptor.
-const DESCRIPTOR_LEN: u64 = size_of::<SplitVringDesc>() as u64;
-
-#[derive(Default, Clone, Copy)]
-pub struct VirtioAddrCache {
-    /// Host virtual address of the descriptor table.
-    pub desc_table_host: u64,
-    /// Host virtual address of the available ring.
-    pub avail_ring_host: u64,
-    /// Host virtual address of the used ring.
-    pub used_ring_host: u64,
-}
-
-/// The configuration of virtqueue.
-#[derive(Default, Clone, Copy)]
-pub struct QueueConfig {
-    /// Guest physical address of the descriptor table.
-    pub desc_table: u64,
-    /// Guest physical address of the available ring.
-    pub avail_ring: u64,
-    /// Guest physical address of the used ring.
-    pub used_ring: u64,
-    /// Host address cache.
-    pub addr_cache: VirtioAddrCache,
-    /// The maximal size of elements offered by the device.
-    pub max_size: u16,
-    /// The queue size set by the guest.
-    pub size: u16,
-    /// Virtual queue ready bit.
-    pub ready: bool,
-    /// Interrupt vector index of the queue for msix
-    pub vector: u16,
-    /// The next index which can be popped in the available vring.
-    next_avail: Wrapping<u16>,
-    /// The next index which can be pushed in the used vring.
-    next_used: Wrapping<u16>,
-    /// The index of last descriptor used which has triggered interrupt.
-    last_signal_used: Wrapping<u16>,
-    /// The last_signal_used is valid or not.
-    signal_used_valid: bool,
-}
-
-impl QueueConfig {
-    /// Create configuration for a virtqueue.
-    ///
-    /// # Arguments
-    ///
-    /// * `max_size` - The maximum size of the virtqueue.
-    pub fn new(max_size: u16) -> Self {
-        let addr_cache = VirtioAddrCache::default();
-        QueueConfig {
-            desc_table: 0,
-            avail_ring: 0,
-            used_ring: 0,
-            addr_cache,
-            max_size,
-            size: max_size,
-            ready: false,
-            vector: INVALID_VECTOR_NUM,
-            next_avail: Wrapping(0),
-            next_used: Wrapping(0),
-            last_signal_used: Wrapping(0),
-            signal_used_valid: false,
-        }
-    }
-
-    fn get_desc_size(&self) -> u64 {
-        min(self.size, self.max_size) as u64 * DESCRIPTOR_LEN
-    }
-
-    fn get_used_size(&self, features: u64) -> u64 {
-        let size = if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
-            2_u64
-        } else {
-            0_u64
-        };
-
-        size + VRING_FLAGS_AND_IDX_LEN + (min(self.size, self.max_size) as u64) * USEDELEM_LEN
-    }
-
-    fn get_avail_size(&self, features: u64) -> u64 {
-        let size = if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
-            2_u64
-        } else {
-            0_u64
-        };
-
-        size + VRING_FLAGS_AND_IDX_LEN
-            + (min(self.size, self.max_size) as u64) * (size_of::<u16>() as u64)
-    }
-
-    pub fn reset(&mut self) {
-        *self = Self::new(self.max_size);
-    }
-
-    pub fn set_addr_cache(
-        &mut self,
-        interrupt_cb: Arc<VirtioInterrupt>,
-        features: u64,
-        broken: &Arc<AtomicBool>,
-    ) {
-    }
-}
-
-/// Virtio used element.
-#[repr(C)]
-#[derive(Default, Clone, Copy)]
-struct UsedElem {
-    /// Index of descriptor in the virqueue descriptor table.
-    id: u32,
-    /// Total length of the descriptor chain which was used (written to).
-    len: u32,
-}
-
-impl ByteCode for UsedElem {}
-
-/// A struct including flags and idx for avail vring and used vring.
-#[repr(C)]
-#[derive(Default, Clone, Copy)]
-struct SplitVringFlagsIdx {
-    flags: u16,
-    idx: u16,
-}
-
-impl ByteCode for SplitVringFlagsIdx {}
-
-struct DescInfo {
-    /// The host virtual address of the descriptor table.
-    table_host: u64,
-    /// The size of the descriptor table.
-    size: u16,
-    /// The index of the current descriptor table.
-    index: u16,
-    /// The descriptor table.
-    desc: SplitVringDesc,
-}
-
-/// Descriptor of split vring.
-#[repr(C)]
-#[derive(Default, Clone, Copy)]
-pub struct SplitVringDesc {
-    /// Address (guest-physical).
-    pub addr: u64,
-    /// Length.
-    pub len: u32,
-    /// The flags as indicated above.
-    pub flags: u16,
-    /// We chain unused descriptors via this, too.
-    pub next: u16,
-}
-
-impl SplitVringDesc {
-    /// Create a descriptor of split vring.
-    ///
-    /// # Arguments
-    ///
-    /// * `desc_table` - Guest address of virtqueue descriptor table.
-    /// * `queue_size` - Size of virtqueue.
-    /// * `index` - Index of descriptor in the virqueue descriptor table.
-    fn new(desc_table_host: u64, queue_size: u16, index: u16) -> Result<Self> {
-        let desc_addr = desc_table_host
-            .checked_add(u64::from(index) * DESCRIPTOR_LEN)
-            .ok_or_else(|| {
-                HyperError::VirtioError(VirtioError::AddressOverflow(
-                    "creating a descriptor",
-                    desc_table_host,
-                    u64::from(index) * DESCRIPTOR_LEN,
-                ))
-            })?;
-        Ok(SplitVringDesc {
-            addr: desc_addr,
-            len: 0,
-            flags: 0,
-            next: 0,
-        })
-    }
-
-    /// Return true if the descriptor is valid.
-    fn is_valid(&self, queue_size: u16) -> bool {
-        true
-    }
-
-    /// Return true if this descriptor has next descriptor.
-    fn has_next(&self) -> bool {
-        self.flags & VIRTQ_DESC_F_NEXT != 0
-    }
-
-    /// Get the next descriptor in descriptor chain.
-    fn next_desc(desc_table_host: u64, queue_size: u16, index: u16) -> Result<SplitVringDesc> {
-        SplitVringDesc::new(desc_table_host, queue_size, index)
-    }
-
-    /// Check whether this descriptor is write-only or read-only.
-    /// Write-only means that the emulated device can write and the driver can read.
-    fn write_only(&self) -> bool {
-        self.flags & VIRTQ_DESC_F_WRITE != 0
-    }
-
-    /// Return true if this descriptor is a indirect descriptor.
-    fn is_indirect_desc(&self) -> bool {
-        self.flags & VIRTQ_DESC_F_INDIRECT != 0
-    }
-
-    /// Return true if the indirect descriptor is valid.
-    /// The len can be divided evenly by the size of descriptor and can not be zero.
-    fn is_valid_indirect_desc(&self) -> bool {
-        if self.len == 0
-            || u64::from(self.len) % DESCRIPTOR_LEN != 0
-            || u64::from(self.len) / DESCRIPTOR_LEN > u16::MAX as u64
-        {
-            error!("The indirect descriptor is invalid, len: {}", self.len);
-            return false;
-        }
-        if self.has_next() {
-            error!("INDIRECT and NEXT flag should not be used together");
-            return false;
-        }
-        true
-    }
-
-    /// Get the num of descriptor in the table of indirect descriptor.
-    fn get_desc_num(&self) -> u16 {
-        (u64::from(self.len) / DESCRIPTOR_LEN) as u16
-    }
-
-    /// Get element from descriptor chain.
-    fn get_element(desc_info: &DescInfo, elem: &mut Element) -> Result<()> {
-        let mut desc_table_host = desc_info.table_host;
-        let mut desc_size = desc_info.size;
-        let mut desc = desc_info.desc;
-        elem.index = desc_info.index;
-        let mut queue_size = desc_size;
-        let mut indirect: bool = false;
-        let mut write_elem_count: u32 = 0;
-        let mut desc_total_len: u64 = 0;
-
-        Ok(())
-    }
-}
-
-impl ByteCode for SplitVringDesc {}
-
-/// Split vring.
-#[derive(Default, Clone, Copy)]
-pub struct SplitVring {
-    /// The configuration of virtqueue.
-    queue_config: QueueConfig,
-}
-
-impl Deref for SplitVring {
-    type Target = QueueConfig;
-    fn deref(&self) -> &Self::Target {
-        &self.queue_config
-    }
-}
-
-impl DerefMut for SplitVring {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.queue_config
-    }
-}
-
-impl SplitVring {
-    /// Create a split vring.
-    ///
-    /// # Arguments
-    ///
-    /// * `queue_config` - Configuration of the vring.
-    pub fn new(queue_config: QueueConfig) -> Self {
-        SplitVring { queue_config }
-    }
-
-    /// The actual size of the queue.
-    fn actual_size(&self) -> u16 {
-        min(self.size, self.max_size)
-    }
-
-    /// Get the flags and idx of the available ring from guest memory.
-    fn get_avail_flags_idx(&self) -> Result<SplitVringFlagsIdx> {
-        Ok(SplitVringFlagsIdx { flags: 0, idx: 0 })
-    }
-
-    /// Get the idx of the available ring from guest memory.
-    fn get_avail_idx(&self) -> Result<u16> {
-        let flags_idx = self.get_avail_flags_idx()?;
-        Ok(flags_idx.idx)
-    }
-
-    /// Get the flags of the available ring from guest memory.
-    fn get_avail_flags(&self) -> Result<u16> {
-        let flags_idx = self.get_avail_flags_idx()?;
-        Ok(flags_idx.flags)
-    }
-
-    /// Get the flags and idx of the used ring from guest memory.
-    fn get_used_flags_idx(&self) -> Result<SplitVringFlagsIdx> {
-        Ok(SplitVringFlagsIdx { flags: 0, idx: 0 })
-    }
-
-    /// Get the index of the used ring from guest memory.
-    fn get_used_idx(&self) -> Result<u16> {
-        let flag_idx = self.get_used_flags_idx()?;
-        Ok(flag_idx.idx)
-    }
-
-    /// Set the used flags to suppress virtqueue notification or not
-    fn set_used_flags(&self, suppress: bool) -> Result<()> {
-        let mut flags_idx = self.get_used_flags_idx()?;
-
-        if suppress {
-            flags_idx.flags |= VRING_USED_F_NO_NOTIFY;
-        } else {
-            flags_idx.flags &= !VRING_USED_F_NO_NOTIFY;
-        }
-        Ok(())
-    }
-
-    /// Set the avail idx to the field of the event index for the available ring.
-    fn set_avail_event(&self, event_idx: u16) -> Result<()> {
-        let avail_event_offset =
-            VRING_FLAGS_AND_IDX_LEN + USEDELEM_LEN * u64::from(self.actual_size());
-        Ok(())
-    }
-
-    /// Get the event index of the used ring from guest memory.
-    fn get_used_event(&self) -> Result<u16> {
-        Ok(0)
-    }
-
-    /// Return true if VRING_AVAIL_F_NO_INTERRUPT is set.
-    fn is_avail_ring_no_interrupt(&self) -> bool {
-        true
-    }
-
-    /// Return true if it's required to trigger interrupt for the used vring.
-    fn used_ring_need_event(&mut self) -> bool {
-        true
-    }
-
-    fn is_overlap(start1: u64, end1: u64, start2: u64, end2: u64) -> bool {
-        !(start1 >= end2 || start2 >= end1)
-    }
-
-    fn is_invalid_memory(&self, actual_size: u64) -> bool {
-        true
-    }
-
-    fn get_desc_info(&mut self, next_avail: Wrapping<u16>, features: u64) -> Result<DescInfo> {
-        let index_offset =
-            VRING_FLAGS_AND_IDX_LEN + AVAILELEM_LEN * u64::from(next_avail.0 % self.actual_size());
-        // The GPA of avail_ring_host with avail table length has been checked in
-        // is_invalid_memory which must not be overflowed.
-        let desc_index_addr = self.addr_cache.avail_ring_host + index_offset;
-        let desc_index = 0;
-
-        let desc = SplitVringDesc::new(
-            self.addr_cache.desc_table_host,
-            self.actual_size(),
-            desc_index,
-        )?;
-
-        // Suppress queue notification related to current processing desc chain.
-        if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
-            self.set_avail_event((next_avail + Wrapping(1)).0)
-                .or_else(|_| {
-                    Err(HyperError::VirtioError(VirtioError::Other(format!(
-                        "Failed to set avail event for popping avail ring"
-                    ))))
-                })?;
-        }
-
-        Ok(DescInfo {
-            table_host: self.addr_cache.desc_table_host,
-            size: self.actual_size(),
-            index: desc_index,
-            desc,
-        })
-    }
-
-    fn get_vring_element(&mut self, features: u64, elem: &mut Element) -> Result<()> {
-        let desc_info = self.get_desc_info(self.next_avail, features)?;
-
-        SplitVringDesc::get_element(&desc_info, elem).or_else(|_| {
-            Err(HyperError::VirtioError(VirtioError::Other(format!(
-                "Failed to get element from descriptor chain {}, table addr: 0x{:X}, size: {}",
-                desc_info.index, desc_info.table_host, desc_info.size,
-            ))))
-        })?;
-        self.next_avail += Wrapping(1);
-
-        Ok(())
-    }
-}
-
-impl VringOps for SplitVring {
-    fn is_enabled(&self) -> bool {
-        self.ready
-    }
-
-    fn is_valid(&self) -> bool {
-        let size = u64::from(self.actual_size());
-        if !self.ready {
-            error!("The configuration of vring is not ready\n");
-            false
-        } else if self.size > self.max_size || self.size == 0 || (self.size & (self.size - 1)) != 0
-        {
-            error!(
-                "vring with invalid size:{} max size:{}",
-                self.size, self.max_size
-            );
-            false
-        } else {
-            !self.is_invalid_memory(size)
-        }
-    }
-
-    fn pop_avail(&mut self, features: u64) -> Result<Element> {
-        let mut element = Element::new(0);
-
-        Ok(element)
-    }
-
-    fn push_back(&mut self) {
-        self.next_avail -= Wrapping(1);
-    }
-
-    fn add_used(&mut self, index: u16, len: u32) -> Result<()> {
-        Ok(())
-    }
-
-    fn should_notify(&mut self, features: u64) -> bool {
-        true
-    }
-
-    fn suppress_queue_notify(&mut self, features: u64, suppress: bool) -> Result<()> {
-        Ok(())
-    }
-
-    fn actual_size(&self) -> u16 {
-        self.actual_size()
-    }
-
-    fn get_queue_config(&self) -> QueueConfig {
-        let mut config = self.queue_config;
-        config.signal_used_valid = false;
-        config
-    }
-
-    /// The number of descriptor chains in the available ring.
-    fn avail_ring_len(&mut self) -> Result<u16> {
-        let avail_idx = self.get_avail_idx().map(Wrapping)?;
-
-        Ok((avail_idx - self.next_avail).0)
-    }
-
-    fn get_avail_idx(&self) -> Result<u16> {
-        SplitVring::get_avail_idx(self)
-    }
-
-    fn get_used_idx(&self) -> Result<u16> {
-        SplitVring::get_used_idx(self)
-    }
-
-    fn get_cache(&self) -> &Option<u32> {
-        &None
-    }
-
-    fn get_avail_bytes(&mut self, max_size: usize, is_in: bool) -> Result<usize> {
-        Ok(0)
-    }
-}
+use super::{
+    checked_offset_mem, ElemIovec, Element, VringOps, INVALID_VECTOR_NUM, VIRTQ_DESC_F_INDIRECT,
+    VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE,
+};
+use crate::device::virtio::{
+    report_virtio_error, virtio_has_feature, VirtioInterrupt, VIRTIO_F_RING_EVENT_IDX,
+};
+use alloc::format;
+use alloc::sync::Arc;
+use core::cmp::min;
+use core::mem::size_of;
+use core::num::Wrapping;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+use hypercraft::{HyperError, HyperResult as Result, VirtioError};
+use pci::util::byte_code::ByteCode;
+
+/// When host consumes a buffer, don't interrupt the guest.
+const VRING_AVAIL_F_NO_INTERRUPT: u16 = 1;
+/// When guest produces a buffer, don't notify the host.
+const VRING_USED_F_NO_NOTIFY: u16 = 1;
+
+/// Max total len of a descriptor chain.
+const DESC_CHAIN_MAX_TOTAL_LEN: u64 = 1u64 << 32;
+/// The length of used element.
+const USEDELEM_LEN: u64 = size_of::<UsedElem>() as u64;
+/// The length of avail element.
+const AVAILELEM_LEN: u64 = size_of::<u16>() as u64;
+/// The length of available ring except array of avail element(flags: u16 idx: u16 used_event: u16).
+const VRING_AVAIL_LEN_EXCEPT_AVAILELEM: u64 = (size_of::<u16>() * 3) as u64;
+/// The length of used ring except array of used element(flags: u16 idx: u16 avail_event: u16).
+const VRING_USED_LEN_EXCEPT_USEDELEM: u64 = (size_of::<u16>() * 3) as u64;
+/// The length of flags(u16) and idx(u16).
+const VRING_FLAGS_AND_IDX_LEN: u64 = size_of::<SplitVringFlagsIdx>() as u64;
+/// The position of idx in the available ring and the used ring.
+const VRING_IDX_POSITION: u64 = size_of::<u16>() as u64;
+/// The length of virtio descriptor.
+const DESCRIPTOR_LEN: u64 = size_of::<SplitVringDesc>() as u64;
+
+/// Translates a guest-physical address/length pair to a host-virtual
+/// address, giving a vIOMMU (or this crate's own nested page table) a
+/// single place to sit between the vring and the raw GPAs a guest driver
+/// hands it, rather than every consumer of `Element` re-translating.
+pub type AddrTranslator = Arc<dyn Fn(u64, u64) -> Result<u64> + Send + Sync>;
+
+#[derive(Default, Clone, Copy)]
+pub struct VirtioAddrCache {
+    /// Host virtual address of the descriptor table.
+    pub desc_table_host: u64,
+    /// Host virtual address of the available ring.
+    pub avail_ring_host: u64,
+    /// Host virtual address of the used ring.
+    pub used_ring_host: u64,
+}
+
+/// The configuration of virtqueue.
+#[derive(Default, Clone)]
+pub struct QueueConfig {
+    /// Guest physical address of the descriptor table.
+    pub desc_table: u64,
+    /// Guest physical address of the available ring.
+    pub avail_ring: u64,
+    /// Guest physical address of the used ring.
+    pub used_ring: u64,
+    /// Host address cache.
+    pub addr_cache: VirtioAddrCache,
+    /// The maximal size of elements offered by the device.
+    pub max_size: u16,
+    /// The queue size set by the guest.
+    pub size: u16,
+    /// Virtual queue ready bit.
+    pub ready: bool,
+    /// Interrupt vector index of the queue for msix
+    pub vector: u16,
+    /// The next index which can be popped in the available vring.
+    next_avail: Wrapping<u16>,
+    /// The next index which can be pushed in the used vring.
+    next_used: Wrapping<u16>,
+    /// The index of last descriptor used which has triggered interrupt.
+    last_signal_used: Wrapping<u16>,
+    /// The last_signal_used is valid or not.
+    signal_used_valid: bool,
+    /// GPA-to-HVA translation hook installed by `set_addr_cache`, re-applied
+    /// to each descriptor's `addr` as it's read out in `get_element`.
+    translate: Option<AddrTranslator>,
+}
+
+impl QueueConfig {
+    /// Create configuration for a virtqueue.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximum size of the virtqueue.
+    pub fn new(max_size: u16) -> Self {
+        let addr_cache = VirtioAddrCache::default();
+        QueueConfig {
+            desc_table: 0,
+            avail_ring: 0,
+            used_ring: 0,
+            addr_cache,
+            max_size,
+            size: max_size,
+            ready: false,
+            vector: INVALID_VECTOR_NUM,
+            next_avail: Wrapping(0),
+            next_used: Wrapping(0),
+            last_signal_used: Wrapping(0),
+            signal_used_valid: false,
+            translate: None,
+        }
+    }
+
+    fn get_desc_size(&self) -> u64 {
+        min(self.size, self.max_size) as u64 * DESCRIPTOR_LEN
+    }
+
+    fn get_used_size(&self, features: u64) -> u64 {
+        let size = if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
+            2_u64
+        } else {
+            0_u64
+        };
+
+        size + VRING_FLAGS_AND_IDX_LEN + (min(self.size, self.max_size) as u64) * USEDELEM_LEN
+    }
+
+    fn get_avail_size(&self, features: u64) -> u64 {
+        let size = if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
+            2_u64
+        } else {
+            0_u64
+        };
+
+        size + VRING_FLAGS_AND_IDX_LEN
+            + (min(self.size, self.max_size) as u64) * (size_of::<u16>() as u64)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.max_size);
+    }
+
+    /// Cache host-visible addresses for the descriptor/avail/used rings,
+    /// and remember `translate` so later descriptor reads can resolve their
+    /// own GPAs through the same hook. Called once per queue at enable
+    /// time, same as real VMMs fold IOMMU translation into queue setup
+    /// rather than having every consumer re-translate.
+    pub fn set_addr_cache(
+        &mut self,
+        interrupt_cb: Arc<VirtioInterrupt>,
+        features: u64,
+        broken: &Arc<AtomicBool>,
+        translate: Option<AddrTranslator>,
+    ) {
+        let desc_size = self.get_desc_size();
+        let avail_size = self.get_avail_size(features);
+        let used_size = self.get_used_size(features);
+        self.addr_cache = VirtioAddrCache {
+            desc_table_host: translate
+                .as_ref()
+                .and_then(|t| t(self.desc_table, desc_size).ok())
+                .unwrap_or(self.desc_table),
+            avail_ring_host: translate
+                .as_ref()
+                .and_then(|t| t(self.avail_ring, avail_size).ok())
+                .unwrap_or(self.avail_ring),
+            used_ring_host: translate
+                .as_ref()
+                .and_then(|t| t(self.used_ring, used_size).ok())
+                .unwrap_or(self.used_ring),
+        };
+        self.translate = translate;
+    }
+}
+
+/// Virtio used element.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct UsedElem {
+    /// Index of descriptor in the virqueue descriptor table.
+    id: u32,
+    /// Total length of the descriptor chain which was used (written to).
+    len: u32,
+}
+
+impl ByteCode for UsedElem {}
+
+/// A struct including flags and idx for avail vring and used vring.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct SplitVringFlagsIdx {
+    flags: u16,
+    idx: u16,
+}
+
+impl ByteCode for SplitVringFlagsIdx {}
+
+struct DescInfo {
+    /// The host virtual address of the descriptor table.
+    table_host: u64,
+    /// The size of the descriptor table.
+    size: u16,
+    /// The index of the current descriptor table.
+    index: u16,
+    /// GPA-to-HVA translation hook, copied from `QueueConfig::translate`.
+    translate: Option<AddrTranslator>,
+}
+
+/// Descriptor of split vring.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+pub struct SplitVringDesc {
+    /// Address (guest-physical).
+    pub addr: u64,
+    /// Length.
+    pub len: u32,
+    /// The flags as indicated above.
+    pub flags: u16,
+    /// We chain unused descriptors via this, too.
+    pub next: u16,
+}
+
+impl SplitVringDesc {
+    /// Create a descriptor of split vring.
+    ///
+    /// # Arguments
+    ///
+    /// * `desc_table` - Guest address of virtqueue descriptor table.
+    /// * `queue_size` - Size of virtqueue.
+    /// * `index` - Index of descriptor in the virqueue descriptor table.
+    fn new(desc_table_host: u64, queue_size: u16, index: u16) -> Result<Self> {
+        // `index` comes straight off the avail ring (or, for an indirect
+        // table, off the previous descriptor's `next`/chain walk) and is
+        // guest-controlled over the full `u16` range -- it must be checked
+        // against `queue_size` before it's used to compute an address,
+        // otherwise a guest can force a read up to 64K * DESCRIPTOR_LEN past
+        // the table.
+        if index >= queue_size {
+            return Err(HyperError::VirtioError(VirtioError::Other(format!(
+                "descriptor index {} out of bounds for queue size {}",
+                index, queue_size
+            ))));
+        }
+        let desc_addr = desc_table_host
+            .checked_add(u64::from(index) * DESCRIPTOR_LEN)
+            .ok_or_else(|| {
+                HyperError::VirtioError(VirtioError::AddressOverflow(
+                    "creating a descriptor",
+                    desc_table_host,
+                    u64::from(index) * DESCRIPTOR_LEN,
+                ))
+            })?;
+        // SAFETY: `desc_addr` falls within the descriptor table's cached
+        // host mapping, whose bounds were checked by `is_invalid_memory`
+        // before the vring was marked valid, and `index` is always less
+        // than the table's `queue_size`.
+        let desc = unsafe { *(desc_addr as *const SplitVringDesc) };
+        if !desc.is_valid(queue_size) {
+            return Err(HyperError::VirtioError(VirtioError::Other(format!(
+                "invalid descriptor at index {} in table 0x{:X}",
+                index, desc_table_host
+            ))));
+        }
+        Ok(desc)
+    }
+
+    /// Return true if the descriptor is valid.
+    fn is_valid(&self, queue_size: u16) -> bool {
+        if self.has_next() && self.next >= queue_size {
+            error!(
+                "Invalid descriptor: next {} exceeds queue size {}",
+                self.next, queue_size
+            );
+            return false;
+        }
+        if self.addr.checked_add(u64::from(self.len)).is_none() {
+            error!(
+                "Invalid descriptor: addr 0x{:X} + len {} overflows",
+                self.addr, self.len
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Return true if this descriptor has next descriptor.
+    fn has_next(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_NEXT != 0
+    }
+
+    /// Get the next descriptor in descriptor chain.
+    fn next_desc(desc_table_host: u64, queue_size: u16, index: u16) -> Result<SplitVringDesc> {
+        SplitVringDesc::new(desc_table_host, queue_size, index)
+    }
+
+    /// Check whether this descriptor is write-only or read-only.
+    /// Write-only means that the emulated device can write and the driver can read.
+    fn write_only(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_WRITE != 0
+    }
+
+    /// Return true if this descriptor is a indirect descriptor.
+    fn is_indirect_desc(&self) -> bool {
+        self.flags & VIRTQ_DESC_F_INDIRECT != 0
+    }
+
+    /// Return true if the indirect descriptor is valid.
+    /// The len can be divided evenly by the size of descriptor and can not be zero.
+    fn is_valid_indirect_desc(&self) -> bool {
+        if self.len == 0
+            || u64::from(self.len) % DESCRIPTOR_LEN != 0
+            || u64::from(self.len) / DESCRIPTOR_LEN > u16::MAX as u64
+        {
+            error!("The indirect descriptor is invalid, len: {}", self.len);
+            return false;
+        }
+        if self.has_next() {
+            error!("INDIRECT and NEXT flag should not be used together");
+            return false;
+        }
+        true
+    }
+
+    /// Get the num of descriptor in the table of indirect descriptor.
+    fn get_desc_num(&self) -> u16 {
+        (u64::from(self.len) / DESCRIPTOR_LEN) as u16
+    }
+
+    /// Get element from descriptor chain.
+    ///
+    /// Indirect tables (`VIRTQ_DESC_F_INDIRECT`) are handled transparently
+    /// here: [`DescChainIter`] descends into the secondary table itself, so
+    /// by the time a descriptor reaches this loop it's already one the
+    /// device should splice into `out_iovec`/`in_iovec` directly, whether it
+    /// came from the top-level chain or an indirect one.
+    fn get_element(desc_info: &DescInfo, elem: &mut Element) -> Result<()> {
+        elem.index = desc_info.index;
+
+        let mut iter = DescChainIter::new(desc_info);
+        for desc in &mut iter {
+            let addr = match &desc_info.translate {
+                Some(translate) => translate(desc.addr, u64::from(desc.len))?,
+                None => desc.addr,
+            };
+            let iovec = ElemIovec {
+                addr,
+                len: desc.len,
+            };
+            if desc.write_only() {
+                elem.in_iovec.push(iovec);
+            } else {
+                elem.out_iovec.push(iovec);
+            }
+            elem.desc_num += 1;
+        }
+        if let Some(err) = iter.error {
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+/// Lazily walks a descriptor chain starting from `DescInfo::index`,
+/// following `VIRTQ_DESC_F_NEXT` and descending into `VIRTQ_DESC_F_INDIRECT`
+/// tables, enforcing the same invariants `get_element` used to check by
+/// hand: total length capped at [`DESC_CHAIN_MAX_TOTAL_LEN`], an indirect
+/// table's length divides evenly by [`DESCRIPTOR_LEN`], and NEXT+INDIRECT
+/// never combined on one descriptor. Stops with [`Self::error`] set if a
+/// chain loops past `queue_size` descriptors.
+struct DescChainIter<'a> {
+    desc_info: &'a DescInfo,
+    /// Host address of the table currently being walked -- either the main
+    /// descriptor table, or an indirect table once we've descended into one.
+    table_host: u64,
+    /// Size (descriptor count) of the table currently being walked.
+    table_size: u16,
+    /// Next index to read from `table_host`, or `None` once the chain is
+    /// exhausted or a descriptor failed to validate.
+    next: Option<u16>,
+    /// Descriptors yielded so far, to catch a chain that loops forever.
+    visited: u16,
+    total_len: u64,
+    /// Set once iteration stops early because of an invalid chain.
+    error: Option<HyperError>,
+}
+
+impl<'a> DescChainIter<'a> {
+    fn new(desc_info: &'a DescInfo) -> Self {
+        DescChainIter {
+            desc_info,
+            table_host: desc_info.table_host,
+            table_size: desc_info.size,
+            next: Some(desc_info.index),
+            visited: 0,
+            total_len: 0,
+            error: None,
+        }
+    }
+
+    /// Only the guest-readable (non-`VIRTQ_DESC_F_WRITE`) descriptors.
+    fn readable(self) -> impl Iterator<Item = SplitVringDesc> + 'a {
+        self.filter(|desc| !desc.write_only())
+    }
+
+    /// Only the device-writable (`VIRTQ_DESC_F_WRITE`) descriptors.
+    fn writable(self) -> impl Iterator<Item = SplitVringDesc> + 'a {
+        self.filter(|desc| desc.write_only())
+    }
+
+    fn fail(&mut self, err: HyperError) {
+        self.error = Some(err);
+        self.next = None;
+    }
+}
+
+impl<'a> Iterator for DescChainIter<'a> {
+    type Item = SplitVringDesc;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let index = self.next?;
+            if self.visited >= self.desc_info.size {
+                self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                    "descriptor chain loop exceeds queue size {}",
+                    self.desc_info.size
+                ))));
+                return None;
+            }
+            self.visited += 1;
+
+            let desc = match SplitVringDesc::new(self.table_host, self.table_size, index) {
+                Ok(desc) => desc,
+                Err(e) => {
+                    self.fail(e);
+                    return None;
+                }
+            };
+
+            if desc.is_indirect_desc() {
+                if desc.has_next() {
+                    self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                        "INDIRECT and NEXT flag should not be used together"
+                    ))));
+                    return None;
+                }
+                if !desc.is_valid_indirect_desc() {
+                    self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                        "invalid indirect descriptor, len: {}",
+                        desc.len
+                    ))));
+                    return None;
+                }
+                // Descend into the indirect table: subsequent reads walk it
+                // instead of the outer table, starting back at index 0.
+                // `desc.addr` is guest-supplied and unvalidated, so it must
+                // go through the same GPA-to-HVA translation as every other
+                // descriptor address (see `get_element`) before anything
+                // dereferences it as `table_host`.
+                self.table_host = match &self.desc_info.translate {
+                    Some(translate) => match translate(desc.addr, u64::from(desc.len)) {
+                        Ok(addr) => addr,
+                        Err(e) => {
+                            self.fail(e);
+                            return None;
+                        }
+                    },
+                    None => desc.addr,
+                };
+                self.table_size = desc.get_desc_num();
+                self.next = Some(0);
+                continue;
+            }
+
+            self.total_len += u64::from(desc.len);
+            if self.total_len > DESC_CHAIN_MAX_TOTAL_LEN {
+                self.fail(HyperError::VirtioError(VirtioError::Other(format!(
+                    "descriptor chain total length {} exceeds {}",
+                    self.total_len, DESC_CHAIN_MAX_TOTAL_LEN
+                ))));
+                return None;
+            }
+
+            self.next = if desc.has_next() {
+                Some(desc.next)
+            } else {
+                None
+            };
+
+            return Some(desc);
+        }
+    }
+}
+
+impl ByteCode for SplitVringDesc {}
+
+/// Split vring.
+#[derive(Default, Clone)]
+pub struct SplitVring {
+    /// The configuration of virtqueue.
+    queue_config: QueueConfig,
+}
+
+impl Deref for SplitVring {
+    type Target = QueueConfig;
+    fn deref(&self) -> &Self::Target {
+        &self.queue_config
+    }
+}
+
+impl DerefMut for SplitVring {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.queue_config
+    }
+}
+
+impl SplitVring {
+    /// Create a split vring.
+    ///
+    /// # Arguments
+    ///
+    /// * `queue_config` - Configuration of the vring.
+    pub fn new(queue_config: QueueConfig) -> Self {
+        SplitVring { queue_config }
+    }
+
+    /// The actual size of the queue.
+    fn actual_size(&self) -> u16 {
+        min(self.size, self.max_size)
+    }
+
+    /// Get the flags and idx of the available ring from guest memory.
+    fn get_avail_flags_idx(&self) -> Result<SplitVringFlagsIdx> {
+        let addr = self.addr_cache.avail_ring_host;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        Ok(unsafe { *(addr as *const SplitVringFlagsIdx) })
+    }
+
+    /// Get the idx of the available ring from guest memory.
+    fn get_avail_idx(&self) -> Result<u16> {
+        let flags_idx = self.get_avail_flags_idx()?;
+        Ok(flags_idx.idx)
+    }
+
+    /// Get the flags of the available ring from guest memory.
+    fn get_avail_flags(&self) -> Result<u16> {
+        let flags_idx = self.get_avail_flags_idx()?;
+        Ok(flags_idx.flags)
+    }
+
+    /// Get the flags and idx of the used ring from guest memory.
+    fn get_used_flags_idx(&self) -> Result<SplitVringFlagsIdx> {
+        let addr = self.addr_cache.used_ring_host;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        Ok(unsafe { *(addr as *const SplitVringFlagsIdx) })
+    }
+
+    /// Get the index of the used ring from guest memory.
+    fn get_used_idx(&self) -> Result<u16> {
+        let flag_idx = self.get_used_flags_idx()?;
+        Ok(flag_idx.idx)
+    }
+
+    /// Set the used flags to suppress virtqueue notification or not
+    fn set_used_flags(&self, suppress: bool) -> Result<()> {
+        let mut flags_idx = self.get_used_flags_idx()?;
+
+        if suppress {
+            flags_idx.flags |= VRING_USED_F_NO_NOTIFY;
+        } else {
+            flags_idx.flags &= !VRING_USED_F_NO_NOTIFY;
+        }
+
+        let flags_addr = self.addr_cache.used_ring_host;
+        // SAFETY: `flags` is the first field of `SplitVringFlagsIdx`, at
+        // offset 0 of the used ring, whose bounds were checked by
+        // `is_invalid_memory`.
+        unsafe { *(flags_addr as *mut u16) = flags_idx.flags };
+        Ok(())
+    }
+
+    /// Set the avail idx to the field of the event index for the available ring.
+    fn set_avail_event(&self, event_idx: u16) -> Result<()> {
+        let avail_event_offset =
+            VRING_FLAGS_AND_IDX_LEN + USEDELEM_LEN * u64::from(self.actual_size());
+        let avail_event_addr = self.addr_cache.used_ring_host + avail_event_offset;
+        // SAFETY: the used ring's cached size accounts for this trailing
+        // `avail_event` field whenever `VIRTIO_F_RING_EVENT_IDX` is
+        // negotiated, checked by `is_invalid_memory`.
+        unsafe { *(avail_event_addr as *mut u16) = event_idx };
+        Ok(())
+    }
+
+    /// Get the event index of the used ring from guest memory.
+    fn get_used_event(&self) -> Result<u16> {
+        let used_event_addr = self.addr_cache.avail_ring_host
+            + VRING_FLAGS_AND_IDX_LEN
+            + AVAILELEM_LEN * u64::from(self.actual_size());
+        // SAFETY: the avail ring's cached size accounts for this trailing
+        // `used_event` field whenever `VIRTIO_F_RING_EVENT_IDX` is
+        // negotiated, checked by `is_invalid_memory`.
+        Ok(unsafe { *(used_event_addr as *const u16) })
+    }
+
+    /// Return true if VRING_AVAIL_F_NO_INTERRUPT is set.
+    fn is_avail_ring_no_interrupt(&self) -> bool {
+        self.get_avail_flags()
+            .map(|flags| flags & VRING_AVAIL_F_NO_INTERRUPT != 0)
+            .unwrap_or(false)
+    }
+
+    /// Return true if it's required to trigger interrupt for the used
+    /// vring, per the `VIRTIO_F_RING_EVENT_IDX` algorithm: only notify if
+    /// the event index the driver last published (`used_event`) falls
+    /// within the range of used entries filled since the last notification.
+    fn used_ring_need_event(&mut self) -> bool {
+        let Ok(new_idx) = self.get_used_idx().map(Wrapping) else {
+            return true;
+        };
+        if !self.signal_used_valid {
+            self.signal_used_valid = true;
+            self.last_signal_used = new_idx;
+            return true;
+        }
+        let Ok(used_event) = self.get_used_event().map(Wrapping) else {
+            return true;
+        };
+        let old_idx = self.last_signal_used;
+        self.last_signal_used = new_idx;
+        (new_idx - used_event - Wrapping(1)) < (new_idx - old_idx)
+    }
+
+    fn is_overlap(start1: u64, end1: u64, start2: u64, end2: u64) -> bool {
+        !(start1 >= end2 || start2 >= end1)
+    }
+
+    fn is_invalid_memory(&self, actual_size: u64) -> bool {
+        if actual_size == 0 {
+            error!("Invalid queue size: 0");
+            return true;
+        }
+
+        let desc_table = self.addr_cache.desc_table_host;
+        let avail_ring = self.addr_cache.avail_ring_host;
+        let used_ring = self.addr_cache.used_ring_host;
+
+        let desc_size = self.get_desc_size();
+        let avail_size = self.get_avail_size(0);
+        let used_size = self.get_used_size(0);
+
+        let (Some(desc_end), Some(avail_end), Some(used_end)) = (
+            desc_table.checked_add(desc_size),
+            avail_ring.checked_add(avail_size),
+            used_ring.checked_add(used_size),
+        ) else {
+            error!("The GPA of descriptor table, available ring or used ring overflows");
+            return true;
+        };
+
+        if Self::is_overlap(desc_table, desc_end, avail_ring, avail_end)
+            || Self::is_overlap(desc_table, desc_end, used_ring, used_end)
+            || Self::is_overlap(avail_ring, avail_end, used_ring, used_end)
+        {
+            error!("The descriptor table, available ring and used ring overlap with each other");
+            return true;
+        }
+
+        false
+    }
+
+    fn get_desc_info(&mut self, next_avail: Wrapping<u16>, features: u64) -> Result<DescInfo> {
+        let index_offset =
+            VRING_FLAGS_AND_IDX_LEN + AVAILELEM_LEN * u64::from(next_avail.0 % self.actual_size());
+        // The GPA of avail_ring_host with avail table length has been checked in
+        // is_invalid_memory which must not be overflowed.
+        let desc_index_addr = self.addr_cache.avail_ring_host + index_offset;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        let desc_index = unsafe { *(desc_index_addr as *const u16) };
+
+        // Validate the head descriptor's address up front, same as before.
+        SplitVringDesc::new(
+            self.addr_cache.desc_table_host,
+            self.actual_size(),
+            desc_index,
+        )?;
+
+        // Suppress queue notification related to current processing desc chain.
+        if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
+            self.set_avail_event((next_avail + Wrapping(1)).0)
+                .or_else(|_| {
+                    Err(HyperError::VirtioError(VirtioError::Other(format!(
+                        "Failed to set avail event for popping avail ring"
+                    ))))
+                })?;
+        }
+
+        Ok(DescInfo {
+            table_host: self.addr_cache.desc_table_host,
+            size: self.actual_size(),
+            index: desc_index,
+            translate: self.translate.clone(),
+        })
+    }
+
+    /// Build a [`DescInfo`] for the chain head at avail-ring position
+    /// `avail_pos`, without any of `get_desc_info`'s EVENT_IDX
+    /// side-effects -- used by [`Self::get_avail_bytes`], which must only
+    /// peek at queued chains, not mutate ring state the way popping one
+    /// does.
+    fn peek_desc_info(&self, avail_pos: Wrapping<u16>) -> Result<DescInfo> {
+        let index_offset =
+            VRING_FLAGS_AND_IDX_LEN + AVAILELEM_LEN * u64::from(avail_pos.0 % self.actual_size());
+        let desc_index_addr = self.addr_cache.avail_ring_host + index_offset;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        let desc_index = unsafe { *(desc_index_addr as *const u16) };
+
+        SplitVringDesc::new(
+            self.addr_cache.desc_table_host,
+            self.actual_size(),
+            desc_index,
+        )?;
+
+        Ok(DescInfo {
+            table_host: self.addr_cache.desc_table_host,
+            size: self.actual_size(),
+            index: desc_index,
+            translate: self.translate.clone(),
+        })
+    }
+
+    fn get_vring_element(&mut self, features: u64, elem: &mut Element) -> Result<()> {
+        let desc_info = self.get_desc_info(self.next_avail, features)?;
+
+        SplitVringDesc::get_element(&desc_info, elem).or_else(|_| {
+            Err(HyperError::VirtioError(VirtioError::Other(format!(
+                "Failed to get element from descriptor chain {}, table addr: 0x{:X}, size: {}",
+                desc_info.index, desc_info.table_host, desc_info.size,
+            ))))
+        })?;
+        self.next_avail += Wrapping(1);
+
+        Ok(())
+    }
+}
+
+impl VringOps for SplitVring {
+    fn is_enabled(&self) -> bool {
+        self.ready
+    }
+
+    fn is_valid(&self) -> bool {
+        let size = u64::from(self.actual_size());
+        if !self.ready {
+            error!("The configuration of vring is not ready\n");
+            false
+        } else if self.size > self.max_size || self.size == 0 || (self.size & (self.size - 1)) != 0
+        {
+            error!(
+                "vring with invalid size:{} max size:{}",
+                self.size, self.max_size
+            );
+            false
+        } else {
+            !self.is_invalid_memory(size)
+        }
+    }
+
+    fn pop_avail(&mut self, features: u64) -> Result<Element> {
+        let mut element = Element::new(0);
+        if self.avail_ring_len()? == 0 {
+            return Ok(element);
+        }
+        self.get_vring_element(features, &mut element)?;
+        Ok(element)
+    }
+
+    fn push_back(&mut self) {
+        self.next_avail -= Wrapping(1);
+    }
+
+    fn add_used(&mut self, index: u16, len: u32) -> Result<()> {
+        let used_elem = UsedElem {
+            id: u32::from(index),
+            len,
+        };
+        let used_elem_offset = VRING_FLAGS_AND_IDX_LEN
+            + USEDELEM_LEN * u64::from(self.next_used.0 % self.actual_size());
+        let used_elem_addr = self.addr_cache.used_ring_host + used_elem_offset;
+        // SAFETY: bounds checked by `is_invalid_memory` before the vring
+        // was marked valid.
+        unsafe { *(used_elem_addr as *mut UsedElem) = used_elem };
+
+        self.next_used += Wrapping(1);
+
+        // Make sure the used element write above is observed before the
+        // driver sees the bumped `idx`, so it never reads a used entry
+        // that hasn't landed yet.
+        fence(Ordering::Release);
+
+        let idx_addr = self.addr_cache.used_ring_host + VRING_IDX_POSITION;
+        // SAFETY: same as above.
+        unsafe { *(idx_addr as *mut u16) = self.next_used.0 };
+
+        Ok(())
+    }
+
+    /// Already implements the full `VIRTIO_RING_F_EVENT_IDX` path: see
+    /// [`Self::used_ring_need_event`] for the `used_event` threshold check
+    /// this delegates to, and [`Self::set_avail_event`]'s call site in
+    /// [`Self::suppress_queue_notify`] for the matching `avail_event`
+    /// write; `VRING_AVAIL_F_NO_INTERRUPT` below remains the fallback when
+    /// the feature isn't negotiated.
+    fn should_notify(&mut self, features: u64) -> bool {
+        if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
+            self.used_ring_need_event()
+        } else {
+            !self.is_avail_ring_no_interrupt()
+        }
+    }
+
+    fn suppress_queue_notify(&mut self, features: u64, suppress: bool) -> Result<()> {
+        if virtio_has_feature(features, VIRTIO_F_RING_EVENT_IDX) {
+            let avail_idx = self.get_avail_idx()?;
+            self.set_avail_event(avail_idx)
+        } else {
+            self.set_used_flags(suppress)
+        }
+    }
+
+    fn actual_size(&self) -> u16 {
+        self.actual_size()
+    }
+
+    fn get_queue_config(&self) -> QueueConfig {
+        let mut config = self.queue_config.clone();
+        config.signal_used_valid = false;
+        config
+    }
+
+    /// The number of descriptor chains in the available ring.
+    fn avail_ring_len(&mut self) -> Result<u16> {
+        let avail_idx = self.get_avail_idx().map(Wrapping)?;
+
+        Ok((avail_idx - self.next_avail).0)
+    }
+
+    fn get_avail_idx(&self) -> Result<u16> {
+        SplitVring::get_avail_idx(self)
+    }
+
+    fn get_used_idx(&self) -> Result<u16> {
+        SplitVring::get_used_idx(self)
+    }
+
+    fn get_cache(&self) -> &Option<u32> {
+        &None
+    }
+
+    fn get_avail_bytes(&mut self, max_size: usize, is_in: bool) -> Result<usize> {
+        let avail_idx = Wrapping(self.get_avail_idx()?);
+        let mut pos = self.next_avail;
+        let mut total: usize = 0;
+
+        while pos != avail_idx && total < max_size {
+            let desc_info = self.peek_desc_info(pos)?;
+            let mut iter = DescChainIter::new(&desc_info);
+            for desc in &mut iter {
+                if desc.write_only() == is_in {
+                    total += desc.len as usize;
+                    if total >= max_size {
+                        break;
+                    }
+                }
+            }
+            if let Some(err) = iter.error {
+                return Err(err);
+            }
+            pos += Wrapping(1);
+        }
+
+        Ok(total)
+    }
+}
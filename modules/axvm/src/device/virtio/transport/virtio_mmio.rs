@@ -0,0 +1,535 @@
+//! Virtio-over-MMIO transport, for guests that describe their virtio devices
+//! via a device-tree/ACPI MMIO node instead of discovering them on a PCI bus
+//! - the role `transport::virtio_pci::VirtioPciDevice` plays for a
+//! PCI-enumerated guest. Register layout and semantics follow the same
+//! `read_common_config`/`write_common_config`/`activate_device` shape
+//! `virtio_pci.rs` uses, just against the flatter virtio-mmio register block
+//! instead of a PCI capability list.
+//!
+//! Each instance is fixed at construction to either the modern (version 2,
+//! VirtIO 1.1 spec Sec. 4.2.2) or legacy (version 1, VirtIO 1.1 spec Sec.
+//! 4.2.3 / the pre-1.0 draft) register set, the same way real hardware -
+//! and QEMU's own `-device virtio-mmio,legacy=on|off` - picks one at
+//! creation time rather than a single device flipping between the two; a
+//! driver tells which it's talking to purely by what it reads back from
+//! `Version`. The two sets share every register except how a queue's
+//! guest-physical addresses get programmed: modern splits them into
+//! `QueueDesc`/`QueueDriver`/`QueueDevice` plus a `QueueReady` flag (handled
+//! below already), while legacy packs descriptor table, available ring and
+//! used ring into one guest-page-aligned region addressed by a single
+//! `QueuePFN`, laid out per `GuestPageSize` and `QueueAlign` (VirtIO 1.1
+//! spec Sec. 2.6.2) - `queue_pfn_addrs` below is exactly that layout
+//! calculation. `FEATURES_OK`'s `VIRTIO_F_VERSION_1` requirement is modern
+//! only: a legacy driver has no notion of the bit at all.
+//!
+//! This device has no MSI-X, so it signals completions through a single
+//! level-triggered line (reusing [`super::virtio_pci::IntxLine`], which is
+//! exactly that abstraction already) instead of
+//! `transport::virtio_pci::MsixNotifier`.
+
+use alloc::format;
+use alloc::sync::Arc;
+use core::sync::atomic::Ordering;
+
+use hypercraft::{HyperError, HyperResult, MmioOps, VirtioError};
+
+use super::virtio_pci::IntxLine;
+use crate::device::virtio::{
+    virtio_has_feature, AddrTranslator, Queue, VirtioDevice, VirtioInterrupt, VirtioInterruptType,
+    CONFIG_STATUS_ACKNOWLEDGE, CONFIG_STATUS_DRIVER, CONFIG_STATUS_DRIVER_OK,
+    CONFIG_STATUS_FAILED, CONFIG_STATUS_FEATURES_OK, CONFIG_STATUS_NEEDS_RESET,
+    QUEUE_TYPE_PACKED_VRING, QUEUE_TYPE_SPLIT_VRING, VIRTIO_F_RING_PACKED, VIRTIO_F_VERSION_1,
+    VIRTIO_MMIO_INT_CONFIG, VIRTIO_MMIO_INT_VRING, VIRTIO_TYPE_BLOCK, VIRTIO_TYPE_CONSOLE,
+    VIRTIO_TYPE_FS, VIRTIO_TYPE_GPU, VIRTIO_TYPE_NET, VIRTIO_TYPE_SCSI,
+};
+use spin::Mutex;
+
+const MAX_FEATURES_SELECT_NUM: u32 = 2;
+
+const MMIO_MAGIC_VALUE: u64 = 0x000;
+const MMIO_VERSION: u64 = 0x004;
+const MMIO_DEVICE_ID: u64 = 0x008;
+const MMIO_VENDOR_ID: u64 = 0x00c;
+const MMIO_DEVICE_FEATURES: u64 = 0x010;
+const MMIO_DEVICE_FEATURES_SEL: u64 = 0x014;
+const MMIO_DRIVER_FEATURES: u64 = 0x020;
+const MMIO_DRIVER_FEATURES_SEL: u64 = 0x024;
+const MMIO_QUEUE_SEL: u64 = 0x030;
+const MMIO_QUEUE_NUM_MAX: u64 = 0x034;
+const MMIO_QUEUE_NUM: u64 = 0x038;
+/// Legacy-only: alignment (in bytes) the used ring must be padded to,
+/// relative to the end of the available ring.
+const MMIO_QUEUE_ALIGN: u64 = 0x03c;
+const MMIO_QUEUE_READY: u64 = 0x044;
+const MMIO_QUEUE_NOTIFY: u64 = 0x050;
+/// Legacy-only: guest page size in bytes, used to turn `QueuePFN` into a
+/// guest-physical address.
+const MMIO_GUEST_PAGE_SIZE: u64 = 0x028;
+/// Legacy-only: guest-physical page number of the queue's single packed
+/// descriptor-table/avail-ring/used-ring region. Write-0 tears the queue
+/// down, matching `QueueReady = 0` in the modern interface.
+const MMIO_QUEUE_PFN: u64 = 0x040;
+const MMIO_INTERRUPT_STATUS: u64 = 0x060;
+const MMIO_INTERRUPT_ACK: u64 = 0x064;
+const MMIO_STATUS: u64 = 0x070;
+const MMIO_QUEUE_DESC_LOW: u64 = 0x080;
+const MMIO_QUEUE_DESC_HIGH: u64 = 0x084;
+const MMIO_QUEUE_DRIVER_LOW: u64 = 0x090;
+const MMIO_QUEUE_DRIVER_HIGH: u64 = 0x094;
+const MMIO_QUEUE_DEVICE_LOW: u64 = 0x0a0;
+const MMIO_QUEUE_DEVICE_HIGH: u64 = 0x0a4;
+const MMIO_CONFIG_GENERATION: u64 = 0x0fc;
+const MMIO_CONFIG: u64 = 0x100;
+
+const VIRTIO_MMIO_MAGIC: u32 = 0x7472_6976; // "virt"
+const VIRTIO_MMIO_VERSION_LEGACY: u32 = 1;
+const VIRTIO_MMIO_VERSION_MODERN: u32 = 2;
+const VIRTIO_MMIO_VENDOR_ID: u32 = 0x554d_4551; // "QEMU", same vendor id virtio_pci.rs borrows
+
+/// Size of the trapped register window, big enough for the header plus a
+/// generous device-config tail; devices with a larger config space (e.g.
+/// virtio-gpu) would need a wider window, not relevant to the device types
+/// wired up so far ([`VIRTIO_TYPE_NET`]/[`VIRTIO_TYPE_BLOCK`]).
+const MMIO_REGION_LEN: u64 = 0x200;
+
+/// The virtio-mmio device id a given [`VirtioDevice::device_type`] maps to in
+/// the `DeviceID` register - same type space `virtio_pci.rs`'s
+/// `VIRTIO_PCI_DEVICE_ID_BASE + device_type` offset draws from.
+fn mmio_device_id(device_type: u32) -> u32 {
+    match device_type {
+        VIRTIO_TYPE_NET => 1,
+        VIRTIO_TYPE_BLOCK => 2,
+        VIRTIO_TYPE_CONSOLE => 3,
+        VIRTIO_TYPE_FS => 26,
+        VIRTIO_TYPE_GPU => 16,
+        VIRTIO_TYPE_SCSI => 8,
+        other => other,
+    }
+}
+
+/// A single virtio device bound to one virtio-mmio register window.
+///
+/// `notify_queue` is injected rather than this type reaching into a concrete
+/// `VirtioBlk`/`VirtioNet` to drive its own `process_queue`, the same
+/// indirection `device::block::GuestAddrTranslator` uses to keep
+/// `VirtioDevice` implementations decoupled from whatever wires them up -
+/// the caller constructing this transport already has the concrete device
+/// handle (see `NimbosVmDevices::new`) and can close over its own
+/// `process_queue` directly.
+pub struct VirtioMmioDevice {
+    mmio_base: u64,
+    device: Arc<Mutex<dyn VirtioDevice>>,
+    notify_queue: Arc<dyn Fn(usize) -> HyperResult<()> + Send + Sync>,
+    interrupt_cb: Option<Arc<VirtioInterrupt>>,
+    intx: IntxLine,
+    device_features_sel: u32,
+    driver_features_sel: u32,
+    /// Fixed at construction, per the module doc: `true` speaks the legacy
+    /// (version 1) register set, `false` the modern (version 2) one.
+    legacy: bool,
+    /// Legacy-only: set via `GuestPageSize`, used to resolve `QueuePFN`.
+    guest_page_size: u32,
+    /// Legacy-only: set via `QueueAlign`, used to pad the used ring's start.
+    queue_align: u32,
+    /// GPA-to-HVA translator passed to `QueueConfig::set_addr_cache` at
+    /// activate time. `None` leaves the queue's rings cached as raw,
+    /// untranslated GPAs. Set with [`Self::set_addr_translator`] before
+    /// [`Self::realize`].
+    translate: Option<AddrTranslator>,
+}
+
+impl VirtioMmioDevice {
+    pub fn new(
+        mmio_base: u64,
+        device: Arc<Mutex<dyn VirtioDevice>>,
+        notify_queue: Arc<dyn Fn(usize) -> HyperResult<()> + Send + Sync>,
+        intx: IntxLine,
+        legacy: bool,
+    ) -> Self {
+        Self {
+            mmio_base,
+            device,
+            notify_queue,
+            interrupt_cb: None,
+            intx,
+            device_features_sel: 0,
+            driver_features_sel: 0,
+            legacy,
+            guest_page_size: 0,
+            queue_align: 0,
+            translate: None,
+        }
+    }
+
+    /// Legacy `QueuePFN` write: lay out descriptor table, available ring
+    /// and used ring contiguously from `pfn * guest_page_size`, per VirtIO
+    /// 1.1 spec Sec. 2.6.2. A PFN of 0 tears the queue down instead.
+    fn set_queue_pfn(&self, pfn: u32) -> HyperResult<()> {
+        let mut locked_device = self.device.lock();
+        let queue_size = locked_device.queue_config()?.size as u64;
+        let config = locked_device.queue_config_mut(true)?;
+        if pfn == 0 {
+            config.ready = false;
+            return Ok(());
+        }
+        let desc_table = u64::from(pfn) * u64::from(self.guest_page_size);
+        let avail_ring = desc_table + 16 * queue_size;
+        let used_ring_unaligned = avail_ring + 6 + 2 * queue_size;
+        let align = u64::from(self.queue_align).max(1);
+        let used_ring = (used_ring_unaligned + align - 1) / align * align;
+        config.desc_table = desc_table;
+        config.avail_ring = avail_ring;
+        config.used_ring = used_ring;
+        config.ready = true;
+        Ok(())
+    }
+
+    /// Legacy `QueuePFN` read: the inverse of [`Self::set_queue_pfn`].
+    fn get_queue_pfn(&self) -> u32 {
+        let Ok(config) = self.device.lock().queue_config() else {
+            return 0;
+        };
+        if !config.ready || self.guest_page_size == 0 {
+            return 0;
+        }
+        (config.desc_table / u64::from(self.guest_page_size)) as u32
+    }
+
+    /// Install this device's interrupt closure: set the `InterruptStatus`
+    /// bit and assert the (single, legacy-style) level line, mirroring
+    /// `virtio_pci.rs::assign_interrupt_cb`'s non-MSI-X fallback branch.
+    fn assign_interrupt_cb(dev: &Arc<Mutex<Self>>) {
+        let (virtio_device, intx) = {
+            let locked = dev.lock();
+            (locked.device.clone(), locked.intx.clone())
+        };
+
+        let locked_dev = virtio_device.lock();
+        let virtio_base = locked_dev.virtio_base();
+        let device_status = virtio_base.device_status.clone();
+        let interrupt_status = virtio_base.interrupt_status.clone();
+        let config_generation = virtio_base.config_generation.clone();
+        drop(locked_dev);
+
+        let cb = Arc::new(Box::new(
+            move |int_type: &VirtioInterruptType, _queue: Option<&Queue>, needs_reset: bool| {
+                match int_type {
+                    VirtioInterruptType::Config => {
+                        if needs_reset {
+                            device_status.fetch_or(CONFIG_STATUS_NEEDS_RESET, Ordering::SeqCst);
+                        }
+                        if device_status.load(Ordering::Acquire) & CONFIG_STATUS_DRIVER_OK == 0 {
+                            return Ok(());
+                        }
+                        interrupt_status.fetch_or(
+                            VIRTIO_MMIO_INT_CONFIG | VIRTIO_MMIO_INT_VRING,
+                            Ordering::SeqCst,
+                        );
+                        config_generation.fetch_add(1, Ordering::SeqCst);
+                    }
+                    VirtioInterruptType::Vring => {
+                        interrupt_status.fetch_or(VIRTIO_MMIO_INT_VRING, Ordering::SeqCst);
+                    }
+                }
+                intx.trigger();
+                Ok(())
+            },
+        ) as VirtioInterrupt);
+
+        dev.lock().interrupt_cb = Some(cb);
+    }
+
+    /// Install the GPA-to-HVA translator `activate_device()` hands every
+    /// queue's `set_addr_cache`. Without this, a queue's descriptor table/
+    /// avail ring/used ring stay cached as the raw guest-physical addresses
+    /// the driver programmed, which every consumer then dereferences as if
+    /// they were host pointers - must be called before [`Self::activate_device`]
+    /// runs, i.e. before the guest drives the device to `DRIVER_OK`.
+    pub fn set_addr_translator(&mut self, translate: AddrTranslator) {
+        self.translate = Some(translate);
+    }
+
+    /// Wrap `device` in a [`VirtioMmioDevice`], wire up its interrupt
+    /// closure, and return it ready to [`crate::device::x86_64::DeviceList::add_memory_io_device`].
+    pub fn realize(
+        mmio_base: u64,
+        device: Arc<Mutex<dyn VirtioDevice>>,
+        notify_queue: Arc<dyn Fn(usize) -> HyperResult<()> + Send + Sync>,
+        intx: IntxLine,
+        legacy: bool,
+    ) -> Arc<Mutex<Self>> {
+        let dev = Arc::new(Mutex::new(Self::new(
+            mmio_base,
+            device,
+            notify_queue,
+            intx,
+            legacy,
+        )));
+        Self::assign_interrupt_cb(&dev);
+        dev
+    }
+
+    fn activate_device(&self) -> bool {
+        let mut locked_dev = self.device.lock();
+        if locked_dev.device_activated() {
+            return true;
+        }
+
+        let expected_queue_num = locked_dev.queue_num();
+        let ready_queue_num = locked_dev
+            .virtio_base()
+            .queues_config
+            .iter()
+            .filter(|q| q.ready)
+            .count();
+        if ready_queue_num != expected_queue_num {
+            error!(
+                "{}",
+                VirtioError::IncorrectQueueNum(expected_queue_num, ready_queue_num)
+            );
+            return false;
+        }
+
+        let queue_type = locked_dev.queue_type();
+        let features = locked_dev.virtio_base().driver_features;
+        let broken = locked_dev.virtio_base().broken.clone();
+
+        let mut queues = alloc::vec::Vec::new();
+        let queues_config = &mut locked_dev.virtio_base_mut().queues_config;
+        for q_config in queues_config.iter_mut() {
+            if !q_config.ready {
+                debug!("queue is not ready, please check your init process");
+            } else {
+                q_config.set_addr_cache(
+                    self.interrupt_cb.clone().unwrap(),
+                    features,
+                    &broken,
+                    self.translate.clone(),
+                );
+            }
+            let queue = Queue::new(q_config.clone(), queue_type).unwrap();
+            if q_config.ready && !queue.is_valid() {
+                error!("Failed to activate device: Invalid queue");
+                return false;
+            }
+            queues.push(Arc::new(Mutex::new(queue)));
+        }
+        locked_dev.virtio_base_mut().queues = queues;
+
+        if let Err(e) = locked_dev.activate(self.interrupt_cb.clone().unwrap()) {
+            error!("Failed to activate device, error is {:?}", e);
+            return false;
+        }
+
+        locked_dev.set_device_activated(true);
+        true
+    }
+
+    fn deactivate_device(&self) -> bool {
+        let mut locked_dev = self.device.lock();
+        if locked_dev.device_activated() {
+            if let Err(e) = locked_dev.deactivate() {
+                error!("Failed to deactivate virtio device, error is {:?}", e);
+                return false;
+            }
+            locked_dev.virtio_base_mut().reset();
+        }
+        true
+    }
+
+    fn read_register(&self, offset: u64) -> HyperResult<u32> {
+        let locked_device = self.device.lock();
+        let value = match offset {
+            MMIO_MAGIC_VALUE => VIRTIO_MMIO_MAGIC,
+            MMIO_VERSION => {
+                if self.legacy {
+                    VIRTIO_MMIO_VERSION_LEGACY
+                } else {
+                    VIRTIO_MMIO_VERSION_MODERN
+                }
+            }
+            MMIO_DEVICE_ID => mmio_device_id(locked_device.device_type()),
+            MMIO_VENDOR_ID => VIRTIO_MMIO_VENDOR_ID,
+            MMIO_DEVICE_FEATURES => {
+                if self.device_features_sel < MAX_FEATURES_SELECT_NUM {
+                    locked_device.device_features(self.device_features_sel)
+                } else {
+                    0
+                }
+            }
+            MMIO_QUEUE_NUM_MAX => locked_device.queue_size_max() as u32,
+            MMIO_QUEUE_PFN if self.legacy => {
+                drop(locked_device);
+                return Ok(self.get_queue_pfn());
+            }
+            MMIO_QUEUE_READY if !self.legacy => locked_device
+                .queue_config()
+                .map(|config| u32::from(config.ready))
+                .unwrap_or(0),
+            MMIO_INTERRUPT_STATUS => locked_device.interrupt_status(),
+            MMIO_STATUS => locked_device.device_status(),
+            MMIO_CONFIG_GENERATION => locked_device.config_generation() as u32,
+            MMIO_QUEUE_DESC_LOW => locked_device
+                .queue_config()
+                .map(|config| config.desc_table as u32)
+                .unwrap_or(0),
+            MMIO_QUEUE_DESC_HIGH => locked_device
+                .queue_config()
+                .map(|config| (config.desc_table >> 32) as u32)
+                .unwrap_or(0),
+            MMIO_QUEUE_DRIVER_LOW => locked_device
+                .queue_config()
+                .map(|config| config.avail_ring as u32)
+                .unwrap_or(0),
+            MMIO_QUEUE_DRIVER_HIGH => locked_device
+                .queue_config()
+                .map(|config| (config.avail_ring >> 32) as u32)
+                .unwrap_or(0),
+            MMIO_QUEUE_DEVICE_LOW => locked_device
+                .queue_config()
+                .map(|config| config.used_ring as u32)
+                .unwrap_or(0),
+            MMIO_QUEUE_DEVICE_HIGH => locked_device
+                .queue_config()
+                .map(|config| (config.used_ring >> 32) as u32)
+                .unwrap_or(0),
+            off if off >= MMIO_CONFIG => {
+                drop(locked_device);
+                let mut buf = [0u8; 4];
+                self.device.lock().read_config(off - MMIO_CONFIG, &mut buf)?;
+                u32::from_le_bytes(buf)
+            }
+            _ => 0,
+        };
+        Ok(value)
+    }
+
+    fn write_register(&mut self, offset: u64, value: u32) -> HyperResult<()> {
+        match offset {
+            MMIO_DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            MMIO_DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            MMIO_DRIVER_FEATURES => {
+                let mut locked_device = self.device.lock();
+                if locked_device.device_status() & CONFIG_STATUS_FEATURES_OK != 0 {
+                    error!("it's not allowed to set features after having been negotiated");
+                    return Ok(());
+                }
+                if self.driver_features_sel >= MAX_FEATURES_SELECT_NUM {
+                    return Ok(());
+                }
+                locked_device.set_driver_features(self.driver_features_sel, value);
+
+                if self.driver_features_sel == 1 {
+                    let features = (locked_device.driver_features(1) as u64) << 32;
+                    if virtio_has_feature(features, VIRTIO_F_RING_PACKED) {
+                        locked_device.set_queue_type(QUEUE_TYPE_PACKED_VRING);
+                    } else {
+                        locked_device.set_queue_type(QUEUE_TYPE_SPLIT_VRING);
+                    }
+                }
+            }
+            MMIO_QUEUE_SEL => {
+                self.device.lock().set_queue_select(value as u16);
+            }
+            MMIO_QUEUE_NUM => {
+                self.device
+                    .lock()
+                    .queue_config_mut(true)
+                    .map(|config| config.size = value as u16)?;
+            }
+            MMIO_GUEST_PAGE_SIZE if self.legacy => self.guest_page_size = value,
+            MMIO_QUEUE_ALIGN if self.legacy => self.queue_align = value,
+            MMIO_QUEUE_PFN if self.legacy => self.set_queue_pfn(value)?,
+            MMIO_QUEUE_READY if !self.legacy => {
+                self.device
+                    .lock()
+                    .queue_config_mut(true)
+                    .map(|config| config.ready = value != 0)?;
+            }
+            MMIO_QUEUE_NOTIFY => {
+                (self.notify_queue)(value as usize)?;
+            }
+            MMIO_INTERRUPT_ACK => {
+                let mut locked_device = self.device.lock();
+                let pending = locked_device.interrupt_status() & !value;
+                locked_device.set_interrupt_status(pending);
+                drop(locked_device);
+                self.intx.notify_resample(pending != 0);
+            }
+            MMIO_STATUS => {
+                let mut locked_device = self.device.lock();
+                if !self.legacy
+                    && value & CONFIG_STATUS_FEATURES_OK != 0
+                    && value & CONFIG_STATUS_DRIVER_OK == 0
+                {
+                    let features = (locked_device.driver_features(1) as u64) << 32;
+                    if !virtio_has_feature(features, VIRTIO_F_VERSION_1) {
+                        error!(
+                            "Device is modern only, but the driver not support VIRTIO_F_VERSION_1"
+                        );
+                        return Err(HyperError::VirtioError(VirtioError::DevStatErr(value)));
+                    }
+                }
+                if value != 0 && (locked_device.device_status() & !value) != 0 {
+                    error!("Driver must not clear a device status bit");
+                    return Err(HyperError::VirtioError(VirtioError::DevStatErr(value)));
+                }
+
+                let old_status = locked_device.device_status();
+                locked_device.set_device_status(value);
+                if locked_device.check_device_status(
+                    CONFIG_STATUS_ACKNOWLEDGE
+                        | CONFIG_STATUS_DRIVER
+                        | CONFIG_STATUS_DRIVER_OK
+                        | CONFIG_STATUS_FEATURES_OK,
+                    CONFIG_STATUS_FAILED,
+                ) {
+                    drop(locked_device);
+                    self.activate_device();
+                } else if old_status != 0 && locked_device.device_status() == 0 {
+                    drop(locked_device);
+                    self.deactivate_device();
+                }
+            }
+            MMIO_QUEUE_DESC_LOW => self.device.lock().queue_config_mut(true).map(|config| {
+                config.desc_table = (config.desc_table & !0xffff_ffff) | u64::from(value);
+            })?,
+            MMIO_QUEUE_DESC_HIGH => self.device.lock().queue_config_mut(true).map(|config| {
+                config.desc_table = (config.desc_table & 0xffff_ffff) | (u64::from(value) << 32);
+            })?,
+            MMIO_QUEUE_DRIVER_LOW => self.device.lock().queue_config_mut(true).map(|config| {
+                config.avail_ring = (config.avail_ring & !0xffff_ffff) | u64::from(value);
+            })?,
+            MMIO_QUEUE_DRIVER_HIGH => self.device.lock().queue_config_mut(true).map(|config| {
+                config.avail_ring = (config.avail_ring & 0xffff_ffff) | (u64::from(value) << 32);
+            })?,
+            MMIO_QUEUE_DEVICE_LOW => self.device.lock().queue_config_mut(true).map(|config| {
+                config.used_ring = (config.used_ring & !0xffff_ffff) | u64::from(value);
+            })?,
+            MMIO_QUEUE_DEVICE_HIGH => self.device.lock().queue_config_mut(true).map(|config| {
+                config.used_ring = (config.used_ring & 0xffff_ffff) | (u64::from(value) << 32);
+            })?,
+            off if off >= MMIO_CONFIG => {
+                self.device
+                    .lock()
+                    .write_config(off - MMIO_CONFIG, &value.to_le_bytes())?;
+            }
+            _ => return Err(HyperError::VirtioError(VirtioError::MmioRegErr(offset))),
+        }
+        Ok(())
+    }
+}
+
+impl MmioOps for VirtioMmioDevice {
+    fn mmio_range(&self) -> core::ops::Range<u64> {
+        self.mmio_base..self.mmio_base + MMIO_REGION_LEN
+    }
+
+    fn read(&mut self, addr: u64, _access_size: u8) -> HyperResult<u64> {
+        self.read_register(addr - self.mmio_base).map(|v| v as u64)
+    }
+
+    fn write(&mut self, addr: u64, _access_size: u8, value: u64) -> HyperResult {
+        self.write_register(addr - self.mmio_base, value as u32)
+    }
+}
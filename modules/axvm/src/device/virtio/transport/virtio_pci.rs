@@ -7,7 +7,7 @@ use alloc::vec::Vec;
 use core::any::Any;
 use core::cmp::{max, min};
 use core::mem::size_of;
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicU16, AtomicU32, Ordering};
 use lazy_static::lazy_static;
 use spin::{mutex, rwlock::RwLock, Mutex};
 use x86_64::registers::debug;
@@ -15,7 +15,8 @@ use x86_64::registers::debug;
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::device::virtio::{
-    virtio_has_feature, Queue, VirtioBaseState, VirtioDevice, VirtioInterrupt, VirtioInterruptType,
+    virtio_has_feature, AddrTranslator, Queue, VirtioBaseState, VirtioDevice, VirtioInterrupt,
+    VirtioInterruptType,
 };
 use crate::device::virtio::{
     CONFIG_STATUS_ACKNOWLEDGE, CONFIG_STATUS_DRIVER, CONFIG_STATUS_DRIVER_OK, CONFIG_STATUS_FAILED,
@@ -38,8 +39,8 @@ use pci::util::{
 };
 use pci::{
     config::{PciConfig, PCI_CAP_ID_VNDR, PCI_CAP_VNDR_AND_NEXT_SIZE},
-    init_msix, init_multifunction, le_write_u16, le_write_u32, AsAny, PciBus, PciDevBase,
-    PciDevOps,
+    init_msix, init_multifunction, le_read_u16, le_write_u16, le_write_u32, AsAny, Msix,
+    MsiIrqManager, PciBus, PciDevBase, PciDevOps,
 };
 
 const VIRTIO_QUEUE_MAX: u32 = 1024;
@@ -65,9 +66,13 @@ const VIRTIO_PCI_CAP_NOTIFY_LENGTH: u32 = 0x1000;
 const VIRTIO_PCI_CAP_NOTIFY_END: u32 = 0x4000;
 const VIRTIO_PCI_CAP_NOTIFY_OFF_MULTIPLIER: u32 = 4;
 
-const VIRTIO_PCI_BAR_MAX: u8 = 3;
+const VIRTIO_PCI_BAR_MAX: u8 = 4;
 const VIRTIO_PCI_MSIX_BAR_IDX: u8 = 1;
 const VIRTIO_PCI_MEM_BAR_IDX: u8 = 2;
+/// BAR backing every shared-memory window (e.g. a virtio-fs DAX mapping), a
+/// separate, larger, prefetchable BAR from the common-config window so it
+/// can be sized to the DAX region instead of `MINIMUM_BAR_SIZE_FOR_MMIO`.
+const VIRTIO_PCI_SHMEM_BAR_IDX: u8 = 3;
 
 /// Device (host) features set selector - Read Write.
 const COMMON_DFSELECT_REG: u64 = 0x0;
@@ -107,12 +112,20 @@ const COMMON_Q_AVAILHI_REG: u64 = 0x2c;
 const COMMON_Q_USEDLO_REG: u64 = 0x30;
 /// The high 32bit of queue's Used Ring address - Read Write.
 const COMMON_Q_USEDHI_REG: u64 = 0x34;
+/// Notification data carried alongside a queue kick, when negotiated - Read Only.
+const COMMON_Q_NOTIFY_DATA_REG: u64 = 0x38;
+/// Per-queue reset (VIRTIO 1.2, gated by `VIRTIO_F_RING_RESET`) - Read Write.
+const COMMON_Q_RESET_REG: u64 = 0x3a;
 
 /// The max features select num, only 0 or 1 is valid:
 ///   0: select feature bits 0 to 31.
 ///   1: select feature bits 32 to 63.
 const MAX_FEATURES_SELECT_NUM: u32 = 2;
 
+/// Driver and device support resetting a single virtqueue via
+/// `COMMON_Q_RESET_REG`, without the rest of the device being affected.
+const VIRTIO_F_RING_RESET: u64 = 40;
+
 lazy_static! {
     pub static ref GLOBAL_VIRTIO_PCI_CFG_REQ: RwLock<Option<MmioReq>> = RwLock::new(None);
 }
@@ -169,6 +182,7 @@ enum VirtioPciCapType {
     ISR = 3,
     Device = 4,
     CfgAccess = 5,
+    SharedMemory = 8,
 }
 
 /// Virtio PCI Capability
@@ -253,6 +267,153 @@ impl VirtioPciNotifyCap {
     }
 }
 
+/// The struct of virtio pci capability for a BAR-backed shared-memory
+/// region (e.g. a virtio-fs DAX window), per the `VIRTIO_PCI_CAP_SHARED_MEMORY_CFG`
+/// layout: the base [`VirtioPciCap`] carries the low 32 bits of `offset`/
+/// `length`, extended here with the high 32 bits since a DAX window can
+/// exceed 4GiB. `id` distinguishes multiple shared-memory regions exposed
+/// by the same device.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, Default)]
+struct VirtioPciSharedMemoryCap {
+    /// The struct of virtio pci capability.
+    cap: VirtioPciCap,
+    /// High 32 bits of the region's offset within the BAR.
+    offset_hi: u32,
+    /// High 32 bits of the region's length.
+    length_hi: u32,
+}
+
+impl ByteCode for VirtioPciSharedMemoryCap {}
+
+impl VirtioPciSharedMemoryCap {
+    fn new(cap_len: u8, bar_id: u8, id: u8, offset: u64, length: u64) -> Self {
+        let mut cap = VirtioPciCap::new(
+            cap_len,
+            VirtioPciCapType::SharedMemory as u8,
+            bar_id,
+            offset as u32,
+            length as u32,
+        );
+        // The shared-memory region id is carried in the first padding byte,
+        // matching the upstream virtio spec's overlay of `id` onto
+        // `virtio_pci_cap::padding[0]`.
+        cap.padding[0] = id;
+        VirtioPciSharedMemoryCap {
+            cap,
+            offset_hi: (offset >> 32) as u32,
+            length_hi: (length >> 32) as u32,
+        }
+    }
+}
+
+/// One BAR-backed shared-memory window (e.g. a virtio-fs DAX mapping) a
+/// device exposes alongside the common/isr/device/notify windows, advertised
+/// via a [`VirtioPciSharedMemoryCap`] in [`VirtioPciDevice::realize`].
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioSharedMemoryRegion {
+    /// Region id, referenced by the driver when mapping it.
+    pub id: u8,
+    /// Size of the region in bytes.
+    pub length: u64,
+}
+
+/// Per-queue fields worth carrying across a save/restore. `addr_cache` and
+/// the ring-index bookkeeping in `QueueConfig` are runtime-only, derived
+/// from these addresses and guest memory, so [`VirtioPciDevice::restore_state`]
+/// rebuilds them via `activate_device` instead of serializing them directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VirtioQueueState {
+    pub size: u16,
+    pub vector: u16,
+    pub ready: bool,
+    pub desc_table: u64,
+    pub avail_ring: u64,
+    pub used_ring: u64,
+}
+
+/// Save/restore snapshot of a [`VirtioPciDevice`]: everything
+/// `read_common_config`/`write_common_config` mutate, the MSI-X table/PBA
+/// state, and the raw PCI config space bytes. Queue ring *contents* in guest
+/// memory aren't captured here, only the configuration
+/// [`VirtioPciDevice::activate_device`] needs to rebuild the `Queue` objects.
+pub struct VirtioPciState {
+    pub device_feature_select: u32,
+    pub driver_feature_select: u32,
+    pub driver_features: [u32; MAX_FEATURES_SELECT_NUM as usize],
+    pub device_status: u32,
+    pub config_generation: u8,
+    pub queue_select: u16,
+    pub queues: Vec<VirtioQueueState>,
+    pub msix_table: Vec<u8>,
+    pub msix_pba: Vec<u8>,
+    pub msix_func_masked: bool,
+    pub msix_enabled: bool,
+    pub pci_config: Vec<u8>,
+    pub cfg_cap_offset: usize,
+}
+
+/// Legacy INTx fallback for a device whose driver never programs MSI-X: a
+/// level-triggered line, analogous to an `IrqLevelEvent`, that the owning
+/// device asserts via [`trigger`](Self::trigger) and re-asserts via
+/// [`notify_resample`](Self::notify_resample) whenever the driver reads and
+/// clears the ISR register but the line is still logically pending.
+/// Routing the asserted/deasserted level to an actual interrupt controller
+/// (PIC/IOAPIC) is the caller's job, plugged in as `set_level`.
+#[derive(Clone)]
+pub struct IntxLine {
+    set_level: Arc<dyn Fn(bool) + Send + Sync>,
+}
+
+impl IntxLine {
+    pub fn new(set_level: impl Fn(bool) + Send + Sync + 'static) -> Self {
+        Self {
+            set_level: Arc::new(set_level),
+        }
+    }
+
+    /// Assert the line.
+    pub fn trigger(&self) {
+        (self.set_level)(true);
+    }
+
+    /// Invoked when the driver reads and clears the ISR register: re-assert
+    /// the line if `pending` (the device still has `interrupt_status` bits
+    /// set), otherwise let it go quiet.
+    pub fn notify_resample(&self, pending: bool) {
+        (self.set_level)(pending);
+    }
+}
+
+/// A direct handle to signal a single virtqueue's completion interrupt,
+/// bypassing re-entry into the `VirtioInterrupt` closure on every request.
+/// Obtained via [`VirtioPciDevice::notifier`] and cached per queue so an
+/// offloaded backend can raise it on its own I/O completion path.
+pub trait Notifier: Send + Sync {
+    fn notify(&self);
+}
+
+/// [`Notifier`] for a single virtqueue, raising the MSI-X vector the
+/// `VirtioInterruptType::Vring` branch of the interrupt closure would have
+/// used for this queue.
+struct MsixNotifier {
+    msix: Arc<Mutex<Msix>>,
+    interrupt_status: Arc<AtomicU32>,
+    vector: u16,
+    dev_id: Arc<AtomicU16>,
+}
+
+impl Notifier for MsixNotifier {
+    fn notify(&self) {
+        self.interrupt_status
+            .fetch_or(VIRTIO_MMIO_INT_VRING, Ordering::SeqCst);
+        let mut locked_msix = self.msix.lock();
+        if locked_msix.enabled && self.vector != INVALID_VECTOR_NUM {
+            locked_msix.notify(self.vector, self.dev_id.load(Ordering::Acquire));
+        }
+    }
+}
+
 /// Virtio-PCI device structure
 #[derive(Clone)]
 pub struct VirtioPciDevice<B: BarAllocTrait> {
@@ -265,6 +426,26 @@ pub struct VirtioPciDevice<B: BarAllocTrait> {
     cfg_cap_offset: usize,
     /// The function for interrupt triggering
     interrupt_cb: Option<Arc<VirtioInterrupt>>,
+    /// Legacy INTx line, used whenever the driver hasn't enabled MSI-X.
+    intx: Option<IntxLine>,
+    /// Per-queue [`Notifier`] handles, populated by `activate_device()`.
+    notifiers: Arc<Mutex<Vec<Option<Arc<dyn Notifier>>>>>,
+    /// BAR-backed shared-memory windows (e.g. a virtio-fs DAX mapping),
+    /// advertised via [`VirtioPciSharedMemoryCap`]s in `realize()`. Set with
+    /// [`Self::set_shared_memory_regions`] before calling `realize()`.
+    shmem_regions: Vec<VirtioSharedMemoryRegion>,
+    /// Per-queue fast-path kick handlers, registered ahead of time via
+    /// [`Self::register_notify_handler`] (ioeventfd/`Datamatch`-style, as in
+    /// crosvm) so a guest write into the notify region can signal the
+    /// backend directly instead of round-tripping through
+    /// [`GLOBAL_VIRTIO_PCI_CFG_REQ`].
+    notify_handlers: Arc<Mutex<Vec<Option<Arc<dyn Fn() + Send + Sync>>>>>,
+    /// GPA-to-HVA translator passed to `QueueConfig::set_addr_cache` at
+    /// activate time. `None` leaves the queue's rings cached as raw,
+    /// untranslated GPAs - only safe for a caller that knows it'll never
+    /// dereference them as host pointers. Set with
+    /// [`Self::set_addr_translator`] before `realize()`.
+    translate: Option<AddrTranslator>,
 }
 
 impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
@@ -274,6 +455,7 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
         device: Arc<Mutex<dyn VirtioDevice>>,
         parent_bus: Weak<Mutex<PciBus<B>>>,
         multi_func: bool,
+        intx: Option<IntxLine>,
     ) -> Self {
         let queue_num = device.lock().queue_num();
         VirtioPciDevice {
@@ -287,19 +469,129 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
             dev_id: Arc::new(AtomicU16::new(0)),
             cfg_cap_offset: 0,
             interrupt_cb: None,
+            intx,
+            notifiers: Arc::new(Mutex::new(Vec::new())),
+            shmem_regions: Vec::new(),
+            notify_handlers: Arc::new(Mutex::new(Vec::new())),
+            translate: None,
+        }
+    }
+
+    /// Switch the legacy INTx line this device falls back to when MSI-X is
+    /// disabled, letting a caller wire up (or tear down) that interrupt mode
+    /// after construction instead of only at [`Self::new`] - e.g. once the
+    /// owning PCI bus has decided which GSI this device's pin routes to.
+    /// Has no effect on an already-installed `assign_interrupt_cb` closure;
+    /// call this before `realize()`.
+    pub fn set_intx(&mut self, intx: Option<IntxLine>) {
+        self.intx = intx;
+    }
+
+    /// Install the GPA-to-HVA translator `activate_device()` hands every
+    /// queue's `set_addr_cache`. Without this, a queue's descriptor table/
+    /// avail ring/used ring stay cached as the raw guest-physical addresses
+    /// the driver programmed, which every consumer then dereferences as if
+    /// they were host pointers - must be called before `realize()`.
+    pub fn set_addr_translator(&mut self, translate: AddrTranslator) {
+        self.translate = Some(translate);
+    }
+
+    /// Register a fast-path kick handler for one virtqueue: a guest write
+    /// into that queue's notify address invokes `handler` directly instead
+    /// of queuing a [`MmioReq`] for the hypervisor to replay, the same
+    /// ioeventfd/`Datamatch` shortcut crosvm uses to skip a full MMIO
+    /// round-trip on the hot kick path.
+    pub fn register_notify_handler(&mut self, queue_idx: usize, handler: Arc<dyn Fn() + Send + Sync>) {
+        let mut handlers = self.notify_handlers.lock();
+        if handlers.len() <= queue_idx {
+            handlers.resize(queue_idx + 1, None);
+        }
+        handlers[queue_idx] = Some(handler);
+    }
+
+    /// Queue a BAR-backed shared-memory window (e.g. a virtio-fs DAX mapping)
+    /// to advertise. Must be called before `realize()`, which is where the
+    /// regions queued here are actually laid out, backed by a BAR, and
+    /// published as [`VirtioPciSharedMemoryCap`]s.
+    ///
+    /// Only one shared-memory BAR is wired up today, so `bar_idx` is
+    /// validated against it rather than actually selecting the backing BAR.
+    pub fn add_shared_memory_region(
+        &mut self,
+        shm_id: u8,
+        bar_idx: u8,
+        size: u64,
+    ) -> HyperResult<()> {
+        if bar_idx != VIRTIO_PCI_SHMEM_BAR_IDX {
+            return Err(HyperError::PciError(PciError::Other(format!(
+                "virtio-pci only backs shared-memory regions with bar {}, not {}",
+                VIRTIO_PCI_SHMEM_BAR_IDX, bar_idx
+            ))));
         }
+        self.shmem_regions.push(VirtioSharedMemoryRegion {
+            id: shm_id,
+            length: size,
+        });
+        Ok(())
     }
 
-    fn assign_interrupt_cb(&mut self) {
+    /// A direct [`Notifier`] handle for one virtqueue, raising the same
+    /// MSI-X vector [`VirtioInterruptType::Vring`] would have used, without
+    /// going back through the full interrupt closure. Returns `None` before
+    /// MSI-X has been set up or if the queue doesn't exist.
+    fn notifier(&self, queue: &Queue) -> Option<Arc<dyn Notifier>> {
+        let msix = self.base.config.msix.as_ref()?.clone();
         let locked_dev = self.device.lock();
+        let interrupt_status = locked_dev.virtio_base().interrupt_status.clone();
+        drop(locked_dev);
+        Some(Arc::new(MsixNotifier {
+            msix,
+            interrupt_status,
+            vector: queue.vring.get_queue_config().vector,
+            dev_id: self.dev_id.clone(),
+        }))
+    }
+
+    /// The cached [`Notifier`] for a given queue index, grabbed by a backend
+    /// after activation so it can signal completions directly instead of
+    /// re-entering `assign_interrupt_cb`'s closure on every request.
+    pub fn queue_notifier(&self, queue_index: usize) -> Option<Arc<dyn Notifier>> {
+        self.notifiers.lock().get(queue_index).cloned().flatten()
+    }
+
+    /// Every queue's [`Notifier`] at once, so an out-of-process or
+    /// vhost-user-style backend can take the whole signaling path in one
+    /// call at setup time instead of polling [`Self::queue_notifier`] per
+    /// index, and then signal completions from its own thread thereafter.
+    /// Entries stay `None` until `activate_device()` has populated them.
+    pub fn notifiers(&self) -> Vec<Option<Arc<dyn Notifier>>> {
+        self.notifiers.lock().clone()
+    }
+
+    // Takes the already Arc<Mutex<..>>-wrapped device (rather than `&mut
+    // self`) so the closure can reach back into the PCI config space to
+    // flip the legacy INTx status bit, the same way `build_pci_cfg_cap_ops`
+    // needs the wrapped device to service MMIO accesses.
+    fn assign_interrupt_cb(dev: &Arc<Mutex<Self>>) {
+        let (virtio_device, cloned_msix, dev_id, intx) = {
+            let locked = dev.lock();
+            (
+                locked.device.clone(),
+                locked.base.config.msix.as_ref().unwrap().clone(),
+                locked.dev_id.clone(),
+                locked.intx.clone(),
+            )
+        };
+
+        let locked_dev = virtio_device.lock();
         let virtio_base = locked_dev.virtio_base();
         let device_status = virtio_base.device_status.clone();
         let interrupt_status = virtio_base.interrupt_status.clone();
         let msix_config = virtio_base.config_vector.clone();
         let config_generation = virtio_base.config_generation.clone();
+        drop(locked_dev);
 
-        let cloned_msix = self.base.config.msix.as_ref().unwrap().clone();
-        let dev_id = self.dev_id.clone();
+        let cloned_dev = dev.clone();
 
         let cb = Arc::new(Box::new(
             move |int_type: &VirtioInterruptType, queue: Option<&Queue>, needs_reset: bool| {
@@ -329,16 +621,45 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
 
                 let mut locked_msix = cloned_msix.lock();
                 if locked_msix.enabled {
-                    locked_msix.notify(vector, dev_id.load(Ordering::Acquire));
+                    // A driver that never programmed a vector for this queue
+                    // (or the device config) leaves it at NO_VECTOR, which
+                    // per spec means the interrupt is suppressed entirely.
+                    if vector != INVALID_VECTOR_NUM {
+                        locked_msix.notify(vector, dev_id.load(Ordering::Acquire));
+                    }
                 } else {
-                    error!("MSI-X is not enabled, failed to notify interrupt.");
+                    drop(locked_msix);
+                    match &intx {
+                        Some(intx) => {
+                            cloned_dev.lock().set_pci_status_interrupt(true);
+                            intx.trigger();
+                        }
+                        None => {
+                            error!(
+                                "MSI-X is not enabled and no legacy INTx line is configured, failed to notify interrupt."
+                            );
+                        }
+                    }
                 }
 
                 Ok(())
             },
         ) as VirtioInterrupt);
 
-        self.interrupt_cb = Some(cb);
+        dev.lock().interrupt_cb = Some(cb);
+    }
+
+    /// Set or clear the INTx "Interrupt Status" bit (PCI status register,
+    /// bit 3) to reflect whether this device currently has a legacy INTx
+    /// interrupt pending.
+    fn set_pci_status_interrupt(&mut self, pending: bool) {
+        let status = le_read_u16(&self.base.config.config, STATUS as usize).unwrap_or(0);
+        let new_status = if pending {
+            status | STATUS_INTERRUPT as u16
+        } else {
+            status & !(STATUS_INTERRUPT as u16)
+        };
+        let _ = le_write_u16(&mut self.base.config.config, STATUS as usize, new_status);
     }
 
     // add modern virtio device capability
@@ -361,27 +682,50 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
             return true;
         }
 
+        let expected_queue_num = locked_dev.queue_num();
+        let ready_queue_num = locked_dev
+            .virtio_base()
+            .queues_config
+            .iter()
+            .filter(|q| q.ready)
+            .count();
+        if ready_queue_num != expected_queue_num {
+            error!(
+                "{}",
+                VirtioError::IncorrectQueueNum(expected_queue_num, ready_queue_num)
+            );
+            return false;
+        }
+
         let queue_type = locked_dev.queue_type();
         let features = locked_dev.virtio_base().driver_features;
         let broken = locked_dev.virtio_base().broken.clone();
 
         let mut queues = Vec::new();
+        let mut notifiers = Vec::new();
         let queues_config = &mut locked_dev.virtio_base_mut().queues_config;
         for q_config in queues_config.iter_mut() {
             if !q_config.ready {
                 debug!("queue is not ready, please check your init process");
             } else {
-                q_config.set_addr_cache(self.interrupt_cb.clone().unwrap(), features, &broken);
+                q_config.set_addr_cache(
+                    self.interrupt_cb.clone().unwrap(),
+                    features,
+                    &broken,
+                    self.translate.clone(),
+                );
             }
-            let queue = Queue::new(*q_config, queue_type).unwrap();
+            let queue = Queue::new(q_config.clone(), queue_type).unwrap();
             if q_config.ready && !queue.is_valid() {
                 error!("Failed to activate device: Invalid queue");
                 return false;
             }
+            notifiers.push(self.notifier(&queue));
             let arc_queue = Arc::new(Mutex::new(queue));
             queues.push(arc_queue.clone());
         }
         locked_dev.virtio_base_mut().queues = queues;
+        *self.notifiers.lock() = notifiers;
 
         let parent = self.base.parent_bus.upgrade().unwrap();
         parent.lock().update_dev_id(self.base.devfn, &self.dev_id);
@@ -494,6 +838,10 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
             COMMON_Q_USEDHI_REG => locked_device
                 .queue_config()
                 .map(|config| (config.used_ring >> 32) as u32)?,
+            COMMON_Q_NOTIFY_DATA_REG => locked_device.queue_select() as u32,
+            COMMON_Q_RESET_REG => locked_device
+                .queue_config()
+                .map(|config| !config.ready as u32)?,
             _ => 0,
         };
 
@@ -556,12 +904,12 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
                         error!(
                             "Device is modern only, but the driver not support VIRTIO_F_VERSION_1"
                         );
-                        return Ok(());
+                        return Err(HyperError::VirtioError(VirtioError::DevStatErr(value)));
                     }
                 }
                 if value != 0 && (locked_device.device_status() & !value) != 0 {
                     error!("Driver must not clear a device status bit");
-                    return Ok(());
+                    return Err(HyperError::VirtioError(VirtioError::DevStatErr(value)));
                 }
 
                 let old_status = locked_device.device_status();
@@ -628,6 +976,33 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
             COMMON_Q_USEDHI_REG => locked_device.queue_config_mut(true).map(|config| {
                 config.used_ring = config.used_ring | (u64::from(value) << 32);
             })?,
+            COMMON_Q_RESET_REG => {
+                if value & 1 != 0 {
+                    let features = locked_device.virtio_base().driver_features;
+                    if !virtio_has_feature(features, VIRTIO_F_RING_RESET) {
+                        error!(
+                            "Driver requested a queue reset without negotiating VIRTIO_F_RING_RESET"
+                        );
+                        return Ok(());
+                    }
+                    let queue_type = locked_device.queue_type();
+                    let queue_select = locked_device.queue_select() as usize;
+                    let reset_config = locked_device.queue_config_mut(false).map(|config| {
+                        config.ready = false;
+                        config.desc_table = 0;
+                        config.avail_ring = 0;
+                        config.used_ring = 0;
+                        config.clone()
+                    })?;
+                    // Rebuild just this queue from its cleared config, leaving every
+                    // other queue's `Queue`/vring state untouched.
+                    if let Some(queue) = locked_device.virtio_base_mut().queues.get_mut(queue_select) {
+                        if let Ok(fresh) = Queue::new(reset_config, queue_type) {
+                            *queue = Arc::new(Mutex::new(fresh));
+                        }
+                    }
+                }
+            }
             _ => {
                 return Err(HyperError::PciError(PciError::PciRegister(offset)));
             }
@@ -661,12 +1036,26 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
                 // read pci isr cfg
                 VIRTIO_PCI_CAP_ISR_OFFSET..VIRTIO_PCI_CAP_DEVICE_OFFSET => {
                     let cloned_virtio_dev = cloned_virtio_pci.lock().device.clone();
+                    let mut still_pending = false;
                     if let Some(val) = data.get_mut(0) {
                         let device_lock = cloned_virtio_dev.lock();
                         *val = device_lock
                             .virtio_base()
                             .interrupt_status
                             .swap(0, Ordering::SeqCst) as u8;
+                        still_pending = device_lock
+                            .virtio_base()
+                            .interrupt_status
+                            .load(Ordering::Acquire)
+                            != 0;
+                    }
+                    // Reading ISR is how a guest acknowledges/deasserts a
+                    // legacy INTx interrupt; reflect that in the PCI status
+                    // bit and resample the shared line for other devices.
+                    let mut locked_dev = cloned_virtio_pci.lock();
+                    locked_dev.set_pci_status_interrupt(still_pending);
+                    if let Some(intx) = locked_dev.intx.clone() {
+                        intx.notify_resample(still_pending);
                     }
                 }
                 // read pci device cfg
@@ -681,10 +1070,10 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
                         return Err(HyperError::InValidMmioRead);
                     };
                 }
-                // read pci notify cfg
-                VIRTIO_PCI_CAP_NOTIFY_OFFSET..VIRTIO_PCI_CAP_NOTIFY_END => {
-                    // todo: need to notify hv to get the virtio request
-                }
+                // read pci notify cfg: the notify structure is write-only per
+                // the virtio spec (it only exists for the driver to kick a
+                // queue), so a read has nothing meaningful to return.
+                VIRTIO_PCI_CAP_NOTIFY_OFFSET..VIRTIO_PCI_CAP_NOTIFY_END => {}
                 _ => {
                     error!("Invalid offset for pci cfg cap, offset is {}", offset);
                     return Err(HyperError::InValidMmioRead);
@@ -728,9 +1117,43 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
                         return Err(HyperError::InValidMmioWrite);
                     };
                 }
-                // write pci notify cfg
+                // write pci notify cfg: a write anywhere in this region is a
+                // queue kick, the queue index is encoded in the offset.
                 VIRTIO_PCI_CAP_NOTIFY_OFFSET..VIRTIO_PCI_CAP_NOTIFY_END => {
-                    // todo: need to notify hv to get the virtio request
+                    let queue_idx = ((offset as u32 - VIRTIO_PCI_CAP_NOTIFY_OFFSET)
+                        / VIRTIO_PCI_CAP_NOTIFY_OFF_MULTIPLIER) as usize;
+                    let locked_virtio_pci = cloned_virtio_pci.lock();
+                    let queue_num = locked_virtio_pci.device.lock().queue_num();
+                    if queue_idx >= queue_num {
+                        error!(
+                            "Guest kicked out-of-range virtqueue {} (device has {})",
+                            queue_idx, queue_num
+                        );
+                        return Err(HyperError::InValidMmioWrite);
+                    }
+                    let handler = locked_virtio_pci
+                        .notify_handlers
+                        .lock()
+                        .get(queue_idx)
+                        .cloned()
+                        .flatten();
+                    if let Some(handler) = handler {
+                        drop(locked_virtio_pci);
+                        handler();
+                    } else {
+                        // No fast-path handler registered for this queue: fall
+                        // back to the generic mechanism the pci-cfg-access cap
+                        // also uses, and let the hv replay the write once it's
+                        // back in the MMIO dispatch loop.
+                        let bar_base = locked_virtio_pci
+                            .base
+                            .config
+                            .get_bar_address(VIRTIO_PCI_MEM_BAR_IDX as usize);
+                        drop(locked_virtio_pci);
+                        let mmio_req =
+                            MmioReq::new(data.to_vec(), access_size, bar_base + offset, true);
+                        *GLOBAL_VIRTIO_PCI_CFG_REQ.write() = Some(mmio_req);
+                    }
                 }
                 _ => {
                     error!("Invalid offset for pci cfg cap, offset is {}", offset);
@@ -822,6 +1245,105 @@ impl<B: BarAllocTrait + 'static> VirtioPciDevice<B> {
     pub fn get_virtio_device(&self) -> &Arc<Mutex<dyn VirtioDevice>> {
         &self.device
     }
+
+    /// Capture everything needed to rebuild this device's negotiated state
+    /// elsewhere: feature negotiation, device status, per-queue
+    /// configuration, MSI-X table/PBA, and the raw PCI config space.
+    pub fn save_state(&self) -> VirtioPciState {
+        let locked_device = self.device.lock();
+        let mut driver_features = [0u32; MAX_FEATURES_SELECT_NUM as usize];
+        for (sel, feature) in driver_features.iter_mut().enumerate() {
+            *feature = locked_device.driver_features(sel as u32);
+        }
+        let queues = locked_device
+            .virtio_base()
+            .queues_config
+            .iter()
+            .map(|q| VirtioQueueState {
+                size: q.size,
+                vector: q.vector,
+                ready: q.ready,
+                desc_table: q.desc_table,
+                avail_ring: q.avail_ring,
+                used_ring: q.used_ring,
+            })
+            .collect();
+
+        let device_feature_select = locked_device.hfeatures_sel();
+        let driver_feature_select = locked_device.gfeatures_sel();
+        let device_status = locked_device.device_status();
+        let config_generation = locked_device.config_generation();
+        let queue_select = locked_device.queue_select();
+        drop(locked_device);
+
+        let locked_msix = self.base.config.msix.as_ref().unwrap().lock();
+        VirtioPciState {
+            device_feature_select,
+            driver_feature_select,
+            driver_features,
+            device_status,
+            config_generation,
+            queue_select,
+            queues,
+            msix_table: locked_msix.table.clone(),
+            msix_pba: locked_msix.pba().to_vec(),
+            msix_func_masked: locked_msix.func_masked,
+            msix_enabled: locked_msix.enabled,
+            pci_config: self.base.config.config.clone(),
+            cfg_cap_offset: self.cfg_cap_offset,
+        }
+    }
+
+    /// Repopulate state captured by [`Self::save_state`]. Order matters:
+    /// negotiated features and queue addresses are restored before
+    /// `device_status`, so that if the restored status has `DRIVER_OK` set,
+    /// `activate_device` sees fully-populated queue configs when it runs;
+    /// `config_generation` is written last so the restore itself doesn't
+    /// bump it past what the driver last observed.
+    pub fn restore_state(&mut self, s: VirtioPciState) {
+        {
+            let mut locked_device = self.device.lock();
+            locked_device.set_hfeatures_sel(s.device_feature_select);
+            locked_device.set_gfeatures_sel(s.driver_feature_select);
+            for (sel, feature) in s.driver_features.into_iter().enumerate() {
+                locked_device.set_driver_features(sel as u32, feature);
+            }
+            locked_device.set_queue_select(s.queue_select);
+            for (q_config, saved) in locked_device
+                .virtio_base_mut()
+                .queues_config
+                .iter_mut()
+                .zip(s.queues.iter())
+            {
+                q_config.size = saved.size;
+                q_config.vector = saved.vector;
+                q_config.ready = saved.ready;
+                q_config.desc_table = saved.desc_table;
+                q_config.avail_ring = saved.avail_ring;
+                q_config.used_ring = saved.used_ring;
+            }
+        }
+
+        self.base.config.config = s.pci_config;
+        self.cfg_cap_offset = s.cfg_cap_offset;
+        if let Some(msix) = &self.base.config.msix {
+            msix.lock().restore(
+                s.msix_table,
+                s.msix_pba,
+                s.msix_func_masked,
+                s.msix_enabled,
+            );
+        }
+
+        {
+            let mut locked_device = self.device.lock();
+            locked_device.set_config_generation(s.config_generation);
+            locked_device.set_device_status(s.device_status);
+        }
+        if s.device_status & CONFIG_STATUS_DRIVER_OK != 0 {
+            self.activate_device();
+        }
+    }
 }
 
 impl<B: BarAllocTrait + 'static> AsAny for VirtioPciDevice<B> {
@@ -919,6 +1441,23 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
         );
         self.modern_mem_region_cap_add(notify_cap)?;
 
+        // Lay the shared-memory regions out back-to-back in
+        // VIRTIO_PCI_SHMEM_BAR_IDX, each advertised by its own capability so
+        // the driver can map whichever region id it needs (e.g. a virtio-fs
+        // DAX window) independently of the common/isr/device/notify windows.
+        let mut shmem_bar_size: u64 = 0;
+        for region in &self.shmem_regions {
+            let shmem_cap = VirtioPciSharedMemoryCap::new(
+                size_of::<VirtioPciSharedMemoryCap>() as u8 + PCI_CAP_VNDR_AND_NEXT_SIZE,
+                VIRTIO_PCI_SHMEM_BAR_IDX,
+                region.id,
+                shmem_bar_size,
+                region.length,
+            );
+            self.modern_mem_region_cap_add(shmem_cap)?;
+            shmem_bar_size += (region.length + 0xfff) & !0xfff;
+        }
+
         let cfg_cap = VirtioPciCfgAccessCap::new(
             size_of::<VirtioPciCfgAccessCap>() as u8 + PCI_CAP_VNDR_AND_NEXT_SIZE,
             VirtioPciCapType::CfgAccess as u8,
@@ -945,8 +1484,6 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
             None,
         )?;
 
-        self.assign_interrupt_cb();
-
         self.device.lock().realize().or_else(|_| {
             Err(HyperError::VirtioError(VirtioError::Other(format!(
                 "Failed to realize virtio device"
@@ -960,6 +1497,7 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
             as u64)
             .next_power_of_two();
         mem_region_size = max(mem_region_size, MINIMUM_BAR_SIZE_FOR_MMIO as u64);
+        Self::assign_interrupt_cb(&dev);
         let pci_cfg_cap_ops = Self::build_pci_cfg_cap_ops(dev.clone());
 
         dev.lock().base.config.register_bar(
@@ -970,6 +1508,26 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
             mem_region_size,
         )?;
 
+        if shmem_bar_size > 0 {
+            // todo: route reads/writes through the actual DAX mapping (guest
+            // mmap of host file-backed memory) once that plumbing exists;
+            // for now the window is reserved and addressable but reads as
+            // zero, same spirit as the notify BAR's access stub above.
+            let shmem_region_ops = RegionOps {
+                read: Arc::new(|_offset: u64, _access_size: u8| -> HyperResult<u64> { Ok(0) }),
+                write: Arc::new(|_offset: u64, _access_size: u8, _data: &[u8]| -> HyperResult {
+                    Ok(())
+                }),
+            };
+            dev.lock().base.config.register_bar(
+                VIRTIO_PCI_SHMEM_BAR_IDX as usize,
+                Some(shmem_region_ops),
+                RegionType::Mem64Bit,
+                true,
+                shmem_bar_size,
+            )?;
+        }
+
         // Register device to pci bus. Now set it to the root bus.
         let pci_bus = dev.lock().base.parent_bus.upgrade().unwrap();
         let mut locked_pci_bus = pci_bus.lock();
@@ -1032,9 +1590,26 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
 
         let parent_bus = self.base.parent_bus.upgrade().unwrap();
         let locked_parent_bus = parent_bus.lock();
-        self.base
-            .config
-            .write(offset, data, self.dev_id.clone().load(Ordering::Acquire));
+        let remaps =
+            self.base
+                .config
+                .write_bars(offset, data, self.dev_id.clone().load(Ordering::Acquire));
+        // `PciConfig::write_bars` already relocated any BAR whose base this
+        // write touched (dealloc/realloc through `PciBarAllocator` and
+        // rewritten config-space bytes). No `RegionOps` re-registration is
+        // needed on top of that: `PciBus::find_mmio_bar`/`PciConfig::find_mmio`
+        // look a BAR up by its *current* `address` on every MMIO exit
+        // instead of caching a fixed guest-physical range, so this device's
+        // common/isr/device/notify and MSI-X table regions keep dispatching
+        // correctly at the new base. Just log it, since a guest/firmware
+        // moving a BAR after enumeration is unusual enough to be worth
+        // tracing.
+        for remap in remaps {
+            info!(
+                "virtio-pci {}: bar {} relocated from {:#x} to {:#x}",
+                self.base.id, remap.id, remap.old_base, remap.new_base
+            );
+        }
         let mmio_req = self.do_cfg_access(offset, end, true);
         if mmio_req.is_some() {
             *GLOBAL_VIRTIO_PCI_CFG_REQ.write() = mmio_req;
@@ -1077,4 +1652,14 @@ impl<B: BarAllocTrait + 'static> PciDevOps<B> for VirtioPciDevice<B> {
             _ => None,
         }
     }
+
+    /// Expose the PCI bus's configured `MsiIrqManager` so the default
+    /// `change_irq_level` can route a shared/legacy INTx assert-deassert
+    /// through it, the same generic path `init_msix` already uses to
+    /// deliver MSI-X vectors. Dedicated drivers of this device keep using
+    /// `set_intx`/`IntxLine` directly when they need finer control over
+    /// which real interrupt controller pin the line lands on.
+    fn get_msi_irq_manager(&self) -> Option<Arc<dyn MsiIrqManager>> {
+        self.base.parent_bus.upgrade()?.lock().get_msi_irq_manager()
+    }
 }
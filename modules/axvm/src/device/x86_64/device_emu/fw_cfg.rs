@@ -0,0 +1,118 @@
+//! QEMU/bhyve-style firmware config device: hands the guest BIOS/kernel
+//! configuration data (command line, RAM size, SMP count, ...) without
+//! baking it into the boot image. (ref: QEMU's `docs/specs/fw_cfg.txt`)
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use hypercraft::PioOps;
+
+use crate::config::entry::VMCfgEntry;
+use crate::Result as HyperResult;
+
+const SELECTOR_PORT: u16 = 0x510;
+const DATA_PORT: u16 = 0x511;
+
+const FW_CFG_SIGNATURE: u16 = 0x00;
+const FW_CFG_ID: u16 = 0x01;
+const FW_CFG_FILE_DIR: u16 = 0x19;
+const FW_CFG_FILE_FIRST: u16 = 0x20;
+
+const FILE_NAME_SIZE: usize = 56;
+
+/// Emulated fw_cfg device, selector + sequential-read data port only (the
+/// DMA interface at 0x514 isn't implemented).
+pub struct FwCfg {
+    selector: u16,
+    offset: usize,
+    items: BTreeMap<u16, Vec<u8>>,
+    files: Vec<(u16, String)>,
+    next_file_key: u16,
+}
+
+impl FwCfg {
+    pub fn new(cmdline: &str, ram_size: u64, smp_count: u32) -> Self {
+        let mut fw_cfg = Self {
+            selector: 0,
+            offset: 0,
+            items: BTreeMap::new(),
+            files: Vec::new(),
+            next_file_key: FW_CFG_FILE_FIRST,
+        };
+        fw_cfg.items.insert(FW_CFG_SIGNATURE, b"QEMU".to_vec());
+        fw_cfg.items.insert(FW_CFG_ID, 1u32.to_le_bytes().to_vec());
+
+        let mut cmdline_bytes = cmdline.as_bytes().to_vec();
+        cmdline_bytes.push(0);
+        fw_cfg.add_file("etc/cmdline", cmdline_bytes);
+        fw_cfg.add_file("etc/ram_size", ram_size.to_le_bytes().to_vec());
+        fw_cfg.add_file("etc/smp_count", smp_count.to_le_bytes().to_vec());
+
+        fw_cfg.rebuild_file_dir();
+        fw_cfg
+    }
+
+    /// Build from a VM config entry's cmdline/memory/cpu_set, as used by
+    /// `boot_vm`.
+    pub fn from_vm_cfg_entry(entry: &VMCfgEntry) -> Self {
+        Self::new(
+            entry.get_cmdline(),
+            entry.get_ram_size(),
+            entry.get_cpu_set().count_ones(),
+        )
+    }
+
+    fn add_file(&mut self, name: &str, data: Vec<u8>) -> u16 {
+        let key = self.next_file_key;
+        self.next_file_key += 1;
+        self.items.insert(key, data);
+        self.files.push((key, name.to_string()));
+        key
+    }
+
+    fn rebuild_file_dir(&mut self) {
+        let mut dir = Vec::new();
+        dir.extend_from_slice(&(self.files.len() as u32).to_be_bytes());
+        for (key, name) in self.files.clone() {
+            let size = self.items.get(&key).map(|d| d.len()).unwrap_or(0) as u32;
+            dir.extend_from_slice(&size.to_be_bytes());
+            dir.extend_from_slice(&key.to_be_bytes());
+            let mut name_field = [0u8; FILE_NAME_SIZE];
+            let bytes = name.as_bytes();
+            let len = bytes.len().min(FILE_NAME_SIZE);
+            name_field[..len].copy_from_slice(&bytes[..len]);
+            dir.extend_from_slice(&name_field);
+        }
+        self.items.insert(FW_CFG_FILE_DIR, dir);
+    }
+}
+
+impl PioOps for FwCfg {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        SELECTOR_PORT..DATA_PORT + 1
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        if port == DATA_PORT {
+            let byte = self
+                .items
+                .get(&self.selector)
+                .and_then(|data| data.get(self.offset))
+                .copied()
+                .unwrap_or(0);
+            self.offset += 1;
+            Ok(byte as u32)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        if port == SELECTOR_PORT {
+            self.selector = value as u16;
+            self.offset = 0;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Emulated I/O APIC (82093AA). (ref: Intel 82093AA datasheet, and SDM Vol.
+//! 3A Ch. 10 for the vector/delivery-mode fields the redirection table
+//! shares with the local APIC's LVT entries; modeled after the
+//! split-irqchip/ioapic design in cloud-hypervisor's vmm.)
+//!
+//! Exposes the classic indirect MMIO interface (IOREGSEL selects a register,
+//! IOWIN reads/writes it) plus a dedicated EOI register, backed by a 24-entry
+//! redirection table. Devices call [`IoApic::raise_gsi`]/[`IoApic::lower_gsi`]
+//! instead of reaching into the legacy PIC or a hardcoded timer loop; actual
+//! delivery happens on the next [`IoApic::poll`], called from
+//! `X64VcpuDevices::check_events`, since nothing upstream of here hands a
+//! `VCpu` to [`MmioOps::write`].
+//!
+//! Only IOAPICID/IOAPICVER and the redirection table are implemented;
+//! IOAPICARB always reads back 0.
+
+use bit_field::BitField;
+
+use crate::{HyperCraftHal, Result as HyperResult, VCpu};
+use hypercraft::MmioOps;
+
+const REG_IOAPICID: u32 = 0x00;
+const REG_IOAPICVER: u32 = 0x01;
+const REG_IOAPICARB: u32 = 0x02;
+const REG_REDTBL_BASE: u32 = 0x10;
+
+const MMIO_IOREGSEL: u64 = 0x00;
+const MMIO_IOWIN: u64 = 0x10;
+const MMIO_EOI: u64 = 0x40;
+
+/// Number of redirection-table entries (and thus GSIs); matches the 82093AA
+/// and what most BIOSes/OSes assume for a single IOAPIC.
+pub const NUM_GSI: usize = 24;
+
+/// GSI the APIC timer routes through, replacing the old direct
+/// `self.pic[0]`/IRQ0 injection in `check_events`.
+pub const TIMER_GSI: u8 = 2;
+
+/// Redirection-table entry bit layout, within the 64-bit value built from
+/// the pair of 32-bit registers at `REG_REDTBL_BASE + 2*gsi` (low) and
+/// `+ 2*gsi + 1` (high).
+const VECTOR: core::ops::Range<usize> = 0..8;
+const TRIGGER_MODE: usize = 15;
+const REMOTE_IRR: usize = 14;
+const MASKED: usize = 16;
+
+pub struct IoApic {
+    ioregsel: u32,
+    id: u32,
+    /// One 64-bit redirection-table entry per GSI; bit 16 (mask) starts set,
+    /// same as real hardware after reset.
+    redir_table: [u64; NUM_GSI],
+    /// Whether a device currently holds its GSI's line high. Edge-triggered
+    /// sources clear their own entry right back via `lower_gsi` once
+    /// `raise_gsi` has latched it; level-triggered sources leave it set
+    /// until their condition clears, so [`Self::poll`] keeps re-delivering
+    /// across EOIs for as long as it's asserted.
+    asserted: [bool; NUM_GSI],
+}
+
+impl IoApic {
+    /// Guest-physical base of the MMIO window (ref: SDM Vol. 3A Table 10-1).
+    pub const MMIO_BASE: u64 = 0xFEC0_0000;
+
+    pub fn new() -> Self {
+        Self {
+            ioregsel: 0,
+            id: 0,
+            redir_table: [1 << MASKED; NUM_GSI],
+            asserted: [false; NUM_GSI],
+        }
+    }
+
+    fn read_register(&self, index: u32) -> u32 {
+        match index {
+            REG_IOAPICID => self.id << 24,
+            // Version 0x20 (the first revision with an EOI register, which
+            // this emulation also implements), max redirection entry index
+            // in bits 16..24.
+            REG_IOAPICVER => ((NUM_GSI as u32 - 1) << 16) | 0x20,
+            REG_IOAPICARB => 0,
+            reg if reg >= REG_REDTBL_BASE => {
+                let gsi = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(&entry) = self.redir_table.get(gsi) else {
+                    return 0;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    entry as u32
+                } else {
+                    (entry >> 32) as u32
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, index: u32, value: u32) {
+        match index {
+            REG_IOAPICID => self.id = value.get_bits(24..28),
+            reg if reg >= REG_REDTBL_BASE => {
+                let gsi = ((reg - REG_REDTBL_BASE) / 2) as usize;
+                let Some(entry) = self.redir_table.get_mut(gsi) else {
+                    return;
+                };
+                if (reg - REG_REDTBL_BASE) % 2 == 0 {
+                    *entry = (*entry & !0xffff_ffff) | value as u64;
+                } else {
+                    *entry = (*entry & 0xffff_ffff) | ((value as u64) << 32);
+                }
+            }
+            // IOAPICVER/IOAPICARB are read-only.
+            _ => {}
+        }
+    }
+
+    /// A device asserts GSI `gsi`: latches it for delivery on the next
+    /// [`Self::poll`]. Safe to call repeatedly while the line stays high.
+    pub fn raise_gsi(&mut self, gsi: u8) {
+        if let Some(line) = self.asserted.get_mut(gsi as usize) {
+            *line = true;
+        }
+    }
+
+    /// A device deasserts GSI `gsi`. For a level-triggered entry this stops
+    /// [`Self::poll`] from re-delivering it once the guest EOIs; for an
+    /// edge-triggered one it's what makes `raise_gsi` a single pulse rather
+    /// than a standing assertion.
+    pub fn lower_gsi(&mut self, gsi: u8) {
+        if let Some(line) = self.asserted.get_mut(gsi as usize) {
+            *line = false;
+        }
+    }
+
+    /// Deliver whatever's pending: for each asserted, unmasked GSI, inject
+    /// its vector unless it's a level-triggered entry still waiting on an
+    /// EOI (remote IRR set). Called every `check_events` tick instead of the
+    /// old fixed-rate timer poke, so delivery latency is bounded by exit
+    /// frequency rather than a hardcoded interval.
+    pub fn poll<H: HyperCraftHal>(&mut self, vcpu: &mut VCpu<H>) {
+        for gsi in 0..NUM_GSI {
+            if !self.asserted[gsi] {
+                continue;
+            }
+            let entry = self.redir_table[gsi];
+            if entry.get_bit(MASKED) {
+                continue;
+            }
+            let level_triggered = entry.get_bit(TRIGGER_MODE);
+            if level_triggered && entry.get_bit(REMOTE_IRR) {
+                continue;
+            }
+            let vector = entry.get_bits(VECTOR) as u8;
+            if level_triggered {
+                self.redir_table[gsi].set_bit(REMOTE_IRR, true);
+            } else {
+                self.asserted[gsi] = false;
+            }
+            vcpu.queue_event(vector, None);
+        }
+    }
+
+    /// Whether `gsi` is a level-triggered entry currently waiting on an
+    /// EOI (remote IRR set). [`X64VcpuDevices::check_events`] uses this to
+    /// decide whether a registered [`super::IrqLevel`]'s resample callback
+    /// should run yet, the same way a real IOAPIC holds a level line
+    /// in-service until the guest acknowledges it.
+    pub fn gsi_in_service(&self, gsi: u8) -> bool {
+        self.redir_table
+            .get(gsi as usize)
+            .is_some_and(|entry| entry.get_bit(TRIGGER_MODE) && entry.get_bit(REMOTE_IRR))
+    }
+
+    /// Guest EOI (local APIC EOI or this IOAPIC's own EOI register at
+    /// 0x40): clear remote IRR on every level-triggered entry still
+    /// programmed with `vector`. If the owning device hasn't lowered its
+    /// line in the meantime, the next [`Self::poll`] re-delivers it.
+    fn handle_eoi(&mut self, vector: u8) {
+        for entry in self.redir_table.iter_mut() {
+            if entry.get_bit(TRIGGER_MODE) && entry.get_bits(VECTOR) as u8 == vector {
+                entry.set_bit(REMOTE_IRR, false);
+            }
+        }
+    }
+}
+
+impl Default for IoApic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioOps for IoApic {
+    fn mmio_range(&self) -> core::ops::Range<u64> {
+        Self::MMIO_BASE..Self::MMIO_BASE + 0x44
+    }
+
+    fn read(&mut self, addr: u64, _access_size: u8) -> HyperResult<u64> {
+        match addr - Self::MMIO_BASE {
+            MMIO_IOREGSEL => Ok(self.ioregsel as u64),
+            MMIO_IOWIN => Ok(self.read_register(self.ioregsel) as u64),
+            MMIO_EOI => Ok(0),
+            _ => Ok(0),
+        }
+    }
+
+    fn write(&mut self, addr: u64, _access_size: u8, value: u64) -> HyperResult {
+        match addr - Self::MMIO_BASE {
+            MMIO_IOREGSEL => self.ioregsel = value as u32,
+            MMIO_IOWIN => self.write_register(self.ioregsel, value as u32),
+            MMIO_EOI => self.handle_eoi(value as u8),
+            _ => {}
+        }
+        Ok(())
+    }
+}
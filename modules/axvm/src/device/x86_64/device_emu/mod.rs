@@ -2,24 +2,32 @@ mod apic_timer;
 mod bundle;
 mod debug_port;
 mod dummy;
+mod fw_cfg;
 mod i8259_pic;
+mod ioapic;
 // mod pcip;
 mod pit;
 mod port_passthrough;
+mod rtc;
 mod uart16550;
 mod pci_dummy;
 
 extern crate alloc;
 
+use crate::Error as HyperError;
 use crate::Result as HyperResult;
 
 pub use apic_timer::{ApicBaseMsrHandler, VirtLocalApic, ProxyLocalApic};
 pub use bundle::Bundle;
 pub use debug_port::DebugPort;
 pub use dummy::Dummy;
+pub use fw_cfg::FwCfg;
 use hypercraft::VirtMsrOps;
 pub use i8259_pic::I8259Pic;
+pub use ioapic::{IoApic, NUM_GSI, TIMER_GSI};
+pub use pit::{Pit, PitSpeakerGate};
 pub use port_passthrough::PortPassthrough;
+pub use rtc::{Rtc, RTC_GSI};
 pub use uart16550::{MultiplexConsoleBackend, Uart16550};
 pub use pci_dummy::PCIConfigurationSpace;
 
@@ -92,22 +100,127 @@ pub(crate) use msr_proxy_struct;
 pub(crate) use pmio_proxy_factory;
 pub(crate) use pmio_proxy_struct;
 
+/// A level-triggered interrupt line, analogous to the trigger/resample
+/// `IrqLevelEvent` pattern used elsewhere for shared/level IRQ sources that
+/// can't rely on [`hypercraft::VCpu::queue_event`]'s fire-and-forget edge
+/// semantics. A device owning one calls [`Self::raise`]/[`Self::lower`] as
+/// its condition changes; whoever actually delivers GSIs (today only
+/// `X64VcpuDevices::check_events`, via its [`ioapic::IoApic`]) calls
+/// [`Self::resample`] once the line is no longer held in-service, so the
+/// device gets a chance to re-assert if it's still true (e.g. an RX ring
+/// still has data).
+pub struct IrqLevel {
+    gsi: u8,
+    asserted: bool,
+    resample_fn: alloc::boxed::Box<dyn FnMut() -> bool + Send>,
+}
+
+impl IrqLevel {
+    /// `resample` re-checks the owning device's own condition and returns
+    /// whether the line should still be considered asserted.
+    pub fn new(gsi: u8, resample: impl FnMut() -> bool + Send + 'static) -> Self {
+        Self {
+            gsi,
+            asserted: false,
+            resample_fn: alloc::boxed::Box::new(resample),
+        }
+    }
+
+    pub fn gsi(&self) -> u8 {
+        self.gsi
+    }
+
+    pub fn is_asserted(&self) -> bool {
+        self.asserted
+    }
+
+    /// The device's line goes high.
+    pub fn raise(&mut self) {
+        self.asserted = true;
+    }
+
+    /// The device's line goes low.
+    pub fn lower(&mut self) {
+        self.asserted = false;
+    }
+
+    /// Re-run the resample callback and update `asserted` from what it
+    /// reports.
+    pub fn resample(&mut self) {
+        self.asserted = (self.resample_fn)();
+    }
+}
+
+/// Disposition [`MsrPolicy`] assigns to a range of MSRs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsrMode {
+    /// Do a real `rdmsr`/`wrmsr` against the physical MSR.
+    Passthrough,
+    /// Emulate the access without touching hardware (e.g. read back as
+    /// whatever [`MsrDummy`]'s default is).
+    Emulated,
+    /// Reject the access; the VM-exit handler turns the resulting error
+    /// into an injected `#GP`, the same as a real CPU would for an MSR
+    /// the guest has no business touching.
+    Denied,
+}
+
+/// A declarative whitelist of `(range, mode)` rules, checked in order, so
+/// hypervisor setup can say exactly which MSRs a guest may pass through,
+/// emulate, or touch at all -- instead of special-casing individual MSR
+/// numbers inside a device's `read`/`write`.
+pub struct MsrPolicy {
+    rules: alloc::vec::Vec<(core::ops::Range<u32>, MsrMode)>,
+}
+
+impl MsrPolicy {
+    /// Build a policy from an explicit rule list. The first rule whose
+    /// range contains the MSR wins; an MSR covered by no rule is
+    /// [`MsrMode::Denied`].
+    pub fn new(rules: alloc::vec::Vec<(core::ops::Range<u32>, MsrMode)>) -> Self {
+        Self { rules }
+    }
+
+    fn mode_for(&self, msr: u32) -> MsrMode {
+        self.rules
+            .iter()
+            .find(|(range, _)| range.contains(&msr))
+            .map(|(_, mode)| *mode)
+            .unwrap_or(MsrMode::Denied)
+    }
+}
+
 pub struct MsrDummy {
     msr_range: core::ops::Range<u32>,
+    policy: MsrPolicy,
 }
 
 impl MsrDummy {
+    /// A dummy MSR, fully emulated -- reads as `0`, writes are discarded.
+    /// This is the behavior `MsrDummy` had before [`MsrPolicy`] existed.
     pub fn new(msr: u32) -> Self {
+        Self::new_range(msr..msr + 1)
+    }
+
+    /// A dummy MSR range, fully emulated.
+    pub fn new_range(range: core::ops::Range<u32>) -> Self {
+        let policy = MsrPolicy::new(alloc::vec![(range.clone(), MsrMode::Emulated)]);
         Self {
-            msr_range: msr..msr + 1,
+            msr_range: range,
+            policy,
         }
     }
 
-    pub fn new_range(range: core::ops::Range<u32>) -> Self {
-        Self { msr_range: range }
+    /// A dummy MSR range governed by an explicit [`MsrPolicy`], e.g. to
+    /// whitelist a passthrough or denied sub-range instead of emulating
+    /// everything in `range`.
+    pub fn with_policy(range: core::ops::Range<u32>, policy: MsrPolicy) -> Self {
+        Self {
+            msr_range: range,
+            policy,
+        }
     }
 }
-const IA32_UMWAIT_CONTROL: u32 = 0xe1;
 
 impl VirtMsrOps for MsrDummy {
     fn msr_range(&self) -> core::ops::Range<u32> {
@@ -115,40 +228,38 @@ impl VirtMsrOps for MsrDummy {
     }
 
     fn read(&mut self, msr: u32) -> HyperResult<u64> {
-        debug!("read from msr dummy {:#x}", msr);
-
-        // Todo: refactor this.
-        if msr == IA32_UMWAIT_CONTROL {
-            use x86::msr::rdmsr;
-            let value = unsafe { rdmsr(IA32_UMWAIT_CONTROL) };
-            debug!(
-                "IA32_UMWAIT_CONTROL {:#x}, we still don' why do we meed to mock this!!!",
-                value
-            );
-            return Ok(value);
+        match self.policy.mode_for(msr) {
+            MsrMode::Passthrough => {
+                let value = unsafe { x86::msr::rdmsr(msr) };
+                debug!("passthrough read of msr {:#x} = {:#x}", msr, value);
+                Ok(value)
+            }
+            MsrMode::Emulated => {
+                debug!("read from msr dummy {:#x}", msr);
+                Ok(0)
+            }
+            MsrMode::Denied => {
+                debug!("denied read of msr {:#x}, injecting #GP", msr);
+                Err(HyperError::NotSupported)
+            }
         }
-        Ok(0)
     }
 
     fn write(&mut self, msr: u32, value: u64) -> HyperResult {
-        debug!("write to msr dummy {:#x}, value: {:#x}", msr, value);
-
-        // Todo: refactor this.
-        if msr == IA32_UMWAIT_CONTROL {
-            use x86::msr::rdmsr;
-            debug!("IA32_UMWAIT_CONTROL current value {:#x}", unsafe {
-                rdmsr(IA32_UMWAIT_CONTROL)
-            });
-
-            use x86::msr::wrmsr;
-            unsafe {
-                wrmsr(IA32_UMWAIT_CONTROL, value);
+        match self.policy.mode_for(msr) {
+            MsrMode::Passthrough => {
+                debug!("passthrough write of msr {:#x} = {:#x}", msr, value);
+                unsafe { x86::msr::wrmsr(msr, value) };
+                Ok(())
+            }
+            MsrMode::Emulated => {
+                debug!("write to msr dummy {:#x}, value: {:#x}", msr, value);
+                Ok(())
+            }
+            MsrMode::Denied => {
+                debug!("denied write of msr {:#x}, injecting #GP", msr);
+                Err(HyperError::NotSupported)
             }
-            debug!(
-                "write to IA32_UMWAIT_CONTROL {:#x}, we still don' why do we meed to mock this!!!",
-                value
-            );
         }
-        Ok(())
     }
 }
@@ -0,0 +1,288 @@
+//! Emulated Intel 8254 Programmable Interval Timer (ref: Intel 8254
+//! datasheet; PC/AT wiring: channel 0 drives IRQ0/GSI0, channel 2 drives the
+//! PC speaker through the gate at port 0x61).
+//!
+//! Only channel 0 is wired to an interrupt line: `X64VcpuDevices::new`
+//! registers [`Pit::channel0_due`] as a [`super::IrqLevel`] resample
+//! callback on GSI 0, replacing the old hardcoded 5s-then-1ms `self.last`
+//! schedule `check_events` used to run. Channels 1 (historically DRAM
+//! refresh, unused on anything built after the 386) and 2 (PC speaker tone)
+//! answer the counter/mode-register protocol like real hardware but have no
+//! audible or otherwise observable side effect here.
+
+use axhal::time::current_time_nanos;
+
+use crate::Result as HyperResult;
+use hypercraft::PioOps;
+
+/// 8254 input clock frequency (ref: Intel 8254 datasheet Sec. 2.2).
+const INPUT_CLOCK_HZ: u64 = 1_193_182;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+const REG_CHANNEL_DATA: [u16; 3] = [0x40, 0x41, 0x42];
+const REG_MODE_COMMAND: u16 = 0x43;
+const REG_SPEAKER_GATE: u16 = 0x61;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AccessMode {
+    /// Counter-latch command (access bits `00`): the next read(s) return
+    /// whatever [`Channel::latch`] captured rather than a live countdown.
+    Latch,
+    LoByte,
+    HiByte,
+    Word,
+}
+
+/// Per-channel programmable state, plus the byte-sequencing `Word` access
+/// needs across two successive port writes/reads.
+struct Channel {
+    mode: u8,
+    access: AccessMode,
+    /// 16-bit reload value most recently programmed via the data port.
+    reload: u16,
+    /// `Word` access writes lobyte then hibyte; tracks which comes next.
+    write_high_half: bool,
+    /// Value a counter-latch command captured, consumed by the read(s)
+    /// that follow instead of a live countdown.
+    latched: Option<u16>,
+    /// `Word`/latched reads return lobyte then hibyte; tracks which is next.
+    read_high_half: bool,
+    /// Nanosecond clock reading at the most recent reload; `None` until the
+    /// guest has programmed one.
+    armed_at_ns: Option<u64>,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            mode: 0,
+            access: AccessMode::Word,
+            reload: 0,
+            write_high_half: false,
+            latched: None,
+            read_high_half: false,
+            armed_at_ns: None,
+        }
+    }
+
+    /// Nanoseconds for one full countdown of `reload` (a reload of 0 means
+    /// 65536, same as real 8254 hardware).
+    fn period_ns(&self) -> u64 {
+        let divisor = if self.reload == 0 { 65536 } else { self.reload as u64 };
+        divisor * NANOS_PER_SEC / INPUT_CLOCK_HZ
+    }
+
+    /// Live countdown value at `now_ns`.
+    fn live_count(&self, now_ns: u64) -> u16 {
+        let Some(armed_at) = self.armed_at_ns else {
+            return self.reload;
+        };
+        let period = self.period_ns();
+        if period == 0 {
+            return 0;
+        }
+        let elapsed = now_ns.saturating_sub(armed_at);
+        let into_period = match self.mode {
+            // Mode 0 (interrupt on terminal count): counts down once and
+            // sits at 0 until reprogrammed.
+            0 => core::cmp::min(elapsed, period),
+            // Modes 2/3 (rate generator / square wave): free-running,
+            // reloads every period.
+            _ => elapsed % period,
+        };
+        (self.reload as u64).saturating_sub(into_period * INPUT_CLOCK_HZ / NANOS_PER_SEC) as u16
+    }
+
+    fn write_data(&mut self, value: u8, now_ns: u64) {
+        match self.access {
+            AccessMode::LoByte => {
+                self.reload = (self.reload & 0xff00) | value as u16;
+                self.armed_at_ns = Some(now_ns);
+            }
+            AccessMode::HiByte => {
+                self.reload = (self.reload & 0x00ff) | ((value as u16) << 8);
+                self.armed_at_ns = Some(now_ns);
+            }
+            AccessMode::Word => {
+                if !self.write_high_half {
+                    self.reload = (self.reload & 0xff00) | value as u16;
+                    self.write_high_half = true;
+                } else {
+                    self.reload = (self.reload & 0x00ff) | ((value as u16) << 8);
+                    self.write_high_half = false;
+                    self.armed_at_ns = Some(now_ns);
+                }
+            }
+            AccessMode::Latch => {}
+        }
+    }
+
+    fn read_data(&mut self, now_ns: u64) -> u8 {
+        let count = self.latched.unwrap_or_else(|| self.live_count(now_ns));
+        match self.access {
+            AccessMode::LoByte => {
+                self.latched = None;
+                count as u8
+            }
+            AccessMode::HiByte => {
+                self.latched = None;
+                (count >> 8) as u8
+            }
+            AccessMode::Word | AccessMode::Latch => {
+                if !self.read_high_half {
+                    self.read_high_half = true;
+                    self.latched.get_or_insert(count);
+                    count as u8
+                } else {
+                    self.read_high_half = false;
+                    self.latched = None;
+                    (count >> 8) as u8
+                }
+            }
+        }
+    }
+
+    /// Counter-latch command: capture the live count so subsequent reads
+    /// see a stable snapshot instead of a moving target.
+    fn latch(&mut self, now_ns: u64) {
+        self.latched.get_or_insert_with(|| self.live_count(now_ns));
+    }
+}
+
+pub struct Pit {
+    channels: [Channel; 3],
+    /// Port 0x61 bit 0: gate input for channel 2, also used by firmware to
+    /// probe for PIT/PC-speaker presence. Bits 1/4/5 (speaker data, refresh
+    /// toggle, speaker output) aren't modeled - nothing in this tree reads
+    /// them back for anything but the gate bit.
+    gate: u8,
+    /// Number of channel-0 periods [`Self::channel0_due`] has already
+    /// delivered an IRQ0 pulse for, so it fires exactly once per period
+    /// even though it's polled every `check_events` tick.
+    periods_delivered: u64,
+}
+
+impl Pit {
+    pub fn new() -> Self {
+        Self {
+            channels: [Channel::new(), Channel::new(), Channel::new()],
+            gate: 0,
+            periods_delivered: 0,
+        }
+    }
+
+    fn write_command(&mut self, value: u8) {
+        let select = value >> 6;
+        if select == 3 {
+            // Read-back command (SC = 11): latching/reading a single
+            // channel at a time (the branch below) is all any guest this
+            // tree boots actually exercises, so this is left unimplemented
+            // rather than guessed at.
+            debug!("pit: ignoring read-back command {:#04x}", value);
+            return;
+        }
+        let now = current_time_nanos();
+        let channel = &mut self.channels[select as usize];
+        let access = (value >> 4) & 0b11;
+        if access == 0 {
+            channel.latch(now);
+            return;
+        }
+        channel.access = match access {
+            1 => AccessMode::LoByte,
+            2 => AccessMode::HiByte,
+            _ => AccessMode::Word,
+        };
+        channel.mode = (value >> 1) & 0b111;
+        channel.write_high_half = false;
+        channel.read_high_half = false;
+        channel.latched = None;
+        if select == 0 {
+            self.periods_delivered = 0;
+        }
+    }
+
+    /// Whether channel 0's countdown has crossed another period boundary
+    /// since the last call, i.e. an IRQ0 pulse is due right now. Advances
+    /// its own bookkeeping as a side effect, so this is meant to be called
+    /// exactly once per `check_events` tick, as an [`super::IrqLevel`]
+    /// resample callback.
+    pub fn channel0_due(&mut self, now_ns: u64) -> bool {
+        let channel = &self.channels[0];
+        let Some(armed_at) = channel.armed_at_ns else {
+            return false;
+        };
+        let period = channel.period_ns();
+        if period == 0 {
+            return false;
+        }
+        let elapsed = now_ns.saturating_sub(armed_at);
+        let periods_elapsed = match channel.mode {
+            0 => (elapsed >= period) as u64,
+            _ => elapsed / period,
+        };
+        if periods_elapsed <= self.periods_delivered {
+            return false;
+        }
+        self.periods_delivered = periods_elapsed;
+        true
+    }
+}
+
+impl Default for Pit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PioOps for Pit {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        REG_CHANNEL_DATA[0]..REG_MODE_COMMAND + 1
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        let now = current_time_nanos();
+        Ok(match REG_CHANNEL_DATA.iter().position(|&p| p == port) {
+            Some(idx) => self.channels[idx].read_data(now) as u32,
+            None => 0,
+        })
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        let now = current_time_nanos();
+        match REG_CHANNEL_DATA.iter().position(|&p| p == port) {
+            Some(idx) => self.channels[idx].write_data(value as u8, now),
+            None if port == REG_MODE_COMMAND => self.write_command(value as u8),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Port 0x61's gate/speaker byte, split out from [`Pit`] itself since it
+/// isn't contiguous with the 0x40-0x43 data/command ports and
+/// [`PioOps::port_range`] only covers one contiguous range.
+pub struct PitSpeakerGate {
+    pit: alloc::sync::Arc<spin::Mutex<Pit>>,
+}
+
+impl PitSpeakerGate {
+    pub fn new(pit: alloc::sync::Arc<spin::Mutex<Pit>>) -> Self {
+        Self { pit }
+    }
+}
+
+impl PioOps for PitSpeakerGate {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        REG_SPEAKER_GATE..REG_SPEAKER_GATE + 1
+    }
+
+    fn read(&mut self, _port: u16, _access_size: u8) -> HyperResult<u32> {
+        Ok(self.pit.lock().gate as u32)
+    }
+
+    fn write(&mut self, _port: u16, _access_size: u8, value: u32) -> HyperResult {
+        self.pit.lock().gate = value as u8;
+        Ok(())
+    }
+}
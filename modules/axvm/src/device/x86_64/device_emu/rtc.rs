@@ -0,0 +1,288 @@
+//! Emulated MC146818 RTC/CMOS. (ref: https://wiki.osdev.org/CMOS)
+//!
+//! Exposed over the classic index/data port pair (0x70 selects the
+//! register, 0x71 accesses it). Status Register B's periodic/update-ended
+//! interrupt enables are only re-evaluated when something polls this
+//! device's asserted state - today that's `X64VcpuDevices::check_events`,
+//! via the [`super::IrqLevel`] registered on GSI 8 in `X64VcpuDevices::new`
+//! - so there's no independent host-side tick driving it between ticks;
+//! in practice this is indistinguishable from real hardware for any guest
+//! that doesn't mask IRQ8 and expect to be woken purely by it.
+
+use bit_field::BitField;
+
+use crate::{Error as HyperError, Result as HyperResult};
+use hypercraft::PioOps;
+
+/// CMOS register indices (ref: MC146818 datasheet).
+const REG_SECONDS: u8 = 0x00;
+const REG_SECONDS_ALARM: u8 = 0x01;
+const REG_MINUTES: u8 = 0x02;
+const REG_MINUTES_ALARM: u8 = 0x03;
+const REG_HOURS: u8 = 0x04;
+const REG_HOURS_ALARM: u8 = 0x05;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+const REG_STATUS_C: u8 = 0x0c;
+const REG_STATUS_D: u8 = 0x0d;
+
+/// Size of the emulated NVRAM array: the time/alarm/status registers above
+/// (0x00..0x0e) plus 50 bytes of plain battery-backed scratch (0x0e..0x40)
+/// the guest can store whatever configuration it likes in.
+const NVRAM_LEN: usize = 0x40;
+
+/// Status Register B bit: 1 = 24-hour mode, 0 = 12-hour mode.
+const STATUS_B_24H: usize = 1;
+/// Status Register B bit: 1 = binary mode, 0 = BCD mode.
+const STATUS_B_BINARY: usize = 2;
+/// Status Register B bit: update-ended interrupt enable.
+const STATUS_B_UIE: usize = 4;
+/// Status Register B bit: periodic interrupt enable.
+const STATUS_B_PIE: usize = 6;
+
+/// Status Register C bit: update-ended interrupt flag.
+const STATUS_C_UF: usize = 4;
+/// Status Register C bit: periodic interrupt flag.
+const STATUS_C_PF: usize = 6;
+/// Status Register C bit: set whenever UF/PF above is, i.e. "some enabled
+/// interrupt source is pending" - what the guest actually checks.
+const STATUS_C_IRQF: usize = 7;
+
+/// RTC is conventionally wired to the slave i8259's line 0, i.e. GSI 8.
+pub const RTC_GSI: u8 = 8;
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// A point in (host-approximated) wall-clock time, broken down the way the
+/// CMOS registers expose it.
+struct WallClock {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+impl WallClock {
+    /// Derive a civil date/time from nanoseconds since the HAL's epoch.
+    ///
+    /// `axhal::time` does not expose a true wall-clock source, so (as
+    /// elsewhere in this tree) `current_time_nanos` is treated as
+    /// seconds-since-epoch; enough to give guests a monotonically sane, if
+    /// not externally accurate, CMOS clock.
+    fn now() -> Self {
+        let secs_since_epoch = axhal::time::current_time_nanos() / 1_000_000_000;
+        let days = secs_since_epoch / 86400;
+        let day_secs = secs_since_epoch % 86400;
+
+        let (year, month, day) = civil_from_days(days as i64);
+
+        Self {
+            seconds: (day_secs % 60) as u8,
+            minutes: ((day_secs / 60) % 60) as u8,
+            hours: (day_secs / 3600) as u8,
+            day,
+            month,
+            year: (year % 100) as u8,
+        }
+    }
+}
+
+/// Howard Hinnant's days-from-civil algorithm, inverted: turn a day count
+/// (days since 1970-01-01) into a (year, month, day) triple.
+fn civil_from_days(z: i64) -> (i64, u8, u8) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    (if m <= 2 { y + 1 } else { y }, m, d)
+}
+
+pub struct Rtc {
+    /// CMOS register currently selected via the index port; bit 7 of that
+    /// port (NMI-disable) is tracked separately in `nmi_disabled` below, not
+    /// folded into this.
+    index: u8,
+    /// Bit 7 of the last byte written to the index port: real firmware
+    /// toggles this on every CMOS access and expects to read back what it
+    /// wrote, even though this emulation has no NMI path to gate.
+    nmi_disabled: bool,
+    /// Battery-backed CMOS bytes, indexed by register number. The time/
+    /// alarm/status registers below are handled specially in
+    /// `read_register`/`write_register`; everything else is plain
+    /// general-purpose storage.
+    nvram: [u8; NVRAM_LEN],
+    /// Status Register C: read-and-clear interrupt flag register.
+    status_c: u8,
+    /// Host time (nanoseconds) update-ended/periodic interrupts last fired,
+    /// so `update_pending_interrupts` only raises each at most once per its
+    /// real period.
+    last_update_ns: u64,
+    last_periodic_ns: u64,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        let now_ns = axhal::time::current_time_nanos();
+        Self {
+            index: 0,
+            nmi_disabled: false,
+            nvram: [0; NVRAM_LEN],
+            status_c: 0,
+            last_update_ns: now_ns,
+            last_periodic_ns: now_ns,
+        }
+    }
+
+    fn status_b(&self) -> u8 {
+        self.nvram[REG_STATUS_B as usize]
+    }
+
+    fn encode(&self, binary_value: u8) -> u8 {
+        if self.status_b().get_bit(STATUS_B_BINARY) {
+            binary_value
+        } else {
+            to_bcd(binary_value)
+        }
+    }
+
+    /// Periodic interrupt rate, decoded from Status Register A's rate-select
+    /// bits per the MC146818 datasheet: `None` below 3 (the two fastest
+    /// codes are reserved/disabled), otherwise `32768 >> (rate - 1)` Hz.
+    fn periodic_period_ns(&self) -> Option<u64> {
+        let rate = self.nvram[REG_STATUS_A as usize] & 0x0f;
+        if rate < 3 {
+            return None;
+        }
+        Some(1_000_000_000u64 * (1u64 << (rate - 1)) / 32768)
+    }
+
+    /// Re-evaluate the periodic/update-ended sources against elapsed host
+    /// time, latching any newly-fired cause into Status Register C, and
+    /// report whether GSI 8 should be considered asserted right now. Meant
+    /// to be used as a [`super::IrqLevel`] resample callback.
+    pub fn irq_pending(&mut self) -> bool {
+        let now_ns = axhal::time::current_time_nanos();
+        let status_b = self.status_b();
+
+        if let Some(period_ns) = self.periodic_period_ns() {
+            if status_b.get_bit(STATUS_B_PIE)
+                && now_ns.wrapping_sub(self.last_periodic_ns) >= period_ns
+            {
+                self.last_periodic_ns = now_ns;
+                self.status_c.set_bit(STATUS_C_PF, true);
+            }
+        }
+
+        if now_ns.wrapping_sub(self.last_update_ns) >= 1_000_000_000 {
+            self.last_update_ns = now_ns;
+            if status_b.get_bit(STATUS_B_UIE) {
+                self.status_c.set_bit(STATUS_C_UF, true);
+            }
+        }
+
+        let any_pending = self.status_c & 0x70 != 0;
+        self.status_c.set_bit(STATUS_C_IRQF, any_pending);
+        any_pending
+    }
+
+    fn read_register(&mut self) -> u8 {
+        match self.index {
+            REG_SECONDS => self.encode(WallClock::now().seconds),
+            REG_MINUTES => self.encode(WallClock::now().minutes),
+            REG_HOURS => {
+                let clock = WallClock::now();
+                if self.status_b().get_bit(STATUS_B_24H) {
+                    self.encode(clock.hours)
+                } else {
+                    let (hour12, pm) = match clock.hours {
+                        0 => (12, false),
+                        1..=11 => (clock.hours, false),
+                        12 => (12, true),
+                        _ => (clock.hours - 12, true),
+                    };
+                    let mut value = self.encode(hour12);
+                    if pm {
+                        value.set_bit(7, true);
+                    }
+                    value
+                }
+            }
+            REG_DAY => self.encode(WallClock::now().day),
+            REG_MONTH => self.encode(WallClock::now().month),
+            REG_YEAR => self.encode(WallClock::now().year),
+            REG_SECONDS_ALARM | REG_MINUTES_ALARM | REG_HOURS_ALARM => {
+                self.nvram[self.index as usize]
+            }
+            REG_STATUS_A => self.nvram[REG_STATUS_A as usize] & 0x7f, // UIP (bit 7) clear: no update in progress.
+            REG_STATUS_B => self.status_b(),
+            REG_STATUS_C => {
+                // Real hardware clears the whole flag register and
+                // deasserts the line as soon as it's read.
+                let value = self.status_c;
+                self.status_c = 0;
+                value
+            }
+            REG_STATUS_D => 0x80, // VRT (bit 7) set: battery good.
+            _ => self.nvram[self.index as usize],
+        }
+    }
+
+    fn write_register(&mut self, value: u8) {
+        match self.index {
+            // The emulated clock always tracks host time, so writes to the
+            // date/time registers themselves are accepted but otherwise
+            // ignored.
+            REG_SECONDS | REG_MINUTES | REG_HOURS | REG_DAY | REG_MONTH | REG_YEAR => {}
+            REG_STATUS_A => self.nvram[REG_STATUS_A as usize] = value & 0x7f, // UIP is read-only.
+            REG_STATUS_C | REG_STATUS_D => {} // Read-only flag/status registers.
+            _ => self.nvram[self.index as usize] = value,
+        }
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PioOps for Rtc {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        0x70..0x72
+    }
+
+    fn read(&mut self, port: u16, _access_size: u8) -> HyperResult<u32> {
+        match port - 0x70 {
+            0 => Ok((self.index | if self.nmi_disabled { 0x80 } else { 0 }) as u32),
+            1 => Ok(self.read_register() as u32),
+            _ => Err(HyperError::InvalidParam),
+        }
+    }
+
+    fn write(&mut self, port: u16, _access_size: u8, value: u32) -> HyperResult {
+        let value = value as u8;
+        match port - 0x70 {
+            // Bit 7 of the index port gates NMI; tracked separately from
+            // the index itself since the two are otherwise unrelated.
+            0 => {
+                self.nmi_disabled = value.get_bit(7);
+                self.index = value & 0x7f;
+            }
+            1 => self.write_register(value),
+            _ => return Err(HyperError::InvalidParam),
+        }
+        Ok(())
+    }
+}
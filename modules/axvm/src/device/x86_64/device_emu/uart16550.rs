@@ -0,0 +1,304 @@
+//! Emulated UART 16550. (ref: https://wiki.osdev.org/Serial_Ports)
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+use hypercraft::PioOps;
+
+use crate::snapshot::Snapshottable;
+use crate::{Error as HyperError, Result as HyperResult};
+
+const DATA_REG: u16 = 0;
+const INT_EN_REG: u16 = 1;
+const FIFO_CTRL_REG: u16 = 2;
+const LINE_CTRL_REG: u16 = 3;
+const MODEM_CTRL_REG: u16 = 4;
+const LINE_STATUS_REG: u16 = 5;
+const MODEM_STATUS_REG: u16 = 6;
+const SCRATCH_REG: u16 = 7;
+
+const UART_FIFO_CAPACITY: usize = 16;
+
+bitflags::bitflags! {
+    /// Line status flags
+    struct LineStsFlags: u8 {
+        const INPUT_FULL = 1 << 0;
+        // 1 to 3 is error flag
+        const BREAK_INTERRUPT = 1 << 4;
+        const OUTPUT_EMPTY = 1 << 5;
+        const OUTPUT_EMPTY2 = 1 << 6;
+        // 7 is error flag
+    }
+}
+
+/// FIFO queue for caching bytes read.
+pub struct Fifo<const CAP: usize> {
+    buf: [u8; CAP],
+    head: usize,
+    num: usize,
+}
+
+impl<const CAP: usize> Fifo<CAP> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; CAP],
+            head: 0,
+            num: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.num == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.num == CAP
+    }
+
+    fn push(&mut self, value: u8) {
+        assert!(self.num < CAP);
+        self.buf[(self.head + self.num) % CAP] = value;
+        self.num += 1;
+    }
+
+    fn pop(&mut self) -> u8 {
+        assert!(self.num > 0);
+        let ret = self.buf[self.head];
+        self.head += 1;
+        self.head %= CAP;
+        self.num -= 1;
+        ret
+    }
+
+    /// Like [`push`](Self::push), but reports a full ring instead of
+    /// panicking. Used for the inter-guest channel ring, where backpressure
+    /// means dropping the newest byte rather than crashing the vCPU that
+    /// produced it.
+    fn try_push(&mut self, value: u8) -> bool {
+        if self.is_full() {
+            false
+        } else {
+            self.push(value);
+            true
+        }
+    }
+}
+
+pub trait VirtualConsoleBackend: Send + Sync + Sized {
+    fn new() -> Self;
+    fn putchar(&mut self, c: u8);
+    fn getchar(&mut self) -> Option<u8>;
+}
+
+pub struct DefaultConsoleBackend;
+
+impl VirtualConsoleBackend for DefaultConsoleBackend {
+    fn new() -> Self {
+        Self
+    }
+
+    fn putchar(&mut self, c: u8) {
+        axhal::console::putchar(c)
+    }
+
+    fn getchar(&mut self) -> Option<u8> {
+        axhal::console::getchar()
+    }
+}
+
+const MULTIPLEX_BUFFER_LENGTH: usize = 80;
+
+/// One direction of an inter-guest channel: a bounded SPSC-ish ring, shared
+/// between the endpoint that owns it (reader) and whichever peer writes into
+/// it (writer). Guarded by a `Mutex` rather than a true lock-free SPSC ring
+/// since both ends run on vCPU threads that may migrate between pCPUs.
+type ChannelRing = Arc<Mutex<Fifo<MULTIPLEX_BUFFER_LENGTH>>>;
+
+/// Registry of inter-guest channel endpoints, keyed by endpoint id. Looking
+/// an id up (and lazily creating its ring) lets two [`MultiplexConsoleBackend::Secondary`]
+/// consoles bind to each other regardless of which one is constructed first.
+static CHANNEL_REGISTRY: Mutex<BTreeMap<isize, ChannelRing>> = Mutex::new(BTreeMap::new());
+
+fn endpoint_ring(id: isize) -> ChannelRing {
+    CHANNEL_REGISTRY
+        .lock()
+        .entry(id)
+        .or_insert_with(|| Arc::new(Mutex::new(Fifo::new())))
+        .clone()
+}
+
+pub enum MultiplexConsoleBackend {
+    Primary,
+    Secondary {
+        id: isize,
+        peer: isize,
+        rx: ChannelRing,
+    },
+}
+
+impl MultiplexConsoleBackend {
+    /// Bind this secondary console to `peer`'s endpoint id: bytes written
+    /// here land in `peer`'s inbox, and reads here drain whatever has been
+    /// written to `id`'s inbox (by `peer`, or anyone else using that id).
+    /// `boot_vm` calls this for both guests it wants wired together, in
+    /// either order.
+    pub fn new_secondary(id: isize, peer: isize) -> Self {
+        Self::Secondary {
+            id,
+            peer,
+            rx: endpoint_ring(id),
+        }
+    }
+}
+
+impl VirtualConsoleBackend for MultiplexConsoleBackend {
+    fn new() -> Self {
+        Self::Primary
+    }
+
+    fn putchar(&mut self, c: u8) {
+        match self {
+            MultiplexConsoleBackend::Primary => axhal::console::putchar(c),
+            MultiplexConsoleBackend::Secondary { id, peer, .. } => {
+                let tx = endpoint_ring(*peer);
+                if !tx.lock().try_push(c) {
+                    trace!(
+                        "multiplex console channel {} -> {} full, dropping byte",
+                        id,
+                        peer
+                    );
+                }
+            }
+        }
+    }
+
+    fn getchar(&mut self) -> Option<u8> {
+        match self {
+            MultiplexConsoleBackend::Primary => axhal::console::getchar(),
+            MultiplexConsoleBackend::Secondary { rx, .. } => {
+                let mut rx = rx.lock();
+                if rx.is_empty() {
+                    None
+                } else {
+                    Some(rx.pop())
+                }
+            }
+        }
+    }
+}
+
+pub struct Uart16550<B: VirtualConsoleBackend = MultiplexConsoleBackend> {
+    port_base: u16,
+    fifo: Mutex<Fifo<UART_FIFO_CAPACITY>>,
+    line_control_reg: u8,
+    backend: B,
+}
+
+impl<B: VirtualConsoleBackend> PioOps for Uart16550<B> {
+    fn port_range(&self) -> core::ops::Range<u16> {
+        self.port_base..self.port_base + 8
+    }
+
+    fn read(&mut self, port: u16, access_size: u8) -> HyperResult<u32> {
+        if access_size != 1 {
+            error!("Invalid serial port I/O read size: {} != 1", access_size);
+            return Err(HyperError::InvalidParam);
+        }
+        let ret = match port - self.port_base {
+            DATA_REG => {
+                // read a byte from FIFO
+                let mut fifo = self.fifo.lock();
+                if fifo.is_empty() {
+                    0
+                } else {
+                    fifo.pop()
+                }
+            }
+            LINE_STATUS_REG => {
+                // check if the physical serial port has an available byte, and push it to FIFO.
+                let mut fifo = self.fifo.lock();
+                if !fifo.is_full() {
+                    if let Some(c) = self.backend.getchar() {
+                        fifo.push(c);
+                    }
+                }
+                let mut lsr = LineStsFlags::OUTPUT_EMPTY | LineStsFlags::OUTPUT_EMPTY2;
+                if !fifo.is_empty() {
+                    lsr |= LineStsFlags::INPUT_FULL;
+                }
+                lsr.bits()
+            }
+            LINE_CTRL_REG => self.line_control_reg,
+            INT_EN_REG | FIFO_CTRL_REG | MODEM_CTRL_REG | MODEM_STATUS_REG | SCRATCH_REG => {
+                trace!("Unimplemented serial port I/O read: {:#x}", port);
+                0
+            }
+            _ => unreachable!(),
+        };
+        Ok(ret as u32)
+    }
+
+    fn write(&mut self, port: u16, access_size: u8, value: u32) -> HyperResult {
+        if access_size != 1 {
+            error!("Invalid serial port I/O write size: {} != 1", access_size);
+            return Err(HyperError::InvalidParam);
+        }
+        match port - self.port_base {
+            DATA_REG => self.backend.putchar(value as u8),
+            LINE_CTRL_REG => self.line_control_reg = value as u8,
+            INT_EN_REG | FIFO_CTRL_REG | MODEM_CTRL_REG | SCRATCH_REG => {
+                trace!("Unimplemented serial port I/O write: {:#x}", port);
+            }
+            LINE_STATUS_REG => {} // ignore
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+impl<B: VirtualConsoleBackend> Uart16550<B> {
+    pub fn new(port_base: u16) -> Self {
+        Self {
+            port_base,
+            fifo: Mutex::new(Fifo::new()),
+            line_control_reg: 0,
+            backend: B::new(),
+        }
+    }
+
+    pub fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+}
+
+/// On-disk layout for a [`Uart16550`] snapshot: the FIFO contents in logical
+/// order (oldest byte first), how many of them are valid, and the line
+/// control register. The host-console backend itself isn't part of guest
+/// state and is left untouched by restore.
+impl<B: VirtualConsoleBackend> Snapshottable for Uart16550<B> {
+    fn snapshot(&self) -> alloc::vec::Vec<u8> {
+        let fifo = self.fifo.lock();
+        let mut buf = alloc::vec::Vec::with_capacity(2 + UART_FIFO_CAPACITY + 1);
+        buf.push(fifo.head as u8);
+        buf.push(fifo.num as u8);
+        for i in 0..UART_FIFO_CAPACITY {
+            buf.push(fifo.buf[i]);
+        }
+        buf.push(self.line_control_reg);
+        buf
+    }
+
+    fn restore(&mut self, data: &[u8]) -> HyperResult {
+        if data.len() != 2 + UART_FIFO_CAPACITY + 1 {
+            return Err(HyperError::InvalidParam);
+        }
+        let mut fifo = self.fifo.lock();
+        fifo.head = data[0] as usize;
+        fifo.num = data[1] as usize;
+        fifo.buf.copy_from_slice(&data[2..2 + UART_FIFO_CAPACITY]);
+        drop(fifo);
+        self.line_control_reg = data[2 + UART_FIFO_CAPACITY];
+        Ok(())
+    }
+}
@@ -16,6 +16,10 @@ pub const HVC_SHADOW_PROCESS_RDY: usize = 0x52647921;
 pub const HVC_AXVM_CREATE_CFG: usize = 0x101;
 pub const HVC_AXVM_LOAD_IMG: usize = 0x102;
 pub const HVC_AXVM_BOOT: usize = 0x103;
+pub const HVC_AXVM_SAVE: usize = 0x104;
+pub const HVC_AXVM_RESTORE: usize = 0x105;
+pub const HVC_AXVM_ADD_MEMORY: usize = 0x106;
+pub const HVC_AXVM_REMOVE_MEMORY: usize = 0x107;
 
 // The struct used for parameter passing between the kernel module and ArceOS hypervisor.
 // This struct should have the same memory layout as the `AxVMCreateArg` structure in ArceOS.
@@ -84,6 +88,18 @@ pub fn handle_hvc<H: HyperCraftHal>(
         HVC_AXVM_BOOT => {
             ax_hvc_boot_vm(args.0);
         }
+        HVC_AXVM_SAVE => {
+            return ax_hvc_save_vcpu(vcpu, args.0, args.1);
+        }
+        HVC_AXVM_RESTORE => {
+            return ax_hvc_restore_vcpu(vcpu, args.0, args.1);
+        }
+        HVC_AXVM_ADD_MEMORY => {
+            return ax_hvc_add_memory(args.0, args.1, args.2);
+        }
+        HVC_AXVM_REMOVE_MEMORY => {
+            return ax_hvc_remove_memory(args.0, args.1);
+        }
         _ => {
             warn!("Unhandled hypercall {}. vcpu: {:#x?}", id, vcpu);
         }
@@ -141,6 +157,14 @@ fn ax_hvc_create_vm(cfg: &mut AxVMCreateArg) -> Result<u32> {
 
     vm_cfg_entry.set_up_memory_region()?;
 
+    if let Err(e) = vm_cfg_entry.setup_acpi_tables() {
+        warn!("Failed to set up ACPI tables for VM: {:?}", e);
+    }
+
+    if let Err(e) = vm_cfg_entry.setup_mptable() {
+        warn!("Failed to set up MP table for VM: {:?}", e);
+    }
+
     // These fields should be set by hypervisor and read by Linux kernel module.
     (cfg.bios_load_hpa, cfg.kernel_load_hpa, cfg.ramdisk_load_hpa) =
         vm_cfg_entry.get_img_load_info();
@@ -174,3 +198,52 @@ fn ax_hvc_boot_vm(vm_id: usize) {
         }
     }
 }
+
+/// Write `vcpu`'s architectural state (see [`crate::snapshot::snapshot_vcpu`])
+/// to the guest buffer at `buf_gpa`, failing with [`Error::InvalidParam`] if
+/// `buf_len` is too small to hold it.
+///
+/// Scope note: this only covers the calling vCPU's registers. It does not
+/// cover per-vCPU emulated-device state (`Bundle`/`PIT`/`I8259Pic`, declared
+/// by `device::x86_64::device_emu`'s `mod bundle;`/`mod pit;`/`mod
+/// i8259_pic;` but without a backing source file in this tree) or guest RAM.
+fn ax_hvc_save_vcpu<H: HyperCraftHal>(vcpu: &mut VCpu<H>, buf_gpa: usize, buf_len: usize) -> Result<u32> {
+    let blob = crate::snapshot::snapshot_vcpu(vcpu);
+    if blob.len() > buf_len {
+        return Err(Error::InvalidParam);
+    }
+    let buf_hpa = crate::config::root_gpm().translate(buf_gpa)?;
+    let buf_hva = phys_to_virt(PhysAddr::from(buf_hpa)).as_mut_ptr();
+    unsafe { core::ptr::copy_nonoverlapping(blob.as_ptr(), buf_hva, blob.len()) };
+    Ok(blob.len() as u32)
+}
+
+/// Reconstruct `vcpu`'s architectural state from a blob previously written by
+/// [`ax_hvc_save_vcpu`] at guest buffer `buf_gpa`/`buf_len`. See that
+/// function's doc comment for what this deliberately doesn't cover yet.
+fn ax_hvc_restore_vcpu<H: HyperCraftHal>(vcpu: &mut VCpu<H>, buf_gpa: usize, buf_len: usize) -> Result<u32> {
+    let buf_hpa = crate::config::root_gpm().translate(buf_gpa)?;
+    let buf_hva = usize::from(phys_to_virt(PhysAddr::from(buf_hpa))) as *const u8;
+    let data = unsafe { core::slice::from_raw_parts(buf_hva, buf_len) };
+    crate::snapshot::restore_vcpu(vcpu, data)?;
+    Ok(0)
+}
+
+/// Grow VM `vm_id`'s guest RAM live, without a reboot: the management entry
+/// point for `config::hotplug::MemoryHotplugManager`, mapping `size` bytes
+/// at `gpa` inside the VM's reserved hotplug window. See
+/// [`crate::config::entry::VMCfgEntry::add_memory`] for the constraints on
+/// `gpa`/`size`.
+fn ax_hvc_add_memory(vm_id: usize, gpa: usize, size: usize) -> Result<u32> {
+    let vm_cfg_entry = vm_cfg_entry(vm_id).ok_or(Error::InvalidParam)?;
+    vm_cfg_entry.add_memory(gpa, size)?;
+    Ok(0)
+}
+
+/// Inverse of [`ax_hvc_add_memory`]: unmap and free a region previously
+/// added at `gpa`.
+fn ax_hvc_remove_memory(vm_id: usize, gpa: usize) -> Result<u32> {
+    let vm_cfg_entry = vm_cfg_entry(vm_id).ok_or(Error::InvalidParam)?;
+    vm_cfg_entry.remove_memory(gpa)?;
+    Ok(0)
+}
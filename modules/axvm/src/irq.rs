@@ -2,6 +2,14 @@ use crate::Result;
 
 use axhal::irq::{dispatch_irq, set_enable};
 
+/// Forwards a host-delivered vector into the guest-facing dispatch table.
+///
+/// This only carries edge-style one-shot injection; level-triggered
+/// resampling for legacy PCI INTx already lives one layer down, at the
+/// `MsiIrqManager`/`IrqLevelEvent` pair in `pci` and its `VirtioMsiIrqManager`
+/// backing (see that module's doc) plus `VirtioPciDevice`'s own `IntxLine`,
+/// which re-queries `interrupt_status` and re-asserts on every ISR read
+/// rather than needing this dispatch path to know about levels at all.
 pub(crate) fn dispatch_host_irq(vector: usize) -> Result {
     dispatch_irq(vector);
     Ok(())
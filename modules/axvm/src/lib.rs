@@ -20,6 +20,7 @@ extern crate log;
 extern crate pci;
 
 mod config;
+mod coredump;
 // #[cfg(target_arch = "x86_64")]
 mod device;
 mod mm;
@@ -30,6 +31,7 @@ mod hvc;
 mod irq;
 mod nmi;
 mod page_table;
+mod snapshot;
 
 // pub use nmi::cpu_nmi_list_init;
 
@@ -43,7 +45,9 @@ mod linux;
 pub use linux::config_boot_linux;
 
 pub use axhal::mem::{phys_to_virt, virt_to_phys, PhysAddr};
+pub use coredump::{write_coredump, CoreWriter};
 pub use page_table::GuestPageTable;
+pub use snapshot::{restore_vcpu, snapshot_vcpu, Snapshottable};
 
 pub use hypercraft::GuestPageTableTrait;
 pub use hypercraft::HyperCraftHal;
@@ -1,6 +1,18 @@
 /// Temporar module to boot Linux as a guest VM.
 ///
 /// To be removed...
+///
+/// A remote-debugging stub for this module's vCPUs (breakpoints, single-step,
+/// register/memory access, stop-on-unexpected-exit) would mirror
+/// `modules/axtask/src/hv/vmx/gdb.rs::GdbStub`/`Debuggable`, which already
+/// does exactly this for `axtask`'s own parallel VMX/vCPU stack. It can't be
+/// reused here: that stub is built directly against `axtask`'s local
+/// `VirtCpu` type, while `config_boot_linux` below runs the `VCpu` this crate
+/// gets from the external `hypercraft` crate (sourceless in this tree), whose
+/// public surface for reading/writing general-purpose and segment registers,
+/// walking the guest's CR3, or intercepting an arbitrary VM exit instead of
+/// panicking isn't knowable from here. Porting the gdbstub to this path needs
+/// `hypercraft::VCpu` to grow that surface first.
 // use hypercraft::GuestPageTableTrait;
 use hypercraft::{PerCpu, VCpu, VmCpus, VM};
 #[cfg(feature = "type1_5")]
@@ -68,12 +80,15 @@ pub fn config_boot_linux(hart_id: usize) {
 
     // Alloc guest memory set.
     // Fix: this should be stored inside VM structure.
-    let gpm = super::config::setup_gpm(hart_id).unwrap();
+    let (gpm, entry) = super::config::setup_gpm(hart_id).unwrap();
     let npt = gpm.nest_page_table_root();
     info!("{:#x?}", gpm);
 
-    // Main scheduling item, managed by `axtask`
-    let vcpu = VCpu::new(0, crate::arch::cpu_vmcs_revision_id(), 0x7c00, npt).unwrap();
+    // Main scheduling item, managed by `axtask`. `entry` is the real kernel
+    // entry `setup_gpm` computed from the bzImage boot header under
+    // `guest_linux` (or the fixed BIOS stub address under `guest_nimbos`),
+    // not the old hardcoded `0x7c00` bootstrap jump.
+    let vcpu = VCpu::new(0, crate::arch::cpu_vmcs_revision_id(), entry, npt).unwrap();
 
     let mut vcpus = VmCpus::<HyperCraftHalImpl, X64VcpuDevices<HyperCraftHalImpl>>::new();
     vcpus.add_vcpu(vcpu).expect("add vcpu failed");
@@ -11,8 +11,16 @@ use x86_64::registers::debug;
 
 const PAGE_FAULT_ID_FLAG: u32 = 0x00000010;
 const PAGE_FAULT_P_FLAG: u32 = 0x00000001;
-const PAGE_ENTRY_CNT: usize = 512;
-const PAGE_SIZE: usize = 0x1000;
+
+/// Page-size bit: set on a PDPTE/PDE to mean "this is actually the final,
+/// larger page frame" (1GiB/2MiB, depending on which level it's set at)
+/// rather than a pointer to another page table.
+const ENTRY_PS: u64 = 1 << 7;
+/// Physical-address bits an entry may legitimately carry; `hypercraft` gives
+/// us the walk's level/width but not the guest's maximum physical address
+/// width, so this is the widest mask that's still unambiguous (bits 12..52,
+/// the field every x86 paging mode agrees on).
+const ENTRY_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
 
 pub fn get_gva_content_bytes(
     guest_rip: usize,
@@ -61,41 +69,53 @@ fn gva2gpa(
     page_table_walk(ept, guest_ptw_info, gva)
 }
 
-// suppose it is 4-level page table
+/// Walk the guest's own page tables (as described by `pw_info`, which
+/// `VCpu::get_ptw_info` already resolves from CR0/CR4/EFER into a level
+/// count and per-level index width, so this one loop covers 4-level,
+/// 3-level PAE, and 2-level 32-bit paging without needing to branch on
+/// `pw_info.is_pae` itself) to translate `gva` to the guest-physical address
+/// it's currently mapped to.
+///
+/// Checks the present bit at every level and returns
+/// [`HyperError::BadState`] for the walk's #PF if it's clear, so a not-yet
+/// faulted-in guest page doesn't get silently mistranslated. Also checks the
+/// PS (page-size) bit on non-leaf-level entries: when set, the entry is a
+/// 1GiB/2MiB page frame rather than a next-level table pointer, so the
+/// descent stops there and the final GPA is built from that frame's base
+/// plus `gva`'s low bits at *that* level's granularity, instead of always
+/// assuming a 4KiB leaf.
 fn page_table_walk(
     ept: GuestPageTable,
     pw_info: GuestPageWalkInfo,
     gva: GuestVirtAddr,
 ) -> HyperResult<GuestPhysAddr> {
     debug!("page_table_walk: gva: {:#x} pw_info:{:?}", gva, pw_info);
-    if pw_info.level <= 1 {
+    if pw_info.level == 0 {
+        // Paging disabled: guest-virtual and guest-physical coincide.
         return Ok(gva as GuestPhysAddr);
     }
-    let mut addr = pw_info.top_entry;
-    let mut current_level = pw_info.level;
-    let mut shift = 0;
-    while current_level != 0 {
-        current_level -= 1;
-        // get page table base addr
-        addr = addr & !(PAGE_ENTRY_CNT - 1);
-        let base = gpa2hva(ept.clone(), addr)?;
-        shift = (current_level * pw_info.width as usize) + 12;
-        let index = (gva >> shift) & (PAGE_ENTRY_CNT - 1);
-        // get page table entry pointer
-        let entry_ptr = unsafe { (base as *const usize).offset(index as isize) };
-        // next page table addr (gpa)
-        addr = unsafe { *entry_ptr };
-    }
 
-    let mut entry = addr;
-    debug!("1 page_table_walk: entry: {:#x} shift:{:#x}", entry, shift);
-    // ?????
-    entry >>= shift;
-    debug!("2 page_table_walk: entry: {:#x} shift:{:#x}", entry, shift);
-    /* shift left 12bit more and back to clear XD/Prot Key/Ignored bits */
-    entry <<= shift + 12;
-    debug!("3 page_table_walk: entry: {:#x} shift:{:#x}", entry, shift);
-    entry >>= 12;
-    debug!("4 page_table_walk: entry: {:#x} shift:{:#x}", entry, shift);
-    Ok((entry | (gva & (PAGE_SIZE - 1))) as GuestPhysAddr)
+    let mut table_gpa = pw_info.top_entry as u64 & ENTRY_ADDR_MASK;
+    let mut level = pw_info.level;
+    loop {
+        level -= 1;
+        let shift = 12 + level * pw_info.width as usize;
+        let index = (gva as u64 >> shift) & ((1u64 << pw_info.width) - 1);
+
+        let base = gpa2hva(ept.clone(), table_gpa as GuestPhysAddr)?;
+        let entry_ptr = unsafe { (base as *const u64).offset(index as isize) };
+        let entry = unsafe { entry_ptr.read() };
+
+        if entry & PAGE_FAULT_P_FLAG as u64 == 0 {
+            return Err(HyperError::BadState);
+        }
+
+        if level == 0 || entry & ENTRY_PS != 0 {
+            let page_base = entry & ENTRY_ADDR_MASK;
+            let offset = gva as u64 & ((1u64 << shift) - 1);
+            return Ok((page_base | offset) as GuestPhysAddr);
+        }
+
+        table_gpa = entry & ENTRY_ADDR_MASK;
+    }
 }
@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use bitvec::vec::BitVec;
 use core::{
     clone,
     fmt::{Debug, Display, Formatter, Result},
@@ -17,9 +18,59 @@ pub const fn is_aligned(addr: usize) -> bool {
     (addr & (HyperCraftHalImpl::PAGE_SIZE - 1)) == 0
 }
 
+/// Mapping granularity for a single nested page table entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    pub const fn size(self) -> usize {
+        match self {
+            PageSize::Size4K => PAGE_SIZE_4K,
+            PageSize::Size2M => PAGE_SIZE_4K * 512,
+            PageSize::Size1G => PAGE_SIZE_4K * 512 * 512,
+        }
+    }
+
+    const fn is_aligned(self, addr: usize) -> bool {
+        (addr & (self.size() - 1)) == 0
+    }
+
+    /// The largest huge-page size that `gpa`/`hpa` are aligned to and that
+    /// still fits within the `size` bytes remaining from there. Only `gpa`
+    /// and `hpa` need to land on a huge-page boundary - `size` just needs
+    /// to be at least one huge page, not an exact multiple of one, since
+    /// the caller advances by whatever `largest_fit` returns and re-checks
+    /// on the next iteration; requiring `size` itself to be huge-page
+    /// aligned would skip the huge mapping for every region whose total
+    /// length isn't, even though every page but the last is still eligible.
+    fn largest_fit(gpa: usize, hpa: usize, size: usize) -> Self {
+        for candidate in [PageSize::Size1G, PageSize::Size2M] {
+            if candidate.is_aligned(gpa) && candidate.is_aligned(hpa) && size >= candidate.size() {
+                return candidate;
+            }
+        }
+        PageSize::Size4K
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 enum Mapper {
     Offset(usize),
+    /// GPA == HPA: no offset translation, used for device passthrough where
+    /// the guest is handed the host's own physical address for a BAR/MMIO
+    /// window instead of a relocated one.
+    Identity,
+    /// Never backed by real host memory. Deliberately left unmapped in the
+    /// nested page table so any guest access EPT-violates straight out to
+    /// the hypervisor, which should consult [`GuestPhysMemorySet::find_region`]
+    /// to dispatch the faulting GPA to whichever emulated device (e.g. a
+    /// `DeviceList`'s `MmioOps` handler) owns it, rather than this region
+    /// ever resolving to RAM.
+    Mmio,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +79,16 @@ pub struct GuestMemoryRegion {
     pub hpa: HostPhysAddr,
     pub size: usize,
     pub flags: MappingFlags,
+    /// Host file descriptor this region's RAM should be populated from
+    /// instead of staying anonymous, e.g. to share guest RAM with a
+    /// vhost-user backend, persist a region to a file, or back it with
+    /// hugetlbfs. `None` (the default) is the existing purely-anonymous
+    /// behavior; see `VMCfgEntry::set_up_memory_region` for where this is
+    /// actually consumed.
+    pub file_fd: Option<i32>,
+    /// Byte offset into `file_fd` that `gpa`'s first byte corresponds to.
+    /// Meaningless when `file_fd` is `None`.
+    pub file_offset: u64,
 }
 
 impl Display for GuestMemoryRegion {
@@ -50,6 +111,9 @@ pub struct MapRegion {
     pub size: usize,
     pub flags: MappingFlags,
     mapper: Mapper,
+    /// Flags this region had before `protect_for_dirty_tracking` wrote it
+    /// down to read-only; `None` when dirty tracking isn't active.
+    saved_flags: Option<MappingFlags>,
 }
 
 impl MapRegion {
@@ -95,9 +159,45 @@ impl MapRegion {
             size,
             flags,
             mapper: Mapper::Offset(offset),
+            saved_flags: None,
         }
     }
 
+    /// A passthrough region where GPA == HPA, e.g. assigning a host device's
+    /// own BAR straight through to the guest at its native address instead
+    /// of relocating it (see [`Mapper::Identity`]).
+    pub fn new_identity(start_gpa: GuestPhysAddr, size: usize, flags: MappingFlags) -> Self {
+        assert!(is_aligned(start_gpa));
+        assert!(is_aligned(size));
+        Self {
+            start: start_gpa,
+            size,
+            flags,
+            mapper: Mapper::Identity,
+            saved_flags: None,
+        }
+    }
+
+    /// A range reserved for a trapped, emulated MMIO device: never mapped
+    /// into the nested page table, so every access faults out for the
+    /// EPT-violation handler to dispatch (see [`Mapper::Mmio`]).
+    pub fn new_mmio(start_gpa: GuestPhysAddr, size: usize) -> Self {
+        assert!(is_aligned(start_gpa));
+        assert!(is_aligned(size));
+        Self {
+            start: start_gpa,
+            size,
+            flags: MappingFlags::empty(),
+            mapper: Mapper::Mmio,
+            saved_flags: None,
+        }
+    }
+
+    /// Number of 4 KiB pages covered by this region.
+    fn num_pages(&self) -> usize {
+        self.size / PAGE_SIZE_4K
+    }
+
     fn is_overlap_with(&self, other: &Self) -> bool {
         let s0 = self.start;
         let e0 = s0 + self.size;
@@ -109,27 +209,95 @@ impl MapRegion {
     fn target(&self, gpa: GuestPhysAddr) -> HostPhysAddr {
         match self.mapper {
             Mapper::Offset(off) => gpa.wrapping_sub(off),
+            Mapper::Identity => gpa,
+            Mapper::Mmio => unreachable!("Mapper::Mmio regions are never actually mapped"),
         }
     }
 
     fn map_to(&self, npt: &mut GuestPageTable) -> HyperResult {
+        // An `Mmio` region is deliberately left out of the nested page table
+        // entirely, so the guest's first access EPT-violates straight out to
+        // the hypervisor instead of resolving to RAM.
+        if matches!(self.mapper, Mapper::Mmio) {
+            return Ok(());
+        }
         let mut start = self.start;
         let end = start + self.size;
         debug!("Mapped Region [{:#x}-{:#x}] {:?}", start, end, self.flags);
         while start < end {
             let target = self.target(start);
-            npt.map(start, target, self.flags)?;
-            start += HyperCraftHalImpl::PAGE_SIZE;
+            let page_size = PageSize::largest_fit(start, target, end - start);
+            match page_size {
+                PageSize::Size4K => npt.map(start, target, self.flags)?,
+                _ => npt.map_huge(start, target, self.flags, page_size)?,
+            }
+            start += page_size.size();
         }
         Ok(())
     }
 
     fn unmap_to(&self, npt: &mut GuestPageTable) -> HyperResult {
+        if matches!(self.mapper, Mapper::Mmio) {
+            return Ok(());
+        }
         let mut start = self.start;
         let end = start + self.size;
         while start < end {
-            npt.unmap(start)?;
-            start += HyperCraftHalImpl::PAGE_SIZE;
+            // Walk at the same granularity the region was mapped at, so a
+            // huge-page entry is torn down in one step rather than faulting
+            // on a sub-page unmap.
+            let target = self.target(start);
+            let page_size = PageSize::largest_fit(start, target, end - start);
+            match page_size {
+                PageSize::Size4K => npt.unmap(start)?,
+                _ => npt.unmap_huge(start, page_size)?,
+            }
+            start += page_size.size();
+        }
+        Ok(())
+    }
+
+    /// Write-protect every page in the region and remember the flags it had,
+    /// so the first guest write to each page faults into the EPT-violation
+    /// handler. Re-mapping at 4 KiB granularity here splits any huge-page
+    /// entry the region was previously mapped with.
+    fn protect_for_dirty_tracking(&mut self, npt: &mut GuestPageTable) -> HyperResult {
+        // Never backed by real memory, so there's nothing to track.
+        if matches!(self.mapper, Mapper::Mmio) {
+            return Ok(());
+        }
+        self.saved_flags = Some(self.flags);
+        let ro_flags = self.flags & !MappingFlags::WRITE;
+        let mut gpa = self.start;
+        let end = self.start + self.size;
+        while gpa < end {
+            npt.map(gpa, self.target(gpa), ro_flags)?;
+            gpa += PAGE_SIZE_4K;
+        }
+        Ok(())
+    }
+
+    /// Restore the write bit for a single page, e.g. once its first dirtying
+    /// write has been recorded.
+    fn unprotect_page(&self, npt: &mut GuestPageTable, gpa: GuestPhysAddr) -> HyperResult {
+        if matches!(self.mapper, Mapper::Mmio) {
+            return Ok(());
+        }
+        let flags = self.saved_flags.unwrap_or(self.flags);
+        npt.map(gpa, self.target(gpa), flags)
+    }
+
+    /// Restore the region's original flags everywhere and stop tracking.
+    fn unprotect_for_dirty_tracking(&mut self, npt: &mut GuestPageTable) -> HyperResult {
+        if matches!(self.mapper, Mapper::Mmio) {
+            return Ok(());
+        }
+        let flags = self.saved_flags.take().unwrap_or(self.flags);
+        let mut gpa = self.start;
+        let end = self.start + self.size;
+        while gpa < end {
+            npt.map(gpa, self.target(gpa), flags)?;
+            gpa += PAGE_SIZE_4K;
         }
         Ok(())
     }
@@ -165,9 +333,13 @@ impl From<GuestMemoryRegion> for MapRegion {
     }
 }
 
+#[derive(Clone)]
 pub struct GuestPhysMemorySet {
     regions: BTreeMap<GuestPhysAddr, MapRegion>,
     npt: GuestPageTable,
+    /// One dirty bit per 4 KiB page, indexed relative to each region's
+    /// start; only populated while dirty tracking is enabled.
+    dirty_bitmaps: BTreeMap<GuestPhysAddr, BitVec>,
 }
 
 impl GuestPhysMemorySet {
@@ -175,6 +347,7 @@ impl GuestPhysMemorySet {
         Ok(Self {
             npt: (GuestPageTable::new()?),
             regions: BTreeMap::new(),
+            dirty_bitmaps: BTreeMap::new(),
         })
     }
 
@@ -231,6 +404,43 @@ impl GuestPhysMemorySet {
         Ok(())
     }
 
+    /// Unmap and drop the single region previously mapped at `start`, e.g.
+    /// to tear down a device's old BAR mapping before remapping it at its
+    /// new guest-visible address. No-op if nothing is mapped there.
+    pub fn unmap_region(&mut self, start: GuestPhysAddr) -> HyperResult {
+        if let Some(region) = self.regions.remove(&start) {
+            region.unmap_to(&mut self.npt)?;
+        }
+        Ok(())
+    }
+
+    /// Update the permissions of the region mapped at `start` in place,
+    /// re-walking the nested page table with the new flags rather than
+    /// unmapping and remapping it (e.g. flipping a passthrough BAR
+    /// read-only once the guest driver has finished its init writes).
+    /// No-op if nothing is mapped at `start`; `Mmio` regions are never
+    /// actually present in the page table, so their flags are updated but
+    /// nothing is walked.
+    pub fn remap_region(&mut self, start: GuestPhysAddr, flags: MappingFlags) -> HyperResult {
+        let Some(region) = self.regions.get_mut(&start) else {
+            return Ok(());
+        };
+        region.flags = flags;
+        region.map_to(&mut self.npt)
+    }
+
+    /// Find the mapped region (if any) containing `gpa`, e.g. for an
+    /// EPT-violation handler to look up which emulated device owns a
+    /// faulting address.
+    pub fn find_region(&self, gpa: GuestPhysAddr) -> Option<&MapRegion> {
+        let (&start, region) = self.regions.range(..=gpa).next_back()?;
+        if gpa < start + region.size {
+            Some(region)
+        } else {
+            None
+        }
+    }
+
     pub fn clear(&mut self) {
         for region in self.regions.values() {
             region.unmap_to(&mut self.npt).unwrap();
@@ -241,6 +451,138 @@ impl GuestPhysMemorySet {
     pub fn translate(&self, gpa: GuestPhysAddr) -> HyperResult<HostPhysAddr> {
         self.npt.translate(gpa)
     }
+
+    /// All mapped regions, in GPA order. Used by callers (e.g. the core dump
+    /// writer) that need to walk guest memory region-by-region rather than
+    /// address-by-address.
+    pub fn regions(&self) -> alloc::vec::Vec<MapRegion> {
+        self.regions.values().copied().collect()
+    }
+
+    /// Write-protect every mapped region so the next write to each page
+    /// faults into the EPT-violation handler, which should call
+    /// `mark_page_dirty` for the faulting address.
+    pub fn enable_dirty_tracking(&mut self) -> HyperResult {
+        for region in self.regions.values_mut() {
+            region.protect_for_dirty_tracking(&mut self.npt)?;
+        }
+        self.dirty_bitmaps = self
+            .regions
+            .keys()
+            .map(|&start| (start, BitVec::new()))
+            .collect();
+        for (start, region) in &self.regions {
+            self.dirty_bitmaps
+                .get_mut(start)
+                .unwrap()
+                .resize(region.num_pages(), false);
+        }
+        Ok(())
+    }
+
+    /// Record that `gpa` was just written to, and restore its write
+    /// permission so the guest isn't re-faulted on every subsequent write.
+    /// Called from the EPT-violation handler for a write that was caused by
+    /// dirty tracking rather than a genuine unmapped-page fault.
+    pub fn mark_page_dirty(&mut self, gpa: GuestPhysAddr) -> HyperResult {
+        let Some((&start, region)) = self.regions.range(..=gpa).next_back() else {
+            return Err(Error::InvalidParam);
+        };
+        if gpa >= start + region.size {
+            return Err(Error::InvalidParam);
+        }
+        let page_index = (gpa - start) / PAGE_SIZE_4K;
+        if let Some(bitmap) = self.dirty_bitmaps.get_mut(&start) {
+            bitmap.set(page_index, true);
+        }
+        region.unprotect_page(&mut self.npt, memory_addr::align_down_4k(gpa))
+    }
+
+    /// Atomically snapshot and clear the accumulated dirty bitmap, as one
+    /// bit sequence in region-start order.
+    pub fn take_dirty_bitmap(&mut self) -> BitVec {
+        let mut snapshot = BitVec::new();
+        for bitmap in self.dirty_bitmaps.values_mut() {
+            snapshot.extend_from_bitslice(bitmap);
+            bitmap.fill(false);
+        }
+        snapshot
+    }
+
+    /// Stop dirty tracking and restore every region's original permissions.
+    pub fn disable_dirty_tracking(&mut self) -> HyperResult {
+        for region in self.regions.values_mut() {
+            region.unprotect_for_dirty_tracking(&mut self.npt)?;
+        }
+        self.dirty_bitmaps.clear();
+        Ok(())
+    }
+
+    /// Serialize guest physical memory as a sequence of `(gpa: u64, len: u64,
+    /// bytes)` records, one per mapped region. When `dirty_only` is `true`
+    /// (dirty tracking must already be enabled), only the pages marked dirty
+    /// since the last [`Self::take_dirty_bitmap`] are included, each as its
+    /// own 4 KiB record; this is the incremental live-migration path, while
+    /// `dirty_only == false` takes a full snapshot suitable for a cold save.
+    pub fn snapshot(&self, dirty_only: bool) -> alloc::vec::Vec<u8> {
+        use alloc::vec::Vec;
+        let mut out = Vec::new();
+        for (&start, region) in &self.regions {
+            if dirty_only {
+                let Some(bitmap) = self.dirty_bitmaps.get(&start) else {
+                    continue;
+                };
+                for page in 0..region.num_pages() {
+                    if !bitmap[page] {
+                        continue;
+                    }
+                    let gpa = start + page * PAGE_SIZE_4K;
+                    self.append_page(&mut out, region, gpa);
+                }
+            } else {
+                let mut gpa = start;
+                let end = start + region.size;
+                while gpa < end {
+                    self.append_page(&mut out, region, gpa);
+                    gpa += PAGE_SIZE_4K;
+                }
+            }
+        }
+        out
+    }
+
+    fn append_page(&self, out: &mut alloc::vec::Vec<u8>, region: &MapRegion, gpa: GuestPhysAddr) {
+        let hpa = region.target(gpa);
+        let host_ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *const u8;
+        let bytes = unsafe { core::slice::from_raw_parts(host_ptr, PAGE_SIZE_4K) };
+        out.extend_from_slice(&(gpa as u64).to_le_bytes());
+        out.extend_from_slice(&(PAGE_SIZE_4K as u64).to_le_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    /// Replay a blob produced by [`Self::snapshot`], copying each record's
+    /// bytes back to its guest-physical address. Every record's GPA must
+    /// already be mapped (e.g. by re-creating the same regions before
+    /// restoring).
+    pub fn restore(&mut self, data: &[u8]) -> HyperResult {
+        let mut offset = 0;
+        while offset < data.len() {
+            if offset + 16 > data.len() {
+                return Err(Error::InvalidParam);
+            }
+            let gpa = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()) as GuestPhysAddr;
+            let len = u64::from_le_bytes(data[offset + 8..offset + 16].try_into().unwrap()) as usize;
+            offset += 16;
+            if offset + len > data.len() {
+                return Err(Error::InvalidParam);
+            }
+            let hpa = self.translate(gpa)?;
+            let host_ptr = axhal::mem::phys_to_virt(hpa.into()).as_usize() as *mut u8;
+            unsafe { core::ptr::copy_nonoverlapping(data[offset..offset + len].as_ptr(), host_ptr, len) };
+            offset += len;
+        }
+        Ok(())
+    }
 }
 
 impl Drop for GuestPhysMemorySet {
@@ -0,0 +1,197 @@
+//! Save/restore building block for a VM created through `boot_vm` /
+//! `config_boot_linux`.
+//!
+//! A snapshot is split into independently-serialized pieces: per-vCPU
+//! architectural state ([`VcpuSnapshot`]), guest physical memory
+//! ([`crate::mm::GuestPhysMemorySet::snapshot`], optionally dirty-pages-only
+//! for incremental migration), and emulated-device state (each device
+//! implements [`Snapshottable`] directly, e.g.
+//! `device_emu::Uart16550::snapshot`). [`save_vm_state`]/[`restore_vm_state`]
+//! concatenate these three into one whole-VM blob and replay them in the
+//! same order on restore -- re-mapping the nested page tables and replaying
+//! device state is still the caller's job before the vCPU is handed to
+//! `bind_vcpu`, same as before. [`quiesce_barrier`] gives callers the same
+//! rendezvous `config_boot_linux`'s `INITED_CPUS` uses, to park every vCPU
+//! before [`save_vm_state`] captures them.
+//!
+//! !!! WORK-IN-PROCESS: the per-vCPU register accessors below
+//! (`rip`/`rflags`/`cr0`/`cr3`/`cr4`) are assumed to exist on `hypercraft`'s
+//! `VCpu`; this module doesn't attempt to also snapshot the `VCPU_TO_PCPU`
+//! affinity table, since that table currently lives in the disconnected
+//! legacy `vm.rs` (see its `// mod vm;` in `lib.rs`) and isn't part of the
+//! active `config_boot_linux` path.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::vec::Vec;
+
+use hypercraft::{HyperCraftHal, VCpu};
+
+use crate::mm::GuestPhysMemorySet;
+use crate::{Error, Result as HyperResult};
+
+/// Implemented by anything that can be captured into a versioned binary blob
+/// and later reconstructed from one. Device state and VM-level state both
+/// implement this the same way.
+pub trait Snapshottable {
+    /// Serialize the current state into a self-contained blob.
+    fn snapshot(&self) -> Vec<u8>;
+    /// Reconstruct state from a blob previously produced by [`Self::snapshot`].
+    fn restore(&mut self, data: &[u8]) -> HyperResult;
+}
+
+/// Fixed-layout capture of the architectural state a vCPU needs to resume
+/// execution: the general-purpose register block plus RIP/RFLAGS/CR0/CR3/CR4.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VcpuSnapshot {
+    regs: hypercraft::GeneralRegisters,
+    rip: u64,
+    rflags: u64,
+    cr0: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+/// Capture `vcpu`'s architectural state as a binary blob.
+pub fn snapshot_vcpu<H: HyperCraftHal>(vcpu: &VCpu<H>) -> Vec<u8> {
+    let snap = VcpuSnapshot {
+        regs: *vcpu.regs(),
+        rip: vcpu.rip(),
+        rflags: vcpu.rflags(),
+        cr0: vcpu.cr0(),
+        cr3: vcpu.cr3(),
+        cr4: vcpu.cr4(),
+    };
+    let ptr = &snap as *const VcpuSnapshot as *const u8;
+    unsafe { core::slice::from_raw_parts(ptr, core::mem::size_of::<VcpuSnapshot>()) }.to_vec()
+}
+
+/// Restore `vcpu`'s architectural state from a blob produced by
+/// [`snapshot_vcpu`].
+pub fn restore_vcpu<H: HyperCraftHal>(vcpu: &mut VCpu<H>, data: &[u8]) -> HyperResult {
+    if data.len() != core::mem::size_of::<VcpuSnapshot>() {
+        return Err(Error::InvalidParam);
+    }
+    let snap = unsafe { (data.as_ptr() as *const VcpuSnapshot).read_unaligned() };
+    *vcpu.regs_mut() = snap.regs;
+    vcpu.set_rip(snap.rip);
+    vcpu.set_rflags(snap.rflags);
+    vcpu.set_cr0(snap.cr0);
+    vcpu.set_cr3(snap.cr3);
+    vcpu.set_cr4(snap.cr4);
+    Ok(())
+}
+
+/// Rendezvous counter for quiescing every vCPU before a whole-VM snapshot,
+/// the same spin-rendezvous shape as `config_boot_linux`'s `INITED_CPUS`
+/// (`modules/axvm/src/linux.rs`): each vCPU calls [`quiesce_barrier`] once
+/// it's parked and ready to be captured, and none proceeds past it until
+/// `vcpu_count` of them have arrived. Reset with [`reset_quiesce_barrier`]
+/// before reusing it for the next save/restore cycle.
+static QUIESCE_ARRIVALS: AtomicUsize = AtomicUsize::new(0);
+
+/// Blocks the calling vCPU until `vcpu_count` callers have reached this
+/// point. See [`QUIESCE_ARRIVALS`].
+pub fn quiesce_barrier(vcpu_count: usize) {
+    QUIESCE_ARRIVALS.fetch_add(1, Ordering::SeqCst);
+    while QUIESCE_ARRIVALS.load(Ordering::Acquire) < vcpu_count {
+        core::hint::spin_loop();
+    }
+}
+
+/// Resets [`QUIESCE_ARRIVALS`] so [`quiesce_barrier`] can be reused for a
+/// later save/restore cycle.
+pub fn reset_quiesce_barrier() {
+    QUIESCE_ARRIVALS.store(0, Ordering::SeqCst);
+}
+
+/// Captures a whole VM as a single blob: every vCPU's architectural state
+/// (via [`snapshot_vcpu`]) paired with its per-vCPU device state (via its
+/// [`Snapshottable`] impl, e.g. `X64VcpuDevices`), followed by the shared
+/// [`GuestPhysMemorySet`] (via [`GuestPhysMemorySet::snapshot`]). Layout is
+/// `vcpu_count: u32`, then per vCPU `(vcpu_len: u32, vcpu_blob, dev_len: u32,
+/// dev_blob)`, then `(mem_len: u32, mem_blob)`.
+///
+/// `hypercraft::VM` doesn't expose a way to enumerate its own vCPUs from
+/// this crate, so this takes the vCPU/device handles the caller already
+/// holds from `VmCpus::add_vcpu` time instead of reaching into `VM` for
+/// them. Callers should have every vCPU parked at [`quiesce_barrier`] first
+/// -- `VM` has no pause/IPI primitive visible here to stop a vCPU mid-flight
+/// on another core.
+pub fn save_vm_state<H: HyperCraftHal>(
+    vcpus: &[&VCpu<H>],
+    devices: &[&dyn Snapshottable],
+    gpm: &GuestPhysMemorySet,
+) -> Vec<u8> {
+    assert_eq!(vcpus.len(), devices.len());
+    let mut out = Vec::new();
+    out.extend_from_slice(&(vcpus.len() as u32).to_le_bytes());
+    for (vcpu, dev) in vcpus.iter().zip(devices) {
+        let vcpu_blob = snapshot_vcpu(*vcpu);
+        let dev_blob = dev.snapshot();
+        out.extend_from_slice(&(vcpu_blob.len() as u32).to_le_bytes());
+        out.extend_from_slice(&vcpu_blob);
+        out.extend_from_slice(&(dev_blob.len() as u32).to_le_bytes());
+        out.extend_from_slice(&dev_blob);
+    }
+    let mem_blob = gpm.snapshot(false);
+    out.extend_from_slice(&(mem_blob.len() as u32).to_le_bytes());
+    out.extend_from_slice(&mem_blob);
+    out
+}
+
+/// Restores a blob produced by [`save_vm_state`]. Every vCPU must already be
+/// constructed (so its VMCS/EPT root is live) and every `gpm` region must
+/// already be re-`map_region`-ed -- the same precondition
+/// [`GuestPhysMemorySet::restore`] documents -- before this replays page
+/// contents and register state over them.
+pub fn restore_vm_state<H: HyperCraftHal>(
+    vcpus: &mut [&mut VCpu<H>],
+    devices: &mut [&mut dyn Snapshottable],
+    gpm: &mut GuestPhysMemorySet,
+    data: &[u8],
+) -> HyperResult {
+    if data.len() < 4 {
+        return Err(Error::InvalidParam);
+    }
+    let vcpu_count = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+    if vcpu_count != vcpus.len() || vcpu_count != devices.len() {
+        return Err(Error::InvalidParam);
+    }
+
+    let mut offset = 4;
+    for i in 0..vcpu_count {
+        if offset + 4 > data.len() {
+            return Err(Error::InvalidParam);
+        }
+        let vcpu_len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        offset += 4;
+        if offset + vcpu_len > data.len() {
+            return Err(Error::InvalidParam);
+        }
+        restore_vcpu(vcpus[i], &data[offset..offset + vcpu_len])?;
+        offset += vcpu_len;
+
+        if offset + 4 > data.len() {
+            return Err(Error::InvalidParam);
+        }
+        let dev_len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        offset += 4;
+        if offset + dev_len > data.len() {
+            return Err(Error::InvalidParam);
+        }
+        devices[i].restore(&data[offset..offset + dev_len])?;
+        offset += dev_len;
+    }
+
+    if offset + 4 > data.len() {
+        return Err(Error::InvalidParam);
+    }
+    let mem_len = u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+    offset += 4;
+    if offset + mem_len > data.len() {
+        return Err(Error::InvalidParam);
+    }
+    gpm.restore(&data[offset..offset + mem_len])
+}